@@ -16,15 +16,16 @@ use test::{black_box, Bencher, TestDesc, TestDescAndFn};
 use test::{DynTestName, DynBenchFn, TDynBenchFn};
 use test::ShouldFail::No;
 
-use html5ever::tokenizer::{TokenSink, Token, Tokenizer, TokenizerOpts};
+use html5ever::tokenizer::{TokenSink, TokenSinkResult, Continue, Token, Tokenizer, TokenizerOpts};
 
+// Unlike `NullTokenSink`, this passes each token to `black_box`, so
+// building the tokens can't be optimized out.
 struct Sink;
 
 impl TokenSink for Sink {
-    fn process_token(&mut self, token: Token) {
-        // Don't use the token, but make sure we don't get
-        // optimized out entirely.
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
         black_box(token);
+        Continue
     }
 }
 
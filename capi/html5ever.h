@@ -45,4 +45,50 @@ void h5e_tokenizer_free(struct h5e_tokenizer *tok);
 void h5e_tokenizer_feed(struct h5e_tokenizer *tok, struct h5e_buf buf);
 void h5e_tokenizer_end(struct h5e_tokenizer *tok);
 
+/* Nodes are identified by handles chosen by the caller. */
+typedef size_t h5e_node;
+
+struct h5e_tree_ops {
+    /* These must be provided, or h5e_parser_new returns NULL. */
+    h5e_node (*get_document)(void *user);
+    h5e_node (*create_element)(void *user, struct h5e_buf ns, struct h5e_buf name);
+    h5e_node (*create_comment)(void *user, struct h5e_buf text);
+
+    /* Add an attribute, unless the element has one with the same name.
+       Also called for each attribute of a new element. */
+    void (*add_attr)(void *user, h5e_node elem, struct h5e_buf ns,
+        struct h5e_buf name, struct h5e_buf value);
+
+    /* Appending text next to a text node should merge them. */
+    void (*append_node)(void *user, h5e_node parent, h5e_node child);
+    void (*append_text)(void *user, h5e_node parent, struct h5e_buf text);
+
+    /* Return 0 if the sibling has no parent, and nonzero otherwise. */
+    int (*insert_node_before)(void *user, h5e_node sibling, h5e_node child);
+    int (*insert_text_before)(void *user, h5e_node sibling, struct h5e_buf text);
+
+    void (*append_doctype)(void *user, struct h5e_buf name,
+        struct h5e_buf pub, struct h5e_buf sys);
+    void (*remove_from_parent)(void *user, h5e_node node);
+    void (*reparent_children)(void *user, h5e_node node, h5e_node new_parent);
+    void (*mark_script_already_started)(void *user, h5e_node node);
+
+    /* 0 for no quirks, 1 for limited quirks, 2 for quirks. */
+    void (*set_quirks_mode)(void *user, int mode);
+    void (*parse_error)(void *user, struct h5e_buf message);
+};
+
+struct h5e_tree_sink {
+    struct h5e_tree_ops *ops;
+    void *user;
+};
+
+struct h5e_parser;
+
+/* Returns NULL if a required tree operation is missing. */
+struct h5e_parser *h5e_parser_new(struct h5e_tree_sink *sink, int scripting);
+void h5e_parser_free(struct h5e_parser *parser);
+void h5e_parser_feed(struct h5e_parser *parser, struct h5e_buf buf);
+void h5e_parser_end(struct h5e_parser *parser);
+
 #endif
@@ -0,0 +1,40 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![feature(io, env)]
+
+//! Render a HTML document from stdin as plain text.
+//!
+//!   html2text [WIDTH]
+//!
+//! Lines are wrapped at WIDTH characters, or 80 by default; 0 means
+//! no wrapping.
+
+extern crate html5ever;
+
+use std::old_io as io;
+use std::env;
+use std::default::Default;
+
+use html5ever::sink::rcdom::RcDom;
+use html5ever::serialize::text::{to_text, TextOpts};
+use html5ever::{parse, one_input};
+
+fn main() {
+    let mut opts: TextOpts = Default::default();
+    match env::args().nth(1) {
+        Some(width) => opts.width = width.parse().ok().expect("width must be a number"),
+        None => (),
+    }
+
+    let input = io::stdin().read_to_string().unwrap();
+    let dom: RcDom = parse(one_input(input), Default::default());
+    io::stdout().write_str(to_text(&dom.document, opts).as_slice())
+        .ok().expect("writing output failed");
+}
@@ -9,29 +9,17 @@
 
 // Run a single benchmark once.  For use with profiling tools.
 
-#![feature(core, env, io, test, path)]
+#![feature(core, env, io, path)]
 
-extern crate test;
 extern crate html5ever;
 
 use std::old_io as io;
 use std::env;
 use std::default::Default;
 
-use test::black_box;
-
-use html5ever::tokenizer::{TokenSink, Token, TokenizerOpts};
+use html5ever::tokenizer::TokenizerOpts;
 use html5ever::driver::{tokenize_to, one_input};
-
-struct Sink;
-
-impl TokenSink for Sink {
-    fn process_token(&mut self, token: Token) {
-        // Don't use the token, but make sure we don't get
-        // optimized out entirely.
-        black_box(token);
-    }
-}
+use html5ever::sink::null::NullTokenSink;
 
 fn main() {
     let mut path = env::current_exe().ok().expect("can't get exe path");
@@ -41,7 +29,7 @@ fn main() {
     let mut file = io::File::open(&path).ok().expect("can't open file");
     let file_input = file.read_to_string().ok().expect("can't read file");
 
-    tokenize_to(Sink, one_input(file_input), TokenizerOpts {
+    tokenize_to(NullTokenSink, one_input(file_input), TokenizerOpts {
         profile: true,
         .. Default::default()
     });
@@ -20,7 +20,7 @@ use std::iter::repeat;
 use std::default::Default;
 use std::string::String;
 
-use html5ever::sink::common::{Document, Doctype, Text, Comment, Element};
+use html5ever::sink::common::{Document, DocumentFragment, Doctype, Text, Comment, Element};
 use html5ever::sink::rcdom::{RcDom, Handle};
 use html5ever::{parse, one_input};
 
@@ -34,6 +34,9 @@ fn walk(indent: usize, handle: Handle) {
         Document
             => println!("#Document"),
 
+        DocumentFragment
+            => println!("#DocumentFragment"),
+
         Doctype(ref name, ref public, ref system)
             => println!("<!DOCTYPE {} \"{}\" \"{}\">", *name, *public, *system),
 
@@ -67,7 +70,7 @@ fn main() {
     if !dom.errors.is_empty() {
         println!("\nParse errors:");
         for err in dom.errors.into_iter() {
-            println!("    {}", err);
+            println!("    {}", err.message);
         }
     }
 }
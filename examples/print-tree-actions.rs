@@ -17,12 +17,12 @@ use std::old_io as io;
 use std::default::Default;
 use std::string::String;
 use std::collections::HashMap;
-use std::string::CowString;
 use string_cache::QualName;
 
 use html5ever::{parse_to, one_input};
 use html5ever::tokenizer::Attribute;
 use html5ever::tree_builder::{TreeSink, QuirksMode, NodeOrText, AppendNode, AppendText};
+use html5ever::diagnostic::Diagnostic;
 
 struct Sink {
     next_id: usize,
@@ -39,9 +39,14 @@ impl Sink {
 
 impl TreeSink for Sink {
     type Handle = usize;
+    type Output = Sink;
 
-    fn parse_error(&mut self, msg: CowString<'static>) {
-        println!("Parse error: {}", msg);
+    fn finish(self) -> Sink {
+        self
+    }
+
+    fn report_diagnostic(&mut self, diagnostic: Diagnostic) {
+        println!("Parse error: {}", diagnostic.message);
     }
 
     fn get_document(&mut self) -> usize {
@@ -52,6 +57,10 @@ impl TreeSink for Sink {
         println!("Set quirks mode to {:?}", mode);
     }
 
+    fn set_base_url(&mut self, url: String) {
+        println!("Set base URL to \"{}\"", url.escape_default());
+    }
+
     fn same_node(&self, x: usize, y: usize) -> bool {
         x == y
     }
@@ -14,7 +14,7 @@ extern crate html5ever;
 use std::old_io as io;
 use std::default::Default;
 
-use html5ever::tokenizer::{TokenSink, Token, TokenizerOpts, ParseError};
+use html5ever::tokenizer::{TokenSink, TokenSinkResult, Continue, Token, TokenizerOpts, ParseError};
 use html5ever::tokenizer::{CharacterTokens, NullCharacterToken, TagToken, StartTag, EndTag};
 use html5ever::driver::{tokenize_to, one_input};
 
@@ -40,7 +40,7 @@ impl TokenPrinter {
 }
 
 impl TokenSink for TokenPrinter {
-    fn process_token(&mut self, token: Token) {
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
         match token {
             CharacterTokens(b) => {
                 for c in b.as_slice().chars() {
@@ -66,13 +66,14 @@ impl TokenSink for TokenPrinter {
             }
             ParseError(err) => {
                 self.is_char(false);
-                println!("ERROR: {}", err);
+                println!("ERROR: {}", err.message);
             }
             _ => {
                 self.is_char(false);
                 println!("OTHER: {:?}", token);
             }
         }
+        Continue
     }
 }
 
@@ -0,0 +1,67 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tables of the mixed-case names of SVG and MathML elements and
+//! attributes, keyed by the lowercase names the tokenizer produces.
+//!
+//! The names live in `data/foreign-names.json`, one object per table.
+//! The mixed-case names there should also be static atoms in
+//! `string_cache`, whose atom list can be generated from the same file.
+
+#![allow(unused_imports)]  // for quotes
+
+use rustc_serialize::json::Json;
+use syntax::codemap::Span;
+use syntax::ast::{TokenTree, TtToken};
+use syntax::parse::token;
+use syntax::ext::base::{ExtCtxt, MacResult, MacExpr};
+
+use internal::read_json_relative;
+
+// Expand foreign_names!("path/to/foreign-names.json", "table") into an
+// invocation of phf_map!().
+pub fn expand(cx: &mut ExtCtxt, sp: Span, tt: &[TokenTree]) -> Box<MacResult+'static> {
+    let usage = "Usage: foreign_names!(\"path/to/foreign-names.json\", \"table\")";
+
+    let (json_filename, table) = match tt {
+        [TtToken(_, token::Literal(token::Lit::Str_(f), _)),
+         TtToken(_, token::Comma),
+         TtToken(_, token::Literal(token::Lit::Str_(t), _))]
+            => (f.as_str().to_string(), t.as_str().to_string()),
+        _ => bail!(cx, sp, usage),
+    };
+
+    let js = match read_json_relative(cx, sp, json_filename.as_slice()) {
+        Ok(js) => js,
+        Err(msg) => bail!(cx, sp, msg),
+    };
+
+    let names = match js {
+        Json::Object(mut tables) => match tables.remove(&table) {
+            Some(Json::Object(names)) => Some(names),
+            _ => None,
+        },
+        _ => None,
+    };
+    let names = expect!(cx, sp, names, "no such table in JSON file");
+
+    // Emit a macro invocation of the form
+    //
+    //     phf_map!(lowercase => mixedCase, ...)
+    let mut toks = vec!();
+    for (k, v) in names.into_iter() {
+        let v = match v {
+            Json::String(v) => v,
+            _ => bail!(cx, sp, "names in the JSON file must be strings"),
+        };
+        let (k, v) = (k.as_slice(), v.as_slice());
+        toks.extend(quote_tokens!(&mut *cx, $k => $v,).into_iter());
+    }
+    MacExpr::new(quote_expr!(&mut *cx, phf_map!($toks)))
+}
@@ -7,7 +7,18 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//! Macros for use in defining other macros.  Not exported.
+//! Macros and functions for use in defining other macros.  Not exported.
+
+use std::old_io as io;
+use std::old_path as path;
+use std::str::FromStr;
+
+use rustc_serialize::json;
+use rustc_serialize::json::Json;
+use syntax::codemap::Span;
+use syntax::ast::{ExprLit, Lit_};
+use syntax::ext::base::ExtCtxt;
+use syntax::ext::source_util::expand_file;
 
 macro_rules! bail ( ($cx:expr, $sp:expr, $msg:expr) => ({
     $cx.span_err($sp, $msg);
@@ -25,3 +36,27 @@ macro_rules! expect ( ($cx:expr, $sp:expr, $e:expr, $msg:expr) => (
     }
 ));
 
+/// Parse a JSON file, whose name is relative to the file containing the
+/// macro invocation.
+pub fn read_json_relative(cx: &mut ExtCtxt, sp: Span, filename: &str)
+        -> Result<Json, &'static str> {
+    // Get the result of calling file!() in the same place as our macro.
+    // This would be a lot nicer if @-patterns were still supported.
+    let mod_filename = try!(match expand_file(cx, sp, &[]).make_expr() {
+        Some(e) => match e.node {
+            ExprLit(ref s) => match s.node {
+                Lit_::LitStr(ref s, _) => Some(s.to_string()),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }.ok_or("unexpected result from file!()"));
+
+    let mod_path: path::Path = try!(FromStr::from_str(mod_filename.as_slice()).ok()
+        .ok_or("can't parse module filename"));
+    let json_path = mod_path.dir_path().join(filename);
+
+    let mut json_file = try!(io::File::open(&json_path).ok().ok_or("can't open JSON file"));
+    json::from_reader(&mut json_file as &mut Reader).ok().ok_or("can't parse JSON file")
+}
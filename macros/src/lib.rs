@@ -27,10 +27,12 @@ mod internal;
 // Make these public so that rustdoc will generate documentation for them.
 pub mod named_entities;
 pub mod match_token;
+pub mod foreign_names;
 
 // NB: This needs to be public or we get a linker error.
 #[plugin_registrar]
 pub fn plugin_registrar(reg: &mut Registry) {
     reg.register_macro("named_entities", named_entities::expand);
     reg.register_macro("match_token", match_token::expand);
+    reg.register_macro("foreign_names", foreign_names::expand);
 }
@@ -9,19 +9,17 @@
 
 #![allow(unused_imports)]  // for quotes
 
-use std::old_io as io;
-use std::old_path as path;
-use std::str::FromStr;
 use std::collections::HashMap;
 
 use rustc_serialize::json;
 use rustc_serialize::json::Json;
 use rustc_serialize::Decodable;
 use syntax::codemap::Span;
-use syntax::ast::{Path, ExprLit, Lit_, TokenTree, TtToken};
+use syntax::ast::{TokenTree, TtToken};
 use syntax::parse::token;
 use syntax::ext::base::{ExtCtxt, MacResult, MacExpr};
-use syntax::ext::source_util::expand_file;
+
+use internal::read_json_relative;
 
 // A struct matching the entries in entities.json.
 // Simplifies JSON parsing because we can use Decodable.
@@ -82,29 +80,11 @@ pub fn expand(cx: &mut ExtCtxt, sp: Span, tt: &[TokenTree]) -> Box<MacResult+'st
         _ => bail!(cx, sp, usage),
     };
 
-    // Get the result of calling file!() in the same place as our macro.
-    // This would be a lot nicer if @-patterns were still supported.
-    let mod_filename = expect!(cx, sp, match expand_file(cx, sp, &[]).make_expr() {
-        Some(e) => match e.node {
-            ExprLit(ref s) => match s.node {
-                Lit_::LitStr(ref s, _) => Some(s.to_string()),
-                _ => None,
-            },
-            _ => None,
-        },
-        _ => None,
-    }, "unexpected result from file!()");
-
-    // Combine those to get an absolute path to entities.json.
-    let mod_path: path::Path = expect!(cx, sp, FromStr::from_str(mod_filename.as_slice()).ok(),
-        "can't parse module filename");
-    let json_path = mod_path.dir_path().join(json_filename);
-
     // Open the JSON file, parse it, and build the map from names to characters.
-    let mut json_file = expect!(cx, sp, io::File::open(&json_path).ok(),
-        "can't open JSON file");
-    let js = expect!(cx, sp, json::from_reader(&mut json_file as &mut Reader).ok(),
-        "can't parse JSON file");
+    let js = match read_json_relative(cx, sp, json_filename.as_slice()) {
+        Ok(js) => js,
+        Err(msg) => bail!(cx, sp, msg),
+    };
     let map = expect!(cx, sp, build_map(js),
         "JSON file does not match entities.json format");
 
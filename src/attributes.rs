@@ -0,0 +1,342 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Classifying attribute names, and parsing security-relevant attribute
+//! values, for sanitizers and other tools that work on the token stream.
+
+use core::prelude::*;
+
+use tokenizer::Attribute;
+
+use util::str::{AsciiExt, is_ascii_whitespace};
+
+use collections::vec::Vec;
+use collections::string::String;
+
+use string_cache::Atom;
+
+use phf::Set;
+
+pub use self::AttrKind::{EventHandler, UrlAttr, OtherAttr};
+pub use self::CrossOrigin::{Anonymous, UseCredentials};
+pub use self::HashAlgorithm::{Sha256, Sha384, Sha512};
+
+/// What sort of value an attribute holds.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum AttrKind {
+    /// Script which runs on some event, such as `onclick`.
+    EventHandler,
+
+    /// A URL, or a list of URLs, such as `href` or `srcset`.
+    UrlAttr,
+
+    /// Anything else.
+    OtherAttr,
+}
+
+/// Is this the name of an event handler content attribute?
+///
+/// Names are expected in lowercase, as produced by the tokenizer.  The
+/// list comes from the event handler IDL attributes on
+/// GlobalEventHandlers, WindowEventHandlers and
+/// DocumentAndElementEventHandlers, plus some widely supported
+/// non-standard ones.  Browsers may support handlers which aren't
+/// listed here, so a sanitizer should also reject other names starting
+/// with `on`.
+pub fn is_event_handler(name: &Atom) -> bool {
+    match *name {
+        atom!(onabort) | atom!(onafterprint) | atom!(onanimationend)
+        | atom!(onanimationiteration) | atom!(onanimationstart)
+        | atom!(onbeforeprint) | atom!(onbeforeunload) | atom!(onblur) | atom!(oncancel)
+        | atom!(oncanplay) | atom!(oncanplaythrough) | atom!(onchange) | atom!(onclick)
+        | atom!(onclose) | atom!(oncontextmenu) | atom!(oncopy) | atom!(oncuechange)
+        | atom!(oncut) | atom!(ondblclick) | atom!(ondrag) | atom!(ondragend)
+        | atom!(ondragenter) | atom!(ondragexit) | atom!(ondragleave) | atom!(ondragover)
+        | atom!(ondragstart) | atom!(ondrop) | atom!(ondurationchange) | atom!(onemptied)
+        | atom!(onended) | atom!(onerror) | atom!(onfocus) | atom!(onfocusin)
+        | atom!(onfocusout) | atom!(onhashchange) | atom!(oninput) | atom!(oninvalid)
+        | atom!(onkeydown) | atom!(onkeypress) | atom!(onkeyup) | atom!(onlanguagechange)
+        | atom!(onload) | atom!(onloadeddata) | atom!(onloadedmetadata) | atom!(onloadstart)
+        | atom!(onmessage) | atom!(onmousedown) | atom!(onmouseenter) | atom!(onmouseleave)
+        | atom!(onmousemove) | atom!(onmouseout) | atom!(onmouseover) | atom!(onmouseup)
+        | atom!(onmousewheel) | atom!(onoffline) | atom!(ononline) | atom!(onpagehide)
+        | atom!(onpageshow) | atom!(onpaste) | atom!(onpause) | atom!(onplay)
+        | atom!(onplaying) | atom!(onpopstate) | atom!(onprogress) | atom!(onratechange)
+        | atom!(onreset) | atom!(onresize) | atom!(onscroll) | atom!(onsearch)
+        | atom!(onseeked) | atom!(onseeking) | atom!(onselect) | atom!(onshow)
+        | atom!(onsort) | atom!(onstalled) | atom!(onstorage) | atom!(onsubmit)
+        | atom!(onsuspend) | atom!(ontimeupdate) | atom!(ontouchcancel)
+        | atom!(ontouchend) | atom!(ontouchmove) | atom!(ontouchstart)
+        | atom!(ontransitionend) | atom!(onunload) | atom!(onvolumechange)
+        | atom!(onwaiting) | atom!(onwheel) => true,
+        // Not among string_cache's static atoms.
+        _ => match name.as_slice() {
+            "onauxclick" | "ontoggle" => true,
+            _ => false,
+        },
+    }
+}
+
+/// Is this the name of an attribute which holds a URL, or a list
+/// of URLs, on some element?
+///
+/// Obsolete and SVG attributes are included, because browsers still
+/// follow them.  This is the local name, so it also covers
+/// `xlink:href`, which the tree builder puts in the XLink namespace
+/// with the local name `href`.
+pub fn is_url_attr(name: &Atom) -> bool {
+    match *name {
+        atom!(action) | atom!(archive) | atom!(background) | atom!(cite) | atom!(classid)
+        | atom!(codebase) | atom!(data) | atom!(formaction) | atom!(href)
+        | atom!(icon) | atom!(itemtype) | atom!(longdesc)
+        | atom!(manifest) | atom!(ping) | atom!(poster) | atom!(profile) | atom!(src)
+        | atom!(srcset) | atom!(usemap) => true,
+        // Not among string_cache's static atoms.
+        _ => match name.as_slice() {
+            "dynsrc" | "itemid" | "lowsrc" => true,
+            _ => false,
+        },
+    }
+}
+
+/// Classify an attribute by name.
+pub fn attr_kind(name: &Atom) -> AttrKind {
+    if is_event_handler(name) {
+        EventHandler
+    } else if is_url_attr(name) {
+        UrlAttr
+    } else {
+        OtherAttr
+    }
+}
+
+/// The state of a `crossorigin` attribute which is present.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum CrossOrigin {
+    /// `crossorigin`, `crossorigin=anonymous`, or any invalid value.
+    Anonymous,
+
+    /// `crossorigin=use-credentials`.
+    UseCredentials,
+}
+
+/// A hash function allowed in an `integrity` attribute.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+/// One hash from an `integrity` attribute, such as `sha384-oqVu...`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct IntegrityMetadata {
+    pub algorithm: HashAlgorithm,
+
+    /// The base64-encoded digest, which is not checked.
+    pub digest: String,
+
+    /// Options following the digest, each introduced by `?`.
+    pub options: Vec<String>,
+}
+
+/// The keywords of a `sandbox` attribute.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Sandbox {
+    /// Keywords which lift a restriction, such as `allow-scripts`,
+    /// in lowercase.
+    pub allow: Vec<String>,
+
+    /// Other keywords, which browsers ignore.
+    pub unknown: Vec<String>,
+}
+
+// The sandbox keywords recognized by browsers.
+static SANDBOX_KEYWORDS: Set<&'static str> = phf_set! {
+    "allow-downloads", "allow-forms", "allow-modals",
+    "allow-orientation-lock", "allow-pointer-lock", "allow-popups",
+    "allow-popups-to-escape-sandbox", "allow-presentation",
+    "allow-same-origin", "allow-scripts", "allow-top-navigation",
+    "allow-top-navigation-by-user-activation",
+};
+
+impl Sandbox {
+    /// Does this lift the restriction named by `keyword`, such as
+    /// `"allow-scripts"`?
+    pub fn allows(&self, keyword: &str) -> bool {
+        self.allow.iter().any(|k| k.as_slice() == keyword)
+    }
+
+    /// Can framed content run script and remove its own sandbox?
+    /// This is so when both `allow-scripts` and `allow-same-origin`
+    /// are given.
+    pub fn is_escapable(&self) -> bool {
+        self.allows("allow-scripts") && self.allows("allow-same-origin")
+    }
+}
+
+/// Parse the value of a `crossorigin` attribute.
+pub fn parse_crossorigin(value: &str) -> CrossOrigin {
+    if value.eq_ignore_ascii_case("use-credentials") {
+        UseCredentials
+    } else {
+        Anonymous
+    }
+}
+
+/// Parse the value of an `integrity` attribute, skipping hashes with an
+/// unknown algorithm as browsers do.
+pub fn parse_integrity(value: &str) -> Vec<IntegrityMetadata> {
+    let mut hashes = vec!();
+    for item in value.split(is_ascii_whitespace).filter(|w| !w.is_empty()) {
+        let dash = unwrap_or_else!(item.find('-'), { continue });
+        let (name, rest) = (&item[..dash], &item[dash+1..]);
+        let algorithm = match name.to_ascii_lower().as_slice() {
+            "sha256" => Sha256,
+            "sha384" => Sha384,
+            "sha512" => Sha512,
+            _ => continue,
+        };
+        let mut parts = rest.split('?');
+        let digest = String::from_str(parts.next().unwrap_or(""));
+        hashes.push(IntegrityMetadata {
+            algorithm: algorithm,
+            digest: digest,
+            options: parts.map(String::from_str).collect(),
+        });
+    }
+    hashes
+}
+
+/// Parse the value of a `sandbox` attribute.
+pub fn parse_sandbox(value: &str) -> Sandbox {
+    let mut sandbox = Sandbox {
+        allow: vec!(),
+        unknown: vec!(),
+    };
+    for word in value.split(is_ascii_whitespace).filter(|w| !w.is_empty()) {
+        let word = word.to_ascii_lower();
+        if SANDBOX_KEYWORDS.contains(&word.as_slice()) {
+            sandbox.allow.push(word);
+        } else {
+            sandbox.unknown.push(word);
+        }
+    }
+    sandbox
+}
+
+/// The security-relevant attributes of an element, parsed.  Each is
+/// `None` if the attribute is missing.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct SecurityAttrs {
+    /// The `nonce` attribute, which a Content Security Policy can
+    /// match against.
+    pub nonce: Option<String>,
+
+    pub integrity: Option<Vec<IntegrityMetadata>>,
+
+    pub crossorigin: Option<CrossOrigin>,
+
+    pub sandbox: Option<Sandbox>,
+}
+
+/// Find and parse the security-relevant attributes in a tag's
+/// attributes.  If an attribute is repeated, the first one counts,
+/// as in the tree builder.
+pub fn security_attrs(attrs: &[Attribute]) -> SecurityAttrs {
+    let mut result = SecurityAttrs {
+        nonce: None,
+        integrity: None,
+        crossorigin: None,
+        sandbox: None,
+    };
+    for attr in attrs.iter().rev() {
+        if attr.name.ns != ns!("") {
+            continue;
+        }
+        let value = attr.value.as_slice();
+        match attr.name.local.as_slice() {
+            "nonce" => result.nonce = Some(String::from_str(value)),
+            "integrity" => result.integrity = Some(parse_integrity(value)),
+            "crossorigin" => result.crossorigin = Some(parse_crossorigin(value)),
+            "sandbox" => result.sandbox = Some(parse_sandbox(value)),
+            _ => (),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::string::String;
+    use super::{attr_kind, EventHandler, UrlAttr, OtherAttr};
+    use super::{security_attrs, parse_integrity, parse_sandbox, IntegrityMetadata};
+    use super::{Sha384, Sha512, Anonymous, UseCredentials};
+    use tokenizer::Attribute;
+    use string_cache::{Atom, QualName};
+
+    fn kind(name: &str) -> super::AttrKind {
+        attr_kind(&Atom::from_slice(name))
+    }
+
+    test_eq!(onclick, kind("onclick"), EventHandler);
+    test_eq!(onload, kind("onload"), EventHandler);
+    test_eq!(href, kind("href"), UrlAttr);
+    test_eq!(formaction, kind("formaction"), UrlAttr);
+    test_eq!(srcset, kind("srcset"), UrlAttr);
+    test_eq!(poster, kind("poster"), UrlAttr);
+    test_eq!(class, kind("class"), OtherAttr);
+    test_eq!(only, kind("only"), OtherAttr);
+
+    fn attr(name: &str, value: &str) -> Attribute {
+        Attribute {
+            name: QualName::new(ns!(""), Atom::from_slice(name)),
+            value: String::from_str(value),
+        }
+    }
+
+    #[test]
+    fn integrity() {
+        assert_eq!(parse_integrity(" sha384-abc+/=?foo md5-xyz SHA512-def "), vec!(
+            IntegrityMetadata {
+                algorithm: Sha384,
+                digest: String::from_str("abc+/="),
+                options: vec!(String::from_str("foo")),
+            },
+            IntegrityMetadata {
+                algorithm: Sha512,
+                digest: String::from_str("def"),
+                options: vec!(),
+            }));
+    }
+
+    #[test]
+    fn sandbox() {
+        let sandbox = parse_sandbox("allow-scripts  Allow-Same-Origin allow-everything");
+        assert!(sandbox.allows("allow-same-origin"));
+        assert!(sandbox.is_escapable());
+        assert_eq!(sandbox.unknown, vec!(String::from_str("allow-everything")));
+        assert!(!parse_sandbox("").is_escapable());
+    }
+
+    #[test]
+    fn attrs() {
+        let attrs = security_attrs(&[
+            attr("src", "a.js"), attr("nonce", "r4nd0m"),
+            attr("crossorigin", ""), attr("crossorigin", "use-credentials")]);
+        assert_eq!(attrs.nonce, Some(String::from_str("r4nd0m")));
+        assert_eq!(attrs.crossorigin, Some(Anonymous));
+        assert!(attrs.integrity.is_none());
+        assert!(attrs.sandbox.is_none());
+
+        let attrs = security_attrs(&[attr("crossorigin", "USE-CREDENTIALS")]);
+        assert_eq!(attrs.crossorigin, Some(UseCredentials));
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Problems found in a document, as reported by both the tokenizer and
+//! the tree builder.
+
+use core::prelude::*;
+
+use tokenizer::Span;
+
+use collections::string::{String, CowString};
+use collections::borrow::Cow::Borrowed;
+
+pub use self::Severity::{Error, Warning};
+
+/// How bad a problem is.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum Severity {
+    /// A parse error, as defined by the spec.
+    Error,
+
+    /// Something which is not a parse error, but probably a mistake.
+    Warning,
+}
+
+/// A problem found in a document.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Diagnostic {
+    /// A short, fixed description of the problem, such as `"Bad
+    /// character"`.  This doesn't depend on the `exact_errors` options,
+    /// so it's suitable for grouping and filtering.
+    pub code: &'static str,
+
+    /// A description of the problem.  This is the same as `code` unless
+    /// `exact_errors` is set, in which case it may give more detail.
+    pub message: CowString<'static>,
+
+    /// Where the problem is in the input, if the tokenizer's
+    /// `track_positions` option is set.  This is the span of the token
+    /// being processed when the problem was found.
+    pub span: Option<Span>,
+
+    pub severity: Severity,
+
+    /// Fragment identifier of the relevant section of the HTML
+    /// standard, such as `"the-ul-element"`, if there is one.
+    pub spec: Option<&'static str>,
+}
+
+impl Diagnostic {
+    /// A parse error with a fixed message.
+    pub fn error(code: &'static str) -> Diagnostic {
+        Diagnostic::error_with_message(code, Borrowed(code))
+    }
+
+    /// A parse error with a more detailed message.
+    pub fn error_with_message(code: &'static str, message: CowString<'static>) -> Diagnostic {
+        Diagnostic {
+            code: code,
+            message: message,
+            span: None,
+            severity: Error,
+            spec: None,
+        }
+    }
+
+    /// URL of the relevant section of the HTML standard, if known.
+    pub fn spec_url(&self) -> Option<String> {
+        self.spec.map(|spec| format!("https://html.spec.whatwg.org/#{}", spec))
+    }
+}
@@ -13,18 +13,90 @@ use core::prelude::*;
 
 use tokenizer::{TokenizerOpts, Tokenizer, TokenSink};
 use tree_builder::{TreeBuilderOpts, TreeBuilder, TreeSink};
+use diagnostic::Diagnostic;
+use encoding::{EncodingOpts, TentativeDecoder, Continue, Restart};
+use encoding::{sniff_encoding, PRESCAN_BYTES};
 
 use core::default::Default;
 use core::option;
+use core::str::Str;
+use core::slice::AsSlice;
+use collections::vec::Vec;
 use collections::string::String;
 
+use string_cache::QualName;
+
 /// Convenience function to turn a single `String` into an iterator.
 pub fn one_input(x: String) -> option::IntoIter<String> {
     Some(x).into_iter()
 }
 
+/// How many characters `char_input` puts in each `String`.
+pub const CHAR_INPUT_CHUNK: uint = 1024;
+
+/// Input from an iterator over characters.  See `char_input`.
+pub struct CharInput<I> {
+    chars: I,
+}
+
+impl<I: Iterator<Item=char>> Iterator for CharInput<I> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let mut s = String::new();
+        for c in self.chars.by_ref().take(CHAR_INPUT_CHUNK) {
+            s.push(c);
+        }
+        if s.is_empty() { None } else { Some(s) }
+    }
+}
+
+/// Turn an iterator over characters into input for `parse`,
+/// `tokenize_to` and the like, gathering the characters into strings.
+///
+/// ## Example
+///
+/// ```ignore
+/// let dom: RcDom = parse(char_input(my_str.chars().map(rot13)), Default::default());
+/// ```
+pub fn char_input<I: Iterator<Item=char>>(chars: I) -> CharInput<I> {
+    CharInput {
+        chars: chars,
+    }
+}
+
+/// Input from an iterator over string chunks.  See `str_input`.
+pub struct StrInput<I> {
+    chunks: I,
+}
+
+impl<S: Str, I: Iterator<Item=S>> Iterator for StrInput<I> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.chunks.next().map(|s| String::from_str(s.as_slice()))
+    }
+}
+
+/// Turn an iterator over chunks of text which aren't `String`s, such as
+/// `&str`s, into input for `parse`, `tokenize_to` and the like.
+///
+/// ## Example
+///
+/// ```ignore
+/// let pieces: Vec<&str> = vec!(header, body, footer);
+/// let dom: RcDom = parse(str_input(pieces.into_iter()), Default::default());
+/// ```
+pub fn str_input<S: Str, I: Iterator<Item=S>>(chunks: I) -> StrInput<I> {
+    StrInput {
+        chunks: chunks,
+    }
+}
+
 /// Tokenize and send results to a `TokenSink`.
 ///
+/// If the sink returns `Stop`, the rest of the input is not read.
+///
 /// ## Example
 ///
 /// ```ignore
@@ -42,6 +114,9 @@ pub fn tokenize_to<
     let mut tok = Tokenizer::new(sink, opts);
     for s in input {
         tok.feed(s);
+        if tok.is_stopped() {
+            break;
+        }
     }
     tok.end();
     tok.unwrap()
@@ -55,15 +130,71 @@ pub struct ParseOpts {
 
     /// Tree builder options.
     pub tree_builder: TreeBuilderOpts,
+
+    /// Options for determining the encoding, used by `parse_bytes`.
+    pub encoding: EncodingOpts,
+
+    /// A time, as given by `time::precise_time_ns()`, after which to
+    /// stop parsing.  The sink gets a `"Parse deadline exceeded"` error
+    /// and whatever tree was built so far.  The clock is checked before
+    /// each input chunk and every `DEADLINE_STEPS` steps of the
+    /// tokenizer.  Without the `std` feature there is no clock, and this
+    /// is ignored.  Default: None
+    pub deadline: Option<u64>,
 }
 
-/// Parse and send results to a `TreeSink`.
+/// How many tokenizer steps to run between checks of
+/// `ParseOpts::deadline`.  See `Tokenizer::feed_interruptible`.
+pub const DEADLINE_STEPS: uint = 16 * 1024;
+
+#[cfg(feature = "std")]
+fn passed(deadline: u64) -> bool {
+    ::time::precise_time_ns() >= deadline
+}
+
+#[cfg(not(feature = "std"))]
+fn passed(_deadline: u64) -> bool {
+    false
+}
+
+// Feed input until it runs out, the sink stops the parse, or the
+// deadline passes.  Returns false in the latter two cases.
+fn feed_all<Handle, Sink, It>(tok: &mut Tokenizer<TreeBuilder<Handle, Sink>>,
+        input: It, deadline: Option<u64>) -> bool
+    where Handle: Clone,
+          Sink: TreeSink<Handle=Handle>,
+          It: Iterator<Item=String>,
+{
+    let expired = |sink: &mut TreeBuilder<Handle, Sink>| match deadline {
+        Some(deadline) if passed(deadline) => {
+            sink.abort(Diagnostic::error("Parse deadline exceeded"));
+            true
+        }
+        _ => false,
+    };
+
+    for s in input {
+        if deadline.is_none() {
+            tok.feed(s);
+        } else if expired(tok.sink_mut()) {
+            return false;
+        } else {
+            tok.feed_interruptible(s, DEADLINE_STEPS, |sink| expired(sink));
+        }
+        if tok.is_stopped() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Parse and send results to a `TreeSink`, returning whatever the
+/// sink's `finish` method does.
 ///
 /// ## Example
 ///
 /// ```ignore
-/// let mut sink = MySink;
-/// parse_to(&mut sink, one_input(my_str), Default::default());
+/// let dom = parse_to(MySink::new(), one_input(my_str), Default::default());
 /// ```
 pub fn parse_to<
         Sink: TreeSink,
@@ -71,24 +202,21 @@ pub fn parse_to<
     >(
         sink: Sink,
         input: It,
-        opts: ParseOpts) -> Sink {
+        opts: ParseOpts) -> Sink::Output {
 
     let tb = TreeBuilder::new(sink, opts.tree_builder);
     let mut tok = Tokenizer::new(tb, opts.tokenizer);
-    for s in input {
-        tok.feed(s);
-    }
+    feed_all(&mut tok, input, opts.deadline);
     tok.end();
-    tok.unwrap().unwrap()
+    tok.unwrap().unwrap().finish()
 }
 
-/// Results which can be extracted from a `TreeSink`.
+/// Parse trees which a `TreeSink` with a default value can build.
 ///
 /// Implement this for your parse tree data type so that it
 /// can be returned by `parse()`.
 pub trait ParseResult {
-    type Sink: TreeSink + Default;
-    fn get_result(sink: Self::Sink) -> Self;
+    type Sink: TreeSink<Output=Self> + Default;
 }
 
 /// Parse into a type which implements `ParseResult`.
@@ -102,6 +230,285 @@ pub fn parse<Output, It>(input: It, opts: ParseOpts) -> Output
     where Output: ParseResult,
           It: Iterator<Item=String>,
 {
-    let sink = parse_to(Default::default(), input, opts);
-    ParseResult::get_result(sink)
+    let sink: Output::Sink = Default::default();
+    parse_to(sink, input, opts)
+}
+
+/// Parse a string with the default options, for tests.
+#[cfg(test)]
+pub fn parse_str<Output: ParseResult>(input: &str) -> Output {
+    parse(one_input(String::from_str(input)), Default::default())
+}
+
+/// A parser which can be used for one document after another, keeping
+/// the memory its tokenizer and tree builder have allocated, for
+/// programs which parse many small documents.
+///
+/// ## Example
+///
+/// ```ignore
+/// let mut parser: Parser<RcDom> = Parser::new(Default::default());
+/// for page in pages.into_iter() {
+///     let dom = parser.parse(one_input(page));
+///     ...
+/// }
+/// ```
+pub struct Parser<Sink: TreeSink> {
+    tok: Tokenizer<TreeBuilder<Sink::Handle, Sink>>,
+    encoding: EncodingOpts,
+    deadline: Option<u64>,
+}
+
+impl<Sink: TreeSink + Default> Parser<Sink> {
+    pub fn new(opts: ParseOpts) -> Parser<Sink> {
+        let sink: Sink = Default::default();
+        Parser {
+            tok: Tokenizer::new(TreeBuilder::new(sink, opts.tree_builder), opts.tokenizer),
+            encoding: opts.encoding,
+            deadline: opts.deadline,
+        }
+    }
+
+    /// Parse a document and send results to `sink`, returning whatever
+    /// its `finish` method does.
+    pub fn parse_to<It>(&mut self, sink: Sink, input: It) -> Sink::Output
+        where It: Iterator<Item=String>,
+    {
+        self.start(sink);
+        feed_all(&mut self.tok, input, self.deadline);
+        self.finish()
+    }
+
+    /// Parse a document with a new default sink.
+    pub fn parse<It>(&mut self, input: It) -> Sink::Output
+        where It: Iterator<Item=String>,
+    {
+        self.parse_to(Default::default(), input)
+    }
+
+    /// Parse bytes of unknown encoding, as `parse_bytes` does, and send
+    /// results to `sink`.  If parsing starts over in another encoding,
+    /// `sink` is dropped and a new default sink used instead.
+    pub fn parse_bytes_to<It, B>(&mut self, sink: Sink, input: It) -> Sink::Output
+        where It: Iterator<Item=B>,
+              B: AsSlice<u8>,
+    {
+        self.start(sink);
+        feed_bytes(&mut self.tok, input, self.encoding, self.deadline, |tok| {
+            tok.reset();
+            tok.sink_mut().reset(Default::default());
+        });
+        self.finish()
+    }
+
+    /// Parse bytes of unknown encoding with a new default sink.
+    pub fn parse_bytes<It, B>(&mut self, input: It) -> Sink::Output
+        where It: Iterator<Item=B>,
+              B: AsSlice<u8>,
+    {
+        self.parse_bytes_to(Default::default(), input)
+    }
+
+    fn start(&mut self, sink: Sink) {
+        self.tok.reset();
+        self.tok.sink_mut().reset(sink);
+    }
+
+    fn finish(&mut self) -> Sink::Output {
+        self.tok.end();
+        self.tok.sink_mut().reset(Default::default()).finish()
+    }
+}
+
+/// Parse a fragment, as if it were the contents of an element named
+/// `context`, and send results to a `TreeSink`.
+///
+/// The fragment ends up as the children of an `<html>` element, the
+/// only child of the sink's document.  Returns whatever the sink's
+/// `finish_fragment` method does.
+///
+/// ## Example
+///
+/// ```ignore
+/// let dom = parse_fragment_to(MySink::new(), one_input(my_str),
+///     qualname!(HTML, body), Default::default());
+/// ```
+pub fn parse_fragment_to<
+        Sink: TreeSink,
+        It: Iterator<Item=String>
+    >(
+        mut sink: Sink,
+        input: It,
+        context: QualName,
+        opts: ParseOpts) -> Sink::Output {
+
+    let context_name = String::from_str(context.local.as_slice());
+    let context_elem = sink.create_element(context, vec!());
+    let tb = TreeBuilder::new_for_fragment(sink, context_elem, None, opts.tree_builder);
+
+    let mut tok_opts = opts.tokenizer;
+    tok_opts.initial_state = Some(tb.tokenizer_state_for_context_elem());
+    tok_opts.last_start_tag_name = Some(context_name);
+
+    let mut tok = Tokenizer::new(tb, tok_opts);
+    feed_all(&mut tok, input, opts.deadline);
+    tok.end();
+    tok.unwrap().unwrap().finish_fragment()
+}
+
+/// Parse a fragment, as if it were the contents of an element named
+/// `context`, into a type which implements `ParseResult`.
+///
+/// ## Example
+///
+/// ```ignore
+/// let dom: RcDom = parse_fragment(one_input(my_str), qualname!(HTML, td), Default::default());
+/// ```
+pub fn parse_fragment<Output, It>(input: It, context: QualName, opts: ParseOpts) -> Output
+    where Output: ParseResult,
+          It: Iterator<Item=String>,
+{
+    let sink: Output::Sink = Default::default();
+    parse_fragment_to(sink, input, context, opts)
+}
+
+/// Parse bytes of unknown encoding into a type which implements
+/// `ParseResult`.
+///
+/// The input can be owned chunks, such as `Vec<u8>`, or borrowed
+/// slices, such as the chunks of a memory-mapped file; either way the
+/// bytes are decoded without being copied first.
+///
+/// The encoding is determined by a byte order mark, the transport
+/// encoding, or a `<meta>` element, in that order.  If a `<meta>`
+/// element which doesn't fit the prescan changes the encoding, parsing
+/// may start over with a fresh sink.
+///
+/// Malformed input is dealt with as `opts.encoding.invalid_input` says.
+/// With `AbortOnInvalid`, the sink gets a `"Malformed input bytes"`
+/// error and whatever tree was built from the input before it.
+///
+/// ## Example
+///
+/// ```ignore
+/// let dom: RcDom = parse_bytes(Some(my_bytes).into_iter(), Default::default());
+/// ```
+pub fn parse_bytes<Output, It, B>(input: It, opts: ParseOpts) -> Output
+    where Output: ParseResult,
+          It: Iterator<Item=B>,
+          B: AsSlice<u8>,
+{
+    let new_tokenizer = || {
+        let sink: Output::Sink = Default::default();
+        Tokenizer::new(TreeBuilder::new(sink, opts.tree_builder), opts.tokenizer.clone())
+    };
+    let mut tok = new_tokenizer();
+    feed_bytes(&mut tok, input, opts.encoding, opts.deadline, |tok| *tok = new_tokenizer());
+    tok.end();
+    tok.unwrap().unwrap().finish()
+}
+
+// Decode bytes and feed them to `tok`, as `parse_bytes` describes.  If
+// the encoding changes too late, `restart` must make `tok` and its sink
+// ready to parse from the start again.
+fn feed_bytes<Handle, Sink, It, B, R>(tok: &mut Tokenizer<TreeBuilder<Handle, Sink>>,
+        mut input: It, opts: EncodingOpts, deadline: Option<u64>, mut restart: R)
+    where Handle: Clone,
+          Sink: TreeSink<Handle=Handle>,
+          It: Iterator<Item=B>,
+          B: AsSlice<u8>,
+          R: FnMut(&mut Tokenizer<TreeBuilder<Handle, Sink>>),
+{
+    // Wait for enough input to prescan.
+    let mut start = vec!();
+    while start.len() < PRESCAN_BYTES {
+        match input.next() {
+            Some(bytes) => start.push_all(bytes.as_slice()),
+            None => break,
+        }
+    }
+
+    let (encoding, confidence) = sniff_encoding(start.as_slice(), opts);
+    let mut decoder = TentativeDecoder::new(encoding, confidence);
+    decoder.set_invalid_input(opts.invalid_input);
+
+    // Did we stop before running out of input?
+    let mut stopped = false;
+    let mut pending = Some(start);
+    loop {
+        let mut s = String::new();
+        match pending.take() {
+            Some(bytes) => decoder.feed(bytes.as_slice(), &mut s),
+            None => {
+                let bytes = unwrap_or_else!(input.next(), { break });
+                decoder.feed(bytes.as_slice(), &mut s);
+            }
+        }
+        if !feed_all(tok, Some(s).into_iter(), deadline) {
+            stopped = true;
+            break;
+        }
+        if decoder.aborted() {
+            break;
+        }
+
+        match tok.sink_mut().take_encoding_change() {
+            None => (),
+            Some(enc) => match decoder.change_encoding(enc) {
+                Continue => (),
+                Restart(seen) => {
+                    // Throw away everything and parse again from the start.
+                    restart(tok);
+                    pending = Some(seen);
+                }
+            },
+        }
+    }
+
+    if stopped {
+        return;
+    }
+    let mut s = String::new();
+    decoder.end(&mut s);
+    tok.feed(s);
+    if decoder.aborted() {
+        tok.sink_mut().abort(Diagnostic::error("Malformed input bytes"));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::{char_input, str_input, one_input, parse, CHAR_INPUT_CHUNK};
+    use sink::rcdom::{RcDom, dump_tree};
+    use tokenizer::splits::{random_split, SplitRng};
+
+    #[test]
+    fn iterator_inputs() {
+        let input = "<title>\u{e9}</title><p>a<b>b</b>\u{1f600}<!--c-->";
+        let expected: RcDom = parse(one_input(String::from_str(input)), Default::default());
+
+        let from_chars: RcDom = parse(char_input(input.chars()), Default::default());
+        assert_eq!(dump_tree(&from_chars.document), dump_tree(&expected.document));
+
+        let mut rng = SplitRng::new(3);
+        for _ in 0..10u {
+            let pieces = random_split(input, &mut rng);
+            let from_strs: RcDom = parse(str_input(pieces.iter().map(|s| s.as_slice())),
+                Default::default());
+            assert_eq!(dump_tree(&from_strs.document), dump_tree(&expected.document));
+        }
+    }
+
+    #[test]
+    fn char_input_chunks() {
+        let lens: Vec<uint> = char_input((0..CHAR_INPUT_CHUNK * 2 + 1).map(|_| 'x'))
+            .map(|s| s.len()).collect();
+        assert_eq!(lens, vec!(CHAR_INPUT_CHUNK, CHAR_INPUT_CHUNK, 1));
+        assert_eq!(char_input("".chars()).next(), None);
+    }
 }
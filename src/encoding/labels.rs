@@ -0,0 +1,274 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use core::prelude::*;
+
+use super::Encoding;
+use super::Encoding::*;
+
+use util::str::{AsciiExt, is_ascii_whitespace};
+
+use phf::Map;
+
+// From https://encoding.spec.whatwg.org/#names-and-labels
+// All keys are lowercase.
+static LABELS: Map<&'static str, Encoding> = phf_map! {
+    "unicode-1-1-utf-8" => Utf8,
+    "unicode11utf8" => Utf8,
+    "unicode20utf8" => Utf8,
+    "utf-8" => Utf8,
+    "utf8" => Utf8,
+    "x-unicode20utf8" => Utf8,
+    "866" => Ibm866,
+    "cp866" => Ibm866,
+    "csibm866" => Ibm866,
+    "ibm866" => Ibm866,
+    "csisolatin2" => Iso8859_2,
+    "iso-8859-2" => Iso8859_2,
+    "iso-ir-101" => Iso8859_2,
+    "iso8859-2" => Iso8859_2,
+    "iso88592" => Iso8859_2,
+    "iso_8859-2" => Iso8859_2,
+    "iso_8859-2:1987" => Iso8859_2,
+    "l2" => Iso8859_2,
+    "latin2" => Iso8859_2,
+    "csisolatin3" => Iso8859_3,
+    "iso-8859-3" => Iso8859_3,
+    "iso-ir-109" => Iso8859_3,
+    "iso8859-3" => Iso8859_3,
+    "iso88593" => Iso8859_3,
+    "iso_8859-3" => Iso8859_3,
+    "iso_8859-3:1988" => Iso8859_3,
+    "l3" => Iso8859_3,
+    "latin3" => Iso8859_3,
+    "csisolatin4" => Iso8859_4,
+    "iso-8859-4" => Iso8859_4,
+    "iso-ir-110" => Iso8859_4,
+    "iso8859-4" => Iso8859_4,
+    "iso88594" => Iso8859_4,
+    "iso_8859-4" => Iso8859_4,
+    "iso_8859-4:1988" => Iso8859_4,
+    "l4" => Iso8859_4,
+    "latin4" => Iso8859_4,
+    "csisolatincyrillic" => Iso8859_5,
+    "cyrillic" => Iso8859_5,
+    "iso-8859-5" => Iso8859_5,
+    "iso-ir-144" => Iso8859_5,
+    "iso8859-5" => Iso8859_5,
+    "iso88595" => Iso8859_5,
+    "iso_8859-5" => Iso8859_5,
+    "iso_8859-5:1988" => Iso8859_5,
+    "arabic" => Iso8859_6,
+    "asmo-708" => Iso8859_6,
+    "csiso88596e" => Iso8859_6,
+    "csiso88596i" => Iso8859_6,
+    "csisolatinarabic" => Iso8859_6,
+    "ecma-114" => Iso8859_6,
+    "iso-8859-6" => Iso8859_6,
+    "iso-8859-6-e" => Iso8859_6,
+    "iso-8859-6-i" => Iso8859_6,
+    "iso-ir-127" => Iso8859_6,
+    "iso8859-6" => Iso8859_6,
+    "iso88596" => Iso8859_6,
+    "iso_8859-6" => Iso8859_6,
+    "iso_8859-6:1987" => Iso8859_6,
+    "csisolatingreek" => Iso8859_7,
+    "ecma-118" => Iso8859_7,
+    "elot_928" => Iso8859_7,
+    "greek" => Iso8859_7,
+    "greek8" => Iso8859_7,
+    "iso-8859-7" => Iso8859_7,
+    "iso-ir-126" => Iso8859_7,
+    "iso8859-7" => Iso8859_7,
+    "iso88597" => Iso8859_7,
+    "iso_8859-7" => Iso8859_7,
+    "iso_8859-7:1987" => Iso8859_7,
+    "sun_eu_greek" => Iso8859_7,
+    "csiso88598e" => Iso8859_8,
+    "csisolatinhebrew" => Iso8859_8,
+    "hebrew" => Iso8859_8,
+    "iso-8859-8" => Iso8859_8,
+    "iso-8859-8-e" => Iso8859_8,
+    "iso-ir-138" => Iso8859_8,
+    "iso8859-8" => Iso8859_8,
+    "iso88598" => Iso8859_8,
+    "iso_8859-8" => Iso8859_8,
+    "iso_8859-8:1988" => Iso8859_8,
+    "visual" => Iso8859_8,
+    "csiso88598i" => Iso8859_8I,
+    "iso-8859-8-i" => Iso8859_8I,
+    "logical" => Iso8859_8I,
+    "csisolatin6" => Iso8859_10,
+    "iso-8859-10" => Iso8859_10,
+    "iso-ir-157" => Iso8859_10,
+    "iso8859-10" => Iso8859_10,
+    "iso885910" => Iso8859_10,
+    "l6" => Iso8859_10,
+    "latin6" => Iso8859_10,
+    "iso-8859-13" => Iso8859_13,
+    "iso8859-13" => Iso8859_13,
+    "iso885913" => Iso8859_13,
+    "iso-8859-14" => Iso8859_14,
+    "iso8859-14" => Iso8859_14,
+    "iso885914" => Iso8859_14,
+    "csisolatin9" => Iso8859_15,
+    "iso-8859-15" => Iso8859_15,
+    "iso8859-15" => Iso8859_15,
+    "iso885915" => Iso8859_15,
+    "iso_8859-15" => Iso8859_15,
+    "l9" => Iso8859_15,
+    "iso-8859-16" => Iso8859_16,
+    "cskoi8r" => Koi8R,
+    "koi" => Koi8R,
+    "koi8" => Koi8R,
+    "koi8-r" => Koi8R,
+    "koi8_r" => Koi8R,
+    "koi8-ru" => Koi8U,
+    "koi8-u" => Koi8U,
+    "csmacintosh" => Macintosh,
+    "mac" => Macintosh,
+    "macintosh" => Macintosh,
+    "x-mac-roman" => Macintosh,
+    "dos-874" => Windows874,
+    "iso-8859-11" => Windows874,
+    "iso8859-11" => Windows874,
+    "iso885911" => Windows874,
+    "tis-620" => Windows874,
+    "windows-874" => Windows874,
+    "cp1250" => Windows1250,
+    "windows-1250" => Windows1250,
+    "x-cp1250" => Windows1250,
+    "cp1251" => Windows1251,
+    "windows-1251" => Windows1251,
+    "x-cp1251" => Windows1251,
+    "ansi_x3.4-1968" => Windows1252,
+    "ascii" => Windows1252,
+    "cp1252" => Windows1252,
+    "cp819" => Windows1252,
+    "csisolatin1" => Windows1252,
+    "ibm819" => Windows1252,
+    "iso-8859-1" => Windows1252,
+    "iso-ir-100" => Windows1252,
+    "iso8859-1" => Windows1252,
+    "iso88591" => Windows1252,
+    "iso_8859-1" => Windows1252,
+    "iso_8859-1:1987" => Windows1252,
+    "l1" => Windows1252,
+    "latin1" => Windows1252,
+    "us-ascii" => Windows1252,
+    "windows-1252" => Windows1252,
+    "x-cp1252" => Windows1252,
+    "cp1253" => Windows1253,
+    "windows-1253" => Windows1253,
+    "x-cp1253" => Windows1253,
+    "cp1254" => Windows1254,
+    "csisolatin5" => Windows1254,
+    "iso-8859-9" => Windows1254,
+    "iso-ir-148" => Windows1254,
+    "iso8859-9" => Windows1254,
+    "iso88599" => Windows1254,
+    "iso_8859-9" => Windows1254,
+    "iso_8859-9:1989" => Windows1254,
+    "l5" => Windows1254,
+    "latin5" => Windows1254,
+    "windows-1254" => Windows1254,
+    "x-cp1254" => Windows1254,
+    "cp1255" => Windows1255,
+    "windows-1255" => Windows1255,
+    "x-cp1255" => Windows1255,
+    "cp1256" => Windows1256,
+    "windows-1256" => Windows1256,
+    "x-cp1256" => Windows1256,
+    "cp1257" => Windows1257,
+    "windows-1257" => Windows1257,
+    "x-cp1257" => Windows1257,
+    "cp1258" => Windows1258,
+    "windows-1258" => Windows1258,
+    "x-cp1258" => Windows1258,
+    "x-mac-cyrillic" => XMacCyrillic,
+    "x-mac-ukrainian" => XMacCyrillic,
+    "chinese" => Gbk,
+    "csgb2312" => Gbk,
+    "csiso58gb231280" => Gbk,
+    "gb2312" => Gbk,
+    "gb_2312" => Gbk,
+    "gb_2312-80" => Gbk,
+    "gbk" => Gbk,
+    "iso-ir-58" => Gbk,
+    "x-gbk" => Gbk,
+    "gb18030" => Gb18030,
+    "big5" => Big5,
+    "big5-hkscs" => Big5,
+    "cn-big5" => Big5,
+    "csbig5" => Big5,
+    "x-x-big5" => Big5,
+    "cseucpkdfmtjapanese" => EucJp,
+    "euc-jp" => EucJp,
+    "x-euc-jp" => EucJp,
+    "csiso2022jp" => Iso2022Jp,
+    "iso-2022-jp" => Iso2022Jp,
+    "csshiftjis" => ShiftJis,
+    "ms932" => ShiftJis,
+    "ms_kanji" => ShiftJis,
+    "shift-jis" => ShiftJis,
+    "shift_jis" => ShiftJis,
+    "sjis" => ShiftJis,
+    "windows-31j" => ShiftJis,
+    "x-sjis" => ShiftJis,
+    "cseuckr" => EucKr,
+    "csksc56011987" => EucKr,
+    "euc-kr" => EucKr,
+    "iso-ir-149" => EucKr,
+    "korean" => EucKr,
+    "ks_c_5601-1987" => EucKr,
+    "ks_c_5601-1989" => EucKr,
+    "ksc5601" => EucKr,
+    "ksc_5601" => EucKr,
+    "windows-949" => EucKr,
+    "csiso2022kr" => Replacement,
+    "hz-gb-2312" => Replacement,
+    "iso-2022-cn" => Replacement,
+    "iso-2022-cn-ext" => Replacement,
+    "iso-2022-kr" => Replacement,
+    "replacement" => Replacement,
+    "unicodefffe" => Utf16Be,
+    "utf-16be" => Utf16Be,
+    "csunicode" => Utf16Le,
+    "iso-10646-ucs-2" => Utf16Le,
+    "ucs-2" => Utf16Le,
+    "unicode" => Utf16Le,
+    "unicodefeff" => Utf16Le,
+    "utf-16" => Utf16Le,
+    "utf-16le" => Utf16Le,
+    "x-user-defined" => XUserDefined,
+};
+
+/// Get the encoding for a label, per the Encoding spec's "get an
+/// encoding" algorithm.  Leading and trailing whitespace is ignored,
+/// and the match is ASCII case-insensitive.
+pub fn label_to_encoding(label: &str) -> Option<Encoding> {
+    let label = label.trim_matches(is_ascii_whitespace).to_ascii_lower();
+    LABELS.get(label.as_slice()).map(|&e| e)
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use super::label_to_encoding;
+    use super::super::{Utf8, Windows1252, Utf16Le, Replacement, ShiftJis};
+
+    test_eq!(label_utf8, label_to_encoding("utf-8"), Some(Utf8));
+    test_eq!(label_latin1, label_to_encoding("latin1"), Some(Windows1252));
+    test_eq!(label_ascii, label_to_encoding("US-ASCII"), Some(Windows1252));
+    test_eq!(label_utf16, label_to_encoding("utf-16"), Some(Utf16Le));
+    test_eq!(label_iso2022kr, label_to_encoding("iso-2022-kr"), Some(Replacement));
+    test_eq!(label_whitespace, label_to_encoding(" \tShift_JIS\n"), Some(ShiftJis));
+    test_eq!(label_unknown, label_to_encoding("utf-7"), None);
+    test_eq!(label_empty, label_to_encoding(""), None);
+}
@@ -0,0 +1,104 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Decoders for the "legacy miscellaneous encodings" other than UTF-16.
+
+use core::prelude::*;
+
+use super::{Decoder, InvalidInput, Malformed};
+
+use core::char::from_u32;
+use collections::string::String;
+
+/// Decoder for the `replacement` encoding.
+///
+/// Several labels for encodings which can smuggle ASCII-looking markup
+/// through other byte sequences (ISO-2022-KR, HZ-GB-2312, ...) map to
+/// this encoding.  Any non-empty input is one malformed sequence,
+/// which by default decodes to a single `U+FFFD`, so none of its
+/// content reaches the tokenizer.
+pub struct ReplacementDecoder {
+    emitted: bool,
+    malformed: Malformed,
+}
+
+impl ReplacementDecoder {
+    pub fn new() -> ReplacementDecoder {
+        ReplacementDecoder {
+            emitted: false,
+            malformed: Malformed::new(),
+        }
+    }
+}
+
+impl Decoder for ReplacementDecoder {
+    fn feed(&mut self, input: &[u8], output: &mut String) {
+        if !self.emitted && !input.is_empty() {
+            self.emitted = true;
+            self.malformed.found(output);
+        }
+    }
+
+    fn end(&mut self, _output: &mut String) { }
+
+    fn set_invalid_input(&mut self, policy: InvalidInput) {
+        self.malformed.policy = policy;
+    }
+
+    fn aborted(&self) -> bool {
+        self.malformed.aborted
+    }
+}
+
+/// Decoder for `x-user-defined`, which maps bytes 0x80 to 0xFF onto
+/// the Private Use Area at U+F780 to U+F7FF.
+#[derive(Copy)]
+pub struct XUserDefinedDecoder;
+
+impl Decoder for XUserDefinedDecoder {
+    fn feed(&mut self, input: &[u8], output: &mut String) {
+        for &b in input.iter() {
+            if b < 0x80 {
+                output.push(b as char);
+            } else {
+                let c = 0xF780 + (b as u32) - 0x80;
+                output.push(from_u32(c).expect("bad x-user-defined char"));
+            }
+        }
+    }
+
+    fn end(&mut self, _output: &mut String) { }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::string::String;
+    use super::{ReplacementDecoder, XUserDefinedDecoder};
+    use super::super::Decoder;
+
+    #[test]
+    fn replacement_emits_once() {
+        let mut dec = ReplacementDecoder::new();
+        let mut out = String::new();
+        dec.feed(&[], &mut out);
+        assert_eq!(out.as_slice(), "");
+        dec.feed(b"<script>", &mut out);
+        dec.feed(b"alert(1)</script>", &mut out);
+        dec.end(&mut out);
+        assert_eq!(out.as_slice(), "\u{fffd}");
+    }
+
+    #[test]
+    fn x_user_defined() {
+        let mut out = String::new();
+        XUserDefinedDecoder.feed(&[b'a', 0x80, 0xFF], &mut out);
+        assert_eq!(out.as_slice(), "a\u{f780}\u{f7ff}");
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use core::prelude::*;
+
+use super::{Encoding, label_to_encoding};
+
+use util::str::AsciiExt;
+
+use core::str;
+
+fn is_space(b: u8) -> bool {
+    match b {
+        b'\t' | b'\n' | b'\x0C' | b'\r' | b' ' => true,
+        _ => false,
+    }
+}
+
+fn skip_spaces(s: &[u8], mut pos: uint) -> uint {
+    while pos < s.len() && is_space(s[pos]) {
+        pos += 1;
+    }
+    pos
+}
+
+//§ extracting-character-encodings-from-meta-elements
+/// Find the encoding named by the `content` attribute of a
+/// `<meta http-equiv="Content-Type">`, such as
+/// `text/html; charset=utf-8`.
+pub fn extract_from_meta_content(content: &[u8]) -> Option<Encoding> {
+    let mut pos = 0;
+    loop {
+        // Find "charset", ASCII case-insensitively.
+        let found = (pos..content.len()).find(|&i| {
+            content.len() - i >= 7 && (&content[i..i+7]).eq_ignore_ascii_case(b"charset")
+        });
+        pos = skip_spaces(content, unwrap_or_return!(found, None) + 7);
+
+        if pos < content.len() && content[pos] == b'=' {
+            break;
+        }
+        // Look for the next "charset".
+    }
+
+    pos = skip_spaces(content, pos + 1);
+    if pos >= content.len() {
+        return None;
+    }
+
+    let value = match content[pos] {
+        q @ b'"' | q @ b'\'' => {
+            let rest = &content[pos+1..];
+            let end = unwrap_or_return!(rest.iter().position(|&b| b == q), None);
+            &rest[..end]
+        }
+        _ => {
+            let rest = &content[pos..];
+            let end = rest.iter().position(|&b| is_space(b) || b == b';')
+                .unwrap_or(rest.len());
+            &rest[..end]
+        }
+    };
+
+    label_to_encoding(unwrap_or_return!(str::from_utf8(value).ok(), None))
+}
+//§ END
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use super::extract_from_meta_content;
+    use super::super::{Utf8, Windows1252, ShiftJis};
+
+    macro_rules! test_extract ( ($name:ident, $input:expr, $expect:expr) => (
+        test_eq!($name, extract_from_meta_content($input.as_bytes()), $expect);
+    ));
+
+    test_extract!(extract_plain, "text/html; charset=utf-8", Some(Utf8));
+    test_extract!(extract_upper, "text/html; CHARSET = Shift_JIS", Some(ShiftJis));
+    test_extract!(extract_double_quoted, "text/html; charset=\"latin1\"", Some(Windows1252));
+    test_extract!(extract_single_quoted, "charset='utf-8';x=y", Some(Utf8));
+    test_extract!(extract_unterminated_quote, "charset=\"utf-8", None);
+    test_extract!(extract_semicolon, "charset=utf-8;foo", Some(Utf8));
+    test_extract!(extract_skips_no_equals, "charset; charset=utf-8", Some(Utf8));
+    test_extract!(extract_none, "text/html", None);
+    test_extract!(extract_empty_value, "charset=", None);
+    test_extract!(extract_unknown, "charset=bogus", None);
+}
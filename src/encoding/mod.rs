@@ -0,0 +1,381 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Converting input bytes to the UTF-8 strings the parser consumes.
+
+use core::prelude::*;
+
+use alloc::boxed::Box;
+use collections::vec::Vec;
+use collections::string::String;
+use core::default::Default;
+
+pub use self::Encoding::*;
+pub use self::InvalidInput::{ReplaceInvalid, SkipInvalid, AbortOnInvalid};
+pub use self::labels::label_to_encoding;
+pub use self::meta::extract_from_meta_content;
+pub use self::prescan::{prescan, PRESCAN_BYTES};
+pub use self::utf8::Utf8Decoder;
+pub use self::utf16::Utf16Decoder;
+pub use self::single_byte::Windows1252Decoder;
+pub use self::legacy::{ReplacementDecoder, XUserDefinedDecoder};
+pub use self::tentative::{TentativeDecoder, Confidence, Tentative, Certain, RESTART_WINDOW};
+pub use self::tentative::{ChangeEncoding, Continue, Restart};
+
+mod labels;
+mod meta;
+mod prescan;
+mod utf8;
+mod utf16;
+mod single_byte;
+mod legacy;
+mod tentative;
+
+/// A character encoding, as named by the WHATWG Encoding spec.
+///
+/// Not all of these can be decoded yet; see `new_decoder`.
+#[allow(non_camel_case_types)]
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum Encoding {
+    Utf8,
+    Ibm866,
+    Iso8859_2,
+    Iso8859_3,
+    Iso8859_4,
+    Iso8859_5,
+    Iso8859_6,
+    Iso8859_7,
+    Iso8859_8,
+    Iso8859_8I,
+    Iso8859_10,
+    Iso8859_13,
+    Iso8859_14,
+    Iso8859_15,
+    Iso8859_16,
+    Koi8R,
+    Koi8U,
+    Macintosh,
+    Windows874,
+    Windows1250,
+    Windows1251,
+    Windows1252,
+    Windows1253,
+    Windows1254,
+    Windows1255,
+    Windows1256,
+    Windows1257,
+    Windows1258,
+    XMacCyrillic,
+    Gbk,
+    Gb18030,
+    Big5,
+    EucJp,
+    Iso2022Jp,
+    ShiftJis,
+    EucKr,
+    Replacement,
+    Utf16Be,
+    Utf16Le,
+    XUserDefined,
+}
+
+impl Encoding {
+    /// The encoding's canonical name.
+    pub fn name(self) -> &'static str {
+        match self {
+            Utf8 => "UTF-8",
+            Ibm866 => "IBM866",
+            Iso8859_2 => "ISO-8859-2",
+            Iso8859_3 => "ISO-8859-3",
+            Iso8859_4 => "ISO-8859-4",
+            Iso8859_5 => "ISO-8859-5",
+            Iso8859_6 => "ISO-8859-6",
+            Iso8859_7 => "ISO-8859-7",
+            Iso8859_8 => "ISO-8859-8",
+            Iso8859_8I => "ISO-8859-8-I",
+            Iso8859_10 => "ISO-8859-10",
+            Iso8859_13 => "ISO-8859-13",
+            Iso8859_14 => "ISO-8859-14",
+            Iso8859_15 => "ISO-8859-15",
+            Iso8859_16 => "ISO-8859-16",
+            Koi8R => "KOI8-R",
+            Koi8U => "KOI8-U",
+            Macintosh => "macintosh",
+            Windows874 => "windows-874",
+            Windows1250 => "windows-1250",
+            Windows1251 => "windows-1251",
+            Windows1252 => "windows-1252",
+            Windows1253 => "windows-1253",
+            Windows1254 => "windows-1254",
+            Windows1255 => "windows-1255",
+            Windows1256 => "windows-1256",
+            Windows1257 => "windows-1257",
+            Windows1258 => "windows-1258",
+            XMacCyrillic => "x-mac-cyrillic",
+            Gbk => "GBK",
+            Gb18030 => "gb18030",
+            Big5 => "Big5",
+            EucJp => "EUC-JP",
+            Iso2022Jp => "ISO-2022-JP",
+            ShiftJis => "Shift_JIS",
+            EucKr => "EUC-KR",
+            Replacement => "replacement",
+            Utf16Be => "UTF-16BE",
+            Utf16Le => "UTF-16LE",
+            XUserDefined => "x-user-defined",
+        }
+    }
+
+    /// Does this encoding decode ASCII bytes as ASCII characters?
+    pub fn is_ascii_compatible(self) -> bool {
+        match self {
+            Utf16Le | Utf16Be | Iso2022Jp | Replacement => false,
+            _ => true,
+        }
+    }
+
+    /// Create a decoder for this encoding, or `None` if html5ever
+    /// can't decode it yet.
+    pub fn new_decoder(self) -> Option<Box<Decoder + 'static>> {
+        Some(match self {
+            Utf8 => box Utf8Decoder::new() as Box<Decoder>,
+            Utf16Le => box Utf16Decoder::new(false) as Box<Decoder>,
+            Utf16Be => box Utf16Decoder::new(true) as Box<Decoder>,
+            Windows1252 => box Windows1252Decoder as Box<Decoder>,
+            Replacement => box ReplacementDecoder::new() as Box<Decoder>,
+            XUserDefined => box XUserDefinedDecoder as Box<Decoder>,
+            _ => return None,
+        })
+    }
+
+    /// Can html5ever decode this encoding?
+    pub fn is_supported(self) -> bool {
+        self.new_decoder().is_some()
+    }
+}
+
+/// Options for determining the encoding of byte input.
+#[derive(Copy, Clone)]
+pub struct EncodingOpts {
+    /// The encoding given by the transport layer, e.g. the `charset`
+    /// parameter of an HTTP `Content-Type` header.  Default: `None`
+    pub transport_encoding: Option<Encoding>,
+
+    /// The encoding to use when nothing else determines one.
+    /// Default: windows-1252
+    pub default_encoding: Encoding,
+
+    /// What to do with malformed input.  Default: `ReplaceInvalid`
+    pub invalid_input: InvalidInput,
+}
+
+impl Default for EncodingOpts {
+    fn default() -> EncodingOpts {
+        EncodingOpts {
+            transport_encoding: None,
+            default_encoding: Windows1252,
+            invalid_input: ReplaceInvalid,
+        }
+    }
+}
+
+/// What a decoder does with malformed input, such as invalid UTF-8 or
+/// an unpaired UTF-16 surrogate.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum InvalidInput {
+    /// Replace each malformed sequence with `U+FFFD REPLACEMENT
+    /// CHARACTER`, as the Encoding spec does.
+    ReplaceInvalid,
+
+    /// Drop malformed sequences.
+    SkipInvalid,
+
+    /// Stop decoding at the first malformed sequence, ignoring it and
+    /// everything after.
+    AbortOnInvalid,
+}
+
+impl Default for InvalidInput {
+    fn default() -> InvalidInput {
+        ReplaceInvalid
+    }
+}
+
+/// Applies an `InvalidInput` policy, for decoders of encodings which
+/// can have malformed input.
+#[derive(Copy, Clone)]
+struct Malformed {
+    policy: InvalidInput,
+    aborted: bool,
+}
+
+impl Malformed {
+    fn new() -> Malformed {
+        Malformed {
+            policy: ReplaceInvalid,
+            aborted: false,
+        }
+    }
+
+    // Deal with one malformed sequence.
+    fn found(&mut self, output: &mut String) {
+        match self.policy {
+            ReplaceInvalid => output.push('\u{fffd}'),
+            SkipInvalid => (),
+            AbortOnInvalid => self.aborted = true,
+        }
+    }
+}
+
+//§ determining-the-character-encoding
+/// Look for a byte order mark at the start of the input.  Returns the
+/// encoding it indicates and the length of the BOM in bytes.
+pub fn sniff_bom(bytes: &[u8]) -> Option<(Encoding, uint)> {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        Some((Utf8, 3))
+    } else if bytes.starts_with(&[0xFE, 0xFF]) {
+        Some((Utf16Be, 2))
+    } else if bytes.starts_with(&[0xFF, 0xFE]) {
+        Some((Utf16Le, 2))
+    } else {
+        None
+    }
+}
+
+/// Determine the encoding of a document from its first bytes, which
+/// should be at least `PRESCAN_BYTES` long unless that's all the input.
+///
+/// A byte order mark is left in place for the decoder to handle.
+pub fn sniff_encoding(bytes: &[u8], opts: EncodingOpts) -> (Encoding, Confidence) {
+    match sniff_bom(bytes) {
+        Some((enc, _)) => return (enc, Certain),
+        None => (),
+    }
+
+    match opts.transport_encoding {
+        Some(enc) if enc.is_supported() => return (enc, Certain),
+        _ => (),
+    }
+
+    match prescan(bytes) {
+        Some(enc) if enc.is_supported() => (enc, Tentative),
+        _ => (opts.default_encoding, Tentative),
+    }
+}
+//§ END
+
+/// Incremental conversion of bytes in some encoding to UTF-8.
+///
+/// Input may be split anywhere, including in the middle of a character.
+/// Malformed input is replaced with `U+FFFD REPLACEMENT CHARACTER`,
+/// unless `set_invalid_input` says otherwise.
+pub trait Decoder {
+    /// Decode a chunk of input, appending the result to `output`.
+    fn feed(&mut self, input: &[u8], output: &mut String);
+
+    /// Signal the end of input, flushing any incomplete sequence
+    /// to `output`.
+    fn end(&mut self, output: &mut String);
+
+    /// Choose what to do with malformed input from now on.  Decoders
+    /// for encodings in which any bytes are valid ignore this.
+    fn set_invalid_input(&mut self, _policy: InvalidInput) { }
+
+    /// Has malformed input stopped decoding, under `AbortOnInvalid`?
+    /// If so, any further input is ignored.
+    fn aborted(&self) -> bool {
+        false
+    }
+}
+
+/// Adapts an iterator of byte buffers into an iterator of strings,
+/// suitable as input to `parse()` and friends.
+pub struct DecodeInput<It> {
+    input: It,
+    decoder: Box<Decoder + 'static>,
+    done: bool,
+}
+
+impl<It> DecodeInput<It>
+    where It: Iterator<Item=Vec<u8>>,
+{
+    pub fn new(input: It, decoder: Box<Decoder + 'static>) -> DecodeInput<It> {
+        DecodeInput {
+            input: input,
+            decoder: decoder,
+            done: false,
+        }
+    }
+
+    /// Choose what to do with malformed input.
+    pub fn set_invalid_input(&mut self, policy: InvalidInput) {
+        self.decoder.set_invalid_input(policy);
+    }
+
+    /// Did malformed input end the iteration early, under
+    /// `AbortOnInvalid`?
+    pub fn aborted(&self) -> bool {
+        self.decoder.aborted()
+    }
+}
+
+impl<It> Iterator for DecodeInput<It>
+    where It: Iterator<Item=Vec<u8>>,
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while !self.done {
+            let mut out = String::new();
+            match self.input.next() {
+                Some(bytes) => self.decoder.feed(bytes.as_slice(), &mut out),
+                None => {
+                    self.decoder.end(&mut out);
+                    self.done = true;
+                }
+            }
+            if self.decoder.aborted() {
+                self.done = true;
+            }
+            if !out.is_empty() {
+                return Some(out);
+            }
+        }
+        None
+    }
+}
+
+/// Decode input in the given encoding, or return `None` if
+/// it is not supported.
+pub fn decode_input<It>(input: It, encoding: Encoding) -> Option<DecodeInput<It>>
+    where It: Iterator<Item=Vec<u8>>,
+{
+    encoding.new_decoder().map(|d| DecodeInput::new(input, d))
+}
+
+/// Decode UTF-16 input.  A byte order mark at the start of the input
+/// determines the byte order; otherwise it is big-endian if `big_endian`
+/// is set, and little-endian if not.
+pub fn utf16_input<It>(input: It, big_endian: bool) -> DecodeInput<It>
+    where It: Iterator<Item=Vec<u8>>,
+{
+    DecodeInput::new(input, box Utf16Decoder::new(big_endian))
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use super::{sniff_bom, Utf8, Utf16Le, Utf16Be};
+
+    test_eq!(bom_utf8, sniff_bom(&[0xEF, 0xBB, 0xBF, b'<']), Some((Utf8, 3)));
+    test_eq!(bom_utf16le, sniff_bom(&[0xFF, 0xFE, b'<', 0]), Some((Utf16Le, 2)));
+    test_eq!(bom_utf16be, sniff_bom(&[0xFE, 0xFF, 0, b'<']), Some((Utf16Be, 2)));
+    test_eq!(bom_none, sniff_bom(b"<html>"), None);
+    test_eq!(bom_short, sniff_bom(&[0xEF, 0xBB]), None);
+}
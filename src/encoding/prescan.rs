@@ -0,0 +1,284 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use core::prelude::*;
+
+use super::{Encoding, Utf8, Utf16Le, Utf16Be, XUserDefined, Windows1252};
+use super::{label_to_encoding, extract_from_meta_content};
+
+use util::str::AsciiExt;
+
+use collections::vec::Vec;
+use core::str;
+
+/// How many bytes of input the prescan examines.
+pub const PRESCAN_BYTES: uint = 1024;
+
+fn is_space(b: u8) -> bool {
+    match b {
+        b'\t' | b'\n' | b'\x0C' | b'\r' | b' ' => true,
+        _ => false,
+    }
+}
+
+fn is_ascii_letter(b: u8) -> bool {
+    match b {
+        b'a'...b'z' | b'A'...b'Z' => true,
+        _ => false,
+    }
+}
+
+fn lower(b: u8) -> u8 {
+    match b {
+        b'A'...b'Z' => b + 0x20,
+        _ => b,
+    }
+}
+
+/// Returned by `get_attribute` when we run off the end of the input,
+/// which aborts the whole prescan.
+struct EndOfInput;
+
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: uint,
+}
+
+impl<'a> Scanner<'a> {
+    fn peek(&self) -> Result<u8, EndOfInput> {
+        match self.bytes.get(self.pos) {
+            Some(&b) => Ok(b),
+            None => Err(EndOfInput),
+        }
+    }
+
+    fn at(&self, pat: &[u8]) -> bool {
+        self.bytes[self.pos..].starts_with(pat)
+    }
+
+    fn at_ignore_case(&self, pat: &[u8]) -> bool {
+        let rest = &self.bytes[self.pos..];
+        rest.len() >= pat.len() && (&rest[..pat.len()]).eq_ignore_ascii_case(pat)
+    }
+
+    /// Move to the next occurrence of `pat` at or after `from`.
+    fn skip_to(&mut self, from: uint, pat: &[u8]) -> Result<(), EndOfInput> {
+        let mut i = from;
+        while i < self.bytes.len() {
+            if self.bytes[i..].starts_with(pat) {
+                self.pos = i;
+                return Ok(());
+            }
+            i += 1;
+        }
+        Err(EndOfInput)
+    }
+
+    //§ concept-get-attributes-when-sniffing
+    fn get_attribute(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>, EndOfInput> {
+        loop {
+            match try!(self.peek()) {
+                b'/' => (),
+                b if is_space(b) => (),
+                b'>' => return Ok(None),
+                _ => break,
+            }
+            self.pos += 1;
+        }
+
+        let mut name = vec!();
+        let mut value = vec!();
+
+        // Attribute name.
+        loop {
+            match try!(self.peek()) {
+                b'=' if !name.is_empty() => break,
+                b if is_space(b) => {
+                    while is_space(try!(self.peek())) {
+                        self.pos += 1;
+                    }
+                    if try!(self.peek()) != b'=' {
+                        return Ok(Some((name, value)));
+                    }
+                    break;
+                }
+                b'/' | b'>' => return Ok(Some((name, value))),
+                b => name.push(lower(b)),
+            }
+            self.pos += 1;
+        }
+
+        // Skip the '=' and any whitespace after it.
+        self.pos += 1;
+        while is_space(try!(self.peek())) {
+            self.pos += 1;
+        }
+
+        // Attribute value.
+        match try!(self.peek()) {
+            q @ b'"' | q @ b'\'' => loop {
+                self.pos += 1;
+                match try!(self.peek()) {
+                    b if b == q => {
+                        self.pos += 1;
+                        return Ok(Some((name, value)));
+                    }
+                    b => value.push(lower(b)),
+                }
+            },
+            b'>' => return Ok(Some((name, value))),
+            _ => (),
+        }
+
+        loop {
+            match try!(self.peek()) {
+                b'>' => return Ok(Some((name, value))),
+                b if is_space(b) => return Ok(Some((name, value))),
+                b => value.push(lower(b)),
+            }
+            self.pos += 1;
+        }
+    }
+    //§ END
+
+    fn meta(&mut self) -> Result<Option<Encoding>, EndOfInput> {
+        // Point at the space or slash after "<meta".
+        self.pos += 5;
+
+        let mut seen: Vec<Vec<u8>> = vec!();
+        let mut got_pragma = false;
+        let mut need_pragma = None;
+        let mut charset = None;
+
+        loop {
+            let (name, value) = match try!(self.get_attribute()) {
+                Some(attr) => attr,
+                None => break,
+            };
+            if seen.contains(&name) {
+                continue;
+            }
+
+            match str::from_utf8(name.as_slice()) {
+                Ok("http-equiv") => if value.as_slice() == &b"content-type"[..] {
+                    got_pragma = true;
+                },
+                Ok("content") => if charset.is_none() {
+                    let enc = extract_from_meta_content(value.as_slice());
+                    if enc.is_some() {
+                        charset = enc;
+                        need_pragma = Some(true);
+                    }
+                },
+                Ok("charset") => if charset.is_none() {
+                    charset = str::from_utf8(value.as_slice()).ok()
+                        .and_then(label_to_encoding);
+                    need_pragma = Some(false);
+                },
+                _ => (),
+            }
+            seen.push(name);
+        }
+
+        match (need_pragma, charset) {
+            (None, _) | (_, None) => Ok(None),
+            (Some(true), _) if !got_pragma => Ok(None),
+            (_, Some(Utf16Le)) | (_, Some(Utf16Be)) => Ok(Some(Utf8)),
+            (_, Some(XUserDefined)) => Ok(Some(Windows1252)),
+            (_, enc) => Ok(enc),
+        }
+    }
+
+    fn run(&mut self) -> Result<Option<Encoding>, EndOfInput> {
+        while self.pos < self.bytes.len() {
+            if self.at(b"<!--") {
+                let from = self.pos + 2;
+                try!(self.skip_to(from, b"-->"));
+                self.pos += 2;
+            } else if self.at_ignore_case(b"<meta")
+                && self.bytes.get(self.pos + 5).map_or(false, |&b| is_space(b) || b == b'/') {
+                match try!(self.meta()) {
+                    Some(enc) => return Ok(Some(enc)),
+                    None => (),
+                }
+            } else if (self.at(b"<") && self.bytes.get(self.pos + 1).map_or(false, |&b| is_ascii_letter(b)))
+                || (self.at(b"</") && self.bytes.get(self.pos + 2).map_or(false, |&b| is_ascii_letter(b))) {
+                loop {
+                    match try!(self.peek()) {
+                        b'>' => break,
+                        b if is_space(b) => break,
+                        _ => self.pos += 1,
+                    }
+                }
+                while try!(self.get_attribute()).is_some() { }
+            } else if self.at(b"<!") || self.at(b"</") || self.at(b"<?") {
+                let from = self.pos + 2;
+                try!(self.skip_to(from, b">"));
+            }
+            self.pos += 1;
+        }
+        Ok(None)
+    }
+}
+
+//§ prescan-a-byte-stream-to-determine-its-encoding
+/// Look for a `<meta>` element declaring the encoding within the first
+/// `PRESCAN_BYTES` bytes of `bytes`.
+pub fn prescan(bytes: &[u8]) -> Option<Encoding> {
+    let len = if bytes.len() < PRESCAN_BYTES { bytes.len() } else { PRESCAN_BYTES };
+    let mut scanner = Scanner {
+        bytes: &bytes[..len],
+        pos: 0,
+    };
+    scanner.run().unwrap_or(None)
+}
+//§ END
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::string::String;
+    use super::prescan;
+    use super::super::{Utf8, Windows1252, ShiftJis};
+
+    macro_rules! test_prescan ( ($name:ident, $input:expr, $expect:expr) => (
+        test_eq!($name, prescan($input.as_bytes()), $expect);
+    ));
+
+    test_prescan!(meta_charset, "<meta charset=utf-8>", Some(Utf8));
+    test_prescan!(meta_charset_quoted, "<!DOCTYPE html><META CHARSET='Shift_JIS'>", Some(ShiftJis));
+    test_prescan!(meta_pragma,
+        "<meta http-equiv=Content-Type content='text/html; charset=latin1'>", Some(Windows1252));
+    test_prescan!(meta_pragma_reversed,
+        "<meta content=\"text/html; charset=utf-8\" http-equiv=\"content-type\">", Some(Utf8));
+    test_prescan!(meta_content_without_pragma,
+        "<meta content='text/html; charset=utf-8'>", None);
+    test_prescan!(meta_utf16_means_utf8, "<meta charset=utf-16le>", Some(Utf8));
+    test_prescan!(meta_in_comment, "<!-- <meta charset=utf-8> -->", None);
+    test_prescan!(meta_after_short_comment, "<!--><meta charset=utf-8>", Some(Utf8));
+    test_prescan!(meta_in_attribute, "<p title='<meta charset=utf-8>'>", None);
+    test_prescan!(meta_after_tags, "<html><head><title>x</title><meta charset=utf-8>", Some(Utf8));
+    test_prescan!(meta_content_before_charset,
+        "<meta http-equiv=content-type content='text/html; charset=latin1' charset=utf-8>",
+        Some(Windows1252));
+    test_prescan!(meta_duplicate_attr, "<meta charset=utf-8 charset=latin1>", Some(Utf8));
+    test_prescan!(meta_truncated, "<meta charset=utf-8", None);
+    test_prescan!(not_meta, "<metal charset=utf-8>", None);
+    test_prescan!(no_meta, "<html><body>hello", None);
+
+    #[test]
+    fn too_late() {
+        let mut input = String::from_str("<p>");
+        for _ in range(0u, 1024) {
+            input.push('x');
+        }
+        input.push_str("<meta charset=utf-8>");
+        assert_eq!(prescan(input.as_bytes()), None);
+    }
+}
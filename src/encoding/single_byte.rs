@@ -0,0 +1,64 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Decoders for the legacy single-byte encodings.
+
+use core::prelude::*;
+
+use super::Decoder;
+
+use core::char::from_u32;
+use collections::string::String;
+
+/// windows-1252 assigns characters to most of 0x80 to 0x9F; the bytes
+/// it leaves unassigned map to the C1 control with the same value.
+/// The rest of the range is identical to ISO-8859-1.
+static WINDOWS_1252_C1: [char; 32] = [
+    '\u{20ac}', '\u{0081}', '\u{201a}', '\u{0192}',
+    '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02c6}', '\u{2030}', '\u{0160}', '\u{2039}',
+    '\u{0152}', '\u{008d}', '\u{017d}', '\u{008f}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201c}',
+    '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02dc}', '\u{2122}', '\u{0161}', '\u{203a}',
+    '\u{0153}', '\u{009d}', '\u{017e}', '\u{0178}',
+];
+
+/// Decoder for windows-1252, which the Encoding spec also uses for
+/// the `iso-8859-1` and `us-ascii` labels.
+#[derive(Copy)]
+pub struct Windows1252Decoder;
+
+impl Decoder for Windows1252Decoder {
+    fn feed(&mut self, input: &[u8], output: &mut String) {
+        for &b in input.iter() {
+            match b {
+                0x80...0x9F => output.push(WINDOWS_1252_C1[(b - 0x80) as uint]),
+                _ => output.push(from_u32(b as u32).expect("bad Latin-1 char")),
+            }
+        }
+    }
+
+    fn end(&mut self, _output: &mut String) { }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::string::String;
+    use super::Windows1252Decoder;
+    use super::super::Decoder;
+
+    #[test]
+    fn windows_1252() {
+        let mut out = String::new();
+        Windows1252Decoder.feed(&[b'a', 0x80, 0x81, 0xE9], &mut out);
+        assert_eq!(out.as_slice(), "a\u{20ac}\u{81}\u{e9}");
+    }
+}
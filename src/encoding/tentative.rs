@@ -0,0 +1,288 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use core::prelude::*;
+
+use super::{Encoding, Decoder, Utf8, Utf16Le, Utf16Be, XUserDefined, Windows1252};
+use super::{InvalidInput, ReplaceInvalid};
+
+use alloc::boxed::Box;
+use collections::vec::Vec;
+use collections::string::String;
+use core::mem::replace;
+
+pub use self::Confidence::{Tentative, Certain};
+pub use self::ChangeEncoding::{Continue, Restart};
+
+/// How sure we are of the document's encoding.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum Confidence {
+    /// The encoding was guessed, and may be changed by a `<meta>`.
+    Tentative,
+
+    /// The encoding is known and won't change.
+    Certain,
+}
+
+/// How many bytes of input a `TentativeDecoder` keeps in case it must
+/// restart.  Once more input than this has been seen, a change of
+/// encoding which would need a restart is ignored, and confidence
+/// becomes `Certain` as soon as the input isn't all ASCII.
+pub const RESTART_WINDOW: uint = 64 * 1024;
+
+/// What the parser must do after a request to change the encoding.
+#[derive(PartialEq, Eq, Debug)]
+pub enum ChangeEncoding {
+    /// Keep going.  The decoder has switched encodings if necessary.
+    Continue,
+
+    /// The input seen so far would decode differently in the new encoding,
+    /// so the document must be parsed again from the start.  This holds
+    /// all of the input bytes consumed so far; the new encoding should be
+    /// used with `Certain` confidence.
+    Restart(Vec<u8>),
+}
+
+/// A decoder for input whose encoding may be changed partway through,
+/// as happens when a `<meta charset>` disagrees with the sniffed encoding.
+pub struct TentativeDecoder {
+    encoding: Encoding,
+    confidence: Confidence,
+    decoder: Box<Decoder + 'static>,
+
+    /// The input consumed so far, kept while confidence is tentative in
+    /// case we must restart.
+    consumed: Vec<u8>,
+
+    /// Has more than `RESTART_WINDOW` bytes of input been consumed?
+    past_window: bool,
+
+    /// Has all input consumed so far been ASCII?
+    all_ascii: bool,
+
+    /// What to do with malformed input, in this and later encodings.
+    invalid_input: InvalidInput,
+}
+
+impl TentativeDecoder {
+    /// Create a decoder.  Panics if `encoding` is not supported;
+    /// check `Encoding::new_decoder` first.
+    pub fn new(encoding: Encoding, confidence: Confidence) -> TentativeDecoder {
+        TentativeDecoder {
+            encoding: encoding,
+            confidence: confidence,
+            decoder: encoding.new_decoder().expect("unsupported encoding"),
+            consumed: vec!(),
+            past_window: false,
+            all_ascii: true,
+            invalid_input: ReplaceInvalid,
+        }
+    }
+
+    /// Choose what to do with malformed input, including after the
+    /// encoding changes.
+    pub fn set_invalid_input(&mut self, policy: InvalidInput) {
+        self.invalid_input = policy;
+        self.decoder.set_invalid_input(policy);
+    }
+
+    /// Has malformed input stopped decoding, under `AbortOnInvalid`?
+    pub fn aborted(&self) -> bool {
+        self.decoder.aborted()
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn confidence(&self) -> Confidence {
+        self.confidence
+    }
+
+    /// Decode a chunk of input, appending the result to `output`.
+    pub fn feed(&mut self, input: &[u8], output: &mut String) {
+        if self.confidence == Tentative {
+            self.all_ascii = self.all_ascii && input.iter().all(|&b| b < 0x80);
+            if !self.past_window && self.consumed.len() + input.len() > RESTART_WINDOW {
+                self.past_window = true;
+                self.consumed = vec!();
+            }
+            if !self.past_window {
+                self.consumed.push_all(input);
+            } else if !self.all_ascii {
+                // Too late to restart, and too late to switch in place.
+                self.make_certain();
+            }
+        }
+        self.decoder.feed(input, output);
+    }
+
+    /// Signal the end of input.
+    pub fn end(&mut self, output: &mut String) {
+        self.decoder.end(output);
+    }
+
+    /// Stop accepting changes of encoding, and stop keeping input for a
+    /// restart.  A parser may call this once it's past the point where a
+    /// `<meta>` could change the encoding, such as the end of `<head>`.
+    pub fn make_certain(&mut self) {
+        self.confidence = Certain;
+        self.consumed = vec!();
+    }
+
+    //§ changing-the-encoding-while-parsing
+    /// Change to the encoding `new`, which was found in a `<meta>`.
+    /// Encodings we can't decode are ignored.
+    pub fn change_encoding(&mut self, new: Encoding) -> ChangeEncoding {
+        if self.confidence == Certain {
+            return Continue;
+        }
+
+        // 1.
+        if self.encoding == Utf16Le || self.encoding == Utf16Be {
+            self.make_certain();
+            return Continue;
+        }
+
+        // 2. 3.
+        let new = match new {
+            Utf16Le | Utf16Be => Utf8,
+            XUserDefined => Windows1252,
+            e => e,
+        };
+
+        // 4.
+        if new == self.encoding {
+            self.make_certain();
+            return Continue;
+        }
+
+        let mut decoder = unwrap_or_return!(new.new_decoder(), Continue);
+        decoder.set_invalid_input(self.invalid_input);
+
+        // 5. Everything converted so far reads the same in both encodings,
+        // so switch decoders without restarting.  We only detect the common
+        // case where it was all ASCII, which also guarantees the old decoder
+        // holds no partial character.
+        if self.all_ascii && self.encoding.is_ascii_compatible()
+            && new.is_ascii_compatible() {
+            self.encoding = new;
+            self.decoder = decoder;
+            self.make_certain();
+            return Continue;
+        }
+
+        // We no longer have the input to restart with.
+        if self.past_window {
+            self.make_certain();
+            return Continue;
+        }
+
+        // 6.
+        self.encoding = new;
+        self.decoder = decoder;
+        self.confidence = Certain;
+        Restart(replace(&mut self.consumed, vec!()))
+    }
+    //§ END
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+    use super::{TentativeDecoder, Tentative, Certain, Continue, Restart, RESTART_WINDOW};
+    use super::super::{Utf8, Utf16Le, Windows1252, XUserDefined};
+
+    #[test]
+    fn in_place_when_ascii() {
+        let mut dec = TentativeDecoder::new(Windows1252, Tentative);
+        let mut out = String::new();
+        dec.feed(b"<meta charset=utf-8>", &mut out);
+        assert_eq!(dec.change_encoding(Utf8), Continue);
+        assert_eq!(dec.encoding(), Utf8);
+        assert_eq!(dec.confidence(), Certain);
+        dec.feed(&[0xC3, 0xA9], &mut out);
+        assert_eq!(out.as_slice(), "<meta charset=utf-8>\u{e9}");
+    }
+
+    #[test]
+    fn restart_when_not_ascii() {
+        let mut dec = TentativeDecoder::new(Windows1252, Tentative);
+        let mut out = String::new();
+        dec.feed(&[b'a', 0xE9], &mut out);
+        assert_eq!(dec.change_encoding(Utf8), Restart(vec![b'a', 0xE9]));
+        assert_eq!(dec.confidence(), Certain);
+    }
+
+    #[test]
+    fn same_encoding_becomes_certain() {
+        let mut dec = TentativeDecoder::new(Utf8, Tentative);
+        assert_eq!(dec.change_encoding(Utf16Le), Continue);
+        assert_eq!(dec.encoding(), Utf8);
+        assert_eq!(dec.confidence(), Certain);
+    }
+
+    #[test]
+    fn x_user_defined_means_windows_1252() {
+        let mut dec = TentativeDecoder::new(Utf8, Tentative);
+        assert_eq!(dec.change_encoding(XUserDefined), Continue);
+        assert_eq!(dec.encoding(), Windows1252);
+    }
+
+    #[test]
+    fn restart_window() {
+        let mut dec = TentativeDecoder::new(Windows1252, Tentative);
+        let mut out = String::new();
+        let ascii: Vec<u8> = (0..RESTART_WINDOW).map(|_| b'a').collect();
+        dec.feed(ascii.as_slice(), &mut out);
+        dec.feed(b"a", &mut out);
+        assert_eq!(dec.confidence(), Tentative);
+        assert_eq!(dec.change_encoding(Utf8), Continue);
+        assert_eq!(dec.encoding(), Utf8);
+
+        let mut dec = TentativeDecoder::new(Windows1252, Tentative);
+        dec.feed(ascii.as_slice(), &mut out);
+        dec.feed(&[0xE9], &mut out);
+        assert_eq!(dec.confidence(), Certain);
+        assert_eq!(dec.change_encoding(Utf8), Continue);
+        assert_eq!(dec.encoding(), Windows1252);
+    }
+
+    #[test]
+    fn no_restart_past_window() {
+        // A single chunk which is larger than the window and not ASCII.
+        let mut dec = TentativeDecoder::new(Windows1252, Tentative);
+        let mut out = String::new();
+        let mut input: Vec<u8> = vec![0xE9];
+        input.extend((0..RESTART_WINDOW).map(|_| b'a'));
+        dec.feed(input.as_slice(), &mut out);
+        assert_eq!(dec.change_encoding(Utf8), Continue);
+        assert_eq!(dec.encoding(), Windows1252);
+        assert_eq!(dec.confidence(), Certain);
+    }
+
+    #[test]
+    fn make_certain() {
+        let mut dec = TentativeDecoder::new(Windows1252, Tentative);
+        let mut out = String::new();
+        dec.feed(&[b'a', 0xE9], &mut out);
+        dec.make_certain();
+        assert_eq!(dec.change_encoding(Utf8), Continue);
+        assert_eq!(dec.encoding(), Windows1252);
+    }
+
+    #[test]
+    fn certain_is_final() {
+        let mut dec = TentativeDecoder::new(Utf8, Certain);
+        assert_eq!(dec.change_encoding(Windows1252), Continue);
+        assert_eq!(dec.encoding(), Utf8);
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use core::prelude::*;
+
+use super::{Decoder, InvalidInput, Malformed};
+
+use core::char::from_u32;
+use collections::string::String;
+
+/// Incremental UTF-16 decoder.
+pub struct Utf16Decoder {
+    big_endian: bool,
+
+    /// Have we yet to look for a byte order mark?
+    check_bom: bool,
+
+    /// The first byte of a code unit which was split between chunks.
+    pending_byte: Option<u8>,
+
+    /// A lead surrogate waiting for its trail surrogate.
+    pending_lead: Option<u16>,
+
+    malformed: Malformed,
+}
+
+impl Utf16Decoder {
+    /// Create a decoder.  A byte order mark at the start of the input
+    /// overrides `big_endian`.
+    pub fn new(big_endian: bool) -> Utf16Decoder {
+        Utf16Decoder {
+            big_endian: big_endian,
+            check_bom: true,
+            pending_byte: None,
+            pending_lead: None,
+            malformed: Malformed::new(),
+        }
+    }
+
+    fn push_unit(&mut self, unit: u16, output: &mut String) {
+        match self.pending_lead.take() {
+            Some(lead) => match unit {
+                0xDC00...0xDFFF => {
+                    let c = 0x10000 + (((lead - 0xD800) as u32) << 10)
+                        + ((unit - 0xDC00) as u32);
+                    output.push(from_u32(c).expect("bad surrogate pair"));
+                    return;
+                }
+                // Unpaired lead surrogate; process `unit` by itself.
+                _ => {
+                    self.malformed.found(output);
+                    if self.malformed.aborted {
+                        return;
+                    }
+                }
+            },
+            None => (),
+        }
+
+        match unit {
+            0xD800...0xDBFF => self.pending_lead = Some(unit),
+            0xDC00...0xDFFF => self.malformed.found(output),
+            _ => output.push(from_u32(unit as u32).expect("bad code unit")),
+        }
+    }
+}
+
+impl Decoder for Utf16Decoder {
+    fn feed(&mut self, input: &[u8], output: &mut String) {
+        for &b in input.iter() {
+            if self.malformed.aborted {
+                return;
+            }
+            let first = unwrap_or_else!(self.pending_byte.take(), {
+                self.pending_byte = Some(b);
+                continue;
+            });
+
+            if self.check_bom {
+                self.check_bom = false;
+                match (first, b) {
+                    (0xFE, 0xFF) => { self.big_endian = true; continue; }
+                    (0xFF, 0xFE) => { self.big_endian = false; continue; }
+                    _ => (),
+                }
+            }
+
+            let unit = if self.big_endian {
+                ((first as u16) << 8) | (b as u16)
+            } else {
+                ((b as u16) << 8) | (first as u16)
+            };
+            self.push_unit(unit, output);
+        }
+    }
+
+    fn end(&mut self, output: &mut String) {
+        if self.pending_lead.take().is_some() && !self.malformed.aborted {
+            self.malformed.found(output);
+        }
+        if self.pending_byte.take().is_some() && !self.malformed.aborted {
+            self.malformed.found(output);
+        }
+    }
+
+    fn set_invalid_input(&mut self, policy: InvalidInput) {
+        self.malformed.policy = policy;
+    }
+
+    fn aborted(&self) -> bool {
+        self.malformed.aborted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::string::String;
+    use super::Utf16Decoder;
+    use super::super::{Decoder, InvalidInput, ReplaceInvalid, SkipInvalid, AbortOnInvalid};
+
+    fn decode(chunks: &[&[u8]], big_endian: bool) -> String {
+        decode_with(chunks, big_endian, ReplaceInvalid)
+    }
+
+    fn decode_with(chunks: &[&[u8]], big_endian: bool, policy: InvalidInput) -> String {
+        let mut dec = Utf16Decoder::new(big_endian);
+        dec.set_invalid_input(policy);
+        let mut out = String::new();
+        for chunk in chunks.iter() {
+            dec.feed(*chunk, &mut out);
+        }
+        dec.end(&mut out);
+        out
+    }
+
+    #[test]
+    fn little_endian() {
+        assert_eq!(decode(&[&[b'h', 0, b'i', 0]], false).as_slice(), "hi");
+    }
+
+    #[test]
+    fn big_endian() {
+        assert_eq!(decode(&[&[0, b'h', 0, b'i']], true).as_slice(), "hi");
+    }
+
+    #[test]
+    fn bom_overrides_default() {
+        assert_eq!(decode(&[&[0xFE, 0xFF, 0, b'h']], false).as_slice(), "h");
+        assert_eq!(decode(&[&[0xFF, 0xFE, b'h', 0]], true).as_slice(), "h");
+    }
+
+    #[test]
+    fn split_code_unit_and_pair() {
+        // U+1F600 is D83D DE00.
+        let out = decode(&[&[0x3D], &[0xD8, 0x00], &[0xDE]], false);
+        assert_eq!(out.as_slice(), "\u{1f600}");
+    }
+
+    #[test]
+    fn unpaired_surrogates() {
+        assert_eq!(decode(&[&[0x00, 0xDC, b'a', 0]], false).as_slice(), "\u{fffd}a");
+        assert_eq!(decode(&[&[0x3D, 0xD8, b'a', 0]], false).as_slice(), "\u{fffd}a");
+        assert_eq!(decode(&[&[0x3D, 0xD8]], false).as_slice(), "\u{fffd}");
+    }
+
+    #[test]
+    fn odd_length() {
+        assert_eq!(decode(&[&[b'a', 0, b'b']], false).as_slice(), "a\u{fffd}");
+    }
+
+    #[test]
+    fn skip_and_abort() {
+        // A lead surrogate split from the unit after it.
+        let input: &[&[u8]] = &[&[b'a', 0, 0x3D], &[0xD8], &[b'b', 0, 0x00, 0xDC, b'c', 0]];
+        assert_eq!(decode_with(input, false, SkipInvalid).as_slice(), "abc");
+        assert_eq!(decode_with(input, false, AbortOnInvalid).as_slice(), "a");
+    }
+}
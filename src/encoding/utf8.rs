@@ -0,0 +1,236 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use core::prelude::*;
+
+use super::{Decoder, InvalidInput, Malformed};
+
+use core::str;
+use core::char::from_u32;
+use collections::string::String;
+
+/// Incremental UTF-8 decoder, following the WHATWG "UTF-8 decoder"
+/// algorithm.  Each maximal invalid subsequence becomes one `U+FFFD`.
+///
+/// Runs of well-formed input are validated and then copied as they
+/// are, rather than decoded and re-encoded a character at a time.
+pub struct Utf8Decoder {
+    code_point: u32,
+    bytes_needed: u8,
+    lower_boundary: u8,
+    upper_boundary: u8,
+    malformed: Malformed,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Utf8Decoder {
+        Utf8Decoder {
+            code_point: 0,
+            bytes_needed: 0,
+            lower_boundary: 0x80,
+            upper_boundary: 0xBF,
+            malformed: Malformed::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.code_point = 0;
+        self.bytes_needed = 0;
+        self.lower_boundary = 0x80;
+        self.upper_boundary = 0xBF;
+    }
+
+    // Returns true if the byte should be processed again.
+    fn step(&mut self, b: u8, output: &mut String) -> bool {
+        if self.bytes_needed == 0 {
+            match b {
+                0x00...0x7F => output.push(b as char),
+                0xC2...0xDF => {
+                    self.bytes_needed = 1;
+                    self.code_point = (b & 0x1F) as u32;
+                }
+                0xE0...0xEF => {
+                    if b == 0xE0 { self.lower_boundary = 0xA0; }
+                    if b == 0xED { self.upper_boundary = 0x9F; }
+                    self.bytes_needed = 2;
+                    self.code_point = (b & 0x0F) as u32;
+                }
+                0xF0...0xF4 => {
+                    if b == 0xF0 { self.lower_boundary = 0x90; }
+                    if b == 0xF4 { self.upper_boundary = 0x8F; }
+                    self.bytes_needed = 3;
+                    self.code_point = (b & 0x07) as u32;
+                }
+                _ => self.malformed.found(output),
+            }
+            return false;
+        }
+
+        if b < self.lower_boundary || b > self.upper_boundary {
+            self.reset();
+            self.malformed.found(output);
+            return true;
+        }
+
+        self.lower_boundary = 0x80;
+        self.upper_boundary = 0xBF;
+        self.code_point = (self.code_point << 6) | ((b & 0x3F) as u32);
+        self.bytes_needed -= 1;
+        if self.bytes_needed == 0 {
+            let c = from_u32(self.code_point).expect("bad UTF-8 decoder state");
+            output.push(c);
+            self.reset();
+        }
+        false
+    }
+}
+
+// The length of the longest prefix of `input` which is a sequence of
+// complete, well-formed characters.
+fn valid_prefix(input: &[u8]) -> uint {
+    let mut i = 0;
+    while i < input.len() {
+        let (len, lower, upper) = match input[i] {
+            0x00...0x7F => {
+                i += 1;
+                continue;
+            }
+            0xC2...0xDF => (2, 0x80, 0xBF),
+            0xE0 => (3, 0xA0, 0xBF),
+            0xED => (3, 0x80, 0x9F),
+            0xE1...0xEF => (3, 0x80, 0xBF),
+            0xF0 => (4, 0x90, 0xBF),
+            0xF1...0xF3 => (4, 0x80, 0xBF),
+            0xF4 => (4, 0x80, 0x8F),
+            _ => return i,
+        };
+        if i + len > input.len() || input[i+1] < lower || input[i+1] > upper
+            || input[i+2..i+len].iter().any(|&b| b < 0x80 || b > 0xBF) {
+            return i;
+        }
+        i += len;
+    }
+    i
+}
+
+impl Decoder for Utf8Decoder {
+    fn feed(&mut self, input: &[u8], output: &mut String) {
+        let mut i = 0;
+        while i < input.len() {
+            if self.malformed.aborted {
+                return;
+            }
+            if self.bytes_needed == 0 {
+                let n = valid_prefix(&input[i..]);
+                if n > 0 {
+                    output.push_str(unsafe { str::from_utf8_unchecked(&input[i..i+n]) });
+                    i += n;
+                    continue;
+                }
+            }
+            let b = input[i];
+            if self.step(b, output) && !self.malformed.aborted {
+                self.step(b, output);
+            }
+            i += 1;
+        }
+    }
+
+    fn end(&mut self, output: &mut String) {
+        if self.bytes_needed != 0 && !self.malformed.aborted {
+            self.reset();
+            self.malformed.found(output);
+        }
+    }
+
+    fn set_invalid_input(&mut self, policy: InvalidInput) {
+        self.malformed.policy = policy;
+    }
+
+    fn aborted(&self) -> bool {
+        self.malformed.aborted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::string::String;
+    use super::{Utf8Decoder, valid_prefix};
+    use super::super::{Decoder, InvalidInput, ReplaceInvalid, SkipInvalid, AbortOnInvalid};
+
+    fn decode(chunks: &[&[u8]]) -> String {
+        decode_with(chunks, ReplaceInvalid)
+    }
+
+    fn decode_with(chunks: &[&[u8]], policy: InvalidInput) -> String {
+        let mut dec = Utf8Decoder::new();
+        dec.set_invalid_input(policy);
+        let mut out = String::new();
+        for chunk in chunks.iter() {
+            dec.feed(*chunk, &mut out);
+        }
+        dec.end(&mut out);
+        out
+    }
+
+    #[test]
+    fn valid() {
+        assert_eq!(decode(&["a\u{e9}\u{4e2d}\u{1f600}".as_bytes()]).as_slice(),
+            "a\u{e9}\u{4e2d}\u{1f600}");
+    }
+
+    #[test]
+    fn split_sequence() {
+        assert_eq!(decode(&[&[0xE4], &[0xB8], &[0xAD]]).as_slice(), "\u{4e2d}");
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        assert_eq!(decode(&[&[b'a', 0xFF, b'b']]).as_slice(), "a\u{fffd}b");
+        assert_eq!(decode(&[&[0xC0, 0x80]]).as_slice(), "\u{fffd}\u{fffd}");
+    }
+
+    #[test]
+    fn truncated_sequence() {
+        assert_eq!(decode(&[&[0xE4, 0xB8, b'a']]).as_slice(), "\u{fffd}a");
+        assert_eq!(decode(&[&[0xE4, 0xB8]]).as_slice(), "\u{fffd}");
+    }
+
+    #[test]
+    fn surrogates_rejected() {
+        // ED A0 80 would be U+D800.
+        assert_eq!(decode(&[&[0xED, 0xA0, 0x80]]).as_slice(),
+            "\u{fffd}\u{fffd}\u{fffd}");
+    }
+
+    #[test]
+    fn skip_invalid() {
+        assert_eq!(decode_with(&[&[b'a', 0xFF, b'b', 0xE4], &[0xB8, b'c', 0xE4]], SkipInvalid)
+            .as_slice(), "abc");
+        assert_eq!(decode_with(&[&[0xED, 0xA0, 0x80, b'd']], SkipInvalid).as_slice(), "d");
+    }
+
+    #[test]
+    fn abort_on_invalid() {
+        assert_eq!(decode_with(&[&[b'a', 0xE4, 0xB8, 0xAD, 0xE4], &[0xB8], &[b'b', b'c']],
+            AbortOnInvalid).as_slice(), "a\u{4e2d}");
+        assert_eq!(decode_with(&[&[b'a', 0xE4, 0xB8]], AbortOnInvalid).as_slice(), "a");
+    }
+
+    #[test]
+    fn valid_prefixes() {
+        assert_eq!(valid_prefix("a\u{e9}\u{4e2d}\u{1f600}".as_bytes()), 10);
+        assert_eq!(valid_prefix(&[b'a', 0xE4, 0xB8]), 1);
+        assert_eq!(valid_prefix(&[b'a', b'b', 0xFF, b'c']), 2);
+        assert_eq!(valid_prefix(&[0xED, 0xA0, 0x80]), 0);
+        assert_eq!(valid_prefix(&[0xF4, 0x90, 0x80, 0x80]), 0);
+        assert_eq!(valid_prefix(&[]), 0);
+    }
+}
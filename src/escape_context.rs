@@ -0,0 +1,416 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Finding the syntactic context at the end of some HTML, for
+//! auto-escaping template engines.
+//!
+//! A value substituted into a template must be escaped differently
+//! depending on where it lands: text needs `<` and `&` escaped, a
+//! double-quoted attribute value needs `"` escaped, a URL attribute
+//! may need its scheme checked, and inside a `<script>` or a comment
+//! there may be no safe way to insert it at all.  Feed the template's
+//! literal text to a `ContextTracker`, and ask for its `context()` at
+//! each substitution.
+//!
+//! The tracker runs the tokenizer without a tree builder.  It switches
+//! to the raw text states after `<script>`, `<style>`, `<title>` and so
+//! on, and follows the tree builder's rules for foreign content: inside
+//! `<svg>` and `<math>` there are CDATA sections and no raw text, until
+//! an HTML integration point such as `<foreignObject>` or `<mi>`, or a
+//! tag such as `<p>` which breaks out of foreign content.  Other parts
+//! of tree construction, such as implied end tags, aren't modelled.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! match escape_context("<a title='") {
+//!     AttributeValue(ref attr) if attr.quote == Some('\'') => ...,
+//!     ...
+//! }
+//! ```
+
+use core::prelude::*;
+
+use attributes::is_url_attr;
+use sink::common::{raw_text_state, is_void};
+use tokenizer::{Tokenizer, TokenSink, TokenSinkResult, Continue, Token, TagToken, Tag};
+use tokenizer::{StartTag, EndTag};
+use tokenizer::states;
+use tree_builder::{mathml_text_integration_point, svg_html_integration_point};
+use tree_builder::{annotation_xml_integration_point, breaks_out_of_foreign_content};
+use tree_builder::svg_tag_name;
+
+use util::str::AsciiExt;
+
+use core::default::Default;
+use collections::vec::Vec;
+use collections::string::String;
+
+use string_cache::{Atom, QualName, Namespace};
+
+pub use self::EscapeContext::{Text, Rcdata, RawText, Script, TagName, Tag};
+pub use self::EscapeContext::{AttributeValue, Comment, Doctype, Cdata};
+
+/// An attribute value being written.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct AttrContext {
+    /// The element, in lowercase.
+    pub element: Atom,
+
+    /// The attribute, in lowercase.
+    pub attribute: Atom,
+
+    /// The quote character, or `None` if the value is unquoted or has
+    /// not started yet.  An unquoted value ends at whitespace or `>`,
+    /// so it's usually best to add quotes.
+    pub quote: Option<char>,
+}
+
+impl AttrContext {
+    /// Is the value a URL, or a list of URLs?  See
+    /// `attributes::is_url_attr`.
+    pub fn is_url(&self) -> bool {
+        is_url_attr(&self.attribute)
+    }
+
+    /// Is the value script, as in an event handler?
+    pub fn is_script(&self) -> bool {
+        self.attribute.as_slice().starts_with("on")
+    }
+
+    /// Is the value CSS?
+    pub fn is_style(&self) -> bool {
+        self.attribute == atom!(style)
+    }
+}
+
+/// Where the end of some HTML is.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum EscapeContext {
+    /// Ordinary text, where character references are recognized.
+    Text,
+
+    /// The text of this `<title>` or `<textarea>`, where character
+    /// references are recognized but tags other than its end tag are not.
+    Rcdata(Atom),
+
+    /// The text of this `<style>`, `<xmp>`, `<iframe>`, `<noembed>`,
+    /// `<noframes>` or `<plaintext>`.  Nothing can be escaped here.
+    RawText(Atom),
+
+    /// The text of a `<script>`.  `true` if inside `<!--`, where a
+    /// `<script>` tag changes how `</script>` is handled.
+    Script(bool),
+
+    /// Just after `<` or `</`, or within a tag name.
+    TagName,
+
+    /// Within this start or end tag, outside attribute values.
+    Tag(Atom),
+
+    /// Within an attribute value.
+    AttributeValue(AttrContext),
+
+    /// Within a comment, or something that will be treated as one.
+    Comment,
+
+    /// Within a doctype.
+    Doctype,
+
+    /// Within a CDATA section in SVG or MathML.
+    Cdata,
+}
+
+// An element on the tracker's stack of open elements.
+struct OpenElement {
+    name: QualName,
+
+    /// Is this an `<annotation-xml>` which is an HTML integration point?
+    html_annotation: bool,
+}
+
+impl OpenElement {
+    fn is_html_integration_point(&self) -> bool {
+        self.html_annotation || svg_html_integration_point(self.name.clone())
+    }
+}
+
+struct ContextSink {
+    next_state: Option<states::State>,
+
+    /// The element whose raw text we'll be in, if any.
+    raw_element: Option<Atom>,
+
+    /// The open elements from the outermost SVG or MathML element on.
+    /// HTML elements outside foreign content aren't tracked.
+    open: Vec<OpenElement>,
+}
+
+impl ContextSink {
+    // Should a start tag with this name be handled as HTML, or as
+    // foreign content?  This follows the tree builder.
+    fn start_tag_is_html(&self, name: &Atom) -> bool {
+        let node = match self.open.last() {
+            Some(node) => node,
+            None => return true,
+        };
+        node.name.ns == ns!(HTML)
+            || node.is_html_integration_point()
+            || (mathml_text_integration_point(node.name.clone())
+                && *name != atom!(mglyph) && *name != atom!(malignmark))
+            || (node.name == qualname!(MathML, "annotation-xml") && *name == atom!(svg))
+    }
+
+    fn html_start_tag(&mut self, tag: Tag) {
+        match tag.name {
+            atom!(svg) | atom!(math) => {
+                let ns = if tag.name == atom!(svg) { ns!(SVG) } else { ns!(MathML) };
+                self.foreign_start_tag(tag, ns);
+            }
+            _ => {
+                self.next_state = raw_text_state(&tag.name);
+                if self.next_state.is_some() {
+                    self.raw_element = Some(tag.name.clone());
+                }
+                if !self.open.is_empty() && !is_void(&tag.name) {
+                    self.open.push(OpenElement {
+                        name: QualName::new(ns!(HTML), tag.name),
+                        html_annotation: false,
+                    });
+                }
+            }
+        }
+    }
+
+    fn foreign_start_tag(&mut self, tag: Tag, ns: Namespace) {
+        if tag.self_closing {
+            return;
+        }
+        let local = match ns {
+            ns!(SVG) => svg_tag_name(&tag.name).unwrap_or(tag.name),
+            _ => tag.name,
+        };
+        let name = QualName::new(ns, local);
+        let html_annotation = name == qualname!(MathML, "annotation-xml")
+            && annotation_xml_integration_point(tag.attrs.as_slice());
+        self.open.push(OpenElement {
+            name: name,
+            html_annotation: html_annotation,
+        });
+    }
+
+    // Pop foreign elements, as the tree builder does for a tag which
+    // breaks out of foreign content.
+    fn break_out(&mut self) {
+        loop {
+            match self.open.last() {
+                Some(node) if node.name.ns != ns!(HTML)
+                    && !node.is_html_integration_point()
+                    && !mathml_text_integration_point(node.name.clone()) => (),
+                _ => return,
+            }
+            self.open.pop();
+        }
+    }
+}
+
+impl TokenSink for ContextSink {
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
+        match token {
+            TagToken(tag) => match tag.kind {
+                StartTag => {
+                    if self.start_tag_is_html(&tag.name) {
+                        self.html_start_tag(tag);
+                    } else if breaks_out_of_foreign_content(&tag.name, tag.attrs.as_slice()) {
+                        self.break_out();
+                        self.html_start_tag(tag);
+                    } else {
+                        let ns = self.open.last().unwrap().name.ns.clone();
+                        self.foreign_start_tag(tag, ns);
+                    }
+                }
+                EndTag => {
+                    let name = tag.name.as_slice();
+                    match self.open.iter()
+                        .rposition(|n| n.name.local.as_slice().eq_ignore_ascii_case(name)) {
+                        Some(i) => self.open.truncate(i),
+                        None => (),
+                    }
+                }
+            },
+            _ => (),
+        }
+        Continue
+    }
+
+    fn query_state_change(&mut self) -> Option<states::State> {
+        self.next_state.take()
+    }
+
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        self.open.last().map_or(false, |n| n.name.ns != ns!(HTML))
+    }
+}
+
+/// Tracks the context at the end of HTML fed to it so far.
+pub struct ContextTracker {
+    tokenizer: Tokenizer<ContextSink>,
+}
+
+impl ContextTracker {
+    pub fn new() -> ContextTracker {
+        let sink = ContextSink {
+            next_state: None,
+            raw_element: None,
+            open: vec!(),
+        };
+        ContextTracker {
+            tokenizer: Tokenizer::new(sink, Default::default()),
+        }
+    }
+
+    /// Feed some more HTML.
+    pub fn push(&mut self, html: &str) {
+        self.tokenizer.feed(String::from_str(html));
+    }
+
+    /// The context at the end of the HTML fed so far.
+    pub fn context(&self) -> EscapeContext {
+        let tok = &self.tokenizer;
+        let raw_element = || tok.sink().raw_element.clone().unwrap_or(atom!(""));
+        let tag = || Atom::from_slice(tok.current_tag_name());
+        match tok.state() {
+            states::Data => Text,
+            states::Plaintext => RawText(atom!(plaintext)),
+
+            states::RawData(kind) | states::RawLessThanSign(kind)
+            | states::RawEndTagOpen(kind) | states::RawEndTagName(kind) => match kind {
+                states::Rcdata => Rcdata(raw_element()),
+                states::Rawtext => RawText(raw_element()),
+                states::ScriptData => Script(false),
+                states::ScriptDataEscaped(_) => Script(true),
+            },
+            states::ScriptDataEscapeStart(states::Escaped)
+            | states::ScriptDataEscapeStartDash => Script(false),
+            states::ScriptDataEscapeStart(states::DoubleEscaped)
+            | states::ScriptDataEscapedDash(_) | states::ScriptDataEscapedDashDash(_)
+            | states::ScriptDataDoubleEscapeEnd => Script(true),
+
+            states::TagOpen | states::EndTagOpen | states::TagName => TagName,
+
+            states::BeforeAttributeName | states::AttributeName | states::AfterAttributeName
+            | states::AfterAttributeValueQuoted | states::SelfClosingStartTag => Tag(tag()),
+
+            states::BeforeAttributeValue | states::AttributeValue(_) => {
+                AttributeValue(AttrContext {
+                    element: tag(),
+                    attribute: Atom::from_slice(tok.current_attr_name()),
+                    quote: match tok.state() {
+                        states::AttributeValue(states::DoubleQuoted) => Some('"'),
+                        states::AttributeValue(states::SingleQuoted) => Some('\''),
+                        _ => None,
+                    },
+                })
+            }
+
+            states::BogusComment | states::MarkupDeclarationOpen | states::CommentStart
+            | states::CommentStartDash | states::Comment | states::CommentEndDash
+            | states::CommentEnd | states::CommentEndBang => Comment,
+
+            states::CdataSection => Cdata,
+
+            states::Doctype | states::BeforeDoctypeName | states::DoctypeName
+            | states::AfterDoctypeName | states::AfterDoctypeKeyword(_)
+            | states::BeforeDoctypeIdentifier(_) | states::DoctypeIdentifierDoubleQuoted(_)
+            | states::DoctypeIdentifierSingleQuoted(_) | states::AfterDoctypeIdentifier(_)
+            | states::BetweenDoctypePublicAndSystemIdentifiers
+            | states::BogusDoctype => Doctype,
+        }
+    }
+}
+
+/// The context at the end of `html`.
+pub fn escape_context(html: &str) -> EscapeContext {
+    let mut tracker = ContextTracker::new();
+    tracker.push(html);
+    tracker.context()
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use string_cache::Atom;
+
+    use super::{escape_context, ContextTracker, AttrContext};
+    use super::{Text, Rcdata, RawText, Script, TagName, Tag};
+    use super::{AttributeValue, Comment, Doctype, Cdata};
+
+    fn attr(element: &str, attribute: &str, quote: Option<char>) -> AttrContext {
+        AttrContext {
+            element: Atom::from_slice(element),
+            attribute: Atom::from_slice(attribute),
+            quote: quote,
+        }
+    }
+
+    test_eq!(text, escape_context("<p class=x>Hello "), Text);
+    test_eq!(after_end_tag, escape_context("<script>x</script>"), Text);
+    test_eq!(rcdata, escape_context("<title>a <b>"), Rcdata(atom!(title)));
+    test_eq!(rawtext, escape_context("<style>p { "), RawText(atom!(style)));
+    test_eq!(script, escape_context("<script>var x = "), Script(false));
+    test_eq!(script_escaped, escape_context("<script><!-- "), Script(true));
+    test_eq!(tag_name, escape_context("<di"), TagName);
+    test_eq!(tag, escape_context("<Div id=a "), Tag(atom!(div)));
+    test_eq!(double_quoted, escape_context("<a href=\"http://"),
+        AttributeValue(attr("a", "href", Some('"'))));
+    test_eq!(single_quoted, escape_context("<img alt='"),
+        AttributeValue(attr("img", "alt", Some('\''))));
+    test_eq!(unquoted, escape_context("<input value=ab"),
+        AttributeValue(attr("input", "value", None)));
+    test_eq!(before_value, escape_context("<input VALUE="),
+        AttributeValue(attr("input", "value", None)));
+    test_eq!(comment, escape_context("<!-- x "), Comment);
+    test_eq!(bogus_comment, escape_context("<?php "), Comment);
+    test_eq!(doctype, escape_context("<!DOCTYPE html "), Doctype);
+    test_eq!(cdata, escape_context("<svg><![CDATA[ "), Cdata);
+    test_eq!(cdata_in_html, escape_context("<div><![CDATA[ "), Comment);
+    test_eq!(svg_title, escape_context("<svg><title>"), Text);
+    test_eq!(svg_script, escape_context("<svg><script>"), Text);
+    test_eq!(svg_breakout, escape_context("<svg><p><script>"), Script(false));
+    test_eq!(math_breakout, escape_context("<math><mrow><font size=2><title>"),
+        Rcdata(atom!(title)));
+    test_eq!(mathml_text, escape_context("<math><mi><script>"), Script(false));
+    test_eq!(foreign_object, escape_context("<svg><foreignObject><style>"),
+        RawText(atom!(style)));
+    test_eq!(annotation_xml, escape_context("<math><annotation-xml encoding=text/html><xmp>"),
+        RawText(atom!(xmp)));
+    test_eq!(plain_annotation_xml, escape_context("<math><annotation-xml><xmp>"), Text);
+    test_eq!(html_in_foreign_object, escape_context("<svg><foreignObject><div><![CDATA[ "),
+        Comment);
+    test_eq!(after_foreign_object, escape_context("<svg><foreignObject></foreignObject><![CDATA[ "),
+        Cdata);
+
+    #[test]
+    fn incremental() {
+        let mut tracker = ContextTracker::new();
+        tracker.push("<a onclick=\"");
+        let ctx = tracker.context();
+        match ctx {
+            AttributeValue(ref a) => assert!(a.is_script() && !a.is_url()),
+            _ => panic!("{:?}", ctx),
+        }
+        tracker.push("go()\">link</a><textarea>");
+        assert_eq!(tracker.context(), Rcdata(atom!(textarea)));
+        tracker.push("</textarea><a href=");
+        match tracker.context() {
+            AttributeValue(ref a) => assert!(a.is_url()),
+            _ => panic!(),
+        }
+    }
+}
@@ -14,8 +14,9 @@ use core::prelude::*;
 use for_c::common::{LifetimeBuf, AsLifetimeBuf, h5e_buf, c_bool};
 
 use tokenizer::{TokenSink, Token, Doctype, Tag, ParseError, DoctypeToken};
-use tokenizer::{CommentToken, CharacterTokens, NullCharacterToken};
+use tokenizer::{CommentToken, BogusCommentToken, CharacterTokens, NullCharacterToken};
 use tokenizer::{TagToken, StartTag, EndTag, EOFToken, Tokenizer};
+use tokenizer::{TokenSinkResult, Continue};
 
 use core::mem;
 use core::default::Default;
@@ -51,7 +52,7 @@ pub struct h5e_token_sink {
 impl Copy for h5e_token_sink { }
 
 impl TokenSink for *mut h5e_token_sink {
-    fn process_token(&mut self, token: Token) {
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
         macro_rules! call {
             ($name:ident, $($arg:expr),*) => (
                 unsafe {
@@ -98,7 +99,7 @@ impl TokenSink for *mut h5e_token_sink {
                 }
             }
 
-            CommentToken(text) => {
+            CommentToken(text) | BogusCommentToken(text) => {
                 let text = text.as_lifetime_buf();
                 call!(do_comment, text.get());
             }
@@ -112,11 +113,12 @@ impl TokenSink for *mut h5e_token_sink {
 
             EOFToken => call!(do_eof),
 
-            ParseError(msg) => {
-                let msg = msg.as_lifetime_buf();
+            ParseError(error) => {
+                let msg = error.message.as_lifetime_buf();
                 call!(do_error, msg.get());
             }
         }
+        Continue
     }
 }
 
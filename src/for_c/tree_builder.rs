@@ -0,0 +1,263 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Parsing to a tree which lives on the other side of the C API.
+//!
+//! Nodes are named by integer handles chosen by the caller, so that the
+//! tree can be kept in e.g. an array, or in a JavaScript heap when
+//! compiled to asm.js or WebAssembly.
+
+#![allow(non_camel_case_types)]
+
+use core::prelude::*;
+
+use for_c::common::{AsLifetimeBuf, h5e_buf};
+
+use tokenizer::{Tokenizer, Attribute};
+use tree_builder::{TreeBuilder, TreeBuilderOpts, TreeSink, QuirksMode, Quirks, LimitedQuirks, NoQuirks};
+use tree_builder::{NodeOrText, AppendNode, AppendText};
+use diagnostic::Diagnostic;
+
+use core::mem;
+use core::ptr;
+use core::default::Default;
+use alloc::boxed::Box;
+use collections::vec::Vec;
+use collections::string::String;
+use libc::{c_void, c_int, size_t};
+
+use string_cache::QualName;
+
+#[repr(C)]
+pub struct h5e_tree_ops {
+    get_document:     Option<extern "C" fn(user: *mut c_void) -> size_t>,
+    create_element:   Option<extern "C" fn(user: *mut c_void, ns: h5e_buf, name: h5e_buf) -> size_t>,
+    create_comment:   Option<extern "C" fn(user: *mut c_void, text: h5e_buf) -> size_t>,
+
+    /// Add an attribute, if the element has none with that name.
+    /// Also called for each attribute of a new element.
+    add_attr:         Option<extern "C" fn(user: *mut c_void, elem: size_t,
+        ns: h5e_buf, name: h5e_buf, value: h5e_buf)>,
+
+    append_node:      Option<extern "C" fn(user: *mut c_void, parent: size_t, child: size_t)>,
+    append_text:      Option<extern "C" fn(user: *mut c_void, parent: size_t, text: h5e_buf)>,
+
+    /// These return 0 if `sibling` has no parent, and nonzero otherwise.
+    insert_node_before: Option<extern "C" fn(user: *mut c_void, sibling: size_t,
+        child: size_t) -> c_int>,
+    insert_text_before: Option<extern "C" fn(user: *mut c_void, sibling: size_t,
+        text: h5e_buf) -> c_int>,
+
+    append_doctype:   Option<extern "C" fn(user: *mut c_void, name: h5e_buf,
+        public: h5e_buf, system: h5e_buf)>,
+    remove_from_parent: Option<extern "C" fn(user: *mut c_void, node: size_t)>,
+    reparent_children: Option<extern "C" fn(user: *mut c_void, node: size_t, new_parent: size_t)>,
+    mark_script_already_started: Option<extern "C" fn(user: *mut c_void, node: size_t)>,
+
+    /// 0 for no quirks, 1 for limited quirks, 2 for quirks.
+    set_quirks_mode:  Option<extern "C" fn(user: *mut c_void, mode: c_int)>,
+    parse_error:      Option<extern "C" fn(user: *mut c_void, message: h5e_buf)>,
+}
+
+impl Copy for h5e_tree_ops { }
+
+#[repr(C)]
+pub struct h5e_tree_sink {
+    ops: *const h5e_tree_ops,
+    user: *mut c_void,
+}
+
+impl Copy for h5e_tree_sink { }
+
+/// A node handle from the caller, along with the element name, which
+/// the tree builder asks for often.
+#[derive(Clone)]
+pub struct NodeHandle {
+    id: size_t,
+    name: Option<QualName>,
+}
+
+// Call an optional operation, doing nothing if it's missing.
+macro_rules! call {
+    ($sink:expr, $name:ident, $($arg:expr),*) => (
+        unsafe {
+            match (*(*$sink).ops).$name {
+                None => Default::default(),
+                Some(f) => f((*$sink).user $(, $arg)*),
+            }
+        }
+    );
+}
+
+// Call an operation which must be provided.  `h5e_parser_new` won't
+// make a parser for a sink which lacks one.
+macro_rules! call_required {
+    ($sink:expr, $name:ident, $($arg:expr),*) => (
+        unsafe {
+            match (*(*$sink).ops).$name {
+                None => unreachable!(),
+                Some(f) => f((*$sink).user $(, $arg)*),
+            }
+        }
+    );
+}
+
+fn add_attrs(sink: *mut h5e_tree_sink, elem: size_t, attrs: Vec<Attribute>) {
+    for attr in attrs.into_iter() {
+        let ns = attr.name.ns.0.as_lifetime_buf();
+        let name = attr.name.local.as_lifetime_buf();
+        let value = attr.value.as_lifetime_buf();
+        call!(sink, add_attr, elem, ns.get(), name.get(), value.get());
+    }
+}
+
+impl TreeSink for *mut h5e_tree_sink {
+    type Handle = NodeHandle;
+    type Output = ();
+
+    fn finish(self) { }
+
+    fn report_diagnostic(&mut self, diagnostic: Diagnostic) {
+        let msg = diagnostic.message.as_lifetime_buf();
+        call!(*self, parse_error, msg.get());
+    }
+
+    fn get_document(&mut self) -> NodeHandle {
+        NodeHandle {
+            id: call_required!(*self, get_document,),
+            name: None,
+        }
+    }
+
+    fn same_node(&self, x: NodeHandle, y: NodeHandle) -> bool {
+        x.id == y.id
+    }
+
+    fn elem_name(&self, target: NodeHandle) -> QualName {
+        target.name.expect("not an element")
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        let mode = match mode {
+            NoQuirks => 0,
+            LimitedQuirks => 1,
+            Quirks => 2,
+        };
+        call!(*self, set_quirks_mode, mode);
+    }
+
+    fn create_element(&mut self, name: QualName, attrs: Vec<Attribute>) -> NodeHandle {
+        let id = {
+            let ns = name.ns.0.as_lifetime_buf();
+            let local = name.local.as_lifetime_buf();
+            call_required!(*self, create_element, ns.get(), local.get())
+        };
+        add_attrs(*self, id, attrs);
+        NodeHandle {
+            id: id,
+            name: Some(name),
+        }
+    }
+
+    fn create_comment(&mut self, text: String) -> NodeHandle {
+        let text = text.as_lifetime_buf();
+        NodeHandle {
+            id: call_required!(*self, create_comment, text.get()),
+            name: None,
+        }
+    }
+
+    fn append(&mut self, parent: NodeHandle, child: NodeOrText<NodeHandle>) {
+        match child {
+            AppendNode(node) => call!(*self, append_node, parent.id, node.id),
+            AppendText(text) => {
+                let text = text.as_lifetime_buf();
+                call!(*self, append_text, parent.id, text.get());
+            }
+        }
+    }
+
+    fn append_before_sibling(&mut self,
+            sibling: NodeHandle,
+            new_node: NodeOrText<NodeHandle>) -> Result<(), NodeOrText<NodeHandle>> {
+        let inserted = match new_node {
+            AppendNode(ref node) => call!(*self, insert_node_before, sibling.id, node.id),
+            AppendText(ref text) => {
+                let text = text.as_lifetime_buf();
+                call!(*self, insert_text_before, sibling.id, text.get())
+            }
+        };
+        match inserted {
+            0 => Err(new_node),
+            _ => Ok(()),
+        }
+    }
+
+    fn append_doctype_to_document(&mut self, name: String, public_id: String, system_id: String) {
+        let name = name.as_lifetime_buf();
+        let public_id = public_id.as_lifetime_buf();
+        let system_id = system_id.as_lifetime_buf();
+        call!(*self, append_doctype, name.get(), public_id.get(), system_id.get());
+    }
+
+    fn add_attrs_if_missing(&mut self, target: NodeHandle, attrs: Vec<Attribute>) {
+        add_attrs(*self, target.id, attrs);
+    }
+
+    fn remove_from_parent(&mut self, target: NodeHandle) {
+        call!(*self, remove_from_parent, target.id);
+    }
+
+    fn reparent_children(&mut self, node: NodeHandle, new_parent: NodeHandle) {
+        call!(*self, reparent_children, node.id, new_parent.id);
+    }
+
+    fn mark_script_already_started(&mut self, node: NodeHandle) {
+        call!(*self, mark_script_already_started, node.id);
+    }
+}
+
+pub type h5e_parser_ptr = *const ();
+
+type Parser = Tokenizer<TreeBuilder<NodeHandle, *mut h5e_tree_sink>>;
+
+#[no_mangle]
+pub unsafe extern "C" fn h5e_parser_new(sink: *mut h5e_tree_sink, scripting: c_int) -> h5e_parser_ptr {
+    if sink.is_null() || (*sink).ops.is_null() {
+        return ptr::null();
+    }
+    let ops = &*(*sink).ops;
+    if ops.get_document.is_none() || ops.create_element.is_none()
+        || ops.create_comment.is_none() {
+        return ptr::null();
+    }
+
+    let mut opts: TreeBuilderOpts = Default::default();
+    opts.scripting_enabled = scripting != 0;
+    let tb = TreeBuilder::new(sink, opts);
+    let parser: Box<Parser> = box Tokenizer::new(tb, Default::default());
+    mem::transmute(parser)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn h5e_parser_free(parser: h5e_parser_ptr) {
+    let _: Box<Parser> = mem::transmute(parser);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn h5e_parser_feed(parser: h5e_parser_ptr, buf: h5e_buf) {
+    let parser: &mut Parser = mem::transmute(parser);
+    parser.feed(String::from_str(buf.as_slice()));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn h5e_parser_end(parser: h5e_parser_ptr) {
+    let parser: &mut Parser = mem::transmute(parser);
+    parser.end();
+}
@@ -0,0 +1,426 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Describing the forms in an `RcDom`, with their controls and initial
+//! values, for crawlers and test tools which fill in and submit forms.
+//!
+//! A control belongs to the `<form>` it is inside, or to the one named
+//! by its `form` attribute.  Controls which the tree builder associated
+//! with a form through the form element pointer, without being inside
+//! it, are not found.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! for form in forms(&dom).iter() {
+//!     println!("{:?} {:?}", form.method, form.action);
+//!     for &(ref name, ref value) in form.data().iter() {
+//!         println!("  {}={}", name, value);
+//!     }
+//! }
+//! ```
+
+use core::prelude::*;
+
+use sink::common::{Text, Element};
+use sink::rcdom::{RcDom, Handle};
+use tokenizer::Attribute;
+
+use util::str::{AsciiExt, is_ascii_whitespace};
+
+use collections::vec::Vec;
+use collections::string::String;
+
+use string_cache::QualName;
+
+pub use self::Method::{Get, Post, Dialog};
+
+/// How a form is submitted.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum Method {
+    Get,
+    Post,
+    Dialog,
+}
+
+/// An `<option>` of a `<select>`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct SelectOption {
+    pub value: String,
+    pub label: String,
+    pub selected: bool,
+    pub disabled: bool,
+}
+
+/// A form control: an `<input>`, `<button>`, `<select>` or `<textarea>`.
+pub struct Field {
+    /// The control element.
+    pub handle: Handle,
+
+    /// The `name` attribute, if any.
+    pub name: Option<String>,
+
+    /// The lowercase `type` of an `<input>` or `<button>`, such as
+    /// `"checkbox"`, or else the element name: `"select"` or `"textarea"`.
+    pub kind: String,
+
+    /// The initial value.  For a `<select>`, this is the value of the
+    /// first selected option.
+    pub value: String,
+
+    /// Is a checkbox or radio button initially checked?
+    pub checked: bool,
+
+    /// Is the control disabled, itself or by a `<fieldset>`?
+    pub disabled: bool,
+
+    /// The options of a `<select>`, including those in `<optgroup>`s.
+    pub options: Vec<SelectOption>,
+}
+
+/// A `<form>` and its controls.
+pub struct Form {
+    /// The form element.
+    pub handle: Handle,
+
+    /// The `action` attribute with surrounding whitespace removed, or
+    /// `None` if it is missing or empty, meaning the document's URL.
+    pub action: Option<String>,
+
+    pub method: Method,
+
+    /// The lowercase `enctype`.  Default: `application/x-www-form-urlencoded`
+    pub enctype: String,
+
+    /// Controls in tree order.
+    pub fields: Vec<Field>,
+}
+
+// The `type`s of `<input>` other than `text`, which is the default.
+static INPUT_TYPES: &'static [&'static str] = &["hidden", "search", "tel", "url", "email",
+    "password", "date", "month", "week", "time", "datetime-local", "number", "range",
+    "color", "checkbox", "radio", "file", "submit", "image", "reset", "button"];
+
+fn html_name(handle: &Handle) -> Option<QualName> {
+    match handle.borrow().node {
+        Element(ref name, _) if name.ns == ns!(HTML) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+fn find_attr(attrs: &[Attribute], name: &str) -> Option<String> {
+    attrs.iter()
+        .find(|a| a.name.ns == ns!("") && a.name.local.as_slice() == name)
+        .map(|a| a.value.clone())
+}
+
+fn get_attr(handle: &Handle, name: &str) -> Option<String> {
+    match handle.borrow().node {
+        Element(_, ref attrs) => find_attr(attrs.as_slice(), name),
+        _ => None,
+    }
+}
+
+fn text_content(handle: &Handle) -> String {
+    let mut out = String::new();
+    let mut stack = vec!(handle.clone());
+    loop {
+        let handle = unwrap_or_else!(stack.pop(), { break });
+        let node = handle.borrow();
+        match node.node {
+            Text(ref text) => out.push_str(text.as_slice()),
+            _ => stack.extend(node.children.iter().rev().map(|c| c.clone())),
+        }
+    }
+    out
+}
+
+fn strip_and_collapse_whitespace(text: &str) -> String {
+    let mut out = String::new();
+    for word in text.split(is_ascii_whitespace).filter(|w| !w.is_empty()) {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out
+}
+
+//§ the-option-element
+fn option(handle: &Handle, group_disabled: bool) -> SelectOption {
+    let text = strip_and_collapse_whitespace(text_content(handle).as_slice());
+    SelectOption {
+        value: get_attr(handle, "value").unwrap_or_else(|| text.clone()),
+        label: match get_attr(handle, "label") {
+            Some(ref label) if !label.is_empty() => label.clone(),
+            _ => text,
+        },
+        selected: get_attr(handle, "selected").is_some(),
+        disabled: group_disabled || get_attr(handle, "disabled").is_some(),
+    }
+}
+//§ END
+
+fn select_options(select: &Handle, multiple: bool, size: uint) -> Vec<SelectOption> {
+    let mut options = vec!();
+    for child in select.borrow().children.iter() {
+        match html_name(child).as_ref().map(|n| n.local.as_slice()) {
+            Some("option") => options.push(option(child, false)),
+            Some("optgroup") => {
+                let disabled = get_attr(child, "disabled").is_some();
+                for opt in child.borrow().children.iter() {
+                    match html_name(opt) {
+                        Some(ref n) if n.local.as_slice() == "option"
+                            => options.push(option(opt, disabled)),
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    //§ selectedness-setting-algorithm
+    if !multiple && size <= 1 {
+        // Only the last selected option stays selected, and a drop-down
+        // box shows its first enabled option if none is.
+        match options.iter().rposition(|o| o.selected) {
+            Some(last) => for (i, opt) in options.iter_mut().enumerate() {
+                opt.selected = i == last;
+            },
+            None => match options.iter_mut().find(|o| !o.disabled) {
+                Some(opt) => opt.selected = true,
+                None => (),
+            },
+        }
+    }
+    //§ END
+    options
+}
+
+fn field(handle: &Handle, name: &QualName, attrs: &[Attribute], disabled: bool) -> Option<Field> {
+    let mut field = Field {
+        handle: handle.clone(),
+        name: find_attr(attrs, "name"),
+        kind: String::new(),
+        value: find_attr(attrs, "value").unwrap_or(String::new()),
+        checked: find_attr(attrs, "checked").is_some(),
+        disabled: disabled || find_attr(attrs, "disabled").is_some(),
+        options: vec!(),
+    };
+
+    match name.local.as_slice() {
+        "input" => {
+            field.kind = match find_attr(attrs, "type").map(|t| t.as_slice().to_ascii_lower()) {
+                Some(ref k) if INPUT_TYPES.contains(&k.as_slice()) => k.clone(),
+                _ => String::from_str("text"),
+            };
+            if (field.kind.as_slice() == "checkbox" || field.kind.as_slice() == "radio")
+                    && find_attr(attrs, "value").is_none() {
+                field.value = String::from_str("on");
+            }
+        }
+        "button" => {
+            field.kind = match find_attr(attrs, "type").map(|t| t.as_slice().to_ascii_lower()) {
+                Some(ref k) if k.as_slice() == "reset" || k.as_slice() == "button" => k.clone(),
+                _ => String::from_str("submit"),
+            };
+        }
+        "select" => {
+            let multiple = find_attr(attrs, "multiple").is_some();
+            let size = find_attr(attrs, "size")
+                .and_then(|s| s.as_slice().trim_matches(is_ascii_whitespace).parse().ok())
+                .unwrap_or(if multiple { 4 } else { 1 });
+            field.kind = String::from_str("select");
+            field.options = select_options(handle, multiple, size);
+            field.value = field.options.iter().find(|o| o.selected)
+                .map(|o| o.value.clone()).unwrap_or(String::new());
+        }
+        "textarea" => {
+            field.kind = String::from_str("textarea");
+            field.value = text_content(handle);
+        }
+        _ => return None,
+    }
+    Some(field)
+}
+
+// Walk the tree, collecting forms, and controls with the id of the form
+// they belong to (if any) or the index of their ancestor form.
+fn walk(root: &Handle,
+        forms: &mut Vec<(Option<String>, Form)>, fields: &mut Vec<(Result<uint, String>, Field)>) {
+    // Each node, with its form and whether an ancestor fieldset disables it.
+    let mut stack: Vec<(Handle, Option<uint>, bool)> = vec!((root.clone(), None, false));
+    loop {
+        let (handle, form, disabled) = unwrap_or_else!(stack.pop(), { break });
+        let node = handle.borrow();
+        let (form, disabled) = match node.node {
+            Element(ref name, ref attrs) if name.ns == ns!(HTML)
+                => visit(&handle, name, attrs.as_slice(), form, disabled, forms, fields),
+            _ => (form, disabled),
+        };
+        stack.extend(node.children.iter().rev().map(|c| (c.clone(), form, disabled)));
+    }
+}
+
+// Record an HTML element which is a form or control, returning the form
+// and disabledness for its children.
+fn visit(handle: &Handle, name: &QualName, attrs: &[Attribute], form: Option<uint>,
+        disabled: bool, forms: &mut Vec<(Option<String>, Form)>,
+        fields: &mut Vec<(Result<uint, String>, Field)>) -> (Option<uint>, bool) {
+    let mut form = form;
+    let mut disabled = disabled;
+    match name.local.as_slice() {
+        "form" => {
+            let method = match find_attr(attrs, "method")
+                    .map(|m| m.as_slice().to_ascii_lower()) {
+                Some(ref m) if m.as_slice() == "post" => Post,
+                Some(ref m) if m.as_slice() == "dialog" => Dialog,
+                _ => Get,
+            };
+            let action = match find_attr(attrs, "action") {
+                Some(ref a) if !a.as_slice().chars().all(is_ascii_whitespace)
+                    => Some(String::from_str(a.as_slice().trim_matches(is_ascii_whitespace))),
+                _ => None,
+            };
+            let enctype = match find_attr(attrs, "enctype")
+                    .map(|e| e.as_slice().to_ascii_lower()) {
+                Some(ref e) if e.as_slice() == "multipart/form-data"
+                    || e.as_slice() == "text/plain" => e.clone(),
+                _ => String::from_str("application/x-www-form-urlencoded"),
+            };
+            form = Some(forms.len());
+            forms.push((find_attr(attrs, "id"), Form {
+                handle: handle.clone(),
+                action: action,
+                method: method,
+                enctype: enctype,
+                fields: vec!(),
+            }));
+        }
+        "fieldset" => disabled = disabled || find_attr(attrs, "disabled").is_some(),
+        _ => match field(handle, name, attrs, disabled) {
+            Some(f) => {
+                let owner = match find_attr(attrs, "form") {
+                    Some(id) => Err(id),
+                    None => match form {
+                        Some(i) => Ok(i),
+                        None => Err(String::new()),
+                    },
+                };
+                fields.push((owner, f));
+            }
+            None => (),
+        },
+    }
+    (form, disabled)
+}
+
+/// Describe the forms in a document, in tree order.
+pub fn forms(dom: &RcDom) -> Vec<Form> {
+    let mut forms = vec!();
+    let mut fields = vec!();
+    walk(&dom.document, &mut forms, &mut fields);
+
+    for (owner, field) in fields.into_iter() {
+        let index = match owner {
+            Ok(i) => Some(i),
+            Err(id) => forms.iter().position(|&(ref form_id, _)| {
+                !id.is_empty() && form_id.as_ref().map_or(false, |f| *f == id)
+            }),
+        };
+        match index {
+            Some(i) => forms[i].1.fields.push(field),
+            None => (),
+        }
+    }
+    forms.into_iter().map(|(_, form)| form).collect()
+}
+
+impl Form {
+    /// The name-value pairs which submitting the form without changing it
+    /// would send, leaving out buttons and file inputs.
+    pub fn data(&self) -> Vec<(String, String)> {
+        let mut data = vec!();
+        for field in self.fields.iter() {
+            let name = match field.name {
+                Some(ref name) if !name.is_empty() && !field.disabled => name.clone(),
+                _ => continue,
+            };
+            match field.kind.as_slice() {
+                "submit" | "reset" | "button" | "image" | "file" => (),
+                "checkbox" | "radio" => if field.checked {
+                    data.push((name, field.value.clone()));
+                },
+                "select" => for opt in field.options.iter() {
+                    if opt.selected && !opt.disabled {
+                        data.push((name.clone(), opt.value.clone()));
+                    }
+                },
+                _ => data.push((name, field.value.clone())),
+            }
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::{forms, Form, Post, Get};
+    use sink::rcdom::RcDom;
+    use driver::parse_str;
+
+    fn parse_forms(input: &str) -> Vec<Form> {
+        let dom: RcDom = parse_str(input);
+        forms(&dom)
+    }
+
+    fn pairs(form: &Form) -> Vec<(&str, &str)> {
+        form.fields.iter().filter_map(|f| f.name.as_ref().map(|n| (n.as_slice(), f.value.as_slice())))
+            .collect()
+    }
+
+    #[test]
+    fn fields() {
+        let forms = parse_forms("<form action=' /go ' method=POST><input name=q value=x>\
+            <input type=checkbox name=c checked><input type=radio name=r>\
+            <select name=s><option>a<option selected value=2>b<option selected>c</select>\
+            <textarea name=t>\nhi</textarea><button name=b>Go</button></form>");
+        assert_eq!(forms.len(), 1);
+        let form = &forms[0];
+        assert_eq!(form.action, Some(String::from_str("/go")));
+        assert_eq!(form.method, Post);
+        assert_eq!(pairs(form), vec!(("q", "x"), ("c", "on"), ("r", "on"), ("s", "c"),
+            ("t", "hi"), ("b", "")));
+        assert_eq!(form.fields[3].options.iter().map(|o| o.selected).collect::<Vec<bool>>(),
+            vec!(false, false, true));
+
+        let data: Vec<(String, String)> = form.data();
+        let data: Vec<(&str, &str)> = data.iter()
+            .map(|&(ref n, ref v)| (n.as_slice(), v.as_slice())).collect();
+        assert_eq!(data, vec!(("q", "x"), ("c", "on"), ("s", "c"), ("t", "hi")));
+    }
+
+    #[test]
+    fn owners_and_disabled() {
+        let forms = parse_forms("<input name=outside form=f2>\
+            <form id=f1><fieldset disabled><input name=a></fieldset><input name=b></form>\
+            <form id=f2 method=dialog></form><input name=orphan>");
+        assert_eq!(forms.len(), 2);
+        assert_eq!(forms[0].method, Get);
+        assert_eq!(forms[0].action, None);
+        assert!(forms[0].fields[0].disabled);
+        assert_eq!(forms[0].data(), vec!((String::from_str("b"), String::new())));
+        assert_eq!(pairs(&forms[1]), vec!(("outside", "")));
+    }
+}
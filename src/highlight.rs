@@ -0,0 +1,591 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Classifying the parts of an HTML document for syntax highlighting.
+//!
+//! The tokenizer finds the tags, comments and doctypes, so markup is
+//! recognized exactly as a browser would, including in `<script>`,
+//! `<textarea>` and the like.  Each tag is then split into its name,
+//! attribute names, attribute values and punctuation.
+//!
+//! Every byte of the input is covered by exactly one `Highlight`,
+//! except for whitespace between the parts of a tag.  Character
+//! references are marked as `Entity` if they are numeric, end with
+//! `;`, or are one of the names that the tokenizer recognizes without
+//! a `;`, such as `&copy`.  A tag which is cut off by the end of the
+//! input is not a tag, so it's highlighted as text.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! for h in highlight(source).iter() {
+//!     let text = &source[h.span.start.offset..h.span.end.offset];
+//!     editor.set_style(h.span, style_for(h.class));
+//! }
+//! ```
+
+use core::prelude::*;
+
+use sink::common::{raw_text_state, is_void};
+use tokenizer::{Tokenizer, TokenizerOpts, TokenSink, TokenSinkResult, Continue};
+use tokenizer::{Token, TagToken, CommentToken, BogusCommentToken, DoctypeToken, Tag};
+use tokenizer::{StartTag, EndTag, Pos, Span, unterminated_name_len};
+use tokenizer::states::{self, State};
+use tree_builder::{mathml_text_integration_point, svg_html_integration_point};
+use tree_builder::{annotation_xml_integration_point, breaks_out_of_foreign_content};
+use tree_builder::svg_tag_name;
+
+use util::str::{AsciiExt, is_ascii_whitespace, is_ascii_alnum};
+
+use core::default::Default;
+use collections::vec::Vec;
+use collections::string::String;
+
+use string_cache::{Atom, QualName, Namespace};
+
+pub use self::Class::{Text, Entity, TagPunctuation, TagName, AttributeName};
+pub use self::Class::{AttributeValue, Comment, Doctype, Cdata};
+
+use self::TextKind::{Data, Rcdata, Raw};
+
+/// What a part of the input is.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum Class {
+    /// Text content, including the contents of `<script>` and `<style>`.
+    Text,
+
+    /// A character reference, in text or an attribute value.
+    Entity,
+
+    /// `<`, `</`, `>`, `/>`, and the `=` and quotes of an attribute.
+    TagPunctuation,
+
+    TagName,
+    AttributeName,
+
+    /// An attribute value, without its quotes.
+    AttributeValue,
+
+    /// A comment, including `<!--` and `-->`, or a bogus comment.
+    Comment,
+
+    Doctype,
+
+    /// A CDATA section in SVG or MathML, including its delimiters.
+    Cdata,
+}
+
+/// A classified part of the input.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct Highlight {
+    pub class: Class,
+    pub span: Span,
+}
+
+/// How the text after a token is tokenized.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+enum TextKind {
+    /// With character references and CDATA sections.
+    Data,
+
+    /// With character references, as in `<title>`.
+    Rcdata,
+
+    /// As is, as in `<script>`.
+    Raw,
+}
+
+struct Markup {
+    class: Class,
+    start: uint,
+    end: uint,
+    text_after: TextKind,
+}
+
+// An element on the sink's stack of open elements.
+struct OpenElement {
+    name: QualName,
+
+    /// Is this an `<annotation-xml>` which is an HTML integration point?
+    html_annotation: bool,
+}
+
+impl OpenElement {
+    fn is_html_integration_point(&self) -> bool {
+        self.html_annotation || svg_html_integration_point(self.name.clone())
+    }
+}
+
+struct MarkupSink {
+    markup: Vec<Markup>,
+    span: Span,
+    next_state: Option<State>,
+
+    /// The open elements from the outermost SVG or MathML element on.
+    /// HTML elements outside foreign content aren't tracked.
+    open: Vec<OpenElement>,
+}
+
+impl MarkupSink {
+    // Should a start tag with this name be handled as HTML, or as
+    // foreign content?  This follows the tree builder.
+    fn start_tag_is_html(&self, name: &Atom) -> bool {
+        let node = match self.open.last() {
+            Some(node) => node,
+            None => return true,
+        };
+        node.name.ns == ns!(HTML)
+            || node.is_html_integration_point()
+            || (mathml_text_integration_point(node.name.clone())
+                && *name != atom!(mglyph) && *name != atom!(malignmark))
+            || (node.name == qualname!(MathML, "annotation-xml") && *name == atom!(svg))
+    }
+
+    // Returns how the text after the tag is tokenized.
+    fn html_start_tag(&mut self, tag: Tag) -> TextKind {
+        match tag.name {
+            atom!(svg) | atom!(math) => {
+                let ns = if tag.name == atom!(svg) { ns!(SVG) } else { ns!(MathML) };
+                self.foreign_start_tag(tag, ns);
+                Data
+            }
+            _ => {
+                self.next_state = raw_text_state(&tag.name);
+                if !self.open.is_empty() && !is_void(&tag.name) {
+                    self.open.push(OpenElement {
+                        name: QualName::new(ns!(HTML), tag.name),
+                        html_annotation: false,
+                    });
+                }
+                match self.next_state {
+                    Some(states::RawData(states::Rcdata)) => Rcdata,
+                    Some(_) => Raw,
+                    None => Data,
+                }
+            }
+        }
+    }
+
+    fn foreign_start_tag(&mut self, tag: Tag, ns: Namespace) {
+        if tag.self_closing {
+            return;
+        }
+        let local = match ns {
+            ns!(SVG) => svg_tag_name(&tag.name).unwrap_or(tag.name),
+            _ => tag.name,
+        };
+        let name = QualName::new(ns, local);
+        let html_annotation = name == qualname!(MathML, "annotation-xml")
+            && annotation_xml_integration_point(tag.attrs.as_slice());
+        self.open.push(OpenElement {
+            name: name,
+            html_annotation: html_annotation,
+        });
+    }
+
+    // Pop foreign elements, as the tree builder does for a tag which
+    // breaks out of foreign content.
+    fn break_out(&mut self) {
+        loop {
+            match self.open.last() {
+                Some(node) if node.name.ns != ns!(HTML)
+                    && !node.is_html_integration_point()
+                    && !mathml_text_integration_point(node.name.clone()) => (),
+                _ => return,
+            }
+            self.open.pop();
+        }
+    }
+}
+
+impl TokenSink for MarkupSink {
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
+        let mut text_after = Data;
+        let class = match token {
+            TagToken(tag) => {
+                match tag.kind {
+                    StartTag => {
+                        if self.start_tag_is_html(&tag.name) {
+                            text_after = self.html_start_tag(tag);
+                        } else if breaks_out_of_foreign_content(&tag.name, tag.attrs.as_slice()) {
+                            self.break_out();
+                            text_after = self.html_start_tag(tag);
+                        } else {
+                            let ns = self.open.last().unwrap().name.ns.clone();
+                            self.foreign_start_tag(tag, ns);
+                        }
+                    }
+                    EndTag => {
+                        let name = tag.name.as_slice();
+                        match self.open.iter()
+                            .rposition(|n| n.name.local.as_slice().eq_ignore_ascii_case(name)) {
+                            Some(i) => self.open.truncate(i),
+                            None => (),
+                        }
+                    }
+                }
+                TagName
+            }
+            CommentToken(_) | BogusCommentToken(_) => Comment,
+            DoctypeToken(_) => Doctype,
+            _ => return Continue,
+        };
+        self.markup.push(Markup {
+            class: class,
+            start: self.span.start.offset,
+            end: self.span.end.offset,
+            text_after: text_after,
+        });
+        Continue
+    }
+
+    fn query_state_change(&mut self) -> Option<State> {
+        self.next_state.take()
+    }
+
+    fn set_current_span(&mut self, span: Span) {
+        self.span = span;
+    }
+
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        self.open.last().map_or(false, |n| n.name.ns != ns!(HTML))
+    }
+}
+
+struct Classifier<'a> {
+    input: &'a str,
+    parts: Vec<(Class, uint, uint)>,
+}
+
+impl<'a> Classifier<'a> {
+    fn push(&mut self, class: Class, start: uint, end: uint) {
+        if start == end {
+            return;
+        }
+        // Merge with the previous part if it's the same class and adjacent.
+        match self.parts.last_mut() {
+            Some(&mut (c, _, ref mut e)) if c == class && *e == start => {
+                *e = end;
+                return;
+            }
+            _ => (),
+        }
+        self.parts.push((class, start, end));
+    }
+
+    // Length of a character reference at `pos`, if there is one.
+    fn entity_len(&self, pos: uint, in_attribute: bool) -> Option<uint> {
+        let rest = &self.input[pos + 1..];
+        let (skip, numeric) = if rest.starts_with("#x") || rest.starts_with("#X") {
+            (2, true)
+        } else if rest.starts_with("#") {
+            (1, true)
+        } else {
+            (0, false)
+        };
+        let digits = rest[skip..].chars().take_while(|&c| match c {
+            '0'...'9' => true,
+            'a'...'f' | 'A'...'F' => skip == 2 || !numeric,
+            'g'...'z' | 'G'...'Z' => !numeric,
+            _ => false,
+        }).count();
+        if digits == 0 {
+            return None;
+        }
+        let end = skip + digits;
+        match rest[end..].chars().next() {
+            Some(';') => Some(end + 2),
+            _ if numeric => Some(end + 1),
+            _ => {
+                let len = unwrap_or_return!(unterminated_name_len(&rest[..end]), None);
+                // In an attribute value, `&copy=` and `&copyx` aren't
+                // references.
+                match rest[len..].chars().next() {
+                    Some(c) if in_attribute && (c == '=' || is_ascii_alnum(c)) => None,
+                    _ => Some(len + 1),
+                }
+            }
+        }
+    }
+
+    // Text or an attribute value, with character references split out.
+    fn text(&mut self, class: Class, start: uint, end: uint, refs: bool) {
+        let mut pos = start;
+        let mut run = start;
+        while pos < end {
+            if refs && self.input.as_bytes()[pos] == b'&' {
+                match self.entity_len(pos, class == AttributeValue) {
+                    Some(len) if pos + len <= end => {
+                        self.push(class, run, pos);
+                        self.push(Entity, pos, pos + len);
+                        pos += len;
+                        run = pos;
+                        continue;
+                    }
+                    _ => (),
+                }
+            }
+            pos += 1;
+        }
+        self.push(class, run, end);
+    }
+
+    // Text between tokens, which may contain CDATA sections.
+    fn gap(&mut self, start: uint, end: uint, kind: TextKind) {
+        if kind != Data {
+            return self.text(Text, start, end, kind == Rcdata);
+        }
+        let mut pos = start;
+        while pos < end {
+            let cdata = match self.input[pos..end].find_str("<![CDATA[") {
+                Some(i) => pos + i,
+                None => break,
+            };
+            let cdata_end = match self.input[cdata..end].find_str("]]>") {
+                Some(i) => cdata + i + 3,
+                None => end,
+            };
+            self.text(Text, pos, cdata, true);
+            self.push(Cdata, cdata, cdata_end);
+            pos = cdata_end;
+        }
+        self.text(Text, pos, end, true);
+    }
+
+    // Split a tag into its parts.
+    fn tag(&mut self, start: uint, end: uint) {
+        let bytes = self.input.as_bytes();
+        let is_space = |b: u8| is_ascii_whitespace(b as char);
+        let mut pos = start + if bytes[start + 1] == b'/' { 2 } else { 1 };
+        self.push(TagPunctuation, start, pos);
+
+        let name_start = pos;
+        while pos < end && !is_space(bytes[pos]) && bytes[pos] != b'/' && bytes[pos] != b'>' {
+            pos += 1;
+        }
+        self.push(TagName, name_start, pos);
+
+        loop {
+            while pos < end && is_space(bytes[pos]) {
+                pos += 1;
+            }
+            if pos >= end {
+                break;
+            }
+            if bytes[pos] == b'/' || bytes[pos] == b'>' {
+                self.push(TagPunctuation, pos, pos + 1);
+                pos += 1;
+                continue;
+            }
+
+            // An attribute name can start with '='.
+            let attr_start = pos;
+            pos += 1;
+            while pos < end && !is_space(bytes[pos])
+                    && bytes[pos] != b'/' && bytes[pos] != b'>' && bytes[pos] != b'=' {
+                pos += 1;
+            }
+            self.push(AttributeName, attr_start, pos);
+
+            let mut after_name = pos;
+            while after_name < end && is_space(bytes[after_name]) {
+                after_name += 1;
+            }
+            if after_name >= end || bytes[after_name] != b'=' {
+                continue;
+            }
+            self.push(TagPunctuation, after_name, after_name + 1);
+            pos = after_name + 1;
+            while pos < end && is_space(bytes[pos]) {
+                pos += 1;
+            }
+            if pos >= end {
+                break;
+            }
+
+            match bytes[pos] {
+                q @ b'"' | q @ b'\'' => {
+                    self.push(TagPunctuation, pos, pos + 1);
+                    let value_start = pos + 1;
+                    let value_end = self.input[value_start..end].find(q as char)
+                        .map_or(end, |i| value_start + i);
+                    self.text(AttributeValue, value_start, value_end, true);
+                    pos = value_end;
+                    if pos < end {
+                        self.push(TagPunctuation, pos, pos + 1);
+                        pos += 1;
+                    }
+                }
+                b'>' => (),
+                _ => {
+                    let value_start = pos;
+                    while pos < end && !is_space(bytes[pos]) && bytes[pos] != b'>' {
+                        pos += 1;
+                    }
+                    self.text(AttributeValue, value_start, pos, true);
+                }
+            }
+        }
+    }
+}
+
+// Positions of the parts, found by walking through the input once.
+fn spans(input: &str, parts: Vec<(Class, uint, uint)>) -> Vec<Highlight> {
+    let mut highlights = Vec::with_capacity(parts.len());
+    let mut pos: Pos = Default::default();
+    let mut after_cr = false;
+    for (class, start, end) in parts.into_iter() {
+        let mut span_start = pos;
+        for (i, c) in input[pos.offset..end].char_indices() {
+            if pos.offset + i == start {
+                span_start = pos;
+            }
+            match c {
+                '\n' if after_cr => (),
+                '\r' | '\n' => {
+                    pos.line += 1;
+                    pos.column = 1;
+                }
+                _ => pos.column += 1,
+            }
+            after_cr = c == '\r';
+        }
+        pos.offset = end;
+        span_start.offset = start;
+        highlights.push(Highlight {
+            class: class,
+            span: Span {
+                start: span_start,
+                end: pos,
+            },
+        });
+    }
+    highlights
+}
+
+/// Classify the parts of a document.
+pub fn highlight(input: &str) -> Vec<Highlight> {
+    let sink = MarkupSink {
+        markup: vec!(),
+        span: Default::default(),
+        next_state: None,
+        open: vec!(),
+    };
+    let mut tok = Tokenizer::new(sink, TokenizerOpts {
+        track_positions: true,
+        discard_bom: false,
+        ..Default::default()
+    });
+    tok.feed(String::from_str(input));
+    tok.end();
+    let markup = tok.unwrap().markup;
+
+    let mut classifier = Classifier {
+        input: input,
+        parts: vec!(),
+    };
+    let mut pos = 0;
+    let mut text_kind = Data;
+    for m in markup.iter() {
+        classifier.gap(pos, m.start, text_kind);
+        match m.class {
+            TagName => classifier.tag(m.start, m.end),
+            class => classifier.push(class, m.start, m.end),
+        }
+        pos = m.end;
+        text_kind = m.text_after;
+    }
+    classifier.gap(pos, input.len(), text_kind);
+
+    spans(input, classifier.parts)
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+
+    use super::{highlight, Class, Text, Entity, TagPunctuation, TagName, AttributeName};
+    use super::{AttributeValue, Comment, Doctype, Cdata};
+
+    fn parts(input: &str) -> Vec<(Class, &str)> {
+        highlight(input).iter()
+            .map(|h| (h.class, &input[h.span.start.offset..h.span.end.offset]))
+            .collect()
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(parts("<!DOCTYPE html><a href=\"x&amp;y\" b=c d>t&lt;u</a>"), vec!(
+            (Doctype, "<!DOCTYPE html>"),
+            (TagPunctuation, "<"), (TagName, "a"),
+            (AttributeName, "href"), (TagPunctuation, "=\""),
+            (AttributeValue, "x"), (Entity, "&amp;"), (AttributeValue, "y"),
+            (TagPunctuation, "\""),
+            (AttributeName, "b"), (TagPunctuation, "="), (AttributeValue, "c"),
+            (AttributeName, "d"), (TagPunctuation, ">"),
+            (Text, "t"), (Entity, "&lt;"), (Text, "u"),
+            (TagPunctuation, "</"), (TagName, "a"), (TagPunctuation, ">")));
+        assert_eq!(parts("<br/><p =x>"), vec!(
+            (TagPunctuation, "<"), (TagName, "br"), (TagPunctuation, "/>"),
+            (TagPunctuation, "<"), (TagName, "p"), (AttributeName, "=x"),
+            (TagPunctuation, ">")));
+    }
+
+    #[test]
+    fn text_kinds() {
+        assert_eq!(parts("<script>a<b &amp;</script><!-- c -->&#x41;&nosemi <title>&#65</title>"), vec!(
+            (TagPunctuation, "<"), (TagName, "script"), (TagPunctuation, ">"),
+            (Text, "a<b &amp;"),
+            (TagPunctuation, "</"), (TagName, "script"), (TagPunctuation, ">"),
+            (Comment, "<!-- c -->"), (Entity, "&#x41;"), (Text, "&nosemi "),
+            (TagPunctuation, "<"), (TagName, "title"), (TagPunctuation, ">"),
+            (Entity, "&#65"),
+            (TagPunctuation, "</"), (TagName, "title"), (TagPunctuation, ">")));
+        assert_eq!(parts("<svg><![CDATA[&amp;]]></svg><div"), vec!(
+            (TagPunctuation, "<"), (TagName, "svg"), (TagPunctuation, ">"),
+            (Cdata, "<![CDATA[&amp;]]>"),
+            (TagPunctuation, "</"), (TagName, "svg"), (TagPunctuation, ">"),
+            (Text, "<div")));
+    }
+
+    #[test]
+    fn html_in_foreign_content() {
+        assert_eq!(parts("<svg><foreignObject><script>a</b></script>"), vec!(
+            (TagPunctuation, "<"), (TagName, "svg"), (TagPunctuation, ">"),
+            (TagPunctuation, "<"), (TagName, "foreignObject"), (TagPunctuation, ">"),
+            (TagPunctuation, "<"), (TagName, "script"), (TagPunctuation, ">"),
+            (Text, "a</b>"),
+            (TagPunctuation, "</"), (TagName, "script"), (TagPunctuation, ">")));
+        assert_eq!(parts("<svg><p><style>a<b</style>"), vec!(
+            (TagPunctuation, "<"), (TagName, "svg"), (TagPunctuation, ">"),
+            (TagPunctuation, "<"), (TagName, "p"), (TagPunctuation, ">"),
+            (TagPunctuation, "<"), (TagName, "style"), (TagPunctuation, ">"),
+            (Text, "a<b"),
+            (TagPunctuation, "</"), (TagName, "style"), (TagPunctuation, ">")));
+
+        // CDATA is allowed in the <foreignObject> itself, but not in HTML
+        // elements inside it.
+        assert_eq!(parts("<svg><foreignObject><![CDATA[x]]><div><![CDATA[y]]>"), vec!(
+            (TagPunctuation, "<"), (TagName, "svg"), (TagPunctuation, ">"),
+            (TagPunctuation, "<"), (TagName, "foreignObject"), (TagPunctuation, ">"),
+            (Cdata, "<![CDATA[x]]>"),
+            (TagPunctuation, "<"), (TagName, "div"), (TagPunctuation, ">"),
+            (Comment, "<![CDATA[y]]>")));
+    }
+
+    #[test]
+    fn positions() {
+        let h = highlight("a\r\n<b\nc=d>");
+        let c = h.iter().find(|h| h.class == AttributeName).unwrap();
+        assert_eq!((c.span.start.line, c.span.start.column), (3, 1));
+        assert_eq!(c.span.start.offset, 6);
+        assert_eq!((c.span.end.line, c.span.end.column), (3, 2));
+    }
+}
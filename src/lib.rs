@@ -12,7 +12,7 @@
 
 #![feature(plugin, int_uint, box_syntax, no_std)]
 #![feature(core, hash, collections, alloc)]
-#![cfg_attr(not(for_c), feature(io))]
+#![cfg_attr(feature = "std", feature(io, std_misc))]
 #![deny(warnings)]
 #![allow(unused_parens)]
 
@@ -28,6 +28,7 @@ extern crate alloc;
 #[macro_use]
 extern crate core;
 
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate std;
 
@@ -37,7 +38,7 @@ extern crate libc;
 #[macro_use]
 extern crate collections;
 
-#[cfg(not(for_c))]
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate log;
 
@@ -46,13 +47,15 @@ extern crate string_cache;
 
 extern crate phf;
 
+#[cfg(feature = "std")]
 extern crate time;
 
 pub use tokenizer::Attribute;
-pub use driver::{one_input, ParseOpts, parse_to, parse};
+pub use driver::{one_input, char_input, str_input, ParseOpts, parse_to, parse, parse_bytes};
+pub use driver::{parse_fragment_to, parse_fragment};
 
-#[cfg(not(for_c))]
-pub use serialize::serialize;
+#[cfg(feature = "std")]
+pub use serialize::{serialize, serialize_to_string};
 
 #[macro_use]
 mod macros;
@@ -60,27 +63,83 @@ mod macros;
 #[macro_use]
 mod util {
     pub mod str;
+    pub mod codec;
     #[macro_use] pub mod smallcharset;
 }
 
 pub mod tokenizer;
 pub mod tree_builder;
+pub mod diagnostic;
+pub mod encoding;
+pub mod attributes;
+pub mod microsyntax;
+pub mod xml;
 
-#[cfg(not(for_c))]
+#[cfg(feature = "std")]
 pub mod serialize;
 
+#[cfg(all(feature = "std", unix))]
+pub mod mmap;
+
 /// Consumers of the parser API.
-#[cfg(not(for_c))]
+///
+/// Nothing limits how deeply a document's elements nest, so the DOMs
+/// here serialize, dump, visit and free their trees with an explicit
+/// stack rather than by recursion, which could overflow the thread's
+/// stack.
+#[cfg(feature = "std")]
 pub mod sink {
     pub mod common;
     pub mod rcdom;
     pub mod owned_dom;
+    pub mod links;
+    pub mod conditional_comments;
+    pub mod metadata;
+    pub mod xpath;
+    pub mod flat;
+    pub mod visit;
+    pub mod tee;
+    pub mod stats;
+    pub mod null;
+    pub mod pattern;
+    pub mod parallel;
 }
 
+#[cfg(feature = "std")]
+pub mod validator;
+
+#[cfg(feature = "std")]
+pub mod readability;
+
+#[cfg(feature = "std")]
+pub mod table;
+
+#[cfg(feature = "std")]
+pub mod forms;
+
+#[cfg(feature = "std")]
+pub mod outline;
+
+#[cfg(feature = "std")]
+pub mod microdata;
+
+#[cfg(feature = "std")]
+pub mod rdfa;
+
+#[cfg(feature = "std")]
+pub mod rewriter;
+
+#[cfg(feature = "std")]
+pub mod escape_context;
+
+#[cfg(feature = "std")]
+pub mod highlight;
+
 pub mod driver;
 
 #[cfg(for_c)]
 pub mod for_c {
     pub mod common;
     pub mod tokenizer;
+    pub mod tree_builder;
 }
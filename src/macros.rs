@@ -37,20 +37,27 @@ macro_rules! addrs_of ( ($obj:expr => $($field:ident),+) => (
 ));
 
 // No format!() without libstd... just use the static message.
-#[cfg(for_c)]
+#[cfg(not(feature = "std"))]
 macro_rules! format_if ( ($pred:expr, $msg_static:expr, $msg_fmt:expr, $($arg:expr),*) => (
-    ::std::borrow::Cow::Borrowed($msg_static)
+    ::collections::borrow::Cow::Borrowed($msg_static)
 ));
 
-#[cfg(not(for_c))]
+#[cfg(feature = "std")]
 macro_rules! format_if ( ($pred:expr, $msg_static:expr, $msg_fmt:expr, $($arg:expr),*) => (
     if $pred {
-        ::std::borrow::Cow::Owned(format!($msg_fmt, $($arg),*))
+        ::collections::borrow::Cow::Owned(format!($msg_fmt, $($arg),*))
     } else {
-        ::std::borrow::Cow::Borrowed($msg_static)
+        ::collections::borrow::Cow::Borrowed($msg_static)
     }
 ));
 
+// A parse error `Diagnostic`, with a detailed message if `$pred`.
+macro_rules! error_if ( ($pred:expr, $code:expr, $msg_fmt:expr, $($arg:expr),*) => (
+    ::diagnostic::Diagnostic::error_with_message($code,
+        format_if!($pred, $code, $msg_fmt, $($arg),*))
+));
+
+#[cfg(feature = "std")]
 macro_rules! time ( ($e:expr) => ({
     let t0 = ::time::precise_time_ns();
     let result = $e;
@@ -58,8 +65,12 @@ macro_rules! time ( ($e:expr) => ({
     (result, dt)
 }));
 
+// There's no clock without libstd, and profiling is disabled.
+#[cfg(not(feature = "std"))]
+macro_rules! time ( ($e:expr) => (($e, 0u64)) );
+
 /// FIXME(rust-lang/rust#16806): copied from libcollections/macros.rs
-#[cfg(for_c)]
+#[cfg(not(feature = "std"))]
 macro_rules! vec(
     ($($e:expr),*) => ({
         // leading _ to allow empty construction without a warning.
@@ -71,7 +82,7 @@ macro_rules! vec(
 );
 
 // Disable logging when building without the runtime.
-#[cfg(for_c)]
+#[cfg(not(feature = "std"))]
 #[macro_use]
 mod log {
     macro_rules! h5e_log   (($($x:tt)*) => (()));
@@ -81,7 +92,7 @@ mod log {
     macro_rules! h5e_error (($($x:tt)*) => (()));
 }
 
-#[cfg(not(for_c))]
+#[cfg(feature = "std")]
 #[macro_use]
 mod log {
     macro_rules! h5e_log   (($($x:tt)*) => (log!($($x)*)));
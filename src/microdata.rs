@@ -0,0 +1,409 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Extracting microdata items from an `RcDom`.
+//!
+//! An element with `itemscope` is an item, and the elements with
+//! `itemprop` inside it, or in elements named by its `itemref`, are its
+//! properties.  `to_json` converts items to the JSON format defined by
+//! the microdata spec.
+//!
+//! URL property values are returned as written, not resolved against
+//! the document's base URL.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! for item in items(&dom).iter() {
+//!     if item.types.iter().any(|t| t.as_slice() == "https://schema.org/Product") {
+//!         println!("{:?}", item.get("name"));
+//!     }
+//! }
+//! ```
+
+use core::prelude::*;
+
+use sink::common::{self, Element, attr};
+use sink::rcdom::{RcDom, Handle, NodeId, node_id};
+
+use util::str::is_ascii_whitespace;
+
+use collections::vec::Vec;
+use collections::string::String;
+use std::collections::HashMap;
+
+pub use self::Value::{Text, Url, Nested};
+
+/// The value of a property.
+pub enum Value {
+    Text(String),
+
+    /// A URL, from an attribute such as `href` or `src`.
+    Url(String),
+
+    /// An item, from an element with `itemscope`.
+    Nested(Item),
+}
+
+/// A microdata item.
+pub struct Item {
+    /// The element with `itemscope`.
+    pub handle: Handle,
+
+    /// The `itemtype`s, which are URLs.
+    pub types: Vec<String>,
+
+    /// The `itemid`, if the item has a type.
+    pub id: Option<String>,
+
+    /// Property names and values, in document order.  An element with
+    /// several names in its `itemprop` gives a property for each.
+    pub properties: Vec<(String, Value)>,
+}
+
+impl Item {
+    /// The values of the properties named `name`.
+    pub fn get<'a>(&'a self, name: &str) -> Vec<&'a Value> {
+        self.properties.iter()
+            .filter(|&&(ref n, _)| n.as_slice() == name)
+            .map(|&(_, ref v)| v)
+            .collect()
+    }
+}
+
+fn words(value: Option<&str>) -> Vec<String> {
+    let mut out: Vec<String> = vec!();
+    for w in value.unwrap_or("").split(is_ascii_whitespace).filter(|w| !w.is_empty()) {
+        if !out.iter().any(|o| o.as_slice() == w) {
+            out.push(String::from_str(w));
+        }
+    }
+    out
+}
+
+fn text_content(handle: &Handle, out: &mut String) {
+    let mut stack = vec!(handle.clone());
+    loop {
+        let handle = unwrap_or_else!(stack.pop(), { break });
+        let node = handle.borrow();
+        match node.node {
+            common::Text(ref text) => out.push_str(text.as_slice()),
+            _ => stack.extend(node.children.iter().rev().map(|c| c.clone())),
+        }
+    }
+}
+
+fn child_elements(handle: &Handle) -> Vec<Handle> {
+    handle.borrow().children.iter()
+        .filter(|c| match c.borrow().node { Element(..) => true, _ => false })
+        .map(|c| c.clone())
+        .collect()
+}
+
+struct Extractor {
+    /// Positions of all nodes in document order.
+    order: HashMap<NodeId, uint>,
+
+    /// The first element with each ID.
+    ids: HashMap<String, Handle>,
+}
+
+impl Extractor {
+    fn index(&mut self, root: &Handle) {
+        let mut stack = vec!(root.clone());
+        loop {
+            let handle = unwrap_or_else!(stack.pop(), { break });
+            let n = self.order.len();
+            self.order.insert(node_id(&handle), n);
+            let node = handle.borrow();
+            match node.node {
+                Element(_, ref attrs) => match attr(attrs.as_slice(), "id") {
+                    Some(id) if !self.ids.contains_key(id) => {
+                        self.ids.insert(String::from_str(id), handle.clone());
+                    }
+                    _ => (),
+                },
+                _ => (),
+            }
+            stack.extend(node.children.iter().rev().map(|c| c.clone()));
+        }
+    }
+
+    //§ associating-names-with-items
+    fn properties(&self, root: &Handle) -> Vec<Handle> {
+        let mut memory = vec!(node_id(root));
+        let mut pending = child_elements(root);
+        match root.borrow().node {
+            Element(_, ref attrs) => for id in words(attr(attrs.as_slice(), "itemref")).iter() {
+                match self.ids.get(id) {
+                    Some(elem) => pending.push(elem.clone()),
+                    None => (),
+                }
+            },
+            _ => (),
+        }
+
+        let mut results = vec!();
+        loop {
+            let current = unwrap_or_else!(pending.pop(), { break });
+            if memory.contains(&node_id(&current)) {
+                continue;
+            }
+            memory.push(node_id(&current));
+            let (scope, prop) = match current.borrow().node {
+                Element(_, ref attrs) => (attr(attrs.as_slice(), "itemscope").is_some(),
+                                          !words(attr(attrs.as_slice(), "itemprop")).is_empty()),
+                _ => (false, false),
+            };
+            if !scope {
+                pending.extend(child_elements(&current).into_iter());
+            }
+            if prop {
+                results.push(current);
+            }
+        }
+
+        let position = |h: &Handle| *self.order.get(&node_id(h)).expect("node not in document");
+        results.sort_by(|a, b| position(a).cmp(&position(b)));
+        results
+    }
+    //§ END
+
+    //§ values
+    fn value(&self, handle: &Handle, chain: &mut Vec<NodeId>) -> Value {
+        let node = handle.borrow();
+        let (name, attrs) = match node.node {
+            Element(ref name, ref attrs) => (name, attrs.as_slice()),
+            _ => unreachable!(),
+        };
+        if attr(attrs, "itemscope").is_some() {
+            // An item which is its own property, directly or not.
+            if chain.contains(&node_id(handle)) {
+                return Text(String::from_str("ERROR"));
+            }
+            return Nested(self.item(handle, chain));
+        }
+
+        let url = |name: &str| Url(String::from_str(attr(attrs, name).unwrap_or("")));
+        let text = |name: &str| Text(String::from_str(attr(attrs, name).unwrap_or("")));
+        match *name {
+            qualname!(HTML, meta) => text("content"),
+            qualname!(HTML, audio) | qualname!(HTML, embed) | qualname!(HTML, iframe)
+            | qualname!(HTML, img) | qualname!(HTML, source) | qualname!(HTML, track)
+            | qualname!(HTML, video) => url("src"),
+            qualname!(HTML, a) | qualname!(HTML, area) | qualname!(HTML, link) => url("href"),
+            qualname!(HTML, object) => url("data"),
+            qualname!(HTML, data) | qualname!(HTML, meter) => text("value"),
+            qualname!(HTML, time) if attr(attrs, "datetime").is_some() => text("datetime"),
+            _ => {
+                let mut s = String::new();
+                text_content(handle, &mut s);
+                Text(s)
+            }
+        }
+    }
+    //§ END
+
+    fn item(&self, handle: &Handle, chain: &mut Vec<NodeId>) -> Item {
+        chain.push(node_id(handle));
+        let (types, id) = match handle.borrow().node {
+            Element(_, ref attrs) => {
+                let types = words(attr(attrs.as_slice(), "itemtype"));
+                let id = match attr(attrs.as_slice(), "itemid") {
+                    Some(id) if !types.is_empty() => Some(String::from_str(id.trim())),
+                    _ => None,
+                };
+                (types, id)
+            }
+            _ => (vec!(), None),
+        };
+
+        let mut properties = vec!();
+        for prop in self.properties(handle).iter() {
+            let names = match prop.borrow().node {
+                Element(_, ref attrs) => words(attr(attrs.as_slice(), "itemprop")),
+                _ => vec!(),
+            };
+            for name in names.into_iter() {
+                let value = self.value(prop, chain);
+                properties.push((name, value));
+            }
+        }
+        chain.pop();
+
+        Item {
+            handle: handle.clone(),
+            types: types,
+            id: id,
+            properties: properties,
+        }
+    }
+}
+
+fn top_level(root: &Handle, out: &mut Vec<Handle>) {
+    let mut stack = vec!(root.clone());
+    loop {
+        let handle = unwrap_or_else!(stack.pop(), { break });
+        let node = handle.borrow();
+        match node.node {
+            Element(_, ref attrs) if attr(attrs.as_slice(), "itemscope").is_some()
+                && attr(attrs.as_slice(), "itemprop").is_none() => out.push(handle.clone()),
+            _ => (),
+        }
+        stack.extend(node.children.iter().rev().map(|c| c.clone()));
+    }
+}
+
+/// The top-level items of a document: those which are not the value of
+/// a property, in document order.
+pub fn items(dom: &RcDom) -> Vec<Item> {
+    let mut extractor = Extractor {
+        order: HashMap::new(),
+        ids: HashMap::new(),
+    };
+    extractor.index(&dom.document);
+    let mut roots = vec!();
+    top_level(&dom.document, &mut roots);
+    roots.iter().map(|r| extractor.item(r, &mut vec!())).collect()
+}
+
+fn push_json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(format!("\\u{:04x}", c as u32).as_slice()),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+//§ json
+fn push_json_item(item: &Item, out: &mut String) {
+    out.push_str("{");
+    if !item.types.is_empty() {
+        out.push_str("\"type\":[");
+        for (i, t) in item.types.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            push_json_string(t.as_slice(), out);
+        }
+        out.push_str("],");
+    }
+    match item.id {
+        Some(ref id) => {
+            out.push_str("\"id\":");
+            push_json_string(id.as_slice(), out);
+            out.push(',');
+        }
+        None => (),
+    }
+
+    // Values grouped by name, in order of each name's first appearance.
+    let mut names: Vec<&str> = vec!();
+    for &(ref name, _) in item.properties.iter() {
+        if !names.contains(&name.as_slice()) {
+            names.push(name.as_slice());
+        }
+    }
+    out.push_str("\"properties\":{");
+    for (i, name) in names.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        push_json_string(*name, out);
+        out.push_str(":[");
+        for (j, value) in item.get(*name).into_iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            match *value {
+                Text(ref s) | Url(ref s) => push_json_string(s.as_slice(), out),
+                Nested(ref item) => push_json_item(item, out),
+            }
+        }
+        out.push(']');
+    }
+    out.push_str("}}");
+}
+
+/// Convert items to JSON, as `{"items":[...]}`.
+pub fn to_json(items: &[Item]) -> String {
+    let mut out = String::from_str("{\"items\":[");
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        push_json_item(item, &mut out);
+    }
+    out.push_str("]}");
+    out
+}
+//§ END
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::string::String;
+
+    use super::{items, to_json, Text, Url, Nested};
+    use sink::rcdom::RcDom;
+    use driver::parse_str;
+
+    fn json(input: &str) -> String {
+        let dom: RcDom = parse_str(input);
+        to_json(items(&dom).as_slice())
+    }
+
+    test_eq!(nested, json("<div itemscope itemtype='https://schema.org/Person http://x'>\
+        <span itemprop='name nick'>Ann</span>\
+        <a itemprop=url href=/ann>home</a>\
+        <div itemprop=address itemscope><meta itemprop=city content=Oslo>\
+        <time itemprop=since datetime=2014>last year</time></div>\
+        </div>"),
+        "{\"items\":[{\"type\":[\"https://schema.org/Person\",\"http://x\"],\"properties\":\
+        {\"name\":[\"Ann\"],\"nick\":[\"Ann\"],\"url\":[\"/ann\"],\
+        \"address\":[{\"properties\":{\"city\":[\"Oslo\"],\"since\":[\"2014\"]}}]}}]}");
+
+    test_eq!(itemref_and_cycles, json("<p id=shared itemprop=tag>\"x\"</p>\
+        <div itemscope itemref='shared loop' itemtype=t itemid=' urn:1 '>\
+        <span itemprop=tag>y</span></div>\
+        <div id=loop itemprop=self itemscope itemref=loop></div>"),
+        "{\"items\":[{\"type\":[\"t\"],\"id\":\"urn:1\",\"properties\":\
+        {\"tag\":[\"\\\"x\\\"\",\"y\"],\"self\":[{\"properties\":{}}]}}]}");
+
+    #[test]
+    fn values() {
+        let dom: RcDom = parse_str(
+            "<div itemscope><img itemprop=a src=i.png><data itemprop=b value=7>seven</data>\
+             <div itemprop=c itemscope><b itemprop=d>D</b></div></div>");
+        let found = items(&dom);
+        assert_eq!(found.len(), 1);
+        let item = &found[0];
+        match *item.get("a")[0] {
+            Url(ref u) => assert_eq!(u.as_slice(), "i.png"),
+            _ => panic!(),
+        }
+        match *item.get("b")[0] {
+            Text(ref t) => assert_eq!(t.as_slice(), "7"),
+            _ => panic!(),
+        }
+        match *item.get("c")[0] {
+            Nested(ref n) => assert_eq!(n.properties.len(), 1),
+            _ => panic!(),
+        }
+        assert!(item.get("d").is_empty());
+    }
+}
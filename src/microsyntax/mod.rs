@@ -0,0 +1,458 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checking attribute values against the common microsyntaxes of
+//! the HTML standard.
+//!
+//! These are the strict "valid ... string" definitions used by
+//! authoring conformance, not the lenient parsing rules browsers
+//! apply to the same attributes.  The exceptions are the `srcset` and
+//! `refresh` modules, which parse values the way browsers do.
+
+use core::prelude::*;
+
+pub mod srcset;
+pub mod refresh;
+
+// Split off a run of ASCII digits.
+fn digits(s: &[u8]) -> (&[u8], &[u8]) {
+    let n = s.iter().take_while(|c| match **c { b'0'...b'9' => true, _ => false }).count();
+    (&s[..n], &s[n..])
+}
+
+// Value of a run of digits, or `None` if it's too long to bother with.
+fn value(digits: &[u8]) -> Option<u64> {
+    if digits.len() > 18 {
+        return None;
+    }
+    Some(digits.iter().fold(0u64, |n, &c| n * 10 + (c - b'0') as u64))
+}
+
+fn two_digits(s: &[u8]) -> Option<(u64, &[u8])> {
+    let (d, _) = digits(s);
+    if d.len() < 2 {
+        return None;
+    }
+    value(&d[..2]).map(|n| (n, &s[2..]))
+}
+
+fn expect(s: &[u8], c: u8) -> Option<&[u8]> {
+    match s.first() {
+        Some(&d) if d == c => Some(&s[1..]),
+        _ => None,
+    }
+}
+
+// Skip an optional character.
+fn skip(s: &[u8], c: u8) -> &[u8] {
+    expect(s, c).unwrap_or(s)
+}
+
+fn whole(rest: Option<&[u8]>) -> bool {
+    rest.map_or(false, |r| r.is_empty())
+}
+
+fn is_leap_year(year: u64) -> bool {
+    (year % 400 == 0) || (year % 4 == 0 && year % 100 != 0)
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 => if is_leap_year(year) { 29 } else { 28 },
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn weeks_in_year(year: u64) -> u64 {
+    // Day of the week of January 1st, with Sunday as 0.
+    let y = year - 1;
+    let jan1 = (1 + 5 * (y % 4) + 4 * (y % 100) + 6 * (y % 400)) % 7;
+    if jan1 == 4 || (jan1 == 3 && is_leap_year(year)) { 53 } else { 52 }
+}
+
+//§ months
+fn year(s: &[u8]) -> Option<(u64, &[u8])> {
+    let (d, rest) = digits(s);
+    if d.len() < 4 {
+        return None;
+    }
+    match value(d) {
+        Some(0) | None => None,
+        Some(y) => Some((y, rest)),
+    }
+}
+
+fn month(s: &[u8]) -> Option<(u64, u64, &[u8])> {
+    let (y, s) = unwrap_or_return!(year(s), None);
+    let s = unwrap_or_return!(expect(s, b'-'), None);
+    match two_digits(s) {
+        Some((m, rest)) if m >= 1 && m <= 12 => Some((y, m, rest)),
+        _ => None,
+    }
+}
+//§ END
+
+//§ dates
+fn date(s: &[u8]) -> Option<&[u8]> {
+    let (y, m, s) = unwrap_or_return!(month(s), None);
+    let s = unwrap_or_return!(expect(s, b'-'), None);
+    match two_digits(s) {
+        Some((d, rest)) if d >= 1 && d <= days_in_month(y, m) => Some(rest),
+        _ => None,
+    }
+}
+//§ END
+
+//§ yearless-dates
+fn yearless_date(s: &[u8]) -> Option<&[u8]> {
+    let s = if s.starts_with(b"--") { &s[2..] } else { s };
+    let (m, s) = match two_digits(s) {
+        Some((m, rest)) if m >= 1 && m <= 12 => (m, rest),
+        _ => return None,
+    };
+    let s = unwrap_or_return!(expect(s, b'-'), None);
+    match two_digits(s) {
+        // February 29th is allowed, as in a leap year.
+        Some((d, rest)) if d >= 1 && d <= days_in_month(4, m) => Some(rest),
+        _ => None,
+    }
+}
+//§ END
+
+//§ times
+fn time(s: &[u8]) -> Option<&[u8]> {
+    let s = match two_digits(s) {
+        Some((h, rest)) if h <= 23 => rest,
+        _ => return None,
+    };
+    let s = unwrap_or_return!(expect(s, b':'), None);
+    let s = match two_digits(s) {
+        Some((m, rest)) if m <= 59 => rest,
+        _ => return None,
+    };
+    let s = match expect(s, b':') {
+        None => return Some(s),
+        Some(s) => s,
+    };
+    let s = match two_digits(s) {
+        Some((sec, rest)) if sec <= 59 => rest,
+        _ => return None,
+    };
+    match expect(s, b'.') {
+        None => Some(s),
+        Some(s) => match digits(s) {
+            (frac, rest) if frac.len() >= 1 && frac.len() <= 3 => Some(rest),
+            _ => None,
+        },
+    }
+}
+//§ END
+
+//§ local-dates-and-times
+fn local_date_and_time(s: &[u8]) -> Option<&[u8]> {
+    let s = unwrap_or_return!(date(s), None);
+    let s = unwrap_or_return!(expect(s, b'T').or_else(|| expect(s, b' ')), None);
+    time(s)
+}
+//§ END
+
+//§ time-zones
+fn time_zone_offset(s: &[u8]) -> Option<&[u8]> {
+    match s.first() {
+        Some(&b'Z') => return Some(&s[1..]),
+        Some(&b'+') | Some(&b'-') => (),
+        _ => return None,
+    }
+    let s = match two_digits(&s[1..]) {
+        Some((h, rest)) if h <= 23 => rest,
+        _ => return None,
+    };
+    match two_digits(skip(s, b':')) {
+        Some((m, rest)) if m <= 59 => Some(rest),
+        _ => None,
+    }
+}
+//§ END
+
+//§ weeks
+fn week(s: &[u8]) -> Option<&[u8]> {
+    let (y, s) = unwrap_or_return!(year(s), None);
+    let s = unwrap_or_return!(expect(s, b'-'), None);
+    let s = unwrap_or_return!(expect(s, b'W'), None);
+    match two_digits(s) {
+        Some((w, rest)) if w >= 1 && w <= weeks_in_year(y) => Some(rest),
+        _ => None,
+    }
+}
+//§ END
+
+//§ durations
+fn seconds_fraction(s: &[u8]) -> Option<(bool, &[u8])> {
+    match expect(s, b'.') {
+        None => Some((false, s)),
+        Some(s) => match digits(s) {
+            (frac, rest) if frac.len() >= 1 && frac.len() <= 3 => Some((true, rest)),
+            _ => None,
+        },
+    }
+}
+
+fn iso_duration(s: &[u8]) -> bool {
+    let mut s = unwrap_or_return!(expect(s, b'P'), false);
+    let mut any = false;
+
+    let (d, rest) = digits(s);
+    if d.len() > 0 {
+        s = unwrap_or_return!(expect(rest, b'D'), false);
+        any = true;
+    }
+
+    match expect(s, b'T') {
+        None => (),
+        Some(rest) => {
+            s = rest;
+            let mut any_time = false;
+            for &unit in [b'H', b'M'].iter() {
+                let (d, rest) = digits(s);
+                if d.len() > 0 && rest.first() == Some(&unit) {
+                    s = &rest[1..];
+                    any_time = true;
+                }
+            }
+            let (d, rest) = digits(s);
+            if d.len() > 0 {
+                let (_, rest) = unwrap_or_return!(seconds_fraction(rest), false);
+                s = unwrap_or_return!(expect(rest, b'S'), false);
+                any_time = true;
+            }
+            if !any_time {
+                return false;
+            }
+            any = true;
+        }
+    }
+
+    any && s.is_empty()
+}
+
+fn skip_whitespace(s: &[u8]) -> &[u8] {
+    let n = s.iter().take_while(|c| match **c {
+        b'\t' | b'\n' | b'\x0C' | b'\r' | b' ' => true,
+        _ => false,
+    }).count();
+    &s[n..]
+}
+
+fn component_duration(s: &[u8]) -> bool {
+    // Bit mask of the units seen so far.
+    let mut seen = 0u8;
+    let mut s = skip_whitespace(s);
+    while !s.is_empty() {
+        let (d, rest) = digits(s);
+        if d.is_empty() {
+            return false;
+        }
+        let (fraction, rest) = unwrap_or_return!(seconds_fraction(rest), false);
+        let rest = skip_whitespace(rest);
+        let unit = match rest.first() {
+            Some(&b'W') | Some(&b'w') => 1,
+            Some(&b'D') | Some(&b'd') => 2,
+            Some(&b'H') | Some(&b'h') => 4,
+            Some(&b'M') | Some(&b'm') => 8,
+            Some(&b'S') | Some(&b's') => 16,
+            _ => return false,
+        };
+        if (seen & unit != 0) || (fraction && unit != 16) {
+            return false;
+        }
+        seen |= unit;
+        s = skip_whitespace(&rest[1..]);
+    }
+    seen != 0
+}
+//§ END
+
+/// Is this a valid integer, i.e. an optional `-` followed by
+/// ASCII digits?
+pub fn is_valid_integer(s: &str) -> bool {
+    is_valid_non_negative_integer(match s.as_bytes().first() {
+        Some(&b'-') => &s[1..],
+        _ => s,
+    })
+}
+
+/// Is this a valid non-negative integer, i.e. one or more ASCII digits?
+pub fn is_valid_non_negative_integer(s: &str) -> bool {
+    let (d, rest) = digits(s.as_bytes());
+    d.len() > 0 && rest.is_empty()
+}
+
+/// Is this a valid floating-point number, such as `-1.5e3`?
+pub fn is_valid_floating_point_number(s: &str) -> bool {
+    let s = skip(s.as_bytes(), b'-');
+    let (int, s) = digits(s);
+    let s = match expect(s, b'.') {
+        Some(s) => match digits(s) {
+            (frac, rest) if frac.len() > 0 => rest,
+            _ => return false,
+        },
+        None if int.len() > 0 => s,
+        None => return false,
+    };
+    let s = match expect(s, b'e').or_else(|| expect(s, b'E')) {
+        None => s,
+        Some(s) => {
+            let s = match s.first() {
+                Some(&b'+') | Some(&b'-') => &s[1..],
+                _ => s,
+            };
+            match digits(s) {
+                (exp, rest) if exp.len() > 0 => rest,
+                _ => return false,
+            }
+        }
+    };
+    s.is_empty()
+}
+
+/// Is this a valid simple color, i.e. `#` followed by six ASCII hex digits?
+pub fn is_valid_simple_color(s: &str) -> bool {
+    let s = s.as_bytes();
+    s.len() == 7 && s[0] == b'#' && s[1..].iter().all(|c| match *c {
+        b'0'...b'9' | b'a'...b'f' | b'A'...b'F' => true,
+        _ => false,
+    })
+}
+
+/// Is this a valid month string, such as `2015-02`?
+pub fn is_valid_month_string(s: &str) -> bool {
+    match month(s.as_bytes()) {
+        Some((_, _, rest)) => rest.is_empty(),
+        None => false,
+    }
+}
+
+/// Is this a valid date string, such as `2015-02-28`?
+pub fn is_valid_date_string(s: &str) -> bool {
+    whole(date(s.as_bytes()))
+}
+
+/// Is this a valid yearless date string, such as `02-29` or `--02-29`?
+pub fn is_valid_yearless_date_string(s: &str) -> bool {
+    whole(yearless_date(s.as_bytes()))
+}
+
+/// Is this a valid time string, such as `14:54`, `14:54:39`, or
+/// `14:54:39.929`?
+pub fn is_valid_time_string(s: &str) -> bool {
+    whole(time(s.as_bytes()))
+}
+
+/// Is this a valid local date and time string, such as `2015-02-28T14:54`
+/// or `2015-02-28 14:54:39`?
+pub fn is_valid_local_date_and_time_string(s: &str) -> bool {
+    whole(local_date_and_time(s.as_bytes()))
+}
+
+/// Is this a valid time-zone offset string, such as `Z`, `+05:30`,
+/// or `-0800`?
+pub fn is_valid_time_zone_offset_string(s: &str) -> bool {
+    whole(time_zone_offset(s.as_bytes()))
+}
+
+/// Is this a valid global date and time string, such as
+/// `2015-02-28T14:54:39Z` or `2015-02-28 14:54-08:00`?
+pub fn is_valid_global_date_and_time_string(s: &str) -> bool {
+    whole(local_date_and_time(s.as_bytes()).and_then(time_zone_offset))
+}
+
+/// Is this a valid week string, such as `2015-W09`?
+pub fn is_valid_week_string(s: &str) -> bool {
+    whole(week(s.as_bytes()))
+}
+
+/// Is this a valid duration string, either in the ISO 8601 form such
+/// as `PT4H18M3S` or as components such as `4h 18m 3s`?
+pub fn is_valid_duration_string(s: &str) -> bool {
+    let s = s.as_bytes();
+    iso_duration(s) || component_duration(s)
+}
+
+/// Is this a valid date string with optional time, as for the
+/// `datetime` attribute of `<ins>` and `<del>`?
+pub fn is_valid_date_string_with_optional_time(s: &str) -> bool {
+    is_valid_date_string(s) || is_valid_global_date_and_time_string(s)
+}
+
+/// Is this one of the formats allowed for the `datetime` attribute
+/// of `<time>`?
+pub fn is_valid_time_datetime_value(s: &str) -> bool {
+    let (d, rest) = digits(s.as_bytes());
+    let is_year = d.len() >= 4 && rest.is_empty() && value(d).map_or(false, |y| y > 0);
+    is_year
+        || is_valid_month_string(s)
+        || is_valid_date_string(s)
+        || is_valid_yearless_date_string(s)
+        || is_valid_time_string(s)
+        || is_valid_local_date_and_time_string(s)
+        || is_valid_time_zone_offset_string(s)
+        || is_valid_global_date_and_time_string(s)
+        || is_valid_week_string(s)
+        || is_valid_duration_string(s)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    test_eq!(integer, is_valid_integer("-12"), true);
+    test_eq!(integer_plus, is_valid_integer("+12"), false);
+    test_eq!(integer_empty, is_valid_integer("-"), false);
+    test_eq!(non_negative_integer, is_valid_non_negative_integer("007"), true);
+    test_eq!(non_negative_integer_space, is_valid_non_negative_integer(" 7"), false);
+
+    test_eq!(float, is_valid_floating_point_number("-1.5e+3"), true);
+    test_eq!(float_leading_dot, is_valid_floating_point_number(".5"), true);
+    test_eq!(float_trailing_dot, is_valid_floating_point_number("1."), false);
+    test_eq!(float_no_exponent, is_valid_floating_point_number("1e"), false);
+
+    test_eq!(color, is_valid_simple_color("#00fF7a"), true);
+    test_eq!(color_short, is_valid_simple_color("#fff"), false);
+    test_eq!(color_name, is_valid_simple_color("red"), false);
+
+    test_eq!(month_ok, is_valid_month_string("2015-12"), true);
+    test_eq!(month_bad, is_valid_month_string("2015-13"), false);
+    test_eq!(date_ok, is_valid_date_string("2016-02-29"), true);
+    test_eq!(date_not_leap, is_valid_date_string("2015-02-29"), false);
+    test_eq!(date_short_year, is_valid_date_string("215-02-01"), false);
+    test_eq!(date_year_zero, is_valid_date_string("0000-02-01"), false);
+    test_eq!(yearless, is_valid_yearless_date_string("--02-29"), true);
+
+    test_eq!(time_short, is_valid_time_string("23:59"), true);
+    test_eq!(time_fraction, is_valid_time_string("23:59:59.999"), true);
+    test_eq!(time_long_fraction, is_valid_time_string("23:59:59.9999"), false);
+    test_eq!(time_bad_hour, is_valid_time_string("24:00"), false);
+
+    test_eq!(local, is_valid_local_date_and_time_string("2015-02-28 14:54:39"), true);
+    test_eq!(local_tz, is_valid_local_date_and_time_string("2015-02-28T14:54Z"), false);
+    test_eq!(global, is_valid_global_date_and_time_string("2015-02-28T14:54-0800"), true);
+    test_eq!(global_z, is_valid_global_date_and_time_string("2015-02-28T14:54:39Z"), true);
+
+    test_eq!(week_53, is_valid_week_string("2015-W53"), true);
+    test_eq!(week_no_53, is_valid_week_string("2014-W53"), false);
+
+    test_eq!(duration_iso, is_valid_duration_string("P1DT4H18M3.5S"), true);
+    test_eq!(duration_iso_empty_time, is_valid_duration_string("PT"), false);
+    test_eq!(duration_components, is_valid_duration_string(" 4h 18m 3.5s "), true);
+    test_eq!(duration_repeated, is_valid_duration_string("4h 4h"), false);
+
+    test_eq!(time_datetime_year, is_valid_time_datetime_value("2015"), true);
+    test_eq!(time_datetime_junk, is_valid_time_datetime_value("yesterday"), false);
+}
@@ -0,0 +1,122 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Parsing the `content` of `<meta http-equiv=refresh>`, as browsers do.
+
+use core::prelude::*;
+use core::u64;
+
+use super::{digits, value};
+
+use collections::string::String;
+
+/// A parsed refresh declaration, such as `5; url=/next`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Refresh {
+    /// Seconds to wait before refreshing.
+    pub delay: u64,
+
+    /// Where to go, unresolved.  `None` means reload the same document.
+    pub url: Option<String>,
+}
+
+fn skip_whitespace(s: &[u8]) -> &[u8] {
+    let n = s.iter().take_while(|&&c| match c {
+        b' ' | b'\t' | b'\n' | b'\x0C' | b'\r' => true,
+        _ => false,
+    }).count();
+    &s[n..]
+}
+
+//§ shared-declarative-refresh-steps
+/// Parse the `content` of a `<meta http-equiv=refresh>` element.
+/// Returns `None` if browsers would ignore it.
+pub fn parse_refresh(content: &str) -> Option<Refresh> {
+    let s = skip_whitespace(content.as_bytes());
+
+    let (time, s) = digits(s);
+    if time.is_empty() && s.first() != Some(&b'.') {
+        return None;
+    }
+    let delay = value(time).unwrap_or(u64::MAX);
+
+    // Fractional seconds are ignored.
+    let n = s.iter().take_while(|&&c| c == b'.' || (c >= b'0' && c <= b'9')).count();
+    let s = &s[n..];
+
+    let mut refresh = Refresh {
+        delay: delay,
+        url: None,
+    };
+    match s.first() {
+        None => return Some(refresh),
+        Some(&b';') | Some(&b',') | Some(&b' ') | Some(&b'\t') | Some(&b'\n')
+            | Some(&b'\x0C') | Some(&b'\r') => (),
+        Some(_) => return None,
+    }
+
+    let mut s = skip_whitespace(s);
+    match s.first() {
+        Some(&b';') | Some(&b',') => s = skip_whitespace(&s[1..]),
+        _ => (),
+    }
+    if s.is_empty() {
+        return Some(refresh);
+    }
+
+    // Skip as much of a case-insensitive "url" as is there, then "=" if
+    // it's all there.  Browsers really do drop a partial match.
+    let n = s.iter().zip(b"url".iter()).take_while(|&(&c, &u)| c | 0x20 == u).count();
+    s = &s[n..];
+    if n == 3 {
+        s = skip_whitespace(s);
+        if s.first() == Some(&b'=') {
+            s = skip_whitespace(&s[1..]);
+        }
+    }
+
+    let quote = match s.first() {
+        Some(&q) if q == b'\'' || q == b'"' => {
+            s = &s[1..];
+            Some(q)
+        }
+        _ => None,
+    };
+    let end = quote.and_then(|q| s.iter().position(|&c| c == q)).unwrap_or(s.len());
+
+    // We only split at ASCII characters, so this is still UTF-8.
+    let url = unsafe { ::core::str::from_utf8_unchecked(&s[..end]) };
+    refresh.url = Some(String::from_str(url));
+    Some(refresh)
+}
+//§ END
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::string::String;
+
+    use super::{parse_refresh, Refresh};
+
+    fn refresh(delay: u64, url: Option<&str>) -> Option<Refresh> {
+        Some(Refresh {
+            delay: delay,
+            url: url.map(String::from_str),
+        })
+    }
+
+    test_eq!(delay_only, parse_refresh(" 5 "), refresh(5, None));
+    test_eq!(fraction, parse_refresh("1.5"), refresh(1, None));
+    test_eq!(leading_dot, parse_refresh(".5; /x"), refresh(0, Some("/x")));
+    test_eq!(url, parse_refresh("0; URL = '/next?a=b'junk"), refresh(0, Some("/next?a=b")));
+    test_eq!(bare_url, parse_refresh("3,http://example.com/"), refresh(3, Some("http://example.com/")));
+    test_eq!(url_named_url, parse_refresh("0; urlfoo"), refresh(0, Some("foo")));
+    test_eq!(no_delay, parse_refresh("url=/x"), None);
+    test_eq!(junk_after_delay, parse_refresh("5x; url=/x"), None);
+}
@@ -0,0 +1,255 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Parsing the `srcset` and `sizes` attributes of `<img>` and
+//! `<source>`, as browsers do.
+
+use core::prelude::*;
+
+use microsyntax::{is_valid_non_negative_integer, is_valid_floating_point_number};
+
+use util::str::is_ascii_whitespace;
+
+use collections::vec::Vec;
+
+/// One image from a `srcset` attribute, such as `a.png 2x`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct ImageCandidate<'a> {
+    /// The URL, unresolved.
+    pub url: &'a str,
+
+    /// The width descriptor, such as `400w`, if any.
+    pub width: Option<u32>,
+
+    /// The pixel density descriptor, such as `1.5x`, if any.
+    pub density: Option<f64>,
+
+    /// The height descriptor, such as `300h`, if any.  This is only
+    /// allowed along with a width.
+    pub height: Option<u32>,
+}
+
+/// One entry in a `sizes` attribute, such as `(max-width: 600px) 100vw`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct SourceSize<'a> {
+    /// The media condition, which is missing on the final entry.
+    pub media: Option<&'a str>,
+
+    /// The size, such as `100vw` or `calc(100vw - 2em)`.
+    pub size: &'a str,
+}
+
+// Split on commas which aren't inside parentheses.
+fn split_top_level_commas(value: &str) -> Vec<&str> {
+    let mut parts = vec!();
+    let mut depth = 0u;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+fn positive_integer(s: &str) -> Option<u32> {
+    if !is_valid_non_negative_integer(s) {
+        return None;
+    }
+    s.parse().ok().and_then(|n| if n > 0 { Some(n) } else { None })
+}
+
+// Apply the descriptors to a candidate, or return None if they are
+// invalid.
+fn parse_descriptors<'a>(url: &'a str, descriptors: &[&str]) -> Option<ImageCandidate<'a>> {
+    let mut candidate = ImageCandidate {
+        url: url,
+        width: None,
+        density: None,
+        height: None,
+    };
+    for &desc in descriptors.iter() {
+        let kind = desc.char_at_reverse(desc.len());
+        let num = &desc[..desc.len() - kind.len_utf8()];
+        match kind {
+            'w' if candidate.width.is_none() && candidate.density.is_none()
+                => candidate.width = Some(unwrap_or_return!(positive_integer(num), None)),
+            'x' if candidate.width.is_none() && candidate.density.is_none()
+                    && candidate.height.is_none() => {
+                if !is_valid_floating_point_number(num) {
+                    return None;
+                }
+                let density: f64 = unwrap_or_return!(num.parse().ok(), None);
+                if density < 0.0 {
+                    return None;
+                }
+                candidate.density = Some(density);
+            }
+            'h' if candidate.height.is_none() && candidate.density.is_none()
+                => candidate.height = Some(unwrap_or_return!(positive_integer(num), None)),
+            _ => return None,
+        }
+    }
+    if candidate.height.is_some() && candidate.width.is_none() {
+        return None;
+    }
+    Some(candidate)
+}
+
+//§ parse-a-srcset-attribute
+/// Parse a `srcset` attribute into image candidates.  Candidates with
+/// invalid descriptors are dropped, as browsers do.
+pub fn parse_srcset(value: &str) -> Vec<ImageCandidate> {
+    let mut candidates = vec!();
+    let mut rest = value;
+    loop {
+        rest = rest.trim_left_matches(|c: char| is_ascii_whitespace(c) || c == ',');
+        if rest.is_empty() {
+            return candidates;
+        }
+
+        let end = rest.find(is_ascii_whitespace).unwrap_or(rest.len());
+        let url = rest[..end].trim_right_matches(',');
+        let had_comma = url.len() < end;
+        rest = &rest[end..];
+
+        // Tokenize the descriptors, up to a comma outside parentheses.
+        let mut descriptors = vec!();
+        if !had_comma {
+            let mut in_parens = false;
+            let mut start = None;
+            let mut end = rest.len();
+            for (i, c) in rest.char_indices() {
+                match c {
+                    '(' => in_parens = true,
+                    ')' => in_parens = false,
+                    ',' if !in_parens => {
+                        end = i;
+                        break;
+                    }
+                    c if is_ascii_whitespace(c) && !in_parens => {
+                        match start.take() {
+                            Some(s) => descriptors.push(&rest[s..i]),
+                            None => (),
+                        }
+                        continue;
+                    }
+                    _ => (),
+                }
+                if start.is_none() {
+                    start = Some(i);
+                }
+            }
+            match start {
+                Some(s) => descriptors.push(&rest[s..end]),
+                None => (),
+            }
+            rest = &rest[end..];
+        }
+
+        match parse_descriptors(url, descriptors.as_slice()) {
+            Some(candidate) => candidates.push(candidate),
+            None => (),
+        }
+    }
+}
+//§ END
+
+/// Parse a `sizes` attribute into its entries.  Media conditions and
+/// sizes are split apart but not otherwise checked.
+pub fn parse_sizes(value: &str) -> Vec<SourceSize> {
+    let mut sizes = vec!();
+    for entry in split_top_level_commas(value).into_iter() {
+        let entry = entry.trim_matches(is_ascii_whitespace);
+        if entry.is_empty() {
+            continue;
+        }
+
+        // The size is the last component value: either a function such
+        // as calc(), or a run of non-whitespace.
+        let start = if entry.ends_with(")") {
+            let mut depth = 0u;
+            let mut open = 0;
+            for (i, c) in entry.char_indices().rev() {
+                match c {
+                    ')' => depth += 1,
+                    '(' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            open = i;
+                            break;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            entry[..open].rfind(|c: char| is_ascii_whitespace(c) || c == '(' || c == ')')
+                .map_or(0, |i| i + 1)
+        } else {
+            entry.rfind(is_ascii_whitespace).map_or(0, |i| i + 1)
+        };
+
+        let media = entry[..start].trim_matches(is_ascii_whitespace);
+        sizes.push(SourceSize {
+            media: if media.is_empty() { None } else { Some(media) },
+            size: &entry[start..],
+        });
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+
+    use super::{parse_srcset, parse_sizes, ImageCandidate, SourceSize};
+
+    fn urls(value: &str) -> Vec<&str> {
+        parse_srcset(value).into_iter().map(|c| c.url).collect()
+    }
+
+    #[test]
+    fn candidates() {
+        assert_eq!(parse_srcset("a.png 400w 300h, b.png 1.5x,c.png"), vec!(
+            ImageCandidate { url: "a.png", width: Some(400), density: None, height: Some(300) },
+            ImageCandidate { url: "b.png", width: None, density: Some(1.5), height: None },
+            ImageCandidate { url: "c.png", width: None, density: None, height: None }));
+    }
+
+    #[test]
+    fn urls_and_separators() {
+        assert_eq!(urls(" a.png,b.png "), vec!("a.png", "b.png"));
+        assert_eq!(urls("data:a,b 1x"), vec!("data:a,b"));
+        assert_eq!(urls("a.png (x, y) 1x, b.png"), vec!("b.png"));
+        assert_eq!(urls(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn invalid_descriptors() {
+        assert_eq!(urls("a.png 1x 2x, b.png 100w 1x, c.png 0w, d.png 10h, e.png 2y, f.png"),
+            vec!("f.png"));
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_sizes("(max-width: 600px) 100vw, (min-width: 2em) calc(50vw - 1em),50vw"),
+            vec!(
+                SourceSize { media: Some("(max-width: 600px)"), size: "100vw" },
+                SourceSize { media: Some("(min-width: 2em)"), size: "calc(50vw - 1em)" },
+                SourceSize { media: None, size: "50vw" }));
+    }
+}
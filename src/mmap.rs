@@ -0,0 +1,97 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Parsing a file through a memory map.
+//!
+//! The file is never read into memory as a whole.  The parser takes it
+//! a chunk at a time, so the bytes are decoded, and UTF-8 validated,
+//! just before they are tokenized, and parsing starts as soon as the
+//! first chunk is mapped in.  The chunks are slices of the map, passed
+//! to `parse_bytes` without being copied.
+//!
+//! ## Truncated files
+//!
+//! If the file is truncated while it's mapped, for example by another
+//! process rewriting it, reading the pages past the new end raises
+//! `SIGBUS`, which kills the process.  This can't be caught as an
+//! `IoResult`, so only map files which nothing else will modify while
+//! they are being parsed, and otherwise read them with `parse_bytes`.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let dom: RcDom = try!(parse_file(&Path::new("big.html"), Default::default()));
+//! ```
+
+use core::prelude::*;
+
+use driver::{ParseOpts, ParseResult, parse_bytes};
+
+use core::slice;
+use collections::vec::Vec;
+use std::old_io::{File, IoResult, IoError, OtherIoError};
+use std::os::{MemoryMap, MapReadable, MapFd};
+use std::os::unix::AsRawFd;
+use std::old_path::Path;
+
+/// How many bytes of the map are given to the parser at once.
+pub const CHUNK_BYTES: uint = 64 * 1024;
+
+/// Parse the bytes of a memory-mapped file, as `parse_bytes` does.
+/// The file must not be truncated until this returns; see the module
+/// documentation.
+pub fn parse_file<Output: ParseResult>(path: &Path, opts: ParseOpts) -> IoResult<Output> {
+    let file = try!(File::open(path));
+    let len = try!(file.stat()).size as uint;
+    if len == 0 {
+        return Ok(parse_bytes(None::<Vec<u8>>.into_iter(), opts));
+    }
+
+    let map = try!(MemoryMap::new(len, &[MapReadable, MapFd(file.as_raw_fd())])
+        .map_err(|e| IoError {
+            kind: OtherIoError,
+            desc: "couldn't map the file",
+            detail: Some(format!("{}", e)),
+        }));
+    let bytes = unsafe { slice::from_raw_parts(map.data() as *const u8, len) };
+    Ok(parse_bytes(bytes.chunks(CHUNK_BYTES), opts))
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::string::String;
+
+    use super::parse_file;
+    use sink::rcdom::{RcDom, dump_tree};
+    use driver::{parse, one_input};
+
+    use std::old_io::{File, TempDir, Writer};
+
+    #[test]
+    fn parse_mapped_file() {
+        let dir = TempDir::new("html5ever").unwrap();
+        let path = dir.path().join("test.html");
+        let mut input = String::from_str("<title>\u{e9}</title>");
+        for _ in 0..10000u {
+            input.push_str("<p>\u{4e2d}\u{1f600}</p>");
+        }
+        File::create(&path).write_str(input.as_slice()).unwrap();
+
+        let mapped: RcDom = parse_file(&path, Default::default()).unwrap();
+        let expected: RcDom = parse(one_input(input), Default::default());
+        assert_eq!(dump_tree(&mapped.document), dump_tree(&expected.document));
+
+        File::create(&path).unwrap();
+        let empty: RcDom = parse_file(&path, Default::default()).unwrap();
+        assert_eq!(dump_tree(&empty.document).as_slice(),
+            "| <html>\n|   <head>\n|   <body>\n");
+    }
+}
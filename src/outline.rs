@@ -0,0 +1,399 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The outline of a document in an `RcDom`: its sections and their
+//! headings, as found by the HTML5 outline algorithm.
+//!
+//! Sectioning content (`<article>`, `<aside>`, `<nav>`, `<section>`)
+//! starts a section, and so does a heading which ranks the same as or
+//! higher than the heading of the section it is in.  Sectioning roots
+//! such as `<blockquote>` and `<td>` have outlines of their own, which
+//! don't contribute to the document's.  Elements with a `hidden`
+//! attribute are ignored.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! fn print(sections: &[Section], depth: uint) {
+//!     for s in sections.iter() {
+//!         println!("{}{}", "  ".repeat(depth), s.heading_text().unwrap_or("Untitled"));
+//!         print(&s.sections, depth + 1);
+//!     }
+//! }
+//! print(&document_outline(&dom), 0);
+//! ```
+
+use core::prelude::*;
+
+use sink::common::{Text, Element};
+use sink::rcdom::{RcDom, Handle};
+
+use util::str::is_ascii_whitespace;
+
+use collections::vec::Vec;
+use collections::string::String;
+
+use string_cache::{Atom, QualName};
+
+use self::Heading::{Missing, Implied, Explicit};
+
+/// A section of an outline.
+pub struct Section {
+    /// The heading element, which may be an `<hgroup>`.  `None` for an
+    /// untitled section: sectioning content without a heading.
+    pub heading: Option<Handle>,
+
+    /// The sectioning element the section is for, or `None` for a
+    /// section started by a heading.
+    pub element: Option<Handle>,
+
+    /// Subsections, in document order.
+    pub sections: Vec<Section>,
+}
+
+impl Section {
+    /// The text of the heading, with whitespace collapsed.  `None` for an
+    /// untitled section.
+    pub fn heading_text(&self) -> Option<String> {
+        self.heading.as_ref().map(|h| {
+            let mut text = String::new();
+            push_text(h, &mut text);
+            let words: Vec<&str> = text.as_slice().split(is_ascii_whitespace)
+                .filter(|w| !w.is_empty()).collect();
+            words.connect(" ")
+        })
+    }
+}
+
+fn push_text(handle: &Handle, out: &mut String) {
+    let node = handle.borrow();
+    match node.node {
+        Text(ref text) => out.push_str(text.as_slice()),
+        Element(..) => for child in node.children.iter() {
+            push_text(child, out);
+        },
+        _ => (),
+    }
+}
+
+fn html_name(handle: &Handle) -> Option<Atom> {
+    match handle.borrow().node {
+        Element(QualName { ns: ns!(HTML), ref local }, _) => Some(local.clone()),
+        _ => None,
+    }
+}
+
+fn is_hidden(handle: &Handle) -> bool {
+    match handle.borrow().node {
+        Element(_, ref attrs) => attrs.iter().any(|a| a.name == qualname!("", "hidden")),
+        _ => false,
+    }
+}
+
+fn is_sectioning_content(name: &Atom) -> bool {
+    match *name {
+        atom!(article) | atom!(aside) | atom!(nav) | atom!(section) => true,
+        _ => false,
+    }
+}
+
+fn is_sectioning_root(name: &Atom) -> bool {
+    match *name {
+        atom!(blockquote) | atom!(body) | atom!(details) | atom!(dialog)
+        | atom!(fieldset) | atom!(figure) | atom!(td) => true,
+        _ => false,
+    }
+}
+
+fn heading_level(name: &Atom) -> Option<uint> {
+    match *name {
+        atom!(h1) => Some(1),
+        atom!(h2) => Some(2),
+        atom!(h3) => Some(3),
+        atom!(h4) => Some(4),
+        atom!(h5) => Some(5),
+        atom!(h6) => Some(6),
+        _ => None,
+    }
+}
+
+// The level of a heading, from 1 for the highest rank to 6.  An
+// `<hgroup>` has the rank of its highest-ranked heading, or of `<h1>`
+// if it has none.
+fn rank(handle: &Handle, name: &Atom) -> uint {
+    if *name != atom!(hgroup) {
+        return heading_level(name).unwrap();
+    }
+    fn highest(handle: &Handle) -> Option<uint> {
+        handle.borrow().children.iter().filter_map(|c| {
+            html_name(c).and_then(|n| heading_level(&n)).or_else(|| highest(c))
+        }).min()
+    }
+    highest(handle).unwrap_or(1)
+}
+
+//§ outlines
+#[derive(Clone)]
+enum Heading {
+    Missing,
+    Implied,
+    Explicit(Handle, uint),
+}
+
+struct SectionData {
+    heading: Heading,
+    element: Option<Handle>,
+    parent: Option<uint>,
+    children: Vec<uint>,
+}
+
+struct Outliner {
+    sections: Vec<SectionData>,
+
+    /// The top-level sections of the current outline target.
+    outline: Vec<uint>,
+
+    current: uint,
+
+    /// Outlines of the enclosing outline targets.
+    stack: Vec<Vec<uint>>,
+}
+
+impl Outliner {
+    fn new_section(&mut self, element: Option<Handle>, parent: Option<uint>) -> uint {
+        self.sections.push(SectionData {
+            heading: Missing,
+            element: element,
+            parent: parent,
+            children: vec!(),
+        });
+        self.sections.len() - 1
+    }
+
+    fn has_heading(&self, section: uint) -> bool {
+        match self.sections[section].heading {
+            Missing => false,
+            _ => true,
+        }
+    }
+
+    fn imply_heading(&mut self) {
+        if !self.has_heading(self.current) {
+            self.sections[self.current].heading = Implied;
+        }
+    }
+
+    fn enter_sectioning(&mut self, handle: &Handle, content: bool) {
+        if content {
+            self.imply_heading();
+        }
+        let section = self.new_section(Some(handle.clone()), None);
+        let outline = ::core::mem::replace(&mut self.outline, vec!(section));
+        self.stack.push(outline);
+        self.current = section;
+    }
+
+    // The exited element's outline goes in the last section of the
+    // enclosing target's outline, which becomes the current section, even
+    // if one of its subsections was current before the element.
+    fn exit_sectioning_content(&mut self) {
+        self.imply_heading();
+        let outline = self.stack.pop().unwrap();
+        let exited = ::core::mem::replace(&mut self.outline, outline);
+        let current = *self.outline.last().unwrap();
+        self.current = current;
+        for &s in exited.iter() {
+            self.sections[s].parent = Some(current);
+        }
+        self.sections[current].children.push_all(exited.as_slice());
+    }
+
+    fn exit_sectioning_root(&mut self) {
+        self.imply_heading();
+        self.outline = self.stack.pop().unwrap();
+        let mut current = *self.outline.last().unwrap();
+        loop {
+            match self.sections[current].children.last() {
+                Some(&last) => current = last,
+                None => break,
+            }
+        }
+        self.current = current;
+    }
+
+    fn heading_rank(&self, section: uint) -> Option<uint> {
+        match self.sections[section].heading {
+            Explicit(_, rank) => Some(rank),
+            _ => None,
+        }
+    }
+
+    fn enter_heading(&mut self, handle: &Handle, rank: uint) {
+        let heading = Explicit(handle.clone(), rank);
+        if !self.has_heading(self.current) {
+            self.sections[self.current].heading = heading;
+            return;
+        }
+
+        let last = *self.outline.last().unwrap();
+        match self.heading_rank(last) {
+            Some(r) if rank > r => (),
+            _ => {
+                let section = self.new_section(None, None);
+                self.sections[section].heading = heading;
+                self.outline.push(section);
+                self.current = section;
+                return;
+            }
+        }
+
+        let mut candidate = self.current;
+        loop {
+            // An implied heading ranks with <h1>.
+            if rank > self.heading_rank(candidate).unwrap_or(1) {
+                let section = self.new_section(None, Some(candidate));
+                self.sections[section].heading = heading;
+                self.sections[candidate].children.push(section);
+                self.current = section;
+                return;
+            }
+            candidate = self.sections[candidate].parent
+                .expect("a top-level section should outrank the heading");
+        }
+    }
+
+    fn walk(&mut self, handle: &Handle) {
+        let name = match html_name(handle) {
+            Some(name) => name,
+            None => {
+                for child in handle.borrow().children.iter() {
+                    self.walk(child);
+                }
+                return;
+            }
+        };
+        if is_hidden(handle) {
+            return;
+        }
+        if name == atom!(hgroup) || heading_level(&name).is_some() {
+            let rank = rank(handle, &name);
+            return self.enter_heading(handle, rank);
+        }
+
+        let content = is_sectioning_content(&name);
+        let root = is_sectioning_root(&name);
+        if content || root {
+            self.enter_sectioning(handle, content);
+        }
+        for child in handle.borrow().children.iter() {
+            self.walk(child);
+        }
+        if content {
+            self.exit_sectioning_content();
+        } else if root {
+            self.exit_sectioning_root();
+        }
+    }
+
+    fn build(&self, section: uint) -> Section {
+        let data = &self.sections[section];
+        Section {
+            heading: match data.heading {
+                Explicit(ref handle, _) => Some(handle.clone()),
+                _ => None,
+            },
+            element: data.element.clone(),
+            sections: data.children.iter().map(|&c| self.build(c)).collect(),
+        }
+    }
+}
+//§ END
+
+/// The outline of a sectioning element, such as `<body>`.
+///
+/// Any other node is outlined as if it were a sectioning root.
+pub fn outline(root: &Handle) -> Vec<Section> {
+    let mut outliner = Outliner {
+        sections: vec!(),
+        outline: vec!(),
+        current: 0,
+        stack: vec!(),
+    };
+    let section = outliner.new_section(Some(root.clone()), None);
+    outliner.outline.push(section);
+    outliner.current = section;
+    for child in root.borrow().children.iter() {
+        outliner.walk(child);
+    }
+    outliner.outline.iter().map(|&s| outliner.build(s)).collect()
+}
+
+/// The outline of a document's `<body>`, or of the whole document if it
+/// has no `<body>`.
+pub fn document_outline(dom: &RcDom) -> Vec<Section> {
+    fn find_body(handle: &Handle) -> Option<Handle> {
+        match html_name(handle) {
+            Some(atom!(body)) => return Some(handle.clone()),
+            _ => (),
+        }
+        handle.borrow().children.iter().filter_map(find_body).next()
+    }
+    outline(&find_body(&dom.document).unwrap_or_else(|| dom.document.clone()))
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::{document_outline, Section};
+    use sink::rcdom::RcDom;
+    use driver::parse_str;
+
+    // Sections as "heading(subsections...)", with "?" for untitled ones.
+    fn describe(sections: &[Section]) -> String {
+        let parts: Vec<String> = sections.iter().map(|s| {
+            let heading = s.heading_text().unwrap_or(String::from_str("?"));
+            if s.sections.is_empty() {
+                heading
+            } else {
+                format!("{}({})", heading, describe(s.sections.as_slice()))
+            }
+        }).collect();
+        parts.connect(" ")
+    }
+
+    fn outline_of(input: &str) -> String {
+        let dom: RcDom = parse_str(input);
+        describe(document_outline(&dom).as_slice())
+    }
+
+    test_eq!(headings, outline_of("<h1>A</h1><h2>B</h2><h3>C</h3><h2>D</h2><h1>E</h1>"),
+        "A(B(C) D) E");
+
+    test_eq!(sectioning, outline_of("<h1>Doc</h1><section><h3>One</h3><h3>Two</h3>\
+        </section><nav><p>links</nav><h2>After</h2>"),
+        "Doc(One Two ? After)");
+
+    test_eq!(after_nested_sections, outline_of("<h1>A</h1><h2>B</h2><section><h1>S</h1>\
+        <section><h1>T</h1></section><h2>U</h2></section><h2>C</h2>"),
+        "A(B S(T U) C)");
+
+    test_eq!(untitled_body, outline_of("<p>Hi <article><h2>A <em>b</em></h2></article>"),
+        "?(A b)");
+
+    test_eq!(roots_and_hidden, outline_of("<h1>T</h1><blockquote><h1>Q</h1></blockquote>\
+        <div hidden><h1>H</h1></div><h2>S</h2>"),
+        "T(S)");
+
+    test_eq!(hgroup, outline_of("<hgroup><h2>Sub</h2> <h1>Main</h1></hgroup><h1>Next</h1>\
+        <h2>Low</h2>"),
+        "Sub Main Next(Low)");
+}
@@ -0,0 +1,312 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Extracting RDFa Lite and Open Graph data from an `RcDom`.
+//!
+//! RDFa Lite marks up resources with `vocab`, `typeof`, `property`,
+//! `resource` and `prefix`.  An element with `typeof` starts a new
+//! resource, which is the value of the enclosing resource's property if
+//! the element also has `property`.  Properties outside any `typeof`
+//! belong to the document, and that's where Open Graph's
+//! `<meta property="og:title" content="...">` tags end up.
+//!
+//! Property names are returned as written, such as `name` or
+//! `og:title`; expanding them into IRIs with the `vocab` and prefixes in
+//! effect is left to the caller.  URLs are not resolved.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! for &(ref key, ref value) in open_graph(&dom).iter() {
+//!     println!("{} = {}", key, value);
+//! }
+//! ```
+
+use core::prelude::*;
+
+use sink::common::{Text, Element, attr};
+use sink::rcdom::{RcDom, Handle};
+use tokenizer::Attribute;
+
+use util::str::is_ascii_whitespace;
+
+use collections::vec::Vec;
+use collections::string::String;
+
+pub use self::Value::{Literal, Url, Nested};
+
+/// The value of a property.
+#[derive(Clone)]
+pub enum Value {
+    /// Text, from `content`, `datetime` or the element's text.
+    Literal(String),
+
+    /// A URL, from `resource`, `href`, `src` or `data`.
+    Url(String),
+
+    /// A resource, from an element with both `property` and `typeof`.
+    Nested(Resource),
+}
+
+/// Something described by RDFa properties.
+#[derive(Clone)]
+pub struct Resource {
+    /// The element with `typeof`, or the document.
+    pub handle: Handle,
+
+    /// The `vocab` in effect.
+    pub vocab: Option<String>,
+
+    /// The `typeof` types.
+    pub types: Vec<String>,
+
+    /// The `resource` attribute of the element with `typeof`, which
+    /// identifies the resource.
+    pub id: Option<String>,
+
+    /// Property names and values, in document order.
+    pub properties: Vec<(String, Value)>,
+}
+
+impl Resource {
+    fn new(handle: &Handle, vocab: Option<String>) -> Resource {
+        Resource {
+            handle: handle.clone(),
+            vocab: vocab,
+            types: vec!(),
+            id: None,
+            properties: vec!(),
+        }
+    }
+
+    /// The values of the properties named `name`.
+    pub fn get<'a>(&'a self, name: &str) -> Vec<&'a Value> {
+        self.properties.iter()
+            .filter(|&&(ref n, _)| n.as_slice() == name)
+            .map(|&(_, ref v)| v)
+            .collect()
+    }
+}
+
+fn words(value: Option<&str>) -> Vec<String> {
+    value.unwrap_or("").split(is_ascii_whitespace)
+        .filter(|w| !w.is_empty())
+        .map(String::from_str)
+        .collect()
+}
+
+fn text_content(handle: &Handle, out: &mut String) {
+    let node = handle.borrow();
+    match node.node {
+        Text(ref text) => out.push_str(text.as_slice()),
+        _ => for child in node.children.iter() {
+            text_content(child, out);
+        },
+    }
+}
+
+// The value of a property on an element without `typeof`.
+fn value(handle: &Handle, attrs: &[Attribute], name: &str) -> Value {
+    let from = |a: &str| attr(attrs, a).map(String::from_str);
+    match from("content") {
+        Some(content) => return Literal(content),
+        None => (),
+    }
+    let url = match name {
+        _ if attr(attrs, "resource").is_some() => from("resource"),
+        "a" | "area" | "link" => from("href"),
+        "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => from("src"),
+        "object" => from("data"),
+        "time" => return match from("datetime") {
+            Some(datetime) => Literal(datetime),
+            None => {
+                let mut s = String::new();
+                text_content(handle, &mut s);
+                Literal(s)
+            }
+        },
+        _ => None,
+    };
+    match url {
+        Some(url) => Url(url),
+        None => {
+            let mut s = String::new();
+            text_content(handle, &mut s);
+            Literal(s)
+        }
+    }
+}
+
+struct Extractor {
+    /// Resources which are not the value of a property, in document
+    /// order, filled in once their elements have been walked.
+    detached: Vec<Option<Resource>>,
+}
+
+impl Extractor {
+    fn walk(&mut self, handle: &Handle, subject: &mut Resource, vocab: &Option<String>) {
+        let node = handle.borrow();
+        let (name, attrs) = match node.node {
+            Element(ref name, ref attrs) => (name, attrs.as_slice()),
+            _ => {
+                for child in node.children.iter() {
+                    self.walk(child, subject, vocab);
+                }
+                return;
+            }
+        };
+
+        let vocab = match attr(attrs, "vocab") {
+            Some(v) => Some(String::from_str(v)),
+            None => vocab.clone(),
+        };
+        let properties = words(attr(attrs, "property"));
+
+        if attr(attrs, "typeof").is_none() {
+            for property in properties.into_iter() {
+                let v = value(handle, attrs, name.local.as_slice());
+                subject.properties.push((property, v));
+            }
+            for child in node.children.iter() {
+                self.walk(child, subject, &vocab);
+            }
+            return;
+        }
+
+        let mut resource = Resource::new(handle, vocab.clone());
+        resource.types = words(attr(attrs, "typeof"));
+        resource.id = attr(attrs, "resource").map(String::from_str);
+        let slot = self.detached.len();
+        if properties.is_empty() {
+            self.detached.push(None);
+        }
+        for child in node.children.iter() {
+            self.walk(child, &mut resource, &vocab);
+        }
+        if properties.is_empty() {
+            self.detached[slot] = Some(resource);
+        } else {
+            for property in properties.into_iter() {
+                subject.properties.push((property, Nested(resource.clone())));
+            }
+        }
+    }
+}
+
+/// The resources described in a document.  The first is the document
+/// itself, with the properties which are not inside any `typeof`.  It's
+/// followed by the resources which are not the value of a property, in
+/// document order.
+pub fn resources(dom: &RcDom) -> Vec<Resource> {
+    let mut extractor = Extractor {
+        detached: vec!(),
+    };
+    let mut document = Resource::new(&dom.document, None);
+    extractor.walk(&dom.document, &mut document, &None);
+    let mut out = vec!(document);
+    out.extend(extractor.detached.into_iter().map(|r| r.unwrap()));
+    out
+}
+
+/// The Open Graph properties of a document, such as `og:title`, with
+/// their values, in document order.  This includes the `article:`,
+/// `book:`, `profile:`, `music:`, `video:` and `fb:` properties.
+pub fn open_graph(dom: &RcDom) -> Vec<(String, String)> {
+    static PREFIXES: &'static [&'static str] = &["og:", "article:", "book:", "profile:",
+        "music:", "video:", "fb:"];
+    let document = resources(dom).into_iter().next().unwrap();
+    document.properties.into_iter().filter_map(|(name, value)| {
+        if !PREFIXES.iter().any(|p| name.as_slice().starts_with(*p)) {
+            return None;
+        }
+        match value {
+            Literal(s) | Url(s) => Some((name, s)),
+            Nested(_) => None,
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::{resources, open_graph, Value, Literal, Url, Nested};
+    use sink::rcdom::RcDom;
+    use driver::parse_str;
+
+    fn dom(input: &str) -> RcDom {
+        parse_str(input)
+    }
+
+    fn describe(value: &Value) -> String {
+        match *value {
+            Literal(ref s) => format!("{:?}", s),
+            Url(ref s) => format!("<{}>", s),
+            Nested(ref r) => {
+                let props: Vec<String> = r.properties.iter()
+                    .map(|&(ref n, ref v)| format!("{}={}", n, describe(v)))
+                    .collect();
+                format!("{}{{{}}}", r.types.connect(" "), props.connect(", "))
+            }
+        }
+    }
+
+    #[test]
+    fn open_graph_meta() {
+        let d = dom("<head><meta property=og:title content='A page'>\
+            <meta property='og:image' content=/i.png><meta property=foo content=x>\
+            <meta name=description content=d><meta property=article:author content=Bo>");
+        let s = |s: &str| String::from_str(s);
+        assert_eq!(open_graph(&d), vec!(
+            (s("og:title"), s("A page")), (s("og:image"), s("/i.png")),
+            (s("article:author"), s("Bo"))));
+    }
+
+    #[test]
+    fn rdfa_lite() {
+        let d = dom("<div vocab='http://schema.org/' typeof=Person resource=#me>\
+            <span property=name>Ann</span>\
+            <a property='url sameAs' href=/ann>home</a>\
+            <div property=address typeof=PostalAddress>\
+            <span property=addressLocality>Oslo</span></div>\
+            <time property=birthDate datetime=1990-01-01>Jan</time></div>\
+            <p typeof=Thing property=subject><span property=name>T</span></p>\
+            <span property=top>page</span>");
+        let found = resources(&d);
+        assert_eq!(found.len(), 2);
+
+        let document = &found[0];
+        let props: Vec<String> = document.properties.iter()
+            .map(|&(ref n, ref v)| format!("{}={}", n, describe(v)))
+            .collect();
+        assert_eq!(props, vec!(String::from_str("subject=Thing{name=\"T\"}"),
+                               String::from_str("top=\"page\"")));
+
+        let person = &found[1];
+        assert_eq!(person.vocab.as_ref().map(|v| v.as_slice()), Some("http://schema.org/"));
+        assert_eq!(person.types, vec!(String::from_str("Person")));
+        assert_eq!(person.id.as_ref().map(|v| v.as_slice()), Some("#me"));
+        let props: Vec<String> = person.properties.iter()
+            .map(|&(ref n, ref v)| format!("{}={}", n, describe(v)))
+            .collect();
+        assert_eq!(props, vec!(
+            String::from_str("name=\"Ann\""),
+            String::from_str("url=</ann>"),
+            String::from_str("sameAs=</ann>"),
+            String::from_str("address=PostalAddress{addressLocality=\"Oslo\"}"),
+            String::from_str("birthDate=\"1990-01-01\"")));
+        match *person.get("address")[0] {
+            Nested(ref a) => assert_eq!(a.vocab.as_ref().map(|v| v.as_slice()),
+                                        Some("http://schema.org/")),
+            _ => panic!(),
+        }
+    }
+}
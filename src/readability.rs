@@ -0,0 +1,330 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Finding the main content of a page, such as the text of a news
+//! article, among navigation, sidebars, comments and so on.
+//!
+//! This uses the heuristics of Arc90's Readability: each paragraph
+//! gives points to its parent and grandparent according to its length,
+//! the points are adjusted by tag name and by words in `class` and `id`,
+//! and then scaled down by the proportion of text inside links.  The
+//! element with the highest score wins.
+
+use core::prelude::*;
+
+use sink::common::{Text, Element};
+use sink::rcdom::{RcDom, Handle, NodeId, node_id};
+use tokenizer::Attribute;
+
+use util::str::{AsciiExt, is_ascii_whitespace};
+
+use collections::vec::Vec;
+use collections::string::String;
+use std::collections::HashMap;
+
+use string_cache::QualName;
+
+// Paragraphs shorter than this, in characters, don't count.
+const MIN_PARAGRAPH_LENGTH: uint = 25;
+
+// Words in `class` or `id` suggesting that an element is not content.
+static UNLIKELY: &'static [&'static str] = &["ad-", "banner", "combx", "comment",
+    "community", "disqus", "footer", "menu", "nav", "related", "remark", "share",
+    "sidebar", "sponsor", "popup", "promo", "social"];
+
+// Words which override `UNLIKELY`.
+static MAYBE: &'static [&'static str] = &["and", "article", "body", "column",
+    "main", "shadow", "content"];
+
+static POSITIVE: &'static [&'static str] = &["article", "body", "content", "entry",
+    "main", "page", "post", "story", "text", "blog"];
+
+static NEGATIVE: &'static [&'static str] = &["comment", "com-", "contact", "footer",
+    "footnote", "masthead", "meta", "outbrain", "related", "scroll", "shoutbox",
+    "sidebar", "sponsor", "shopping", "tags", "widget"];
+
+fn class_and_id(attrs: &[Attribute]) -> String {
+    let mut out = String::new();
+    for attr in attrs.iter() {
+        let local = &attr.name.local;
+        if attr.name.ns == ns!("") && (*local == atom!(class) || *local == atom!(id)) {
+            out.push_str(attr.value.as_slice().to_ascii_lower().as_slice());
+            out.push(' ');
+        }
+    }
+    out
+}
+
+fn contains_any(haystack: &str, words: &[&str]) -> bool {
+    words.iter().any(|w| haystack.contains(*w))
+}
+
+// Is this element unlikely to be, or contain, the main content?
+fn is_unlikely(name: &QualName, attrs: &[Attribute]) -> bool {
+    match name.local.as_slice() {
+        "script" | "style" | "noscript" | "nav" | "aside" | "footer" | "form"
+            | "iframe" | "head" => return true,
+        "body" | "html" | "article" | "main" => return false,
+        _ => (),
+    }
+    let names = class_and_id(attrs);
+    contains_any(names.as_slice(), UNLIKELY) && !contains_any(names.as_slice(), MAYBE)
+}
+
+fn class_weight(attrs: &[Attribute]) -> f64 {
+    let names = class_and_id(attrs);
+    let mut weight = 0.0;
+    if contains_any(names.as_slice(), POSITIVE) {
+        weight += 25.0;
+    }
+    if contains_any(names.as_slice(), NEGATIVE) {
+        weight -= 25.0;
+    }
+    weight
+}
+
+fn tag_weight(name: &QualName) -> f64 {
+    match name.local.as_slice() {
+        "article" => 10.0,
+        "div" | "section" | "main" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    }
+}
+
+// Count the characters of text under a node, with runs of whitespace
+// counted once, returning (all, inside links).
+fn text_length(handle: &Handle, in_link: bool, lengths: &mut (uint, uint)) {
+    let node = handle.borrow();
+    match node.node {
+        Text(ref text) => {
+            let mut space = true;
+            for c in text.as_slice().chars() {
+                let ws = is_ascii_whitespace(c);
+                if !(ws && space) {
+                    lengths.0 += 1;
+                    if in_link {
+                        lengths.1 += 1;
+                    }
+                }
+                space = ws;
+            }
+        }
+        Element(ref name, _) => {
+            let in_link = in_link || name == &qualname!(HTML, a);
+            for child in node.children.iter() {
+                text_length(child, in_link, lengths);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn link_density(lengths: (uint, uint)) -> f64 {
+    match lengths {
+        (0, _) => 0.0,
+        (all, links) => links as f64 / all as f64,
+    }
+}
+
+fn count_commas(handle: &Handle) -> uint {
+    let node = handle.borrow();
+    match node.node {
+        Text(ref text) => text.as_slice().chars().filter(|&c| c == ',').count(),
+        _ => node.children.iter().map(count_commas).fold(0, |a, b| a + b),
+    }
+}
+
+struct Candidate {
+    handle: Handle,
+    score: f64,
+
+    /// Set once the walk has left the element.
+    link_density: f64,
+}
+
+struct Scorer {
+    /// Elements with a score, in the order they were first scored.
+    candidates: Vec<Candidate>,
+
+    /// Where each element in `candidates` is.
+    index: HashMap<NodeId, uint>,
+}
+
+impl Scorer {
+    fn add_score(&mut self, handle: &Handle, score: f64) {
+        let id = node_id(handle);
+        match self.index.get(&id) {
+            Some(&i) => {
+                self.candidates[i].score += score;
+                return;
+            }
+            None => (),
+        }
+
+        let initial = match handle.borrow().node {
+            Element(ref name, ref attrs) => tag_weight(name) + class_weight(attrs.as_slice()),
+            _ => return,
+        };
+        self.index.insert(id, self.candidates.len());
+        self.candidates.push(Candidate {
+            handle: handle.clone(),
+            score: initial + score,
+            link_density: 0.0,
+        });
+    }
+
+    fn score_paragraph(&mut self, handle: &Handle, length: uint) {
+        if length < MIN_PARAGRAPH_LENGTH {
+            return;
+        }
+
+        // One point for the paragraph, one for each comma, and one
+        // for every 100 characters, up to 3.
+        let score = 1.0 + count_commas(handle) as f64
+            + if length >= 300 { 3.0 } else { (length / 100) as f64 };
+
+        let parent = unwrap_or_return!(parent_of(handle), ());
+        self.add_score(&parent, score);
+        match parent_of(&parent) {
+            Some(grandparent) => self.add_score(&grandparent, score / 2.0),
+            None => (),
+        }
+    }
+
+    // Score the paragraphs under a node, returning the lengths of its
+    // text as `text_length` counts them.  The lengths of each element
+    // are known only once its subtree has been walked, so paragraphs are
+    // scored, and link densities found, on the way back up.
+    fn walk(&mut self, handle: &Handle, in_link: bool) -> (uint, uint) {
+        let mut lengths = (0, 0);
+        let node = handle.borrow();
+        let mut in_link = in_link;
+        let mut paragraph = false;
+        match node.node {
+            Text(_) => {
+                text_length(handle, in_link, &mut lengths);
+                return lengths;
+            }
+            Element(ref name, ref attrs) => {
+                if is_unlikely(name, attrs.as_slice()) {
+                    text_length(handle, in_link, &mut lengths);
+                    return lengths;
+                }
+                paragraph = match name.local.as_slice() {
+                    "p" | "pre" | "td" | "blockquote" => true,
+                    _ => false,
+                };
+                in_link = in_link || name == &qualname!(HTML, a);
+            }
+            _ => (),
+        }
+        for child in node.children.iter() {
+            let (all, links) = self.walk(child, in_link);
+            lengths.0 += all;
+            lengths.1 += links;
+        }
+
+        if paragraph {
+            self.score_paragraph(handle, lengths.0);
+        }
+        match self.index.get(&node_id(handle)) {
+            Some(&i) => self.candidates[i].link_density = link_density(lengths),
+            None => (),
+        }
+        lengths
+    }
+
+    fn best(&self) -> Option<Handle> {
+        let mut best: Option<(&Handle, f64)> = None;
+        for candidate in self.candidates.iter() {
+            let score = candidate.score * (1.0 - candidate.link_density);
+            match best {
+                Some((_, best_score)) if best_score >= score => (),
+                _ => best = Some((&candidate.handle, score)),
+            }
+        }
+        best.map(|(handle, _)| handle.clone())
+    }
+}
+
+fn parent_of(handle: &Handle) -> Option<Handle> {
+    handle.borrow().parent.as_ref().and_then(|p| p.upgrade()).map(Handle)
+}
+
+fn find_body(handle: &Handle) -> Option<Handle> {
+    let node = handle.borrow();
+    match node.node {
+        Element(ref name, _) if name == &qualname!(HTML, body) => return Some(handle.clone()),
+        _ => (),
+    }
+    node.children.iter().filter_map(find_body).next()
+}
+
+/// Find the element holding the main content of a document.
+///
+/// If no element looks like content, this returns the `<body>`, or
+/// failing that the document itself.
+pub fn extract(dom: &RcDom) -> Handle {
+    let mut scorer = Scorer {
+        candidates: vec!(),
+        index: HashMap::new(),
+    };
+    scorer.walk(&dom.document, false);
+    match scorer.best() {
+        Some(handle) => handle,
+        None => find_body(&dom.document).unwrap_or_else(|| dom.document.clone()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::string::String;
+
+    use super::extract;
+    use sink::common::Element;
+    use sink::rcdom::RcDom;
+    use driver::parse_str;
+
+    fn extracted(input: &str) -> String {
+        let dom: RcDom = parse_str(input);
+        let handle = extract(&dom);
+        let node = handle.borrow();
+        match node.node {
+            Element(ref name, ref attrs) => {
+                match attrs.iter().find(|a| a.name == qualname!("", "id")) {
+                    Some(id) => format!("{}#{}", name.local.as_slice(), id.value),
+                    None => String::from_str(name.local.as_slice()),
+                }
+            }
+            _ => String::from_str("document"),
+        }
+    }
+
+    test_eq!(article, extracted("<div id=nav><p><a href=/>Home</a> <a href=/a>About us and \
+        other pages</a></p></div><div id=story><p>The first paragraph of the story, which \
+        goes on for a while.</p><p>The second paragraph, with a comma, and more text.</p>\
+        </div><div class=sidebar><p>Related stories which are not what we want here.</p></div>"),
+        "div#story");
+
+    test_eq!(links_only, extracted("<div id=a><p><a href=/1>A long list of links, one</a> \
+        <a href=/2>and two, and three</a></p></div>\
+        <div id=b><p>Somewhat shorter prose text.</p></div>"),
+        "div#b");
+
+    test_eq!(no_content, extracted("<p>Short."), "body");
+
+    test_eq!(nested, extracted("<div id=outer><div id=inner><p>The first paragraph, which \
+        is long enough.</p><p>The <a href=/>second</a> paragraph, also long enough.</p></div>\
+        <p>Trailing prose which is also long enough.</p></div>"),
+        "div#inner");
+}
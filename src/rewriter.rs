@@ -0,0 +1,1047 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Rewriting HTML as it streams through, without building a DOM.
+//!
+//! Handlers are registered for elements, attributes and text matching
+//! simple selectors, and can change attributes, insert content around
+//! elements, or remove them.  Everything else is written out as it
+//! comes in, re-serialized from the tokens; so character references
+//! are resolved and attribute quoting normalized, but the document
+//! means the same.
+//!
+//! Selectors are compound selectors, such as `a[href^="http:"]`,
+//! `div.note#top` or `*`, separated by commas.  Combinators are not
+//! supported, because only the element's own start tag is known when
+//! its handlers run.
+//!
+//! There is no tree builder, so elements are tracked with a stack of
+//! open elements which knows about void elements and the most common
+//! implied end tags (`<p>`, `<li>`, table parts and so on).  Content
+//! appended to an element whose end tag is implied is written where
+//! the rewriter notices that the element has ended.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let mut rewriter = Rewriter::new(output);
+//! rewriter.on_attribute("a[href]", "href", |href| {
+//!     *href = href.replace("http:", "https:");
+//! }).unwrap();
+//! rewriter.on_element("head", |el| {
+//!     el.append("<script src=/inject.js></script>", Html);
+//! }).unwrap();
+//! try!(rewriter.write(chunk));
+//! let output = try!(rewriter.end());
+//! ```
+
+use core::prelude::*;
+
+use sink::common::{raw_text_state, is_void};
+use sink::links::{url_kind, SingleUrl, Srcset};
+use microsyntax::srcset::parse_srcset;
+use tokenizer::{Tokenizer, TokenSink, TokenSinkResult, Continue, Stop, Token};
+use tokenizer::{DoctypeToken, TagToken, CommentToken, BogusCommentToken};
+use tokenizer::{CharacterTokens, NullCharacterToken, EOFToken, ParseError};
+use tokenizer::{Doctype, Tag, StartTag, EndTag, Attribute};
+use tokenizer::states::{State, RawData, Rcdata};
+
+use util::str::{AsciiExt, is_ascii_whitespace};
+
+use core::default::Default;
+use alloc::boxed::Box;
+use collections::vec::Vec;
+use collections::string::String;
+use std::old_io::{Writer, IoResult, IoError};
+
+use string_cache::{Atom, QualName};
+
+pub use self::ContentType::{Html, PlainText};
+
+use self::AttrOp::{Exists, Equals, Prefix, Suffix, Substring, Word};
+
+/// How inserted content is written.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum ContentType {
+    /// As markup, unchanged.
+    Html,
+
+    /// As text, with `&`, `<` and `>` escaped.
+    PlainText,
+}
+
+fn escape(text: &str, attr: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '"' if attr => out.push_str("&quot;"),
+            '<' if !attr => out.push_str("&lt;"),
+            '>' if !attr => out.push_str("&gt;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn content(text: &str, kind: ContentType) -> String {
+    match kind {
+        Html => String::from_str(text),
+        PlainText => escape(text, false),
+    }
+}
+
+/// An error in a selector.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct SelectorError {
+    /// Byte offset of the error.
+    pub position: uint,
+
+    /// What was wrong.
+    pub message: &'static str,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+enum AttrOp {
+    Exists,
+    Equals,
+    Prefix,
+    Suffix,
+    Substring,
+    Word,
+}
+
+#[derive(Clone, Debug)]
+struct AttrTest {
+    name: Atom,
+    op: AttrOp,
+    value: String,
+}
+
+#[derive(Clone, Debug)]
+struct Compound {
+    name: Option<Atom>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attrs: Vec<AttrTest>,
+}
+
+/// A comma-separated list of compound selectors.
+#[derive(Clone, Debug)]
+pub struct Selector {
+    alternatives: Vec<Compound>,
+}
+
+struct SelectorParser<'a> {
+    input: &'a str,
+    pos: uint,
+}
+
+impl<'a> SelectorParser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn error<T>(&self, message: &'static str) -> Result<T, SelectorError> {
+        Err(SelectorError {
+            position: self.pos,
+            message: message,
+        })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> Result<&'a str, SelectorError> {
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(c) if c.is_alphanumeric() || c == '-' || c == '_' || (c as u32) >= 0x80
+                    => self.pos += c.len_utf8(),
+                _ => break,
+            }
+        }
+        if self.pos == start {
+            return self.error("Expected a name");
+        }
+        Ok(&self.input[start..self.pos])
+    }
+
+    fn value(&mut self) -> Result<String, SelectorError> {
+        match self.peek() {
+            Some(q) if q == '"' || q == '\'' => {
+                self.pos += 1;
+                let rest = &self.input[self.pos..];
+                let end = unwrap_or_else!(rest.find(q), { return self.error("Unterminated string") });
+                self.pos += end + 1;
+                Ok(String::from_str(&rest[..end]))
+            }
+            _ => self.ident().map(String::from_str),
+        }
+    }
+
+    fn attr_test(&mut self) -> Result<AttrTest, SelectorError> {
+        self.skip_whitespace();
+        let name = Atom::from_slice(try!(self.ident()).to_ascii_lower().as_slice());
+        self.skip_whitespace();
+        let op = match self.peek() {
+            Some(']') => Exists,
+            Some('=') => Equals,
+            Some('^') => Prefix,
+            Some('$') => Suffix,
+            Some('*') => Substring,
+            Some('~') => Word,
+            _ => return self.error("Expected an attribute operator"),
+        };
+        let mut test = AttrTest {
+            name: name,
+            op: op,
+            value: String::new(),
+        };
+        if op != Exists {
+            self.pos += 1;
+            if op != Equals && !self.eat('=') {
+                return self.error("Expected '='");
+            }
+            self.skip_whitespace();
+            test.value = try!(self.value());
+            self.skip_whitespace();
+        }
+        if !self.eat(']') {
+            return self.error("Expected ']'");
+        }
+        Ok(test)
+    }
+
+    fn compound(&mut self) -> Result<Compound, SelectorError> {
+        let mut compound = Compound {
+            name: None,
+            id: None,
+            classes: vec!(),
+            attrs: vec!(),
+        };
+        let start = self.pos;
+        if !self.eat('*') && self.peek().map_or(false, |c| c.is_alphanumeric()) {
+            let name = try!(self.ident()).to_ascii_lower();
+            compound.name = Some(Atom::from_slice(name.as_slice()));
+        }
+        loop {
+            if self.eat('#') {
+                compound.id = Some(String::from_str(try!(self.ident())));
+            } else if self.eat('.') {
+                compound.classes.push(String::from_str(try!(self.ident())));
+            } else if self.eat('[') {
+                compound.attrs.push(try!(self.attr_test()));
+            } else {
+                break;
+            }
+        }
+        if self.pos == start {
+            return self.error("Expected a selector");
+        }
+        Ok(compound)
+    }
+}
+
+fn attr_value<'a>(attrs: &'a [Attribute], name: &Atom) -> Option<&'a str> {
+    attrs.iter().find(|a| a.name.ns == ns!("") && a.name.local == *name)
+        .map(|a| a.value.as_slice())
+}
+
+impl Compound {
+    fn matches(&self, name: &Atom, attrs: &[Attribute]) -> bool {
+        if self.name.as_ref().map_or(false, |n| n != name) {
+            return false;
+        }
+        match self.id {
+            Some(ref id) if attr_value(attrs, &atom!(id)) != Some(id.as_slice()) => return false,
+            _ => (),
+        }
+        if !self.classes.is_empty() {
+            let classes = attr_value(attrs, &atom!(class)).unwrap_or("");
+            let words: Vec<&str> = classes.split(is_ascii_whitespace).collect();
+            if !self.classes.iter().all(|c| words.contains(&c.as_slice())) {
+                return false;
+            }
+        }
+        self.attrs.iter().all(|test| {
+            let value = unwrap_or_return!(attr_value(attrs, &test.name), false);
+            let expected = test.value.as_slice();
+            match test.op {
+                Exists => true,
+                Equals => value == expected,
+                Prefix => !expected.is_empty() && value.starts_with(expected),
+                Suffix => !expected.is_empty() && value.ends_with(expected),
+                Substring => !expected.is_empty() && value.contains(expected),
+                Word => value.split(is_ascii_whitespace).any(|w| w == expected),
+            }
+        })
+    }
+}
+
+impl Selector {
+    /// Parse a selector.
+    pub fn parse(input: &str) -> Result<Selector, SelectorError> {
+        let mut parser = SelectorParser {
+            input: input,
+            pos: 0,
+        };
+        let mut alternatives = vec!();
+        loop {
+            parser.skip_whitespace();
+            alternatives.push(try!(parser.compound()));
+            parser.skip_whitespace();
+            match parser.peek() {
+                None => break,
+                Some(',') => parser.pos += 1,
+                Some(_) => return parser.error("Combinators are not supported"),
+            }
+        }
+        Ok(Selector {
+            alternatives: alternatives,
+        })
+    }
+
+    /// Does this match an element with the given name and attributes?
+    pub fn matches(&self, name: &Atom, attrs: &[Attribute]) -> bool {
+        self.alternatives.iter().any(|c| c.matches(name, attrs))
+    }
+}
+
+/// An element whose start tag is being rewritten.
+pub struct Element {
+    name: Atom,
+    attrs: Vec<Attribute>,
+    self_closing: bool,
+    before: String,
+    prepend: String,
+    append: String,
+    after: String,
+    inner: Option<String>,
+    removed: bool,
+    keep_content: bool,
+}
+
+impl Element {
+    /// The element's local name, in lowercase.
+    pub fn tag_name(&self) -> &str {
+        self.name.as_slice()
+    }
+
+    /// Rename the element.  Its end tag is renamed too.
+    pub fn set_tag_name(&mut self, name: &str) {
+        self.name = Atom::from_slice(name.to_ascii_lower().as_slice());
+    }
+
+    /// The element's attributes, in source order.
+    pub fn attributes(&self) -> &[Attribute] {
+        self.attrs.as_slice()
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        attr_value(self.attrs.as_slice(), &Atom::from_slice(name.to_ascii_lower().as_slice()))
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.get_attribute(name).is_some()
+    }
+
+    /// Set an attribute, adding it if it isn't there.
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        let name = Atom::from_slice(name.to_ascii_lower().as_slice());
+        match self.attrs.iter_mut().find(|a| a.name.ns == ns!("") && a.name.local == name) {
+            Some(attr) => {
+                attr.value = String::from_str(value);
+                return;
+            }
+            None => (),
+        }
+        self.attrs.push(Attribute {
+            name: QualName::new(ns!(""), name),
+            value: String::from_str(value),
+        });
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) {
+        let name = Atom::from_slice(name.to_ascii_lower().as_slice());
+        self.attrs.retain(|a| !(a.name.ns == ns!("") && a.name.local == name));
+    }
+
+    /// Insert content before the start tag.
+    pub fn before(&mut self, text: &str, kind: ContentType) {
+        self.before.push_str(content(text, kind).as_slice());
+    }
+
+    /// Insert content after the end tag.
+    pub fn after(&mut self, text: &str, kind: ContentType) {
+        let mut text = content(text, kind);
+        text.push_str(self.after.as_slice());
+        self.after = text;
+    }
+
+    /// Insert content just after the start tag.
+    pub fn prepend(&mut self, text: &str, kind: ContentType) {
+        let mut text = content(text, kind);
+        text.push_str(self.prepend.as_slice());
+        self.prepend = text;
+    }
+
+    /// Insert content just before the end tag.
+    pub fn append(&mut self, text: &str, kind: ContentType) {
+        self.append.push_str(content(text, kind).as_slice());
+    }
+
+    /// Replace the element's content.  Handlers don't run on the old
+    /// content.
+    pub fn set_inner_content(&mut self, text: &str, kind: ContentType) {
+        self.inner = Some(content(text, kind));
+    }
+
+    /// Remove the element and its content.  Content inserted with
+    /// `before` and `after` is still written.
+    pub fn remove(&mut self) {
+        self.removed = true;
+        self.keep_content = false;
+    }
+
+    /// Remove the element's start and end tags, keeping its content.
+    pub fn remove_and_keep_content(&mut self) {
+        self.removed = true;
+        self.keep_content = true;
+    }
+}
+
+/// Text being rewritten.  The text of one element may arrive in several
+/// chunks.
+pub struct TextChunk {
+    text: String,
+    replacement: Option<String>,
+}
+
+impl TextChunk {
+    pub fn as_str(&self) -> &str {
+        self.text.as_slice()
+    }
+
+    /// Write this in place of the text.
+    pub fn replace(&mut self, text: &str, kind: ContentType) {
+        self.replacement = Some(content(text, kind));
+    }
+
+    /// Remove the text.
+    pub fn remove(&mut self) {
+        self.replacement = Some(String::new());
+    }
+}
+
+struct OpenElement {
+    name: Atom,
+
+    /// Name to write in the end tag, if the tag is kept.
+    end_name: Option<Atom>,
+
+    append: String,
+    after: String,
+
+    /// Is the element's content dropped?
+    skip_content: bool,
+
+    /// Is this an SVG or MathML element?
+    foreign: bool,
+
+    /// Indices of the text handlers which this element matched.
+    text_handlers: Vec<uint>,
+}
+
+// Does a start tag named `new` imply the end of an open element named
+// `open`?  This covers the usual cases of optional end tags.
+fn implies_end(open: &Atom, new: &Atom) -> bool {
+    match (open, new) {
+        (&atom!(li), &atom!(li)) => true,
+        (&atom!(dd), &atom!(dd)) | (&atom!(dd), &atom!(dt))
+        | (&atom!(dt), &atom!(dd)) | (&atom!(dt), &atom!(dt)) => true,
+        (&atom!(option), &atom!(option)) | (&atom!(option), &atom!(optgroup))
+        | (&atom!(optgroup), &atom!(optgroup)) => true,
+        (&atom!(td), &atom!(td)) | (&atom!(td), &atom!(th)) | (&atom!(td), &atom!(tr))
+        | (&atom!(th), &atom!(td)) | (&atom!(th), &atom!(th)) | (&atom!(th), &atom!(tr))
+        | (&atom!(tr), &atom!(tr)) => true,
+        (&atom!(td), n) | (&atom!(th), n) | (&atom!(tr), n) | (&atom!(thead), n)
+        | (&atom!(tbody), n) | (&atom!(tfoot), n)
+            => *n == atom!(thead) || *n == atom!(tbody) || *n == atom!(tfoot),
+        (&atom!(p), n) => match *n {
+            atom!(address) | atom!(article) | atom!(aside) | atom!(blockquote)
+            | atom!(details) | atom!(div) | atom!(dl) | atom!(fieldset)
+            | atom!(figcaption) | atom!(figure) | atom!(footer) | atom!(form)
+            | atom!(h1) | atom!(h2) | atom!(h3) | atom!(h4) | atom!(h5) | atom!(h6)
+            | atom!(header) | atom!(hr) | atom!(main) | atom!(menu) | atom!(nav)
+            | atom!(ol) | atom!(p) | atom!(pre) | atom!(section) | atom!(table)
+            | atom!(ul) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+struct RewriteSink<'h, Wr> {
+    output: Wr,
+    error: Option<IoError>,
+    element_handlers: Vec<(Selector, Box<FnMut(&mut Element) + 'h>)>,
+    text_handlers: Vec<(Selector, Box<FnMut(&mut TextChunk) + 'h>)>,
+    stack: Vec<OpenElement>,
+    next_state: Option<State>,
+}
+
+impl<'h, Wr: Writer> RewriteSink<'h, Wr> {
+    fn skipping(&self) -> bool {
+        self.stack.iter().any(|e| e.skip_content)
+    }
+
+    fn in_foreign(&self) -> bool {
+        self.stack.last().map_or(false, |e| e.foreign)
+    }
+
+    fn write(&mut self, s: &str) -> IoResult<()> {
+        if s.is_empty() {
+            Ok(())
+        } else {
+            self.output.write_str(s)
+        }
+    }
+
+    // Close the top open element.  Only an explicit end tag is written,
+    // because implied end tags were not in the input either.
+    fn pop(&mut self, explicit: bool) -> IoResult<()> {
+        let elem = self.stack.pop().expect("no open element");
+        if self.skipping() {
+            return Ok(());
+        }
+        try!(self.write(elem.append.as_slice()));
+        match elem.end_name {
+            Some(ref name) if explicit => try!(self.output.write_str(
+                format!("</{}>", name.as_slice()).as_slice())),
+            _ => (),
+        }
+        self.write(elem.after.as_slice())
+    }
+
+    fn start_tag(&mut self, tag: Tag) -> IoResult<()> {
+        while self.stack.last().map_or(false, |e| !e.foreign && implies_end(&e.name, &tag.name)) {
+            try!(self.pop(false));
+        }
+
+        let foreign = self.in_foreign() || tag.name == atom!(svg) || tag.name == atom!(math);
+        let void = if foreign { tag.self_closing } else { is_void(&tag.name) };
+        if !foreign {
+            self.next_state = raw_text_state(&tag.name);
+        }
+
+        if self.skipping() {
+            if !void {
+                self.stack.push(OpenElement {
+                    name: tag.name,
+                    end_name: None,
+                    append: String::new(),
+                    after: String::new(),
+                    skip_content: false,
+                    foreign: foreign,
+                    text_handlers: vec!(),
+                });
+            }
+            return Ok(());
+        }
+
+        let Tag { name, attrs, self_closing, .. } = tag;
+        let mut elem = Element {
+            name: name.clone(),
+            attrs: attrs,
+            self_closing: self_closing,
+            before: String::new(),
+            prepend: String::new(),
+            append: String::new(),
+            after: String::new(),
+            inner: None,
+            removed: false,
+            keep_content: false,
+        };
+        for &mut (ref selector, ref mut handler) in self.element_handlers.iter_mut() {
+            if selector.matches(&name, elem.attrs.as_slice()) {
+                (*handler)(&mut elem);
+            }
+        }
+        let text_handlers = self.text_handlers.iter().enumerate()
+            .filter(|&(_, &(ref selector, _))| selector.matches(&name, elem.attrs.as_slice()))
+            .map(|(i, _)| i)
+            .collect();
+
+        try!(self.write(elem.before.as_slice()));
+        let keep_tags = !elem.removed;
+        if keep_tags {
+            let mut start = String::new();
+            start.push('<');
+            start.push_str(elem.name.as_slice());
+            for attr in elem.attrs.iter() {
+                start.push(' ');
+                start.push_str(attr.name.local.as_slice());
+                start.push_str("=\"");
+                start.push_str(escape(attr.value.as_slice(), true).as_slice());
+                start.push('"');
+            }
+            start.push_str(if elem.self_closing { "/>" } else { ">" });
+            try!(self.write(start.as_slice()));
+        }
+
+        let skip_content = (elem.removed && !elem.keep_content) || elem.inner.is_some();
+        if !elem.removed || elem.keep_content {
+            try!(self.write(elem.prepend.as_slice()));
+            match elem.inner {
+                Some(ref inner) => try!(self.write(inner.as_slice())),
+                None => (),
+            }
+        }
+
+        if void {
+            return self.write(elem.after.as_slice());
+        }
+        self.stack.push(OpenElement {
+            name: name,
+            end_name: if keep_tags { Some(elem.name) } else { None },
+            append: if skip_content && elem.inner.is_none() { String::new() } else { elem.append },
+            after: elem.after,
+            skip_content: skip_content,
+            foreign: foreign,
+            text_handlers: text_handlers,
+        });
+        Ok(())
+    }
+
+    fn end_tag(&mut self, tag: Tag) -> IoResult<()> {
+        match self.stack.iter().rposition(|e| e.name == tag.name) {
+            Some(i) => {
+                while self.stack.len() > i + 1 {
+                    try!(self.pop(false));
+                }
+                self.pop(true)
+            }
+            // A stray end tag, which we leave for the parser to ignore.
+            None if !self.skipping() => self.write(format!("</{}>", tag.name.as_slice()).as_slice()),
+            None => Ok(()),
+        }
+    }
+
+    fn text(&mut self, text: String) -> IoResult<()> {
+        if self.skipping() {
+            return Ok(());
+        }
+
+        let mut chunk = TextChunk {
+            text: text,
+            replacement: None,
+        };
+        let stack = &self.stack;
+        for (i, &mut (_, ref mut handler)) in self.text_handlers.iter_mut().enumerate() {
+            if stack.iter().any(|e| e.text_handlers.contains(&i)) {
+                (*handler)(&mut chunk);
+            }
+        }
+
+        // Character references are resolved in RCDATA, so it's escaped
+        // like normal text.
+        let raw = match self.stack.last() {
+            Some(e) if !e.foreign => match raw_text_state(&e.name) {
+                Some(RawData(Rcdata)) | None => false,
+                Some(_) => true,
+            },
+            _ => false,
+        };
+        match chunk.replacement {
+            Some(ref replacement) => self.write(replacement.as_slice()),
+            None if raw => self.write(chunk.text.as_slice()),
+            None => self.write(escape(chunk.text.as_slice(), false).as_slice()),
+        }
+    }
+
+    fn doctype(&mut self, doctype: Doctype) -> IoResult<()> {
+        let mut out = String::from_str("<!DOCTYPE");
+        match doctype.name {
+            Some(ref name) => {
+                out.push(' ');
+                out.push_str(name.as_slice());
+            }
+            None => (),
+        }
+        match (doctype.public_id, doctype.system_id) {
+            (Some(public), system) => {
+                out.push_str(" PUBLIC \"");
+                out.push_str(public.as_slice());
+                out.push('"');
+                match system {
+                    Some(system) => {
+                        out.push_str(" \"");
+                        out.push_str(system.as_slice());
+                        out.push('"');
+                    }
+                    None => (),
+                }
+            }
+            (None, Some(system)) => {
+                out.push_str(" SYSTEM \"");
+                out.push_str(system.as_slice());
+                out.push('"');
+            }
+            (None, None) => (),
+        }
+        out.push('>');
+        self.write(out.as_slice())
+    }
+
+    fn process(&mut self, token: Token) -> IoResult<()> {
+        match token {
+            TagToken(tag) => match tag.kind {
+                StartTag => self.start_tag(tag),
+                EndTag => self.end_tag(tag),
+            },
+            CharacterTokens(text) => self.text(text),
+            NullCharacterToken => self.text(String::from_str("\u{FFFD}")),
+            CommentToken(text) | BogusCommentToken(text) => if self.skipping() {
+                Ok(())
+            } else {
+                self.write(format!("<!--{}-->", text).as_slice())
+            },
+            DoctypeToken(doctype) => self.doctype(doctype),
+            EOFToken => {
+                while !self.stack.is_empty() {
+                    try!(self.pop(false));
+                }
+                self.output.flush()
+            }
+            ParseError(_) => Ok(()),
+        }
+    }
+}
+
+impl<'h, Wr: Writer> TokenSink for RewriteSink<'h, Wr> {
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
+        if self.error.is_some() {
+            return Stop;
+        }
+        match self.process(token) {
+            Ok(()) => Continue,
+            Err(e) => {
+                self.error = Some(e);
+                Stop
+            }
+        }
+    }
+
+    fn query_state_change(&mut self) -> Option<State> {
+        self.next_state.take()
+    }
+
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        self.in_foreign()
+    }
+}
+
+// Resolve each URL in a `srcset`, keeping everything else as it was.
+// Returns `None` if nothing changed.
+fn resolve_srcset<F>(value: &str, mut resolve: F) -> Option<String>
+    where F: FnMut(&str) -> Option<String>,
+{
+    let mut out = String::new();
+    let mut copied = 0;
+    let mut changed = false;
+    for candidate in parse_srcset(value).into_iter() {
+        let new = unwrap_or_else!(resolve(candidate.url), { continue });
+        // The URL is a slice of `value`.
+        let start = candidate.url.as_ptr() as uint - value.as_ptr() as uint;
+        out.push_str(&value[copied..start]);
+        out.push_str(new.as_slice());
+        copied = start + candidate.url.len();
+        changed = true;
+    }
+    if !changed {
+        return None;
+    }
+    out.push_str(&value[copied..]);
+    Some(out)
+}
+
+/// Rewrites HTML written to it, passing the result to a `Writer`.
+pub struct Rewriter<'h, Wr> {
+    tokenizer: Tokenizer<RewriteSink<'h, Wr>>,
+}
+
+impl<'h, Wr: Writer> Rewriter<'h, Wr> {
+    pub fn new(output: Wr) -> Rewriter<'h, Wr> {
+        let sink = RewriteSink {
+            output: output,
+            error: None,
+            element_handlers: vec!(),
+            text_handlers: vec!(),
+            stack: vec!(),
+            next_state: None,
+        };
+        Rewriter {
+            tokenizer: Tokenizer::new(sink, Default::default()),
+        }
+    }
+
+    /// Run `handler` on the start tag of each element matching
+    /// `selector`.  Handlers run in the order they were registered.
+    pub fn on_element<F>(&mut self, selector: &str, handler: F) -> Result<(), SelectorError>
+        where F: FnMut(&mut Element) + 'h,
+    {
+        let selector = try!(Selector::parse(selector));
+        self.tokenizer.sink_mut().element_handlers.push((selector, box handler));
+        Ok(())
+    }
+
+    /// Run `handler` on the value of the attribute `attr` of each element
+    /// matching `selector` which has it.
+    pub fn on_attribute<F>(&mut self, selector: &str, attr: &str, mut handler: F)
+            -> Result<(), SelectorError>
+        where F: FnMut(&mut String) + 'h,
+    {
+        let attr = Atom::from_slice(attr.to_ascii_lower().as_slice());
+        self.on_element(selector, move |elem: &mut Element| {
+            for a in elem.attrs.iter_mut() {
+                if a.name.ns == ns!("") && a.name.local == attr {
+                    handler(&mut a.value);
+                }
+            }
+        })
+    }
+
+    /// Run `handler` on text inside elements matching `selector`.
+    pub fn on_text<F>(&mut self, selector: &str, handler: F) -> Result<(), SelectorError>
+        where F: FnMut(&mut TextChunk) + 'h,
+    {
+        let selector = try!(Selector::parse(selector));
+        self.tokenizer.sink_mut().text_handlers.push((selector, box handler));
+        Ok(())
+    }
+
+    /// Make the URLs in the attributes which `LinkSink` knows about,
+    /// such as `<a href>` and `<img srcset>`, absolute, as a proxy would.
+    ///
+    /// `resolve(base, url)` resolves a URL with the URL parser of the
+    /// caller's choice, or returns `None` to leave it alone, for example
+    /// if it doesn't parse.  URLs are resolved against `base` until the
+    /// first `<base href>`, which is resolved itself and then used
+    /// instead; later `<base>` elements are rewritten but don't count,
+    /// as in a browser.
+    pub fn resolve_urls<F>(&mut self, base: &str, mut resolve: F)
+        where F: FnMut(&str, &str) -> Option<String> + 'h,
+    {
+        let mut base = String::from_str(base);
+        let mut seen_base = false;
+        self.on_element("*", move |elem: &mut Element| {
+            for attr in elem.attrs.iter_mut() {
+                if attr.name.ns != ns!("") {
+                    continue;
+                }
+                let new = match url_kind(&elem.name, &attr.name.local) {
+                    Some(SingleUrl) => resolve(base.as_slice(),
+                        attr.value.as_slice().trim_matches(is_ascii_whitespace)),
+                    Some(Srcset) => resolve_srcset(attr.value.as_slice(),
+                        |url: &str| resolve(base.as_slice(), url)),
+                    None => continue,
+                };
+                let first_base = elem.name == atom!(base) && !seen_base;
+                seen_base = seen_base || first_base;
+                let new = unwrap_or_else!(new, { continue });
+                if first_base {
+                    base = new.clone();
+                }
+                attr.value = new;
+            }
+        }).ok().expect("\"*\" is a valid selector");
+    }
+
+    /// The `Writer` receiving output.
+    pub fn output(&self) -> &Wr {
+        &self.tokenizer.sink().output
+    }
+
+    /// The `Writer` receiving output, for example to `resume()` a
+    /// `ChunkedWriter`.
+    pub fn output_mut(&mut self) -> &mut Wr {
+        &mut self.tokenizer.sink_mut().output
+    }
+
+    fn take_error(&mut self) -> IoResult<()> {
+        match self.tokenizer.sink_mut().error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Rewrite some more input.  Output for a tag or text may wait
+    /// until the next input shows where it ends.
+    pub fn write(&mut self, input: &str) -> IoResult<()> {
+        self.tokenizer.feed(String::from_str(input));
+        self.take_error()
+    }
+
+    /// Finish rewriting, closing any open elements, and return the
+    /// `Writer`.
+    pub fn end(mut self) -> IoResult<Wr> {
+        self.tokenizer.end();
+        try!(self.take_error());
+        Ok(self.tokenizer.unwrap().output)
+    }
+}
+
+/// Rewrite a complete document into a `String`.  `setup` registers
+/// the handlers.
+pub fn rewrite_str<'h, F>(input: &str, setup: F) -> Result<String, SelectorError>
+    where F: FnOnce(&mut Rewriter<'h, Vec<u8>>) -> Result<(), SelectorError>,
+{
+    let mut rewriter = Rewriter::new(vec!());
+    try!(setup(&mut rewriter));
+    rewriter.write(input).ok().expect("writing to a Vec failed");
+    let output = rewriter.end().ok().expect("writing to a Vec failed");
+    Ok(String::from_utf8(output).ok().expect("rewriter produced invalid UTF-8"))
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::{rewrite_str, Rewriter, Selector, SelectorError, Html, PlainText};
+    use serialize::chunked::{ChunkedWriter, Proceed, Pause};
+
+    fn same(input: &str) -> String {
+        rewrite_str(input, |_: &mut Rewriter<_>| Ok(())).unwrap()
+    }
+
+    #[test]
+    fn passes_through() {
+        assert_eq!(same("<!DOCTYPE html><p class=x>a &amp; b<br><!-- c --><script>if (a<b) x()</script>"),
+            "<!DOCTYPE html><p class=\"x\">a &amp; b<br><!-- c --><script>if (a<b) x()</script>");
+        assert_eq!(same("<svg><path d=x /></svg><title>a<b</title>"),
+            "<svg><path d=\"x\"/></svg><title>a&lt;b</title>");
+        assert_eq!(same("<style>p>b{}</style><textarea>&lt;&amp;</textarea>"),
+            "<style>p>b{}</style><textarea>&lt;&amp;</textarea>");
+    }
+
+    #[test]
+    fn attributes() {
+        let out = rewrite_str("<a href=http://a/>x</a><img src=http://b/><a>y</a>", |r| {
+            try!(r.on_attribute("a, img", "href", |href| {
+                *href = href.replace("http:", "https:");
+            }));
+            r.on_element("img[src^=\"http:\"]", |el| {
+                assert_eq!(el.get_attribute("SRC"), Some("http://b/"));
+                el.set_attribute("loading", "lazy");
+                el.remove_attribute("src");
+            })
+        }).unwrap();
+        assert_eq!(out, "<a href=\"https://a/\">x</a><img loading=\"lazy\"><a>y</a>");
+    }
+
+    #[test]
+    fn insertion_and_removal() {
+        let out = rewrite_str("<head><title>T</title></head><div class='ad x'>ad<p>z</div>\
+                <ul><li>1<li>2</ul><span>s</span>", |r| {
+            try!(r.on_element("head", |el| el.append("<script src=i.js></script>", Html)));
+            try!(r.on_element(".ad", |el| {
+                el.remove();
+                el.after("<!-- removed -->", Html);
+            }));
+            try!(r.on_element("li", |el| el.after("!", PlainText)));
+            r.on_element("span", |el| el.remove_and_keep_content())
+        }).unwrap();
+        assert_eq!(out, "<head><title>T</title><script src=i.js></script></head><!-- removed -->\
+            <ul><li>1!<li>2!</ul>s");
+    }
+
+    #[test]
+    fn text() {
+        let out = rewrite_str("<p>keep</p><div id=x>a<b>b</b></div>", |r| {
+            r.on_text("#x", |t| {
+                let text = format!("[{}]", t.as_str());
+                t.replace(text.as_slice(), PlainText);
+            })
+        }).unwrap();
+        assert_eq!(out, "<p>keep</p><div id=\"x\">[a]<b>[b]</b></div>");
+
+        let out = rewrite_str("<div><p>old</p></div>", |r| {
+            r.on_element("div", |el| el.set_inner_content("<new>", PlainText))
+        }).unwrap();
+        assert_eq!(out, "<div>&lt;new&gt;</div>");
+    }
+
+    #[test]
+    fn chunked_output() {
+        let mut chunks: Vec<Vec<u8>> = vec!();
+        {
+            let mut rewriter = Rewriter::new(ChunkedWriter::new(8, |c: &[u8]| {
+                chunks.push(c.to_vec());
+                if chunks.len() == 1 { Pause } else { Proceed }
+            }));
+            rewriter.on_element("b", |el| el.set_tag_name("strong")).unwrap();
+            rewriter.write("<p>one <b>two</b> ").unwrap();
+            assert!(rewriter.output().is_paused());
+            rewriter.output_mut().resume();
+            rewriter.write("three").unwrap();
+            rewriter.end().unwrap();
+        }
+        let all: Vec<u8> = chunks.iter().flat_map(|c| c.iter().map(|&b| b)).collect();
+        assert_eq!(all.as_slice(), b"<p>one <strong>two</strong> three");
+        assert!(chunks.iter().all(|c| c.len() <= 8));
+    }
+
+    #[test]
+    fn selector_errors() {
+        assert!(Selector::parse("div > p").is_err());
+        assert_eq!(Selector::parse("a[href").err(),
+            Some(SelectorError { position: 6, message: "Expected an attribute operator" }));
+        assert!(Selector::parse("").is_err());
+        assert!(Selector::parse("a[x~=y], *.c#d").is_ok());
+    }
+
+    #[test]
+    fn resolve_urls() {
+        // A stand-in for a real URL parser.
+        let resolve = |base: &str, url: &str| {
+            if url.contains(":") {
+                None
+            } else if url.starts_with("/") {
+                Some(format!("http://h{}", url))
+            } else {
+                Some(format!("{}{}", base, url))
+            }
+        };
+        let out = rewrite_str("<a href=' x.html '>a</a><img src=/i.png srcset='a.png 1x,b.png  2x'>\
+            <base href=sub/><a href=y>b</a><base href=/other/><a href=mailto:m>c</a>\
+            <p title=x.html></p>", |r| {
+            r.resolve_urls("http://h/d/", resolve);
+            Ok(())
+        });
+        assert_eq!(out.unwrap(), "<a href=\"http://h/d/x.html\">a</a><img src=\"http://h/i.png\" \
+            srcset=\"http://h/d/a.png 1x,http://h/d/b.png  2x\"><base href=\"http://h/d/sub/\">\
+            <a href=\"http://h/d/sub/y\">b</a><base href=\"http://h/other/\">\
+            <a href=\"mailto:m\">c</a><p title=\"x.html\"></p>");
+    }
+}
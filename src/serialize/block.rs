@@ -0,0 +1,165 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Laying out blocks of lines with prefixes, for the plain text and
+//! Markdown renderers.
+//!
+//! Each list item and block quote adds a level of prefix to the lines
+//! inside it.  A list item's level starts with its bullet or number,
+//! which is used for the first line written inside the item and then
+//! replaced by spaces.  Since each level keeps its own bullet, an item
+//! whose first content is a nested list gets both bullets on one line.
+
+use core::prelude::*;
+
+use sink::common::attr;
+use tokenizer::Attribute;
+
+use collections::vec::Vec;
+use collections::string::String;
+use std::iter::repeat;
+
+use string_cache::Atom;
+
+struct Level {
+    /// Prefix for lines at this level.
+    indent: String,
+
+    /// Bullet or number to use instead of `indent`, on the next line.
+    bullet: Option<String>,
+}
+
+pub struct Blocks {
+    /// Finished output.
+    pub out: String,
+
+    /// Put a blank line before the next output?
+    pub blank_line: bool,
+
+    levels: Vec<Level>,
+
+    /// For each open list, the next item number, or `None` for bullets.
+    lists: Vec<Option<i64>>,
+}
+
+impl Blocks {
+    pub fn new() -> Blocks {
+        Blocks {
+            out: String::new(),
+            blank_line: false,
+            levels: vec!(),
+            lists: vec!(),
+        }
+    }
+
+    fn prefix(&self, bullets: bool) -> String {
+        let mut prefix = String::new();
+        for level in self.levels.iter() {
+            match level.bullet {
+                Some(ref b) if bullets => prefix.push_str(b.as_slice()),
+                _ => prefix.push_str(level.indent.as_slice()),
+            }
+        }
+        prefix
+    }
+
+    /// Width in characters of the prefix of lines after the first.
+    pub fn indent_width(&self) -> uint {
+        self.levels.iter().map(|l| l.indent.as_slice().chars().count()).fold(0, |a, b| a + b)
+    }
+
+    /// Is there a bullet which hasn't been written yet?
+    pub fn bullet_pending(&self) -> bool {
+        self.levels.iter().any(|l| l.bullet.is_some())
+    }
+
+    /// Write the blank line, if one is wanted, before more output.
+    pub fn start_output(&mut self) {
+        if self.blank_line && !self.out.is_empty() {
+            let prefix = self.prefix(false);
+            self.out.push_str(prefix.as_slice().trim_right());
+            self.out.push('\n');
+        }
+        self.blank_line = false;
+    }
+
+    // The prefix for the next line, using up the bullets.
+    fn take_prefix(&mut self) -> String {
+        let prefix = self.prefix(true);
+        for level in self.levels.iter_mut() {
+            level.bullet = None;
+        }
+        prefix
+    }
+
+    /// Write one line, with its prefix.
+    pub fn line(&mut self, text: &str) {
+        let prefix = self.take_prefix();
+        self.out.push_str(prefix.as_slice());
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Add a prefix, such as `"> "`, for the lines of a block.
+    pub fn push_prefix(&mut self, indent: &str) {
+        self.levels.push(Level {
+            indent: String::from_str(indent),
+            bullet: None,
+        });
+    }
+
+    pub fn pop_prefix(&mut self) {
+        self.levels.pop();
+    }
+
+    pub fn in_list(&self) -> bool {
+        !self.lists.is_empty()
+    }
+
+    /// Start a `<ul>`, `<ol>` or `<menu>`.
+    pub fn start_list(&mut self, name: &Atom, attrs: &[Attribute]) {
+        self.lists.push(match name.as_slice() {
+            "ol" => Some(attr(attrs, "start").and_then(|s| s.trim().parse().ok()).unwrap_or(1)),
+            _ => None,
+        });
+    }
+
+    pub fn end_list(&mut self) {
+        self.lists.pop();
+    }
+
+    /// Start a list item, numbered if it's in an `<ol>` and otherwise
+    /// with `bullet`.
+    pub fn start_item(&mut self, bullet: &str) {
+        let bullet = match self.lists.last_mut() {
+            Some(&mut Some(ref mut n)) => {
+                let number = format!("{}. ", *n);
+                *n += 1;
+                number
+            }
+            _ => String::from_str(bullet),
+        };
+        self.levels.push(Level {
+            indent: repeat(' ').take(bullet.as_slice().chars().count()).collect(),
+            bullet: Some(bullet),
+        });
+    }
+
+    /// End a list item, whose content must have been written.  An empty
+    /// item still gets its bullet.
+    pub fn end_item(&mut self) {
+        if self.levels.last().map_or(false, |l| l.bullet.is_some()) {
+            self.start_output();
+            let prefix = self.take_prefix();
+            self.out.push_str(prefix.as_slice().trim_right());
+            self.out.push('\n');
+        }
+        self.levels.pop();
+    }
+}
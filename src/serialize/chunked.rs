@@ -0,0 +1,211 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Passing output to a callback in bounded-size chunks.
+//!
+//! A `ChunkedWriter` can be given to `serialize` or to a `Rewriter`.
+//! It passes output to its callback in chunks of exactly `chunk_size`
+//! bytes, except for the last chunk, which is delivered by `flush()`.
+//! Chunk boundaries may fall inside a UTF-8 sequence.
+//!
+//! The callback returns `Pause` to say it can't take any more for now.
+//! Output is then buffered until `resume()` is called, and the producer
+//! should stop feeding input while `is_paused()` is true; this bounds
+//! the buffer by the output of one input chunk.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let mut rewriter = Rewriter::new(ChunkedWriter::new(4096, |chunk| {
+//!     if socket.try_send(chunk) { Proceed } else { Pause }
+//! }));
+//! loop {
+//!     while rewriter.output().is_paused() {
+//!         socket.wait_writable();
+//!         rewriter.output_mut().resume();
+//!     }
+//!     match upstream.next_chunk() {
+//!         Some(chunk) => try!(rewriter.write(chunk)),
+//!         None => break,
+//!     }
+//! }
+//! ```
+
+use core::prelude::*;
+
+use collections::vec::Vec;
+use std::old_io::{Writer, IoResult};
+
+pub use self::Flow::{Proceed, Pause};
+
+/// What the consumer wants after taking a chunk.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Flow {
+    /// Send more when it's ready.
+    Proceed,
+
+    /// Buffer output until `resume()` is called.
+    Pause,
+}
+
+/// A `Writer` which passes output to a callback in chunks.
+pub struct ChunkedWriter<F> {
+    callback: F,
+    chunk_size: uint,
+
+    /// Output, of which everything before `start` has been delivered.
+    buffer: Vec<u8>,
+    start: uint,
+
+    paused: bool,
+    flushing: bool,
+}
+
+impl<F: FnMut(&[u8]) -> Flow> ChunkedWriter<F> {
+    /// Create a writer delivering chunks of `chunk_size` bytes, which
+    /// must be positive.
+    pub fn new(chunk_size: uint, callback: F) -> ChunkedWriter<F> {
+        assert!(chunk_size > 0);
+        ChunkedWriter {
+            callback: callback,
+            chunk_size: chunk_size,
+            buffer: Vec::new(),
+            start: 0,
+            paused: false,
+            flushing: false,
+        }
+    }
+
+    /// Has the callback asked to pause?
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Number of bytes waiting to be delivered.
+    pub fn buffered(&self) -> uint {
+        self.buffer.len() - self.start
+    }
+
+    /// Deliver buffered output again, until the buffer is drained or the
+    /// callback pauses.  A partial chunk is only delivered if `flush()`
+    /// was called while paused.
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.deliver();
+    }
+
+    /// Take back the callback, dropping anything still buffered.
+    pub fn unwrap(self) -> F {
+        self.callback
+    }
+
+    fn deliver(&mut self) {
+        while !self.paused {
+            let len = self.buffered();
+            if len == 0 || (len < self.chunk_size && !self.flushing) {
+                break;
+            }
+            let end = self.start + if len < self.chunk_size { len } else { self.chunk_size };
+            if (self.callback)(&self.buffer[self.start..end]) == Pause {
+                self.paused = true;
+            }
+            self.start = end;
+        }
+
+        if self.start == self.buffer.len() {
+            self.flushing = false;
+            self.buffer.clear();
+            self.start = 0;
+        } else if self.start * 2 >= self.buffer.len() {
+            // Drop the delivered output only once it's at least half of
+            // the buffer, so that the copying takes linear time overall.
+            // FIXME: drain in place once Vec can
+            self.buffer = self.buffer[self.start..].to_vec();
+            self.start = 0;
+        }
+    }
+}
+
+impl<F: FnMut(&[u8]) -> Flow> Writer for ChunkedWriter<F> {
+    fn write_all(&mut self, buf: &[u8]) -> IoResult<()> {
+        self.buffer.push_all(buf);
+        self.deliver();
+        Ok(())
+    }
+
+    /// Deliver everything buffered, including a final partial chunk,
+    /// unless paused.  If paused, the rest is delivered by `resume()`.
+    fn flush(&mut self) -> IoResult<()> {
+        self.flushing = true;
+        self.deliver();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use std::old_io::Writer;
+
+    use super::{ChunkedWriter, Proceed, Pause};
+
+    #[test]
+    fn chunks() {
+        let mut chunks: Vec<Vec<u8>> = vec!();
+        {
+            let mut w = ChunkedWriter::new(4, |c: &[u8]| { chunks.push(c.to_vec()); Proceed });
+            w.write_str("abcdef").unwrap();
+            w.write_str("ghij").unwrap();
+            assert_eq!(w.buffered(), 2);
+            w.flush().unwrap();
+            assert_eq!(w.buffered(), 0);
+        }
+        assert_eq!(chunks, vec!(b"abcd".to_vec(), b"efgh".to_vec(), b"ij".to_vec()));
+    }
+
+    #[test]
+    fn backpressure() {
+        let mut chunks: Vec<Vec<u8>> = vec!();
+        {
+            let mut w = ChunkedWriter::new(2, |c: &[u8]| { chunks.push(c.to_vec()); Pause });
+            w.write_str("abcde").unwrap();
+            assert!(w.is_paused());
+            assert_eq!(w.buffered(), 3);
+            w.flush().unwrap();
+            assert_eq!(w.buffered(), 3);
+            w.resume();
+            w.resume();
+            assert_eq!(w.buffered(), 1);
+            w.resume();
+            assert_eq!(w.buffered(), 0);
+        }
+        assert_eq!(chunks, vec!(b"ab".to_vec(), b"cd".to_vec(), b"e".to_vec()));
+    }
+
+    #[test]
+    fn many_writes_while_paused() {
+        let mut out: Vec<u8> = vec!();
+        let mut expected: Vec<u8> = vec!();
+        {
+            let mut w = ChunkedWriter::new(3, |c: &[u8]| { out.push_all(c); Pause });
+            for i in 0..1000u {
+                let piece = format!("{},", i);
+                expected.push_all(piece.as_bytes());
+                w.write_str(piece.as_slice()).unwrap();
+                w.resume();
+            }
+            w.flush().unwrap();
+            while w.buffered() > 0 {
+                w.resume();
+            }
+        }
+        assert_eq!(out, expected);
+    }
+}
@@ -0,0 +1,444 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Converting a DOM to CommonMark.
+//!
+//! Headings, paragraphs, emphasis, code, links, images, lists, block
+//! quotes, rules and line breaks have direct equivalents.  Tables become
+//! GitHub-style pipe tables, with the first row as the header, and
+//! strikethrough uses `~~`.  Anything else is converted to its content,
+//! so some formatting is lost; raw HTML is never written.
+
+use core::prelude::*;
+
+use sink::common::{Document, DocumentFragment, Text, Element, attr};
+use sink::rcdom::Handle;
+use serialize::block::Blocks;
+use tokenizer::Attribute;
+
+use util::str::is_ascii_whitespace;
+
+use core::cmp::max;
+use core::mem::replace;
+use collections::vec::Vec;
+use collections::string::String;
+use std::iter::repeat;
+
+use string_cache::Atom;
+
+fn repeated(c: char, n: uint) -> String {
+    repeat(c).take(n).collect()
+}
+
+// Length of the longest run of `c` in `s`.
+fn longest_run(s: &str, c: char) -> uint {
+    let mut longest = 0;
+    let mut run = 0;
+    for d in s.chars() {
+        run = if d == c { run + 1 } else { 0 };
+        longest = max(longest, run);
+    }
+    longest
+}
+
+fn text_content(handle: &Handle, out: &mut String) {
+    let node = handle.borrow();
+    match node.node {
+        Text(ref text) => out.push_str(text.as_slice()),
+        _ => for child in node.children.iter() {
+            text_content(child, out);
+        },
+    }
+}
+
+// Escape characters which could start a block construct at the
+// beginning of a line, such as `# ` or `1. `.
+fn escape_line_start(line: &str) -> String {
+    let digits = line.chars().take_while(|c| c.is_digit(10)).count();
+    let mut out = String::new();
+    match line.chars().next() {
+        Some('#') | Some('>') | Some('-') | Some('+') | Some('=') => out.push('\\'),
+        _ if digits > 0 => match line.chars().nth(digits) {
+            Some('.') | Some(')') => {
+                out.push_str(&line[..digits]);
+                out.push('\\');
+                out.push_str(&line[digits..]);
+                return out;
+            }
+            _ => (),
+        },
+        _ => (),
+    }
+    out.push_str(line);
+    out
+}
+
+fn is_block(name: &Atom) -> bool {
+    match name.as_slice() {
+        "address" | "article" | "aside" | "blockquote" | "body" | "center"
+            | "dd" | "details" | "div" | "dl" | "dt" | "fieldset" | "figcaption"
+            | "figure" | "footer" | "form" | "h1" | "h2" | "h3" | "h4" | "h5"
+            | "h6" | "header" | "hr" | "html" | "main" | "menu" | "nav"
+            | "ol" | "p" | "pre" | "section" | "summary" | "table" | "ul" => true,
+        _ => false,
+    }
+}
+
+struct Converter {
+    /// Finished output, and the prefixes of list items and block quotes.
+    blocks: Blocks,
+
+    /// Inline content of the current block.
+    line: String,
+
+    /// Whitespace seen since the last text in `line`.
+    pending_space: bool,
+}
+
+impl Converter {
+    fn new() -> Converter {
+        Converter {
+            blocks: Blocks::new(),
+            line: String::new(),
+            pending_space: false,
+        }
+    }
+
+    // Write lines of block content, with prefixes.
+    fn emit(&mut self, text: &str, escape: bool) {
+        self.blocks.start_output();
+        for line in text.split('\n') {
+            if escape {
+                self.blocks.line(escape_line_start(line).as_slice());
+            } else {
+                self.blocks.line(line);
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        let line = replace(&mut self.line, String::new());
+        self.pending_space = false;
+        let trimmed = line.as_slice().trim_right();
+        if !trimmed.is_empty() {
+            self.emit(trimmed, true);
+        }
+    }
+
+    fn block_boundary(&mut self) {
+        self.flush();
+        self.blocks.blank_line = true;
+    }
+
+    fn push_str(&mut self, s: &str) {
+        if self.pending_space && !self.line.is_empty() && !self.line.ends_with("\n") {
+            self.line.push(' ');
+        }
+        self.pending_space = false;
+        self.line.push_str(s);
+    }
+
+    fn text(&mut self, text: &str) {
+        for c in text.chars() {
+            if is_ascii_whitespace(c) {
+                self.pending_space = true;
+                continue;
+            }
+            if self.pending_space && !self.line.is_empty() && !self.line.ends_with("\n") {
+                self.line.push(' ');
+            }
+            self.pending_space = false;
+            match c {
+                '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '&' => self.line.push('\\'),
+                _ => (),
+            }
+            self.line.push(c);
+        }
+    }
+
+    fn children(&mut self, handle: &Handle) {
+        for child in handle.borrow().children.iter() {
+            self.node(child);
+        }
+    }
+
+    // Convert the children of a node to a string of inline content.
+    fn inline_content(handle: &Handle) -> String {
+        let mut converter = Converter::new();
+        converter.children(handle);
+        let line = replace(&mut converter.line, String::new());
+        let mut out = converter.blocks.out;
+        out.push_str(line.as_slice());
+        out.as_slice().trim().replace("\n", " ")
+    }
+
+    fn wrap_inline(&mut self, handle: &Handle, delim: &str) {
+        let content = Converter::inline_content(handle);
+        if !content.is_empty() {
+            let wrapped = format!("{}{}{}", delim, content, delim);
+            self.push_str(wrapped.as_slice());
+        }
+    }
+
+    fn code_span(&mut self, handle: &Handle) {
+        let mut code = String::new();
+        text_content(handle, &mut code);
+        let mut collapsed = String::new();
+        for word in code.as_slice().split(is_ascii_whitespace).filter(|w| !w.is_empty()) {
+            if !collapsed.is_empty() {
+                collapsed.push(' ');
+            }
+            collapsed.push_str(word);
+        }
+        let code = collapsed;
+        if code.is_empty() {
+            return;
+        }
+        let fence = repeated('`', longest_run(code.as_slice(), '`') + 1);
+        let pad = if code.starts_with("`") || code.ends_with("`") { " " } else { "" };
+        let span = format!("{}{}{}{}{}", fence, pad, code, pad, fence);
+        self.push_str(span.as_slice());
+    }
+
+    fn code_block(&mut self, handle: &Handle, attrs: &[Attribute]) {
+        let mut code = String::new();
+        text_content(handle, &mut code);
+
+        // The language, from `class="language-x"` on the `<pre>`
+        // or a `<code>` inside it.
+        let mut classes = attr(attrs, "class").map(String::from_str).unwrap_or(String::new());
+        for child in handle.borrow().children.iter() {
+            match child.borrow().node {
+                Element(ref name, ref attrs) if name.local == atom!(code) => {
+                    classes.push(' ');
+                    classes.push_str(attr(attrs.as_slice(), "class").unwrap_or(""));
+                }
+                _ => (),
+            }
+        }
+        let lang = classes.as_slice().split(is_ascii_whitespace)
+            .filter(|c| c.starts_with("language-"))
+            .map(|c| &c["language-".len()..])
+            .next().unwrap_or("");
+
+        let fence = repeated('`', max(3, longest_run(code.as_slice(), '`') + 1));
+        let code = code.as_slice().trim_right_matches('\n');
+        let block = format!("{}{}\n{}\n{}", fence, lang, code, fence);
+        self.emit(block.as_slice(), false);
+    }
+
+    fn table(&mut self, handle: &Handle) {
+        fn rows(handle: &Handle, out: &mut Vec<Vec<String>>) {
+            for child in handle.borrow().children.iter() {
+                match child.borrow().node {
+                    Element(ref name, _) => match name.local.as_slice() {
+                        "tr" => {
+                            let cells = child.borrow().children.iter().filter(|c| {
+                                match c.borrow().node {
+                                    Element(ref name, _) =>
+                                        name.local == atom!(td) || name.local == atom!(th),
+                                    _ => false,
+                                }
+                            }).map(|c| Converter::inline_content(c).replace("|", "\\|")).collect();
+                            out.push(cells);
+                        }
+                        "thead" | "tbody" | "tfoot" => rows(child, out),
+                        _ => (),
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        let mut table = vec!();
+        rows(handle, &mut table);
+        let columns = table.iter().map(|r| r.len()).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+
+        let mut lines = String::new();
+        for (i, row) in table.iter().enumerate() {
+            if i > 0 {
+                lines.push('\n');
+            }
+            let mut line = String::from_str("|");
+            for c in range(0, columns) {
+                line.push(' ');
+                line.push_str(row.get(c).map(|s| s.as_slice()).unwrap_or(""));
+                line.push_str(" |");
+            }
+            lines.push_str(line.as_slice());
+            if i == 0 {
+                lines.push_str("\n|");
+                for _ in range(0, columns) {
+                    lines.push_str(" --- |");
+                }
+            }
+        }
+        self.emit(lines.as_slice(), false);
+    }
+
+    fn list_item(&mut self, handle: &Handle) {
+        self.blocks.start_item("- ");
+        self.children(handle);
+        self.flush();
+        self.blocks.end_item();
+        // Items are tight, unless they contain paragraphs.
+        self.blocks.blank_line = false;
+    }
+
+    fn element(&mut self, handle: &Handle, name: &Atom, attrs: &[Attribute]) {
+        let block = is_block(name);
+        if block {
+            self.block_boundary();
+        }
+
+        match name.as_slice() {
+            "head" | "script" | "style" | "template" | "title" | "noscript" => (),
+
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = (name.as_slice().as_bytes()[1] - b'0') as uint;
+                let content = Converter::inline_content(handle);
+                if !content.is_empty() {
+                    let heading = format!("{} {}", repeated('#', level), content);
+                    self.emit(heading.as_slice(), false);
+                }
+            }
+
+            "pre" => self.code_block(handle, attrs),
+            "table" => self.table(handle),
+            "hr" => self.emit("---", false),
+
+            "blockquote" => {
+                self.blocks.push_prefix("> ");
+                self.children(handle);
+                self.flush();
+                self.blocks.pop_prefix();
+            }
+
+            "ul" | "ol" | "menu" => {
+                // A nested list is part of a tight list item.
+                if self.blocks.in_list() {
+                    self.blocks.blank_line = false;
+                }
+                self.blocks.start_list(name, attrs);
+                self.children(handle);
+                self.flush();
+                self.blocks.end_list();
+            }
+
+            "li" => {
+                self.flush();
+                self.list_item(handle);
+            }
+
+            "br" => {
+                if !self.line.is_empty() {
+                    self.line.push_str("\\\n");
+                    self.pending_space = false;
+                }
+            }
+
+            "em" | "i" | "cite" | "dfn" | "var" => self.wrap_inline(handle, "*"),
+            "strong" | "b" => self.wrap_inline(handle, "**"),
+            "del" | "s" | "strike" => self.wrap_inline(handle, "~~"),
+            "code" | "kbd" | "samp" | "tt" => self.code_span(handle),
+
+            "a" => {
+                let content = Converter::inline_content(handle);
+                let link = match attr(attrs, "href") {
+                    Some(href) => {
+                        let href = href.replace(" ", "%20").replace(")", "%29");
+                        match attr(attrs, "title") {
+                            Some(title) => format!("[{}]({} \"{}\")", content, href,
+                                title.replace("\"", "\\\"")),
+                            None => format!("[{}]({})", content, href),
+                        }
+                    }
+                    None => content,
+                };
+                self.push_str(link.as_slice());
+            }
+
+            "img" => match attr(attrs, "src") {
+                Some(src) => {
+                    let alt = attr(attrs, "alt").unwrap_or("").replace("]", "\\]");
+                    let image = format!("![{}]({})", alt,
+                        src.replace(" ", "%20").replace(")", "%29"));
+                    self.push_str(image.as_slice());
+                }
+                None => (),
+            },
+
+            _ => self.children(handle),
+        }
+
+        if block {
+            self.block_boundary();
+        }
+    }
+
+    fn node(&mut self, handle: &Handle) {
+        let node = handle.borrow();
+        match node.node {
+            Document | DocumentFragment => self.children(handle),
+            Text(ref text) => self.text(text.as_slice()),
+            Element(ref name, ref attrs) if name.ns == ns!(HTML) =>
+                self.element(handle, &name.local, attrs.as_slice()),
+            _ => (),
+        }
+    }
+}
+
+/// Convert a node and its descendants to CommonMark.
+pub fn to_markdown(node: &Handle) -> String {
+    let mut converter = Converter::new();
+    converter.node(node);
+    converter.flush();
+    converter.blocks.out
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::string::String;
+
+    use super::to_markdown;
+    use sink::rcdom::RcDom;
+    use driver::parse_str;
+
+    fn md(input: &str) -> String {
+        let dom: RcDom = parse_str(input);
+        to_markdown(&dom.document)
+    }
+
+    test_eq!(inline, md("<h2>Intro</h2><p>Some <em>emphasis</em>, <b>bold</b> \
+        and <code>a`b</code>.<br>Next *line*"),
+        "## Intro\n\nSome *emphasis*, **bold** and ``a`b``.\\\nNext \\*line\\*\n");
+
+    test_eq!(links, md("<p><a href='/x y' title=T>here</a> <img src=a.png alt=pic>"),
+        "[here](/x%20y \"T\") ![pic](a.png)\n");
+
+    test_eq!(lists, md("<ul><li>a<li>b<ol><li>c</ol></ul><p>1. not a list"),
+        "- a\n- b\n  1. c\n\n1\\. not a list\n");
+
+    test_eq!(code_block, md("<pre><code class='language-rust'>fn main() {}\n</code></pre>"),
+        "```rust\nfn main() {}\n```\n");
+
+    test_eq!(quote, md("<blockquote><p>a</p><p>b</p></blockquote>"), "> a\n>\n> b\n");
+
+    test_eq!(nested_lists, md("<ul><li><ul><li>a<li>b</ul><li><ol><li></ol></ul>"),
+        "- - a\n  - b\n- 1.\n");
+
+    test_eq!(escapes, md("<p>a &amp;amp; b &lt;c&gt;"), "a \\&amp; b \\<c>\n");
+
+    test_eq!(table, md("<table><tr><th>A<th>B<tr><td>1<td>x|y</table>"),
+        "| A | B |\n| --- | --- |\n| 1 | x\\|y |\n");
+}
@@ -12,35 +12,187 @@ use core::prelude::*;
 use std::old_io::{Writer, IoResult};
 use core::default::Default;
 use collections::vec::Vec;
+use collections::string::String;
+
+use sink::common::is_void;
+
+use util::str::is_ascii_whitespace;
 
 use string_cache::{Atom, QualName};
 
+use self::EscapeMode::{Text, Attribute};
+
+pub use self::TraversalScope::{IncludeNode, ChildrenOnly};
+pub use self::EscapePolicy::{Minimal, AsciiOnly, NamedEntities};
+pub use self::QuoteStyle::{AlwaysDouble, AlwaysSingle, PreferUnquoted};
+pub use self::AttrWhitespace::{PreserveWhitespace, EscapeWhitespace, NormalizeWhitespace};
+
+mod block;
+mod entities;
+pub mod chunked;
+pub mod markdown;
+pub mod text;
+
 //§ serializing-html-fragments
 pub trait Serializable {
-    fn serialize<'wr, Wr: Writer>(&self, serializer: &mut Serializer<'wr, Wr>, incl_self: bool) -> IoResult<()>;
+    fn serialize<'wr, Wr: Writer>(&self, serializer: &mut Serializer<'wr, Wr>,
+                                  traversal_scope: TraversalScope) -> IoResult<()>;
 }
 
 pub fn serialize<Wr: Writer, T: Serializable>
     (writer: &mut Wr, node: &T, opts: SerializeOpts) -> IoResult<()> {
 
     let mut ser = Serializer::new(writer, opts);
-    node.serialize(&mut ser, false)
+    try!(node.serialize(&mut ser, opts.traversal_scope));
+    try!(ser.finish());
+    ser.flush_buf()
+}
+
+/// Serialize into a new `String`.
+pub fn serialize_to_string<T: Serializable>(node: &T, opts: SerializeOpts) -> String {
+    let mut buf = vec!();
+    serialize(&mut buf, node, opts).ok().expect("writing to a Vec failed");
+    String::from_utf8(buf).ok().expect("serializer produced invalid UTF-8")
+}
+
+/// Which nodes are serialized, relative to the node passed to `serialize`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TraversalScope {
+    /// Serialize the node itself and its descendants, like `outerHTML`.
+    IncludeNode,
+
+    /// Serialize only the node's descendants, like `innerHTML`.
+    ChildrenOnly,
+}
+
+/// How characters in text and attribute values are escaped.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum EscapePolicy {
+    /// Escape only what the spec's serialization algorithm requires.
+    Minimal,
+
+    /// Additionally escape every non-ASCII character in text and
+    /// attribute values as a numeric character reference.
+    ///
+    /// The output is pure ASCII only if the names, comments and doctype
+    /// are, and there's no non-ASCII text in raw text elements such as
+    /// `<script>`: character references aren't recognized there, so
+    /// those are written as they are.
+    AsciiOnly,
+
+    /// Like `AsciiOnly`, but use a named character reference where
+    /// one exists in HTML 4.
+    NamedEntities,
+}
+
+/// How attribute values are quoted.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum QuoteStyle {
+    /// `name="value"`, escaping `"` as `&quot;`.
+    AlwaysDouble,
+
+    /// `name='value'`, escaping `'` as `&#39;`.
+    AlwaysSingle,
+
+    /// `name=value` when the value is non-empty and contains nothing that
+    /// would end an unquoted value; otherwise as `AlwaysDouble`.
+    PreferUnquoted,
+}
+
+/// How tabs, newlines and carriage returns in attribute values are written.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AttrWhitespace {
+    /// As-is.  A parser will turn a carriage return into a newline.
+    PreserveWhitespace,
+
+    /// As numeric character references, e.g. `&#10;`, so the value
+    /// survives being parsed again exactly, and the output has no
+    /// line breaks inside tags.
+    EscapeWhitespace,
+
+    /// As a space each, counting `\r\n` as one, as XML attribute value
+    /// normalization would.  This changes the value.
+    NormalizeWhitespace,
 }
 
 #[derive(Copy)]
 pub struct SerializeOpts {
     /// Is scripting enabled?
     pub scripting_enabled: bool,
+
+    /// Serialize the root node itself, or only its children?
+    /// Default: ChildrenOnly
+    pub traversal_scope: TraversalScope,
+
+    /// Which characters to escape, and how.  Default: Minimal
+    pub escape_policy: EscapePolicy,
+
+    /// How to quote attribute values.  Default: AlwaysDouble
+    pub quote_style: QuoteStyle,
+
+    /// When a top-level element can't appear without certain ancestors
+    /// (e.g. `<td>` outside `<tr>`), write start and end tags for those
+    /// ancestors around it?  Otherwise the element is written as-is, and
+    /// a parser will drop its tags.  Default: false
+    pub create_missing_parent: bool,
+
+    /// How to write tabs and line breaks in attribute values.
+    /// Default: PreserveWhitespace
+    pub attr_whitespace: AttrWhitespace,
 }
 
 impl Default for SerializeOpts {
     fn default() -> SerializeOpts {
         SerializeOpts {
             scripting_enabled: true,
+            traversal_scope: ChildrenOnly,
+            escape_policy: Minimal,
+            quote_style: AlwaysDouble,
+            create_missing_parent: false,
+            attr_whitespace: PreserveWhitespace,
         }
     }
 }
 
+#[derive(Copy, PartialEq)]
+enum EscapeMode {
+    Text,
+
+    /// An attribute value delimited by this quote character, or unquoted.
+    Attribute(Option<char>),
+}
+
+/// Can this attribute value be written without quotes?
+fn can_be_unquoted(value: &str) -> bool {
+    !value.is_empty() && !value.chars().any(|c| match c {
+        '\t' | '\n' | '\x0C' | '\r' | ' '
+        | '"' | '\'' | '=' | '<' | '>' | '`' => true,
+        _ => false,
+    })
+}
+
+/// Size of the serializer's output buffer, in bytes.
+const BUF_SIZE: uint = 4096;
+
+/// The ancestors, outermost first, without which an element would not
+/// survive being parsed in body content.
+fn required_parents(name: &Atom) -> &'static [&'static str] {
+    static CELL: &'static [&'static str] = &["table", "tbody", "tr"];
+    static ROW: &'static [&'static str] = &["table", "tbody"];
+    static TABLE: &'static [&'static str] = &["table"];
+    static COL: &'static [&'static str] = &["table", "colgroup"];
+    static NONE: &'static [&'static str] = &[];
+
+    match *name {
+        atom!(td) | atom!(th) => CELL,
+        atom!(tr) => ROW,
+        atom!(tbody) | atom!(thead) | atom!(tfoot)
+        | atom!(caption) | atom!(colgroup) => TABLE,
+        atom!(col) => COL,
+        _ => NONE,
+    }
+}
+
 struct ElemInfo {
     html_name: Option<Atom>,
     ignore_children: bool,
@@ -53,6 +205,14 @@ pub struct Serializer<'wr, Wr:'wr> {
     writer: &'wr mut Wr,
     opts: SerializeOpts,
     stack: Vec<ElemInfo>,
+
+    /// Output not yet passed to `writer`.  Most writes are a few bytes,
+    /// and a `Writer` call per write dominates the cost of serializing.
+    buf: Vec<u8>,
+
+    /// Ancestors we have written start tags for, because of
+    /// `create_missing_parent`, and not yet closed.
+    synthesized: &'static [&'static str],
 }
 
 impl<'wr, Wr: Writer> Serializer<'wr, Wr> {
@@ -65,27 +225,127 @@ impl<'wr, Wr: Writer> Serializer<'wr, Wr> {
                 ignore_children: false,
                 processed_first_child: false,
             }),
+            buf: Vec::with_capacity(BUF_SIZE),
+            synthesized: &[],
         }
     }
 
+    fn flush_buf(&mut self) -> IoResult<()> {
+        try!(self.writer.write_all(self.buf.as_slice()));
+        self.buf.truncate(0);
+        Ok(())
+    }
+
+    #[inline]
+    fn maybe_flush(&mut self) -> IoResult<()> {
+        if self.buf.len() >= BUF_SIZE {
+            self.flush_buf()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn write_str(&mut self, s: &str) -> IoResult<()> {
+        self.buf.push_all(s.as_bytes());
+        self.maybe_flush()
+    }
+
+    fn write_char(&mut self, c: char) -> IoResult<()> {
+        if (c as u32) < 0x80 {
+            self.buf.push(c as u8);
+        } else {
+            let mut utf8 = [0u8; 4];
+            let n = c.encode_utf8(&mut utf8).expect("no room for char");
+            self.buf.push_all(&utf8[..n]);
+        }
+        self.maybe_flush()
+    }
+
+    /// Write several pieces with a single check of the buffer.
+    fn write_pieces(&mut self, pieces: &[&str]) -> IoResult<()> {
+        for piece in pieces.iter() {
+            self.buf.push_all(piece.as_bytes());
+        }
+        self.maybe_flush()
+    }
+
+    /// Write end tags for any synthesized ancestors.
+    fn finish(&mut self) -> IoResult<()> {
+        let open = self.synthesized;
+        for name in open.iter().rev() {
+            try!(self.write_pieces(&["</", *name, ">"]));
+        }
+        self.synthesized = &[];
+        Ok(())
+    }
+
+    /// Make the synthesized ancestors match those required by a
+    /// top-level element named `name`.
+    fn synthesize_parents(&mut self, name: &Atom) -> IoResult<()> {
+        let required = required_parents(name);
+        if required == self.synthesized {
+            return Ok(());
+        }
+
+        try!(self.finish());
+        for parent in required.iter() {
+            try!(self.write_pieces(&["<", *parent, ">"]));
+        }
+        self.synthesized = required;
+        Ok(())
+    }
+
+    fn at_top_level(&self) -> bool {
+        self.stack.len() == 1
+    }
+
     fn parent<'a>(&'a mut self) -> &'a mut ElemInfo {
         self.stack.last_mut().expect("no parent ElemInfo")
     }
 
-    fn write_escaped(&mut self, text: &str, attr_mode: bool) -> IoResult<()> {
+    fn write_escaped(&mut self, text: &str, mode: EscapeMode) -> IoResult<()> {
+        let whitespace = match mode {
+            Attribute(_) => self.opts.attr_whitespace,
+            Text => PreserveWhitespace,
+        };
+        let mut last = '\0';
         for c in text.chars() {
             try!(match c {
-                '&' => self.writer.write_str("&amp;"),
-                '\u{00A0}' => self.writer.write_str("&nbsp;"),
-                '"' if attr_mode => self.writer.write_str("&quot;"),
-                '<' if !attr_mode => self.writer.write_str("&lt;"),
-                '>' if !attr_mode => self.writer.write_str("&gt;"),
-                c => self.writer.write_char(c),
+                '&' => self.write_str("&amp;"),
+                '\u{00A0}' => self.write_str("&nbsp;"),
+                '"' if mode == Attribute(Some('"')) => self.write_str("&quot;"),
+                '\'' if mode == Attribute(Some('\'')) => self.write_str("&#39;"),
+                '<' if mode == Text => self.write_str("&lt;"),
+                '>' if mode == Text => self.write_str("&gt;"),
+                '\t' if whitespace == EscapeWhitespace => self.write_str("&#9;"),
+                '\n' if whitespace == EscapeWhitespace => self.write_str("&#10;"),
+                '\r' if whitespace == EscapeWhitespace => self.write_str("&#13;"),
+                '\n' if whitespace == NormalizeWhitespace && last == '\r' => Ok(()),
+                '\t' | '\n' | '\r' if whitespace == NormalizeWhitespace => self.write_char(' '),
+                c if (c as u32) < 0x80 => self.write_char(c),
+                c => self.write_non_ascii(c),
             });
+            last = c;
         }
         Ok(())
     }
 
+    fn write_non_ascii(&mut self, c: char) -> IoResult<()> {
+        let name = match self.opts.escape_policy {
+            Minimal => return self.write_char(c),
+            AsciiOnly => None,
+            NamedEntities => entities::entity_name(c),
+        };
+
+        match name {
+            Some(name) => self.write_pieces(&["&", name, ";"]),
+            None => {
+                try!(write!(&mut self.buf, "&#x{:X};", c as u32));
+                self.maybe_flush()
+            }
+        }
+    }
+
     pub fn start_elem<'a, AttrIter: Iterator<Item=AttrRef<'a>>>(
         &mut self,
         name: QualName,
@@ -93,7 +353,7 @@ impl<'wr, Wr: Writer> Serializer<'wr, Wr> {
 
         let html_name = match name.ns {
             ns!(HTML) => Some(name.local.clone()),
-            _ => panic!("FIXME: Handle qualified tag names"),
+            _ => None,
         };
 
         if self.parent().ignore_children {
@@ -105,27 +365,40 @@ impl<'wr, Wr: Writer> Serializer<'wr, Wr> {
             return Ok(());
         }
 
-        try!(self.writer.write_char('<'));
-        try!(self.writer.write_str(name.local.as_slice()));
+        if self.opts.create_missing_parent && self.at_top_level() {
+            try!(self.synthesize_parents(&name.local));
+        }
+
+        // FIXME: elements outside the HTML, SVG and MathML namespaces
+        // should be written with their prefix, which we don't keep.
+        try!(self.write_pieces(&["<", name.local.as_slice()]));
         for (name, value) in attrs {
-            try!(self.writer.write_char(' '));
-            // FIXME: qualified names
-            assert!(name.ns == ns!(""));
-            try!(self.writer.write_str(name.local.as_slice()));
-            try!(self.writer.write_str("=\""));
-            try!(self.write_escaped(value, true));
-            try!(self.writer.write_char('"'));
-        }
-        try!(self.writer.write_char('>'));
-
-        let ignore_children = name.ns == ns!(HTML) && match name.local {
-            atom!(area) | atom!(base) | atom!(basefont) | atom!(bgsound) | atom!(br)
-            | atom!(col) | atom!(embed) | atom!(frame) | atom!(hr) | atom!(img)
-            | atom!(input) | atom!(keygen) | atom!(link) | atom!(menuitem)
-            | atom!(meta) | atom!(param) | atom!(source) | atom!(track) | atom!(wbr)
-                => true,
-            _ => false,
-        };
+            let prefix = match name.ns {
+                ns!(XML) => "xml:",
+                ns!(XMLNS) if name.local == atom!(xmlns) => "",
+                ns!(XMLNS) => "xmlns:",
+                ns!(XLink) => "xlink:",
+                _ => "",
+            };
+            try!(self.write_pieces(&[" ", prefix, name.local.as_slice(), "="]));
+
+            let quote = match self.opts.quote_style {
+                AlwaysSingle => Some('\''),
+                PreferUnquoted if can_be_unquoted(value) => None,
+                _ => Some('"'),
+            };
+            match quote {
+                Some(q) => {
+                    try!(self.write_char(q));
+                    try!(self.write_escaped(value, Attribute(quote)));
+                    try!(self.write_char(q));
+                }
+                None => try!(self.write_escaped(value, Attribute(None))),
+            }
+        }
+        try!(self.write_char('>'));
+
+        let ignore_children = name.ns == ns!(HTML) && is_void(&name.local);
 
         self.parent().processed_first_child = true;
 
@@ -144,13 +417,16 @@ impl<'wr, Wr: Writer> Serializer<'wr, Wr> {
             return Ok(());
         }
 
-        // FIXME: Handle qualified tag names
-        try!(self.writer.write_str("</"));
-        try!(self.writer.write_str(name.local.as_slice()));
-        self.writer.write_char('>')
+        self.write_pieces(&["</", name.local.as_slice(), ">"])
     }
 
     pub fn write_text(&mut self, text: &str) -> IoResult<()> {
+        // Whitespace can stay inside synthesized table ancestors;
+        // anything else would be foster-parented out of them.
+        if self.at_top_level() && !text.chars().all(is_ascii_whitespace) {
+            try!(self.finish());
+        }
+
         let prepend_lf = text.starts_with("\n") && {
             let parent = self.parent();
             !parent.processed_first_child && match parent.html_name {
@@ -160,7 +436,7 @@ impl<'wr, Wr: Writer> Serializer<'wr, Wr> {
         };
 
         if prepend_lf {
-            try!(self.writer.write_char('\n'));
+            try!(self.write_char('\n'));
         }
 
         let escape = match self.parent().html_name {
@@ -174,21 +450,191 @@ impl<'wr, Wr: Writer> Serializer<'wr, Wr> {
         };
 
         if escape {
-            self.write_escaped(text, false)
+            self.write_escaped(text, Text)
         } else {
-            self.writer.write_str(text)
+            self.write_str(text)
         }
     }
 
     pub fn write_comment(&mut self, text: &str) -> IoResult<()> {
-        try!(self.writer.write_str("<!--"));
-        try!(self.writer.write_str(text));
-        self.writer.write_str("-->")
+        self.write_pieces(&["<!--", text, "-->"])
     }
 
     pub fn write_doctype(&mut self, name: &str) -> IoResult<()> {
-        try!(self.writer.write_str("<!DOCTYPE "));
-        try!(self.writer.write_str(name));
-        self.writer.write_char('\n')
+        try!(self.finish());
+        try!(self.write_str("<!DOCTYPE "));
+        try!(self.write_str(name));
+        self.write_char('\n')
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::vec::Vec;
+    use collections::string::String;
+    use std::old_io::{Writer, IoResult};
+
+    use super::{can_be_unquoted, serialize, serialize_to_string, SerializeOpts, AttrWhitespace};
+    use super::{PreserveWhitespace, EscapeWhitespace, NormalizeWhitespace};
+    use super::{EscapePolicy, Minimal, AsciiOnly, NamedEntities};
+    use super::{TraversalScope, IncludeNode, ChildrenOnly, BUF_SIZE};
+    use sink::rcdom::{RcDom, Handle};
+    use driver::parse_str;
+
+    fn reserialize(input: &str) -> String {
+        let dom: RcDom = parse_str(input);
+        serialize_to_string(&dom.document, Default::default())
+    }
+
+    fn reserialize_attrs(input: &str, attr_whitespace: AttrWhitespace) -> String {
+        let dom: RcDom = parse_str(input);
+        serialize_to_string(&dom.document, SerializeOpts {
+            attr_whitespace: attr_whitespace,
+            .. Default::default()
+        })
+    }
+
+    fn reserialize_escaped(input: &str, escape_policy: EscapePolicy) -> String {
+        let dom: RcDom = parse_str(input);
+        serialize_to_string(&dom.document, SerializeOpts {
+            escape_policy: escape_policy,
+            .. Default::default()
+        })
+    }
+
+    test_eq!(unquoted_plain, can_be_unquoted("foo-bar_1.png"), true);
+    test_eq!(unquoted_empty, can_be_unquoted(""), false);
+    test_eq!(unquoted_space, can_be_unquoted("a b"), false);
+    test_eq!(unquoted_quote, can_be_unquoted("a'b"), false);
+    test_eq!(unquoted_equals, can_be_unquoted("a=b"), false);
+    test_eq!(unquoted_backtick, can_be_unquoted("`a"), false);
+
+    test_eq!(foreign_attrs,
+        reserialize("<svg xlink:href=a xml:lang=en xmlns=s xmlns:xlink=l><circle xlink:href=b /></svg>"),
+        "<html><head></head><body><svg xlink:href=\"a\" xml:lang=\"en\" xmlns=\"s\" \
+        xmlns:xlink=\"l\"><circle xlink:href=\"b\"></circle></svg></body></html>");
+
+    test_eq!(foreign_duplicate_attrs,
+        reserialize("<svg xlink:href=a XLINK:href=b viewbox=c viewBox=d>"),
+        "<html><head></head><body><svg xlink:href=\"a\" viewBox=\"c\"></svg></body></html>");
+
+    test_eq!(foreign_case,
+        reserialize("<svg viewbox='0 0 1 1'><foreignobject><p>x</p></foreignobject>\
+            <lineargradient/></svg><math definitionurl=u>"),
+        "<html><head></head><body><svg viewBox=\"0 0 1 1\"><foreignObject><p>x</p>\
+        </foreignObject><linearGradient></linearGradient></svg><math definitionURL=\"u\">\
+        </math></body></html>");
+
+    test_eq!(annotation_xml,
+        reserialize("<math><annotation-xml encoding=Text/HTML><p>x</p></annotation-xml>\
+            <annotation-xml><p>y</p></annotation-xml></math>"),
+        "<html><head></head><body><math><annotation-xml encoding=\"Text/HTML\"><p>x</p>\
+        </annotation-xml><annotation-xml></annotation-xml></math><p>y</p></body></html>");
+
+    test_eq!(cdata,
+        reserialize("<svg><![CDATA[a<b]]></svg><![CDATA[c]]>"),
+        "<html><head></head><body><svg>a&lt;b</svg><!--[CDATA[c]]--></body></html>");
+
+    #[test]
+    fn traversal_scope() {
+        let dom: RcDom = parse_str("<p id=a>x<b>y</b></p>");
+        let p = dom.get_element_by_id("a").unwrap();
+        let text = p.borrow().children[0].clone();
+        let scoped = |node: &Handle, scope: TraversalScope| {
+            serialize_to_string(node, SerializeOpts {
+                traversal_scope: scope,
+                .. Default::default()
+            })
+        };
+
+        assert_eq!(scoped(&p, IncludeNode), "<p id=\"a\">x<b>y</b></p>");
+        assert_eq!(scoped(&p, ChildrenOnly), "x<b>y</b>");
+        assert_eq!(scoped(&text, IncludeNode), "x");
+        assert_eq!(scoped(&text, ChildrenOnly), "");
+
+        // The document has no markup of its own.
+        assert_eq!(scoped(&dom.document, IncludeNode), scoped(&dom.document, ChildrenOnly));
+    }
+
+    #[test]
+    fn create_missing_parent() {
+        let dom: RcDom = parse_str("<table><tr id=r><td id=a>1</td><td>2</td></tr></table>");
+        let opts = |scope: TraversalScope, create: bool| SerializeOpts {
+            traversal_scope: scope,
+            create_missing_parent: create,
+            .. Default::default()
+        };
+        let td = dom.get_element_by_id("a").unwrap();
+        let tr = dom.get_element_by_id("r").unwrap();
+
+        assert_eq!(serialize_to_string(&td, opts(IncludeNode, false)), "<td id=\"a\">1</td>");
+        assert_eq!(serialize_to_string(&td, opts(IncludeNode, true)),
+            "<table><tbody><tr><td id=\"a\">1</td></tr></tbody></table>");
+        assert_eq!(serialize_to_string(&tr, opts(ChildrenOnly, true)),
+            "<table><tbody><tr><td id=\"a\">1</td><td>2</td></tr></tbody></table>");
+    }
+
+    // Keeps what is written to it, counting the calls.
+    struct CountWrites(Vec<u8>, uint);
+
+    impl Writer for CountWrites {
+        fn write_all(&mut self, buf: &[u8]) -> IoResult<()> {
+            self.0.push_all(buf);
+            self.1 += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn buffered_output() {
+        let mut input = String::new();
+        let mut expected = String::from_str("<html><head></head><body>");
+        for i in 0..1000u {
+            input.push_str(format!("<p class=c{}>{}&amp;</p>", i, i).as_slice());
+            expected.push_str(format!("<p class=\"c{}\">{}&amp;</p>", i, i).as_slice());
+        }
+        expected.push_str("</body></html>");
+        let dom: RcDom = parse_str(input.as_slice());
+
+        let mut out = CountWrites(vec!(), 0);
+        serialize(&mut out, &dom.document, Default::default()).unwrap();
+        assert_eq!(out.0.as_slice(), expected.as_bytes());
+        assert!(expected.len() > 4 * BUF_SIZE);
+        assert!(out.1 <= expected.len() / BUF_SIZE + 1);
+    }
+
+    #[test]
+    fn escape_policy() {
+        let input = "<p title='\u{e9}&amp;'>\u{e9}\u{1f600}&nbsp;<!--\u{e9}-->\
+            <script>\u{e9}</script></p>";
+        let wrap = |p: &str| format!("<html><head></head><body>{}</body></html>", p);
+
+        assert_eq!(reserialize_escaped(input, Minimal),
+            wrap("<p title=\"\u{e9}&amp;\">\u{e9}\u{1f600}&nbsp;<!--\u{e9}-->\
+                <script>\u{e9}</script></p>"));
+        assert_eq!(reserialize_escaped(input, AsciiOnly),
+            wrap("<p title=\"&#xE9;&amp;\">&#xE9;&#x1F600;&nbsp;<!--\u{e9}-->\
+                <script>\u{e9}</script></p>"));
+        assert_eq!(reserialize_escaped(input, NamedEntities),
+            wrap("<p title=\"&eacute;&amp;\">&eacute;&#x1F600;&nbsp;<!--\u{e9}-->\
+                <script>\u{e9}</script></p>"));
+    }
+
+    #[test]
+    fn attr_whitespace() {
+        let input = "<p title='a&#10;b&#9;c&#13;&#10;d'>\n</p>";
+        let wrap = |p: &str| format!("<html><head></head><body>{}</body></html>", p);
+
+        assert_eq!(reserialize_attrs(input, PreserveWhitespace),
+            wrap("<p title=\"a\nb\tc\r\nd\">\n</p>"));
+        assert_eq!(reserialize_attrs(input, NormalizeWhitespace),
+            wrap("<p title=\"a b c d\">\n</p>"));
+
+        // Escaped whitespace survives another round trip.
+        let escaped = reserialize_attrs(input, EscapeWhitespace);
+        assert_eq!(escaped, wrap("<p title=\"a&#10;b&#9;c&#13;&#10;d\">\n</p>"));
+        assert_eq!(reserialize_attrs(escaped.as_slice(), EscapeWhitespace), escaped);
     }
 }
@@ -0,0 +1,413 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Rendering a DOM as plain text, for plain-text email, terminal
+//! previews and the like.
+//!
+//! Block elements start new lines, and paragraphs, headings, lists,
+//! and so on are separated by blank lines.  Whitespace is collapsed
+//! except inside `<pre>`.  List items get bullets or numbers, block
+//! quotes are prefixed with `> `, and links are numbered with their
+//! URLs listed at the end.
+
+use core::prelude::*;
+
+use sink::common::{Document, DocumentFragment, Text, Element, attr};
+use sink::rcdom::Handle;
+use serialize::block::Blocks;
+use tokenizer::Attribute;
+
+use util::str::is_ascii_whitespace;
+
+use core::cmp::{max, min};
+use core::default::Default;
+use collections::vec::Vec;
+use collections::string::String;
+use std::iter::repeat;
+
+use string_cache::Atom;
+
+/// Options for `to_text`.
+#[derive(Copy, Clone)]
+pub struct TextOpts {
+    /// Wrap lines at this many characters, where possible.  Zero means
+    /// don't wrap.  Default: 80
+    pub width: uint,
+
+    /// Number links, and list their URLs at the end?  Default: true
+    pub link_footnotes: bool,
+}
+
+impl Default for TextOpts {
+    fn default() -> TextOpts {
+        TextOpts {
+            width: 80,
+            link_footnotes: true,
+        }
+    }
+}
+
+fn char_len(s: &str) -> uint {
+    s.chars().count()
+}
+
+/// How an element is laid out.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Layout {
+    /// Not rendered at all.
+    Hidden,
+
+    /// Part of the surrounding line.
+    Inline,
+
+    /// Starts and ends a line.
+    Block,
+
+    /// Separated from surrounding blocks by blank lines.
+    Paragraph,
+}
+
+fn layout(name: &Atom) -> Layout {
+    match name.as_slice() {
+        "head" | "script" | "style" | "template" | "title" | "noscript"
+            | "iframe" | "object" | "embed" | "select" | "datalist" => Layout::Hidden,
+
+        "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "ul" | "ol" | "menu"
+            | "dl" | "pre" | "blockquote" | "table" | "figure" | "hr"
+            | "address" | "fieldset" => Layout::Paragraph,
+
+        "html" | "body" | "div" | "li" | "dt" | "dd" | "tr" | "caption"
+            | "section" | "article" | "header" | "footer" | "nav" | "aside"
+            | "main" | "form" | "figcaption" | "details" | "summary" | "legend"
+            | "center" | "thead" | "tbody" | "tfoot" | "option" => Layout::Block,
+
+        _ => Layout::Inline,
+    }
+}
+
+struct Renderer {
+    opts: TextOpts,
+
+    /// Finished output, and the prefixes of list items and block quotes.
+    blocks: Blocks,
+
+    /// Text of the current line or paragraph, not yet wrapped.
+    line: String,
+
+    /// Whitespace seen since the last text in `line`.
+    pending_space: bool,
+
+    /// Depth of `<pre>` elements.
+    pre_depth: uint,
+
+    /// Number of cells so far in the current table row.
+    cells: uint,
+
+    /// URLs of links, for footnotes.
+    links: Vec<String>,
+}
+
+impl Renderer {
+    fn new(opts: TextOpts) -> Renderer {
+        Renderer {
+            opts: opts,
+            blocks: Blocks::new(),
+            line: String::new(),
+            pending_space: false,
+            pre_depth: 0,
+            cells: 0,
+            links: vec!(),
+        }
+    }
+
+    // Wrap and write out the current line, if any.  Returns the width of
+    // its longest line.
+    fn flush(&mut self) -> uint {
+        if self.line.is_empty() {
+            return 0;
+        }
+        self.blocks.start_output();
+
+        let line = ::core::mem::replace(&mut self.line, String::new());
+        let width = match self.opts.width {
+            0 => 0,
+            w => max(w - min(w, self.blocks.indent_width()), 1),
+        };
+
+        let mut longest = 0;
+        let mut current = String::new();
+        for word in line.as_slice().split(' ') {
+            if !current.is_empty() && width > 0
+                && char_len(current.as_slice()) + 1 + char_len(word) > width {
+                longest = max(longest, char_len(current.as_slice()));
+                self.blocks.line(current.as_slice());
+                current.truncate(0);
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        longest = max(longest, char_len(current.as_slice()));
+        self.blocks.line(current.as_slice());
+        self.pending_space = false;
+        longest
+    }
+
+    // End the current line, even if it's empty, as for `<br>`.
+    fn line_break(&mut self) {
+        if self.line.is_empty() {
+            self.blocks.start_output();
+            self.blocks.line("");
+        } else {
+            self.flush();
+        }
+        self.pending_space = false;
+    }
+
+    fn block(&mut self, layout: Layout) {
+        self.flush();
+        self.pending_space = false;
+        if layout == Layout::Paragraph {
+            self.blocks.blank_line = true;
+        }
+    }
+
+    // Write out the current line of a `<pre>`, as it is.
+    fn pre_line(&mut self) {
+        self.blocks.start_output();
+        let line = ::core::mem::replace(&mut self.line, String::new());
+        self.blocks.line(line.as_slice());
+    }
+
+    fn push_str(&mut self, s: &str) {
+        if self.pending_space && !self.line.is_empty() {
+            self.line.push(' ');
+        }
+        self.pending_space = false;
+        self.line.push_str(s);
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.pre_depth > 0 {
+            let mut first = true;
+            for part in text.split('\n') {
+                if !first {
+                    self.pre_line();
+                }
+                first = false;
+                self.line.push_str(part);
+            }
+            return;
+        }
+
+        for c in text.chars() {
+            if is_ascii_whitespace(c) {
+                self.pending_space = true;
+            } else {
+                if self.pending_space && !self.line.is_empty() {
+                    self.line.push(' ');
+                }
+                self.pending_space = false;
+                self.line.push(c);
+            }
+        }
+    }
+
+    fn children(&mut self, handle: &Handle) {
+        for child in handle.borrow().children.iter() {
+            self.node(child);
+        }
+    }
+
+    fn list_item(&mut self, handle: &Handle) {
+        self.blocks.start_item("* ");
+        self.children(handle);
+        self.flush();
+        self.blocks.end_item();
+    }
+
+    fn element(&mut self, handle: &Handle, name: &Atom, attrs: &[Attribute]) {
+        let layout = layout(name);
+        if layout == Layout::Hidden || attr(attrs, "hidden").is_some() {
+            return;
+        }
+        if layout != Layout::Inline {
+            self.block(layout);
+        }
+
+        match name.as_slice() {
+            "br" => self.line_break(),
+
+            "hr" => {
+                self.blocks.start_output();
+                let width = match self.opts.width {
+                    0 => 40,
+                    w => w - min(w, self.blocks.indent_width()),
+                };
+                let rule: String = repeat('-').take(max(width, 3)).collect();
+                self.blocks.line(rule.as_slice());
+            }
+
+            "img" => match attr(attrs, "alt") {
+                Some(alt) if !alt.trim().is_empty() => {
+                    let alt = format!("[{}]", alt.trim());
+                    self.push_str(alt.as_slice());
+                }
+                _ => (),
+            },
+
+            "ul" | "menu" | "ol" => {
+                self.blocks.start_list(name, attrs);
+                self.children(handle);
+                self.blocks.end_list();
+            }
+
+            "li" => self.list_item(handle),
+
+            "dd" | "blockquote" => {
+                self.blocks.push_prefix(match name.as_slice() {
+                    "dd" => "    ",
+                    _ => "> ",
+                });
+                self.children(handle);
+                self.flush();
+                self.blocks.pop_prefix();
+            }
+
+            "pre" => {
+                self.pre_depth += 1;
+                self.children(handle);
+                self.pre_depth -= 1;
+                if !self.line.is_empty() {
+                    self.pre_line();
+                }
+            }
+
+            "h1" | "h2" => {
+                self.children(handle);
+                let width = self.flush();
+                if width > 0 {
+                    let c = if name.as_slice() == "h1" { '=' } else { '-' };
+                    let rule: String = repeat(c).take(width).collect();
+                    self.blocks.line(rule.as_slice());
+                }
+            }
+
+            "tr" => {
+                self.cells = 0;
+                self.children(handle);
+            }
+
+            "td" | "th" => {
+                if self.cells > 0 {
+                    self.pending_space = false;
+                    self.line.push_str(" | ");
+                }
+                self.cells += 1;
+                self.children(handle);
+            }
+
+            "a" => {
+                self.children(handle);
+                match attr(attrs, "href") {
+                    Some(href) if self.opts.link_footnotes && !href.is_empty()
+                        && !href.starts_with("#") && !href.starts_with("javascript:") => {
+                        self.links.push(String::from_str(href));
+                        let mark = format!("[{}]", self.links.len());
+                        self.line.push_str(mark.as_slice());
+                    }
+                    _ => (),
+                }
+            }
+
+            _ => self.children(handle),
+        }
+
+        if layout != Layout::Inline {
+            self.block(layout);
+        }
+    }
+
+    fn node(&mut self, handle: &Handle) {
+        let node = handle.borrow();
+        match node.node {
+            Document | DocumentFragment => self.children(handle),
+            Text(ref text) => self.text(text.as_slice()),
+            Element(ref name, ref attrs) if name.ns == ns!(HTML) =>
+                self.element(handle, &name.local, attrs.as_slice()),
+            _ => (),
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush();
+        let mut out = self.blocks.out;
+        if !self.links.is_empty() {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            for (i, url) in self.links.iter().enumerate() {
+                out.push_str(format!("[{}] {}\n", i + 1, url).as_slice());
+            }
+        }
+        out
+    }
+}
+
+/// Render a node and its descendants as plain text.
+pub fn to_text(node: &Handle, opts: TextOpts) -> String {
+    let mut renderer = Renderer::new(opts);
+    renderer.node(node);
+    renderer.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::string::String;
+
+    use super::{to_text, TextOpts};
+    use sink::rcdom::RcDom;
+    use driver::parse_str;
+
+    fn text(input: &str, width: uint) -> String {
+        let dom: RcDom = parse_str(input);
+        to_text(&dom.document, TextOpts {
+            width: width,
+            .. Default::default()
+        })
+    }
+
+    test_eq!(paragraphs, text("<title>x</title><p>One\n  two</p><p>three<br>four", 80),
+        "One two\n\nthree\nfour\n");
+
+    test_eq!(wrap, text("<p>aaa bbb ccc ddd", 8), "aaa bbb\nccc ddd\n");
+
+    test_eq!(lists, text("<p>x<ul><li>a<li>b<ol start=3><li>c<li>d</ol></ul>", 80),
+        "x\n\n* a\n* b\n\n  3. c\n  4. d\n");
+
+    test_eq!(headings, text("<h1>Title</h1><h3>Sub</h3>text", 80),
+        "Title\n=====\n\nSub\n\ntext\n");
+
+    test_eq!(links, text("<p>See <a href='http://example.com/'>this</a> \
+        and <a href='#top'>that</a>.", 80),
+        "See this[1] and that.\n\n[1] http://example.com/\n");
+
+    test_eq!(pre, text("<pre>  a\n   b</pre>after", 80), "  a\n   b\n\nafter\n");
+
+    test_eq!(quote, text("<blockquote><p>a<p>b</blockquote>", 80), "> a\n>\n> b\n");
+
+    test_eq!(table, text("<table><tr><th>A<th>B<tr><td>1<td>2</table>", 80),
+        "A | B\n1 | 2\n");
+
+    test_eq!(nested_lists, text("<ul><li><ul><li>a<li>b</ul><li><ol><li></ol></ul>", 80),
+        "* * a\n  * b\n\n* 1.\n");
+}
@@ -7,20 +7,29 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use core::prelude::*;
+
 use tokenizer::Attribute;
+use tokenizer::states::{State, RawData, Rcdata, Rawtext, ScriptData, Plaintext};
 
+use core::default::Default;
 use collections::vec::Vec;
 use collections::string::String;
-use string_cache::QualName;
+use string_cache::{Atom, QualName};
 
-pub use self::NodeEnum::{Document, Doctype, Text, Comment, Element};
+pub use self::NodeEnum::{Document, DocumentFragment, Doctype, Text, Comment, Element};
+pub use self::TextMerging::{MergeText, PreserveTokenBoundaries};
+pub use self::Direction::{Ltr, Rtl};
 
 /// The different kinds of nodes in the DOM.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum NodeEnum {
     /// The `Document` itself.
     Document,
 
+    /// A `DocumentFragment`, holding the result of parsing a fragment.
+    DocumentFragment,
+
     /// A `DOCTYPE` with name, public id, and system id.
     Doctype(String, String, String),
 
@@ -33,3 +42,285 @@ pub enum NodeEnum {
     /// An element with attributes.
     Element(QualName, Vec<Attribute>),
 }
+
+/// Whether a DOM sink merges text inserted next to a text node into
+/// that node.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum TextMerging {
+    /// Merge, so that there are never adjacent text nodes, as in a
+    /// browser.  The default.
+    MergeText,
+
+    /// Make a new text node for each piece of text the tree builder
+    /// inserts.  These mostly follow the boundaries of character tokens,
+    /// which is useful for testing the tokenizer and tree builder.
+    PreserveTokenBoundaries,
+}
+
+impl Default for TextMerging {
+    fn default() -> TextMerging {
+        MergeText
+    }
+}
+
+/// The directionality of a node, as matched by the `:dir()` selector.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+/// The direction of a character which has a strong bidirectional type,
+/// or `None` for a weak or neutral character.
+///
+/// This goes by Unicode block rather than the full bidi data, so some
+/// punctuation and marks outside ASCII count as strong, but letters
+/// are right.
+pub fn strong_direction(c: char) -> Option<Direction> {
+    match c as u32 {
+        0x41...0x5A | 0x61...0x7A | 0xAA | 0xB5 | 0xBA => Some(Ltr),
+        0x00...0xBF | 0xD7 | 0xF7 => None,
+        0x0300...0x036F => None,
+        0x0590...0x08FF | 0xFB1D...0xFDFF | 0xFE70...0xFEFF
+            | 0x10800...0x10FFF | 0x1E800...0x1EFFF => Some(Rtl),
+        0x2000...0x2BFF | 0x3000...0x303F | 0xFE00...0xFE6F
+            | 0xFF00...0xFF20 | 0xFF3B...0xFF40 | 0xFF5B...0xFF65 => None,
+        _ => Some(Ltr),
+    }
+}
+
+//§ dom-dataset
+/// Convert the name of a `data-*` attribute to the corresponding
+/// `dataset` property name, e.g. `data-foo-bar` to `fooBar`.
+///
+/// Returns `None` if this isn't a `data-*` attribute, or contains ASCII
+/// uppercase letters and so can't be reached through `dataset`.
+pub fn dataset_name(attr_name: &QualName) -> Option<String> {
+    if attr_name.ns != ns!("") {
+        return None;
+    }
+
+    let name = attr_name.local.as_slice();
+    if !name.starts_with("data-") || name.chars().any(|c| match c { 'A'...'Z' => true, _ => false }) {
+        return None;
+    }
+
+    let mut out = String::new();
+    let mut hyphen = false;
+    for c in name[5..].chars() {
+        match c {
+            'a'...'z' if hyphen => out.push((c as u8 - b'a' + b'A') as char),
+            '-' => {
+                if hyphen {
+                    out.push('-');
+                }
+                hyphen = true;
+                continue;
+            }
+            _ => {
+                if hyphen {
+                    out.push('-');
+                }
+                out.push(c);
+            }
+        }
+        hyphen = false;
+    }
+    if hyphen {
+        out.push('-');
+    }
+    Some(out)
+}
+//§ END
+
+impl NodeEnum {
+    /// The `data-*` attributes of an element, as `(name, value)` pairs with
+    /// names converted as for the DOM's `element.dataset`.  Empty for other
+    /// nodes.
+    pub fn dataset(&self) -> Vec<(String, String)> {
+        match *self {
+            Element(_, ref attrs) => attrs.iter().filter_map(|attr| {
+                dataset_name(&attr.name).map(|name| (name, attr.value.clone()))
+            }).collect(),
+            _ => vec!(),
+        }
+    }
+
+    /// Look up a `data-*` attribute by its `dataset` name, e.g. `fooBar`
+    /// for `data-foo-bar`.
+    pub fn data(&self, name: &str) -> Option<&str> {
+        match *self {
+            Element(_, ref attrs) => attrs.iter()
+                .find(|attr| dataset_name(&attr.name).map_or(false, |n| n.as_slice() == name))
+                .map(|attr| attr.value.as_slice()),
+            _ => None,
+        }
+    }
+}
+
+/// Append the lines for one node to an html5lib-style tree dump, as
+/// in the `#document` sections of the tree construction tests:
+///
+/// ```text
+/// | <!DOCTYPE html>
+/// | <html>
+/// |   <body>
+/// |     <p>
+/// |       class="a"
+/// |       "text"
+/// ```
+///
+/// `depth` is the number of ancestors being dumped.  The children are
+/// left to the caller, at `depth + 1`.  Each line ends with `\n`.
+/// Attributes are sorted by name.
+pub fn dump_node(out: &mut String, depth: uint, node: &NodeEnum) {
+    fn indent(out: &mut String, depth: uint) {
+        out.push_str("| ");
+        for _ in range(0, depth) {
+            out.push_str("  ");
+        }
+    }
+
+    match *node {
+        Document | DocumentFragment => return,
+        _ => indent(out, depth),
+    }
+    match *node {
+        Document | DocumentFragment => (),
+
+        Doctype(ref name, ref public, ref system) => {
+            out.push_str("<!DOCTYPE ");
+            out.push_str(name.as_slice());
+            if !public.is_empty() || !system.is_empty() {
+                out.push_str(format!(" \"{}\" \"{}\"", public, system).as_slice());
+            }
+            out.push_str(">\n");
+        }
+
+        Text(ref text) => {
+            out.push_str("\"");
+            out.push_str(text.as_slice());
+            out.push_str("\"\n");
+        }
+
+        Comment(ref text) => {
+            out.push_str("<!-- ");
+            out.push_str(text.as_slice());
+            out.push_str(" -->\n");
+        }
+
+        Element(ref name, ref attrs) => {
+            out.push_str("<");
+            match name.ns {
+                ns!(SVG) => out.push_str("svg "),
+                ns!(MathML) => out.push_str("math "),
+                _ => (),
+            }
+            out.push_str(name.local.as_slice());
+            out.push_str(">\n");
+
+            let mut attrs: Vec<(String, &str)> = attrs.iter().map(|attr| {
+                let prefix = match attr.name.ns {
+                    ns!(XLink) => "xlink ",
+                    ns!(XML) => "xml ",
+                    ns!(XMLNS) => "xmlns ",
+                    _ => "",
+                };
+                (format!("{}{}", prefix, attr.name.local.as_slice()), attr.value.as_slice())
+            }).collect();
+            // FIXME: html5lib sorts by UTF-16 code unit.
+            attrs.sort();
+
+            for &(ref name, value) in attrs.iter() {
+                indent(out, depth + 1);
+                out.push_str(format!("{}=\"{}\"\n", name, value).as_slice());
+            }
+        }
+    }
+}
+
+/// The tokenizer state which the tree builder would switch to after an
+/// HTML start tag with this name, if any.
+///
+/// For `TokenSink`s that run without a tree builder but still want
+/// `<script>`, `<style>` and friends tokenized correctly.  This ignores
+/// foreign content, and treats `<noscript>` as if scripting is disabled.
+pub fn raw_text_state(name: &Atom) -> Option<State> {
+    match *name {
+        atom!(title) | atom!(textarea) => Some(RawData(Rcdata)),
+        atom!(style) | atom!(xmp) | atom!(iframe) | atom!(noembed)
+            | atom!(noframes) => Some(RawData(Rawtext)),
+        atom!(script) => Some(RawData(ScriptData)),
+        atom!(plaintext) => Some(Plaintext),
+        _ => None,
+    }
+}
+
+/// Is this an HTML void element, which has no end tag and no content?
+pub fn is_void(name: &Atom) -> bool {
+    match *name {
+        atom!(area) | atom!(base) | atom!(basefont) | atom!(bgsound) | atom!(br)
+        | atom!(col) | atom!(embed) | atom!(frame) | atom!(hr) | atom!(img)
+        | atom!(input) | atom!(keygen) | atom!(link) | atom!(menuitem)
+        | atom!(meta) | atom!(param) | atom!(source) | atom!(track) | atom!(wbr)
+            => true,
+        _ => false,
+    }
+}
+
+/// The value of the attribute with no namespace and the local name
+/// `name`, if there is one.
+pub fn attr<'a>(attrs: &'a [Attribute], name: &str) -> Option<&'a str> {
+    attrs.iter()
+        .find(|a| a.name.ns == ns!("") && a.name.local.as_slice() == name)
+        .map(|a| a.value.as_slice())
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::string::String;
+    use string_cache::{Atom, QualName};
+    use tokenizer::Attribute;
+    use super::{Element, Text, dataset_name, strong_direction, Ltr, Rtl};
+
+    fn name(local: &str) -> Option<String> {
+        dataset_name(&QualName::new(ns!(""), Atom::from_slice(local)))
+    }
+
+    fn s(x: &str) -> Option<String> {
+        Some(String::from_str(x))
+    }
+
+    test_eq!(dataset_simple, name("data-foo"), s("foo"));
+    test_eq!(dataset_camel, name("data-foo-bar-baz"), s("fooBarBaz"));
+    test_eq!(dataset_digit, name("data-foo-1"), s("foo-1"));
+    test_eq!(dataset_double_hyphen, name("data-foo--bar"), s("foo-Bar"));
+    test_eq!(dataset_trailing_hyphen, name("data-foo-"), s("foo-"));
+    test_eq!(dataset_empty, name("data-"), s(""));
+    test_eq!(dataset_not_data, name("foo-bar"), None);
+    test_eq!(dataset_upper, name("data-Foo"), None);
+
+    test_eq!(strong_latin, strong_direction('\u{e9}'), Some(Ltr));
+    test_eq!(strong_hebrew, strong_direction('\u{5D0}'), Some(Rtl));
+    test_eq!(strong_arabic, strong_direction('\u{627}'), Some(Rtl));
+    test_eq!(strong_cjk, strong_direction('\u{4E2D}'), Some(Ltr));
+    test_eq!(neutral_digit, strong_direction('1'), None);
+    test_eq!(neutral_dash, strong_direction('\u{2014}'), None);
+
+    #[test]
+    fn dataset_on_element() {
+        let attr = |n: &str, v: &str| Attribute {
+            name: QualName::new(ns!(""), Atom::from_slice(n)),
+            value: String::from_str(v),
+        };
+        let elem = Element(qualname!(HTML, div),
+            vec!(attr("id", "x"), attr("data-user-id", "42"), attr("data-x", "")));
+        assert_eq!(elem.dataset(), vec!(
+            (String::from_str("userId"), String::from_str("42")),
+            (String::from_str("x"), String::from_str(""))));
+        assert_eq!(elem.data("userId"), Some("42"));
+        assert_eq!(elem.data("user-id"), None);
+        assert_eq!(Text(String::from_str("x")).data("x"), None);
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A token filter which picks Internet Explorer's conditional comments
+//! out of the token stream, for tools (such as HTML email processors)
+//! which need to keep or rewrite them.
+//!
+//! HTML parses these as ordinary comments.  Wrapping a sink in
+//! `ConditionalCommentFilter` delivers them to the sink's
+//! `process_conditional_comment` instead; all other tokens pass
+//! through untouched.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let filter = ConditionalCommentFilter::new(my_sink);
+//! let my_sink = tokenize_to(filter, one_input(my_str), Default::default()).into_inner();
+//! ```
+
+use core::prelude::*;
+
+use tokenizer::{TokenSink, TokenSinkResult, Token, CommentToken, BogusCommentToken, Span};
+use tokenizer::states::State;
+
+use util::str::is_ascii_whitespace;
+
+use collections::string::String;
+
+use self::ConditionalComment::{DownlevelHidden, RevealedStart, RevealedEnd};
+
+/// A conditional comment, as recognized by `ConditionalCommentFilter`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum ConditionalComment {
+    /// `<!--[if IE]>content<![endif]-->`: markup which only IE sees.
+    /// The content is left unparsed.
+    DownlevelHidden {
+        condition: String,
+        content: String,
+    },
+
+    /// `<![if !IE]>` or `<!--[if !IE]><!-->`: the start of markup which
+    /// other browsers see, and IE sees if the condition holds.
+    RevealedStart(String),
+
+    /// `<![endif]>` or `<!--<![endif]-->`: the end of a downlevel-revealed
+    /// block.
+    RevealedEnd,
+}
+
+/// A `TokenSink` which also accepts conditional comments.
+pub trait ConditionalCommentSink: TokenSink {
+    /// Process a conditional comment, in place of the comment token
+    /// it came from.
+    fn process_conditional_comment(&mut self, comment: ConditionalComment) -> TokenSinkResult;
+}
+
+/// Recognize a conditional comment from the text of a comment token.
+pub fn parse_conditional_comment(text: &str) -> Option<ConditionalComment> {
+    if text == "[endif]" || text == "<![endif]" {
+        return Some(RevealedEnd);
+    }
+
+    if !text.starts_with("[if") {
+        return None;
+    }
+    let rest = &text[3..];
+    if !rest.chars().next().map_or(false, is_ascii_whitespace) {
+        return None;
+    }
+    let end = unwrap_or_return!(rest.find(']'), None);
+    let condition = rest[..end].trim_matches(is_ascii_whitespace);
+    if condition.is_empty() {
+        return None;
+    }
+    let condition = String::from_str(condition);
+
+    match &rest[end+1..] {
+        "" | "><!" => Some(RevealedStart(condition)),
+        body if body.starts_with(">") && body.ends_with("<![endif]") => {
+            let content = &body[1..body.len() - "<![endif]".len()];
+            Some(DownlevelHidden {
+                condition: condition,
+                content: String::from_str(content),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Wraps a `ConditionalCommentSink`, diverting conditional comments to
+/// its `process_conditional_comment`.
+pub struct ConditionalCommentFilter<Sink> {
+    sink: Sink,
+}
+
+impl<Sink: ConditionalCommentSink> ConditionalCommentFilter<Sink> {
+    pub fn new(sink: Sink) -> ConditionalCommentFilter<Sink> {
+        ConditionalCommentFilter {
+            sink: sink,
+        }
+    }
+
+    /// Get a reference to the wrapped sink.
+    pub fn sink<'a>(&'a self) -> &'a Sink {
+        &self.sink
+    }
+
+    /// Get a mutable reference to the wrapped sink.
+    pub fn sink_mut<'a>(&'a mut self) -> &'a mut Sink {
+        &mut self.sink
+    }
+
+    /// Unwrap the sink.
+    pub fn into_inner(self) -> Sink {
+        self.sink
+    }
+}
+
+impl<Sink: ConditionalCommentSink> TokenSink for ConditionalCommentFilter<Sink> {
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
+        let comment = match token {
+            CommentToken(ref text) | BogusCommentToken(ref text)
+                => parse_conditional_comment(text.as_slice()),
+            _ => None,
+        };
+        match comment {
+            Some(comment) => self.sink.process_conditional_comment(comment),
+            None => self.sink.process_token(token),
+        }
+    }
+
+    fn query_state_change(&mut self) -> Option<State> {
+        self.sink.query_state_change()
+    }
+
+    fn set_current_span(&mut self, span: Span) {
+        self.sink.set_current_span(span)
+    }
+
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        self.sink.adjusted_current_node_present_but_not_in_html_namespace()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+    use core::default::Default;
+
+    use tokenizer::{TokenSink, TokenSinkResult, Continue, Token, CommentToken};
+    use driver::{tokenize_to, one_input};
+
+    use super::{ConditionalComment, ConditionalCommentSink, ConditionalCommentFilter};
+    use super::ConditionalComment::{DownlevelHidden, RevealedStart, RevealedEnd};
+
+    struct RecordingSink {
+        comments: Vec<String>,
+        conditionals: Vec<ConditionalComment>,
+    }
+
+    impl TokenSink for RecordingSink {
+        fn process_token(&mut self, token: Token) -> TokenSinkResult {
+            match token {
+                CommentToken(text) => self.comments.push(text),
+                _ => (),
+            }
+            Continue
+        }
+    }
+
+    impl ConditionalCommentSink for RecordingSink {
+        fn process_conditional_comment(&mut self, comment: ConditionalComment) -> TokenSinkResult {
+            self.conditionals.push(comment);
+            Continue
+        }
+    }
+
+    fn filter(input: &str) -> RecordingSink {
+        let sink = RecordingSink {
+            comments: vec!(),
+            conditionals: vec!(),
+        };
+        tokenize_to(ConditionalCommentFilter::new(sink), one_input(String::from_str(input)),
+            Default::default()).into_inner()
+    }
+
+    fn s(x: &str) -> String {
+        String::from_str(x)
+    }
+
+    #[test]
+    fn downlevel_hidden() {
+        let sink = filter("<!--[if gte mso 9]><xml><o:foo/></xml><![endif]--><!-- plain -->");
+        assert_eq!(sink.conditionals, vec!(DownlevelHidden {
+            condition: s("gte mso 9"),
+            content: s("<xml><o:foo/></xml>"),
+        }));
+        assert_eq!(sink.comments, vec!(s(" plain ")));
+    }
+
+    #[test]
+    fn downlevel_revealed() {
+        let sink = filter("<![if !IE]><p>x<![endif]><!--[if !mso]><!--><p>y<!--<![endif]-->");
+        assert_eq!(sink.conditionals, vec!(
+            RevealedStart(s("!IE")), RevealedEnd,
+            RevealedStart(s("!mso")), RevealedEnd));
+        assert!(sink.comments.is_empty());
+    }
+
+    #[test]
+    fn not_conditional() {
+        let sink = filter("<!--[iffy]--><!--[if ]><![endif]--><!--[if IE]> oops -->");
+        assert!(sink.conditionals.is_empty());
+        assert_eq!(sink.comments, vec!(s("[iffy]"), s("[if ]><![endif]"), s("[if IE]> oops ")));
+    }
+}
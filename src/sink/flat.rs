@@ -0,0 +1,380 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A compact binary encoding of an `RcDom`, for caching parsed
+//! documents between processes.
+//!
+//! The encoding is a table of the atoms used for element and attribute
+//! names, followed by a table of nodes in document order, each with its
+//! number of children.  Decoding rebuilds the tree without recursion,
+//! so deep trees are fine.
+//!
+//! The document's quirks mode, `base_url`, parse errors and node spans
+//! are kept.  Decoded errors have the code `"Decoded error"` and the
+//! original message, severity and span.  The element index of
+//! `RcDom::with_index()` is not kept; adopt the decoded document into
+//! an indexed `RcDom` if you need it.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let bytes = encode(&dom);
+//! ...
+//! let dom = decode(bytes.as_slice()).unwrap();
+//! ```
+
+use core::prelude::*;
+
+pub use util::codec::DecodeError;
+
+use sink::common::{NodeEnum, Document, DocumentFragment, Doctype, Text, Comment, Element};
+use sink::rcdom::{RcDom, Node, Handle};
+use tokenizer::Attribute;
+use tree_builder::{Quirks, LimitedQuirks, NoQuirks};
+use util::codec::{put_uint, put_str, put_opt_span, put_diagnostic, Reader};
+
+use core::mem;
+use core::cell::RefCell;
+use core::default::Default;
+use alloc::rc::Rc;
+use collections::vec::Vec;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry::{Occupied, Vacant};
+
+use string_cache::{Atom, QualName, Namespace};
+
+// The start of every encoding: a signature and a format version.
+static MAGIC: &'static [u8] = b"H5DM\x02";
+
+// Node kinds.
+const DOCUMENT: u8 = 0;
+const DOCUMENT_FRAGMENT: u8 = 1;
+const DOCTYPE: u8 = 2;
+const TEXT: u8 = 3;
+const COMMENT: u8 = 4;
+const ELEMENT: u8 = 5;
+
+struct Encoder {
+    atoms: Vec<Atom>,
+    atom_index: HashMap<Atom, uint>,
+    nodes: Vec<u8>,
+    node_count: uint,
+}
+
+impl Encoder {
+    fn atom(&mut self, atom: &Atom) {
+        let next = self.atoms.len();
+        let index = match self.atom_index.entry(atom.clone()) {
+            Occupied(e) => *e.get(),
+            Vacant(e) => *e.insert(next),
+        };
+        if index == next {
+            self.atoms.push(atom.clone());
+        }
+        put_uint(&mut self.nodes, index);
+    }
+
+    fn name(&mut self, name: &QualName) {
+        self.atom(&name.ns.0);
+        self.atom(&name.local);
+    }
+
+    fn node(&mut self, node: &Node) {
+        self.node_count += 1;
+        let kind = match node.node {
+            Document => DOCUMENT,
+            DocumentFragment => DOCUMENT_FRAGMENT,
+            Doctype(..) => DOCTYPE,
+            Text(_) => TEXT,
+            Comment(_) => COMMENT,
+            Element(..) => ELEMENT,
+        };
+        self.nodes.push(kind);
+        put_uint(&mut self.nodes, node.children.len());
+        put_opt_span(&mut self.nodes, node.span);
+        match node.node {
+            Document | DocumentFragment => (),
+            Doctype(ref name, ref public_id, ref system_id) => {
+                put_str(&mut self.nodes, name.as_slice());
+                put_str(&mut self.nodes, public_id.as_slice());
+                put_str(&mut self.nodes, system_id.as_slice());
+            }
+            Text(ref text) | Comment(ref text) => put_str(&mut self.nodes, text.as_slice()),
+            Element(ref name, ref attrs) => {
+                self.name(name);
+                put_uint(&mut self.nodes, attrs.len());
+                for attr in attrs.iter() {
+                    self.name(&attr.name);
+                    put_str(&mut self.nodes, attr.value.as_slice());
+                }
+            }
+        }
+    }
+}
+
+/// Encode a document.
+pub fn encode(dom: &RcDom) -> Vec<u8> {
+    let mut encoder = Encoder {
+        atoms: vec!(),
+        atom_index: HashMap::new(),
+        nodes: vec!(),
+        node_count: 0,
+    };
+    let mut stack = vec!(dom.document.clone());
+    loop {
+        let handle = unwrap_or_else!(stack.pop(), { break });
+        let node = handle.borrow();
+        encoder.node(&*node);
+        stack.extend(node.children.iter().rev().map(|c| c.clone()));
+    }
+
+    let mut out = Vec::new();
+    out.push_all(MAGIC);
+    out.push(match dom.quirks_mode {
+        NoQuirks => 0,
+        LimitedQuirks => 1,
+        Quirks => 2,
+    });
+    match dom.base_url {
+        Some(ref url) => {
+            out.push(1);
+            put_str(&mut out, url.as_slice());
+        }
+        None => out.push(0),
+    }
+    put_uint(&mut out, dom.errors.len());
+    for e in dom.errors.iter() {
+        put_diagnostic(&mut out, e);
+    }
+    put_uint(&mut out, encoder.atoms.len());
+    for atom in encoder.atoms.iter() {
+        put_str(&mut out, atom.as_slice());
+    }
+    put_uint(&mut out, encoder.node_count);
+    out.push_all(encoder.nodes.as_slice());
+    out
+}
+
+struct Decoder<'a> {
+    r: Reader<'a>,
+    atoms: Vec<Atom>,
+}
+
+impl<'a> Decoder<'a> {
+    fn atom(&mut self) -> Result<Atom, DecodeError> {
+        let index = try!(self.r.uint());
+        match self.atoms.get(index) {
+            Some(atom) => Ok(atom.clone()),
+            None => self.r.error("Atom index out of range"),
+        }
+    }
+
+    fn name(&mut self) -> Result<QualName, DecodeError> {
+        let ns = Namespace(try!(self.atom()));
+        let local = try!(self.atom());
+        Ok(QualName::new(ns, local))
+    }
+
+    // Read a node and the number of children it has.
+    fn node(&mut self) -> Result<(Node, uint), DecodeError> {
+        let kind = try!(self.r.byte());
+        let children = try!(self.r.uint());
+        let span = try!(self.r.opt_span());
+        let node = match kind {
+            DOCUMENT => Document,
+            DOCUMENT_FRAGMENT => DocumentFragment,
+            DOCTYPE => {
+                let name = try!(self.r.string());
+                let public_id = try!(self.r.string());
+                Doctype(name, public_id, try!(self.r.string()))
+            }
+            TEXT => Text(try!(self.r.string())),
+            COMMENT => Comment(try!(self.r.string())),
+            ELEMENT => {
+                let name = try!(self.name());
+                let count = try!(self.r.uint());
+                let mut attrs = vec!();
+                for _ in 0..count {
+                    let name = try!(self.name());
+                    attrs.push(Attribute {
+                        name: name,
+                        value: try!(self.r.string()),
+                    });
+                }
+                Element(name, attrs)
+            }
+            _ => return self.r.error("Unknown node kind"),
+        };
+        Ok((Node {
+            node: node,
+            parent: None,
+            children: vec!(),
+            script_already_started: false,
+            span: span,
+        }, children))
+    }
+}
+
+fn is_root(node: &NodeEnum) -> bool {
+    match *node {
+        Document | DocumentFragment => true,
+        _ => false,
+    }
+}
+
+/// Decode a document produced by `encode`.
+pub fn decode(bytes: &[u8]) -> Result<RcDom, DecodeError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(DecodeError {
+            position: 0,
+            message: "Not an encoded document",
+        });
+    }
+
+    let mut d = Decoder {
+        r: Reader::new(bytes, MAGIC.len()),
+        atoms: vec!(),
+    };
+    let mut dom = RcDom::default();
+    dom.quirks_mode = match try!(d.r.byte()) {
+        0 => NoQuirks,
+        1 => LimitedQuirks,
+        2 => Quirks,
+        _ => return d.r.error("Unknown quirks mode"),
+    };
+    dom.base_url = match try!(d.r.byte()) {
+        0 => None,
+        _ => Some(try!(d.r.string())),
+    };
+    for _ in 0..try!(d.r.uint()) {
+        dom.errors.push(try!(d.r.diagnostic("Decoded error")));
+    }
+    for _ in 0..try!(d.r.uint()) {
+        let atom = Atom::from_slice(try!(d.r.str()));
+        d.atoms.push(atom);
+    }
+
+    let count = try!(d.r.uint());
+    if count == 0 {
+        return d.r.error("No document node");
+    }
+    let (root, mut remaining) = try!(d.node());
+    if !is_root(&root.node) {
+        return d.r.error("First node is not a document");
+    }
+    dom.document = Handle(Rc::new(RefCell::new(root)));
+
+    // Parents which are still waiting for children, and how many.
+    let mut open: Vec<(Handle, uint)> = vec!();
+    let mut parent = dom.document.clone();
+    for _ in 1..count {
+        while remaining == 0 {
+            match open.pop() {
+                Some((p, r)) => {
+                    parent = p;
+                    remaining = r;
+                }
+                None => return d.r.error("More nodes than the tree has room for"),
+            }
+        }
+        let start = d.r.pos;
+        let (mut node, children) = try!(d.node());
+        if is_root(&node.node) {
+            d.r.pos = start;
+            return d.r.error("Document node inside the tree");
+        }
+        node.parent = Some(parent.downgrade());
+        let handle = Handle(Rc::new(RefCell::new(node)));
+        parent.borrow_mut().children.push(handle.clone());
+        remaining -= 1;
+        if children > 0 {
+            open.push((mem::replace(&mut parent, handle), remaining));
+            remaining = children;
+        }
+    }
+    if remaining > 0 || open.iter().any(|&(_, r)| r > 0) {
+        return d.r.error("Missing nodes");
+    }
+    if !d.r.at_end() {
+        return d.r.error("Trailing bytes after the document");
+    }
+    Ok(dom)
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::{encode, decode, DecodeError};
+    use sink::rcdom::{RcDom, Handle, dump_tree};
+    use tokenizer::{TokenizerOpts, Span};
+    use driver::{parse_to, parse_str, one_input, ParseOpts};
+
+    fn spans(handle: &Handle, out: &mut Vec<Option<Span>>) {
+        let node = handle.borrow();
+        out.push(node.span);
+        for child in node.children.iter() {
+            spans(child, out);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = "<!DOCTYPE html PUBLIC '-//W3C//DTD HTML 3.2 Final//EN'>\
+            <base href=/b/><p class=a id=b>x<!--c-->y<table><td>z</table>\
+            <svg viewBox='0 0 1 1'><path xlink:href=#p /></svg><i><i><i>";
+        let opts = ParseOpts {
+            tokenizer: TokenizerOpts {
+                track_positions: true,
+                .. Default::default()
+            },
+            .. Default::default()
+        };
+        let dom: RcDom = parse_to(RcDom::default(), one_input(String::from_str(input)), opts);
+        let decoded = decode(encode(&dom).as_slice()).unwrap();
+
+        assert_eq!(dump_tree(&decoded.document), dump_tree(&dom.document));
+        assert_eq!(decoded.quirks_mode, dom.quirks_mode);
+        assert_eq!(decoded.base_url, dom.base_url);
+        assert_eq!(decoded.errors.len(), dom.errors.len());
+        for (a, b) in decoded.errors.iter().zip(dom.errors.iter()) {
+            assert_eq!((&a.message, a.severity, a.span), (&b.message, b.severity, b.span));
+        }
+        let (mut a, mut b) = (vec!(), vec!());
+        spans(&decoded.document, &mut a);
+        spans(&dom.document, &mut b);
+        assert_eq!(a, b);
+
+        let p = decoded.document.borrow().children[1].borrow().children[1].borrow()
+            .children[0].clone();
+        let parent = Handle(p.borrow().parent.as_ref().unwrap().upgrade().unwrap());
+        assert!(parent == decoded.document.borrow().children[1].borrow().children[1]);
+    }
+
+    #[test]
+    fn bad_input() {
+        let dom: RcDom = parse_str("<p>x");
+        let bytes = encode(&dom);
+        let err = |position: uint, message: &'static str| Some(DecodeError {
+            position: position,
+            message: message,
+        });
+
+        assert_eq!(decode(b"<p>").err(), err(0, "Not an encoded document"));
+        assert_eq!(decode(&bytes[..bytes.len() - 1]).err(),
+            err(bytes.len() - 1, "Unexpected end of input"));
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert_eq!(decode(extra.as_slice()).err(),
+            err(bytes.len(), "Trailing bytes after the document"));
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A `TokenSink` which collects the URLs in a document, for crawlers
+//! and other consumers that don't need a DOM.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let sink = tokenize_to(LinkSink::new(), one_input(my_str), Default::default());
+//! for link in sink.links.iter() {
+//!     println!("{}", link.url);
+//! }
+//! ```
+
+use core::prelude::*;
+
+use sink::common::raw_text_state;
+use microsyntax::srcset::parse_srcset;
+use tokenizer::{TokenSink, TokenSinkResult, Continue, Token, TagToken, StartTag, Tag, Span};
+use tokenizer::states::State;
+
+use util::str::is_ascii_whitespace;
+
+use core::default::Default;
+use collections::vec::Vec;
+use collections::string::String;
+
+use string_cache::Atom;
+
+pub use self::UrlKind::{SingleUrl, Srcset};
+
+/// A URL found in an attribute.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Link {
+    /// Name of the element carrying the URL.
+    pub element: Atom,
+
+    /// Name of the attribute carrying the URL.
+    pub attr: Atom,
+
+    /// The URL, with surrounding whitespace removed but otherwise
+    /// unresolved.
+    pub url: String,
+
+    /// Number of start tags before the element in the document.
+    /// Identifies which element a group of links came from.
+    pub tag_index: uint,
+
+    /// The start tag carrying the URL, if the tokenizer's
+    /// `track_positions` option is set.
+    pub span: Option<Span>,
+}
+
+/// Collects `Link`s from start tags.
+pub struct LinkSink {
+    /// Links found so far, in document order.
+    pub links: Vec<Link>,
+
+    tag_index: uint,
+    next_state: Option<State>,
+    current_span: Option<Span>,
+}
+
+impl Default for LinkSink {
+    fn default() -> LinkSink {
+        LinkSink::new()
+    }
+}
+
+/// How to find URLs in an attribute's value.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum UrlKind {
+    /// The value is one URL, possibly with surrounding whitespace.
+    SingleUrl,
+
+    /// The value is a `srcset`, with a URL in each image candidate.
+    Srcset,
+}
+
+/// Does the attribute `attr` of an HTML element named `element` hold
+/// URLs, and how?
+pub fn url_kind(element: &Atom, attr: &Atom) -> Option<UrlKind> {
+    match (element.as_slice(), attr.as_slice()) {
+        ("a", "href")
+        | ("area", "href")
+        | ("link", "href")
+        | ("base", "href")
+        | ("img", "src")
+        | ("script", "src")
+        | ("iframe", "src")
+        | ("frame", "src")
+        | ("embed", "src")
+        | ("source", "src")
+        | ("track", "src")
+        | ("audio", "src")
+        | ("video", "src")
+        | ("video", "poster")
+        | ("input", "src")
+        | ("object", "data")
+        | ("form", "action")
+        | ("button", "formaction")
+        | ("input", "formaction")
+        | ("blockquote", "cite")
+        | ("q", "cite")
+        | ("del", "cite")
+        | ("ins", "cite")
+        | ("html", "manifest") => Some(SingleUrl),
+
+        ("img", "srcset")
+        | ("source", "srcset") => Some(Srcset),
+
+        _ => None,
+    }
+}
+
+impl LinkSink {
+    pub fn new() -> LinkSink {
+        LinkSink {
+            links: vec!(),
+            tag_index: 0,
+            next_state: None,
+            current_span: None,
+        }
+    }
+
+    fn start_tag(&mut self, tag: Tag) {
+        for attr in tag.attrs.into_iter() {
+            let kind = unwrap_or_else!(url_kind(&tag.name, &attr.name.local), { continue });
+            let urls = match kind {
+                SingleUrl => vec!(attr.value.as_slice().trim_matches(is_ascii_whitespace)),
+                Srcset => parse_srcset(attr.value.as_slice())
+                    .into_iter().map(|c| c.url).collect(),
+            };
+            for url in urls.into_iter() {
+                self.links.push(Link {
+                    element: tag.name.clone(),
+                    attr: attr.name.local.clone(),
+                    url: String::from_str(url),
+                    tag_index: self.tag_index,
+                    span: self.current_span,
+                });
+            }
+        }
+        self.tag_index += 1;
+    }
+}
+
+impl TokenSink for LinkSink {
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
+        match token {
+            TagToken(tag) => if tag.kind == StartTag {
+                self.next_state = raw_text_state(&tag.name);
+                self.start_tag(tag);
+            },
+            _ => (),
+        }
+        Continue
+    }
+
+    fn query_state_change(&mut self) -> Option<State> {
+        self.next_state.take()
+    }
+
+    fn set_current_span(&mut self, span: Span) {
+        self.current_span = Some(span);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+    use core::default::Default;
+
+    use super::LinkSink;
+    use driver::{tokenize_to, one_input};
+    use tokenizer::TokenizerOpts;
+
+    fn urls(input: &str) -> Vec<(String, String, String, uint)> {
+        let sink = tokenize_to(LinkSink::new(), one_input(String::from_str(input)),
+            Default::default());
+        sink.links.into_iter().map(|l| (String::from_str(l.element.as_slice()),
+            String::from_str(l.attr.as_slice()), l.url, l.tag_index)).collect()
+    }
+
+    fn s(x: &str) -> String {
+        String::from_str(x)
+    }
+
+    #[test]
+    fn basic() {
+        assert_eq!(urls("<a href=' /foo '>x</a><p><img src=a.png alt=x>"), vec!(
+            (s("a"), s("href"), s("/foo"), 0),
+            (s("img"), s("src"), s("a.png"), 2)));
+    }
+
+    #[test]
+    fn ignores_raw_text() {
+        assert_eq!(urls("<script src=x.js>document.write('<a href=no>')</script><a href=yes>"),
+            vec!((s("script"), s("src"), s("x.js"), 0), (s("a"), s("href"), s("yes"), 1)));
+    }
+
+    #[test]
+    fn ignores_other_attrs() {
+        assert_eq!(urls("<a title=foo><div href=x>"), vec!());
+    }
+
+    #[test]
+    fn srcset() {
+        assert_eq!(urls("<img srcset='a.png 1x, b.png 2x,c.png'>"), vec!(
+            (s("img"), s("srcset"), s("a.png"), 0),
+            (s("img"), s("srcset"), s("b.png"), 0),
+            (s("img"), s("srcset"), s("c.png"), 0)));
+
+        // Candidates with invalid descriptors are dropped.
+        assert_eq!(urls("<source srcset='a.png (x, y) 1x, b.png'>"), vec!(
+            (s("source"), s("srcset"), s("b.png"), 0)));
+    }
+
+    #[test]
+    fn spans() {
+        let opts = TokenizerOpts {
+            track_positions: true,
+            .. Default::default()
+        };
+        let sink = tokenize_to(LinkSink::new(), one_input(s("<p>\n<a href=x>y</a>")), opts);
+        let span = sink.links[0].span.unwrap();
+        assert_eq!((span.start.offset, span.end.offset), (4, 14));
+        assert_eq!((span.start.line, span.start.column), (2, 1));
+
+        let sink = tokenize_to(LinkSink::new(), one_input(s("<a href=x>")), Default::default());
+        assert_eq!(sink.links[0].span, None);
+    }
+}
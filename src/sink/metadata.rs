@@ -0,0 +1,290 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A `TokenSink` which extracts page metadata from the `<head>`, without
+//! building a DOM or looking at the rest of the document.
+
+use core::prelude::*;
+
+use sink::common::raw_text_state;
+use microsyntax::refresh::{Refresh, parse_refresh};
+use tokenizer::{TokenSink, TokenSinkResult, Continue, Stop};
+use tokenizer::{Token, TagToken, CharacterTokens, StartTag, EndTag, Tag, Attribute};
+use driver::tokenize_to;
+use tokenizer::states::State;
+
+use util::str::{AsciiExt, is_ascii_whitespace};
+
+use core::default::Default;
+use collections::vec::Vec;
+use collections::string::String;
+
+use string_cache::Atom;
+
+/// A `<meta>` element with a `content` attribute.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct MetaTag {
+    /// The `name` attribute, e.g. `description`.
+    pub name: Option<String>,
+
+    /// The `property` attribute, used by Open Graph, e.g. `og:title`.
+    pub property: Option<String>,
+
+    /// The `content` attribute.
+    pub content: String,
+}
+
+/// Metadata found in the `<head>` of a document.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Metadata {
+    /// Text of the first `<title>`, with whitespace stripped and
+    /// collapsed as for `document.title`.
+    pub title: Option<String>,
+
+    /// `<meta>` elements with content, in document order.
+    pub meta: Vec<MetaTag>,
+
+    /// The `href` of the first `<link rel=canonical>`.
+    pub canonical: Option<String>,
+
+    /// The first valid `<meta http-equiv=refresh>`.
+    pub refresh: Option<Refresh>,
+}
+
+/// Collects `Metadata` until the end of the `<head>`.
+pub struct MetadataSink {
+    /// Metadata found so far.
+    pub metadata: Metadata,
+
+    /// Element whose text content we're inside, if any.
+    text_elem: Option<Atom>,
+
+    /// Text of the first `<title>`, while we're inside it.
+    title_buf: Option<String>,
+
+    done: bool,
+    next_state: Option<State>,
+}
+
+impl Default for MetadataSink {
+    fn default() -> MetadataSink {
+        MetadataSink::new()
+    }
+}
+
+fn get_attr<'a>(tag: &'a Tag, name: &str) -> Option<&'a Attribute> {
+    tag.attrs.iter().find(|at| at.name.local.as_slice() == name)
+}
+
+fn attr_string(tag: &Tag, name: &str) -> Option<String> {
+    get_attr(tag, name).map(|at| at.value.clone())
+}
+
+//§ dom-document-title
+fn strip_and_collapse_whitespace(text: &str) -> String {
+    let mut out = String::new();
+    for word in text.split(is_ascii_whitespace).filter(|w| !w.is_empty()) {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out
+}
+//§ END
+
+impl MetadataSink {
+    pub fn new() -> MetadataSink {
+        MetadataSink {
+            metadata: Default::default(),
+            text_elem: None,
+            title_buf: None,
+            done: false,
+            next_state: None,
+        }
+    }
+
+    /// Has the `<head>` ended?  After this, no more metadata will be
+    /// collected, and the sink asks the tokenizer to `Stop`.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn start_tag(&mut self, tag: Tag) {
+        match tag.name {
+            atom!(html) | atom!(head) | atom!(base) | atom!(basefont)
+                | atom!(bgsound) | atom!(template) => (),
+
+            atom!(title) => {
+                if self.metadata.title.is_none() && self.title_buf.is_none() {
+                    self.title_buf = Some(String::new());
+                }
+                self.text_elem = Some(tag.name.clone());
+            }
+
+            atom!(script) | atom!(style) | atom!(noscript) | atom!(noframes) => {
+                self.text_elem = Some(tag.name.clone());
+            }
+
+            atom!(meta) => match attr_string(&tag, "content") {
+                None => (),
+                Some(content) => {
+                    let is_refresh = get_attr(&tag, "http-equiv")
+                        .map_or(false, |h| h.value.as_slice().eq_ignore_ascii_case("refresh"));
+                    if is_refresh && self.metadata.refresh.is_none() {
+                        self.metadata.refresh = parse_refresh(content.as_slice());
+                    }
+                    self.metadata.meta.push(MetaTag {
+                        name: attr_string(&tag, "name"),
+                        property: attr_string(&tag, "property"),
+                        content: content,
+                    });
+                }
+            },
+
+            atom!(link) => {
+                let canonical = get_attr(&tag, "rel").map_or(false, |rel| {
+                    rel.value.as_slice().split(is_ascii_whitespace)
+                        .any(|t| t.eq_ignore_ascii_case("canonical"))
+                });
+                if canonical && self.metadata.canonical.is_none() {
+                    self.metadata.canonical = attr_string(&tag, "href")
+                        .map(|h| String::from_str(h.as_slice().trim_matches(is_ascii_whitespace)));
+                }
+            }
+
+            // Anything else starts the body.
+            _ => self.done = true,
+        }
+    }
+
+    fn end_tag(&mut self, tag: Tag) {
+        if self.text_elem.as_ref() == Some(&tag.name) {
+            self.text_elem = None;
+            match self.title_buf.take() {
+                Some(ref buf) if tag.name == atom!(title) => {
+                    self.metadata.title = Some(strip_and_collapse_whitespace(buf.as_slice()));
+                }
+                buf => self.title_buf = buf,
+            }
+            return;
+        }
+
+        match tag.name {
+            atom!(head) | atom!(body) | atom!(html) => self.done = true,
+            _ => (),
+        }
+    }
+
+    fn characters(&mut self, text: String) {
+        match self.text_elem {
+            Some(atom!(title)) => match self.title_buf {
+                Some(ref mut buf) => buf.push_str(text.as_slice()),
+                None => (),
+            },
+            Some(_) => (),
+            None => if !text.as_slice().chars().all(is_ascii_whitespace) {
+                self.done = true;
+            },
+        }
+    }
+}
+
+impl TokenSink for MetadataSink {
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
+        if self.done {
+            return Stop;
+        }
+
+        match token {
+            TagToken(tag) => match tag.kind {
+                StartTag => {
+                    self.next_state = raw_text_state(&tag.name);
+                    self.start_tag(tag);
+                }
+                EndTag => self.end_tag(tag),
+            },
+            CharacterTokens(text) => self.characters(text),
+            _ => (),
+        }
+
+        if self.done { Stop } else { Continue }
+    }
+
+    fn query_state_change(&mut self) -> Option<State> {
+        self.next_state.take()
+    }
+}
+
+/// Extract metadata from a document, reading input only until the end
+/// of the `<head>`.
+pub fn extract_metadata<It>(input: It) -> Metadata
+    where It: Iterator<Item=String>,
+{
+    tokenize_to(MetadataSink::new(), input, Default::default()).metadata
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::{extract_metadata, Metadata, MetaTag};
+    use microsyntax::refresh::Refresh;
+
+    fn extract(input: &[&str]) -> Metadata {
+        let chunks: Vec<String> = input.iter().map(|&s| String::from_str(s)).collect();
+        extract_metadata(chunks.into_iter())
+    }
+
+    fn s(x: &str) -> String {
+        String::from_str(x)
+    }
+
+    #[test]
+    fn title() {
+        let md = extract(&["<title>\n  Hello &amp;\n world </title><title>Second</title>"]);
+        assert_eq!(md.title, Some(s("Hello & world")));
+    }
+
+    #[test]
+    fn meta_and_canonical() {
+        let md = extract(&["<head><meta name=description content='A page'>",
+            "<meta property=og:title content=Page><meta charset=utf-8>",
+            "<link rel='alternate Canonical' href=' /page '></head>"]);
+        assert_eq!(md.meta, vec!(
+            MetaTag { name: Some(s("description")), property: None, content: s("A page") },
+            MetaTag { name: None, property: Some(s("og:title")), content: s("Page") }));
+        assert_eq!(md.canonical, Some(s("/page")));
+    }
+
+    #[test]
+    fn refresh() {
+        let md = extract(&["<meta http-equiv=Refresh content=nonsense>",
+            "<meta http-equiv=refresh content='5; url=/next'>",
+            "<meta http-equiv=refresh content=0>"]);
+        assert_eq!(md.refresh, Some(Refresh { delay: 5, url: Some(s("/next")) }));
+        assert_eq!(md.meta.len(), 3);
+    }
+
+    #[test]
+    fn stops_at_body() {
+        let md = extract(&["<title>x</title><script>'<meta content=no>'</script>",
+            "<p>Text<meta name=late content=no>"]);
+        assert_eq!(md.title, Some(s("x")));
+        assert!(md.meta.is_empty());
+    }
+
+    #[test]
+    fn stops_at_text() {
+        let md = extract(&["<title>x</title> Hello <meta name=late content=no>"]);
+        assert!(md.meta.is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Sinks which throw everything away, for measuring the cost of the
+//! tokenizer or tree builder alone.
+//!
+//! `NullTokenSink` never changes the tokenizer's state, as a tree
+//! builder would after `<script>` and the like, so the contents of such
+//! elements are tokenized as markup.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! // Tokenizer only.
+//! tokenize_to(NullTokenSink, one_input(my_str), Default::default());
+//!
+//! // Tokenizer and tree builder, without building a tree.
+//! parse_to(NullTreeSink::new(), one_input(my_str), Default::default());
+//! ```
+
+use core::prelude::*;
+
+use tokenizer::{TokenSink, TokenSinkResult, Continue, Token, Attribute};
+use tree_builder::{TreeSink, QuirksMode, NodeOrText};
+use diagnostic::Diagnostic;
+
+use core::default::Default;
+use collections::vec::Vec;
+use collections::string::String;
+
+use string_cache::QualName;
+
+/// A `TokenSink` which drops every token.
+#[derive(Copy, Clone, Default)]
+pub struct NullTokenSink;
+
+impl TokenSink for NullTokenSink {
+    fn process_token(&mut self, _token: Token) -> TokenSinkResult {
+        Continue
+    }
+}
+
+/// A `TreeSink` which builds nothing.  Handles are indices into a table
+/// of element names, which the tree builder needs to ask about; the
+/// document and comments have no name.
+pub struct NullTreeSink {
+    names: Vec<Option<QualName>>,
+}
+
+impl Default for NullTreeSink {
+    fn default() -> NullTreeSink {
+        NullTreeSink::new()
+    }
+}
+
+impl NullTreeSink {
+    pub fn new() -> NullTreeSink {
+        NullTreeSink {
+            names: vec!(None),
+        }
+    }
+
+    fn new_node(&mut self, name: Option<QualName>) -> uint {
+        self.names.push(name);
+        self.names.len() - 1
+    }
+}
+
+impl TreeSink for NullTreeSink {
+    type Handle = uint;
+    type Output = ();
+
+    fn finish(self) { }
+
+    fn report_diagnostic(&mut self, _diagnostic: Diagnostic) { }
+
+    fn get_document(&mut self) -> uint {
+        0
+    }
+
+    fn same_node(&self, x: uint, y: uint) -> bool {
+        x == y
+    }
+
+    fn elem_name(&self, target: uint) -> QualName {
+        self.names[target].clone().expect("not an element")
+    }
+
+    fn set_quirks_mode(&mut self, _mode: QuirksMode) { }
+
+    fn create_element(&mut self, name: QualName, _attrs: Vec<Attribute>) -> uint {
+        self.new_node(Some(name))
+    }
+
+    fn create_comment(&mut self, _text: String) -> uint {
+        self.new_node(None)
+    }
+
+    fn append(&mut self, _parent: uint, _child: NodeOrText<uint>) { }
+
+    // `sibling` always has a parent, since no script can have moved it.
+    fn append_before_sibling(&mut self, _sibling: uint, _new_node: NodeOrText<uint>)
+            -> Result<(), NodeOrText<uint>> {
+        Ok(())
+    }
+
+    fn append_doctype_to_document(&mut self, _name: String, _public_id: String,
+        _system_id: String) { }
+
+    fn add_attrs_if_missing(&mut self, _target: uint, _attrs: Vec<Attribute>) { }
+
+    fn remove_from_parent(&mut self, _target: uint) { }
+
+    fn reparent_children(&mut self, _node: uint, _new_parent: uint) { }
+
+    fn mark_script_already_started(&mut self, _node: uint) { }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::string::String;
+
+    use super::{NullTokenSink, NullTreeSink};
+    use driver::{tokenize_to, parse_to, one_input};
+
+    #[test]
+    fn null_sinks() {
+        let input = "<!DOCTYPE html><table>x<tr><td><a><p>y</a>z<svg><desc><b>";
+        tokenize_to(NullTokenSink, one_input(String::from_str(input)), Default::default());
+        parse_to(NullTreeSink::new(), one_input(String::from_str(input)), Default::default());
+    }
+}
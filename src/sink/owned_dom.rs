@@ -21,13 +21,15 @@
 
 use core::prelude::*;
 
-use sink::common::{NodeEnum, Document, Doctype, Text, Comment, Element};
+use sink::common::{NodeEnum, Document, DocumentFragment, Doctype, Text, Comment, Element};
+use sink::common::{TextMerging, MergeText, dump_node};
 
 use tokenizer::Attribute;
 use tree_builder::{TreeSink, QuirksMode, NodeOrText, AppendNode, AppendText};
 use tree_builder;
-use serialize::{Serializable, Serializer};
+use serialize::{Serializable, Serializer, TraversalScope, IncludeNode, ChildrenOnly};
 use driver::ParseResult;
+use diagnostic::Diagnostic;
 
 use core::cell::UnsafeCell;
 use core::default::Default;
@@ -37,13 +39,14 @@ use core::ptr;
 use alloc::boxed::Box;
 use collections::vec::Vec;
 use collections::string::String;
-use std::string::CowString;
 use std::old_io::{Writer, IoResult};
 use std::collections::HashSet;
 use std::ops::{Deref, DerefMut};
 
 use string_cache::QualName;
 
+use self::SerializeOp::{Open, Close};
+
 /// The internal type we use for nodes during parsing.
 struct SquishyNode {
     node: NodeEnum,
@@ -150,8 +153,12 @@ fn append_to_existing_text(mut prev: Handle, text: &str) -> bool {
 pub struct Sink {
     nodes: Vec<Box<UnsafeCell<SquishyNode>>>,
     document: Handle,
-    errors: Vec<CowString<'static>>,
+    errors: Vec<Diagnostic>,
     quirks_mode: QuirksMode,
+    base_url: Option<String>,
+
+    /// Whether to merge adjacent text while parsing.  Default: `MergeText`
+    pub text_merging: TextMerging,
 }
 
 impl Default for Sink {
@@ -161,6 +168,8 @@ impl Default for Sink {
             document: Handle::null(),
             errors: vec!(),
             quirks_mode: tree_builder::NoQuirks,
+            base_url: None,
+            text_merging: MergeText,
         };
         sink.document = sink.new_node(Document);
         sink
@@ -185,9 +194,59 @@ impl Sink {
 
 impl TreeSink for Sink {
     type Handle = Handle;
+    type Output = OwnedDom;
+
+    fn finish(self) -> OwnedDom {
+        // Collect addresses of all the nodes that made it into the final tree.
+        let mut live = HashSet::new();
+        let mut stack = vec!(self.document);
+        loop {
+            let node = unwrap_or_else!(stack.pop(), { break });
+            live.insert(node.ptr as uint);
+            stack.push_all(node.deref().children.as_slice());
+        }
+
+        // Forget about the nodes in the final tree; they will be owned by
+        // their parent.  In the process of iterating we drop all nodes that
+        // aren't in the tree.
+        for node in self.nodes.into_iter() {
+            let ptr: *const UnsafeCell<SquishyNode> = &*node;
+            if live.contains(&(ptr as uint)) {
+                unsafe {
+                    mem::forget(node);
+                }
+            }
+        }
+
+        let old_addrs = addrs_of!(self.document => node, parent, children);
+
+        // Transmute the root to a Node, finalizing the transfer of ownership.
+        let document = unsafe {
+            mem::transmute::<*const UnsafeCell<SquishyNode>, Box<Node>>(self.document.ptr)
+        };
 
-    fn parse_error(&mut self, msg: CowString<'static>) {
-        self.errors.push(msg);
+        // FIXME: do this assertion statically
+        let new_addrs = addrs_of!(document => node, _parent_not_accessible, children);
+        assert_eq!(old_addrs, new_addrs);
+
+        OwnedDom {
+            document: document,
+            errors: self.errors,
+            quirks_mode: self.quirks_mode,
+            base_url: self.base_url,
+        }
+    }
+
+    fn finish_fragment(mut self) -> OwnedDom {
+        let root = self.document.children[0];
+        let fragment = self.new_node(DocumentFragment);
+        self.reparent_children(root, fragment);
+        self.document = fragment;
+        self.finish()
+    }
+
+    fn report_diagnostic(&mut self, diagnostic: Diagnostic) {
+        self.errors.push(diagnostic);
     }
 
     fn get_document(&mut self) -> Handle {
@@ -198,6 +257,10 @@ impl TreeSink for Sink {
         self.quirks_mode = mode;
     }
 
+    fn set_base_url(&mut self, url: String) {
+        self.base_url = Some(url);
+    }
+
     fn same_node(&self, x: Handle, y: Handle) -> bool {
         x == y
     }
@@ -221,7 +284,10 @@ impl TreeSink for Sink {
         // Append to an existing Text node if we have one.
         match child {
             AppendText(ref text) => match parent.children.last() {
-                Some(h) => if append_to_existing_text(*h, text.as_slice()) { return; },
+                Some(h) => if self.text_merging == MergeText
+                        && append_to_existing_text(*h, text.as_slice()) {
+                    return;
+                },
                 _ => (),
             },
             _ => (),
@@ -245,7 +311,7 @@ impl TreeSink for Sink {
             // Look for a text node before the insertion point.
             (AppendText(text), i) => {
                 let prev = parent.children[i-1];
-                if append_to_existing_text(prev, text.as_slice()) {
+                if self.text_merging == MergeText && append_to_existing_text(prev, text.as_slice()) {
                     return Ok(());
                 }
                 self.new_node(Text(text))
@@ -288,6 +354,10 @@ impl TreeSink for Sink {
     }
 
     fn reparent_children(&mut self, mut node: Handle, mut new_parent: Handle) {
+        for &child in node.children.iter() {
+            let mut child = child;
+            child.parent = new_parent;
+        }
         new_parent.children.append(&mut node.children);
     }
 
@@ -302,92 +372,164 @@ pub struct Node {
 
 pub struct OwnedDom {
     pub document: Box<Node>,
-    pub errors: Vec<CowString<'static>>,
+    pub errors: Vec<Diagnostic>,
     pub quirks_mode: QuirksMode,
+    pub base_url: Option<String>,
 }
 
 impl ParseResult for OwnedDom {
     type Sink = Sink;
+}
 
-    fn get_result(sink: Sink) -> OwnedDom {
-        fn walk(live: &mut HashSet<uint>, node: Handle) {
-            live.insert(node.ptr as uint);
-            for &child in node.deref().children.iter() {
-                walk(live, child);
-            }
-        }
-
-        // Collect addresses of all the nodes that made it into the final tree.
-        let mut live = HashSet::new();
-        walk(&mut live, sink.document);
-
-        // Forget about the nodes in the final tree; they will be owned by
-        // their parent.  In the process of iterating we drop all nodes that
-        // aren't in the tree.
-        for node in sink.nodes.into_iter() {
-            let ptr: *const UnsafeCell<SquishyNode> = &*node;
-            if live.contains(&(ptr as uint)) {
-                unsafe {
-                    mem::forget(node);
-                }
-            }
+/// Frees the tree a node at a time.  A `Drop` impl on `Node` would add
+/// a drop flag to it, and its layout must match `SquishyNode`'s, so a
+/// `Node` taken out of the tree is still freed recursively.
+impl Drop for OwnedDom {
+    fn drop(&mut self) {
+        let mut stack = mem::replace(&mut self.document.children, vec!());
+        loop {
+            let mut node = unwrap_or_else!(stack.pop(), { break });
+            stack.extend(mem::replace(&mut node.children, vec!()).into_iter());
         }
+    }
+}
 
-        let old_addrs = addrs_of!(sink.document => node, parent, children);
+/// Deep-copy a node and its descendants.
+pub fn clone_subtree(node: &Node) -> Box<Node> {
+    let mut copy = box Node {
+        node: node.node.clone(),
+        _parent_not_accessible: 0,
+        children: Vec::with_capacity(node.children.len()),
+    };
+    let parent = &*copy as *const Node as uint;
+    for child in node.children.iter() {
+        let mut child = clone_subtree(&**child);
+        child._parent_not_accessible = parent;
+        copy.children.push(child);
+    }
+    copy
+}
 
-        // Transmute the root to a Node, finalizing the transfer of ownership.
-        let document = unsafe {
-            mem::transmute::<*const UnsafeCell<SquishyNode>, Box<Node>>(sink.document.ptr)
+/// Dump a node and its descendants in the html5lib test format, as
+/// described for `sink::common::dump_node`.  A `Document` or
+/// `DocumentFragment` dumps its children.
+pub fn dump_tree(node: &Node) -> String {
+    let mut out = String::new();
+    let mut stack = vec!((node, 0u));
+    loop {
+        let (node, depth) = unwrap_or_else!(stack.pop(), { break });
+        dump_node(&mut out, depth, &node.node);
+        let depth = match node.node {
+            Document | DocumentFragment => depth,
+            _ => depth + 1,
         };
-
-        // FIXME: do this assertion statically
-        let new_addrs = addrs_of!(document => node, _parent_not_accessible, children);
-        assert_eq!(old_addrs, new_addrs);
-
-        OwnedDom {
-            document: document,
-            errors: sink.errors,
-            quirks_mode: sink.quirks_mode,
-        }
+        stack.extend(node.children.iter().rev().map(|c| (&**c, depth)));
     }
+    out
+}
+
+// A step in serializing a subtree.
+enum SerializeOp<'a> {
+    Open(&'a Node),
+    Close(&'a QualName),
 }
 
 impl Serializable for Node {
     fn serialize<'wr, Wr: Writer>(&self,
             serializer: &mut Serializer<'wr, Wr>,
-            incl_self: bool) -> IoResult<()> {
-
-        match (incl_self, &self.node) {
-            (_, &Element(ref name, ref attrs)) => {
-                if incl_self {
-                    try!(serializer.start_elem(name.clone(),
-                        attrs.iter().map(|at| (&at.name, at.value.as_slice()))));
-                }
+            traversal_scope: TraversalScope) -> IoResult<()> {
 
-                for child in self.children.iter() {
-                    try!(child.serialize(serializer, true));
-                }
-
-                if incl_self {
-                    try!(serializer.end_elem(name.clone()));
-                }
-                Ok(())
-            }
+        // The Document has no markup of its own, so both scopes produce
+        // its children.  Likewise a DocumentFragment.
+        let mut ops = match traversal_scope {
+            IncludeNode => vec!(Open(self)),
+            ChildrenOnly => self.children.iter().rev().map(|c| Open(&**c)).collect(),
+        };
 
-            (false, &Document) => {
-                for child in self.children.iter() {
-                    try!(child.serialize(serializer, true));
+        loop {
+            match unwrap_or_else!(ops.pop(), { break }) {
+                Open(node) => {
+                    match node.node {
+                        Element(ref name, ref attrs) => {
+                            try!(serializer.start_elem(name.clone(),
+                                attrs.iter().map(|at| (&at.name, at.value.as_slice()))));
+                            ops.push(Close(name));
+                        }
+                        Document | DocumentFragment => (),
+                        Doctype(ref name, _, _) => try!(serializer.write_doctype(name.as_slice())),
+                        Text(ref text) => try!(serializer.write_text(text.as_slice())),
+                        Comment(ref text) => try!(serializer.write_comment(text.as_slice())),
+                    }
+                    ops.extend(node.children.iter().rev().map(|c| Open(&**c)));
                 }
-                Ok(())
+                Close(name) => try!(serializer.end_elem(name.clone())),
             }
+        }
+        Ok(())
+    }
+}
 
-            (false, _) => Ok(()),
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::string::String;
+
+    use super::{Sink, OwnedDom, dump_tree};
+    use sink::visit::{Visit, Visitable};
+    use sink::parallel::subtrees;
+    use tree_builder::{TreeSink, AppendNode, AppendText};
+    use serialize::serialize_to_string;
+    use tokenizer::Attribute;
+
+    use string_cache::QualName;
+
+    // `depth` nested `<div>`s around a text node.
+    fn deep_dom(depth: uint) -> OwnedDom {
+        let mut sink = Sink::default();
+        let mut parent = sink.get_document();
+        for _ in 0..depth {
+            let div = sink.create_element(qualname!(HTML, div), vec!());
+            sink.append(parent, AppendNode(div));
+            parent = div;
+        }
+        sink.append(parent, AppendText(String::from_str("x")));
+        sink.finish()
+    }
 
-            (true, &Doctype(ref name, _, _)) => serializer.write_doctype(name.as_slice()),
-            (true, &Text(ref text)) => serializer.write_text(text.as_slice()),
-            (true, &Comment(ref text)) => serializer.write_comment(text.as_slice()),
+    struct CountElements(uint);
 
-            (true, &Document) => panic!("Can't serialize Document node itself"),
+    impl Visit for CountElements {
+        fn enter_element(&mut self, _name: &QualName, _attrs: &[Attribute]) -> bool {
+            self.0 += 1;
+            true
         }
     }
+
+    #[test]
+    fn deep_tree() {
+        let depth = 100000;
+        let dom = deep_dom(depth);
+
+        let html = serialize_to_string(&*dom.document, Default::default());
+        assert_eq!(html.len(), depth * "<div></div>".len() + 1);
+        assert!(html.ends_with("<div>x</div></div>"));
+
+        let mut count = CountElements(0);
+        dom.document.visit(&mut count);
+        assert_eq!(count.0, depth);
+
+        // Only the nine deepest <div>s and the text fit in a part.
+        let parts = subtrees(&*dom.document, 10);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(dump_tree(parts[0]).lines().count(), 10);
+
+        // Dropping the tree mustn't recurse once per level either.
+        drop(dom);
+
+        // Dumps indent each level, so their size is quadratic in the
+        // depth.
+        let dump = dump_tree(&*deep_dom(10000).document);
+        assert_eq!(dump.lines().count(), 10001);
+    }
 }
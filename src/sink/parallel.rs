@@ -0,0 +1,163 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Running a `Visit` over a large `OwnedDom` on several tasks.
+//!
+//! The tree is split into independent subtrees of bounded size, which
+//! are divided among the tasks in document order.  Each task visits its
+//! subtrees with a fresh visitor, and the visitors are merged in order.
+//!
+//! A node which is too big to be a subtree by itself is split into its
+//! children, and no visitor sees that node: `enter_element` and
+//! `leave_element` are not called for it.  An analysis which counts
+//! elements should use a `max_nodes` bigger than the interesting
+//! elements, and one which needs every ancestor of a node should walk
+//! the tree on one task.
+//!
+//! An `RcDom` can't be shared between tasks, so this works only on
+//! `OwnedDom`.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let count = visit_parallel(&*dom.document, 10000, 4,
+//!     || CountLinks(0), |a, b| CountLinks(a.0 + b.0));
+//! ```
+
+use core::prelude::*;
+
+use sink::owned_dom::Node;
+use sink::visit::{Visit, Visitable};
+
+use collections::vec::Vec;
+use std::thread::Thread;
+
+// Append the subtrees of `root` to `out`.  Each node is finished after
+// its children, and one which is small enough replaces the subtrees
+// they appended.
+fn split<'a>(root: &'a Node, max_nodes: uint, out: &mut Vec<&'a Node>) {
+    // The open nodes, each with the index of its next child, the length
+    // of `out` when it was opened, and its size so far.
+    let mut stack = vec!((root, 0u, out.len(), 1u));
+    loop {
+        let (node, next, mark, size) = unwrap_or_else!(stack.pop(), { break });
+        if next < node.children.len() {
+            stack.push((node, next + 1, mark, size));
+            stack.push((&*node.children[next], 0, out.len(), 1));
+            continue;
+        }
+
+        if size <= max_nodes {
+            out.truncate(mark);
+            out.push(node);
+        }
+        match stack.last_mut() {
+            Some(parent) => parent.3 += size,
+            None => (),
+        }
+    }
+}
+
+/// Split the tree below `root` into subtrees of at most `max_nodes`
+/// nodes each, in document order.  Nodes which have too many
+/// descendants are left out, but all of their descendants are in some
+/// subtree.  `root` itself is the only subtree if it's small enough.
+pub fn subtrees<'a>(root: &'a Node, max_nodes: uint) -> Vec<&'a Node> {
+    let mut out = vec!();
+    split(root, max_nodes, &mut out);
+    out
+}
+
+/// Visit `parts` on up to `tasks` tasks, each with a visitor made by
+/// `new`, and combine the visitors in document order with `merge`.
+pub fn visit_parts<V, F, M>(parts: &[&Node], tasks: uint, new: F, mut merge: M) -> V
+    where V: Visit + Send,
+          F: Fn() -> V + Sync,
+          M: FnMut(V, V) -> V,
+{
+    assert!(tasks > 0, "no tasks to run");
+    if parts.is_empty() {
+        return new();
+    }
+
+    let per_task = (parts.len() + tasks - 1) / tasks;
+    let new = &new;
+    let guards: Vec<_> = parts.chunks(per_task).map(|chunk| Thread::scoped(move || {
+        let mut visitor = new();
+        for part in chunk.iter() {
+            part.visit(&mut visitor);
+        }
+        visitor
+    })).collect();
+
+    let mut result = None;
+    for guard in guards.into_iter() {
+        let visitor = guard.join().ok().expect("visitor task panicked");
+        result = Some(match result {
+            None => visitor,
+            Some(so_far) => merge(so_far, visitor),
+        });
+    }
+    result.expect("no visitors")
+}
+
+/// Split the tree below `root` with `subtrees` and visit the parts
+/// with `visit_parts`.
+pub fn visit_parallel<V, F, M>(root: &Node, max_nodes: uint, tasks: uint, new: F, merge: M) -> V
+    where V: Visit + Send,
+          F: Fn() -> V + Sync,
+          M: FnMut(V, V) -> V,
+{
+    visit_parts(subtrees(root, max_nodes).as_slice(), tasks, new, merge)
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::string::String;
+
+    use super::{subtrees, visit_parallel};
+    use sink::owned_dom::OwnedDom;
+    use sink::visit::{Visit, Visitable};
+    use driver::{parse, one_input};
+
+    struct Text(String);
+
+    impl Visit for Text {
+        fn text(&mut self, text: &str) {
+            self.0.push_str(text);
+        }
+    }
+
+    #[test]
+    fn parallel_text() {
+        let mut input = String::new();
+        for i in 0..200u {
+            input.push_str(format!("<div><p>{}<b>,</b></p><!--x--></div>", i).as_slice());
+        }
+        let dom: OwnedDom = parse(one_input(input), Default::default());
+
+        let mut expected = Text(String::new());
+        dom.document.visit(&mut expected);
+
+        for &(max_nodes, tasks) in [(1u, 1u), (5, 3), (50, 4), (100000, 2)].iter() {
+            let parts = subtrees(&*dom.document, max_nodes);
+            assert!(parts.len() > 0);
+            let text = visit_parallel(&*dom.document, max_nodes, tasks,
+                || Text(String::new()), |mut a, b| { a.0.push_str(b.0.as_slice()); a });
+            assert_eq!(text.0, expected.0);
+        }
+
+        // The empty <head>, and each <div> with its <p>, text, <b>, text
+        // and comment.
+        assert_eq!(subtrees(&*dom.document, 6).len(), 201);
+        assert_eq!(subtrees(&*dom.document, 100000).len(), 1);
+    }
+}
@@ -0,0 +1,451 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Matching the structure of an `RcDom` subtree against a pattern,
+//! binding attribute values and text, for scraping.
+//!
+//! A pattern is an element, written as its name (optionally quoted) or
+//! `*`, followed by any number of attribute tests in brackets and then,
+//! optionally, patterns for its children in braces:
+//!
+//! * `[href]` requires an attribute, `[rel="next"]` requires a value,
+//!   and `[href=?url]` binds the value to `url`.
+//! * `text()` matches a text node, `text("x")` one with that content,
+//!   and `text(?t)` binds its content to `t`.  `comment(...)` is the
+//!   same for comments.  An element named `text` must be quoted.
+//! * Child patterns match children in order, but other children may
+//!   come before, between and after them.
+//!
+//! A name bound twice must have the same value both times.  Names are
+//! compared ignoring ASCII case.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let pattern = Pattern::parse("li { a[href=?url] { text(?title) } }").unwrap();
+//! for (_, captures) in pattern.find_all(&dom.document).into_iter() {
+//!     println!("{:?} {:?}", captures.get("title"), captures.get("url"));
+//! }
+//! ```
+
+use core::prelude::*;
+
+use sink::common::{Text, Comment, Element};
+use sink::rcdom::Handle;
+
+use util::str::AsciiExt;
+
+use collections::vec::Vec;
+use collections::string::String;
+use std::collections::HashMap;
+
+use self::Value::{AnyValue, Literal, Capture};
+use self::NodePattern::{ElementPattern, TextPattern, CommentPattern};
+
+/// An error in a pattern.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PatternError {
+    /// Byte offset of the error.
+    pub position: uint,
+
+    /// What was wrong.
+    pub message: &'static str,
+}
+
+/// The values bound by a successful match, by name.
+pub type Captures = HashMap<String, String>;
+
+#[derive(Clone, Debug)]
+enum Value {
+    AnyValue,
+    Literal(String),
+    Capture(String),
+}
+
+#[derive(Clone, Debug)]
+struct AttrPattern {
+    name: String,
+    value: Value,
+}
+
+#[derive(Clone, Debug)]
+enum NodePattern {
+    ElementPattern {
+        /// `None` for `*`.
+        name: Option<String>,
+        attrs: Vec<AttrPattern>,
+        children: Vec<NodePattern>,
+
+        /// Does this pattern, or a child pattern, bind any names?
+        binds: bool,
+    },
+    TextPattern(Value),
+    CommentPattern(Value),
+}
+
+/// A parsed pattern.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    root: NodePattern,
+}
+
+struct PatternParser<'a> {
+    input: &'a str,
+    pos: uint,
+}
+
+impl<'a> PatternParser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn error<T>(&self, message: &'static str) -> Result<T, PatternError> {
+        Err(PatternError {
+            position: self.pos,
+            message: message,
+        })
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => self.pos += c.len_utf8(),
+                _ => return,
+            }
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> Result<&'a str, PatternError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(c) if c.is_alphanumeric() || c == '-' || c == '_' || (c as u32) >= 0x80
+                    => self.pos += c.len_utf8(),
+                _ => break,
+            }
+        }
+        if self.pos == start {
+            return self.error("Expected a name");
+        }
+        Ok(&self.input[start..self.pos])
+    }
+
+    fn string(&mut self) -> Result<String, PatternError> {
+        let q = self.peek().expect("string without a quote");
+        self.pos += 1;
+        let rest = &self.input[self.pos..];
+        let end = unwrap_or_else!(rest.find(q), { return self.error("Unterminated string") });
+        self.pos += end + 1;
+        Ok(String::from_str(&rest[..end]))
+    }
+
+    // A value after `=` or in `text(...)`, where `empty` is what to
+    // return if there is none.
+    fn value(&mut self, empty: Option<Value>) -> Result<Value, PatternError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('?') => {
+                self.pos += 1;
+                Ok(Capture(String::from_str(try!(self.ident()))))
+            }
+            Some('"') | Some('\'') => Ok(Literal(try!(self.string()))),
+            _ => match empty {
+                Some(value) => Ok(value),
+                None => self.error("Expected a value"),
+            },
+        }
+    }
+
+    fn attr(&mut self) -> Result<AttrPattern, PatternError> {
+        let name = String::from_str(try!(self.ident()));
+        let value = if self.eat('=') {
+            try!(self.value(None))
+        } else {
+            AnyValue
+        };
+        if !self.eat(']') {
+            return self.error("Expected ']'");
+        }
+        Ok(AttrPattern {
+            name: name,
+            value: value,
+        })
+    }
+
+    fn node(&mut self) -> Result<NodePattern, PatternError> {
+        self.skip_whitespace();
+        let name = match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                None
+            }
+            Some('"') | Some('\'') => Some(try!(self.string())),
+            _ => {
+                let name = try!(self.ident());
+                let is_test = name == "text" || name == "comment";
+                if is_test && self.eat('(') {
+                    let value = try!(self.value(Some(AnyValue)));
+                    if !self.eat(')') {
+                        return self.error("Expected ')'");
+                    }
+                    return Ok(if name == "text" {
+                        TextPattern(value)
+                    } else {
+                        CommentPattern(value)
+                    });
+                }
+                Some(String::from_str(name))
+            }
+        };
+
+        let mut attrs = vec!();
+        while self.eat('[') {
+            attrs.push(try!(self.attr()));
+        }
+
+        let mut children = vec!();
+        if self.eat('{') {
+            while !self.eat('}') {
+                if self.peek().is_none() {
+                    return self.error("Expected '}'");
+                }
+                children.push(try!(self.node()));
+            }
+        }
+
+        let any_binds = attrs.iter().any(|a| is_capture(&a.value)) || children.iter().any(binds);
+        Ok(ElementPattern {
+            name: name,
+            attrs: attrs,
+            children: children,
+            binds: any_binds,
+        })
+    }
+}
+
+fn is_capture(value: &Value) -> bool {
+    match *value {
+        Capture(_) => true,
+        _ => false,
+    }
+}
+
+// Does matching `pattern` bind any names?
+fn binds(pattern: &NodePattern) -> bool {
+    match *pattern {
+        ElementPattern { binds, .. } => binds,
+        TextPattern(ref value) | CommentPattern(ref value) => is_capture(value),
+    }
+}
+
+// Bind or compare `value` against `actual`.
+fn match_value(value: &Value, actual: &str, captures: &mut Vec<(String, String)>) -> bool {
+    match *value {
+        AnyValue => true,
+        Literal(ref s) => s.as_slice() == actual,
+        Capture(ref name) => {
+            match captures.iter().find(|&&(ref n, _)| n == name) {
+                Some(&(_, ref v)) => return v.as_slice() == actual,
+                None => (),
+            }
+            captures.push((name.clone(), String::from_str(actual)));
+            true
+        }
+    }
+}
+
+fn match_node(pattern: &NodePattern, handle: &Handle,
+        captures: &mut Vec<(String, String)>) -> bool {
+    let node = handle.borrow();
+    let matched = match (pattern, &node.node) {
+        (&TextPattern(ref value), &Text(ref text))
+            => match_value(value, text.as_slice(), captures),
+        (&CommentPattern(ref value), &Comment(ref text))
+            => match_value(value, text.as_slice(), captures),
+        (&ElementPattern { ref name, ref attrs, ref children, .. },
+                &Element(ref elem_name, ref elem_attrs)) => {
+            match *name {
+                Some(ref name)
+                    if !name.as_slice().eq_ignore_ascii_case(elem_name.local.as_slice())
+                    => return false,
+                _ => (),
+            }
+            for test in attrs.iter() {
+                let attr = elem_attrs.iter().find(|a|
+                    a.name.local.as_slice().eq_ignore_ascii_case(test.name.as_slice()));
+                let attr = unwrap_or_return!(attr, false);
+                if !match_value(&test.value, attr.value.as_slice(), captures) {
+                    return false;
+                }
+            }
+            match_children(children.as_slice(), node.children.as_slice(), captures)
+        }
+        _ => false,
+    };
+    matched
+}
+
+// Match `patterns` against a subsequence of `children`.
+//
+// A pattern which binds no names is matched to the first child it
+// matches: the later patterns then have as many children left as they
+// could have, and nothing they bind depends on the choice.  A pattern
+// which binds names may match several children with different values,
+// and a later pattern may only match given some of them, so each child
+// is tried in turn.  That backtracking is exponential in the number of
+// binding child patterns in the worst case, so patterns with many
+// captures among the children of one element may be slow on large
+// documents.
+fn match_children(patterns: &[NodePattern], children: &[Handle],
+        captures: &mut Vec<(String, String)>) -> bool {
+    if patterns.is_empty() {
+        return true;
+    }
+    if !binds(&patterns[0]) {
+        let i = unwrap_or_return!(
+            children.iter().position(|c| match_node(&patterns[0], c, captures)), false);
+        return match_children(&patterns[1..], &children[i+1..], captures);
+    }
+    for i in 0..children.len() {
+        let mark = captures.len();
+        if match_node(&patterns[0], &children[i], captures)
+            && match_children(&patterns[1..], &children[i+1..], captures) {
+            return true;
+        }
+        captures.truncate(mark);
+    }
+    false
+}
+
+impl Pattern {
+    /// Parse a pattern.
+    pub fn parse(input: &str) -> Result<Pattern, PatternError> {
+        let mut parser = PatternParser {
+            input: input,
+            pos: 0,
+        };
+        let root = try!(parser.node());
+        parser.skip_whitespace();
+        if parser.peek().is_some() {
+            return parser.error("Expected the end of the pattern");
+        }
+        Ok(Pattern {
+            root: root,
+        })
+    }
+
+    /// Match `node` itself against the pattern.
+    pub fn matches(&self, node: &Handle) -> Option<Captures> {
+        let mut captures = vec!();
+        if match_node(&self.root, node, &mut captures) {
+            Some(captures.into_iter().collect())
+        } else {
+            None
+        }
+    }
+
+    /// Match `root` and each of its descendants, in document order,
+    /// returning those which match.
+    pub fn find_all(&self, root: &Handle) -> Vec<(Handle, Captures)> {
+        let mut found = vec!();
+        let mut stack = vec!(root.clone());
+        loop {
+            let node = unwrap_or_else!(stack.pop(), { break });
+            match self.matches(&node) {
+                Some(captures) => found.push((node.clone(), captures)),
+                None => (),
+            }
+            stack.extend(node.borrow().children.iter().rev().map(|c| c.clone()));
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::{Pattern, PatternError};
+    use sink::rcdom::RcDom;
+    use driver::parse_str;
+
+    // Check the values bound to `name` in each match.
+    fn check(input: &str, pattern: &str, name: &str, expected: &[&str]) {
+        let dom: RcDom = parse_str(input);
+        let found: Vec<String> = Pattern::parse(pattern).unwrap().find_all(&dom.document)
+            .into_iter().map(|(_, captures)| {
+                captures.get(&String::from_str(name)).expect("name not bound").clone()
+            }).collect();
+        assert_eq!(found.iter().map(|s| s.as_slice()).collect::<Vec<&str>>(), expected);
+    }
+
+    #[test]
+    fn scrape() {
+        let input = "<ul><li><a href=/a>A</a><li><a>no href</a>\
+            <li><b>x</b><a href='/c' rel=next>C</a></ul><svg><text>t</text></svg><!--c-->";
+        check(input, "li { a[href=?url] { text(?t) } }", "url", &["/a", "/c"]);
+        check(input, "li{a[href=?url]{text(?t)}}", "t", &["A", "C"]);
+        check(input, "A[REL=\"next\"][href=?u]", "u", &["/c"]);
+        check(input, "li { b a[href=?u] }", "u", &["/c"]);
+        check(input, "li { a b }", "u", &[]);
+        check(input, "\"text\" { text(?t) }", "t", &["t"]);
+        check(input, "* { comment(?c) }", "c", &["c"]);
+    }
+
+    #[test]
+    fn same_name_twice() {
+        let input = "<p title=x>x</p><p title=x>y</p>";
+        check(input, "p[title=?v] { text(?v) }", "v", &["x"]);
+    }
+
+    #[test]
+    fn many_children() {
+        // Without greedy matching this would try every way of choosing
+        // twenty of the <p>s.
+        let mut input = String::from_str("<div>");
+        for _ in 0..200u {
+            input.push_str("<p>x</p>");
+        }
+        let mut pattern = String::from_str("div[id=?id] {");
+        for _ in 0..20u {
+            pattern.push_str(" p");
+        }
+        pattern.push_str(" q }");
+        check(input.as_slice(), pattern.as_slice(), "id", &[]);
+        check("<div id=a><p>x</p><p>y</p><q>z</q></div>", pattern.as_slice(), "id", &[]);
+        check("<div id=a><p>x</p><p>y</p><q>z</q></div>", "div[id=?id] { p p q }", "id", &["a"]);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |p: &str| Pattern::parse(p).err().map(|e| e.message);
+        assert_eq!(Pattern::parse("a[href"),
+            Err(PatternError { position: 6, message: "Expected ']'" }));
+        assert_eq!(error("a[href=]"), Some("Expected a value"));
+        assert_eq!(error("a { text(?t }"), Some("Expected ')'"));
+        assert_eq!(error("a { b"), Some("Expected '}'"));
+        assert_eq!(error("a b"), Some("Expected the end of the pattern"));
+        assert_eq!(error("a[title='x]"), Some("Unterminated string"));
+        assert!(Pattern::parse(" li { a[href=?url] { text(?t) } } ").is_ok());
+        assert!(Pattern::parse("\u{3000}li\u{a0}{ a }\u{2028}").is_ok());
+    }
+}
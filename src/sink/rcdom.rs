@@ -11,28 +11,44 @@
 //!
 //! This is sufficient as a static parse tree, but don't build a
 //! web browser using it. :)
+//!
+//! Children are held by strong references and parents by weak ones, so
+//! there are no cycles: dropping the `RcDom` frees the whole tree,
+//! unless you keep a `Handle` to some node, which keeps that node and
+//! its descendants alive.
 
 use core::prelude::*;
 
-use sink::common::{NodeEnum, Document, Doctype, Text, Comment, Element};
+use sink::common::{NodeEnum, Document, DocumentFragment, Doctype, Text, Comment, Element};
+use sink::common::{TextMerging, MergeText, Direction, Ltr, Rtl, strong_direction, dump_node};
 
-use tokenizer::Attribute;
-use tree_builder::{TreeSink, QuirksMode, NodeOrText, AppendNode, AppendText};
+use tokenizer::{Attribute, Pos, Span};
+use tree_builder::{TreeSink, QuirksMode, NodeOrText, AppendNode, AppendText, RecoveryPolicy};
 use tree_builder;
-use serialize::{Serializable, Serializer};
+use serialize::{Serializable, Serializer, TraversalScope, IncludeNode, ChildrenOnly};
 use driver::ParseResult;
+use diagnostic::Diagnostic;
+
+use util::str::{AsciiExt, is_ascii_whitespace};
 
 use core::cell::RefCell;
+use core::{fmt, mem};
 use core::default::Default;
+use core::hash::{Hash, Hasher};
+use alloc::rc;
 use alloc::rc::{Rc, Weak};
+use alloc::boxed::Box;
 use collections::vec::Vec;
 use collections::string::String;
-use std::string::CowString;
 use std::old_io::{Writer, IoResult};
-use std::ops::DerefMut;
+use std::ops::{Deref, DerefMut};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry::{Occupied, Vacant};
 
 use string_cache::QualName;
 
+use self::SerializeOp::{Open, Close};
+
 /// A DOM node.
 pub struct Node {
     pub node: NodeEnum,
@@ -43,6 +59,15 @@ pub struct Node {
     ///
     /// Not meaningful for nodes other than HTML `<script>`.
     pub script_already_started: bool,
+
+    /// Where the node came from in the input, if the tokenizer's
+    /// `track_positions` option was set.
+    ///
+    /// For an element this runs from the start of its start tag to the
+    /// end of its end tag, or to wherever the end tag was implied.  An
+    /// element still open at the end of input, or created by the parser
+    /// without a start tag, has the span of the token which created it.
+    pub span: Option<Span>,
 }
 
 impl Node {
@@ -52,23 +77,157 @@ impl Node {
             parent: None,
             children: vec!(),
             script_already_started: false,
+            span: None,
+        }
+    }
+
+    /// Show this node for debugging, with its descendants down to
+    /// `depth` levels below it, one per line.  Deeper nodes are left
+    /// out, but each node shows how many children it has.  Parents are
+    /// never followed.
+    ///
+    /// `{:?}` on a `Node` is the same as `debug_depth(0)`.
+    pub fn debug_depth<'a>(&'a self, depth: uint) -> DebugNode<'a> {
+        DebugNode {
+            node: self,
+            depth: depth,
+        }
+    }
+}
+
+/// Shows a node and its descendants to a limited depth, for debugging.
+/// Made by `Node::debug_depth`.
+pub struct DebugNode<'a> {
+    node: &'a Node,
+    depth: uint,
+}
+
+// Show one node, without its descendants.
+fn fmt_one(f: &mut fmt::Formatter, node: &Node, indent: uint) -> fmt::Result {
+    for _ in range(0, indent) {
+        try!(f.write_str("  "));
+    }
+    match node.node {
+        Document => try!(f.write_str("Document")),
+        DocumentFragment => try!(f.write_str("DocumentFragment")),
+        Doctype(ref name, _, _) => try!(write!(f, "Doctype {}", name)),
+        Text(ref text) => try!(write!(f, "Text {:?}", text)),
+        Comment(ref text) => try!(write!(f, "Comment {:?}", text)),
+        Element(ref name, ref attrs) => {
+            let local = name.local.as_slice();
+            try!(match name.ns {
+                ns!(HTML) => write!(f, "<{}>", local),
+                ns!(SVG) => write!(f, "<svg:{}>", local),
+                ns!(MathML) => write!(f, "<math:{}>", local),
+                ref ns => write!(f, "<{{{}}}{}>", ns.0.as_slice(), local),
+            });
+            if !attrs.is_empty() {
+                try!(write!(f, " ({} attrs)", attrs.len()));
+            }
         }
     }
+    if !node.children.is_empty() {
+        try!(write!(f, " [{} children]", node.children.len()));
+    }
+    Ok(())
+}
+
+fn fmt_node(f: &mut fmt::Formatter, node: &Node, depth: uint) -> fmt::Result {
+    try!(fmt_one(f, node, 0));
+    if depth == 0 {
+        return Ok(());
+    }
+
+    // Each node still to show, with its depth below `node`.
+    let mut stack: Vec<(Handle, uint)> =
+        node.children.iter().rev().map(|c| (c.clone(), 1)).collect();
+    loop {
+        let (handle, indent) = unwrap_or_else!(stack.pop(), { break });
+        let node = handle.borrow();
+        try!(f.write_str("\n"));
+        try!(fmt_one(f, &*node, indent));
+        if indent < depth {
+            stack.extend(node.children.iter().rev().map(|c| (c.clone(), indent + 1)));
+        }
+    }
+    Ok(())
+}
+
+impl<'a> fmt::Debug for DebugNode<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_node(f, self.node, self.depth)
+    }
+}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_node(f, self, 0)
+    }
 }
 
 /// Reference to a DOM node.
-pub type Handle = Rc<RefCell<Node>>;
+///
+/// Handles are equal, and hash alike, exactly when they refer to the
+/// same node, so they can be kept in a `HashSet`.  Neither borrows the
+/// node.
+#[derive(Clone)]
+pub struct Handle(pub Rc<RefCell<Node>>);
+
+impl Deref for Handle {
+    type Target = Rc<RefCell<Node>>;
+    fn deref<'a>(&'a self) -> &'a Rc<RefCell<Node>> {
+        &self.0
+    }
+}
+
+impl PartialEq for Handle {
+    fn eq(&self, other: &Handle) -> bool {
+        node_id(self) == node_id(other)
+    }
+}
+
+impl Eq for Handle { }
+
+impl Hash for Handle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        node_id(self).hash(state)
+    }
+}
 
 /// Weak reference to a DOM node, used for parent pointers.
 pub type WeakHandle = Weak<RefCell<Node>>;
 
+/// Frees the descendants no other handle refers to.
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut stack = mem::replace(&mut self.children, vec!());
+        loop {
+            let handle = unwrap_or_else!(stack.pop(), { break });
+            if rc::strong_count(&handle.0) == 1 {
+                stack.extend(mem::replace(&mut handle.borrow_mut().children, vec!()).into_iter());
+            }
+        }
+    }
+}
+
+/// The identity of a node, for sets and maps of nodes such as the
+/// visited set of a traversal.  Handles have the same `NodeId` exactly
+/// when they refer to the same node.  Once a node has been freed its id
+/// may be reused, so keep a `Handle` as well if that matters.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug)]
+pub struct NodeId(uint);
+
+/// The identity of the node a handle refers to.
+pub fn node_id(handle: &Handle) -> NodeId {
+    NodeId(&*handle.0 as *const RefCell<Node> as uint)
+}
+
 fn same_node(x: &Handle, y: &Handle) -> bool {
-    // FIXME: This shouldn't really need to touch the borrow flags, right?
-    (&*x.borrow() as *const Node) == (&*y.borrow() as *const Node)
+    node_id(x) == node_id(y)
 }
 
 fn new_node(node: NodeEnum) -> Handle {
-    Rc::new(RefCell::new(Node::new(node)))
+    Handle(Rc::new(RefCell::new(Node::new(node))))
 }
 
 fn append(new_parent: &Handle, child: Handle) {
@@ -80,8 +239,8 @@ fn append(new_parent: &Handle, child: Handle) {
 
 fn get_parent_and_index(target: &Handle) -> Option<(Handle, uint)> {
     let child = target.borrow();
-    let parent = unwrap_or_return!(child.parent.as_ref(), None)
-        .upgrade().expect("dangling weak pointer");
+    let parent = Handle(unwrap_or_return!(child.parent.as_ref(), None)
+        .upgrade().expect("dangling weak pointer"));
 
     let i = match parent.borrow_mut().children.iter().enumerate()
                 .find(|&(_, n)| same_node(n, target)) {
@@ -91,10 +250,16 @@ fn get_parent_and_index(target: &Handle) -> Option<(Handle, uint)> {
     Some((parent, i))
 }
 
-fn append_to_existing_text(prev: &Handle, text: &str) -> bool {
-    match prev.borrow_mut().deref_mut().node {
+fn append_to_existing_text(prev: &Handle, text: &str, span: Option<Span>) -> bool {
+    let mut prev = prev.borrow_mut();
+    let prev = prev.deref_mut();
+    match prev.node {
         Text(ref mut existing) => {
             existing.push_str(text);
+            match (prev.span.as_mut(), span) {
+                (Some(prev_span), Some(span)) => prev_span.end = span.end,
+                _ => (),
+            }
             true
         }
         _ => false,
@@ -111,23 +276,378 @@ fn remove_from_parent(target: &Handle) {
     (*child).parent = None;
 }
 
+fn in_tree(node: &Handle, root: &Handle) -> bool {
+    let mut node = node.clone();
+    loop {
+        if same_node(&node, root) {
+            return true;
+        }
+        let parent = match node.borrow().parent {
+            Some(ref p) => Handle(p.upgrade().expect("dangling weak pointer")),
+            None => return false,
+        };
+        node = parent;
+    }
+}
+
+fn class_names<'a>(value: &'a str) -> Vec<&'a str> {
+    value.split(is_ascii_whitespace).filter(|c| !c.is_empty()).collect()
+}
+
+/// Elements indexed by `id` and class name, in order of creation.
+#[derive(Default)]
+struct ElementIndex {
+    ids: HashMap<String, Vec<Handle>>,
+    classes: HashMap<String, Vec<Handle>>,
+}
+
+impl ElementIndex {
+    fn add(&mut self, node: &Handle, attrs: &[Attribute]) {
+        for attr in attrs.iter().filter(|a| a.name.ns == ns!("")) {
+            match attr.name.local {
+                atom!(id) => {
+                    let nodes = match self.ids.entry(attr.value.clone()) {
+                        Vacant(e) => e.insert(vec!()),
+                        Occupied(e) => e.into_mut(),
+                    };
+                    nodes.push(node.clone());
+                }
+                atom!(class) => for class in class_names(attr.value.as_slice()).into_iter() {
+                    let nodes = match self.classes.entry(String::from_str(class)) {
+                        Vacant(e) => e.insert(vec!()),
+                        Occupied(e) => e.into_mut(),
+                    };
+                    nodes.push(node.clone());
+                },
+                _ => (),
+            }
+        }
+    }
+}
+
 /// The DOM itself; the result of parsing.
 pub struct RcDom {
     /// The `Document` itself.
     pub document: Handle,
 
-    /// Errors that occurred during parsing.
-    pub errors: Vec<CowString<'static>>,
+    /// Parse errors and other problems found while parsing.
+    pub errors: Vec<Diagnostic>,
 
     /// The document's quirks mode.
     pub quirks_mode: QuirksMode,
+
+    /// The `href` of the first `<base>` element with one.
+    pub base_url: Option<String>,
+
+    /// Whether to merge adjacent text while parsing.  Default: `MergeText`
+    pub text_merging: TextMerging,
+
+    /// How to recover from parse errors, if not as the spec says.
+    /// Default: None
+    pub recovery_policy: Option<Box<RecoveryPolicy + 'static>>,
+
+    /// Index for `get_element_by_id` and `get_elements_by_class_name`,
+    /// if enabled.
+    index: Option<ElementIndex>,
+
+    /// Input span of the token being processed, if known.
+    current_span: Option<Span>,
+}
+
+impl RcDom {
+    /// Create an empty `RcDom` which indexes elements by `id` and class
+    /// while parsing, for faster lookups.  Use it with `parse_to()`.
+    pub fn with_index() -> RcDom {
+        RcDom {
+            index: Some(Default::default()),
+            .. Default::default()
+        }
+    }
+
+    /// Take a node from wherever it is, possibly another `RcDom`, so that
+    /// it can be inserted into this one: detach it from its parent, and
+    /// add it and its descendants to this DOM's element index, if there
+    /// is one.  Insert it afterwards with `append` or
+    /// `append_before_sibling`.
+    ///
+    /// The old DOM's index keeps its entries, but lookups there skip
+    /// nodes which are no longer in its tree.
+    ///
+    /// Panics if `node` is a `Document` or `DocumentFragment`.
+    pub fn adopt_node(&mut self, node: &Handle) {
+        fn index_subtree(index: &mut ElementIndex, root: &Handle) {
+            let mut stack = vec!(root.clone());
+            loop {
+                let handle = unwrap_or_else!(stack.pop(), { break });
+                let node = handle.borrow();
+                match node.node {
+                    Element(_, ref attrs) => index.add(&handle, attrs.as_slice()),
+                    _ => (),
+                }
+                stack.extend(node.children.iter().rev().map(|c| c.clone()));
+            }
+        }
+
+        match node.borrow().node {
+            Document | DocumentFragment => panic!("can't adopt a document"),
+            _ => (),
+        }
+        remove_from_parent(node);
+        match self.index {
+            Some(ref mut index) => index_subtree(index, node),
+            None => (),
+        }
+    }
+
+    // Create a node, recording the current span.
+    fn new_node(&self, node: NodeEnum) -> Handle {
+        let node = new_node(node);
+        node.borrow_mut().span = self.current_span;
+        node
+    }
+
+    fn elements_in_tree_order(&self) -> Vec<Handle> {
+        let mut out = vec!();
+        let mut stack = vec!(self.document.clone());
+        loop {
+            let handle = unwrap_or_else!(stack.pop(), { break });
+            let node = handle.borrow();
+            match node.node {
+                Element(..) => out.push(handle.clone()),
+                _ => (),
+            }
+            stack.extend(node.children.iter().rev().map(|c| c.clone()));
+        }
+        out
+    }
+
+    /// Find the first element with the given `id`.
+    ///
+    /// With an index this takes time proportional to the number of elements
+    /// with the `id`, and "first" means first created, which differs from
+    /// tree order only for misnested markup.  Otherwise it walks the tree.
+    pub fn get_element_by_id(&self, id: &str) -> Option<Handle> {
+        match self.index {
+            Some(ref index) => index.ids.get(id).and_then(|nodes| {
+                nodes.iter().find(|n| in_tree(*n, &self.document)).map(|n| n.clone())
+            }),
+            None => self.elements_in_tree_order().into_iter().find(|n| {
+                match n.borrow().node {
+                    Element(_, ref attrs) => attrs.iter().any(|a|
+                        a.name == qualname!("", "id") && a.value.as_slice() == id),
+                    _ => false,
+                }
+            }),
+        }
+    }
+
+    /// Find the elements which have all of the whitespace-separated
+    /// class names in `names`, as for the DOM's
+    /// `getElementsByClassName()`.
+    ///
+    /// With an index, this only examines elements which have the first
+    /// of the class names, and results are in order of creation.
+    /// Class names are always compared case-sensitively, even in
+    /// quirks mode.
+    pub fn get_elements_by_class_name(&self, names: &str) -> Vec<Handle> {
+        let names = class_names(names);
+        if names.is_empty() {
+            return vec!();
+        }
+
+        let has_all = |node: &Handle| match node.borrow().node {
+            Element(_, ref attrs) => attrs.iter()
+                .find(|a| a.name == qualname!("", "class"))
+                .map_or(false, |a| {
+                    let classes = class_names(a.value.as_slice());
+                    names.iter().all(|n| classes.contains(n))
+                }),
+            _ => false,
+        };
+
+        let candidates = match self.index {
+            Some(ref index) => match index.classes.get(names[0]) {
+                Some(nodes) => nodes.iter()
+                    .filter(|n| in_tree(*n, &self.document))
+                    .map(|n| n.clone()).collect(),
+                None => vec!(),
+            },
+            None => self.elements_in_tree_order(),
+        };
+        candidates.into_iter().filter(|n| has_all(n)).collect()
+    }
+}
+
+fn parent_element(node: &Handle) -> Option<Handle> {
+    let parent = Handle(unwrap_or_return!(node.borrow().parent.as_ref(), None)
+        .upgrade().expect("dangling weak pointer"));
+    let is_element = match parent.borrow().node {
+        Element(..) => true,
+        _ => false,
+    };
+    if is_element { Some(parent) } else { None }
+}
+
+fn get_attr(node: &Handle, name: &QualName) -> Option<String> {
+    match node.borrow().node {
+        Element(_, ref attrs) => attrs.iter().find(|a| a.name == *name).map(|a| a.value.clone()),
+        _ => None,
+    }
+}
+
+/// The language of a node, from the `xml:lang` or `lang` attribute of the
+/// nearest element which has one, starting with the node itself.
+///
+/// `Some("")` means the language is explicitly unknown, and `None` that
+/// no element gives a language.
+pub fn language(node: &Handle) -> Option<String> {
+    let mut node = node.clone();
+    loop {
+        match get_attr(&node, &qualname!(XML, "lang")) {
+            Some(lang) => return Some(lang),
+            None => (),
+        }
+        match get_attr(&node, &qualname!("", "lang")) {
+            Some(lang) => return Some(lang),
+            None => (),
+        }
+        node = match node.borrow().parent {
+            Some(ref p) => Handle(p.upgrade().expect("dangling weak pointer")),
+            None => return None,
+        };
+    }
+}
+
+//§ the-directionality
+// The direction of the first strong character in an element's text,
+// skipping elements which have their own direction.
+fn auto_direction(node: &Handle) -> Option<Direction> {
+    let mut stack: Vec<Handle> = node.borrow().children.iter().rev().map(|c| c.clone()).collect();
+    loop {
+        let handle = unwrap_or_else!(stack.pop(), { return None });
+        let node = handle.borrow();
+        let skip = match node.node {
+            Text(ref text) => match text.as_slice().chars().filter_map(strong_direction).next() {
+                Some(dir) => return Some(dir),
+                None => true,
+            },
+            Element(ref name, ref attrs) => match name.local {
+                atom!(bdi) | atom!(script) | atom!(style) | atom!(textarea)
+                    => name.ns == ns!(HTML),
+                _ => false,
+            } || attrs.iter().any(|a| a.name == qualname!("", "dir")),
+            _ => true,
+        };
+        if !skip {
+            stack.extend(node.children.iter().rev().map(|c| c.clone()));
+        }
+    }
+}
+
+/// The directionality of a node, following `dir` attributes up the tree.
+/// `dir=auto` and `<bdi>` look at the first strong character of their
+/// text, as for the `:dir()` selector.  Nodes outside any element are
+/// left-to-right.
+pub fn directionality(node: &Handle) -> Direction {
+    let mut node = node.clone();
+    loop {
+        let element = match node.borrow().node {
+            Element(ref name, ref attrs) => Some((name.clone(), attrs.iter()
+                .find(|a| a.name == qualname!("", "dir"))
+                .map(|a| a.value.as_slice().to_ascii_lower()))),
+            _ => None,
+        };
+
+        match element {
+            Some((name, dir)) => match dir.as_ref().map(|d| d.as_slice()) {
+                Some("ltr") => return Ltr,
+                Some("rtl") => return Rtl,
+                Some("auto") if name == qualname!(HTML, "input")
+                    => return get_attr(&node, &qualname!("", "value"))
+                        .and_then(|v| v.as_slice().chars().filter_map(strong_direction).next())
+                        .unwrap_or(Ltr),
+                Some("auto") => return auto_direction(&node).unwrap_or(Ltr),
+                _ if name == qualname!(HTML, "bdi") => return auto_direction(&node).unwrap_or(Ltr),
+                _ => (),
+            },
+            None => (),
+        }
+        node = unwrap_or_return!(parent_element(&node), Ltr);
+    }
+}
+//§ END
+
+/// Deep-copy a node and its descendants into fresh nodes.  The copy has
+/// no parent, and isn't in any `RcDom`'s element index.
+pub fn clone_subtree(handle: &Handle) -> Handle {
+    fn clone_node(handle: &Handle) -> Handle {
+        let node = handle.borrow();
+        let copy = new_node(node.node.clone());
+        {
+            let mut c = copy.borrow_mut();
+            c.script_already_started = node.script_already_started;
+            c.span = node.span;
+        }
+        copy
+    }
+
+    let root = clone_node(handle);
+    let mut stack = vec!((handle.clone(), root.clone()));
+    loop {
+        let (original, copy) = unwrap_or_else!(stack.pop(), { break });
+        for child in original.borrow().children.iter() {
+            let child_copy = clone_node(child);
+            append(&copy, child_copy.clone());
+            stack.push((child.clone(), child_copy));
+        }
+    }
+    root
+}
+
+/// Dump a node and its descendants in the html5lib test format, as
+/// described for `sink::common::dump_node`.  A `Document` or
+/// `DocumentFragment` dumps its children.  Useful for golden tests.
+pub fn dump_tree(node: &Handle) -> String {
+    let mut out = String::new();
+    let mut stack = vec!((node.clone(), 0u));
+    loop {
+        let (handle, depth) = unwrap_or_else!(stack.pop(), { break });
+        let node = handle.borrow();
+        dump_node(&mut out, depth, &node.node);
+        let depth = match node.node {
+            Document | DocumentFragment => depth,
+            _ => depth + 1,
+        };
+        stack.extend(node.children.iter().rev().map(|c| (c.clone(), depth)));
+    }
+    out
 }
 
 impl TreeSink for RcDom {
     type Handle = Handle;
+    type Output = RcDom;
 
-    fn parse_error(&mut self, msg: CowString<'static>) {
-        self.errors.push(msg);
+    fn finish(self) -> RcDom {
+        self
+    }
+
+    fn finish_fragment(mut self) -> RcDom {
+        let root = self.document.borrow().children[0].clone();
+        let fragment = new_node(DocumentFragment);
+        self.reparent_children(root, fragment.clone());
+        self.document = fragment;
+        self
+    }
+
+    fn report_diagnostic(&mut self, diagnostic: Diagnostic) {
+        self.errors.push(diagnostic);
+    }
+
+    fn recovery_policy<'a>(&'a mut self) -> Option<&'a mut (RecoveryPolicy + 'a)> {
+        match self.recovery_policy {
+            Some(ref mut policy) => Some(&mut **policy),
+            None => None,
+        }
     }
 
     fn get_document(&mut self) -> Handle {
@@ -138,6 +658,21 @@ impl TreeSink for RcDom {
         self.quirks_mode = mode;
     }
 
+    fn set_current_span(&mut self, span: Span) {
+        self.current_span = Some(span);
+    }
+
+    fn set_element_end(&mut self, target: Handle, end: Pos) {
+        match target.borrow_mut().span.as_mut() {
+            Some(span) => span.end = end,
+            None => (),
+        }
+    }
+
+    fn set_base_url(&mut self, url: String) {
+        self.base_url = Some(url);
+    }
+
     fn same_node(&self, x: Handle, y: Handle) -> bool {
         same_node(&x, &y)
     }
@@ -151,25 +686,36 @@ impl TreeSink for RcDom {
     }
 
     fn create_element(&mut self, name: QualName, attrs: Vec<Attribute>) -> Handle {
-        new_node(Element(name, attrs))
+        let node = self.new_node(Element(name, attrs));
+        match self.index {
+            Some(ref mut index) => match node.borrow().node {
+                Element(_, ref attrs) => index.add(&node, attrs.as_slice()),
+                _ => unreachable!(),
+            },
+            None => (),
+        }
+        node
     }
 
     fn create_comment(&mut self, text: String) -> Handle {
-        new_node(Comment(text))
+        self.new_node(Comment(text))
     }
 
     fn append(&mut self, parent: Handle, child: NodeOrText<Handle>) {
         // Append to an existing Text node if we have one.
         match child {
             AppendText(ref text) => match parent.borrow().children.last() {
-                Some(h) => if append_to_existing_text(h, text.as_slice()) { return; },
+                Some(h) => if self.text_merging == MergeText
+                        && append_to_existing_text(h, text.as_slice(), self.current_span) {
+                    return;
+                },
                 _ => (),
             },
             _ => (),
         }
 
         append(&parent, match child {
-            AppendText(text) => new_node(Text(text)),
+            AppendText(text) => self.new_node(Text(text)),
             AppendNode(node) => node
         });
     }
@@ -181,16 +727,17 @@ impl TreeSink for RcDom {
 
         let child = match (child, i) {
             // No previous node.
-            (AppendText(text), 0) => new_node(Text(text)),
+            (AppendText(text), 0) => self.new_node(Text(text)),
 
             // Look for a text node before the insertion point.
             (AppendText(text), i) => {
                 let parent = parent.borrow();
                 let prev = &parent.children[i-1];
-                if append_to_existing_text(prev, text.as_slice()) {
+                if self.text_merging == MergeText
+                        && append_to_existing_text(prev, text.as_slice(), self.current_span) {
                     return Ok(());
                 }
-                new_node(Text(text))
+                self.new_node(Text(text))
             }
 
             // The tree builder promises we won't have a text node after
@@ -210,7 +757,8 @@ impl TreeSink for RcDom {
     }
 
     fn append_doctype_to_document(&mut self, name: String, public_id: String, system_id: String) {
-        append(&self.document, new_node(Doctype(name, public_id, system_id)));
+        let doctype = self.new_node(Doctype(name, public_id, system_id));
+        append(&self.document, doctype);
     }
 
     fn add_attrs_if_missing(&mut self, target: Handle, mut attrs: Vec<Attribute>) {
@@ -224,6 +772,10 @@ impl TreeSink for RcDom {
         // FIXME: quadratic time
         attrs.retain(|attr|
             !existing.iter().any(|e| e.name == attr.name));
+        match self.index {
+            Some(ref mut index) => index.add(&target, attrs.as_slice()),
+            None => (),
+        }
         existing.extend(attrs.into_iter());
     }
 
@@ -254,52 +806,682 @@ impl Default for RcDom {
             document: new_node(Document),
             errors: vec!(),
             quirks_mode: tree_builder::NoQuirks,
+            base_url: None,
+            text_merging: MergeText,
+            recovery_policy: None,
+            index: None,
+            current_span: None,
         }
     }
 }
 
 impl ParseResult for RcDom {
     type Sink = RcDom;
+}
 
-    fn get_result(sink: RcDom) -> RcDom {
-        sink
-    }
+// A step in serializing a subtree.
+enum SerializeOp {
+    Open(Handle),
+    Close(QualName),
 }
 
 impl Serializable for Handle {
-    fn serialize<'wr, Wr: Writer>(&self, serializer: &mut Serializer<'wr, Wr>, incl_self: bool) -> IoResult<()> {
-        let node = self.borrow();
-        match (incl_self, &node.node) {
-            (_, &Element(ref name, ref attrs)) => {
-                if incl_self {
-                    try!(serializer.start_elem(name.clone(),
-                        attrs.iter().map(|at| (&at.name, at.value.as_slice()))));
-                }
+    fn serialize<'wr, Wr: Writer>(&self, serializer: &mut Serializer<'wr, Wr>,
+                                  traversal_scope: TraversalScope) -> IoResult<()> {
+        // The Document has no markup of its own, so both scopes produce
+        // its children.  Likewise a DocumentFragment.
+        let mut ops = match traversal_scope {
+            IncludeNode => vec!(Open(self.clone())),
+            ChildrenOnly => self.borrow().children.iter().rev()
+                .map(|h| Open(h.clone())).collect(),
+        };
 
-                for handle in node.children.iter() {
-                    try!(handle.clone().serialize(serializer, true));
+        loop {
+            match unwrap_or_else!(ops.pop(), { break }) {
+                Open(handle) => {
+                    let node = handle.borrow();
+                    match node.node {
+                        Element(ref name, ref attrs) => {
+                            try!(serializer.start_elem(name.clone(),
+                                attrs.iter().map(|at| (&at.name, at.value.as_slice()))));
+                            ops.push(Close(name.clone()));
+                        }
+                        Document | DocumentFragment => (),
+                        Doctype(ref name, _, _) => try!(serializer.write_doctype(name.as_slice())),
+                        Text(ref text) => try!(serializer.write_text(text.as_slice())),
+                        Comment(ref text) => try!(serializer.write_comment(text.as_slice())),
+                    }
+                    ops.extend(node.children.iter().rev().map(|h| Open(h.clone())));
                 }
+                Close(name) => try!(serializer.end_elem(name)),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+    use core::default::Default;
+
+    use super::{RcDom, Handle, same_node, node_id, language, directionality, dump_tree};
+    use super::clone_subtree;
+    use sink::common::{Element, DocumentFragment, Text};
+    use sink::common::{TextMerging, MergeText, PreserveTokenBoundaries, Ltr, Rtl};
+    use tree_builder::{TreeSink, AppendNode, AppendText, RecoveryPolicy, EndTagRecovery};
+    use tree_builder::{Ignore, Abort};
+    use driver::{parse_to, parse_str, parse_fragment, parse_bytes, one_input, ParseOpts, Parser};
+    use driver::DEADLINE_STEPS;
+    use encoding::{EncodingOpts, Utf8, InvalidInput, ReplaceInvalid, SkipInvalid, AbortOnInvalid};
+    use tokenizer::TokenizerOpts;
+    use tree_builder::TreeBuilderOpts;
+    use serialize::serialize_to_string;
+    use string_cache::{Atom, QualName};
+    use std::collections::HashSet;
+    use time::precise_time_ns;
+
+    // Parse a document into `dom`.
+    fn parse_into(dom: RcDom, input: &str, opts: ParseOpts) -> RcDom {
+        parse_to(dom, one_input(String::from_str(input)), opts)
+    }
+
+    fn parse_opts(input: &str, opts: ParseOpts) -> RcDom {
+        parse_into(RcDom::default(), input, opts)
+    }
+
+    fn parent_of(node: &Handle) -> Handle {
+        Handle(node.borrow().parent.as_ref().unwrap().upgrade().unwrap())
+    }
+
+    fn ids(nodes: Vec<Handle>) -> Vec<String> {
+        nodes.iter().map(|n| match n.borrow().node {
+            Element(_, ref attrs) => attrs.iter()
+                .find(|a| a.name == qualname!("", "id"))
+                .map(|a| a.value.clone()).unwrap_or(String::new()),
+            _ => panic!("not an element"),
+        }).collect()
+    }
+
+    fn check(dom: RcDom) {
+        assert_eq!(ids(dom.get_element_by_id("b").into_iter().collect()), vec!("b"));
+        assert!(dom.get_element_by_id("nope").is_none());
+        assert_eq!(ids(dom.get_elements_by_class_name("x")), vec!("a", "b", "c"));
+        assert_eq!(ids(dom.get_elements_by_class_name(" y  x ")), vec!("b"));
+        assert_eq!(ids(dom.get_elements_by_class_name("")), Vec::<String>::new());
+    }
+
+    static INPUT: &'static str = "<div id=a class=x><p id=b class='y x'>\
+        <span id=c class=x>t</span><i id=d class=y></i></div>";
+
+    #[test]
+    fn lookup_without_index() {
+        check(parse_str(INPUT));
+    }
+
+    #[test]
+    fn lookup_with_index() {
+        check(parse_into(RcDom::with_index(), INPUT, Default::default()));
+    }
+
+    fn span(node: &Handle) -> (uint, uint, uint, uint) {
+        let span = node.borrow().span.expect("no span");
+        (span.start.line, span.start.column, span.end.line, span.end.column)
+    }
+
+    #[test]
+    fn spans() {
+        let opts = ParseOpts {
+            tokenizer: TokenizerOpts {
+                track_positions: true,
+                .. Default::default()
+            },
+            .. Default::default()
+        };
+        let input = "<p id=a>one\r\n<b id=b>two</b><p id=c><!-- x -->";
+        let dom = parse_opts(input, opts);
+
+        let a = dom.get_element_by_id("a").unwrap();
+        assert_eq!(span(&a), (1, 1, 2, 1 + "<b id=b>two</b>".len()));
+        assert_eq!(span(&a.borrow().children[0]), (1, 9, 2, 1));
+
+        let b = dom.get_element_by_id("b").unwrap();
+        assert_eq!(span(&b), (2, 1, 2, 1 + "<b id=b>two</b>".len()));
+        assert_eq!(b.borrow().span.unwrap().start.offset, "<p id=a>one\r\n".len());
+
+        let c = dom.get_element_by_id("c").unwrap();
+        let start = 1 + "<b id=b>two</b>".len();
+        assert_eq!(span(&c), (2, start, 2, start + "<p id=c>".len()));
+        assert_eq!(span(&c.borrow().children[0]),
+            (2, start + "<p id=c>".len(), 2, start + "<p id=c><!-- x -->".len()));
+    }
+
+    #[test]
+    fn self_closing_errors() {
+        let input = "<!DOCTYPE html><div/><br/><svg/></div/>";
+        let dom: RcDom = parse_str(input);
+        let errors: Vec<&str> = dom.errors.iter().map(|e| &*e.message).collect();
+        assert_eq!(errors, vec!("Unacknowledged self-closing tag", "Self-closing end tag"));
+    }
+
+    #[test]
+    fn fragment() {
+        let input = "<td>a</td><td id=b>b";
+        let dom: RcDom = parse_fragment(one_input(String::from_str(input)),
+            qualname!(HTML, tr), Default::default());
+        let doc = dom.document.borrow();
+        match doc.node {
+            DocumentFragment => (),
+            _ => panic!("not a fragment"),
+        }
+        assert_eq!(ids(doc.children.clone()), vec!("", "b"));
+
+        let parent = parent_of(&doc.children[1]);
+        assert!(same_node(&parent, &dom.document));
+        assert!(dom.get_element_by_id("b").is_some());
+    }
+
+    // Only the first `<base>` with an `href` counts, even in the body.
+    #[test]
+    fn base_url() {
+        let base = |input: &str| parse_str::<RcDom>(input).base_url;
+        assert_eq!(base("<base target=_top><base href=/a/><base href=/b/>"),
+            Some(String::from_str("/a/")));
+        assert_eq!(base("<p>x<base href=/c/>"), Some(String::from_str("/c/")));
+        assert_eq!(base("<base target=_top><p>x"), None);
+    }
+
+    #[test]
+    fn tree_dump() {
+        let dom: RcDom = parse_str("<!DOCTYPE html><p id=b class=a>x<!--c--><svg xlink:href=u>");
+        assert_eq!(dump_tree(&dom.document).as_slice(), "\
+            | <!DOCTYPE html>\n\
+            | <html>\n\
+            |   <head>\n\
+            |   <body>\n\
+            |     <p>\n\
+            |       class=\"a\"\n\
+            |       id=\"b\"\n\
+            |       \"x\"\n\
+            |       <!-- c -->\n\
+            |       <svg svg>\n\
+            |         xlink href=\"u\"\n");
+
+        let p = dom.get_element_by_id("b").unwrap();
+        let text = p.borrow().children[0].clone();
+        assert_eq!(dump_tree(&text).as_slice(), "| \"x\"\n");
+    }
+
+    #[test]
+    fn debug_depth() {
+        let dom: RcDom = parse_str("<p class=a>x<!--c--><svg><path/></svg></p>");
+        let p = dom.get_elements_by_class_name("a")[0].clone();
+        let p = p.borrow();
+        assert_eq!(format!("{:?}", *p).as_slice(), "<p> (1 attrs) [3 children]");
+        assert_eq!(format!("{:?}", p.debug_depth(1)).as_slice(), "\
+            <p> (1 attrs) [3 children]\n  \
+              Text \"x\"\n  \
+              Comment \"c\"\n  \
+              <svg:svg> [1 children]");
+    }
+
+    #[test]
+    fn no_leaks() {
+        let mut input = String::new();
+        for i in range(0u, 1000) {
+            input.push_str(format!("<div id=d{}><p>text<b>bold", i).as_slice());
+        }
+        let dom = parse_into(RcDom::with_index(), input.as_slice(), Default::default());
+        let document = dom.document.downgrade();
+        let outer = dom.get_element_by_id("d400").unwrap().downgrade();
+        let inner = dom.get_element_by_id("d999").unwrap().downgrade();
+        let kept = dom.get_element_by_id("d500").unwrap();
+        drop(dom);
+
+        assert!(document.upgrade().is_none());
+        assert!(outer.upgrade().is_none());
+
+        // A handle keeps its node's descendants alive, but not its
+        // ancestors.
+        assert!(inner.upgrade().is_some());
+        assert!(kept.borrow().parent.as_ref().unwrap().upgrade().is_none());
+        drop(kept);
+        assert!(inner.upgrade().is_none());
+    }
+
+    #[test]
+    fn identity() {
+        let dom: RcDom = parse_str("<p id=a>x</p><p id=b>x</p>");
+        let a = dom.get_element_by_id("a").unwrap();
+        let b = dom.get_element_by_id("b").unwrap();
+        assert!(a == a.clone());
+        assert!(a != b);
+        assert!(same_node(&a, &dom.get_element_by_id("a").unwrap()));
+
+        let mut seen = HashSet::new();
+        assert!(seen.insert(node_id(&a)));
+        assert!(seen.insert(node_id(&b)));
+        assert!(!seen.insert(node_id(&a.clone())));
+
+        let mut handles = HashSet::new();
+        assert!(handles.insert(a.clone()));
+        assert!(handles.insert(b.clone()));
+        assert!(!handles.insert(a.clone()));
+
+        // Identity doesn't need to borrow the node.
+        let _borrowed = a.borrow_mut();
+        assert!(node_id(&a) != node_id(&b));
+        assert!(a == a.clone());
+        assert!(a != b);
+        assert!(handles.contains(&a));
+    }
+
+    #[test]
+    fn deep_copy() {
+        let dom: RcDom = parse_str("<div id=a><p class=x>one<b>two</b></p><!--c--></div>");
+        let original = dom.get_element_by_id("a").unwrap();
+        let copy = clone_subtree(&original);
+        assert_eq!(dump_tree(&copy), dump_tree(&original));
+        assert!(copy.borrow().parent.is_none());
+
+        let p = copy.borrow().children[0].clone();
+        assert!(p != original.borrow().children[0]);
+        let parent = parent_of(&p);
+        assert!(same_node(&parent, &copy));
+
+        // Changing the copy leaves the original alone.
+        p.borrow_mut().children.clear();
+        assert_eq!(original.borrow().children[0].borrow().children.len(), 2);
+    }
+
+    #[test]
+    fn adopt_between_documents() {
+        let parse = |input: &str| parse_into(RcDom::with_index(), input, Default::default());
+        let from = parse("<div id=a><p id=b class=x>moved</p></div>");
+        let mut to = parse("<div id=c></div>");
+
+        let b = from.get_element_by_id("b").unwrap();
+        to.adopt_node(&b);
+        assert!(b.borrow().parent.is_none());
+        assert!(from.get_element_by_id("b").is_none());
+        assert!(to.get_element_by_id("b").is_none());
+
+        let c = to.get_element_by_id("c").unwrap();
+        to.append(c.clone(), AppendNode(b.clone()));
+        assert!(same_node(&to.get_element_by_id("b").unwrap(), &b));
+        assert_eq!(ids(to.get_elements_by_class_name("x")), vec!("b"));
+        assert_eq!(from.get_element_by_id("a").unwrap().borrow().children.len(), 0);
+    }
 
-                if incl_self {
-                    try!(serializer.end_elem(name.clone()));
+    #[test]
+    fn reused_parser() {
+        let inputs = ["<table><tr><td><b>x", "<title>t</title><p>a<i>b",
+            "<!DOCTYPE html><svg><p>x", "<plaintext>y", "<p>a</p> \n", "<p> </p>b"];
+        for &ignore_whitespace_text in [false, true].iter() {
+            let opts = ParseOpts {
+                tree_builder: TreeBuilderOpts {
+                    ignore_whitespace_text: ignore_whitespace_text,
+                    .. Default::default()
+                },
+                .. Default::default()
+            };
+            let mut parser: Parser<RcDom> = Parser::new(opts.clone());
+            for _ in range(0u, 2) {
+                for input in inputs.iter() {
+                    let reused = parser.parse(one_input(String::from_str(*input)));
+                    let fresh = parse_opts(*input, opts.clone());
+                    assert_eq!(dump_tree(&reused.document), dump_tree(&fresh.document));
+                    assert_eq!(reused.errors.len(), fresh.errors.len());
                 }
-                Ok(())
             }
+        }
+    }
 
-            (false, &Document) => {
-                for handle in node.children.iter() {
-                    try!(handle.clone().serialize(serializer, true));
-                }
-                Ok(())
+    #[test]
+    fn allocation_budget() {
+        let parse_with = |input: &str, max_nodes: Option<uint>, max_text: Option<uint>| {
+            let opts = ParseOpts {
+                tree_builder: TreeBuilderOpts {
+                    max_nodes: max_nodes,
+                    max_text: max_text,
+                    .. Default::default()
+                },
+                .. Default::default()
+            };
+            // The parse stops at the first chunk over budget.
+            let chunks = vec!(String::from_str(input), String::from_str("<p id=late>"));
+            let dom: RcDom = parse_to(RcDom::default(), chunks.into_iter(), opts);
+            dom
+        };
+        let input = "<!DOCTYPE html><div id=a><b>xxxx</b><!-- yy --><i id=i>z</i></div>";
+
+        let dom = parse_with(input, None, None);
+        assert!(dom.errors.is_empty());
+        assert!(dom.get_element_by_id("late").is_some());
+
+        // html, head, body, div, b and the comment.
+        let dom = parse_with(input, Some(5), None);
+        assert!(dom.get_element_by_id("a").is_some());
+        assert!(dom.get_element_by_id("i").is_none());
+        assert!(dom.get_element_by_id("late").is_none());
+        assert_eq!(dom.errors.iter().map(|e| &*e.message).collect::<Vec<&str>>(),
+            vec!("Allocation budget exceeded"));
+
+        let dom = parse_with(input, None, Some(5));
+        assert!(dom.get_element_by_id("i").is_none());
+        assert_eq!(dom.errors.len(), 1);
+    }
+
+    #[test]
+    fn deadline() {
+        let input = format!("<!DOCTYPE html>{}", "<p id=a>x</p>".repeat(DEADLINE_STEPS));
+        let parse_with = |deadline: u64| parse_opts(input.as_slice(), ParseOpts {
+            deadline: Some(deadline),
+            .. Default::default()
+        });
+
+        let dom = parse_with(precise_time_ns() + 60_000_000_000);
+        assert!(dom.errors.is_empty());
+        assert!(dom.get_element_by_id("a").is_some());
+
+        let dom = parse_with(0);
+        assert_eq!(dom.errors.iter().map(|e| &*e.message).collect::<Vec<&str>>(),
+            vec!("Parse deadline exceeded"));
+        assert!(dom.document.borrow().children.is_empty());
+
+        // A reused parser keeps the deadline.
+        let mut parser: Parser<RcDom> = Parser::new(ParseOpts {
+            deadline: Some(0),
+            .. Default::default()
+        });
+        let dom = parser.parse(one_input(input.clone()));
+        assert_eq!(dom.errors.len(), 1);
+        assert!(dom.document.borrow().children.is_empty());
+    }
+
+    #[test]
+    fn reused_parser_bytes() {
+        let opts = ParseOpts {
+            encoding: EncodingOpts {
+                transport_encoding: Some(Utf8),
+                .. Default::default()
+            },
+            .. Default::default()
+        };
+        let mut parser: Parser<RcDom> = Parser::new(opts.clone());
+        let inputs = [b"<p id=a>\xC3\xA9".to_vec(), b"<meta charset=utf-8><p>x".to_vec()];
+        for input in inputs.iter() {
+            let chunks: Vec<&[u8]> = input.as_slice().chunks(3).collect();
+            let reused = parser.parse_bytes(chunks.iter().map(|c| *c));
+            let fresh: RcDom = parse_bytes(Some(input.clone()).into_iter(), opts.clone());
+            assert_eq!(dump_tree(&reused.document), dump_tree(&fresh.document));
+        }
+    }
+
+    #[test]
+    fn invalid_input() {
+        let parse_with = |policy: InvalidInput| {
+            let opts = ParseOpts {
+                encoding: EncodingOpts {
+                    transport_encoding: Some(Utf8),
+                    invalid_input: policy,
+                    .. Default::default()
+                },
+                .. Default::default()
+            };
+            // A malformed sequence split between chunks.
+            let input = vec!(b"<!DOCTYPE html><p id=a>x\xE4".to_vec(),
+                b"\xB8y<p id=b>".to_vec());
+            let dom: RcDom = parse_bytes(input.into_iter(), opts);
+            let p = dom.get_element_by_id("a").expect("no <p id=a>");
+            let p = p.borrow();
+            let text = match p.children[0].borrow().node {
+                Text(ref text) => text.clone(),
+                _ => panic!("expected text"),
+            };
+            (dom.errors.iter().map(|e| String::from_str(&*e.message)).collect::<Vec<String>>(),
+                text, dom.get_element_by_id("b").is_some())
+        };
+
+        assert_eq!(parse_with(ReplaceInvalid), (vec!(), String::from_str("x\u{fffd}y"), true));
+        assert_eq!(parse_with(SkipInvalid), (vec!(), String::from_str("xy"), true));
+        assert_eq!(parse_with(AbortOnInvalid),
+            (vec!(String::from_str("Malformed input bytes")), String::from_str("x"), false));
+    }
+
+    #[test]
+    fn stop_before_partial_char() {
+        let opts = ParseOpts {
+            tree_builder: TreeBuilderOpts {
+                max_nodes: Some(4),
+                .. Default::default()
+            },
+            encoding: EncodingOpts {
+                transport_encoding: Some(Utf8),
+                invalid_input: AbortOnInvalid,
+                .. Default::default()
+            },
+            .. Default::default()
+        };
+        // The second <p> is over budget.  The decoder still holds the
+        // start of a character, which mustn't be flushed after the stop.
+        let input = vec!(b"<p id=a>x<p id=b>y\xC3".to_vec());
+        let dom: RcDom = parse_bytes(input.into_iter(), opts);
+        assert!(dom.get_element_by_id("a").is_some());
+        assert!(dom.get_element_by_id("b").is_none());
+        assert_eq!(dom.errors.iter().map(|e| &*e.message).collect::<Vec<&str>>(),
+            vec!("Allocation budget exceeded"));
+    }
+
+    #[test]
+    fn meta_pragma_after_unknown_charset() {
+        // Put the <meta> past the prescan, so the tree builder finds it.
+        let mut input = b"<!--".to_vec();
+        input.extend(range(0u, 1024).map(|_| b'x'));
+        input.push_all(b"--><meta charset=bogus http-equiv=content-type ");
+        input.push_all(b"content='text/html; charset=utf-8'><p id=a>\xC3\xA9");
+        let dom: RcDom = parse_bytes(Some(input).into_iter(), Default::default());
+        let p = dom.get_element_by_id("a").expect("no <p id=a>");
+        let p = p.borrow();
+        match p.children[0].borrow().node {
+            Text(ref text) => assert_eq!(text.as_slice(), "\u{e9}"),
+            _ => panic!("expected text"),
+        }
+    }
+
+    fn texts(text_merging: TextMerging) -> Vec<String> {
+        let mut dom = RcDom::default();
+        dom.text_merging = text_merging;
+        let doc = dom.get_document();
+        for text in ["a", "b"].iter() {
+            dom.append(doc.clone(), AppendText(String::from_str(*text)));
+        }
+        let doc = doc.borrow();
+        doc.children.iter().map(|n| match n.borrow().node {
+            Text(ref text) => text.clone(),
+            _ => panic!("not text"),
+        }).collect()
+    }
+
+    #[test]
+    fn text_merging() {
+        assert_eq!(texts(MergeText), vec!("ab"));
+        assert_eq!(texts(PreserveTokenBoundaries), vec!("a", "b"));
+    }
+
+    #[test]
+    fn ignore_whitespace_text() {
+        let opts = ParseOpts {
+            tree_builder: TreeBuilderOpts {
+                ignore_whitespace_text: true,
+                .. Default::default()
+            },
+            .. Default::default()
+        };
+        let input = "<ul>\n  <li id=a>a b</li>\n</ul>\n<pre id=b> \n</pre>\
+            <p id=c>&lt; &gt;</p><p id=d> &amp;</p><pre><span id=e> </span></pre>";
+        let dom = parse_opts(input, opts);
+
+        let a = dom.get_element_by_id("a").unwrap();
+        let ul = parent_of(&a);
+        assert_eq!(ul.borrow().children.len(), 1);
+        assert_eq!(a.borrow().children.len(), 1);
+
+        let b = dom.get_element_by_id("b").unwrap();
+        let body = parent_of(&b);
+        assert_eq!(body.borrow().children.len(), 5);
+        assert_eq!(b.borrow().children.len(), 1);
+
+        // Whitespace anywhere inside a <pre> is kept.
+        let e = dom.get_element_by_id("e").unwrap();
+        assert_eq!(e.borrow().children.len(), 1);
+
+        // Whitespace between character references is kept.
+        for &(id, text) in [("c", "< >"), ("d", " &")].iter() {
+            let p = dom.get_element_by_id(id).unwrap();
+            let p = p.borrow();
+            assert_eq!(p.children.len(), 1);
+            match p.children[0].borrow().node {
+                Text(ref t) => assert_eq!(t.as_slice(), text),
+                _ => panic!("expected text in <p id={}>", id),
             }
+        }
+    }
+
+    #[test]
+    fn drop_doctype_and_exact_errors() {
+        let opts = ParseOpts {
+            tree_builder: TreeBuilderOpts {
+                drop_doctype: true,
+                exact_errors: true,
+                .. Default::default()
+            },
+            .. Default::default()
+        };
+        let input = "<!DOCTYPE html><div/>";
+        let dom = parse_opts(input, opts);
+
+        let doc = dom.document.borrow();
+        assert_eq!(doc.children.len(), 1);
+        match doc.children[0].borrow().node {
+            Element(ref name, _) => assert_eq!(name.local.as_slice(), "html"),
+            _ => panic!("expected <html>"),
+        }
+
+        let errors: Vec<&str> = dom.errors.iter().map(|e| &*e.message).collect();
+        assert_eq!(errors, vec!("Self-closing tag <div/> on an element which is not void or foreign"));
+    }
+
+    struct Strict(EndTagRecovery);
+
+    impl RecoveryPolicy for Strict {
+        fn unexpected_end_tag(&mut self, _name: &Atom, _open_elems: &[QualName])
+                -> EndTagRecovery {
+            self.0
+        }
+    }
 
-            (false, _) => Ok(()),
+    fn parse_with_policy(input: &str, recovery: EndTagRecovery) -> RcDom {
+        let dom = RcDom {
+            recovery_policy: Some(box Strict(recovery)),
+            .. Default::default()
+        };
+        parse_into(dom, input, Default::default())
+    }
+
+    #[test]
+    fn recovery_policy() {
+        let input = "<div id=a><b id=b></div><p id=c>";
+
+        // The spec closes the <b> and then the <div>.
+        let dom: RcDom = parse_str(input);
+        let c = dom.get_element_by_id("c").unwrap();
+        let parent = parent_of(&c);
+        assert_eq!(ids(vec!(parent)), vec!(""));
+
+        let dom = parse_with_policy(input, Ignore);
+        let c = dom.get_element_by_id("c").unwrap();
+        let parent = parent_of(&c);
+        assert_eq!(ids(vec!(parent)), vec!("b"));
 
-            (true, &Doctype(ref name, _, _)) => serializer.write_doctype(name.as_slice()),
-            (true, &Text(ref text)) => serializer.write_text(text.as_slice()),
-            (true, &Comment(ref text)) => serializer.write_comment(text.as_slice()),
+        let dom = parse_with_policy(input, Abort);
+        assert!(dom.get_element_by_id("b").is_some());
+        assert!(dom.get_element_by_id("c").is_none());
+    }
+
+    #[test]
+    fn language_and_direction() {
+        let input = "<html lang=en><body dir=rtl><p id=a>x</p>\
+            <div lang=fr dir=auto id=b><span dir=rtl>\u{5D0}</span> \u{e9}<svg xml:lang=de id=c></svg></div>\
+            <bdi id=d>1 \u{627}</bdi><input dir=auto value=' abc' id=e>";
+        let dom: RcDom = parse_str(input);
+        let get = |id: &str| dom.get_element_by_id(id).unwrap();
+
+        assert_eq!(language(&get("a")), Some(String::from_str("en")));
+        assert_eq!(language(&get("b")), Some(String::from_str("fr")));
+        assert_eq!(language(&get("c")), Some(String::from_str("de")));
+        assert_eq!(language(&dom.document), None);
+
+        assert_eq!(directionality(&get("a")), Rtl);
+        assert_eq!(directionality(&get("a").borrow().children[0]), Rtl);
+        assert_eq!(directionality(&get("b")), Ltr);
+        assert_eq!(directionality(&get("c")), Ltr);
+        assert_eq!(directionality(&get("d")), Rtl);
+        assert_eq!(directionality(&get("e")), Ltr);
+    }
 
-            (true, &Document) => panic!("Can't serialize Document node itself"),
+    // `depth` nested `<div>`s around a text node.
+    fn deep_dom(depth: uint) -> RcDom {
+        let mut dom = RcDom::default();
+        let mut parent = dom.get_document();
+        for _ in 0..depth {
+            let div = dom.create_element(qualname!(HTML, div), vec!());
+            dom.append(parent, AppendNode(div.clone()));
+            parent = div;
         }
+        dom.append(parent, AppendText(String::from_str("x")));
+        dom
+    }
+
+    #[test]
+    fn deep_tree() {
+        let depth = 100000;
+        let dom = deep_dom(depth);
+
+        let html = serialize_to_string(&dom.document, Default::default());
+        assert_eq!(html.len(), depth * "<div></div>".len() + 1);
+        assert!(html.starts_with("<div><div>"));
+        assert!(html.ends_with("<div>x</div></div>"));
+
+        // Without an index, lookups walk the whole tree.
+        assert!(dom.get_element_by_id("nope").is_none());
+        assert!(dom.get_elements_by_class_name("nope").is_empty());
+
+        let mut deepest = dom.document.clone();
+        loop {
+            let child = unwrap_or_else!(deepest.borrow().children.last().map(|c| c.clone()),
+                { break });
+            deepest = child;
+        }
+        assert_eq!(directionality(&deepest), Ltr);
+
+        let copy = clone_subtree(&dom.document);
+        assert_eq!(serialize_to_string(&copy, Default::default()), html);
+
+        // Dropping the tree mustn't recurse once per level either.
+        drop(copy);
+        drop(dom);
+
+        // Dumps indent each level, so their size is quadratic in the
+        // depth.
+        let dom = deep_dom(10000);
+        let dump = dump_tree(&dom.document);
+        assert_eq!(dump.lines().count(), 10001);
+        assert!(dump.ends_with("  \"x\"\n"));
+        let debug = format!("{:?}", dom.document.borrow().debug_depth(10000));
+        assert_eq!(debug.lines().count(), 10001);
+        assert!(debug.ends_with("<div> [1 children]"));
     }
 }
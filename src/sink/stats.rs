@@ -0,0 +1,218 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A `TreeSink` which counts what the tree builder creates, without
+//! building a tree, for analyzing a corpus.  It's also a small example
+//! of a `TreeSink`.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let stats: Stats = parse(one_input(my_str), Default::default());
+//! println!("{} <p> elements", stats.elements.get(&qualname!(HTML, p)).unwrap_or(&0));
+//! ```
+
+use core::prelude::*;
+
+use tokenizer::Attribute;
+use tree_builder::{TreeSink, QuirksMode, NodeOrText, AppendNode, AppendText};
+use driver::ParseResult;
+use diagnostic::Diagnostic;
+
+use core::cmp;
+use core::default::Default;
+use collections::vec::Vec;
+use collections::string::String;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry::{Occupied, Vacant};
+
+use string_cache::QualName;
+
+/// What was found in a document.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    /// How many elements were created with each name, including the
+    /// context element when parsing a fragment.
+    pub elements: HashMap<QualName, uint>,
+
+    /// How many attributes the elements were created with.  Attributes
+    /// which a later `<html>` or `<body>` tag adds to an existing
+    /// element are not counted.
+    pub attributes: uint,
+
+    /// How many bytes of text were inserted.
+    pub text_bytes: uint,
+
+    /// How many comments were created.
+    pub comments: uint,
+
+    /// How deep the deepest node was when it was inserted.  Children
+    /// of the document are at depth 1.
+    pub max_depth: uint,
+
+    /// How many parse errors were reported.
+    pub errors: uint,
+}
+
+/// What the sink remembers about a node: its name, if it's an element,
+/// and its depth, if it has a parent.
+struct NodeInfo {
+    name: Option<QualName>,
+    depth: Option<uint>,
+}
+
+/// Collects `Stats`.  Handles are indices into a table of the nodes
+/// created, which is all the sink keeps besides the counts.
+pub struct StatsSink {
+    stats: Stats,
+    nodes: Vec<NodeInfo>,
+}
+
+impl Default for StatsSink {
+    fn default() -> StatsSink {
+        StatsSink::new()
+    }
+}
+
+impl ParseResult for Stats {
+    type Sink = StatsSink;
+}
+
+impl StatsSink {
+    pub fn new() -> StatsSink {
+        StatsSink {
+            stats: Default::default(),
+            nodes: vec!(NodeInfo {
+                name: None,
+                depth: Some(0),
+            }),
+        }
+    }
+
+    pub fn stats<'a>(&'a self) -> &'a Stats {
+        &self.stats
+    }
+
+    fn new_node(&mut self, name: Option<QualName>) -> uint {
+        self.nodes.push(NodeInfo {
+            name: name,
+            depth: None,
+        });
+        self.nodes.len() - 1
+    }
+
+    fn insert_at(&mut self, depth: uint, child: NodeOrText<uint>) {
+        match child {
+            AppendNode(node) => self.nodes[node].depth = Some(depth),
+            AppendText(text) => self.stats.text_bytes += text.len(),
+        }
+        self.stats.max_depth = cmp::max(self.stats.max_depth, depth);
+    }
+}
+
+impl TreeSink for StatsSink {
+    type Handle = uint;
+    type Output = Stats;
+
+    fn finish(self) -> Stats {
+        self.stats
+    }
+
+    fn report_diagnostic(&mut self, _diagnostic: Diagnostic) {
+        self.stats.errors += 1;
+    }
+
+    fn get_document(&mut self) -> uint {
+        0
+    }
+
+    fn same_node(&self, x: uint, y: uint) -> bool {
+        x == y
+    }
+
+    fn elem_name(&self, target: uint) -> QualName {
+        self.nodes[target].name.clone().expect("not an element")
+    }
+
+    fn set_quirks_mode(&mut self, _mode: QuirksMode) { }
+
+    fn create_element(&mut self, name: QualName, attrs: Vec<Attribute>) -> uint {
+        match self.stats.elements.entry(name.clone()) {
+            Vacant(e) => {
+                e.insert(1);
+            }
+            Occupied(mut e) => *e.get_mut() += 1,
+        }
+        self.stats.attributes += attrs.len();
+        self.new_node(Some(name))
+    }
+
+    fn create_comment(&mut self, _text: String) -> uint {
+        self.stats.comments += 1;
+        self.new_node(None)
+    }
+
+    fn append(&mut self, parent: uint, child: NodeOrText<uint>) {
+        let depth = self.nodes[parent].depth.unwrap_or(0) + 1;
+        self.insert_at(depth, child);
+    }
+
+    fn append_before_sibling(&mut self, sibling: uint, child: NodeOrText<uint>)
+            -> Result<(), NodeOrText<uint>> {
+        match self.nodes[sibling].depth {
+            Some(depth) => {
+                self.insert_at(depth, child);
+                Ok(())
+            }
+            None => Err(child),
+        }
+    }
+
+    fn append_doctype_to_document(&mut self, _name: String, _public_id: String,
+            _system_id: String) {
+        self.stats.max_depth = cmp::max(self.stats.max_depth, 1);
+    }
+
+    fn add_attrs_if_missing(&mut self, _target: uint, _attrs: Vec<Attribute>) { }
+
+    fn remove_from_parent(&mut self, target: uint) {
+        self.nodes[target].depth = None;
+    }
+
+    // The depths of the children are not updated.
+    fn reparent_children(&mut self, _node: uint, _new_parent: uint) { }
+
+    fn mark_script_already_started(&mut self, _node: uint) { }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+
+    use super::Stats;
+    use driver::parse_str;
+
+    #[test]
+    fn stats() {
+        let stats: Stats = parse_str(
+            "<!DOCTYPE html><p class=a id=b>xy<!--c--><p><b><i>z</i></b></p>\
+             <table>t<tr><td>\u{e9}</table>");
+
+        assert_eq!(stats.elements.get(&qualname!(HTML, p)), Some(&2));
+        assert_eq!(stats.elements.get(&qualname!(HTML, td)), Some(&1));
+        assert_eq!(stats.elements.get(&qualname!(HTML, tbody)), Some(&1));
+        assert_eq!(stats.elements.get(&qualname!(HTML, div)), None);
+        assert_eq!(stats.attributes, 2);
+        assert_eq!(stats.text_bytes, 6);
+        assert_eq!(stats.comments, 1);
+        // html, body, table, tbody, tr, td, text
+        assert_eq!(stats.max_depth, 7);
+        assert_eq!(stats.errors, 1);
+    }
+}
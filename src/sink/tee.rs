@@ -0,0 +1,237 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A `TreeSink` which passes every tree operation on to two sinks, for
+//! example to build a DOM while collecting statistics.
+//!
+//! Handles are pairs of the two sinks' handles.  Questions the tree
+//! builder asks, like `elem_name` and `same_node`, are answered by the
+//! first sink, and so is `recovery_policy`; both sinks see the same
+//! operations, so their answers should agree.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let (dom, links): (RcDom, Vec<Link>) = parse_to(TeeSink::new(RcDom::default(),
+//!     LinkSink::new()), one_input(my_str), Default::default());
+//! ```
+
+use core::prelude::*;
+
+use tokenizer::{Attribute, Pos, Span};
+use tree_builder::{TreeSink, QuirksMode, NodeOrText, AppendNode, AppendText, RecoveryPolicy};
+use tree_builder::{ElementFlags, InsertionPoint, LastChild, BeforeSibling, TableFosterParenting};
+use diagnostic::Diagnostic;
+
+use collections::vec::Vec;
+use collections::string::String;
+
+use string_cache::{Atom, QualName};
+
+/// Sends tree operations to sinks `A` and `B`.
+pub struct TeeSink<A, B> {
+    pub a: A,
+    pub b: B,
+}
+
+impl<A: TreeSink, B: TreeSink> TeeSink<A, B> {
+    pub fn new(a: A, b: B) -> TeeSink<A, B> {
+        TeeSink {
+            a: a,
+            b: b,
+        }
+    }
+}
+
+fn split<HA, HB>(child: NodeOrText<(HA, HB)>) -> (NodeOrText<HA>, NodeOrText<HB>) {
+    match child {
+        AppendNode((a, b)) => (AppendNode(a), AppendNode(b)),
+        AppendText(text) => (AppendText(text.clone()), AppendText(text)),
+    }
+}
+
+fn split_point<HA, HB>(point: InsertionPoint<(HA, HB)>)
+        -> (InsertionPoint<HA>, InsertionPoint<HB>) {
+    match point {
+        LastChild((a, b)) => (LastChild(a), LastChild(b)),
+        BeforeSibling((a, b)) => (BeforeSibling(a), BeforeSibling(b)),
+        TableFosterParenting { table: (ta, tb), prev_element: (pa, pb) } => (
+            TableFosterParenting { table: ta, prev_element: pa },
+            TableFosterParenting { table: tb, prev_element: pb },
+        ),
+    }
+}
+
+impl<A: TreeSink, B: TreeSink> TreeSink for TeeSink<A, B> {
+    type Handle = (A::Handle, B::Handle);
+    type Output = (A::Output, B::Output);
+
+    fn finish(self) -> (A::Output, B::Output) {
+        (self.a.finish(), self.b.finish())
+    }
+
+    fn finish_fragment(self) -> (A::Output, B::Output) {
+        (self.a.finish_fragment(), self.b.finish_fragment())
+    }
+
+    fn report_diagnostic(&mut self, diagnostic: Diagnostic) {
+        self.a.report_diagnostic(diagnostic.clone());
+        self.b.report_diagnostic(diagnostic);
+    }
+
+    fn recovery_policy<'a>(&'a mut self) -> Option<&'a mut (RecoveryPolicy + 'a)> {
+        self.a.recovery_policy()
+    }
+
+    fn get_document(&mut self) -> (A::Handle, B::Handle) {
+        (self.a.get_document(), self.b.get_document())
+    }
+
+    fn same_node(&self, x: (A::Handle, B::Handle), y: (A::Handle, B::Handle)) -> bool {
+        self.a.same_node(x.0, y.0)
+    }
+
+    fn elem_name(&self, target: (A::Handle, B::Handle)) -> QualName {
+        self.a.elem_name(target.0)
+    }
+
+    fn set_current_span(&mut self, span: Span) {
+        self.a.set_current_span(span);
+        self.b.set_current_span(span);
+    }
+
+    fn set_element_end(&mut self, target: (A::Handle, B::Handle), end: Pos) {
+        self.a.set_element_end(target.0, end);
+        self.b.set_element_end(target.1, end);
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.a.set_quirks_mode(mode);
+        self.b.set_quirks_mode(mode);
+    }
+
+    fn set_base_url(&mut self, url: String) {
+        self.a.set_base_url(url.clone());
+        self.b.set_base_url(url);
+    }
+
+    fn create_element(&mut self, name: QualName, attrs: Vec<Attribute>)
+            -> (A::Handle, B::Handle) {
+        (self.a.create_element(name.clone(), attrs.clone()), self.b.create_element(name, attrs))
+    }
+
+    fn create_element_with_flags(&mut self, name: QualName, attrs: Vec<Attribute>,
+            flags: ElementFlags) -> (A::Handle, B::Handle) {
+        (self.a.create_element_with_flags(name.clone(), attrs.clone(), flags.clone()),
+         self.b.create_element_with_flags(name, attrs, flags))
+    }
+
+    fn create_comment(&mut self, text: String) -> (A::Handle, B::Handle) {
+        (self.a.create_comment(text.clone()), self.b.create_comment(text))
+    }
+
+    fn append(&mut self, parent: (A::Handle, B::Handle),
+            child: NodeOrText<(A::Handle, B::Handle)>) {
+        let (ca, cb) = split(child);
+        self.a.append(parent.0, ca);
+        self.b.append(parent.1, cb);
+    }
+
+    fn append_before_sibling(&mut self,
+            sibling: (A::Handle, B::Handle),
+            new_node: NodeOrText<(A::Handle, B::Handle)>)
+            -> Result<(), NodeOrText<(A::Handle, B::Handle)>> {
+        let (na, nb) = split(new_node);
+        match (self.a.append_before_sibling(sibling.0, na),
+               self.b.append_before_sibling(sibling.1, nb)) {
+            (Ok(()), Ok(())) => Ok(()),
+            (Err(AppendNode(a)), Err(AppendNode(b))) => Err(AppendNode((a, b))),
+            (Err(AppendText(text)), Err(AppendText(_))) => Err(AppendText(text)),
+            _ => panic!("sinks disagree about whether a node has a parent"),
+        }
+    }
+
+    fn insert(&mut self, point: InsertionPoint<(A::Handle, B::Handle)>,
+            child: NodeOrText<(A::Handle, B::Handle)>) {
+        let (pa, pb) = split_point(point);
+        let (ca, cb) = split(child);
+        self.a.insert(pa, ca);
+        self.b.insert(pb, cb);
+    }
+
+    fn append_doctype_to_document(&mut self, name: String, public_id: String, system_id: String) {
+        self.a.append_doctype_to_document(name.clone(), public_id.clone(), system_id.clone());
+        self.b.append_doctype_to_document(name, public_id, system_id);
+    }
+
+    fn add_attrs_if_missing(&mut self, target: (A::Handle, B::Handle), attrs: Vec<Attribute>) {
+        self.a.add_attrs_if_missing(target.0, attrs.clone());
+        self.b.add_attrs_if_missing(target.1, attrs);
+    }
+
+    fn remove_from_parent(&mut self, target: (A::Handle, B::Handle)) {
+        self.a.remove_from_parent(target.0);
+        self.b.remove_from_parent(target.1);
+    }
+
+    fn reparent_children(&mut self, node: (A::Handle, B::Handle),
+            new_parent: (A::Handle, B::Handle)) {
+        self.a.reparent_children(node.0, new_parent.0);
+        self.b.reparent_children(node.1, new_parent.1);
+    }
+
+    fn mark_script_already_started(&mut self, node: (A::Handle, B::Handle)) {
+        self.a.mark_script_already_started(node.0);
+        self.b.mark_script_already_started(node.1);
+    }
+
+    fn complete_script(&mut self, node: (A::Handle, B::Handle)) {
+        self.a.complete_script(node.0);
+        self.b.complete_script(node.1);
+    }
+
+    fn raw_text_content(&mut self, node: (A::Handle, B::Handle), name: Atom,
+            attrs: Vec<Attribute>, text: String) {
+        self.a.raw_text_content(node.0, name.clone(), attrs.clone(), text.clone());
+        self.b.raw_text_content(node.1, name, attrs, text);
+    }
+
+    fn append_processing_instruction(&mut self, parent: (A::Handle, B::Handle),
+            target: String, data: String) {
+        self.a.append_processing_instruction(parent.0, target.clone(), data.clone());
+        self.b.append_processing_instruction(parent.1, target, data);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::string::String;
+
+    use super::TeeSink;
+    use sink::rcdom::{RcDom, dump_tree};
+    use sink::owned_dom;
+    use sink::owned_dom::OwnedDom;
+    use driver::{parse_to, parse_str, one_input};
+
+    #[test]
+    fn tee() {
+        let input = "<title>t</title><table><tr><td>a</td>b</table><b><p>c</b>d<!--e-->";
+        let (rc, owned): (RcDom, OwnedDom) = parse_to(
+            TeeSink::new(RcDom::default(), owned_dom::Sink::default()),
+            one_input(String::from_str(input)), Default::default());
+
+        let alone: RcDom = parse_str(input);
+        assert_eq!(dump_tree(&rc.document), dump_tree(&alone.document));
+        assert_eq!(owned_dom::dump_tree(&*owned.document), dump_tree(&alone.document));
+        assert_eq!(rc.errors, alone.errors);
+        assert_eq!(owned.errors, alone.errors);
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Depth-first walks over the bundled DOMs.
+//!
+//! An analysis written as a `Visit` works on an `RcDom` or an
+//! `OwnedDom` alike.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! struct CountLinks(uint);
+//!
+//! impl Visit for CountLinks {
+//!     fn enter_element(&mut self, name: &QualName, _attrs: &[Attribute]) -> bool {
+//!         if *name == qualname!(HTML, a) {
+//!             self.0 += 1;
+//!         }
+//!         true
+//!     }
+//! }
+//!
+//! let mut count = CountLinks(0);
+//! dom.document.visit(&mut count);
+//! ```
+
+use core::prelude::*;
+
+use sink::common::{NodeEnum, Document, DocumentFragment, Doctype, Text, Comment, Element};
+use sink::rcdom;
+use sink::owned_dom;
+use tokenizer::Attribute;
+
+use collections::vec::Vec;
+
+use string_cache::QualName;
+
+use self::Step::{Enter, Leave};
+
+/// Callbacks for the nodes of a tree, in document order.  Documents and
+/// document fragments have no callbacks of their own; their children
+/// are visited.
+pub trait Visit {
+    fn doctype(&mut self, _name: &str, _public_id: &str, _system_id: &str) { }
+
+    /// Called before an element's children.  Return false to skip
+    /// them; `leave_element` is called either way.
+    fn enter_element(&mut self, _name: &QualName, _attrs: &[Attribute]) -> bool {
+        true
+    }
+
+    fn leave_element(&mut self, _name: &QualName) { }
+
+    fn text(&mut self, _text: &str) { }
+
+    fn comment(&mut self, _text: &str) { }
+}
+
+/// A node which a `Visit` can walk, along with its descendants.
+pub trait Visitable {
+    fn visit<V: Visit>(&self, visitor: &mut V);
+}
+
+// A step in visiting a subtree.
+enum Step<N, Q> {
+    Enter(N),
+    Leave(Q),
+}
+
+// Call the visitor for a node, other than `leave_element`, and return
+// whether to visit its children.
+fn enter<V: Visit>(node: &NodeEnum, visitor: &mut V) -> bool {
+    match *node {
+        Document | DocumentFragment => true,
+        Doctype(ref name, ref public_id, ref system_id) => {
+            visitor.doctype(name.as_slice(), public_id.as_slice(), system_id.as_slice());
+            false
+        }
+        Text(ref text) => {
+            visitor.text(text.as_slice());
+            false
+        }
+        Comment(ref text) => {
+            visitor.comment(text.as_slice());
+            false
+        }
+        Element(ref name, ref attrs) => visitor.enter_element(name, attrs.as_slice()),
+    }
+}
+
+impl Visitable for rcdom::Handle {
+    fn visit<V: Visit>(&self, visitor: &mut V) {
+        let mut steps = vec!(Enter(self.clone()));
+        loop {
+            match unwrap_or_else!(steps.pop(), { break }) {
+                Enter(handle) => {
+                    let node = handle.borrow();
+                    match node.node {
+                        Element(ref name, _) => steps.push(Leave(name.clone())),
+                        _ => (),
+                    }
+                    if enter(&node.node, visitor) {
+                        steps.extend(node.children.iter().rev().map(|c| Enter(c.clone())));
+                    }
+                }
+                Leave(name) => visitor.leave_element(&name),
+            }
+        }
+    }
+}
+
+impl Visitable for owned_dom::Node {
+    fn visit<V: Visit>(&self, visitor: &mut V) {
+        let mut steps: Vec<Step<&owned_dom::Node, &QualName>> = vec!(Enter(self));
+        loop {
+            match unwrap_or_else!(steps.pop(), { break }) {
+                Enter(node) => {
+                    match node.node {
+                        Element(ref name, _) => steps.push(Leave(name)),
+                        _ => (),
+                    }
+                    if enter(&node.node, visitor) {
+                        steps.extend(node.children.iter().rev().map(|c| Enter(&**c)));
+                    }
+                }
+                Leave(name) => visitor.leave_element(name),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::{Visit, Visitable};
+    use sink::rcdom::RcDom;
+    use sink::owned_dom::OwnedDom;
+    use tokenizer::Attribute;
+    use driver::parse_str;
+
+    use string_cache::QualName;
+
+    struct Outline(Vec<String>);
+
+    impl Visit for Outline {
+        fn doctype(&mut self, name: &str, _public_id: &str, _system_id: &str) {
+            self.0.push(format!("!{}", name));
+        }
+
+        fn enter_element(&mut self, name: &QualName, attrs: &[Attribute]) -> bool {
+            self.0.push(format!("<{} {}>", name.local.as_slice(), attrs.len()));
+            name.local.as_slice() != "script"
+        }
+
+        fn leave_element(&mut self, name: &QualName) {
+            self.0.push(format!("</{}>", name.local.as_slice()));
+        }
+
+        fn text(&mut self, text: &str) {
+            self.0.push(String::from_str(text));
+        }
+
+        fn comment(&mut self, text: &str) {
+            self.0.push(format!("<!--{}-->", text));
+        }
+    }
+
+    #[test]
+    fn visit_both_doms() {
+        let input = "<!DOCTYPE html><p class=a>x<!--c--><script>s()</script>y";
+        let rc: RcDom = parse_str(input);
+        let owned: OwnedDom = parse_str(input);
+
+        let mut a = Outline(vec!());
+        rc.document.visit(&mut a);
+        let mut b = Outline(vec!());
+        owned.document.visit(&mut b);
+
+        let expected: Vec<String> = ["!html", "<html 0>", "<head 0>", "</head>", "<body 0>",
+            "<p 1>", "x", "<!--c-->", "<script 0>", "</script>", "y", "</p>", "</body>",
+            "</html>"].iter().map(|s| String::from_str(*s)).collect();
+        assert_eq!(a.0, expected);
+        assert_eq!(b.0, expected);
+    }
+}
@@ -0,0 +1,637 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A small subset of XPath 1.0 for querying an `RcDom`.
+//!
+//! Supported are location paths built from the abbreviated syntax:
+//! `/`, `//`, `.`, `..`, `*`, element names, `text()`, `node()`,
+//! `comment()`, and a final `@attr` step.  Predicates may be a position
+//! (`[1]`, `[last()]`), an attribute test (`[@id]`, `[@class="x"]`,
+//! `[@class!="x"]`), a text test (`[text()="x"]`, `[.="x"]`), or
+//! `contains()` / `starts-with()` on any of those, combined with `and`,
+//! `or` and `not()`.
+//!
+//! Names of HTML elements and their attributes are matched ignoring
+//! ASCII case, as in CSS selectors.  Names in SVG and MathML are matched
+//! exactly, so `foreignObject` must be written that way.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let xpath = XPath::parse("//div[@class=\"x\"]/p[1]").unwrap();
+//! for node in xpath.select(&dom.document).iter() {
+//!     ...
+//! }
+//! ```
+
+use core::prelude::*;
+
+use sink::common::{Text, Comment, Element};
+use sink::rcdom::{Handle, NodeId, node_id};
+
+use util::str::AsciiExt;
+
+use collections::vec::Vec;
+use collections::string::String;
+use std::collections::HashMap;
+
+use string_cache::{Atom, Namespace};
+
+use self::StepKind::{ChildStep, SelfStep, ParentStep, AttrStep};
+use self::NodeTest::{NameTest, AnyElement, TextTest, CommentTest, AnyNode};
+use self::Operand::{AttrValue, TextValue, StringValue};
+use self::Expr::{Position, Last, Exists, Equals, NotEquals, Contains, StartsWith};
+use self::Expr::{And, Or, Not};
+
+/// An error in an XPath expression.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct XPathError {
+    /// Character offset of the error.
+    pub position: uint,
+
+    /// What was wrong.
+    pub message: &'static str,
+}
+
+#[derive(Debug)]
+enum NodeTest {
+    NameTest(String),
+    AnyElement,
+    TextTest,
+    CommentTest,
+    AnyNode,
+}
+
+#[derive(Debug)]
+enum StepKind {
+    ChildStep(NodeTest),
+    SelfStep,
+    ParentStep,
+    AttrStep(String),
+}
+
+#[derive(Debug)]
+enum Operand {
+    AttrValue(String),
+    TextValue,
+    StringValue,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Position(uint),
+    Last,
+    Exists(Operand),
+    Equals(Operand, String),
+    NotEquals(Operand, String),
+    Contains(Operand, String),
+    StartsWith(Operand, String),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Debug)]
+struct Step {
+    /// Was the step preceded by `//`?
+    descendant: bool,
+    kind: StepKind,
+    predicates: Vec<Expr>,
+}
+
+/// A parsed XPath expression.
+#[derive(Debug)]
+pub struct XPath {
+    absolute: bool,
+    steps: Vec<Step>,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: uint,
+}
+
+fn is_name_char(c: char) -> bool {
+    match c {
+        'a'...'z' | 'A'...'Z' | '0'...'9' | '-' | '_' | ':' | '.' => true,
+        _ => false,
+    }
+}
+
+impl Parser {
+    fn error<T>(&self, message: &'static str) -> Result<T, XPathError> {
+        Err(XPathError {
+            position: self.pos,
+            message: message,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|&c| c)
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().map_or(false, |c| c == ' ' || c == '\t' || c == '\n') {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        self.skip_space();
+        let n = s.chars().count();
+        if self.pos + n <= self.chars.len()
+            && s.chars().zip(self.chars[self.pos..].iter()).all(|(a, &b)| a == b) {
+            self.pos += n;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str, message: &'static str) -> Result<(), XPathError> {
+        if self.eat(s) { Ok(()) } else { self.error(message) }
+    }
+
+    fn name(&mut self) -> Result<String, XPathError> {
+        self.skip_space();
+        let mut name = String::new();
+        loop {
+            match self.peek() {
+                Some(c) if is_name_char(c) && !(name.is_empty() && c == '.') => name.push(c),
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        if name.is_empty() {
+            return self.error("expected a name");
+        }
+        Ok(name)
+    }
+
+    // Eat the operator `word` if it's not just the start of a longer
+    // name, so that `and(` is an operator but `android` is not.
+    fn eat_word(&mut self, word: &str) -> bool {
+        let start = self.pos;
+        if !self.eat(word) {
+            return false;
+        }
+        if self.peek().map_or(false, is_name_char) {
+            self.pos = start;
+            return false;
+        }
+        true
+    }
+
+    fn literal(&mut self) -> Result<String, XPathError> {
+        self.skip_space();
+        let quote = match self.peek() {
+            Some(q @ '"') | Some(q @ '\'') => q,
+            _ => return self.error("expected a quoted string"),
+        };
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                None => return self.error("unterminated string"),
+                Some(c) if c == quote => break,
+                Some(c) => value.push(c),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Option<uint> {
+        self.skip_space();
+        let start = self.pos;
+        let mut n = 0;
+        loop {
+            match self.peek().and_then(|c| c.to_digit(10)) {
+                Some(d) => n = n * 10 + d as uint,
+                None => break,
+            }
+            self.pos += 1;
+        }
+        if self.pos > start { Some(n) } else { None }
+    }
+
+    fn operand(&mut self) -> Result<Operand, XPathError> {
+        if self.eat("@") {
+            Ok(AttrValue(try!(self.name())))
+        } else if self.eat("text()") {
+            Ok(TextValue)
+        } else if self.eat(".") {
+            Ok(StringValue)
+        } else {
+            self.error("expected @name, text() or .")
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, XPathError> {
+        match self.number() {
+            Some(n) => return Ok(Position(n)),
+            None => (),
+        }
+        if self.eat("last()") {
+            return Ok(Last);
+        }
+        if self.eat("(") {
+            let e = try!(self.or_expr());
+            try!(self.expect(")", "expected )"));
+            return Ok(e);
+        }
+        if self.eat("not(") {
+            let e = try!(self.or_expr());
+            try!(self.expect(")", "expected )"));
+            return Ok(Not(box e));
+        }
+
+        for &(func, is_contains) in [("contains(", true), ("starts-with(", false)].iter() {
+            if self.eat(func) {
+                let operand = try!(self.operand());
+                try!(self.expect(",", "expected ,"));
+                let lit = try!(self.literal());
+                try!(self.expect(")", "expected )"));
+                return Ok(if is_contains {
+                    Contains(operand, lit)
+                } else {
+                    StartsWith(operand, lit)
+                });
+            }
+        }
+
+        let operand = try!(self.operand());
+        if self.eat("!=") {
+            Ok(NotEquals(operand, try!(self.literal())))
+        } else if self.eat("=") {
+            Ok(Equals(operand, try!(self.literal())))
+        } else {
+            Ok(Exists(operand))
+        }
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, XPathError> {
+        let mut e = try!(self.primary());
+        while self.eat_word("and") {
+            e = And(box e, box try!(self.primary()));
+        }
+        Ok(e)
+    }
+
+    fn or_expr(&mut self) -> Result<Expr, XPathError> {
+        let mut e = try!(self.and_expr());
+        while self.eat_word("or") {
+            e = Or(box e, box try!(self.and_expr()));
+        }
+        Ok(e)
+    }
+
+    fn step(&mut self, descendant: bool) -> Result<Step, XPathError> {
+        let kind = if self.eat("..") {
+            ParentStep
+        } else if self.eat(".") {
+            SelfStep
+        } else if self.eat("@") {
+            AttrStep(try!(self.name()))
+        } else if self.eat("*") {
+            ChildStep(AnyElement)
+        } else if self.eat("text()") {
+            ChildStep(TextTest)
+        } else if self.eat("comment()") {
+            ChildStep(CommentTest)
+        } else if self.eat("node()") {
+            ChildStep(AnyNode)
+        } else {
+            ChildStep(NameTest(try!(self.name())))
+        };
+
+        let mut predicates = vec!();
+        while self.eat("[") {
+            predicates.push(try!(self.or_expr()));
+            try!(self.expect("]", "expected ]"));
+        }
+
+        Ok(Step {
+            descendant: descendant,
+            kind: kind,
+            predicates: predicates,
+        })
+    }
+
+    fn path(&mut self) -> Result<XPath, XPathError> {
+        let mut steps = vec!();
+        let absolute = self.peek() == Some('/');
+        loop {
+            let descendant = if self.eat("//") {
+                true
+            } else if self.eat("/") {
+                false
+            } else if steps.is_empty() {
+                false
+            } else {
+                break;
+            };
+
+            // A lone "/" selects the root.
+            if absolute && steps.is_empty() && !descendant {
+                self.skip_space();
+                if self.peek().is_none() {
+                    break;
+                }
+            }
+
+            match steps.last() {
+                Some(&Step { kind: AttrStep(_), .. })
+                    => return self.error("an attribute step must come last"),
+                _ => (),
+            }
+            steps.push(try!(self.step(descendant)));
+        }
+
+        self.skip_space();
+        if self.peek().is_some() {
+            return self.error("unexpected character");
+        }
+
+        Ok(XPath {
+            absolute: absolute,
+            steps: steps,
+        })
+    }
+}
+
+fn root_of(node: &Handle) -> Handle {
+    let mut node = node.clone();
+    loop {
+        let parent = match node.borrow().parent {
+            Some(ref p) => Handle(p.upgrade().expect("dangling weak pointer")),
+            None => return node.clone(),
+        };
+        node = parent;
+    }
+}
+
+fn string_value(node: &Handle, out: &mut String) {
+    let node = node.borrow();
+    match node.node {
+        Text(ref t) | Comment(ref t) => out.push_str(t.as_slice()),
+        _ => for child in node.children.iter() {
+            match child.borrow().node {
+                Comment(_) => continue,
+                _ => (),
+            }
+            string_value(child, out);
+        },
+    }
+}
+
+// Does the name of an element or attribute in namespace `ns` match
+// `name` from the expression?
+fn name_matches(ns: &Namespace, local: &Atom, name: &str) -> bool {
+    if *ns == ns!(HTML) {
+        local.as_slice().eq_ignore_ascii_case(name)
+    } else {
+        local.as_slice() == name
+    }
+}
+
+fn attr_value(node: &Handle, name: &str) -> Option<String> {
+    match node.borrow().node {
+        Element(ref elem, ref attrs) => attrs.iter()
+            .find(|a| name_matches(&elem.ns, &a.name.local, name))
+            .map(|a| a.value.clone()),
+        _ => None,
+    }
+}
+
+/// The values of an operand for a node; a test succeeds if any matches.
+fn operand_values(node: &Handle, operand: &Operand) -> Vec<String> {
+    match *operand {
+        AttrValue(ref name) => attr_value(node, name.as_slice()).into_iter().collect(),
+        TextValue => node.borrow().children.iter().filter_map(|c| match c.borrow().node {
+            Text(ref t) => Some(t.clone()),
+            _ => None,
+        }).collect(),
+        StringValue => {
+            let mut s = String::new();
+            string_value(node, &mut s);
+            vec!(s)
+        }
+    }
+}
+
+fn eval_expr(expr: &Expr, node: &Handle, position: uint, size: uint) -> bool {
+    let values = |op| operand_values(node, op);
+
+    match *expr {
+        Position(n) => position == n,
+        Last => position == size,
+        Exists(ref op) => !values(op).is_empty(),
+        Equals(ref op, ref lit) => values(op).iter().any(|v| v == lit),
+        NotEquals(ref op, ref lit) => values(op).iter().any(|v| v != lit),
+        Contains(ref op, ref lit) => values(op).iter()
+            .any(|v| v.as_slice().contains(lit.as_slice())),
+        StartsWith(ref op, ref lit) => values(op).iter()
+            .any(|v| v.as_slice().starts_with(lit.as_slice())),
+        And(ref a, ref b) => eval_expr(&**a, node, position, size)
+            && eval_expr(&**b, node, position, size),
+        Or(ref a, ref b) => eval_expr(&**a, node, position, size)
+            || eval_expr(&**b, node, position, size),
+        Not(ref a) => !eval_expr(&**a, node, position, size),
+    }
+}
+
+fn matches(test: &NodeTest, node: &Handle) -> bool {
+    match (test, &node.borrow().node) {
+        (&NameTest(ref name), &Element(ref qn, _))
+            => name_matches(&qn.ns, &qn.local, name.as_slice()),
+        (&AnyElement, &Element(..)) => true,
+        (&TextTest, &Text(_)) => true,
+        (&CommentTest, &Comment(_)) => true,
+        (&AnyNode, _) => true,
+        _ => false,
+    }
+}
+
+/// Positions of all nodes in document order.
+fn document_order(root: &Handle) -> HashMap<NodeId, uint> {
+    fn walk(node: &Handle, order: &mut HashMap<NodeId, uint>) {
+        let n = order.len();
+        order.insert(node_id(node), n);
+        for child in node.borrow().children.iter() {
+            walk(child, order);
+        }
+    }
+
+    let mut order = HashMap::new();
+    walk(root, &mut order);
+    order
+}
+
+fn sort_and_dedup(nodes: Vec<Handle>, order: &HashMap<NodeId, uint>) -> Vec<Handle> {
+    let mut keyed: Vec<(uint, Handle)> = nodes.into_iter()
+        .map(|n| (*order.get(&node_id(&n)).expect("node not in document"), n))
+        .collect();
+    keyed.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+
+    let mut out: Vec<Handle> = Vec::with_capacity(keyed.len());
+    let mut last = None;
+    for (key, node) in keyed.into_iter() {
+        if last != Some(key) {
+            out.push(node);
+            last = Some(key);
+        }
+    }
+    out
+}
+
+fn descendants_or_self(node: &Handle, out: &mut Vec<Handle>) {
+    out.push(node.clone());
+    for child in node.borrow().children.iter() {
+        descendants_or_self(child, out);
+    }
+}
+
+impl XPath {
+    /// Parse an XPath expression.
+    pub fn parse(expr: &str) -> Result<XPath, XPathError> {
+        let mut parser = Parser {
+            chars: expr.chars().collect(),
+            pos: 0,
+        };
+        parser.path()
+    }
+
+    fn eval(&self, context: &Handle) -> (Vec<Handle>, Option<&str>) {
+        let root = root_of(context);
+        let order = document_order(&root);
+
+        let mut nodes = vec!(if self.absolute { root } else { context.clone() });
+        for step in self.steps.iter() {
+            if step.descendant {
+                let mut expanded = vec!();
+                for node in nodes.iter() {
+                    descendants_or_self(node, &mut expanded);
+                }
+                nodes = sort_and_dedup(expanded, &order);
+            }
+
+            let test = match step.kind {
+                AttrStep(ref name) => {
+                    nodes.retain(|n| attr_value(n, name.as_slice()).is_some());
+                    return (nodes, Some(name.as_slice()));
+                }
+                ChildStep(ref test) => Some(test),
+                _ => None,
+            };
+
+            let mut next = vec!();
+            for node in nodes.iter() {
+                let mut candidates: Vec<Handle> = match (&step.kind, test) {
+                    (_, Some(test)) => node.borrow().children.iter()
+                        .filter(|c| matches(test, *c)).map(|c| c.clone()).collect(),
+                    (&ParentStep, _) => node.borrow().parent.as_ref()
+                        .and_then(|p| p.upgrade()).map(Handle).into_iter().collect(),
+                    _ => vec!(node.clone()),
+                };
+
+                for pred in step.predicates.iter() {
+                    let size = candidates.len();
+                    candidates = candidates.into_iter().enumerate()
+                        .filter(|&(i, ref c)| eval_expr(pred, c, i + 1, size))
+                        .map(|(_, c)| c).collect();
+                }
+                next.extend(candidates.into_iter());
+            }
+            nodes = sort_and_dedup(next, &order);
+        }
+        (nodes, None)
+    }
+
+    /// Select nodes, relative to `context` unless the path is absolute.
+    ///
+    /// For a path ending in an attribute step, returns the elements
+    /// which have that attribute.
+    pub fn select(&self, context: &Handle) -> Vec<Handle> {
+        self.eval(context).0
+    }
+
+    /// Select strings: attribute values for a path ending in an
+    /// attribute step, and otherwise the text content of each node.
+    pub fn select_strings(&self, context: &Handle) -> Vec<String> {
+        let (nodes, attr) = self.eval(context);
+        nodes.iter().map(|node| match attr {
+            Some(name) => attr_value(node, name).unwrap_or(String::new()),
+            None => {
+                let mut s = String::new();
+                string_value(node, &mut s);
+                s
+            }
+        }).collect()
+    }
+}
+
+/// Parse `expr` and select nodes from the document containing `context`.
+pub fn select(context: &Handle, expr: &str) -> Result<Vec<Handle>, XPathError> {
+    XPath::parse(expr).map(|xpath| xpath.select(context))
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::{XPath, XPathError};
+    use sink::rcdom::RcDom;
+    use driver::parse_str;
+
+    static DOC: &'static str = "<div class=x id=d1><p>one</p><p>two <b>bold</b></p></div>\
+        <div id=d2><p class=y>three</p><!-- c --></div><a href=/u>link</a>\
+        <svg><foreignObject viewbox='0 0 1 1'>fo</foreignObject></svg>";
+
+    fn strings(expr: &str) -> Vec<String> {
+        let dom: RcDom = parse_str(DOC);
+        XPath::parse(expr).unwrap().select_strings(&dom.document)
+    }
+
+    fn s(xs: &[&str]) -> Vec<String> {
+        xs.iter().map(|&x| String::from_str(x)).collect()
+    }
+
+    test_eq!(descendant, strings("//p"), s(&["one", "two bold", "three"]));
+    test_eq!(absolute, strings("/html/body/div/p/b"), s(&["bold"]));
+    test_eq!(attr_pred, strings("//div[@class=\"x\"]/p[1]"), s(&["one"]));
+    test_eq!(last, strings("//div/p[last()]"), s(&["two bold", "three"]));
+    test_eq!(position_per_parent, strings("//p[1]"), s(&["one", "three"]));
+    test_eq!(attr_step, strings("//a/@href"), s(&["/u"]));
+    test_eq!(exists, strings("//*[@class]/@id"), s(&["d1"]));
+    test_eq!(not_equals, strings("//div[@id!='d1']/p"), s(&["three"]));
+    test_eq!(contains, strings("//p[contains(., 'bo')]"), s(&["two bold"]));
+    test_eq!(starts_with, strings("//p[starts-with(text(), 'th')]"), s(&["three"]));
+    test_eq!(and_or, strings("//p[@class='y' or text()='one']"), s(&["one", "three"]));
+    test_eq!(not, strings("//div[not(@class)]/@id"), s(&["d2"]));
+    test_eq!(parent, strings("//b/../../@id"), s(&["d1"]));
+    test_eq!(text_nodes, strings("//p/text()"), s(&["one", "two ", "three"]));
+    test_eq!(comment, strings("//comment()"), s(&[" c "]));
+    test_eq!(upper_name, strings("//DIV[2]/P"), s(&["three"]));
+    test_eq!(upper_attr, strings("//A/@HREF"), s(&["/u"]));
+    test_eq!(svg_name, strings("//svg/foreignObject/@viewBox"), s(&["0 0 1 1"]));
+    test_eq!(svg_name_case, strings("//foreignobject"), s(&[]));
+    test_eq!(svg_attr_case, strings("//svg/*/@viewbox"), s(&[]));
+    test_eq!(operator_paren, strings("//p[@class='y'and(text()='three')]"), s(&["three"]));
+    test_eq!(or_paren, strings("//p[text()='one'or(@class)]"), s(&["one", "three"]));
+    test_eq!(name_like_operator, strings("//p[@order or @andx]"), s(&[]));
+
+    test_eq!(bad_pred, XPath::parse("//p[").err(),
+        Some(XPathError { position: 4, message: "expected @name, text() or ." }));
+    test_eq!(attr_not_last, XPath::parse("//@a/b").err().map(|e| e.message),
+        Some("an attribute step must come last"));
+}
@@ -0,0 +1,328 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Interpreting a `<table>` in an `RcDom` as a grid of cells, with
+//! `colspan` and `rowspan` resolved as in the HTML table model.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let table = Table::from_element(&handle).unwrap();
+//! for row in table.text_grid().iter() {
+//!     println!("{}", row.connect("\t"));
+//! }
+//! ```
+
+use core::prelude::*;
+use core::cmp;
+
+use sink::common::{Text, Element};
+use sink::rcdom::Handle;
+
+use util::str::is_ascii_whitespace;
+
+use collections::vec::Vec;
+use collections::string::String;
+
+use string_cache::QualName;
+
+/// A `<td>` or `<th>` and the slots it covers.
+pub struct Cell {
+    /// The cell element.
+    pub handle: Handle,
+
+    /// Row of the cell's top left slot.
+    pub row: uint,
+
+    /// Column of the cell's top left slot.
+    pub col: uint,
+
+    /// Number of rows covered, at least 1.
+    pub rowspan: uint,
+
+    /// Number of columns covered, at least 1.
+    pub colspan: uint,
+
+    /// Is this a `<th>`?
+    pub header: bool,
+}
+
+/// A table as a grid of slots, each covered by at most one cell.
+pub struct Table {
+    /// The `<caption>`, if any.
+    pub caption: Option<Handle>,
+
+    /// The cells, in the order the table model assigns them slots.
+    pub cells: Vec<Cell>,
+
+    /// Number of rows.
+    pub height: uint,
+
+    /// Number of columns.
+    pub width: uint,
+
+    /// Index into `cells` of the cell covering each slot, by row.
+    slots: Vec<Vec<Option<uint>>>,
+}
+
+fn html_name(handle: &Handle) -> Option<QualName> {
+    match handle.borrow().node {
+        Element(ref name, _) if name.ns == ns!(HTML) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+fn children_named(handle: &Handle, names: &[&str]) -> Vec<Handle> {
+    handle.borrow().children.iter()
+        .filter(|c| html_name(*c).map_or(false, |n| names.contains(&n.local.as_slice())))
+        .map(|c| c.clone())
+        .collect()
+}
+
+//§ rules-for-parsing-non-negative-integers
+fn parse_non_negative(value: &str) -> Option<uint> {
+    let value = value.trim_left_matches(is_ascii_whitespace);
+    let value = if value.starts_with("+") { &value[1..] } else { value };
+    let end = value.find(|c: char| c < '0' || c > '9').unwrap_or(value.len());
+    if end == 0 {
+        return None;
+    }
+    // Values too large for a uint are clamped by the caller anyway.
+    Some(value[..end].parse().unwrap_or(!0))
+}
+//§ END
+
+fn span_attr(handle: &Handle, name: &str) -> Option<uint> {
+    match handle.borrow().node {
+        Element(_, ref attrs) => attrs.iter()
+            .find(|a| a.name.ns == ns!("") && a.name.local.as_slice() == name)
+            .and_then(|a| parse_non_negative(a.value.as_slice())),
+        _ => None,
+    }
+}
+
+fn push_text(handle: &Handle, out: &mut String) {
+    let node = handle.borrow();
+    match node.node {
+        Text(ref text) => out.push_str(text.as_slice()),
+        Element(..) => for child in node.children.iter() {
+            push_text(child, out);
+        },
+        _ => (),
+    }
+}
+
+/// The text of a node, with runs of whitespace collapsed to a single
+/// space and leading and trailing whitespace removed.
+pub fn cell_text(handle: &Handle) -> String {
+    let mut text = String::new();
+    push_text(handle, &mut text);
+    let mut out = String::new();
+    for word in text.as_slice().split(is_ascii_whitespace).filter(|w| !w.is_empty()) {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out
+}
+
+//§ forming-a-table
+impl Table {
+    /// Form the table for an HTML `<table>` element.  Returns `None` for
+    /// any other node.
+    ///
+    /// Rows come from the table's `<thead>`, `<tbody>` and `<tr>`
+    /// children in order, then its `<tfoot>`s.  Cells of nested tables
+    /// are not included.
+    pub fn from_element(table: &Handle) -> Option<Table> {
+        match html_name(table) {
+            Some(ref name) if name.local.as_slice() == "table" => (),
+            _ => return None,
+        }
+
+        let mut result = Table {
+            caption: children_named(table, &["caption"]).into_iter().next(),
+            cells: vec!(),
+            height: 0,
+            width: 0,
+            slots: vec!(),
+        };
+
+        for col in children_named(table, &["colgroup"]).iter() {
+            let cols = children_named(col, &["col"]);
+            result.width += if cols.is_empty() {
+                cmp::min(cmp::max(span_attr(col, "span").unwrap_or(1), 1), 1000)
+            } else {
+                cols.iter()
+                    .map(|c| cmp::min(cmp::max(span_attr(c, "span").unwrap_or(1), 1), 1000))
+                    .fold(0, |a, b| a + b)
+            };
+        }
+
+        for child in children_named(table, &["thead", "tbody", "tr"]).iter() {
+            if html_name(child).unwrap().local.as_slice() == "tr" {
+                result.process_rows(vec!(child.clone()));
+            } else {
+                result.process_rows(children_named(child, &["tr"]));
+            }
+        }
+        for child in children_named(table, &["tfoot"]).iter() {
+            result.process_rows(children_named(child, &["tr"]));
+        }
+
+        Some(result)
+    }
+
+    // Process the rows of one row group, or a lone row.
+    fn process_rows(&mut self, rows: Vec<Handle>) {
+        let group_start = self.height;
+        let group_end = group_start + rows.len();
+        for (i, row) in rows.iter().enumerate() {
+            let y = group_start + i;
+            self.grow(y + 1, 0);
+            let mut x = 0;
+            for cell in children_named(row, &["td", "th"]).into_iter() {
+                while x < self.slots[y].len() && self.slots[y][x].is_some() {
+                    x += 1;
+                }
+
+                let colspan = cmp::min(cmp::max(span_attr(&cell, "colspan").unwrap_or(1), 1), 1000);
+                let rowspan = match cmp::min(span_attr(&cell, "rowspan").unwrap_or(1), 65534) {
+                    // Zero means the rest of the row group.
+                    0 => group_end - y,
+                    n => n,
+                };
+
+                self.grow(y + rowspan, x + colspan);
+                let index = self.cells.len();
+                for slot_y in range(y, y + rowspan) {
+                    for slot_x in range(x, x + colspan) {
+                        self.slots[slot_y][slot_x] = Some(index);
+                    }
+                }
+
+                let header = html_name(&cell).unwrap().local.as_slice() == "th";
+                self.cells.push(Cell {
+                    handle: cell,
+                    row: y,
+                    col: x,
+                    rowspan: rowspan,
+                    colspan: colspan,
+                    header: header,
+                });
+                x += colspan;
+            }
+        }
+
+        // Rows spanned past the end of the group don't count.
+        self.slots.truncate(group_end);
+        for cell in self.cells.iter_mut().filter(|c| c.row >= group_start) {
+            cell.rowspan = cmp::min(cell.rowspan, group_end - cell.row);
+        }
+        self.height = self.slots.len();
+    }
+
+    // Make sure the grid has at least this many rows and columns.
+    fn grow(&mut self, height: uint, width: uint) {
+        self.width = cmp::max(self.width, width);
+        while self.slots.len() < height {
+            self.slots.push(vec!());
+        }
+        let width = self.width;
+        for row in self.slots.iter_mut() {
+            while row.len() < width {
+                row.push(None);
+            }
+        }
+    }
+
+    /// The cell covering a slot, if any.
+    pub fn cell_at<'a>(&'a self, row: uint, col: uint) -> Option<&'a Cell> {
+        self.slots.get(row)
+            .and_then(|r| r.get(col))
+            .and_then(|&i| i)
+            .map(|i| &self.cells[i])
+    }
+
+    /// The text of every slot, by row.  A cell spanning several slots
+    /// has its text repeated in each, and empty slots are empty strings.
+    pub fn text_grid(&self) -> Vec<Vec<String>> {
+        let texts: Vec<String> = self.cells.iter().map(|c| cell_text(&c.handle)).collect();
+        range(0, self.height).map(|row| {
+            range(0, self.width).map(|col| {
+                match self.slots[row].get(col) {
+                    Some(&Some(i)) => texts[i].clone(),
+                    _ => String::new(),
+                }
+            }).collect()
+        }).collect()
+    }
+}
+//§ END
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::Table;
+    use sink::common::Element;
+    use sink::rcdom::{RcDom, Handle};
+    use driver::parse_str;
+
+    fn find_table(handle: &Handle) -> Option<Handle> {
+        let node = handle.borrow();
+        match node.node {
+            Element(ref name, _) if name.local.as_slice() == "table" => return Some(handle.clone()),
+            _ => (),
+        }
+        node.children.iter().filter_map(find_table).next()
+    }
+
+    fn grid(input: &str) -> Vec<Vec<String>> {
+        let dom: RcDom = parse_str(input);
+        let table = find_table(&dom.document).unwrap();
+        Table::from_element(&table).unwrap().text_grid()
+    }
+
+    fn row(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|&c| String::from_str(c)).collect()
+    }
+
+    #[test]
+    fn spans() {
+        assert_eq!(grid("<table><tr><th colspan=2>a</th><td rowspan=2>b\
+                <tr><td>c<td>d\
+                <tr><td>e</table>"),
+            vec!(row(&["a", "a", "b"]), row(&["c", "d", "b"]), row(&["e", "", ""])));
+    }
+
+    #[test]
+    fn row_groups() {
+        assert_eq!(grid("<table><tfoot><tr><td>foot</tfoot>\
+                <thead><tr><td rowspan=0>h<td>1<tr><td>2</thead>\
+                <tbody><tr><td rowspan=5>x<td>y</table>"),
+            vec!(row(&["h", "1"]), row(&["h", "2"]), row(&["x", "y"]), row(&["foot", ""])));
+    }
+
+    #[test]
+    fn nested_and_caption() {
+        let dom: RcDom = parse_str(
+            "<table><caption>Cap</caption><tr><td><table><tr><td>in</table> out</table>");
+        let table = Table::from_element(&find_table(&dom.document).unwrap()).unwrap();
+        assert!(table.caption.is_some());
+        assert_eq!((table.width, table.height), (1, 1));
+        assert!(table.cell_at(0, 0).unwrap().rowspan == 1);
+        assert!(table.cell_at(0, 1).is_none());
+        assert_eq!(table.text_grid(), vec!(row(&["in out"])));
+        assert!(Table::from_element(&dom.document).is_none());
+    }
+}
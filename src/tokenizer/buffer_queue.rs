@@ -11,7 +11,9 @@ use core::prelude::*;
 
 use util::str::AsciiCast;
 use util::smallcharset::SmallCharSet;
+use tokenizer::Pos;
 
+use core::default::Default;
 use core::str::CharRange;
 use collections::string::String;
 use collections::RingBuf;
@@ -37,6 +39,15 @@ pub enum SetResult {
 pub struct BufferQueue {
     /// Buffers to process.
     buffers: RingBuf<Buffer>,
+
+    /// Are we keeping track of the position?
+    tracking: bool,
+
+    /// Position of the next character to be consumed.
+    position: Pos,
+
+    /// Was the last character consumed a carriage return?
+    last_was_cr: bool,
 }
 
 impl BufferQueue {
@@ -44,6 +55,48 @@ impl BufferQueue {
     pub fn new() -> BufferQueue {
         BufferQueue {
             buffers: RingBuf::with_capacity(3),
+            tracking: false,
+            position: Default::default(),
+            last_was_cr: false,
+        }
+    }
+
+    /// Drop all the buffers and go back to the start of the input,
+    /// keeping the queue's capacity.
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+        self.position = Default::default();
+        self.last_was_cr = false;
+    }
+
+    /// Keep track of the position of consumed characters from now on.
+    pub fn track_positions(&mut self) {
+        self.tracking = true;
+    }
+
+    /// Position of the next character to be consumed.  Always the start
+    /// of the input unless `track_positions` was called.
+    pub fn position(&self) -> Pos {
+        self.position
+    }
+
+    fn advance_char(&mut self, c: char) {
+        let pos = &mut self.position;
+        pos.offset += c.len_utf8();
+        match c {
+            '\n' if self.last_was_cr => (),
+            '\r' | '\n' => {
+                pos.line += 1;
+                pos.column = 1;
+            }
+            _ => pos.column += 1,
+        }
+        self.last_was_cr = c == '\r';
+    }
+
+    fn advance(&mut self, s: &str) {
+        for c in s.chars() {
+            self.advance_char(c);
         }
     }
 
@@ -52,6 +105,11 @@ impl BufferQueue {
         if buf.len() == 0 {
             return;
         }
+        if self.tracking {
+            // Unconsumed characters never include a newline.
+            self.position.offset -= buf.len();
+            self.position.column -= buf.as_slice().chars().count();
+        }
         self.buffers.push_front(Buffer {
             pos: 0,
             buf: buf,
@@ -94,6 +152,11 @@ impl BufferQueue {
             self.buffers.pop_front();
         }
 
+        match result {
+            Some(c) if self.tracking => self.advance_char(c),
+            _ => (),
+        }
+
         result
     }
 
@@ -124,9 +187,73 @@ impl BufferQueue {
             self.buffers.pop_front();
         }
 
+        if self.tracking {
+            match result {
+                Some(FromSet(c)) => self.advance_char(c),
+                Some(NotFromSet(ref s)) => self.advance(s.as_slice()),
+                None => (),
+            }
+        }
+
         result
     }
 
+    /// Consume a run of the characters `[a-z0-9-]`, which are common in
+    /// tag and attribute names, from the front buffer, and append them
+    /// to `out`.  Returns the number of characters consumed.
+    pub fn pop_name_chars(&mut self, out: &mut String) -> uint {
+        let (n, now_empty) = match self.buffers.front_mut() {
+            Some(&mut Buffer { ref mut pos, ref buf }) => {
+                let n = buf.as_bytes()[*pos..].iter().take_while(|&&b| match b {
+                    b'a'...b'z' | b'0'...b'9' | b'-' => true,
+                    _ => false,
+                }).count();
+                out.push_str(&buf[*pos..*pos + n]);
+                *pos += n;
+                (n, *pos >= buf.len())
+            }
+            None => (0, false),
+        };
+
+        if now_empty {
+            self.buffers.pop_front();
+        }
+
+        if self.tracking && n > 0 {
+            self.position.offset += n;
+            self.position.column += n;
+            self.last_was_cr = false;
+        }
+
+        n
+    }
+
+    /// Consume a run of characters not in `set` from the front buffer,
+    /// as `pop_except_from` would, but append them to `out` rather than
+    /// returning a new `String`.  Returns the number of bytes consumed.
+    pub fn pop_except_from_into(&mut self, set: SmallCharSet, out: &mut String) -> uint {
+        let start = out.len();
+        let (n, now_empty) = match self.buffers.front_mut() {
+            Some(&mut Buffer { ref mut pos, ref buf }) => {
+                let n = set.nonmember_prefix_len(&buf[*pos..]);
+                out.push_str(&buf[*pos..*pos + n]);
+                *pos += n;
+                (n, *pos >= buf.len())
+            }
+            None => (0, false),
+        };
+
+        if now_empty {
+            self.buffers.pop_front();
+        }
+
+        if self.tracking && n > 0 {
+            self.advance(&out[start..]);
+        }
+
+        n
+    }
+
     // Check if the next characters are an ASCII case-insensitive match for
     // `pat`, which must be non-empty.
     //
@@ -134,6 +261,15 @@ impl BufferQueue {
     // If they do not match, return Some(false).
     // If not enough characters are available to know, return None.
     pub fn eat(&mut self, pat: &str) -> Option<bool> {
+        self.eat_with_case(pat, false)
+    }
+
+    // Like `eat`, but the match is case-sensitive.
+    pub fn eat_exact(&mut self, pat: &str) -> Option<bool> {
+        self.eat_with_case(pat, true)
+    }
+
+    fn eat_with_case(&mut self, pat: &str, exact: bool) -> Option<bool> {
         let mut buffers_exhausted = 0u;
         let mut consumed_from_last = match self.buffers.front() {
             None => return None,
@@ -148,7 +284,12 @@ impl BufferQueue {
 
             let d = buf.buf.as_slice().char_at(consumed_from_last);
             match (c.to_ascii_opt(), d.to_ascii_opt()) {
-                (Some(c), Some(d)) => if c.eq_ignore_case(d) { () } else { return Some(false) },
+                (Some(c), Some(d)) => {
+                    let same = if exact { c == d } else { c.eq_ignore_case(d) };
+                    if !same {
+                        return Some(false);
+                    }
+                }
                 _ => return Some(false),
             }
 
@@ -170,6 +311,10 @@ impl BufferQueue {
             Some(ref mut buf) => buf.pos = consumed_from_last,
         }
 
+        if self.tracking {
+            self.advance(pat);
+        }
+
         Some(true)
     }
 }
@@ -213,6 +358,28 @@ mod test {
         assert_eq!(bq.next(), None);
     }
 
+    #[test]
+    fn can_pop_name_chars() {
+        let mut bq = BufferQueue::new();
+        bq.push_back(String::from_str("my-tag2 x"), 0);
+        let mut name = String::from_str("<");
+        assert_eq!(bq.pop_name_chars(&mut name), 7);
+        assert_eq!(name.as_slice(), "<my-tag2");
+        assert_eq!(bq.pop_name_chars(&mut name), 0);
+        assert_eq!(bq.next(), Some(' '));
+    }
+
+    #[test]
+    fn can_pop_except_set_into() {
+        let mut bq = BufferQueue::new();
+        bq.push_back(String::from_str("ab\ncd\"e"), 0);
+        let mut value = String::from_str("x");
+        assert_eq!(bq.pop_except_from_into(small_char_set!('"'), &mut value), 5);
+        assert_eq!(value.as_slice(), "xab\ncd");
+        assert_eq!(bq.pop_except_from_into(small_char_set!('"'), &mut value), 0);
+        assert_eq!(bq.next(), Some('"'));
+    }
+
     #[test]
     fn can_pop_except_set() {
         let mut bq = BufferQueue::new();
@@ -233,6 +400,30 @@ mod test {
         assert_eq!(bq.next(), None);
     }
 
+    #[test]
+    fn tracks_position() {
+        let mut bq = BufferQueue::new();
+        bq.track_positions();
+        bq.push_back(String::from_str("a\r\nb\u{e9}\nc"), 0);
+        assert_eq!(bq.next(), Some('a'));
+        assert_eq!(bq.next(), Some('\r'));
+        assert_eq!(bq.next(), Some('\n'));
+        let pos = bq.position();
+        assert_eq!((pos.offset, pos.line, pos.column), (3, 2, 1));
+        assert_eq!(bq.pop_except_from(small_char_set!('\n')),
+            Some(NotFromSet(String::from_str("b\u{e9}"))));
+        let pos = bq.position();
+        assert_eq!((pos.offset, pos.line, pos.column), (6, 2, 3));
+        bq.push_front(String::from_str("\u{e9}"));
+        let pos = bq.position();
+        assert_eq!((pos.offset, pos.line, pos.column), (4, 2, 2));
+        assert_eq!(bq.next(), Some('\u{e9}'));
+        assert_eq!(bq.next(), Some('\n'));
+        assert_eq!(bq.eat("c"), Some(true));
+        let pos = bq.position();
+        assert_eq!((pos.offset, pos.line, pos.column), (8, 3, 2));
+    }
+
     #[test]
     fn can_eat() {
         // This is not very comprehensive.  We rely on the tokenizer
@@ -247,4 +438,13 @@ mod test {
         assert_eq!(bq.next(), Some('c'));
         assert_eq!(bq.next(), None);
     }
+
+    #[test]
+    fn can_eat_exact() {
+        let mut bq = BufferQueue::new();
+        bq.push_back(String::from_str("[cdata[x"), 0);
+        assert_eq!(bq.eat_exact("[CDATA["), Some(false));
+        assert_eq!(bq.eat("[CDATA["), Some(true));
+        assert_eq!(bq.next(), Some('x'));
+    }
 }
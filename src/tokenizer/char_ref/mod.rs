@@ -13,8 +13,9 @@ use super::{Tokenizer, TokenSink};
 
 use util::str::{is_ascii_alnum, empty_str};
 
+use diagnostic::Diagnostic;
+
 use core::char::from_u32;
-use std::borrow::Cow::Borrowed;
 use collections::string::String;
 
 pub use self::Status::*;
@@ -22,6 +23,14 @@ use self::State::*;
 
 mod data;
 
+/// The length of the longest prefix of `name` which is the name of a
+/// character reference that's recognized without a `;`, such as `copy`
+/// in `&copy 2024`.
+pub fn unterminated_name_len(name: &str) -> Option<uint> {
+    name.char_indices().map(|(i, c)| i + c.len_utf8()).rev()
+        .find(|&len| data::NAMED_ENTITIES.get(&name[..len]).map_or(false, |m| m[0] != 0))
+}
+
 //§ tokenizing-character-references
 pub struct CharRef {
     /// The resulting character(s)
@@ -196,7 +205,7 @@ impl CharRefTokenizer {
     fn do_numeric_semicolon<Sink: TokenSink>(&mut self, tokenizer: &mut Tokenizer<Sink>) -> Status {
         match unwrap_or_return!(tokenizer.peek(), Stuck) {
             ';' => tokenizer.discard_char(),
-            _   => tokenizer.emit_error(Borrowed("Semicolon missing after numeric character reference")),
+            _   => tokenizer.emit_error(Diagnostic::error("Semicolon missing after numeric character reference")),
         };
         self.finish_numeric(tokenizer)
     }
@@ -209,7 +218,7 @@ impl CharRefTokenizer {
         }
 
         tokenizer.unconsume(unconsume);
-        tokenizer.emit_error(Borrowed("Numeric character reference without digits"));
+        tokenizer.emit_error(Diagnostic::error("Numeric character reference without digits"));
         self.finish_none()
     }
 
@@ -237,10 +246,10 @@ impl CharRefTokenizer {
         };
 
         if error {
-            let msg = format_if!(tokenizer.opts.exact_errors,
+            let error = error_if!(tokenizer.opts.exact_errors,
                 "Invalid numeric character reference",
                 "Invalid numeric character reference value 0x{:06X}", self.num);
-            tokenizer.emit_error(msg);
+            tokenizer.emit_error(error);
         }
 
         self.finish_one(c)
@@ -267,10 +276,10 @@ impl CharRefTokenizer {
     }
 
     fn emit_name_error<Sink: TokenSink>(&mut self, tokenizer: &mut Tokenizer<Sink>) {
-        let msg = format_if!(tokenizer.opts.exact_errors,
+        let error = error_if!(tokenizer.opts.exact_errors,
             "Invalid character reference",
             "Invalid character reference &{}", self.name_buf().as_slice());
-        tokenizer.emit_error(msg);
+        tokenizer.emit_error(error);
     }
 
     fn unconsume_name<Sink: TokenSink>(&mut self, tokenizer: &mut Tokenizer<Sink>) {
@@ -334,12 +343,12 @@ impl CharRefTokenizer {
                 let unconsume_all = match (self.addnl_allowed, last_matched, next_after) {
                     (_, ';', _) => false,
                     (Some(_), _, Some('=')) => {
-                        tokenizer.emit_error(Borrowed("Equals sign after character reference in attribute"));
+                        tokenizer.emit_error(Diagnostic::error("Equals sign after character reference in attribute"));
                         true
                     }
                     (Some(_), _, Some(c)) if is_ascii_alnum(c) => true,
                     _ => {
-                        tokenizer.emit_error(Borrowed("Character reference does not end with semicolon"));
+                        tokenizer.emit_error(Diagnostic::error("Character reference does not end with semicolon"));
                         false
                     }
                 };
@@ -380,7 +389,7 @@ impl CharRefTokenizer {
                     => drop(self.unconsume_numeric(tokenizer)),
 
                 Numeric(_) | NumericSemicolon => {
-                    tokenizer.emit_error(Borrowed("EOF in numeric character reference"));
+                    tokenizer.emit_error(Diagnostic::error("EOF in numeric character reference"));
                     self.finish_numeric(tokenizer);
                 }
 
@@ -393,7 +402,7 @@ impl CharRefTokenizer {
 
                 Octothorpe => {
                     tokenizer.unconsume(String::from_str("#"));
-                    tokenizer.emit_error(Borrowed("EOF after '#' in character reference"));
+                    tokenizer.emit_error(Diagnostic::error("EOF after '#' in character reference"));
                     self.finish_none();
                 }
             }
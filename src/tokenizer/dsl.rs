@@ -0,0 +1,136 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The macros in which the tokenizer's states are written.
+//!
+//! These were moved here out of the tokenizer module unchanged; the
+//! states themselves are still hand-written in `Tokenizer::step`.  Each
+//! is a `match` on the next input character, whose arms are sequences
+//! of actions written close to the wording of the spec:
+//!
+//! ```ignore
+//! //§ comment-end-dash-state
+//! states::CommentEndDash => loop { match get_char!(self) {
+//!     '-'  => go!(self: to CommentEnd),
+//!     '\0' => go!(self: error; append_comment "-\u{fffd}"; to Comment),
+//!     c    => go!(self: push_comment '-'; push_comment c; to Comment),
+//! }},
+//! ```
+//!
+//! Each `//§` comment names the section of the spec implemented below
+//! it, as does `State::spec_anchor`, so states can be audited against
+//! the spec one at a time.
+//!
+//! The macros name fields and methods of `Tokenizer`, the `states`
+//! module, and the helpers `option_push` and `append_strings`, all
+//! looked up where the macros are used, so they are only for use inside
+//! this tokenizer.
+
+// Shorthand for common state machine behaviors.
+macro_rules! shorthand (
+    ( $me:ident : emit $c:expr                     ) => ( $me.emit_char($c);                                   );
+    ( $me:ident : create_tag $kind:ident $c:expr   ) => ( $me.create_tag($kind, $c);                           );
+    ( $me:ident : push_tag $c:expr                 ) => ( $me.current_tag_name.push($c);                       );
+    ( $me:ident : discard_tag                      ) => ( $me.discard_tag();                                   );
+    ( $me:ident : push_temp $c:expr                ) => ( $me.temp_buf.push($c);                               );
+    ( $me:ident : emit_temp                        ) => ( $me.emit_temp_buf();                                 );
+    ( $me:ident : clear_temp                       ) => ( $me.clear_temp_buf();                                );
+    ( $me:ident : create_attr $c:expr              ) => ( $me.create_attribute($c);                            );
+    ( $me:ident : push_name $c:expr                ) => ( $me.current_attr_name.push($c);                      );
+    ( $me:ident : push_value $c:expr               ) => ( $me.current_attr_value.push($c);                     );
+    ( $me:ident : append_value $c:expr             ) => ( $me.current_attr_value.push_str($c.as_slice());      );
+    ( $me:ident : push_comment $c:expr             ) => ( $me.current_comment.push($c);                        );
+    ( $me:ident : append_comment $c:expr           ) => ( $me.current_comment.push_str($c);                    );
+    ( $me:ident : emit_comment                     ) => ( $me.emit_current_comment();                          );
+    ( $me:ident : clear_comment                    ) => ( $me.current_comment.truncate(0);                     );
+    ( $me:ident : create_doctype                   ) => ( $me.current_doctype = Doctype::new();                );
+    ( $me:ident : push_doctype_name $c:expr        ) => ( option_push(&mut $me.current_doctype.name, $c);      );
+    ( $me:ident : push_doctype_id $k:ident $c:expr ) => ( option_push($me.doctype_id($k), $c);                 );
+    ( $me:ident : clear_doctype_id $k:ident        ) => ( $me.clear_doctype_id($k);                            );
+    ( $me:ident : force_quirks                     ) => ( $me.current_doctype.force_quirks = true;             );
+    ( $me:ident : emit_doctype                     ) => ( $me.emit_current_doctype();                          );
+    ( $me:ident : discard_char                     ) => ( $me.discard_char();                                  );
+    ( $me:ident : error                            ) => ( $me.bad_char_error();                                );
+    ( $me:ident : error_eof                        ) => ( $me.bad_eof_error();                                 );
+);
+
+// Tracing of tokenizer actions.  This adds significant bloat and compile time,
+// so it's behind a cfg flag.
+#[cfg(trace_tokenizer)]
+macro_rules! sh_trace ( ( $me:ident : $($cmds:tt)* ) => ({
+    h5e_debug!("  {:s}", stringify!($($cmds)*));
+    shorthand!($me:expr : $($cmds)*);
+}));
+
+#[cfg(not(trace_tokenizer))]
+macro_rules! sh_trace ( ( $me:ident : $($cmds:tt)* ) => ( shorthand!($me: $($cmds)*) ) );
+
+// A little DSL for sequencing shorthand actions.
+macro_rules! go (
+    // A pattern like $($cmd:tt)* ; $($rest:tt)* causes parse ambiguity.
+    // We have to tell the parser how much lookahead we need.
+
+    ( $me:ident : $a:tt                   ; $($rest:tt)* ) => ({ sh_trace!($me: $a);          go!($me: $($rest)*); });
+    ( $me:ident : $a:tt $b:tt             ; $($rest:tt)* ) => ({ sh_trace!($me: $a $b);       go!($me: $($rest)*); });
+    ( $me:ident : $a:tt $b:tt $c:tt       ; $($rest:tt)* ) => ({ sh_trace!($me: $a $b $c);    go!($me: $($rest)*); });
+    ( $me:ident : $a:tt $b:tt $c:tt $d:tt ; $($rest:tt)* ) => ({ sh_trace!($me: $a $b $c $d); go!($me: $($rest)*); });
+
+    // These can only come at the end.
+
+    ( $me:ident : to $s:ident                    ) => ({ $me.state = states::$s; return true;           });
+    ( $me:ident : to $s:ident $k1:expr           ) => ({ $me.state = states::$s($k1); return true;      });
+    ( $me:ident : to $s:ident $k1:ident $k2:expr ) => ({ $me.state = states::$s($k1($k2)); return true; });
+
+    ( $me:ident : consume_char_ref             ) => ({ $me.consume_char_ref(None); return true;         });
+    ( $me:ident : consume_char_ref $addnl:expr ) => ({ $me.consume_char_ref(Some($addnl)); return true; });
+
+    // We have a default next state after emitting a tag, but the sink can override.
+    ( $me:ident : emit_tag $s:ident ) => ({
+        $me.state = states::$s;
+        $me.emit_current_tag();
+        return true;
+    });
+
+    ( $me:ident : eof ) => ({ $me.emit_eof(); return false; });
+
+    // If nothing else matched, it's a single command
+    ( $me:ident : $($cmd:tt)+ ) => ( sh_trace!($me: $($cmd)+); );
+
+    // or nothing.
+    ( $me:ident : ) => (());
+);
+
+macro_rules! go_match ( ( $me:ident : $x:expr, $($pats:pat),+ => $($cmds:tt)* ) => (
+    match $x {
+        $($pats)|+ => go!($me: $($cmds)*),
+        _ => (),
+    }
+));
+
+// This is a macro because it can cause early return
+// from the function where it is used.
+macro_rules! get_char ( ($me:expr) => (
+    unwrap_or_return!($me.get_char(), false)
+));
+
+macro_rules! peek_char ( ($me:expr) => (
+    unwrap_or_return!($me.peek_char(), false)
+));
+
+macro_rules! pop_except_from ( ($me:expr, $set:expr) => (
+    unwrap_or_return!($me.pop_except_from($set), false)
+));
+
+macro_rules! eat ( ($me:expr, $pat:expr) => (
+    unwrap_or_return!($me.eat($pat), false)
+));
+
+macro_rules! eat_exact ( ($me:expr, $pat:expr) => (
+    unwrap_or_return!($me.eat_exact($pat), false)
+));
@@ -9,19 +9,22 @@
 
 use core::option::Option::{self, None};
 use core::clone::Clone;
+use core::default::Default;
 
 use tokenizer::states;
 
+use diagnostic::Diagnostic;
+
 use collections::vec::Vec;
 use collections::slice::SliceExt;
 use collections::string::String;
-use std::string::CowString;
 
 use string_cache::{Atom, QualName};
 
 pub use self::TagKind::{StartTag, EndTag};
 pub use self::Token::{DoctypeToken, TagToken, CommentToken, CharacterTokens};
-pub use self::Token::{NullCharacterToken, EOFToken, ParseError};
+pub use self::Token::{BogusCommentToken, NullCharacterToken, EOFToken, ParseError};
+pub use self::TokenSinkResult::{Continue, Stop};
 
 /// A `DOCTYPE` token.
 // FIXME: already exists in Servo DOM
@@ -44,6 +47,38 @@ impl Doctype {
     }
 }
 
+/// A position in the input.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash, Debug)]
+pub struct Pos {
+    /// Offset in bytes from the start of the input, not counting a
+    /// discarded byte order mark.
+    pub offset: uint,
+
+    /// Line number, starting at 1.  CR, LF and CRLF each end a line.
+    pub line: uint,
+
+    /// Column number, in characters, starting at 1.
+    pub column: uint,
+}
+
+impl Default for Pos {
+    fn default() -> Pos {
+        Pos {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+/// The part of the input a token or node came from, from `start` up to
+/// but not including `end`.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug, Default)]
+pub struct Span {
+    pub start: Pos,
+    pub end: Pos,
+}
+
 /// A tag attribute.
 ///
 /// The namespace on the attribute name is almost always ns!("").
@@ -93,16 +128,35 @@ pub enum Token {
     DoctypeToken(Doctype),
     TagToken(Tag),
     CommentToken(String),
+
+    /// A bogus comment, such as `<!x>`, `</%x>` or `<?x>`, with the text
+    /// after the `<!`, `</` or `<`.  These are only produced if the
+    /// tokenizer's `mark_bogus_comments` option is set; otherwise they
+    /// are `CommentToken`s.
+    BogusCommentToken(String),
+
     CharacterTokens(String),
     NullCharacterToken,
     EOFToken,
-    ParseError(CowString<'static>),
+    ParseError(Diagnostic),
+}
+
+/// What the tokenizer should do after a token is processed.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum TokenSinkResult {
+    /// Keep tokenizing.
+    Continue,
+
+    /// Stop tokenizing immediately, ignoring the rest of the input.
+    /// The sink will get no more tokens, except for an `EOFToken` when
+    /// the tokenizer's `end()` is called.
+    Stop,
 }
 
 /// Types which can receive tokens from the tokenizer.
 pub trait TokenSink {
     /// Process a token.
-    fn process_token(&mut self, token: Token);
+    fn process_token(&mut self, token: Token) -> TokenSinkResult;
 
     /// The tokenizer will call this after emitting any start tag.
     /// This allows the tree builder to change the tokenizer's state.
@@ -110,4 +164,16 @@ pub trait TokenSink {
     fn query_state_change(&mut self) -> Option<states::State> {
         None
     }
+
+    /// The tokenizer will call this before each token, with the part of
+    /// the input the token came from, if the `track_positions` option
+    /// is set.
+    fn set_current_span(&mut self, _span: Span) { }
+
+    /// Is the tree builder's adjusted current node an element outside
+    /// the HTML namespace?  Only there does `<![CDATA[` start a CDATA
+    /// section; elsewhere it's a bogus comment.  By default, no.
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        false
+    }
 }
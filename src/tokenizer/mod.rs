@@ -8,6 +8,25 @@
 // except according to those terms.
 
 //! The HTML5 tokenizer.
+//!
+//! ## U+0000 NULL
+//!
+//! NUL characters in the input are dealt with as the spec says for
+//! each state:
+//!
+//! * In the data and CDATA section states, a NUL is passed to the sink
+//!   as a `NullCharacterToken`, with a parse error in the data state.
+//!   The tree builder ignores it, except in foreign content, where it
+//!   becomes `U+FFFD REPLACEMENT CHARACTER`.
+//! * In the bogus comment state, it becomes `U+FFFD` without a parse
+//!   error.
+//! * Everywhere else it can appear, in text, tag and attribute names,
+//!   attribute values, comments and doctypes, it becomes `U+FFFD` with
+//!   a parse error.
+//!
+//! So NUL never reaches a `CharacterTokens`, a tag, a comment or a
+//! doctype.  Consumers which can't handle `U+FFFD` either can set
+//! `TokenizerOpts::strip_nul` to remove NULs before tokenizing.
 
 #![allow(unused_imports)]
 
@@ -17,10 +36,11 @@ use core::iter::{range, IteratorExt};
 use core::option::Option::{self, Some, None};
 use core::str::Str;
 
-pub use self::interface::{Doctype, Attribute, TagKind, StartTag, EndTag, Tag};
-pub use self::interface::{Token, DoctypeToken, TagToken, CommentToken};
+pub use self::interface::{Doctype, Attribute, TagKind, StartTag, EndTag, Tag, Pos, Span};
+pub use self::interface::{Token, DoctypeToken, TagToken, CommentToken, BogusCommentToken};
 pub use self::interface::{CharacterTokens, NullCharacterToken, EOFToken, ParseError};
-pub use self::interface::TokenSink;
+pub use self::interface::{TokenSink, TokenSinkResult, Continue, Stop};
+pub use self::char_ref::unterminated_name_len;
 
 use self::states::{RawLessThanSign, RawEndTagOpen, RawEndTagName};
 use self::states::{Rcdata, Rawtext, ScriptData, ScriptDataEscaped};
@@ -35,6 +55,8 @@ use self::buffer_queue::{BufferQueue, SetResult, FromSet, NotFromSet};
 use util::str::{lower_ascii, lower_ascii_letter, empty_str};
 use util::smallcharset::SmallCharSet;
 
+use diagnostic::Diagnostic;
+
 use core::mem::replace;
 use core::default::Default;
 use alloc::boxed::Box;
@@ -42,13 +64,14 @@ use collections::vec::Vec;
 use collections::slice::SliceExt;
 use collections::string::{String, ToString};
 use collections::str::StrExt;
-use std::string::CowString;
-use std::borrow::Cow::Borrowed;
-use std::collections::BTreeMap;
+use collections::BTreeMap;
 
 use string_cache::{Atom, QualName};
 
 pub mod states;
+pub mod record;
+pub mod splits;
+#[macro_use] mod dsl;
 mod interface;
 mod char_ref;
 mod buffer_queue;
@@ -60,6 +83,20 @@ fn option_push(opt_str: &mut Option<String>, c: char) {
     }
 }
 
+// The parse error, if any, for a code point which made it through
+// preprocessing.  These are the spec's control-character-in-input-stream
+// and noncharacter-in-input-stream errors.  NUL is dealt with by the
+// individual states.
+fn preprocessing_error(c: char) -> Option<&'static str> {
+    match c as u32 {
+        0x01...0x08 | 0x0B | 0x0E...0x1F | 0x7F...0x9F
+            => Some("Control character in input stream"),
+        0xFDD0...0xFDEF => Some("Noncharacter in input stream"),
+        n if (n & 0xFFFE) == 0xFFFE => Some("Noncharacter in input stream"),
+        _ => None,
+    }
+}
+
 fn append_strings(lhs: &mut String, rhs: String) {
     if lhs.is_empty() {
         *lhs = rhs;
@@ -83,13 +120,35 @@ pub struct TokenizerOpts {
     /// when `end()` is called.  Default: false
     pub profile: bool,
 
-    /// Initial state override.  Only the test runner should use
-    /// a non-`None` value!
+    /// Initial state override, for tokenizing a fragment which is
+    /// known to be e.g. the contents of a `<textarea>`.  Default: `None`,
+    /// meaning the data state.
     pub initial_state: Option<states::State>,
 
-    /// Last start tag.  Only the test runner should use a
-    /// non-`None` value!
+    /// Last start tag, which decides what is an appropriate end tag in
+    /// the RCDATA, RAWTEXT and script data states.  Set this along with
+    /// `initial_state`.  Default: `None`
     pub last_start_tag_name: Option<String>,
+
+    /// Keep track of where each token is in the input, and pass
+    /// it to the sink's `set_current_span`?  Default: false
+    pub track_positions: bool,
+
+    /// Coalesce runs of text into `CharacterTokens` of up to about this
+    /// many bytes, rather than passing each piece to the sink as soon as
+    /// it's tokenized.  Text is always passed on before any other token,
+    /// and at the end of each call to `feed`.  Default: 0, meaning no
+    /// coalescing.
+    pub max_text_batch: uint,
+
+    /// Pass bogus comments, such as `<!x>` and `</%x>`, to the sink as
+    /// `BogusCommentToken` rather than `CommentToken`?  Default: false
+    pub mark_bogus_comments: bool,
+
+    /// Remove `U+0000 NULL` from the input as it's fed in, so that it
+    /// neither reaches the sink nor causes parse errors.  Positions then
+    /// refer to the input without NULs.  Default: false
+    pub strip_nul: bool,
 }
 
 impl Default for TokenizerOpts {
@@ -100,6 +159,10 @@ impl Default for TokenizerOpts {
             profile: false,
             initial_state: None,
             last_start_tag_name: None,
+            track_positions: false,
+            max_text_batch: 0,
+            mark_bogus_comments: false,
+            strip_nul: false,
         }
     }
 }
@@ -122,6 +185,17 @@ pub struct Tokenizer<Sink> {
     /// completely? This affects whether we will wait for lookahead or not.
     at_eof: bool,
 
+    /// Has the sink asked us to stop?
+    stopped: bool,
+
+    /// Position of the character, or run of characters, most recently
+    /// taken from the input.  Only kept if `track_positions` is set.
+    char_start: Pos,
+
+    /// Position of the `<` which began the current tag, comment or
+    /// doctype.  Only kept if `track_positions` is set.
+    markup_start: Pos,
+
     /// Tokenizer for character references, if we're tokenizing
     /// one at the moment.
     char_ref_tokenizer: Option<Box<CharRefTokenizer>>,
@@ -129,13 +203,19 @@ pub struct Tokenizer<Sink> {
     /// Current input character.  Just consumed, may reconsume.
     current_char: char,
 
-    /// Should we reconsume the current input character?
+    /// Should we reconsume the current input character?  Only set by
+    /// `peek_char`, when it had to consume the character it peeked at.
     reconsume: bool,
 
     /// Did we just consume \r, translating it to \n?  In that case we need
     /// to ignore the next character if it's \n.
     ignore_lf: bool,
 
+    /// How many characters at the front of the input buffers were
+    /// unconsumed, and so have already been through preprocessing?
+    /// Only counted when reporting exact errors.
+    reread_chars: uint,
+
     /// Discard a U+FEFF BYTE ORDER MARK if we see one?  Only done at the
     /// beginning of the stream.
     discard_bom: bool,
@@ -155,9 +235,17 @@ pub struct Tokenizer<Sink> {
     /// Current attribute name.
     current_attr_name: String,
 
-    /// Current attribute value.
+    /// Current attribute value.  The token gets a copy, so the buffer
+    /// can be reused for the next value.
     current_attr_value: String,
 
+    /// How many times the tag name and attribute buffers have grown,
+    /// which the tests use to check that they're being reused.
+    scratch_allocs: uint,
+
+    /// The total capacity of those buffers when last checked.
+    scratch_capacity: uint,
+
     /// Current comment.
     current_comment: String,
 
@@ -170,6 +258,12 @@ pub struct Tokenizer<Sink> {
     /// The "temporary buffer" mentioned in the spec.
     temp_buf: String,
 
+    /// Text not yet passed to the sink, if `max_text_batch` is set.
+    pending_text: String,
+
+    /// Where `pending_text` starts and ends, if tracking positions.
+    pending_text_span: Span,
+
     /// Record of how many ns we spent in each state, if profiling is enabled.
     state_profile: BTreeMap<states::State, u64>,
 
@@ -180,7 +274,7 @@ pub struct Tokenizer<Sink> {
 impl<Sink: TokenSink> Tokenizer<Sink> {
     /// Create a new tokenizer which feeds tokens to a particular `TokenSink`.
     pub fn new(sink: Sink, mut opts: TokenizerOpts) -> Tokenizer<Sink> {
-        if opts.profile && cfg!(for_c) {
+        if opts.profile && !cfg!(feature = "std") {
             panic!("Can't profile tokenizer when built as a C library");
         }
 
@@ -188,16 +282,24 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             .map(|s| Atom::from_slice(s.as_slice()));
         let state = opts.initial_state.unwrap_or(states::Data);
         let discard_bom = opts.discard_bom;
+        let mut input_buffers = BufferQueue::new();
+        if opts.track_positions {
+            input_buffers.track_positions();
+        }
         Tokenizer {
             opts: opts,
             sink: sink,
             state: state,
             char_ref_tokenizer: None,
-            input_buffers: BufferQueue::new(),
+            input_buffers: input_buffers,
             at_eof: false,
+            stopped: false,
+            char_start: Default::default(),
+            markup_start: Default::default(),
             current_char: '\0',
             reconsume: false,
             ignore_lf: false,
+            reread_chars: 0,
             discard_bom: discard_bom,
             current_tag_kind: StartTag,
             current_tag_name: empty_str(),
@@ -205,15 +307,52 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             current_tag_attrs: vec!(),
             current_attr_name: empty_str(),
             current_attr_value: empty_str(),
+            scratch_allocs: 0,
+            scratch_capacity: 0,
             current_comment: empty_str(),
             current_doctype: Doctype::new(),
             last_start_tag_name: start_tag_name,
             temp_buf: empty_str(),
+            pending_text: empty_str(),
+            pending_text_span: Default::default(),
             state_profile: BTreeMap::new(),
             time_in_sink: 0,
         }
     }
 
+    /// Get ready to tokenize another document from the start, as if
+    /// newly created with the same options, except that the last start
+    /// tag name given in the options is forgotten.  The sink is kept,
+    /// and so is the memory allocated for buffers.
+    pub fn reset(&mut self) {
+        self.state = self.opts.initial_state.unwrap_or(states::Data);
+        self.input_buffers.clear();
+        self.at_eof = false;
+        self.stopped = false;
+        self.char_start = Default::default();
+        self.markup_start = Default::default();
+        self.char_ref_tokenizer = None;
+        self.current_char = '\0';
+        self.reconsume = false;
+        self.ignore_lf = false;
+        self.reread_chars = 0;
+        self.discard_bom = self.opts.discard_bom;
+        self.current_tag_kind = StartTag;
+        self.current_tag_name.truncate(0);
+        self.current_tag_self_closing = false;
+        self.current_tag_attrs.truncate(0);
+        self.current_attr_name.truncate(0);
+        self.current_attr_value.truncate(0);
+        self.current_comment.truncate(0);
+        self.current_doctype = Doctype::new();
+        self.last_start_tag_name = None;
+        self.temp_buf.truncate(0);
+        self.pending_text.truncate(0);
+        self.pending_text_span = Default::default();
+        self.state_profile.clear();
+        self.time_in_sink = 0;
+    }
+
     pub fn unwrap(self) -> Sink {
         self.sink
     }
@@ -226,10 +365,75 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         &mut self.sink
     }
 
+    /// The current state.
+    ///
+    /// While a character reference is being consumed, this is the state
+    /// to return to afterwards.
+    pub fn state(&self) -> states::State {
+        self.state
+    }
+
+    /// Switch to another state, as the tree builder does when it sees
+    /// e.g. `<script>`.
+    ///
+    /// This should be called between calls to `feed`.  In the RCDATA,
+    /// RAWTEXT and script data states, an end tag is only recognized
+    /// if it matches the last start tag; pass the name of that tag,
+    /// or `None` to keep the last one seen.
+    pub fn set_state(&mut self, state: states::State, last_start_tag_name: Option<&str>) {
+        self.state = state;
+        match last_start_tag_name {
+            Some(name) => self.last_start_tag_name = Some(Atom::from_slice(name)),
+            None => (),
+        }
+    }
+
+    /// Has the sink returned `Stop`?  If so, further input is ignored.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// The name of the tag being tokenized, lowercased, as far as it has
+    /// been read.  Only meaningful in the tag states.
+    pub fn current_tag_name<'a>(&'a self) -> &'a str {
+        self.current_tag_name.as_slice()
+    }
+
+    /// The name of the attribute being tokenized, lowercased, as far as
+    /// it has been read.  Only meaningful in the attribute states.
+    pub fn current_attr_name<'a>(&'a self) -> &'a str {
+        self.current_attr_name.as_slice()
+    }
+
     /// Feed an input string into the tokenizer.
     pub fn feed(&mut self, input: String) {
-        if input.len() == 0 {
-            return;
+        if self.push_input(input) {
+            self.run();
+        }
+    }
+
+    /// Feed an input string, as `feed` does, but call `interrupt` with
+    /// the sink after every `every` steps of the state machine.  If it
+    /// returns true, tokenizing stops as if the sink had returned
+    /// `Stop`, and the rest of the input is dropped.
+    ///
+    /// A step may take a long run of text, or a long comment, in one go,
+    /// so this bounds the time between calls only roughly.
+    pub fn feed_interruptible<F>(&mut self, input: String, every: uint, interrupt: F)
+        where F: FnMut(&mut Sink) -> bool,
+    {
+        if self.push_input(input) {
+            self.run_interruptible(every, interrupt);
+        }
+    }
+
+    // Queue up input.  Returns false if there's nothing to tokenize.
+    fn push_input(&mut self, mut input: String) -> bool {
+        if self.opts.strip_nul && input.as_slice().contains_char('\0') {
+            input = input.as_slice().chars().filter(|&c| c != '\0').collect();
+        }
+        if input.len() == 0 || self.stopped {
+            return false;
         }
 
         let pos = if self.discard_bom && input.as_slice().char_at(0) == '\u{feff}' {
@@ -240,15 +444,68 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         };
 
         self.input_buffers.push_back(input, pos);
-        self.run();
+        true
     }
 
-    fn process_token(&mut self, token: Token) {
-        if self.opts.profile {
-            let (_, dt) = time!(self.sink.process_token(token));
+    // Where the current token ends.
+    fn token_end(&mut self) -> Pos {
+        if self.reconsume {
+            self.char_start
+        } else {
+            self.input_buffers.position()
+        }
+    }
+
+    fn process_token(&mut self, mut token: Token) {
+        if self.stopped {
+            return;
+        }
+        self.flush_text();
+
+        if self.opts.track_positions {
+            let start = match token {
+                TagToken(_) | CommentToken(_) | BogusCommentToken(_) | DoctypeToken(_)
+                    => self.markup_start,
+                EOFToken => self.input_buffers.position(),
+                _ => self.char_start,
+            };
+            let span = Span {
+                start: start,
+                end: self.token_end(),
+            };
+            match token {
+                ParseError(ref mut error) => error.span = Some(span),
+                _ => (),
+            }
+            self.sink.set_current_span(span);
+        }
+
+        self.send_token(token);
+    }
+
+    // Pass on text saved up by `emit_chars`.
+    fn flush_text(&mut self) {
+        if self.pending_text.is_empty() || self.stopped {
+            return;
+        }
+        let text = replace(&mut self.pending_text, empty_str());
+        if self.opts.track_positions {
+            self.sink.set_current_span(self.pending_text_span);
+        }
+        self.send_token(CharacterTokens(text));
+    }
+
+    fn send_token(&mut self, token: Token) {
+        let result = if self.opts.profile {
+            let (result, dt) = time!(self.sink.process_token(token));
             self.time_in_sink += dt;
+            result
         } else {
-            self.sink.process_token(token);
+            self.sink.process_token(token)
+        };
+
+        if result == Stop {
+            self.stopped = true;
         }
     }
 
@@ -259,6 +516,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         if self.ignore_lf {
             self.ignore_lf = false;
             if c == '\n' {
+                self.char_start = self.input_buffers.position();
                 c = unwrap_or_return!(self.input_buffers.next(), None);
             }
         }
@@ -268,15 +526,18 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             c = '\n';
         }
 
-        if self.opts.exact_errors && match c as u32 {
-            0x01...0x08 | 0x0B | 0x0E...0x1F | 0x7F...0x9F | 0xFDD0...0xFDEF => true,
-            n if (n & 0xFFFE) == 0xFFFE => true,
-            _ => false,
-        } {
-            // format_if!(true) will still use the static error when built for C.
-            let msg = format_if!(true, "Bad character",
-                "Bad character {}", c);
-            self.emit_error(msg);
+        if self.reread_chars > 0 {
+            // Already reported, if need be, when first consumed.
+            self.reread_chars -= 1;
+        } else if self.opts.exact_errors {
+            match preprocessing_error(c) {
+                Some(code) => {
+                    // error_if!(true) will still use the static error when built for C.
+                    let error = error_if!(true, code, "{}: U+{:04X}", code, c as u32);
+                    self.emit_error(error);
+                }
+                None => (),
+            }
         }
 
         h5e_debug!("got character {}", c);
@@ -287,34 +548,51 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
     //§ tokenization
     // Get the next input character, if one is available.
     fn get_char(&mut self) -> Option<char> {
-        if self.reconsume {
+        if self.stopped {
+            None
+        } else if self.reconsume {
             self.reconsume = false;
             Some(self.current_char)
         } else {
+            self.char_start = self.input_buffers.position();
             self.input_buffers.next()
                 .and_then(|c| self.get_preprocessed_char(c))
         }
     }
 
     fn pop_except_from(&mut self, set: SmallCharSet) -> Option<SetResult> {
+        if self.stopped {
+            return None;
+        }
+
         // Bail to the slow path for various corner cases.
         // This means that `FromSet` can contain characters not in the set!
         // It shouldn't matter because the fallback `FromSet` case should
         // always do the same thing as the `NotFromSet` case.
-        if self.opts.exact_errors || self.reconsume || self.ignore_lf {
-            return self.get_char().map(|x| FromSet(x));
-        }
-
-        let d = self.input_buffers.pop_except_from(set);
-        h5e_debug!("got characters {:?}", d);
-        match d {
-            Some(FromSet(c)) => self.get_preprocessed_char(c).map(|x| FromSet(x)),
+        let result = if self.opts.exact_errors || self.reconsume || self.ignore_lf {
+            self.get_char().map(|x| FromSet(x))
+        } else {
+            self.char_start = self.input_buffers.position();
+            let d = self.input_buffers.pop_except_from(set);
+            h5e_debug!("got characters {:?}", d);
+            match d {
+                Some(FromSet(c)) => self.get_preprocessed_char(c).map(|x| FromSet(x)),
+
+                // NB: We don't set self.current_char for a run of characters not
+                // in the set.  It shouldn't matter for the codepaths that use
+                // this.
+                _ => d
+            }
+        };
 
-            // NB: We don't set self.current_char for a run of characters not
-            // in the set.  It shouldn't matter for the codepaths that use
-            // this.
-            _ => d
+        // Remember where markup begins, for the span of the token.
+        match (self.state, &result) {
+            (states::Data, &Some(FromSet('<')))
+            | (states::RawData(_), &Some(FromSet('<'))) => self.markup_start = self.char_start,
+            _ => (),
         }
+
+        result
     }
 
     // Check if the next characters are an ASCII case-insensitive match.  See
@@ -323,72 +601,132 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
     // NB: this doesn't do input stream preprocessing or set the current input
     // character.
     fn eat(&mut self, pat: &str) -> Option<bool> {
+        if self.stopped {
+            return None;
+        }
+
         match self.input_buffers.eat(pat) {
             None if self.at_eof => Some(false),
             r => r,
         }
     }
 
+    // Like `eat`, but case-sensitive.
+    fn eat_exact(&mut self, pat: &str) -> Option<bool> {
+        if self.stopped {
+            return None;
+        }
+
+        match self.input_buffers.eat_exact(pat) {
+            None if self.at_eof => Some(false),
+            r => r,
+        }
+    }
+
     // Run the state machine for as long as we can.
     fn run(&mut self) {
         if self.opts.profile {
-            loop {
-                let state = self.state;
-                let old_sink = self.time_in_sink;
-                let (run, mut dt) = time!(self.step());
-                dt -= (self.time_in_sink - old_sink);
-                let new = match self.state_profile.get_mut(&state) {
-                    Some(x) => {
-                        *x += dt;
-                        false
-                    }
-                    None => true,
-                };
-                if new {
-                    // do this here because of borrow shenanigans
-                    self.state_profile.insert(state, dt);
-                }
-                if !run { break; }
+            while self.profiled_step() {
             }
         } else {
             while self.step() {
             }
         }
+        self.flush_text();
+    }
+
+    // Run the state machine for as long as we can, or until `interrupt`,
+    // called every `every` steps, returns true.
+    fn run_interruptible<F>(&mut self, every: uint, mut interrupt: F)
+        where F: FnMut(&mut Sink) -> bool,
+    {
+        let mut steps = 0u;
+        loop {
+            let run = if self.opts.profile { self.profiled_step() } else { self.step() };
+            if !run {
+                break;
+            }
+            steps += 1;
+            if steps >= every {
+                steps = 0;
+                if interrupt(&mut self.sink) {
+                    self.stopped = true;
+                    self.input_buffers.clear();
+                    break;
+                }
+            }
+        }
+        self.flush_text();
+    }
+
+    fn profiled_step(&mut self) -> bool {
+        let state = self.state;
+        let old_sink = self.time_in_sink;
+        let (run, mut dt) = time!(self.step());
+        dt -= (self.time_in_sink - old_sink);
+        let new = match self.state_profile.get_mut(&state) {
+            Some(x) => {
+                *x += dt;
+                false
+            }
+            None => true,
+        };
+        if new {
+            // do this here because of borrow shenanigans
+            self.state_profile.insert(state, dt);
+        }
+        run
     }
 
     fn bad_char_error(&mut self) {
-        let msg = format_if!(
+        let error = error_if!(
             self.opts.exact_errors,
             "Bad character",
             "Saw {} in state {:?}", self.current_char, self.state);
-        self.emit_error(msg);
+        self.emit_error(error);
     }
 
     fn bad_eof_error(&mut self) {
-        let msg = format_if!(
+        let error = error_if!(
             self.opts.exact_errors,
             "Unexpected EOF",
             "Saw EOF in state {:?}", self.state);
-        self.emit_error(msg);
+        self.emit_error(error);
     }
 
     fn emit_char(&mut self, c: char) {
-        self.process_token(match c {
-            '\0' => NullCharacterToken,
-            _ => CharacterTokens(c.to_string()),
-        });
+        match c {
+            '\0' => self.process_token(NullCharacterToken),
+            _ => self.emit_chars(c.to_string()),
+        }
     }
 
     // The string must not contain '\0'!
     fn emit_chars(&mut self, b: String) {
-        self.process_token(CharacterTokens(b));
+        if self.opts.max_text_batch == 0 {
+            self.process_token(CharacterTokens(b));
+            return;
+        }
+
+        if self.opts.track_positions {
+            if self.pending_text.is_empty() {
+                self.pending_text_span.start = self.char_start;
+            }
+            self.pending_text_span.end = self.token_end();
+        }
+        append_strings(&mut self.pending_text, b);
+        if self.pending_text.len() >= self.opts.max_text_batch {
+            self.flush_text();
+        }
     }
 
     fn emit_current_tag(&mut self) {
         self.finish_attribute();
+        self.count_scratch_allocs();
 
-        let name = replace(&mut self.current_tag_name, String::new());
-        let name = Atom::from_slice(name.as_slice());
+        // Names become atoms, so the buffer can be reused for the next tag.
+        let name = Atom::from_slice(self.current_tag_name.as_slice());
+        self.current_tag_name.truncate(0);
 
         match self.current_tag_kind {
             StartTag => {
@@ -396,10 +734,11 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             }
             EndTag => {
                 if !self.current_tag_attrs.is_empty() {
-                    self.emit_error(Borrowed("Attributes on an end tag"));
+                    self.emit_error(Diagnostic::error("Attributes on an end tag"));
+                    self.current_tag_attrs.truncate(0);
                 }
                 if self.current_tag_self_closing {
-                    self.emit_error(Borrowed("Self-closing end tag"));
+                    self.emit_error(Diagnostic::error("Self-closing end tag"));
                 }
             }
         }
@@ -432,11 +771,15 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
 
     fn emit_current_comment(&mut self) {
         let comment = replace(&mut self.current_comment, empty_str());
-        self.process_token(CommentToken(comment));
+        if self.opts.mark_bogus_comments && self.state == states::BogusComment {
+            self.process_token(BogusCommentToken(comment));
+        } else {
+            self.process_token(CommentToken(comment));
+        }
     }
 
     fn discard_tag(&mut self) {
-        self.current_tag_name = String::new();
+        self.current_tag_name.truncate(0);
         self.current_tag_self_closing = false;
         self.current_tag_attrs = vec!();
     }
@@ -447,6 +790,9 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         self.current_tag_kind = kind;
     }
 
+    // Is the end tag being built an appropriate end tag, i.e. does its name
+    // match the last start tag's?  The name is compared as it is, without
+    // interning it.
     fn have_appropriate_end_tag(&self) -> bool {
         match self.last_start_tag_name.as_ref() {
             Some(last) =>
@@ -456,6 +802,28 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         }
     }
 
+    // Note whether the tag name and attribute buffers grew while building
+    // this tag.
+    fn count_scratch_allocs(&mut self) {
+        let capacity = self.current_tag_name.capacity()
+            + self.current_attr_name.capacity()
+            + self.current_attr_value.capacity();
+        if capacity != self.scratch_capacity {
+            self.scratch_allocs += 1;
+            self.scratch_capacity = capacity;
+        }
+    }
+
+    // Append a run of value characters straight from the input buffers
+    // to the attribute value, without making a `String` for the run.
+    // This is skipped when reporting exact errors, since some of those
+    // characters are errors in unquoted values.
+    fn pop_value_chars(&mut self, set: SmallCharSet) {
+        if self.can_pop_name_chars() && !self.opts.exact_errors {
+            self.input_buffers.pop_except_from_into(set, &mut self.current_attr_value);
+        }
+    }
+
     fn create_attribute(&mut self, c: char) {
         self.finish_attribute();
 
@@ -476,16 +844,19 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         };
 
         if dup {
-            self.emit_error(Borrowed("Duplicate attribute"));
+            self.emit_error(Diagnostic::error("Duplicate attribute"));
             self.current_attr_name.truncate(0);
             self.current_attr_value.truncate(0);
         } else {
-            let name = replace(&mut self.current_attr_name, String::new());
+            let name = Atom::from_slice(self.current_attr_name.as_slice());
+            let value = String::from_str(self.current_attr_value.as_slice());
+            self.current_attr_name.truncate(0);
+            self.current_attr_value.truncate(0);
             self.current_tag_attrs.push(Attribute {
                 // The tree builder will adjust the namespace if necessary.
                 // This only happens in foreign elements.
-                name: QualName::new(ns!(""), Atom::from_slice(name.as_slice())),
-                value: replace(&mut self.current_attr_value, empty_str()),
+                name: QualName::new(ns!(""), name),
+                value: value,
             });
         }
     }
@@ -528,119 +899,54 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         }
     }
 
+    // Get the next input character without consuming it, so that a
+    // state which would reconsume it can just switch states instead.
+    //
+    // If the character needs preprocessing, or might be a parse error,
+    // we consume it as usual and set the reconsume flag, which has the
+    // same effect.
+    fn peek_char(&mut self) -> Option<char> {
+        if self.stopped {
+            return None;
+        }
+        if !(self.reconsume || self.ignore_lf || self.opts.exact_errors) {
+            match self.input_buffers.peek() {
+                Some('\r') => (),
+                c => return c,
+            }
+        }
+        let c = self.get_char();
+        if c.is_some() {
+            self.reconsume = true;
+        }
+        c
+    }
+
+    // Can we take a run of name characters straight from the input
+    // buffers?  They never need preprocessing, but a pending reconsume
+    // or CR must be dealt with first.
+    fn can_pop_name_chars(&self) -> bool {
+        !(self.stopped || self.reconsume || self.ignore_lf || self.reread_chars > 0)
+    }
+
     fn discard_char(&mut self) {
         let c = self.get_char();
         assert!(c.is_some());
     }
 
     fn unconsume(&mut self, buf: String) {
+        if self.opts.exact_errors {
+            self.reread_chars += buf.as_slice().chars().count();
+        }
         self.input_buffers.push_front(buf);
     }
 
-    fn emit_error(&mut self, error: CowString<'static>) {
+    fn emit_error(&mut self, error: Diagnostic) {
         self.process_token(ParseError(error));
     }
 }
 //§ END
 
-// Shorthand for common state machine behaviors.
-macro_rules! shorthand (
-    ( $me:ident : emit $c:expr                     ) => ( $me.emit_char($c);                                   );
-    ( $me:ident : create_tag $kind:ident $c:expr   ) => ( $me.create_tag($kind, $c);                           );
-    ( $me:ident : push_tag $c:expr                 ) => ( $me.current_tag_name.push($c);                       );
-    ( $me:ident : discard_tag                      ) => ( $me.discard_tag();                                   );
-    ( $me:ident : push_temp $c:expr                ) => ( $me.temp_buf.push($c);                               );
-    ( $me:ident : emit_temp                        ) => ( $me.emit_temp_buf();                                 );
-    ( $me:ident : clear_temp                       ) => ( $me.clear_temp_buf();                                );
-    ( $me:ident : create_attr $c:expr              ) => ( $me.create_attribute($c);                            );
-    ( $me:ident : push_name $c:expr                ) => ( $me.current_attr_name.push($c);                      );
-    ( $me:ident : push_value $c:expr               ) => ( $me.current_attr_value.push($c);                     );
-    ( $me:ident : append_value $c:expr             ) => ( append_strings(&mut $me.current_attr_value, $c);     );
-    ( $me:ident : push_comment $c:expr             ) => ( $me.current_comment.push($c);                        );
-    ( $me:ident : append_comment $c:expr           ) => ( $me.current_comment.push_str($c);                    );
-    ( $me:ident : emit_comment                     ) => ( $me.emit_current_comment();                          );
-    ( $me:ident : clear_comment                    ) => ( $me.current_comment.truncate(0);                     );
-    ( $me:ident : create_doctype                   ) => ( $me.current_doctype = Doctype::new();                );
-    ( $me:ident : push_doctype_name $c:expr        ) => ( option_push(&mut $me.current_doctype.name, $c);      );
-    ( $me:ident : push_doctype_id $k:ident $c:expr ) => ( option_push($me.doctype_id($k), $c);                 );
-    ( $me:ident : clear_doctype_id $k:ident        ) => ( $me.clear_doctype_id($k);                            );
-    ( $me:ident : force_quirks                     ) => ( $me.current_doctype.force_quirks = true;             );
-    ( $me:ident : emit_doctype                     ) => ( $me.emit_current_doctype();                          );
-    ( $me:ident : error                            ) => ( $me.bad_char_error();                                );
-    ( $me:ident : error_eof                        ) => ( $me.bad_eof_error();                                 );
-);
-
-// Tracing of tokenizer actions.  This adds significant bloat and compile time,
-// so it's behind a cfg flag.
-#[cfg(trace_tokenizer)]
-macro_rules! sh_trace ( ( $me:ident : $($cmds:tt)* ) => ({
-    h5e_debug!("  {:s}", stringify!($($cmds)*));
-    shorthand!($me:expr : $($cmds)*);
-}));
-
-#[cfg(not(trace_tokenizer))]
-macro_rules! sh_trace ( ( $me:ident : $($cmds:tt)* ) => ( shorthand!($me: $($cmds)*) ) );
-
-// A little DSL for sequencing shorthand actions.
-macro_rules! go (
-    // A pattern like $($cmd:tt)* ; $($rest:tt)* causes parse ambiguity.
-    // We have to tell the parser how much lookahead we need.
-
-    ( $me:ident : $a:tt                   ; $($rest:tt)* ) => ({ sh_trace!($me: $a);          go!($me: $($rest)*); });
-    ( $me:ident : $a:tt $b:tt             ; $($rest:tt)* ) => ({ sh_trace!($me: $a $b);       go!($me: $($rest)*); });
-    ( $me:ident : $a:tt $b:tt $c:tt       ; $($rest:tt)* ) => ({ sh_trace!($me: $a $b $c);    go!($me: $($rest)*); });
-    ( $me:ident : $a:tt $b:tt $c:tt $d:tt ; $($rest:tt)* ) => ({ sh_trace!($me: $a $b $c $d); go!($me: $($rest)*); });
-
-    // These can only come at the end.
-
-    ( $me:ident : to $s:ident                    ) => ({ $me.state = states::$s; return true;           });
-    ( $me:ident : to $s:ident $k1:expr           ) => ({ $me.state = states::$s($k1); return true;      });
-    ( $me:ident : to $s:ident $k1:ident $k2:expr ) => ({ $me.state = states::$s($k1($k2)); return true; });
-
-    ( $me:ident : reconsume $s:ident                    ) => ({ $me.reconsume = true; go!($me: to $s);         });
-    ( $me:ident : reconsume $s:ident $k1:expr           ) => ({ $me.reconsume = true; go!($me: to $s $k1);     });
-    ( $me:ident : reconsume $s:ident $k1:ident $k2:expr ) => ({ $me.reconsume = true; go!($me: to $s $k1 $k2); });
-
-    ( $me:ident : consume_char_ref             ) => ({ $me.consume_char_ref(None); return true;         });
-    ( $me:ident : consume_char_ref $addnl:expr ) => ({ $me.consume_char_ref(Some($addnl)); return true; });
-
-    // We have a default next state after emitting a tag, but the sink can override.
-    ( $me:ident : emit_tag $s:ident ) => ({
-        $me.state = states::$s;
-        $me.emit_current_tag();
-        return true;
-    });
-
-    ( $me:ident : eof ) => ({ $me.emit_eof(); return false; });
-
-    // If nothing else matched, it's a single command
-    ( $me:ident : $($cmd:tt)+ ) => ( sh_trace!($me: $($cmd)+); );
-
-    // or nothing.
-    ( $me:ident : ) => (());
-);
-
-macro_rules! go_match ( ( $me:ident : $x:expr, $($pats:pat),+ => $($cmds:tt)* ) => (
-    match $x {
-        $($pats)|+ => go!($me: $($cmds)*),
-        _ => (),
-    }
-));
-
-// This is a macro because it can cause early return
-// from the function where it is used.
-macro_rules! get_char ( ($me:expr) => (
-    unwrap_or_return!($me.get_char(), false)
-));
-
-macro_rules! pop_except_from ( ($me:expr, $set:expr) => (
-    unwrap_or_return!($me.pop_except_from($set), false)
-));
-
-macro_rules! eat ( ($me:expr, $pat:expr) => (
-    unwrap_or_return!($me.eat($pat), false)
-));
-
 impl<Sink: TokenSink> Tokenizer<Sink> {
     // Run the state machine for a while.
     // Return true if we should be immediately re-invoked
@@ -726,13 +1032,13 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             },
 
             //§ tag-open-state
-            states::TagOpen => loop { match get_char!(self) {
-                '!' => go!(self: to MarkupDeclarationOpen),
-                '/' => go!(self: to EndTagOpen),
-                '?' => go!(self: error; clear_comment; push_comment '?'; to BogusComment),
+            states::TagOpen => loop { match peek_char!(self) {
+                '!' => go!(self: discard_char; to MarkupDeclarationOpen),
+                '/' => go!(self: discard_char; to EndTagOpen),
+                '?' => go!(self: discard_char; error; clear_comment; push_comment '?'; to BogusComment),
                 c => match lower_ascii_letter(c) {
-                    Some(cl) => go!(self: create_tag StartTag cl; to TagName),
-                    None     => go!(self: error; emit '<'; reconsume Data),
+                    Some(cl) => go!(self: discard_char; create_tag StartTag cl; to TagName),
+                    None     => go!(self: error; emit '<'; to Data),
                 }
             }},
 
@@ -747,92 +1053,98 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             }},
 
             //§ tag-name-state
-            states::TagName => loop { match get_char!(self) {
-                '\t' | '\n' | '\x0C' | ' '
-                     => go!(self: to BeforeAttributeName),
-                '/'  => go!(self: to SelfClosingStartTag),
-                '>'  => go!(self: emit_tag Data),
-                '\0' => go!(self: error; push_tag '\u{fffd}'),
-                c    => go!(self: push_tag (lower_ascii(c))),
-            }},
+            states::TagName => loop {
+                if self.can_pop_name_chars() {
+                    self.input_buffers.pop_name_chars(&mut self.current_tag_name);
+                }
+                match get_char!(self) {
+                    '\t' | '\n' | '\x0C' | ' '
+                         => go!(self: to BeforeAttributeName),
+                    '/'  => go!(self: to SelfClosingStartTag),
+                    '>'  => go!(self: emit_tag Data),
+                    '\0' => go!(self: error; push_tag '\u{fffd}'),
+                    c    => go!(self: push_tag (lower_ascii(c))),
+                }
+            },
 
             //§ script-data-escaped-less-than-sign-state
-            states::RawLessThanSign(ScriptDataEscaped(Escaped)) => loop { match get_char!(self) {
-                '/' => go!(self: clear_temp; to RawEndTagOpen ScriptDataEscaped Escaped),
+            states::RawLessThanSign(ScriptDataEscaped(Escaped)) => loop { match peek_char!(self) {
+                '/' => go!(self: discard_char; clear_temp; to RawEndTagOpen ScriptDataEscaped Escaped),
                 c => match lower_ascii_letter(c) {
-                    Some(cl) => go!(self: clear_temp; push_temp cl; emit '<'; emit c;
+                    Some(cl) => go!(self: discard_char; clear_temp; push_temp cl; emit '<'; emit c;
                                     to ScriptDataEscapeStart DoubleEscaped),
-                    None => go!(self: emit '<'; reconsume RawData ScriptDataEscaped Escaped),
+                    None => go!(self: emit '<'; to RawData ScriptDataEscaped Escaped),
                 }
             }},
 
             //§ script-data-double-escaped-less-than-sign-state
-            states::RawLessThanSign(ScriptDataEscaped(DoubleEscaped)) => loop { match get_char!(self) {
-                '/' => go!(self: clear_temp; emit '/'; to ScriptDataDoubleEscapeEnd),
-                _   => go!(self: reconsume RawData ScriptDataEscaped DoubleEscaped),
+            states::RawLessThanSign(ScriptDataEscaped(DoubleEscaped)) => loop { match peek_char!(self) {
+                '/' => go!(self: discard_char; clear_temp; emit '/'; to ScriptDataDoubleEscapeEnd),
+                _   => go!(self: to RawData ScriptDataEscaped DoubleEscaped),
             }},
 
             //§ rcdata-less-than-sign-state rawtext-less-than-sign-state script-data-less-than-sign-state
             // otherwise
-            states::RawLessThanSign(kind) => loop { match get_char!(self) {
-                '/' => go!(self: clear_temp; to RawEndTagOpen kind),
-                '!' if kind == ScriptData => go!(self: emit '<'; emit '!'; to ScriptDataEscapeStart Escaped),
-                _   => go!(self: emit '<'; reconsume RawData kind),
+            states::RawLessThanSign(kind) => loop { match peek_char!(self) {
+                '/' => go!(self: discard_char; clear_temp; to RawEndTagOpen kind),
+                '!' if kind == ScriptData => go!(self: discard_char; emit '<'; emit '!';
+                                                 to ScriptDataEscapeStart Escaped),
+                _   => go!(self: emit '<'; to RawData kind),
             }},
 
             //§ rcdata-end-tag-open-state rawtext-end-tag-open-state script-data-end-tag-open-state script-data-escaped-end-tag-open-state
             states::RawEndTagOpen(kind) => loop {
-                let c = get_char!(self);
+                let c = peek_char!(self);
                 match lower_ascii_letter(c) {
-                    Some(cl) => go!(self: create_tag EndTag cl; push_temp c; to RawEndTagName kind),
-                    None     => go!(self: emit '<'; emit '/'; reconsume RawData kind),
+                    Some(cl) => go!(self: discard_char; create_tag EndTag cl; push_temp c; to RawEndTagName kind),
+                    None     => go!(self: emit '<'; emit '/'; to RawData kind),
                 }
             },
 
             //§ rcdata-end-tag-name-state rawtext-end-tag-name-state script-data-end-tag-name-state script-data-escaped-end-tag-name-state
             states::RawEndTagName(kind) => loop {
-                let c = get_char!(self);
-                if self.have_appropriate_end_tag() {
-                    match c {
-                        '\t' | '\n' | '\x0C' | ' '
-                            => go!(self: to BeforeAttributeName),
-                        '/' => go!(self: to SelfClosingStartTag),
-                        '>' => go!(self: emit_tag Data),
-                        _ => (),
-                    }
+                let c = peek_char!(self);
+                match c {
+                    '\t' | '\n' | '\x0C' | ' ' if self.have_appropriate_end_tag()
+                        => go!(self: discard_char; to BeforeAttributeName),
+                    '/' if self.have_appropriate_end_tag()
+                        => go!(self: discard_char; to SelfClosingStartTag),
+                    '>' if self.have_appropriate_end_tag()
+                        => go!(self: discard_char; emit_tag Data),
+                    _ => (),
                 }
 
                 match lower_ascii_letter(c) {
-                    Some(cl) => go!(self: push_tag cl; push_temp c),
-                    None     => go!(self: discard_tag; emit '<'; emit '/'; emit_temp; reconsume RawData kind),
+                    Some(cl) => go!(self: discard_char; push_tag cl; push_temp c),
+                    None     => go!(self: discard_tag; emit '<'; emit '/'; emit_temp; to RawData kind),
                 }
             },
 
             //§ script-data-double-escape-start-state
             states::ScriptDataEscapeStart(DoubleEscaped) => loop {
-                let c = get_char!(self);
+                let c = peek_char!(self);
                 match c {
                     '\t' | '\n' | '\x0C' | ' ' | '/' | '>' => {
                         let esc = if self.temp_buf.as_slice() == "script" { DoubleEscaped } else { Escaped };
-                        go!(self: emit c; to RawData ScriptDataEscaped esc);
+                        go!(self: discard_char; emit c; to RawData ScriptDataEscaped esc);
                     }
                     _ => match lower_ascii_letter(c) {
-                        Some(cl) => go!(self: push_temp cl; emit c),
-                        None     => go!(self: reconsume RawData ScriptDataEscaped Escaped),
+                        Some(cl) => go!(self: discard_char; push_temp cl; emit c),
+                        None     => go!(self: to RawData ScriptDataEscaped Escaped),
                     }
                 }
             },
 
             //§ script-data-escape-start-state
-            states::ScriptDataEscapeStart(Escaped) => loop { match get_char!(self) {
-                '-' => go!(self: emit '-'; to ScriptDataEscapeStartDash),
-                _   => go!(self: reconsume RawData ScriptData),
+            states::ScriptDataEscapeStart(Escaped) => loop { match peek_char!(self) {
+                '-' => go!(self: discard_char; emit '-'; to ScriptDataEscapeStartDash),
+                _   => go!(self: to RawData ScriptData),
             }},
 
             //§ script-data-escape-start-dash-state
-            states::ScriptDataEscapeStartDash => loop { match get_char!(self) {
-                '-' => go!(self: emit '-'; to ScriptDataEscapedDashDash Escaped),
-                _   => go!(self: reconsume RawData ScriptData),
+            states::ScriptDataEscapeStartDash => loop { match peek_char!(self) {
+                '-' => go!(self: discard_char; emit '-'; to ScriptDataEscapedDashDash Escaped),
+                _   => go!(self: to RawData ScriptData),
             }},
 
             //§ script-data-escaped-dash-state script-data-double-escaped-dash-state
@@ -860,15 +1172,15 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
 
             //§ script-data-double-escape-end-state
             states::ScriptDataDoubleEscapeEnd => loop {
-                let c = get_char!(self);
+                let c = peek_char!(self);
                 match c {
                     '\t' | '\n' | '\x0C' | ' ' | '/' | '>' => {
                         let esc = if self.temp_buf.as_slice() == "script" { Escaped } else { DoubleEscaped };
-                        go!(self: emit c; to RawData ScriptDataEscaped esc);
+                        go!(self: discard_char; emit c; to RawData ScriptDataEscaped esc);
                     }
                     _ => match lower_ascii_letter(c) {
-                        Some(cl) => go!(self: push_temp cl; emit c),
-                        None     => go!(self: reconsume RawData ScriptDataEscaped DoubleEscaped),
+                        Some(cl) => go!(self: discard_char; push_temp cl; emit c),
+                        None     => go!(self: to RawData ScriptDataEscaped DoubleEscaped),
                     }
                 }
             },
@@ -890,22 +1202,27 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             }},
 
             //§ attribute-name-state
-            states::AttributeName => loop { match get_char!(self) {
-                '\t' | '\n' | '\x0C' | ' '
-                     => go!(self: to AfterAttributeName),
-                '/'  => go!(self: to SelfClosingStartTag),
-                '='  => go!(self: to BeforeAttributeValue),
-                '>'  => go!(self: emit_tag Data),
-                '\0' => go!(self: error; push_name '\u{fffd}'),
-                c    => match lower_ascii_letter(c) {
-                    Some(cl) => go!(self: push_name cl),
-                    None => {
-                        go_match!(self: c,
-                            '"' , '\'' , '<' => error);
-                        go!(self: push_name c);
+            states::AttributeName => loop {
+                if self.can_pop_name_chars() {
+                    self.input_buffers.pop_name_chars(&mut self.current_attr_name);
+                }
+                match get_char!(self) {
+                    '\t' | '\n' | '\x0C' | ' '
+                         => go!(self: to AfterAttributeName),
+                    '/'  => go!(self: to SelfClosingStartTag),
+                    '='  => go!(self: to BeforeAttributeValue),
+                    '>'  => go!(self: emit_tag Data),
+                    '\0' => go!(self: error; push_name '\u{fffd}'),
+                    c    => match lower_ascii_letter(c) {
+                        Some(cl) => go!(self: push_name cl),
+                        None => {
+                            go_match!(self: c,
+                                '"' , '\'' , '<' => error);
+                            go!(self: push_name c);
+                        }
                     }
                 }
-            }},
+            },
 
             //§ after-attribute-name-state
             states::AfterAttributeName => loop { match get_char!(self) {
@@ -925,14 +1242,16 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             }},
 
             //§ before-attribute-value-state
-            states::BeforeAttributeValue => loop { match get_char!(self) {
-                '\t' | '\n' | '\x0C' | ' ' => (),
-                '"'  => go!(self: to AttributeValue DoubleQuoted),
-                '&'  => go!(self: reconsume AttributeValue Unquoted),
-                '\'' => go!(self: to AttributeValue SingleQuoted),
-                '\0' => go!(self: error; push_value '\u{fffd}'; to AttributeValue Unquoted),
-                '>'  => go!(self: error; emit_tag Data),
+            states::BeforeAttributeValue => loop { match peek_char!(self) {
+                '\t' | '\n' | '\x0C' | ' ' => go!(self: discard_char),
+                '"'  => go!(self: discard_char; to AttributeValue DoubleQuoted),
+                '&'  => go!(self: to AttributeValue Unquoted),
+                '\'' => go!(self: discard_char; to AttributeValue SingleQuoted),
+                '\0' => go!(self: discard_char; error; push_value '\u{fffd}';
+                             to AttributeValue Unquoted),
+                '>'  => go!(self: discard_char; error; emit_tag Data),
                 c => {
+                    go!(self: discard_char);
                     go_match!(self: c,
                         '<' , '=' , '`' => error);
                     go!(self: push_value c; to AttributeValue Unquoted);
@@ -941,6 +1260,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
 
             //§ attribute-value-(double-quoted)-state
             states::AttributeValue(DoubleQuoted) => loop {
+                self.pop_value_chars(small_char_set!('\r' '"' '&' '\0'));
                 match pop_except_from!(self, small_char_set!('\r' '"' '&' '\0')) {
                     FromSet('"')  => go!(self: to AfterAttributeValueQuoted),
                     FromSet('&')  => go!(self: consume_char_ref '"'),
@@ -952,6 +1272,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
 
             //§ attribute-value-(single-quoted)-state
             states::AttributeValue(SingleQuoted) => loop {
+                self.pop_value_chars(small_char_set!('\r' '\'' '&' '\0'));
                 match pop_except_from!(self, small_char_set!('\r' '\'' '&' '\0')) {
                     FromSet('\'') => go!(self: to AfterAttributeValueQuoted),
                     FromSet('&')  => go!(self: consume_char_ref '\''),
@@ -963,6 +1284,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
 
             //§ attribute-value-(unquoted)-state
             states::AttributeValue(Unquoted) => loop {
+                self.pop_value_chars(small_char_set!('\r' '\t' '\n' '\x0C' ' ' '&' '>' '\0'));
                 match pop_except_from!(self, small_char_set!('\r' '\t' '\n' '\x0C' ' ' '&' '>' '\0')) {
                     FromSet('\t') | FromSet('\n') | FromSet('\x0C') | FromSet(' ')
                      => go!(self: to BeforeAttributeName),
@@ -979,21 +1301,22 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             },
 
             //§ after-attribute-value-(quoted)-state
-            states::AfterAttributeValueQuoted => loop { match get_char!(self) {
+            states::AfterAttributeValueQuoted => loop { match peek_char!(self) {
                 '\t' | '\n' | '\x0C' | ' '
-                     => go!(self: to BeforeAttributeName),
-                '/'  => go!(self: to SelfClosingStartTag),
-                '>'  => go!(self: emit_tag Data),
-                _    => go!(self: error; reconsume BeforeAttributeName),
+                     => go!(self: discard_char; to BeforeAttributeName),
+                '/'  => go!(self: discard_char; to SelfClosingStartTag),
+                '>'  => go!(self: discard_char; emit_tag Data),
+                _    => go!(self: error; to BeforeAttributeName),
             }},
 
             //§ self-closing-start-tag-state
-            states::SelfClosingStartTag => loop { match get_char!(self) {
+            states::SelfClosingStartTag => loop { match peek_char!(self) {
                 '>' => {
+                    self.discard_char();
                     self.current_tag_self_closing = true;
                     go!(self: emit_tag Data);
                 }
-                _ => go!(self: error; reconsume BeforeAttributeName),
+                _ => go!(self: error; to BeforeAttributeName),
             }},
 
             //§ comment-start-state
@@ -1044,10 +1367,10 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             }},
 
             //§ doctype-state
-            states::Doctype => loop { match get_char!(self) {
+            states::Doctype => loop { match peek_char!(self) {
                 '\t' | '\n' | '\x0C' | ' '
-                    => go!(self: to BeforeDoctypeName),
-                _   => go!(self: error; reconsume BeforeDoctypeName),
+                    => go!(self: discard_char; to BeforeDoctypeName),
+                _   => go!(self: error; to BeforeDoctypeName),
             }},
 
             //§ before-doctype-name-state
@@ -1162,16 +1485,25 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                     go!(self: clear_comment; to CommentStart);
                 } else if eat!(self, "doctype") {
                     go!(self: to Doctype);
+                } else if self.sink.adjusted_current_node_present_but_not_in_html_namespace()
+                        && eat_exact!(self, "[CDATA[") {
+                    go!(self: to CdataSection);
                 } else {
-                    // FIXME: CDATA, requires "adjusted current node" from tree builder
                     // FIXME: 'error' gives wrong message
                     go!(self: error; to BogusComment);
                 }
             },
 
             //§ cdata-section-state
-            states::CdataSection
-                => panic!("FIXME: state {:?} not implemented", self.state),
+            states::CdataSection => loop {
+                if eat_exact!(self, "]]>") {
+                    go!(self: to Data);
+                }
+                match pop_except_from!(self, small_char_set!('\r' '\0' ']')) {
+                    FromSet(c) => go!(self: emit c),
+                    NotFromSet(b) => self.emit_chars(b),
+                }
+            },
             //§ END
         }
     }
@@ -1220,6 +1552,12 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
 
     /// Indicate that we have reached the end of the input.
     pub fn end(&mut self) {
+        if self.stopped {
+            // Let the sink finish up, but don't tokenize any more.
+            self.sink.process_token(EOFToken);
+            return;
+        }
+
         // Handle EOF in the char ref sub-tokenizer, if there is one.
         // Do this first because it might un-consume stuff.
         match self.char_ref_tokenizer.take() {
@@ -1244,12 +1582,12 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         }
     }
 
-    #[cfg(for_c)]
+    #[cfg(not(feature = "std"))]
     fn dump_profile(&self) {
         unreachable!();
     }
 
-    #[cfg(not(for_c))]
+    #[cfg(feature = "std")]
     fn dump_profile(&self) {
         use core::iter::AdditiveIterator;
 
@@ -1334,7 +1672,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                 => go!(self: error; to BogusComment),
 
             states::CdataSection
-                => panic!("FIXME: state {:?} not implemented in EOF", self.state),
+                => go!(self: error_eof; to Data),
         }
     }
 }
@@ -1345,7 +1683,66 @@ mod test {
     use core::prelude::*;
     use collections::vec::Vec;
     use collections::string::String;
+    use core::default::Default;
     use super::{option_push, append_strings}; // private items
+    use super::{Tokenizer, TokenizerOpts, TokenSink, TokenSinkResult, Continue, Stop, Token};
+    use super::{CharacterTokens, TagToken, CommentToken, BogusCommentToken, ParseError, EndTag};
+    use super::{DoctypeToken, NullCharacterToken, EOFToken};
+    use super::states::{State, Data, RawData, Rcdata, ScriptData};
+    use super::states::{Rawtext, ScriptDataEscaped, Escaped, DoubleEscaped};
+    use super::states::{Plaintext, CdataSection};
+    use diagnostic::Diagnostic;
+
+    struct RecordingSink {
+        chars: String,
+        char_tokens: uint,
+        end_tags: Vec<String>,
+        attrs: Vec<String>,
+        comments: Vec<(bool, String)>,
+        errors: uint,
+        diagnostics: Vec<Diagnostic>,
+    }
+
+    impl RecordingSink {
+        fn new() -> RecordingSink {
+            RecordingSink {
+                chars: String::new(),
+                char_tokens: 0,
+                end_tags: vec!(),
+                attrs: vec!(),
+                comments: vec!(),
+                errors: 0,
+                diagnostics: vec!(),
+            }
+        }
+    }
+
+    impl TokenSink for RecordingSink {
+        fn process_token(&mut self, token: Token) -> TokenSinkResult {
+            match token {
+                CharacterTokens(s) => {
+                    self.chars.push_str(s.as_slice());
+                    self.char_tokens += 1;
+                }
+                TagToken(ref t) => {
+                    if t.kind == EndTag {
+                        self.end_tags.push(String::from_str(t.name.as_slice()));
+                    }
+                    for attr in t.attrs.iter() {
+                        self.attrs.push(format!("{}={}", attr.name.local.as_slice(), attr.value));
+                    }
+                }
+                CommentToken(s) => self.comments.push((false, s)),
+                BogusCommentToken(s) => self.comments.push((true, s)),
+                ParseError(error) => {
+                    self.errors += 1;
+                    self.diagnostics.push(error);
+                }
+                _ => (),
+            }
+            Continue
+        }
+    }
 
     #[test]
     fn push_to_None_gives_singleton() {
@@ -1387,4 +1784,263 @@ mod test {
         let ptr_new = lhs.into_bytes()[0] as *const u8;
         assert_eq!(ptr_old, ptr_new);
     }
+
+    #[test]
+    fn set_state() {
+        let mut tok = Tokenizer::new(RecordingSink::new(), Default::default());
+        assert_eq!(tok.state(), Data);
+        tok.set_state(RawData(Rcdata), Some("title"));
+        tok.feed(String::from_str("a<b></p></title>"));
+        tok.end();
+        assert_eq!(tok.state(), Data);
+        assert_eq!(tok.sink().chars.as_slice(), "a<b></p>");
+        assert_eq!(tok.sink().end_tags, vec!(String::from_str("title")));
+    }
+
+    // An end tag inside `<!--<script>` doesn't end the script, whether
+    // the input arrives at once or a character at a time.
+    #[test]
+    fn double_escaped_script() {
+        let input = "var s = \"<!--<script>x</script>-->\";</script>";
+        for &split in [false, true].iter() {
+            let mut tok = Tokenizer::new(RecordingSink::new(), Default::default());
+            tok.set_state(RawData(ScriptData), Some("script"));
+            if split {
+                for c in input.chars() {
+                    let mut s = String::new();
+                    s.push(c);
+                    tok.feed(s);
+                }
+            } else {
+                tok.feed(String::from_str(input));
+            }
+            tok.end();
+            assert_eq!(tok.sink().chars.as_slice(), "var s = \"<!--<script>x</script>-->\";");
+            assert_eq!(tok.sink().end_tags, vec!(String::from_str("script")));
+        }
+    }
+
+    // Attribute names are compared after lowercasing, and the first wins.
+    #[test]
+    fn duplicate_attributes() {
+        let mut tok = Tokenizer::new(RecordingSink::new(), Default::default());
+        tok.feed(String::from_str("<p a=1 B=2 A=3 b=4 xlink:href=5 XLink:Href=6>"));
+        tok.end();
+        assert_eq!(tok.sink().attrs, vec!(String::from_str("a=1"),
+            String::from_str("b=2"), String::from_str("xlink:href=5")));
+        assert_eq!(tok.sink().errors, 3);
+    }
+
+    #[test]
+    fn end_tag_attributes_dropped() {
+        let mut tok = Tokenizer::new(RecordingSink::new(), Default::default());
+        tok.feed(String::from_str("<p></p class=x/>"));
+        tok.end();
+        assert_eq!(tok.sink().end_tags, vec!(String::from_str("p")));
+        assert_eq!(tok.sink().attrs, Vec::<String>::new());
+        assert_eq!(tok.sink().errors, 2);
+    }
+
+    #[test]
+    fn bogus_comments() {
+        let opts = TokenizerOpts {
+            mark_bogus_comments: true,
+            .. Default::default()
+        };
+        let mut tok = Tokenizer::new(RecordingSink::new(), opts);
+        tok.feed(String::from_str("<!x><?y></%z><!-- a -- b --!><!-z"));
+        tok.end();
+        assert_eq!(tok.sink().comments, vec!(
+            (true, String::from_str("x")),
+            (true, String::from_str("?y")),
+            (true, String::from_str("%z")),
+            (false, String::from_str(" a -- b ")),
+            (true, String::from_str("-z"))));
+    }
+
+    #[test]
+    fn batched_text() {
+        let opts = TokenizerOpts {
+            max_text_batch: 1024,
+            .. Default::default()
+        };
+        let mut tok = Tokenizer::new(RecordingSink::new(), opts);
+        tok.feed(String::from_str("a &amp; b\r\n"));
+        tok.feed(String::from_str("c &lt; d</p>e"));
+        tok.end();
+        assert_eq!(tok.sink().chars.as_slice(), "a & b\nc < de");
+        assert_eq!(tok.sink().char_tokens, 3);
+    }
+
+    // Once the tag name and attribute buffers are big enough, more tags
+    // shouldn't allocate them again.
+    #[test]
+    fn reuses_scratch_buffers() {
+        let mut tok = Tokenizer::new(RecordingSink::new(), Default::default());
+        tok.feed(String::from_str("<blockquote data-source='http://example.com/'>"));
+        let allocs = tok.scratch_allocs;
+        assert!(allocs > 0);
+        for _ in 0..100u {
+            tok.feed(String::from_str("<abbr class=y title=\"a &amp; b\"></abbr>"));
+        }
+        tok.end();
+        assert_eq!(tok.scratch_allocs, allocs);
+        assert_eq!(tok.sink().attrs.len(), 201);
+        assert_eq!(tok.sink().attrs[200].as_slice(), "title=a & b");
+    }
+
+    // Keeps the text before the first end tag, where it stops, and
+    // counts `EOFToken`s.
+    struct StopAtEndTag(String, uint);
+
+    impl TokenSink for StopAtEndTag {
+        fn process_token(&mut self, token: Token) -> TokenSinkResult {
+            match token {
+                CharacterTokens(s) => self.0.push_str(s.as_slice()),
+                TagToken(ref t) if t.kind == EndTag => return Stop,
+                EOFToken => self.1 += 1,
+                _ => (),
+            }
+            Continue
+        }
+    }
+
+    #[test]
+    fn stop() {
+        let mut tok = Tokenizer::new(StopAtEndTag(String::new(), 0), Default::default());
+        tok.feed(String::from_str("a<b>c</b>d<i>e"));
+        assert!(tok.is_stopped());
+        tok.feed(String::from_str("f</i>"));
+        tok.end();
+        assert_eq!(tok.sink().0.as_slice(), "ac");
+        assert_eq!(tok.sink().1, 1);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let opts = TokenizerOpts {
+            exact_errors: true,
+            track_positions: true,
+            .. Default::default()
+        };
+        let mut tok = Tokenizer::new(RecordingSink::new(), opts);
+        tok.feed(String::from_str("ab&#0;"));
+        tok.end();
+
+        let diagnostics = &tok.sink().diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "Invalid numeric character reference");
+        assert_eq!(diagnostics[0].message.as_slice(),
+            "Invalid numeric character reference value 0x000000");
+        assert!(diagnostics[0].span.is_some());
+    }
+
+    #[test]
+    fn preprocessing_errors() {
+        let opts = TokenizerOpts {
+            exact_errors: true,
+            .. Default::default()
+        };
+        let mut tok = Tokenizer::new(RecordingSink::new(), opts);
+        // The U+0001 is reread after the character reference fails to
+        // match, but reported only once.
+        tok.feed(String::from_str("a\x0Bb\u{FDD0}<p title=\"\x7F\u{10FFFF}\">&no\x01\tc\x0C"));
+        tok.end();
+
+        let errors: Vec<(&str, &str)> = tok.sink().diagnostics.iter()
+            .map(|d| (d.code, d.message.as_slice())).collect();
+        assert_eq!(errors, vec!(
+            ("Control character in input stream", "Control character in input stream: U+000B"),
+            ("Noncharacter in input stream", "Noncharacter in input stream: U+FDD0"),
+            ("Control character in input stream", "Control character in input stream: U+007F"),
+            ("Noncharacter in input stream", "Noncharacter in input stream: U+10FFFF"),
+            ("Control character in input stream", "Control character in input stream: U+0001"),
+        ));
+    }
+
+    // Writes tokens out as markup, with `<NUL>` for a `NullCharacterToken`,
+    // and counts parse errors.
+    struct Summary(String, uint);
+
+    impl TokenSink for Summary {
+        fn process_token(&mut self, token: Token) -> TokenSinkResult {
+            match token {
+                CharacterTokens(s) => self.0.push_str(s.as_slice()),
+                NullCharacterToken => self.0.push_str("<NUL>"),
+                TagToken(t) => {
+                    self.0.push_str(if t.kind == EndTag { "</" } else { "<" });
+                    self.0.push_str(t.name.as_slice());
+                    for attr in t.attrs.iter() {
+                        self.0.push_str(format!(" {}={}", attr.name.local.as_slice(),
+                            attr.value).as_slice());
+                    }
+                    self.0.push('>');
+                }
+                CommentToken(s) | BogusCommentToken(s)
+                    => self.0.push_str(format!("<!--{}-->", s).as_slice()),
+                DoctypeToken(d) => self.0.push_str(format!("<!DOCTYPE {} '{}' '{}'>",
+                    d.name.unwrap_or(String::new()), d.public_id.unwrap_or(String::new()),
+                    d.system_id.unwrap_or(String::new())).as_slice()),
+                ParseError(_) => self.1 += 1,
+                _ => (),
+            }
+            Continue
+        }
+    }
+
+    fn summarize(state: State, strip_nul: bool, input: &str) -> (String, uint) {
+        let opts = TokenizerOpts {
+            initial_state: Some(state),
+            strip_nul: strip_nul,
+            .. Default::default()
+        };
+        let mut tok = Tokenizer::new(Summary(String::new(), 0), opts);
+        tok.feed(String::from_str(input));
+        tok.end();
+        let Summary(out, errors) = tok.unwrap();
+        (out, errors)
+    }
+
+    #[test]
+    fn nul_in_each_state() {
+        let cases: &[(State, &str, &str, uint)] = &[
+            (Data, "a\0b", "a<NUL>b", 1),
+            (RawData(Rcdata), "a\0b", "a\u{fffd}b", 1),
+            (RawData(Rawtext), "a\0b", "a\u{fffd}b", 1),
+            (RawData(ScriptData), "a\0b", "a\u{fffd}b", 1),
+            (Plaintext, "a\0b", "a\u{fffd}b", 1),
+            (RawData(ScriptDataEscaped(Escaped)), "a\0-\0--\0-->",
+                "a\u{fffd}-\u{fffd}--\u{fffd}-->", 3),
+            (RawData(ScriptDataEscaped(DoubleEscaped)), "a\0-\0--\0-->",
+                "a\u{fffd}-\u{fffd}--\u{fffd}-->", 3),
+            (CdataSection, "a\0b]]>", "a<NUL>b", 0),
+            (Data, "<a\0 \0 b\0=\0 c=\"\0\" d='\0'>",
+                "<a\u{fffd} \u{fffd}= b\u{fffd}=\u{fffd} c=\u{fffd} d=\u{fffd}>", 6),
+            (Data, "<a b \0>", "<a b= \u{fffd}=>", 1),
+            (Data, "</\0>", "<!--\u{fffd}-->", 1),
+            (Data, "<?\0>", "<!--?\u{fffd}-->", 1),
+            (Data, "<!--\0-->", "<!--\u{fffd}-->", 1),
+            (Data, "<!---\0-->", "<!---\u{fffd}-->", 1),
+            (Data, "<!--a\0-\0--\0--!\0-->",
+                "<!--a\u{fffd}-\u{fffd}--\u{fffd}--!\u{fffd}-->", 5),
+            (Data, "<!DOCTYPE \0>", "<!DOCTYPE \u{fffd} '' ''>", 1),
+            (Data, "<!DOCTYPE a\0 PUBLIC \"\0\" \"\0\">",
+                "<!DOCTYPE a\u{fffd} '\u{fffd}' '\u{fffd}'>", 3),
+        ];
+        for &(state, input, output, errors) in cases.iter() {
+            assert_eq!(summarize(state, false, input), (String::from_str(output), errors));
+        }
+    }
+
+    #[test]
+    fn eof_in_cdata() {
+        assert_eq!(summarize(CdataSection, false, "a]]"), (String::from_str("a]]"), 1));
+        assert_eq!(summarize(CdataSection, false, "a]]>"), (String::from_str("a"), 0));
+    }
+
+    #[test]
+    fn strip_nul() {
+        assert_eq!(summarize(Data, true, "a\0b<p\0 c=d\0>\0"), (String::from_str("ab<p c=d>"), 0));
+        assert_eq!(summarize(RawData(Rcdata), true, "\0\0"), (String::new(), 0));
+    }
 }
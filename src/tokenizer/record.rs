@@ -0,0 +1,349 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Recording the tokens produced by the tokenizer, in a compact binary
+//! format, and replaying them into a `TokenSink` later.
+//!
+//! This lets a large corpus be tokenized once and then run through tree
+//! construction many times.  Which tokens come out depends on the tree
+//! builder, which switches the tokenizer into raw text states after
+//! tags like `<script>`, so record with a `TreeBuilder` inside the
+//! `Recorder`.
+//!
+//! Spans passed to `set_current_span` are recorded too.  Diagnostic
+//! codes are `&'static str`s and can't be read back, so replayed parse
+//! errors have the code `"Recorded error"` and the original message.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let tb = TreeBuilder::new(RcDom::default(), Default::default());
+//! let (_, recording) = tokenize_to(Recorder::new(tb), input, Default::default()).unwrap();
+//!
+//! let mut tb = TreeBuilder::new(RcDom::default(), Default::default());
+//! replay(recording.as_slice(), &mut tb).unwrap();
+//! let dom = tb.unwrap().finish();
+//! ```
+
+use core::prelude::*;
+
+pub use util::codec::DecodeError as ReplayError;
+
+use tokenizer::{Token, DoctypeToken, TagToken, CommentToken, BogusCommentToken};
+use tokenizer::{CharacterTokens, NullCharacterToken, EOFToken, ParseError};
+use tokenizer::{TokenSink, TokenSinkResult, Stop};
+use tokenizer::{Doctype, Tag, StartTag, EndTag, Attribute, Span};
+use tokenizer::states;
+use util::codec::{put_uint, put_str, put_span, put_diagnostic, Reader};
+
+use collections::vec::Vec;
+
+use string_cache::{Atom, QualName, Namespace};
+
+// The start of every recording: a signature and a format version.
+static MAGIC: &'static [u8] = b"H5TK\x01";
+
+// Record kinds.
+const DOCTYPE: u8 = 0;
+const START_TAG: u8 = 1;
+const END_TAG: u8 = 2;
+const COMMENT: u8 = 3;
+const BOGUS_COMMENT: u8 = 4;
+const CHARACTERS: u8 = 5;
+const NULL_CHARACTER: u8 = 6;
+const EOF: u8 = 7;
+const PARSE_ERROR: u8 = 8;
+const SPAN: u8 = 9;
+
+/// A `TokenSink` which records tokens before passing them on to another
+/// sink.
+pub struct Recorder<Sink> {
+    sink: Sink,
+    recording: Vec<u8>,
+}
+
+impl<Sink: TokenSink> Recorder<Sink> {
+    pub fn new(sink: Sink) -> Recorder<Sink> {
+        let mut recording = Vec::new();
+        recording.push_all(MAGIC);
+        Recorder {
+            sink: sink,
+            recording: recording,
+        }
+    }
+
+    pub fn sink<'a>(&'a self) -> &'a Sink {
+        &self.sink
+    }
+
+    pub fn sink_mut<'a>(&'a mut self) -> &'a mut Sink {
+        &mut self.sink
+    }
+
+    /// The recording so far.
+    pub fn recording<'a>(&'a self) -> &'a [u8] {
+        self.recording.as_slice()
+    }
+
+    /// Get the inner sink and the recording.
+    pub fn unwrap(self) -> (Sink, Vec<u8>) {
+        (self.sink, self.recording)
+    }
+
+    fn record(&mut self, token: &Token) {
+        let out = &mut self.recording;
+        match *token {
+            DoctypeToken(ref d) => {
+                out.push(DOCTYPE);
+                out.push((d.name.is_some() as u8)
+                    | (d.public_id.is_some() as u8) << 1
+                    | (d.system_id.is_some() as u8) << 2
+                    | (d.force_quirks as u8) << 3);
+                for s in [&d.name, &d.public_id, &d.system_id].iter() {
+                    match **s {
+                        Some(ref s) => put_str(out, s.as_slice()),
+                        None => (),
+                    }
+                }
+            }
+
+            TagToken(ref tag) => {
+                out.push(match tag.kind {
+                    StartTag => START_TAG,
+                    EndTag => END_TAG,
+                });
+                put_str(out, tag.name.as_slice());
+                out.push(tag.self_closing as u8);
+                put_uint(out, tag.attrs.len());
+                for attr in tag.attrs.iter() {
+                    put_str(out, attr.name.ns.0.as_slice());
+                    put_str(out, attr.name.local.as_slice());
+                    put_str(out, attr.value.as_slice());
+                }
+            }
+
+            CommentToken(ref s) => {
+                out.push(COMMENT);
+                put_str(out, s.as_slice());
+            }
+
+            BogusCommentToken(ref s) => {
+                out.push(BOGUS_COMMENT);
+                put_str(out, s.as_slice());
+            }
+
+            CharacterTokens(ref s) => {
+                out.push(CHARACTERS);
+                put_str(out, s.as_slice());
+            }
+
+            NullCharacterToken => out.push(NULL_CHARACTER),
+
+            EOFToken => out.push(EOF),
+
+            ParseError(ref e) => {
+                out.push(PARSE_ERROR);
+                put_diagnostic(out, e);
+            }
+        }
+    }
+}
+
+impl<Sink: TokenSink> TokenSink for Recorder<Sink> {
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
+        self.record(&token);
+        self.sink.process_token(token)
+    }
+
+    fn query_state_change(&mut self) -> Option<states::State> {
+        self.sink.query_state_change()
+    }
+
+    fn set_current_span(&mut self, span: Span) {
+        self.recording.push(SPAN);
+        put_span(&mut self.recording, span);
+        self.sink.set_current_span(span);
+    }
+
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        self.sink.adjusted_current_node_present_but_not_in_html_namespace()
+    }
+}
+
+// Read a record.  Spans are returned separately, since they're not
+// tokens.
+fn read_record(r: &mut Reader) -> Result<Result<Token, Span>, ReplayError> {
+    let kind = try!(r.byte());
+    let token = match kind {
+        DOCTYPE => {
+            let flags = try!(r.byte());
+            let mut d = Doctype::new();
+            for (i, s) in [&mut d.name, &mut d.public_id, &mut d.system_id]
+                    .iter_mut().enumerate() {
+                if flags & (1 << i) != 0 {
+                    **s = Some(try!(r.string()));
+                }
+            }
+            d.force_quirks = flags & 8 != 0;
+            DoctypeToken(d)
+        }
+
+        START_TAG | END_TAG => {
+            let name = Atom::from_slice(try!(r.str()));
+            let self_closing = try!(r.byte()) != 0;
+            let count = try!(r.uint());
+            let mut attrs = vec!();
+            for _ in 0..count {
+                let ns = Namespace(Atom::from_slice(try!(r.str())));
+                let local = Atom::from_slice(try!(r.str()));
+                attrs.push(Attribute {
+                    name: QualName::new(ns, local),
+                    value: try!(r.string()),
+                });
+            }
+            TagToken(Tag {
+                kind: if kind == START_TAG { StartTag } else { EndTag },
+                name: name,
+                self_closing: self_closing,
+                attrs: attrs,
+            })
+        }
+
+        COMMENT => CommentToken(try!(r.string())),
+        BOGUS_COMMENT => BogusCommentToken(try!(r.string())),
+        CHARACTERS => CharacterTokens(try!(r.string())),
+        NULL_CHARACTER => NullCharacterToken,
+        EOF => EOFToken,
+
+        PARSE_ERROR => ParseError(try!(r.diagnostic("Recorded error"))),
+
+        SPAN => return Ok(Err(try!(r.span()))),
+
+        _ => {
+            r.pos -= 1;
+            return r.error("Unknown record");
+        }
+    };
+    Ok(Ok(token))
+}
+
+/// Send the tokens in a recording to a sink.
+///
+/// The whole recording is read before the sink gets any tokens, so an
+/// invalid recording leaves the sink as it was.  If the sink returns
+/// `Stop`, it gets no more tokens except for an `EOFToken`, as with the
+/// tokenizer.
+pub fn replay<Sink: TokenSink>(recording: &[u8], sink: &mut Sink) -> Result<(), ReplayError> {
+    if recording.len() < MAGIC.len() || &recording[..MAGIC.len()] != MAGIC {
+        return Err(ReplayError {
+            position: 0,
+            message: "Not a token recording",
+        });
+    }
+
+    let mut reader = Reader::new(recording, MAGIC.len());
+    let mut records = vec!();
+    while !reader.at_end() {
+        records.push(try!(read_record(&mut reader)));
+    }
+
+    let mut stopped = false;
+    for record in records.into_iter() {
+        match record {
+            Ok(token) => {
+                if stopped && token != EOFToken {
+                    continue;
+                }
+                if sink.process_token(token) == Stop {
+                    stopped = true;
+                }
+                // Recorded tokens already reflect any state changes.
+                sink.query_state_change();
+            }
+            Err(span) => if !stopped {
+                sink.set_current_span(span);
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::string::String;
+
+    use super::{Recorder, replay, ReplayError};
+    use tokenizer::{Tokenizer, TokenizerOpts, TokenSink, TokenSinkResult, Token, Continue};
+    use tree_builder::{TreeBuilder, TreeSink};
+    use driver::{tokenize_to, one_input};
+    use sink::rcdom::{RcDom, dump_tree};
+
+    #[test]
+    fn round_trip() {
+        let input = "<!DOCTYPE html PUBLIC 'x'><title>a&amp;b</title>\
+            <script>if (a<b) x()</script><p class=\"c\" id=d>\u{0}x<!-- c --></b>\
+            <svg><path d='M0'/></svg><?php ?>";
+        let opts = TokenizerOpts {
+            track_positions: true,
+            .. Default::default()
+        };
+        let tb = TreeBuilder::new(RcDom::default(), Default::default());
+        let (tb, recording) = tokenize_to(Recorder::new(tb), one_input(String::from_str(input)),
+            opts).unwrap();
+        let recorded = tb.unwrap().finish();
+
+        let mut tb = TreeBuilder::new(RcDom::default(), Default::default());
+        replay(recording.as_slice(), &mut tb).unwrap();
+        let replayed = tb.unwrap().finish();
+
+        assert_eq!(dump_tree(&replayed.document), dump_tree(&recorded.document));
+        assert_eq!(replayed.errors.len(), recorded.errors.len());
+        for (a, b) in replayed.errors.iter().zip(recorded.errors.iter()) {
+            assert_eq!((&a.message, a.span), (&b.message, b.span));
+        }
+        assert!(!recorded.errors.is_empty());
+    }
+
+    struct CountTokens(uint);
+
+    impl TokenSink for CountTokens {
+        fn process_token(&mut self, _token: Token) -> TokenSinkResult {
+            self.0 += 1;
+            Continue
+        }
+    }
+
+    #[test]
+    fn bad_recordings() {
+        let mut count = CountTokens(0);
+        let mut tok = Tokenizer::new(Recorder::new(TreeBuilder::new(RcDom::default(),
+            Default::default())), Default::default());
+        tok.feed(String::from_str("<p>x"));
+        tok.end();
+        let (_, recording) = tok.unwrap().unwrap();
+
+        let err = |position: uint, message: &'static str| Err(ReplayError {
+            position: position,
+            message: message,
+        });
+        assert_eq!(replay(b"<p>", &mut count), err(0, "Not a token recording"));
+        assert_eq!(replay(&recording[..recording.len() - 2], &mut count),
+            err(recording.len() - 2, "Unexpected end of input"));
+        let mut bad = recording.clone();
+        bad.push(99);
+        assert_eq!(replay(bad.as_slice(), &mut count), err(recording.len(), "Unknown record"));
+
+        // Nothing is replayed from a bad recording.
+        assert_eq!(count.0, 0);
+        replay(recording.as_slice(), &mut count).unwrap();
+        assert!(count.0 > 0);
+    }
+}
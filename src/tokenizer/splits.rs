@@ -0,0 +1,258 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checking that the tokenizer's output doesn't depend on how its input
+//! is split into chunks.
+//!
+//! The input is tokenized whole, and then again for each way of
+//! splitting it, and the tokens must be the same every time.  Adjacent
+//! character tokens are joined before comparing, since where those are
+//! broken up does depend on the chunks.
+//!
+//! `check_all_splits` tries every way of cutting short input into a few
+//! pieces.  `check_random_splits` tries pseudo-random cuts anywhere in
+//! longer input; the cuts depend only on the seed, so a failure can be
+//! reproduced.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let opts = TokenizerOpts { initial_state: Some(my_new_state), .. Default::default() };
+//! check_all_splits("<a b='c'>", opts.clone(), 3).unwrap();
+//! check_random_splits(my_long_input, opts, 42, 100).unwrap();
+//! ```
+
+use core::prelude::*;
+
+use tokenizer::{Tokenizer, TokenizerOpts, TokenSink, TokenSinkResult, Continue};
+use tokenizer::{Token, CharacterTokens};
+
+use core::mem;
+use collections::vec::Vec;
+use collections::string::String;
+
+/// A way of splitting the input which changed the tokens.
+#[derive(Debug)]
+pub struct SplitMismatch {
+    /// The chunks which were fed to the tokenizer.
+    pub chunks: Vec<String>,
+
+    /// The tokens from the whole input.
+    pub expected: Vec<Token>,
+
+    /// The tokens from the chunks.
+    pub got: Vec<Token>,
+}
+
+/// A small, seeded pseudo-random number generator (xorshift), so that
+/// splits can be reproduced without depending on a random crate.
+#[derive(Clone, Debug)]
+pub struct SplitRng {
+    state: u64,
+}
+
+impl SplitRng {
+    pub fn new(seed: u64) -> SplitRng {
+        SplitRng {
+            // The state must never be zero.
+            state: if seed == 0 { 0x9E3779B97F4A7C15 } else { seed },
+        }
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    // A number less than `n`, which must not be zero.
+    fn below(&mut self, n: uint) -> uint {
+        (self.next() % (n as u64)) as uint
+    }
+}
+
+// Collects tokens, joining adjacent character tokens.
+struct Logger {
+    tokens: Vec<Token>,
+    chars: String,
+}
+
+impl Logger {
+    fn finish_chars(&mut self) {
+        if !self.chars.is_empty() {
+            let chars = mem::replace(&mut self.chars, String::new());
+            self.tokens.push(CharacterTokens(chars));
+        }
+    }
+}
+
+impl TokenSink for Logger {
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
+        match token {
+            CharacterTokens(s) => self.chars.push_str(s.as_slice()),
+            token => {
+                self.finish_chars();
+                self.tokens.push(token);
+            }
+        }
+        Continue
+    }
+}
+
+/// Tokenize `chunks`, one `feed` each, joining adjacent character
+/// tokens in the output.
+pub fn tokenize_chunks(chunks: Vec<String>, opts: TokenizerOpts) -> Vec<Token> {
+    let logger = Logger {
+        tokens: vec!(),
+        chars: String::new(),
+    };
+    let mut tok = Tokenizer::new(logger, opts);
+    for chunk in chunks.into_iter() {
+        tok.feed(chunk);
+    }
+    tok.end();
+    let mut logger = tok.unwrap();
+    logger.finish_chars();
+    logger.tokens
+}
+
+/// Every way of splitting `input` into at most `max_pieces` pieces,
+/// which may be empty, at character boundaries.
+pub fn all_splits(input: &str, max_pieces: uint) -> Vec<Vec<String>> {
+    if max_pieces <= 1 {
+        return vec!(vec!(String::from_str(input)));
+    }
+
+    let mut points: Vec<uint> = input.char_indices().map(|(i, _)| i).collect();
+    points.push(input.len());
+
+    let mut out = vec!();
+    for &p in points.iter() {
+        for mut split in all_splits(&input[..p], max_pieces - 1).into_iter() {
+            split.push(String::from_str(&input[p..]));
+            out.push(split);
+        }
+    }
+    out
+}
+
+/// Split `input` at up to 16 pseudo-random character boundaries.
+pub fn random_split(input: &str, rng: &mut SplitRng) -> Vec<String> {
+    let mut points: Vec<uint> = input.char_indices().map(|(i, _)| i).collect();
+    points.push(input.len());
+
+    let cuts = rng.below(16) + 1;
+    let mut chosen: Vec<uint> = (0..cuts).map(|_| points[rng.below(points.len())]).collect();
+    chosen.sort();
+
+    let mut out = vec!();
+    let mut start = 0;
+    for &p in chosen.iter() {
+        out.push(String::from_str(&input[start..p]));
+        start = p;
+    }
+    out.push(String::from_str(&input[start..]));
+    out
+}
+
+/// Check that each of `splits` gives the same tokens as `input` whole.
+pub fn check_splits<I>(input: &str, opts: TokenizerOpts, splits: I) -> Result<(), SplitMismatch>
+    where I: Iterator<Item=Vec<String>>,
+{
+    let expected = tokenize_chunks(vec!(String::from_str(input)), opts.clone());
+    for chunks in splits {
+        let got = tokenize_chunks(chunks.clone(), opts.clone());
+        if got != expected {
+            return Err(SplitMismatch {
+                chunks: chunks,
+                expected: expected,
+                got: got,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check every way of splitting `input` into at most `max_pieces`
+/// pieces.  The number of splits grows quickly with the length of the
+/// input, so this is for short inputs and few pieces.
+pub fn check_all_splits(input: &str, opts: TokenizerOpts, max_pieces: uint)
+        -> Result<(), SplitMismatch> {
+    check_splits(input, opts, all_splits(input, max_pieces).into_iter())
+}
+
+/// Check `count` pseudo-random ways of splitting `input`, chosen by
+/// `seed`.
+pub fn check_random_splits(input: &str, opts: TokenizerOpts, seed: u64, count: uint)
+        -> Result<(), SplitMismatch> {
+    let mut rng = SplitRng::new(seed);
+    let splits: Vec<Vec<String>> = (0..count).map(|_| random_split(input, &mut rng)).collect();
+    check_splits(input, opts, splits.into_iter())
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::cmp;
+    use core::default::Default;
+    use collections::string::String;
+
+    use super::{all_splits, random_split, SplitRng, check_all_splits, check_random_splits};
+    use tokenizer::TokenizerOpts;
+    use tokenizer::states::{State, Data, RawData, Rcdata, ScriptData, ScriptDataEscaped};
+    use tokenizer::states::{Escaped, Plaintext, CdataSection};
+
+    #[test]
+    fn splits() {
+        assert_eq!(all_splits("ab", 1), vec!(vec!(String::from_str("ab"))));
+        assert_eq!(all_splits("ab", 2).len(), 3);
+        assert_eq!(all_splits("a\u{e9}", 3).len(), 6);
+
+        let mut a = SplitRng::new(7);
+        let mut b = SplitRng::new(7);
+        for _ in 0..20u {
+            let split = random_split("<p a=b>\u{e9}&amp;</p>", &mut a);
+            let mut joined = String::new();
+            for piece in split.iter() {
+                joined.push_str(piece.as_slice());
+            }
+            assert_eq!(joined.as_slice(), "<p a=b>\u{e9}&amp;</p>");
+            assert_eq!(split, random_split("<p a=b>\u{e9}&amp;</p>", &mut b));
+        }
+    }
+
+    #[test]
+    fn tokenizer_ignores_splits() {
+        let cases: &[(State, &str)] = &[
+            (Data, "<!DOCTYPE html><p class='a' id=b>x&amp;y&notin;z</p>"),
+            (Data, "<!--a-b--!>c<?d>e</f g>&#x41;&#65;&#0;\0"),
+            (Data, "a\r\nb\rc<br/>"),
+            (RawData(Rcdata), "a&lt;</title>"),
+            (RawData(ScriptData), "a<!--<script>b</script>--></script>"),
+            (RawData(ScriptDataEscaped(Escaped)), "a-\0--></script>"),
+            (Plaintext, "<a>&amp;\0"),
+            (CdataSection, "a]b]]c]]>"),
+        ];
+        for &(state, input) in cases.iter() {
+            for &exact_errors in [false, true].iter() {
+                let opts = TokenizerOpts {
+                    exact_errors: exact_errors,
+                    initial_state: Some(state),
+                    last_start_tag_name: Some(String::from_str("script")),
+                    .. Default::default()
+                };
+                // All splits of long input would take too long.
+                let short = &input[..cmp::min(input.len(), 16)];
+                check_all_splits(short, opts.clone(), 3).unwrap();
+                check_random_splits(input, opts, 1, 200).unwrap();
+            }
+        }
+    }
+}
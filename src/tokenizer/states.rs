@@ -9,8 +9,10 @@
 
 //! Tokenizer states.
 //!
-//! This is public for use by the tokenizer tests.  Other library
-//! users should not have to care about this.
+//! These are the states of the tokenization section of the spec, with
+//! families of similar states, such as the RCDATA, RAWTEXT and script
+//! data states, parameterized by a kind.  A `TokenSink` can switch the
+//! tokenizer to one of them with `query_state_change`.
 
 use core::prelude::*;
 
@@ -20,26 +22,46 @@ pub use self::RawKind::*;
 pub use self::AttrValueKind::*;
 pub use self::State::*;
 
+/// Which of the escaped script data states.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash, Debug)]
 pub enum ScriptEscapeKind {
+    /// Inside `<!--` in a script.
     Escaped,
+
+    /// Inside `<!--<script>` in a script.
     DoubleEscaped,
 }
 
+/// Which identifier of a doctype.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash, Debug)]
 pub enum DoctypeIdKind {
     Public,
     System,
 }
 
+/// Which kind of raw text is being tokenized.  These differ in which
+/// characters are special; in all of them, only an end tag matching the
+/// last start tag ends the text.
+///
+/// `<plaintext>` can't be ended at all, so it has its own `Plaintext`
+/// state instead.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash, Debug)]
 pub enum RawKind {
+    /// Text with character references, as in `<title>` and `<textarea>`.
     Rcdata,
+
+    /// Text without character references, as in `<style>`, `<xmp>`,
+    /// `<iframe>`, `<noembed>` and `<noframes>`.
     Rawtext,
+
+    /// The contents of `<script>`.
     ScriptData,
+
+    /// The contents of `<script>` after `<!--`.
     ScriptDataEscaped(ScriptEscapeKind),
 }
 
+/// How an attribute value is quoted.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash, Debug)]
 pub enum AttrValueKind {
     Unquoted,
@@ -47,6 +69,11 @@ pub enum AttrValueKind {
     DoubleQuoted,
 }
 
+/// A tokenizer state.  See `spec_anchor` for where each is defined.
+///
+/// `RawData(kind)` is the state for the text of an element such as
+/// `<title>` or `<script>`; the tree builder switches to it after
+/// the start tag.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash, Debug)]
 pub enum State {
     Data,
@@ -91,3 +118,131 @@ pub enum State {
     BogusDoctype,
     CdataSection,
 }
+
+impl State {
+    /// The anchor in the spec of the section defining this state, within
+    /// `https://html.spec.whatwg.org/multipage/syntax.html`.
+    ///
+    /// Each state's code in the tokenizer is marked with the same anchor.
+    pub fn spec_anchor(self) -> &'static str {
+        match self {
+            Data => "data-state",
+            Plaintext => "plaintext-state",
+            TagOpen => "tag-open-state",
+            EndTagOpen => "end-tag-open-state",
+            TagName => "tag-name-state",
+            RawData(Rcdata) => "rcdata-state",
+            RawData(Rawtext) => "rawtext-state",
+            RawData(ScriptData) => "script-data-state",
+            RawData(ScriptDataEscaped(Escaped)) => "script-data-escaped-state",
+            RawData(ScriptDataEscaped(DoubleEscaped)) => "script-data-double-escaped-state",
+            RawLessThanSign(Rcdata) => "rcdata-less-than-sign-state",
+            RawLessThanSign(Rawtext) => "rawtext-less-than-sign-state",
+            RawLessThanSign(ScriptData) => "script-data-less-than-sign-state",
+            RawLessThanSign(ScriptDataEscaped(Escaped))
+                => "script-data-escaped-less-than-sign-state",
+            RawLessThanSign(ScriptDataEscaped(DoubleEscaped))
+                => "script-data-double-escaped-less-than-sign-state",
+            RawEndTagOpen(Rcdata) => "rcdata-end-tag-open-state",
+            RawEndTagOpen(Rawtext) => "rawtext-end-tag-open-state",
+            RawEndTagOpen(ScriptData) => "script-data-end-tag-open-state",
+            RawEndTagOpen(ScriptDataEscaped(_)) => "script-data-escaped-end-tag-open-state",
+            RawEndTagName(Rcdata) => "rcdata-end-tag-name-state",
+            RawEndTagName(Rawtext) => "rawtext-end-tag-name-state",
+            RawEndTagName(ScriptData) => "script-data-end-tag-name-state",
+            RawEndTagName(ScriptDataEscaped(_)) => "script-data-escaped-end-tag-name-state",
+            ScriptDataEscapeStart(Escaped) => "script-data-escape-start-state",
+            ScriptDataEscapeStart(DoubleEscaped) => "script-data-double-escape-start-state",
+            ScriptDataEscapeStartDash => "script-data-escape-start-dash-state",
+            ScriptDataEscapedDash(Escaped) => "script-data-escaped-dash-state",
+            ScriptDataEscapedDash(DoubleEscaped) => "script-data-double-escaped-dash-state",
+            ScriptDataEscapedDashDash(Escaped) => "script-data-escaped-dash-dash-state",
+            ScriptDataEscapedDashDash(DoubleEscaped)
+                => "script-data-double-escaped-dash-dash-state",
+            ScriptDataDoubleEscapeEnd => "script-data-double-escape-end-state",
+            BeforeAttributeName => "before-attribute-name-state",
+            AttributeName => "attribute-name-state",
+            AfterAttributeName => "after-attribute-name-state",
+            BeforeAttributeValue => "before-attribute-value-state",
+            AttributeValue(DoubleQuoted) => "attribute-value-(double-quoted)-state",
+            AttributeValue(SingleQuoted) => "attribute-value-(single-quoted)-state",
+            AttributeValue(Unquoted) => "attribute-value-(unquoted)-state",
+            AfterAttributeValueQuoted => "after-attribute-value-(quoted)-state",
+            SelfClosingStartTag => "self-closing-start-tag-state",
+            BogusComment => "bogus-comment-state",
+            MarkupDeclarationOpen => "markup-declaration-open-state",
+            CommentStart => "comment-start-state",
+            CommentStartDash => "comment-start-dash-state",
+            Comment => "comment-state",
+            CommentEndDash => "comment-end-dash-state",
+            CommentEnd => "comment-end-state",
+            CommentEndBang => "comment-end-bang-state",
+            Doctype => "doctype-state",
+            BeforeDoctypeName => "before-doctype-name-state",
+            DoctypeName => "doctype-name-state",
+            AfterDoctypeName => "after-doctype-name-state",
+            AfterDoctypeKeyword(Public) => "after-doctype-public-keyword-state",
+            AfterDoctypeKeyword(System) => "after-doctype-system-keyword-state",
+            BeforeDoctypeIdentifier(Public) => "before-doctype-public-identifier-state",
+            BeforeDoctypeIdentifier(System) => "before-doctype-system-identifier-state",
+            DoctypeIdentifierDoubleQuoted(Public)
+                => "doctype-public-identifier-(double-quoted)-state",
+            DoctypeIdentifierDoubleQuoted(System)
+                => "doctype-system-identifier-(double-quoted)-state",
+            DoctypeIdentifierSingleQuoted(Public)
+                => "doctype-public-identifier-(single-quoted)-state",
+            DoctypeIdentifierSingleQuoted(System)
+                => "doctype-system-identifier-(single-quoted)-state",
+            AfterDoctypeIdentifier(Public) => "after-doctype-public-identifier-state",
+            AfterDoctypeIdentifier(System) => "after-doctype-system-identifier-state",
+            BetweenDoctypePublicAndSystemIdentifiers
+                => "between-doctype-public-and-system-identifiers-state",
+            BogusDoctype => "bogus-doctype-state",
+            CdataSection => "cdata-section-state",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+
+    use super::*;
+
+    // Every state should be implemented under a `//§` comment naming
+    // its anchor.
+    #[test]
+    fn anchors_marked_in_tokenizer() {
+        let source = include_str!("mod.rs");
+        let kinds = [Rcdata, Rawtext, ScriptData,
+            ScriptDataEscaped(Escaped), ScriptDataEscaped(DoubleEscaped)];
+        let ids = [Public, System];
+        let mut all = vec!(Data, Plaintext, TagOpen, EndTagOpen, TagName,
+            ScriptDataEscapeStart(Escaped), ScriptDataEscapeStart(DoubleEscaped),
+            ScriptDataEscapeStartDash, ScriptDataDoubleEscapeEnd,
+            ScriptDataEscapedDash(Escaped), ScriptDataEscapedDash(DoubleEscaped),
+            ScriptDataEscapedDashDash(Escaped), ScriptDataEscapedDashDash(DoubleEscaped),
+            BeforeAttributeName, AttributeName, AfterAttributeName, BeforeAttributeValue,
+            AttributeValue(Unquoted), AttributeValue(SingleQuoted), AttributeValue(DoubleQuoted),
+            AfterAttributeValueQuoted, SelfClosingStartTag, BogusComment, MarkupDeclarationOpen,
+            CommentStart, CommentStartDash, Comment, CommentEndDash, CommentEnd, CommentEndBang,
+            Doctype, BeforeDoctypeName, DoctypeName, AfterDoctypeName,
+            BetweenDoctypePublicAndSystemIdentifiers, BogusDoctype, CdataSection);
+        for &k in kinds.iter() {
+            all.push_all(&[RawData(k), RawLessThanSign(k), RawEndTagOpen(k), RawEndTagName(k)]);
+        }
+        for &k in ids.iter() {
+            all.push_all(&[AfterDoctypeKeyword(k), BeforeDoctypeIdentifier(k),
+                DoctypeIdentifierDoubleQuoted(k), DoctypeIdentifierSingleQuoted(k),
+                AfterDoctypeIdentifier(k)]);
+        }
+
+        for state in all.into_iter() {
+            let anchor = state.spec_anchor();
+            let marked = source.lines()
+                .filter(|l| l.trim_left().starts_with("//§"))
+                .any(|l| l.split(' ').any(|w| w == anchor));
+            assert!(marked, "no //§ {} for {:?}", anchor, state);
+        }
+    }
+}
@@ -17,25 +17,33 @@ use core::prelude::*;
 use tree_builder::types::*;
 use tree_builder::tag_sets::*;
 use tree_builder::interface::{TreeSink, QuirksMode, NodeOrText, AppendNode, AppendText};
+use tree_builder::interface::{ElementFlags, InsertionPoint, LastChild, TableFosterParenting};
+use tree_builder::interface::{Recover, Ignore, CloseTo, Abort};
 use tree_builder::rules::TreeBuilderStep;
+use tree_builder::data;
 
-use tokenizer::{Attribute, Tag, EndTag};
+use tokenizer::{Attribute, Tag, StartTag, EndTag};
 use tokenizer::states::{RawData, RawKind};
 
-use util::str::AsciiExt;
+use encoding::{Encoding, label_to_encoding, extract_from_meta_content};
 
-#[cfg(not(for_c))]
+use diagnostic::Diagnostic;
+
+use util::str::{AsciiExt, is_ascii_whitespace};
+
+#[cfg(feature = "std")]
 use util::str::to_escaped_string;
 
+use core::default::Default;
 use core::mem::replace;
+use core::cmp;
 use core::iter::{Rev, Enumerate};
 use core::slice;
 use core::fmt::Debug;
 use collections::vec::Vec;
 use collections::string::String;
-use std::borrow::Cow::Borrowed;
 
-use string_cache::{Atom, QualName};
+use string_cache::{Atom, QualName, Namespace};
 
 pub use self::PushFlag::*;
 
@@ -65,6 +73,8 @@ enum Bookmark<Handle> {
 
 // These go in a trait so that we can control visibility.
 pub trait TreeBuilderActions<Handle> {
+    fn report(&mut self, error: Diagnostic);
+    fn charge(&mut self, nodes: uint, text: uint);
     fn unexpected<T: Debug>(&mut self, thing: &T) -> ProcessResult;
     fn assert_named(&mut self, node: Handle, name: Atom);
     fn clear_active_formatting_to_marker(&mut self);
@@ -73,17 +83,21 @@ pub trait TreeBuilderActions<Handle> {
     fn append_comment(&mut self, text: String) -> ProcessResult;
     fn append_comment_to_doc(&mut self, text: String) -> ProcessResult;
     fn append_comment_to_html(&mut self, text: String) -> ProcessResult;
+    fn appropriate_place_for_insertion(&self, override_target: Option<Handle>) -> InsertionPoint<Handle>;
     fn insert_appropriately(&mut self, child: NodeOrText<Handle>, override_target: Option<Handle>);
     fn insert_phantom(&mut self, name: Atom) -> Handle;
     fn insert_and_pop_element_for(&mut self, tag: Tag) -> Handle;
     fn insert_element_for(&mut self, tag: Tag) -> Handle;
     fn insert_element(&mut self, push: PushFlag, name: Atom, attrs: Vec<Attribute>) -> Handle;
     fn create_root(&mut self, attrs: Vec<Attribute>);
+    fn create_element_for_token(&mut self, name: QualName, attrs: Vec<Attribute>) -> Handle;
     fn close_the_cell(&mut self);
     fn reset_insertion_mode(&mut self) -> InsertionMode;
     fn process_chars_in_table(&mut self, token: Token) -> ProcessResult;
     fn foster_parent_in_body(&mut self, token: Token) -> ProcessResult;
     fn is_type_hidden(&self, tag: &Tag) -> bool;
+    fn meta_encoding(&self, tag: &Tag) -> Option<Encoding>;
+    fn check_base_href(&mut self, tag: &Tag);
     fn close_p_element_in_button_scope(&mut self);
     fn close_p_element(&mut self);
     fn expect_to_close(&mut self, name: Atom);
@@ -103,11 +117,15 @@ pub trait TreeBuilderActions<Handle> {
     fn reconstruct_formatting(&mut self);
     fn remove_from_stack(&mut self, elem: &Handle);
     fn pop(&mut self) -> Handle;
+    fn pop_to(&mut self, len: uint);
+    fn element_closed(&mut self, elem: Handle);
     fn push(&mut self, elem: &Handle);
     fn adoption_agency(&mut self, subject: Atom);
     fn current_node_in<TagSet>(&self, set: TagSet) -> bool where TagSet: Fn(QualName) -> bool;
     fn current_node(&self) -> Handle;
     fn parse_raw_data(&mut self, tag: Tag, k: RawKind);
+    fn start_raw_text_report(&mut self, tag: &Tag);
+    fn end_raw_text_report(&mut self, node: Handle);
     fn to_raw_text_mode(&mut self, k: RawKind);
     fn stop_parsing(&mut self) -> ProcessResult;
     fn set_quirks_mode(&mut self, mode: QuirksMode);
@@ -115,7 +133,19 @@ pub trait TreeBuilderActions<Handle> {
     fn is_marker_or_open(&self, entry: &FormatEntry<Handle>) -> bool;
     fn position_in_active_formatting(&self, element: &Handle) -> Option<usize>;
     fn process_end_tag_in_body(&mut self, tag: Tag);
+    fn end_tag_recovery(&mut self, name: &Atom) -> bool;
     fn handle_misnested_a_tags(&mut self, tag: &Tag);
+    fn adjusted_current_node(&self) -> Handle;
+    fn is_html_integration_point(&self, elem: Handle) -> bool;
+    fn is_foreign(&self, token: &Token) -> bool;
+    fn adjust_foreign_attributes(&mut self, tag: &mut Tag);
+    fn adjust_svg_tag_name(&mut self, tag: &mut Tag);
+    fn adjust_svg_attributes(&mut self, tag: &mut Tag);
+    fn adjust_mathml_attributes(&mut self, tag: &mut Tag);
+    fn insert_foreign_element(&mut self, tag: Tag, ns: Namespace) -> ProcessResult;
+    fn foreign_start_tag(&mut self, tag: Tag) -> ProcessResult;
+    fn unexpected_start_tag_in_foreign_content(&mut self, tag: Tag) -> ProcessResult;
+    fn foreign_end_tag(&mut self, tag: Tag) -> ProcessResult;
 }
 
 #[doc(hidden)]
@@ -124,8 +154,30 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
     where Handle: Clone,
           Sink: TreeSink<Handle=Handle>,
 {
+    fn report(&mut self, mut error: Diagnostic) {
+        if error.span.is_none() {
+            error.span = self.current_span;
+        }
+        self.sink.report_diagnostic(error);
+    }
+
+    // Count nodes and text against the `max_nodes` and `max_text`
+    // budgets.  Running out stops the parse after the current token.
+    fn charge(&mut self, nodes: uint, text: uint) {
+        self.node_count += nodes;
+        self.text_count += text;
+        if self.aborted {
+            return;
+        }
+        let over = |count: uint, max: Option<uint>| max.map_or(false, |max| count > max);
+        if over(self.node_count, self.opts.max_nodes) || over(self.text_count, self.opts.max_text) {
+            self.report(Diagnostic::error("Allocation budget exceeded"));
+            self.aborted = true;
+        }
+    }
+
     fn unexpected<T: Debug>(&mut self, _thing: &T) -> ProcessResult {
-        self.sink.parse_error(format_if!(
+        self.report(error_if!(
             self.opts.exact_errors,
             "Unexpected token",
             "Unexpected token {} in insertion mode {:?}", to_escaped_string(_thing), self.mode));
@@ -179,11 +231,28 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
 
     // The generic raw text / RCDATA parsing algorithm.
     fn parse_raw_data(&mut self, tag: Tag, k: RawKind) {
+        self.start_raw_text_report(&tag);
         self.insert_element_for(tag);
         self.to_raw_text_mode(k);
     }
     //§ END
 
+    fn start_raw_text_report(&mut self, tag: &Tag) {
+        if self.opts.report_raw_text && match tag.name {
+            atom!(script) | atom!(style) => true,
+            _ => false,
+        } {
+            self.raw_text = Some((tag.name.clone(), tag.attrs.clone(), String::new()));
+        }
+    }
+
+    fn end_raw_text_report(&mut self, node: Handle) {
+        match self.raw_text.take() {
+            Some((name, attrs, text)) => self.sink.raw_text_content(node, name, attrs, text),
+            None => (),
+        }
+    }
+
     fn current_node(&self) -> Handle {
         self.open_elems.last().expect("no current element").clone()
     }
@@ -194,13 +263,13 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
         set(self.sink.elem_name(self.current_node()))
     }
 
-    // Insert at the "appropriate place for inserting a node".
-    fn insert_appropriately(&mut self, child: NodeOrText<Handle>, override_target: Option<Handle>) {
+    //§ appropriate-place-for-inserting-a-node
+    fn appropriate_place_for_insertion(&self, override_target: Option<Handle>) -> InsertionPoint<Handle> {
         declare_tag_set!(foster_target = table tbody tfoot thead tr);
         let target = override_target.unwrap_or_else(|| self.current_node());
         if !(self.foster_parenting && self.elem_in(target.clone(), foster_target)) {
             // No foster parenting (the common case).
-            return self.sink.append(target, child);
+            return LastChild(target);
         }
 
         // Foster parenting
@@ -212,25 +281,23 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
             .next();
 
         match last_table {
-            None => {
-                let html_elem = self.html_elem();
-                self.sink.append(html_elem, child);
-            }
-            Some((idx, last_table)) => {
-                // Try inserting "inside last table's parent node, immediately before last table"
-                match self.sink.append_before_sibling(last_table.clone(), child) {
-                    Ok(()) => (),
-
-                    // If last_table has no parent, we regain ownership of the child.
-                    // Insert "inside previous element, after its last child (if any)"
-                    Err(child) => {
-                        let previous_element = self.open_elems[idx-1].clone();
-                        self.sink.append(previous_element, child);
-                    }
-                }
-            }
+            None => LastChild(self.html_elem()),
+
+            // Inside last table's parent node, immediately before last table,
+            // or if it has no parent, inside the previous element, after its
+            // last child (if any).
+            Some((idx, last_table)) => TableFosterParenting {
+                table: last_table.clone(),
+                prev_element: self.open_elems[idx-1].clone(),
+            },
         }
     }
+    //§ END
+
+    fn insert_appropriately(&mut self, child: NodeOrText<Handle>, override_target: Option<Handle>) {
+        let point = self.appropriate_place_for_insertion(override_target);
+        self.sink.insert(point, child);
+    }
 
     fn adoption_agency(&mut self, subject: Atom) {
         // 1.
@@ -266,20 +333,20 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
                     .rposition(|n| self.sink.same_node(n.clone(), fmt_elem.clone())),
 
                 {
-                    self.sink.parse_error(Borrowed("Formatting element not open"));
+                    self.report(Diagnostic::error("Formatting element not open"));
                     self.active_formatting.remove(fmt_elem_index);
                 }
             );
 
             // 7.
             if !self.in_scope(default_scope, |n| self.sink.same_node(n.clone(), fmt_elem.clone())) {
-                self.sink.parse_error(Borrowed("Formatting element not in scope"));
+                self.report(Diagnostic::error("Formatting element not in scope"));
                 return;
             }
 
             // 8.
             if !self.sink.same_node(self.current_node(), fmt_elem.clone()) {
-                self.sink.parse_error(Borrowed("Formatting element not current node"));
+                self.report(Diagnostic::error("Formatting element not current node"));
             }
 
             // 9.
@@ -293,7 +360,7 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
 
                 // 10.
                 {
-                    self.open_elems.truncate(fmt_elem_stack_index);
+                    self.pop_to(fmt_elem_stack_index);
                     self.active_formatting.remove(fmt_elem_index);
                 }
             );
@@ -329,6 +396,7 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
                     self.position_in_active_formatting(&node)
                         .map(|position| self.active_formatting.remove(position));
                     self.open_elems.remove(node_index);
+                    self.element_closed(node);
                     continue;
                 }
 
@@ -338,6 +406,7 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
                     // 13.6.
                     {
                         self.open_elems.remove(node_index);
+                        self.element_closed(node);
                         continue;
                     }
                 );
@@ -352,7 +421,7 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
                 };
                 // FIXME: Is there a way to avoid cloning the attributes twice here (once on their
                 // own, once as part of t.clone() above)?
-                let new_element = self.sink.create_element(
+                let new_element = self.create_element_for_token(
                     QualName::new(ns!(HTML), tag.name.clone()), tag.attrs.clone());
                 self.open_elems[node_index] = new_element.clone();
                 self.active_formatting[node_formatting_index] = Element(new_element.clone(), tag);
@@ -380,7 +449,7 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
             // 15.
             // FIXME: Is there a way to avoid cloning the attributes twice here (once on their own,
             // once as part of t.clone() above)?
-            let new_element = self.sink.create_element(
+            let new_element = self.create_element_for_token(
                 QualName::new(ns!(HTML), fmt_elem_tag.name.clone()), fmt_elem_tag.attrs.clone());
             let new_entry = Element(new_element.clone(), fmt_elem_tag);
 
@@ -425,13 +494,36 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
     }
 
     fn pop(&mut self) -> Handle {
-        self.open_elems.pop().expect("no current element")
+        let elem = self.open_elems.pop().expect("no current element");
+        self.element_closed(elem.clone());
+        elem
+    }
+
+    // Pop elements until only `len` remain.
+    fn pop_to(&mut self, len: uint) {
+        while self.open_elems.len() > len {
+            self.pop();
+        }
     }
 
     fn remove_from_stack(&mut self, elem: &Handle) {
         let mut open_elems = replace(&mut self.open_elems, vec!());
+        let len = open_elems.len();
         open_elems.retain(|x| !self.sink.same_node(elem.clone(), x.clone()));
         self.open_elems = open_elems;
+        if self.open_elems.len() < len {
+            self.element_closed(elem.clone());
+        }
+    }
+
+    // Tell the sink where an element ended, if we are tracking positions.
+    fn element_closed(&mut self, elem: Handle) {
+        let span = unwrap_or_return!(self.current_span, ());
+        let end = match self.current_end_tag {
+            Some(ref name) if self.html_elem_named(elem.clone(), name.clone()) => span.end,
+            _ => span.start,
+        };
+        self.sink.set_element_end(elem, end);
     }
 
     fn is_marker_or_open(&self, entry: &FormatEntry<Handle>) -> bool {
@@ -512,7 +604,7 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
         for elem in self.open_elems.iter() {
             let name = self.sink.elem_name(elem.clone());
             if !body_end_ok(name.clone()) {
-                self.sink.parse_error(format_if!(self.opts.exact_errors,
+                self.report(error_if!(self.opts.exact_errors,
                     "Unexpected open tag at end of body",
                     "Unexpected open tag {:?} at end of body", name));
                 // FIXME: Do we keep checking after finding one bad tag?
@@ -588,7 +680,7 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
             if self.current_node_in(|x| pred(x)) {
                 break;
             }
-            self.open_elems.pop();
+            self.pop();
         }
     }
 
@@ -598,11 +690,11 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
         where P: Fn(QualName) -> bool
     {
         let mut n = 0;
-        loop {
+        while !self.open_elems.is_empty() {
             n += 1;
-            match self.open_elems.pop() {
-                None => break,
-                Some(elem) => if pred(self.sink.elem_name(elem)) { break; },
+            let elem = self.pop();
+            if pred(self.sink.elem_name(elem)) {
+                break;
             }
         }
         n
@@ -616,7 +708,7 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
     // Signal an error if it was not the first one.
     fn expect_to_close(&mut self, name: Atom) {
         if self.pop_until_named(name.clone()) != 1 {
-            self.sink.parse_error(format_if!(self.opts.exact_errors,
+            self.report(error_if!(self.opts.exact_errors,
                 "Unexpected open element",
                 "Unexpected open element while closing {:?}", name));
         }
@@ -642,6 +734,40 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
         }
     }
 
+    fn meta_encoding(&self, tag: &Tag) -> Option<Encoding> {
+        let attr = |name| tag.attrs.iter().find(|&at| at.name == name);
+
+        // An unrecognized charset label doesn't hide a pragma.
+        let charset = attr(qualname!("", "charset"))
+            .and_then(|at| label_to_encoding(at.value.as_slice()));
+        match charset {
+            Some(enc) => return Some(enc),
+            None => (),
+        }
+
+        match (attr(qualname!("", "http-equiv")), attr(qualname!("", "content"))) {
+            (Some(equiv), Some(content))
+                if equiv.value.as_slice().eq_ignore_ascii_case("content-type")
+                => extract_from_meta_content(content.value.as_slice().as_bytes()),
+            _ => None,
+        }
+    }
+
+    //§ frozen-base-url
+    fn check_base_href(&mut self, tag: &Tag) {
+        if self.seen_base_href {
+            return;
+        }
+        match tag.attrs.iter().find(|&at| at.name == qualname!("", "href")) {
+            None => (),
+            Some(at) => {
+                self.seen_base_href = true;
+                self.sink.set_base_url(at.value.clone());
+            }
+        }
+    }
+    //§ END
+
     fn foster_parent_in_body(&mut self, token: Token) -> ProcessResult {
         h5e_warn!("foster parenting not implemented");
         self.foster_parenting = true;
@@ -658,7 +784,7 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
             self.orig_mode = Some(self.mode);
             Reprocess(InTableText, token)
         } else {
-            self.sink.parse_error(format_if!(self.opts.exact_errors,
+            self.report(error_if!(self.opts.exact_errors,
                 "Unexpected characters in table",
                 "Unexpected characters {} in table", to_escaped_string(&token)));
             self.foster_parent_in_body(token)
@@ -666,13 +792,18 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
     }
 
     fn reset_insertion_mode(&mut self) -> InsertionMode {
-        for (i, node) in self.open_elems.iter().enumerate().rev() {
+        for (i, mut node) in self.open_elems.iter().enumerate().rev() {
+            let last = i == 0u;
+            if last {
+                match self.context_elem {
+                    Some(ref ctx) => node = ctx,
+                    None => (),
+                }
+            }
             let name = match self.sink.elem_name(node.clone()) {
                 QualName { ns: ns!(HTML), local } => local,
                 _ => continue,
             };
-            let last = i == 0u;
-            // FIXME: fragment case context element
             match name {
                 // FIXME: <select> sub-steps
                 atom!(select) => return InSelect,
@@ -702,23 +833,44 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
     fn close_the_cell(&mut self) {
         self.generate_implied_end(cursory_implied_end);
         if self.pop_until(td_th) != 1 {
-            self.sink.parse_error(Borrowed("expected to close <td> or <th> with cell"));
+            self.report(Diagnostic::error("expected to close <td> or <th> with cell"));
         }
         self.clear_active_formatting_to_marker();
     }
 
-    fn append_text(&mut self, text: String) -> ProcessResult {
+    fn append_text(&mut self, mut text: String) -> ProcessResult {
+        declare_tag_set!(preformatted = pre listing textarea);
+        // Whitespace is dropped only if the whole text node would be
+        // whitespace, which may not be known until the next token.  It's
+        // kept anywhere inside preformatted text, not just directly.
+        if self.opts.ignore_whitespace_text
+            && self.mode != Text
+            && !self.open_elems.iter().any(|e| self.elem_in(e.clone(), preformatted)) {
+            if !self.in_text && text.chars().all(is_ascii_whitespace) {
+                self.pending_whitespace.push_str(text.as_slice());
+                return Done;
+            }
+            if !self.pending_whitespace.is_empty() {
+                let rest = replace(&mut text, String::new());
+                text = replace(&mut self.pending_whitespace, String::new());
+                text.push_str(rest.as_slice());
+            }
+            self.in_text = true;
+        }
+        self.charge(0, text.len());
         self.insert_appropriately(AppendText(text), None);
         Done
     }
 
     fn append_comment(&mut self, text: String) -> ProcessResult {
+        self.charge(1, text.len());
         let comment = self.sink.create_comment(text);
         self.insert_appropriately(AppendNode(comment), None);
         Done
     }
 
     fn append_comment_to_doc(&mut self, text: String) -> ProcessResult {
+        self.charge(1, text.len());
         let target = self.doc_handle.clone();
         let comment = self.sink.create_comment(text);
         self.sink.append(target, AppendNode(comment));
@@ -726,6 +878,7 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
     }
 
     fn append_comment_to_html(&mut self, text: String) -> ProcessResult {
+        self.charge(1, text.len());
         let target = self.html_elem();
         let comment = self.sink.create_comment(text);
         self.sink.append(target, AppendNode(comment));
@@ -734,15 +887,28 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
 
     //§ creating-and-inserting-nodes
     fn create_root(&mut self, attrs: Vec<Attribute>) {
-        let elem = self.sink.create_element(qualname!(HTML, html), attrs);
+        let elem = self.create_element_for_token(qualname!(HTML, html), attrs);
         self.push(&elem);
         self.sink.append(self.doc_handle.clone(), AppendNode(elem));
         // FIXME: application cache selection algorithm
     }
 
+    fn create_element_for_token(&mut self, name: QualName, attrs: Vec<Attribute>) -> Handle {
+        let text = attrs.iter().fold(0, |n, attr| n + attr.value.len());
+        self.charge(1, text);
+        let mut flags: ElementFlags = Default::default();
+        if name.ns == ns!(HTML) {
+            flags.is = attrs.iter()
+                .find(|attr| attr.name.ns == ns!("") && attr.name.local.as_slice() == "is")
+                .map(|attr| attr.value.clone());
+            flags.valid_custom_element_name = data::valid_custom_element_name(name.local.as_slice());
+        }
+        self.sink.create_element_with_flags(name, attrs, flags)
+    }
+
     fn insert_element(&mut self, push: PushFlag, name: Atom, attrs: Vec<Attribute>)
             -> Handle {
-        let elem = self.sink.create_element(QualName::new(ns!(HTML), name), attrs);
+        let elem = self.create_element_for_token(QualName::new(ns!(HTML), name), attrs);
         self.insert_appropriately(AppendNode(elem.clone()), None);
         match push {
             Push => self.push(&elem),
@@ -763,6 +929,74 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
     fn insert_phantom(&mut self, name: Atom) -> Handle {
         self.insert_element(Push, name, vec!())
     }
+
+    fn insert_foreign_element(&mut self, tag: Tag, ns: Namespace) -> ProcessResult {
+        let name = QualName::new(ns, tag.name);
+        let integration_point = name == qualname!(MathML, "annotation-xml")
+            && annotation_xml_integration_point(tag.attrs.as_slice());
+
+        let elem = self.create_element_for_token(name, tag.attrs);
+        if integration_point {
+            self.annotation_xml_integration_points.push(elem.clone());
+        }
+        self.insert_appropriately(AppendNode(elem.clone()), None);
+        self.push(&elem);
+        if tag.self_closing {
+            self.pop();
+            DoneAckSelfClosing
+        } else {
+            Done
+        }
+    }
+    //§ END
+
+    fn adjust_svg_tag_name(&mut self, tag: &mut Tag) {
+        match data::svg_tag_name(&tag.name) {
+            Some(name) => tag.name = name,
+            None => (),
+        }
+    }
+
+    //§ adjust-svg-attributes
+    fn adjust_svg_attributes(&mut self, tag: &mut Tag) {
+        for attr in tag.attrs.iter_mut() {
+            if attr.name.ns != ns!("") {
+                continue;
+            }
+            match data::svg_attr_name(&attr.name.local) {
+                Some(local) => attr.name.local = local,
+                None => (),
+            }
+        }
+    }
+    //§ END
+
+    //§ adjust-mathml-attributes
+    fn adjust_mathml_attributes(&mut self, tag: &mut Tag) {
+        for attr in tag.attrs.iter_mut() {
+            if attr.name.ns != ns!("") {
+                continue;
+            }
+            match data::mathml_attr_name(&attr.name.local) {
+                Some(local) => attr.name.local = local,
+                None => (),
+            }
+        }
+    }
+    //§ END
+
+    //§ adjust-foreign-attributes
+    fn adjust_foreign_attributes(&mut self, tag: &mut Tag) {
+        for attr in tag.attrs.iter_mut() {
+            if attr.name.ns != ns!("") {
+                continue;
+            }
+            match data::foreign_attr_name(attr.name.local.as_slice()) {
+                Some(name) => attr.name = name,
+                None => (),
+            }
+        }
+    }
     //§ END
 
     fn create_formatting_element_for(&mut self, tag: Tag) -> Handle {
@@ -804,7 +1038,8 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
             }
 
             if self.elem_in(elem.clone(), special_tag) {
-                self.sink.parse_error(Borrowed("Found special tag while closing generic tag"));
+                self.report(Diagnostic::error("Found special tag while closing generic tag"));
+                self.end_tag_recovery(&tag.name);
                 return;
             }
         }
@@ -825,8 +1060,35 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
         if match_idx != self.open_elems.len() - 1 {
             // mis-nested tags
             self.unexpected(&tag);
+            if !self.end_tag_recovery(&tag.name) {
+                return;
+            }
+        }
+        self.pop_to(match_idx);
+    }
+
+    // Ask the sink's recovery policy what to do about an end tag which
+    // is a parse error.  Returns true if we should recover as the spec
+    // says; otherwise the policy's choice has been carried out.
+    fn end_tag_recovery(&mut self, name: &Atom) -> bool {
+        if self.sink.recovery_policy().is_none() {
+            return true;
+        }
+
+        let open_elems: Vec<QualName> = self.open_elems.iter()
+            .map(|e| self.sink.elem_name(e.clone())).collect();
+        let recovery = match self.sink.recovery_policy() {
+            Some(policy) => policy.unexpected_end_tag(name, open_elems.as_slice()),
+            None => Recover,
+        };
+
+        match recovery {
+            Recover => return true,
+            Ignore => (),
+            CloseTo(idx) => self.pop_to(cmp::max(idx, 1)),
+            Abort => self.aborted = true,
         }
-        self.open_elems.truncate(match_idx);
+        false
     }
 
     fn handle_misnested_a_tags(&mut self, tag: &Tag) {
@@ -845,4 +1107,133 @@ impl<Handle, Sink> TreeBuilderActions<Handle>
             .map(|index| self.active_formatting.remove(index));
         self.remove_from_stack(&node);
     }
+
+    fn adjusted_current_node(&self) -> Handle {
+        if self.open_elems.len() == 1 {
+            match self.context_elem {
+                Some(ref ctx) => return ctx.clone(),
+                None => (),
+            }
+        }
+        self.current_node()
+    }
+
+    // Whether an element is an HTML integration point depends on the
+    // attributes of an `<annotation-xml>`, which we noted when inserting it.
+    fn is_html_integration_point(&self, elem: Handle) -> bool {
+        let name = self.sink.elem_name(elem.clone());
+        if name == qualname!(MathML, "annotation-xml") {
+            self.annotation_xml_integration_points.iter()
+                .any(|h| self.sink.same_node(h.clone(), elem.clone()))
+        } else {
+            svg_html_integration_point(name)
+        }
+    }
+
+    //§ tree-construction
+    // Should this token be processed by the rules for foreign content,
+    // rather than those of the current insertion mode?
+    fn is_foreign(&self, token: &Token) -> bool {
+        if self.open_elems.is_empty() {
+            return false;
+        }
+
+        let node = self.adjusted_current_node();
+        let name = self.sink.elem_name(node.clone());
+        if name.ns == ns!(HTML) {
+            return false;
+        }
+
+        if mathml_text_integration_point(name.clone()) {
+            match *token {
+                CharacterTokens(..) | NullCharacterToken => return false,
+                TagToken(Tag { kind: StartTag, ref name, .. })
+                    if *name != atom!(mglyph) && *name != atom!(malignmark) => return false,
+                _ => (),
+            }
+        }
+
+        if name == qualname!(MathML, "annotation-xml") {
+            match *token {
+                TagToken(Tag { kind: StartTag, name: atom!(svg), .. }) => return false,
+                _ => (),
+            }
+        }
+
+        if self.is_html_integration_point(node) {
+            match *token {
+                CharacterTokens(..) | NullCharacterToken => return false,
+                TagToken(Tag { kind: StartTag, .. }) => return false,
+                _ => (),
+            }
+        }
+
+        match *token {
+            EOFToken => false,
+            _ => true,
+        }
+    }
+    //§ END
+
+    //§ parsing-main-inforeign
+    fn foreign_start_tag(&mut self, mut tag: Tag) -> ProcessResult {
+        let current_ns = self.sink.elem_name(self.adjusted_current_node()).ns;
+        match current_ns {
+            ns!(MathML) => self.adjust_mathml_attributes(&mut tag),
+            ns!(SVG) => {
+                self.adjust_svg_tag_name(&mut tag);
+                self.adjust_svg_attributes(&mut tag);
+            }
+            _ => (),
+        }
+        self.adjust_foreign_attributes(&mut tag);
+        self.insert_foreign_element(tag, current_ns)
+    }
+
+    fn unexpected_start_tag_in_foreign_content(&mut self, tag: Tag) -> ProcessResult {
+        self.unexpected(&tag);
+        if self.opts.fragment {
+            return self.foreign_start_tag(tag);
+        }
+
+        loop {
+            self.pop();
+            let node = self.current_node();
+            let name = self.sink.elem_name(node.clone());
+            if name.ns == ns!(HTML)
+                || mathml_text_integration_point(name)
+                || self.is_html_integration_point(node) {
+                break;
+            }
+        }
+        Reprocess(self.mode, TagToken(tag))
+    }
+
+    fn foreign_end_tag(&mut self, tag: Tag) -> ProcessResult {
+        let current_idx = self.open_elems.len() - 1;
+        let mut node_idx = current_idx;
+        loop {
+            let name = self.sink.elem_name(self.open_elems[node_idx].clone());
+            if node_idx != current_idx && name.ns == ns!(HTML) {
+                let mode = self.mode;
+                return self.step(mode, TagToken(tag));
+            }
+
+            if node_idx == 0 {
+                return Done;
+            }
+
+            // End tag names have been lowercased by the tokenizer.
+            if name.local.as_slice().to_ascii_lower().as_slice() == tag.name.as_slice() {
+                self.pop_to(node_idx);
+                return Done;
+            }
+
+            if node_idx == current_idx {
+                self.unexpected(&tag);
+            }
+            node_idx -= 1;
+        }
+    }
+    //§ END
 }
@@ -15,6 +15,10 @@ use util::str::AsciiExt;
 
 use collections::string::String;
 
+use string_cache::{Atom, QualName};
+
+use phf::Map;
+
 // These should all be lowercase, for ASCII-case-insensitive matching.
 static QUIRKY_PUBLIC_PREFIXES: &'static [&'static str] = &[
     "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
@@ -152,3 +156,101 @@ pub fn doctype_error_and_quirks(doctype: &Doctype, iframe_srcdoc: bool) -> (bool
 
     (err, quirk)
 }
+
+// The foreign_names! macro is defined in html5/macros/foreign_names.rs.
+static SVG_TAG_NAMES: Map<&'static str, &'static str>
+    = foreign_names!("../../data/foreign-names.json", "svg-tags");
+
+static SVG_ATTRIBUTES: Map<&'static str, &'static str>
+    = foreign_names!("../../data/foreign-names.json", "svg-attributes");
+
+static MATHML_ATTRIBUTES: Map<&'static str, &'static str>
+    = foreign_names!("../../data/foreign-names.json", "mathml-attributes");
+
+fn adjusted_name(map: &Map<&'static str, &'static str>, name: &Atom) -> Option<Atom> {
+    map.get(name.as_slice()).map(|&n| Atom::from_slice(n))
+}
+
+//§ parsing-main-inforeign
+/// The mixed-case name of an SVG element, if its lowercased name differs.
+pub fn svg_tag_name(name: &Atom) -> Option<Atom> {
+    adjusted_name(&SVG_TAG_NAMES, name)
+}
+//§ END
+
+//§ adjust-svg-attributes
+/// The mixed-case name of an attribute on an SVG element, if its
+/// lowercased name differs.
+pub fn svg_attr_name(name: &Atom) -> Option<Atom> {
+    adjusted_name(&SVG_ATTRIBUTES, name)
+}
+//§ END
+
+//§ adjust-mathml-attributes
+/// The mixed-case name of an attribute on a MathML element, if its
+/// lowercased name differs.
+pub fn mathml_attr_name(name: &Atom) -> Option<Atom> {
+    adjusted_name(&MATHML_ATTRIBUTES, name)
+}
+//§ END
+
+//§ adjust-foreign-attributes
+/// The namespaced name of an attribute on a MathML or SVG element, if
+/// its name as tokenized is one that gets a namespace.
+///
+/// The prefix is not kept; a serializer can recover it from the namespace.
+pub fn foreign_attr_name(name: &str) -> Option<QualName> {
+    let (ns, local) = match name {
+        "xlink:actuate" | "xlink:arcrole" | "xlink:href" | "xlink:role"
+            | "xlink:show" | "xlink:title" | "xlink:type"
+            => (ns!(XLink), &name[6..]),
+        "xml:lang" | "xml:space" => (ns!(XML), &name[4..]),
+        "xmlns" => (ns!(XMLNS), "xmlns"),
+        "xmlns:xlink" => (ns!(XMLNS), "xlink"),
+        _ => return None,
+    };
+    Some(QualName::new(ns, Atom::from_slice(local)))
+}
+//§ END
+
+//§ valid-custom-element-name
+/// Is this a valid custom element name?  It must start with a lowercase
+/// ASCII letter, contain a hyphen, and not be one of the hyphenated names
+/// already used by SVG and MathML.
+pub fn valid_custom_element_name(name: &str) -> bool {
+    match name {
+        "annotation-xml" | "color-profile" | "font-face" | "font-face-src"
+            | "font-face-uri" | "font-face-format" | "font-face-name"
+            | "missing-glyph" => return false,
+        _ => (),
+    }
+
+    match name.chars().next() {
+        Some('a'...'z') => (),
+        _ => return false,
+    }
+
+    name.contains_char('-') && name.chars().all(|c| match c {
+        '-' | '.' | '0'...'9' | '_' | 'a'...'z' | '\u{b7}'
+            | '\u{c0}'...'\u{d6}' | '\u{d8}'...'\u{f6}' | '\u{f8}'...'\u{37d}'
+            | '\u{37f}'...'\u{1fff}' | '\u{200c}'...'\u{200d}' | '\u{203f}'...'\u{2040}'
+            | '\u{2070}'...'\u{218f}' | '\u{2c00}'...'\u{2fef}' | '\u{3001}'...'\u{d7ff}'
+            | '\u{f900}'...'\u{fdcf}' | '\u{fdf0}'...'\u{fffd}' | '\u{10000}'...'\u{effff}'
+            => true,
+        _ => false,
+    })
+}
+//§ END
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use super::valid_custom_element_name;
+
+    test_eq!(custom_name_hyphen, valid_custom_element_name("x-foo"), true);
+    test_eq!(custom_name_nonascii, valid_custom_element_name("emotion-\u{1f60d}"), true);
+    test_eq!(custom_name_no_hyphen, valid_custom_element_name("xfoo"), false);
+    test_eq!(custom_name_uppercase, valid_custom_element_name("x-Foo"), false);
+    test_eq!(custom_name_leading_digit, valid_custom_element_name("1-foo"), false);
+    test_eq!(custom_name_reserved, valid_custom_element_name("font-face"), false);
+}
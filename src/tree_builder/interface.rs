@@ -12,16 +12,18 @@
 
 use core::prelude::*;
 
-use tokenizer::Attribute;
+use tokenizer::{Attribute, Pos, Span};
+use diagnostic::Diagnostic;
 
 use collections::vec::Vec;
 use collections::string::String;
-use std::string::CowString;
 
-use string_cache::QualName;
+use string_cache::{Atom, QualName};
 
 pub use self::QuirksMode::{Quirks, LimitedQuirks, NoQuirks};
 pub use self::NodeOrText::{AppendNode, AppendText};
+pub use self::InsertionPoint::{LastChild, BeforeSibling, TableFosterParenting};
+pub use self::EndTagRecovery::{Recover, Ignore, CloseTo, Abort};
 
 /// A document's quirks mode.
 #[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
@@ -31,6 +33,18 @@ pub enum QuirksMode {
     NoQuirks,
 }
 
+/// Extra information about an element being created, for sinks which
+/// implement custom elements.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct ElementFlags {
+    /// The value of the element's `is` attribute, if any.
+    pub is: Option<String>,
+
+    /// Is the element's local name a valid custom element name?
+    /// Always false outside the HTML namespace.
+    pub valid_custom_element_name: bool,
+}
+
 /// Something which can be inserted into the DOM.
 ///
 /// Adjacent sibling text nodes are merged into a single node, so
@@ -40,6 +54,63 @@ pub enum NodeOrText<Handle> {
     AppendText(String),
 }
 
+/// Where to insert a node.
+pub enum InsertionPoint<Handle> {
+    /// As the last child of this node.
+    LastChild(Handle),
+
+    /// Immediately before this node, which is not a text node.  If the
+    /// node has no parent, nothing is inserted.
+    BeforeSibling(Handle),
+
+    /// Where a foster parented node goes: immediately before `table` if
+    /// it has a parent, and otherwise as the last child of `prev_element`,
+    /// the element below `table` on the stack of open elements.
+    TableFosterParenting {
+        table: Handle,
+        prev_element: Handle,
+    },
+}
+
+/// What to do with an end tag which is a parse error, as decided by a
+/// `RecoveryPolicy`.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum EndTagRecovery {
+    /// Recover as the spec says, like a browser would.
+    Recover,
+
+    /// Ignore the end tag.
+    Ignore,
+
+    /// Close the open element at this index in the stack of open
+    /// elements, and every element above it.  The root `<html>` element
+    /// is never closed this way.
+    CloseTo(uint),
+
+    /// Stop parsing.  The tree built so far is left as it is, and the
+    /// rest of the input is ignored.
+    Abort,
+}
+
+/// Replaces the spec's error recovery at certain points, for tools which
+/// prefer deterministic strictness over browser-style fixups.
+///
+/// Every method has a default which keeps the spec's behavior.
+pub trait RecoveryPolicy {
+    /// An end tag named `name` doesn't close the current node, either
+    /// because no matching element is open or because other elements
+    /// would have to be closed implicitly.  `open_elems` are the names on
+    /// the stack of open elements, the current node last.
+    ///
+    /// This is consulted in the "in body" insertion mode, for end tags
+    /// which the spec handles with "any other end tag" and for block
+    /// end tags such as `</div>`.
+    fn unexpected_end_tag(&mut self, _name: &Atom, _open_elems: &[QualName])
+            -> EndTagRecovery {
+        Recover
+    }
+}
+
 /// Types which can process tree modifications from the tree builder.
 pub trait TreeSink {
     /// `Handle` is a reference to a DOM node.  The tree builder requires
@@ -47,8 +118,29 @@ pub trait TreeSink {
     /// the same node.
     type Handle: Clone;
 
-    /// Signal a parse error.
-    fn parse_error(&mut self, msg: CowString<'static>);
+    /// The overall result of parsing, returned by `finish`.
+    type Output;
+
+    /// Consume the sink and return the result of parsing, once the
+    /// parser is done with it.
+    fn finish(self) -> Self::Output;
+
+    /// Like `finish`, after parsing a fragment.  The document then has
+    /// a single `<html>` element, whose children are the fragment.
+    /// By default this is the same as `finish`.
+    fn finish_fragment(self) -> Self::Output {
+        self.finish()
+    }
+
+    /// Receive a parse error or other problem found by the tokenizer or
+    /// the tree builder.
+    fn report_diagnostic(&mut self, diagnostic: Diagnostic);
+
+    /// The policy for recovering from parse errors, if the sink wants to
+    /// replace the spec's.  Default: None
+    fn recovery_policy<'a>(&'a mut self) -> Option<&'a mut (RecoveryPolicy + 'a)> {
+        None
+    }
 
     /// Get a handle to the `Document` node.
     fn get_document(&mut self) -> Self::Handle;
@@ -62,12 +154,37 @@ pub trait TreeSink {
     /// feel free to `panic!`.
     fn elem_name(&self, target: Self::Handle) -> QualName;
 
+    /// Receive the part of the input which the token now being processed
+    /// came from.  Nodes created or text appended before the next call
+    /// came from this token.  Only called if the tokenizer's
+    /// `track_positions` option is set.
+    fn set_current_span(&mut self, _span: Span) { }
+
+    /// Report where in the input an element ended, when it is popped from
+    /// the stack of open elements.  This is the end of its end tag, if it
+    /// had one, or else the start of whatever token implied its end.  Only
+    /// called if the tokenizer's `track_positions` option is set.
+    fn set_element_end(&mut self, _target: Self::Handle, _end: Pos) { }
+
     /// Set the document's quirks mode.
     fn set_quirks_mode(&mut self, mode: QuirksMode);
 
+    /// Report the `href` of the first `<base>` element with one, which
+    /// determines the document base URL.  The value is not parsed or
+    /// resolved.
+    fn set_base_url(&mut self, _url: String) { }
+
     /// Create an element.
     fn create_element(&mut self, name: QualName, attrs: Vec<Attribute>) -> Self::Handle;
 
+    /// Create an element, given some extra information for custom
+    /// elements.  The tree builder always calls this rather than
+    /// `create_element`, which it calls by default.
+    fn create_element_with_flags(&mut self, name: QualName, attrs: Vec<Attribute>,
+            _flags: ElementFlags) -> Self::Handle {
+        self.create_element(name, attrs)
+    }
+
     /// Create a comment node.
     fn create_comment(&mut self, text: String) -> Self::Handle;
 
@@ -91,6 +208,25 @@ pub trait TreeSink {
         sibling: Self::Handle,
         new_node: NodeOrText<Self::Handle>) -> Result<(), NodeOrText<Self::Handle>>;
 
+    /// Insert a node or text at the given point.  This is how the tree
+    /// builder inserts all content below the `<html>` element, including
+    /// foster parented content.  By default it calls `append` or
+    /// `append_before_sibling`.
+    fn insert(&mut self, point: InsertionPoint<Self::Handle>, child: NodeOrText<Self::Handle>) {
+        match point {
+            LastChild(parent) => self.append(parent, child),
+            BeforeSibling(sibling) => {
+                let _ = self.append_before_sibling(sibling, child);
+            }
+            TableFosterParenting { table, prev_element } => {
+                match self.append_before_sibling(table, child) {
+                    Ok(()) => (),
+                    Err(child) => self.append(prev_element, child),
+                }
+            }
+        }
+    }
+
     /// Append a `DOCTYPE` element to the `Document` node.
     fn append_doctype_to_document(&mut self, name: String, public_id: String, system_id: String);
 
@@ -109,6 +245,20 @@ pub trait TreeSink {
 
     /// Indicate that a `<script>` element is complete.
     fn complete_script(&mut self, _node: Self::Handle) { }
+
+    /// Receive the whole text content of a `<script>` or `<style>`
+    /// element, along with its name and attributes, when the element is
+    /// closed.  Only called if the `report_raw_text` option is set.
+    ///
+    /// The text is also appended to the element as usual.
+    fn raw_text_content(&mut self, _node: Self::Handle, _name: Atom,
+        _attrs: Vec<Attribute>, _text: String) { }
+
+    /// Append a processing instruction, such as `<?xml-stylesheet
+    /// href="a.css"?>`, to a node.  Only the XML parser produces these.
+    /// By default they are dropped.
+    fn append_processing_instruction(&mut self, _parent: Self::Handle,
+        _target: String, _data: String) { }
 }
 
 /// Trace hooks for a garbage-collected DOM.
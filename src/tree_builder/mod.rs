@@ -13,15 +13,25 @@ use core::prelude::*;
 
 pub use self::interface::{QuirksMode, Quirks, LimitedQuirks, NoQuirks};
 pub use self::interface::{NodeOrText, AppendNode, AppendText};
-pub use self::interface::{TreeSink, Tracer};
+pub use self::interface::{InsertionPoint, LastChild, BeforeSibling, TableFosterParenting};
+pub use self::interface::{TreeSink, Tracer, ElementFlags};
+pub use self::interface::{RecoveryPolicy, EndTagRecovery, Recover, Ignore, CloseTo, Abort};
+pub use self::tag_sets::{mathml_text_integration_point, svg_html_integration_point};
+pub use self::tag_sets::{annotation_xml_integration_point, breaks_out_of_foreign_content};
+pub use self::data::svg_tag_name;
 
 use self::types::*;
 use self::actions::TreeBuilderActions;
 use self::rules::TreeBuilderStep;
 
 use tokenizer;
-use tokenizer::{Doctype, Tag};
+use tokenizer::{Doctype, Tag, Attribute, StartTag, EndTag, Span};
 use tokenizer::TokenSink;
+use tokenizer::states;
+use tokenizer::states::{Data, RawData, Rcdata, Rawtext, ScriptData, Plaintext};
+
+use encoding::Encoding;
+use diagnostic::Diagnostic;
 
 use util::str::{is_ascii_whitespace, char_run};
 
@@ -29,9 +39,10 @@ use core::default::Default;
 use core::mem::replace;
 use collections::vec::Vec;
 use collections::string::String;
-use std::borrow::Cow::Borrowed;
 use collections::RingBuf;
 
+use string_cache::{Atom, QualName};
+
 #[macro_use] mod tag_sets;
 mod interface;
 mod data;
@@ -39,6 +50,12 @@ mod types;
 mod actions;
 mod rules;
 
+// Space to reserve for the stack of open elements and the list of active
+// formatting elements, so that typical documents never grow them.  Both
+// keep their capacity as elements are popped.
+const OPEN_ELEMS_CAPACITY: uint = 64;
+const ACTIVE_FORMATTING_CAPACITY: uint = 16;
+
 /// Tree builder options, with an impl for Default.
 #[derive(Copy, Clone)]
 pub struct TreeBuilderOpts {
@@ -58,15 +75,36 @@ pub struct TreeBuilderOpts {
     /// Should we drop the DOCTYPE (if any) from the tree?
     pub drop_doctype: bool,
 
-    /// The `<svg>`, `<math>`, and `<template>` tags have special
-    /// parsing rules that are currently unimplemented.  By default
-    /// we `panic!()` if any of these tags is encountered.  If this
-    /// option is enabled, we will instead attempt to parse them
-    /// using the ordinary HTML parsing rules.
+    /// Should we drop text which is entirely whitespace, such as the
+    /// indentation between elements?  Whitespace inside `<pre>`,
+    /// `<listing>`, `<textarea>`, and raw text elements like `<script>`
+    /// is kept.  Default: false
+    pub ignore_whitespace_text: bool,
+
+    /// The `<template>` tag has special parsing rules that are
+    /// currently unimplemented.  By default we `panic!()` if this
+    /// tag is encountered.  If this option is enabled, we will
+    /// instead attempt to parse it using the ordinary HTML parsing
+    /// rules.
     ///
     /// **Warning**: This may produce extremely incorrect results
     /// on some documents!
     pub ignore_missing_rules: bool,
+
+    /// Deliver the complete text of each `<script>` and `<style>`
+    /// element to `TreeSink::raw_text_content`?  Default: false
+    pub report_raw_text: bool,
+
+    /// Stop parsing once this many elements and comments have been
+    /// created, reporting an `"Allocation budget exceeded"` error.
+    /// This protects against small inputs which expand into huge trees.
+    /// Default: None
+    pub max_nodes: Option<uint>,
+
+    /// Stop parsing once this many bytes of text, comments and attribute
+    /// values have been passed to the sink, reporting an `"Allocation
+    /// budget exceeded"` error.  Default: None
+    pub max_text: Option<uint>,
 }
 
 impl Default for TreeBuilderOpts {
@@ -77,7 +115,11 @@ impl Default for TreeBuilderOpts {
             iframe_srcdoc: false,
             fragment: false,
             drop_doctype: false,
+            ignore_whitespace_text: false,
             ignore_missing_rules: false,
+            report_raw_text: false,
+            max_nodes: None,
+            max_text: None,
         }
     }
 }
@@ -123,18 +165,63 @@ pub struct TreeBuilder<Handle, Sink> {
     form_elem: Option<Handle>,
     //§ END
 
+    /// The context element, when parsing a fragment.
+    context_elem: Option<Handle>,
+
     /// Next state change for the tokenizer, if any.
     next_tokenizer_state: Option<tokenizer::states::State>,
 
+    /// Encoding declared by a `<meta>` element and not yet taken
+    /// by `take_encoding_change`.
+    next_encoding: Option<Encoding>,
+
+    /// Have we reported a `<base href>` to the sink?
+    seen_base_href: bool,
+
+    /// Name, attributes, and text so far of the `<script>` or `<style>`
+    /// being parsed, if `report_raw_text` is set.
+    raw_text: Option<(Atom, Vec<Attribute>, String)>,
+
+    /// Input span of the token being processed, if the tokenizer
+    /// is tracking positions.
+    current_span: Option<Span>,
+
+    /// Name of the end tag being processed, if any, when the tokenizer
+    /// is tracking positions.
+    current_end_tag: Option<Atom>,
+
     /// Frameset-ok flag.
     frameset_ok: bool,
 
     /// Ignore a following U+000A LINE FEED?
     ignore_lf: bool,
 
+    /// With `ignore_whitespace_text`, whitespace which we'll insert only
+    /// if more text follows it in the same text node.
+    pending_whitespace: String,
+
+    /// With `ignore_whitespace_text`, has text been inserted since the
+    /// last token that wasn't text?  Whitespace after it goes into the
+    /// same text node, so it's kept.
+    in_text: bool,
+
+    /// Has the sink's `RecoveryPolicy` told us to stop parsing, or has
+    /// the allocation budget run out?
+    aborted: bool,
+
+    /// Elements and comments created so far, for `max_nodes`.
+    node_count: uint,
+
+    /// Bytes of text passed to the sink so far, for `max_text`.
+    text_count: uint,
+
     /// Is foster parenting enabled?
     foster_parenting: bool,
 
+    /// MathML `<annotation-xml>` elements which are HTML integration
+    /// points, because of their `encoding` attribute.
+    annotation_xml_integration_points: Vec<Handle>,
+
     // WARNING: If you add new fields that contain Handles, you
     // must add them to trace_handles() below to preserve memory
     // safety!
@@ -160,16 +247,122 @@ impl<Handle, Sink> TreeBuilder<Handle, Sink>
             pending_table_text: vec!(),
             quirks_mode: NoQuirks,
             doc_handle: doc_handle,
-            open_elems: vec!(),
-            active_formatting: vec!(),
+            open_elems: Vec::with_capacity(OPEN_ELEMS_CAPACITY),
+            active_formatting: Vec::with_capacity(ACTIVE_FORMATTING_CAPACITY),
             head_elem: None,
             form_elem: None,
+            context_elem: None,
             next_tokenizer_state: None,
+            next_encoding: None,
+            seen_base_href: false,
+            raw_text: None,
+            current_span: None,
+            current_end_tag: None,
             frameset_ok: true,
             ignore_lf: false,
+            pending_whitespace: String::new(),
+            in_text: false,
+            aborted: false,
+            node_count: 0,
+            text_count: 0,
             foster_parenting: false,
+            annotation_xml_integration_points: vec!(),
+        }
+    }
+
+    //§ parsing-html-fragments
+    /// Create a new tree builder for parsing a fragment, as if it were
+    /// the contents of `context_elem`.
+    ///
+    /// The context element and `form_elem`, which should be its nearest
+    /// `<form>` ancestor if any, don't become part of the tree.  The
+    /// fragment ends up as the children of an `<html>` element, the only
+    /// child of the sink's document.  Start the tokenizer in the state
+    /// given by `tokenizer_state_for_context_elem`.
+    pub fn new_for_fragment(sink: Sink, context_elem: Handle, form_elem: Option<Handle>,
+            mut opts: TreeBuilderOpts) -> TreeBuilder<Handle, Sink> {
+        opts.fragment = true;
+        let mut tb = TreeBuilder::new(sink, opts);
+        tb.context_elem = Some(context_elem);
+        tb.form_elem = form_elem;
+        tb.create_root(vec!());
+        // FIXME: <template> context
+        tb.mode = tb.reset_insertion_mode();
+        tb
+    }
+
+    /// The state the tokenizer should start in, when parsing a fragment.
+    pub fn tokenizer_state_for_context_elem(&self) -> states::State {
+        let elem = unwrap_or_return!(self.context_elem.as_ref(), Data);
+        let name = match self.sink.elem_name(elem.clone()) {
+            QualName { ns: ns!(HTML), local } => local,
+            _ => return Data,
+        };
+        match name {
+            atom!(title) | atom!(textarea) => RawData(Rcdata),
+
+            atom!(style) | atom!(xmp) | atom!(iframe) | atom!(noembed) | atom!(noframes)
+                => RawData(Rawtext),
+
+            atom!(script) => RawData(ScriptData),
+
+            atom!(noscript) => if self.opts.scripting_enabled {
+                RawData(Rawtext)
+            } else {
+                Data
+            },
+
+            atom!(plaintext) => Plaintext,
+
+            _ => Data,
         }
     }
+    //§ END
+
+    /// Start building a new document with another sink, as if newly
+    /// created with the same options, and return the old sink.  The
+    /// memory allocated for the stack of open elements and the other
+    /// lists is kept.  Not for fragments.
+    pub fn reset(&mut self, mut sink: Sink) -> Sink {
+        self.doc_handle = sink.get_document();
+        self.mode = Initial;
+        self.orig_mode = None;
+        self.template_modes.truncate(0);
+        self.pending_table_text.truncate(0);
+        self.quirks_mode = NoQuirks;
+        self.open_elems.truncate(0);
+        self.active_formatting.truncate(0);
+        self.head_elem = None;
+        self.form_elem = None;
+        self.context_elem = None;
+        self.next_tokenizer_state = None;
+        self.next_encoding = None;
+        self.seen_base_href = false;
+        self.raw_text = None;
+        self.current_span = None;
+        self.current_end_tag = None;
+        self.frameset_ok = true;
+        self.ignore_lf = false;
+        self.aborted = false;
+        self.node_count = 0;
+        self.text_count = 0;
+        self.foster_parenting = false;
+        self.annotation_xml_integration_points.truncate(0);
+        self.pending_whitespace.truncate(0);
+        self.in_text = false;
+        replace(&mut self.sink, sink)
+    }
+
+    /// Stop parsing, reporting `error` to the sink unless parsing has
+    /// already stopped.  Later tokens are ignored, and the tree is left
+    /// as it is.
+    pub fn abort(&mut self, error: Diagnostic) {
+        if self.aborted {
+            return;
+        }
+        self.report(error);
+        self.aborted = true;
+    }
 
     pub fn unwrap(self) -> Sink {
         self.sink
@@ -183,6 +376,16 @@ impl<Handle, Sink> TreeBuilder<Handle, Sink>
         &mut self.sink
     }
 
+    /// Get the encoding most recently declared by a `<meta charset>` or
+    /// `<meta http-equiv="Content-Type">` element, if any since the last
+    /// call.
+    ///
+    /// Whoever is decoding the input should try to change to this
+    /// encoding, if their confidence in the current one is tentative.
+    pub fn take_encoding_change(&mut self) -> Option<Encoding> {
+        self.next_encoding.take()
+    }
+
     /// Call the `Tracer`'s `trace_handle` method on every `Handle` in the tree builder's
     /// internal state.  This is intended to support garbage-collected DOMs.
     pub fn trace_handles(&self, tracer: &Tracer<Handle=Handle>) {
@@ -198,21 +401,23 @@ impl<Handle, Sink> TreeBuilder<Handle, Sink>
         }
         self.head_elem.as_ref().map(|h| tracer.trace_handle(h.clone()));
         self.form_elem.as_ref().map(|h| tracer.trace_handle(h.clone()));
+        self.context_elem.as_ref().map(|h| tracer.trace_handle(h.clone()));
+        for e in self.annotation_xml_integration_points.iter() {
+            tracer.trace_handle(e.clone());
+        }
     }
 
     // Debug helper
-    #[cfg(not(for_c))]
+    #[cfg(feature = "std")]
     #[allow(dead_code)]
     fn dump_state(&self, label: String) {
-        use string_cache::QualName;
-
         println!("dump_state on {}", label);
         print!("    open_elems:");
         for node in self.open_elems.iter() {
             let QualName { ns, local } = self.sink.elem_name(node.clone());
             match ns {
                 ns!(HTML) => print!(" {}", local.as_slice()),
-                _ => panic!(),
+                _ => print!(" {}:{}", ns.0.as_slice(), local.as_slice()),
             }
         }
         println!("");
@@ -224,7 +429,7 @@ impl<Handle, Sink> TreeBuilder<Handle, Sink>
                     let QualName { ns, local } = self.sink.elem_name(h.clone());
                     match ns {
                         ns!(HTML) => print!(" {}", local.as_slice()),
-                        _ => panic!(),
+                        _ => print!(" {}:{}", ns.0.as_slice(), local.as_slice()),
                     }
                 }
             }
@@ -232,11 +437,11 @@ impl<Handle, Sink> TreeBuilder<Handle, Sink>
         println!("");
     }
 
-    #[cfg(for_c)]
+    #[cfg(not(feature = "std"))]
     fn debug_step(&self, _mode: InsertionMode, _token: &Token) {
     }
 
-    #[cfg(not(for_c))]
+    #[cfg(feature = "std")]
     fn debug_step(&self, mode: InsertionMode, token: &Token) {
         use util::str::to_escaped_string;
         h5e_debug!("processing {} in insertion mode {:?}", to_escaped_string(token), mode);
@@ -248,15 +453,31 @@ impl<Handle, Sink> TreeBuilder<Handle, Sink>
         let mut more_tokens = RingBuf::new();
 
         loop {
-            let is_self_closing = match token {
-                TagToken(Tag { self_closing: c, .. }) => c,
-                _ => false,
+            // Only start tags need their self-closing flag acknowledged.
+            // The tokenizer reports self-closing end tags.
+            let self_closing_name = match token {
+                TagToken(Tag { kind: StartTag, self_closing: true, ref name, .. })
+                    => Some(name.clone()),
+                _ => None,
+            };
+            let result = if self.is_foreign(&token) {
+                self.step_foreign(token)
+            } else {
+                let mode = self.mode;
+                self.step(mode, token)
             };
-            let mode = self.mode;
-            match self.step(mode, token) {
+            if self.aborted {
+                return;
+            }
+            match result {
                 Done => {
-                    if is_self_closing {
-                        self.sink.parse_error(Borrowed("Unacknowledged self-closing tag"));
+                    match self_closing_name {
+                        Some(_name) => self.report(error_if!(
+                            self.opts.exact_errors,
+                            "Unacknowledged self-closing tag",
+                            "Self-closing tag <{}/> on an element which is not void or foreign",
+                            _name.as_slice())),
+                        None => (),
                     }
                     token = unwrap_or_return!(more_tokens.pop_front(), ());
                 }
@@ -292,20 +513,42 @@ impl<Handle, Sink> TokenSink
     where Handle: Clone,
           Sink: TreeSink<Handle=Handle>,
 {
-    fn process_token(&mut self, token: tokenizer::Token) {
+    fn process_token(&mut self, token: tokenizer::Token) -> tokenizer::TokenSinkResult {
+        if self.aborted {
+            return tokenizer::Stop;
+        }
+
         let ignore_lf = replace(&mut self.ignore_lf, false);
 
+        if self.current_span.is_some() {
+            self.current_end_tag = match token {
+                tokenizer::TagToken(Tag { kind: EndTag, ref name, .. }) => Some(name.clone()),
+                _ => None,
+            };
+        }
+
+        // Text from different character tokens ends up in one text node
+        // only if nothing else comes between them.
+        match token {
+            tokenizer::CharacterTokens(..) | tokenizer::NullCharacterToken
+                | tokenizer::ParseError(..) => (),
+            _ => {
+                self.pending_whitespace.clear();
+                self.in_text = false;
+            }
+        }
+
         // Handle `ParseError` and `DoctypeToken`; convert everything else to the local `Token` type.
         let token = match token {
             tokenizer::ParseError(e) => {
-                self.sink.parse_error(e);
-                return;
+                self.report(e);
+                return tokenizer::Continue;
             }
 
             tokenizer::DoctypeToken(dt) => if self.mode == Initial {
                 let (err, quirk) = data::doctype_error_and_quirks(&dt, self.opts.iframe_srcdoc);
                 if err {
-                    self.sink.parse_error(format_if!(
+                    self.report(error_if!(
                         self.opts.exact_errors,
                         "Bad DOCTYPE",
                         "Bad DOCTYPE: {:?}", dt));
@@ -321,17 +564,17 @@ impl<Handle, Sink> TokenSink
                 self.set_quirks_mode(quirk);
 
                 self.mode = BeforeHtml;
-                return;
+                return tokenizer::Continue;
             } else {
-                self.sink.parse_error(format_if!(
+                self.report(error_if!(
                     self.opts.exact_errors,
                     "DOCTYPE in body",
                     "DOCTYPE in insertion mode {:?}", self.mode));
-                return;
+                return tokenizer::Continue;
             },
 
             tokenizer::TagToken(x) => TagToken(x),
-            tokenizer::CommentToken(x) => CommentToken(x),
+            tokenizer::CommentToken(x) | tokenizer::BogusCommentToken(x) => CommentToken(x),
             tokenizer::NullCharacterToken => NullCharacterToken,
             tokenizer::EOFToken => EOFToken,
 
@@ -340,16 +583,142 @@ impl<Handle, Sink> TokenSink
                     x.remove(0);
                 }
                 if x.is_empty() {
-                    return;
+                    return tokenizer::Continue;
                 }
                 CharacterTokens(NotSplit, x)
             }
         };
 
         self.process_to_completion(token);
+        if self.aborted {
+            tokenizer::Stop
+        } else {
+            tokenizer::Continue
+        }
     }
 
     fn query_state_change(&mut self) -> Option<tokenizer::states::State> {
         self.next_tokenizer_state.take()
     }
+
+    fn set_current_span(&mut self, span: Span) {
+        self.current_span = Some(span);
+        self.sink.set_current_span(span);
+    }
+
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        !self.open_elems.is_empty() &&
+            self.sink.elem_name(self.adjusted_current_node()).ns != ns!(HTML)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::{TreeSink, TreeBuilderOpts, QuirksMode, NodeOrText};
+    use tokenizer::Attribute;
+    use driver::{parse_to, one_input, ParseOpts};
+    use diagnostic::Diagnostic;
+
+    use string_cache::{Atom, QualName};
+
+    // Builds no tree, and keeps what `raw_text_content` reports.  Handles
+    // are indices into a table of element names.
+    struct RawTextSink {
+        names: Vec<Option<QualName>>,
+        reported: Vec<String>,
+    }
+
+    impl RawTextSink {
+        fn new_node(&mut self, name: Option<QualName>) -> uint {
+            self.names.push(name);
+            self.names.len() - 1
+        }
+    }
+
+    impl TreeSink for RawTextSink {
+        type Handle = uint;
+        type Output = Vec<String>;
+
+        fn finish(self) -> Vec<String> {
+            self.reported
+        }
+
+        fn report_diagnostic(&mut self, _diagnostic: Diagnostic) { }
+
+        fn get_document(&mut self) -> uint {
+            0
+        }
+
+        fn same_node(&self, x: uint, y: uint) -> bool {
+            x == y
+        }
+
+        fn elem_name(&self, target: uint) -> QualName {
+            self.names[target].clone().expect("not an element")
+        }
+
+        fn set_quirks_mode(&mut self, _mode: QuirksMode) { }
+
+        fn create_element(&mut self, name: QualName, _attrs: Vec<Attribute>) -> uint {
+            self.new_node(Some(name))
+        }
+
+        fn create_comment(&mut self, _text: String) -> uint {
+            self.new_node(None)
+        }
+
+        fn append(&mut self, _parent: uint, _child: NodeOrText<uint>) { }
+
+        fn append_before_sibling(&mut self, _sibling: uint, _new_node: NodeOrText<uint>)
+                -> Result<(), NodeOrText<uint>> {
+            Ok(())
+        }
+
+        fn append_doctype_to_document(&mut self, _name: String, _public_id: String,
+            _system_id: String) { }
+
+        fn add_attrs_if_missing(&mut self, _target: uint, _attrs: Vec<Attribute>) { }
+
+        fn remove_from_parent(&mut self, _target: uint) { }
+
+        fn reparent_children(&mut self, _node: uint, _new_parent: uint) { }
+
+        fn mark_script_already_started(&mut self, _node: uint) { }
+
+        fn raw_text_content(&mut self, node: uint, name: Atom, attrs: Vec<Attribute>,
+                text: String) {
+            assert_eq!(self.elem_name(node).local, name);
+            self.reported.push(format!("{} {} {}", name.as_slice(), attrs.len(), text));
+        }
+    }
+
+    fn reported(input: &str, report_raw_text: bool) -> Vec<String> {
+        let sink = RawTextSink {
+            names: vec!(None),
+            reported: vec!(),
+        };
+        let opts = ParseOpts {
+            tree_builder: TreeBuilderOpts {
+                report_raw_text: report_raw_text,
+                .. Default::default()
+            },
+            .. Default::default()
+        };
+        parse_to(sink, one_input(String::from_str(input)), opts)
+    }
+
+    #[test]
+    fn raw_text_content() {
+        let input = "<script type=a>x<!--y</script><style>s</style><title>t</title>\
+            <p>z<script>unclosed";
+        let expected: Vec<String> = ["script 1 x<!--y", "style 0 s", "script 0 unclosed"]
+            .iter().map(|s| String::from_str(*s)).collect();
+        assert_eq!(reported(input, true), expected);
+        assert_eq!(reported(input, false), Vec::<String>::new());
+    }
 }
@@ -21,9 +21,10 @@ use tokenizer::states::{Rcdata, Rawtext, ScriptData, Plaintext};
 
 use util::str::is_ascii_whitespace;
 
+use diagnostic::Diagnostic;
+
 use core::mem::replace;
 use collections::string::String;
-use std::borrow::Cow::Borrowed;
 
 fn any_not_whitespace(x: &String) -> bool {
     // FIXME: this might be much faster as a byte scan
@@ -33,6 +34,7 @@ fn any_not_whitespace(x: &String) -> bool {
 // This goes in a trait so that we can control visibility.
 pub trait TreeBuilderStep<Handle> {
     fn step(&mut self, mode: InsertionMode, token: Token) -> ProcessResult;
+    fn step_foreign(&mut self, token: Token) -> ProcessResult;
 }
 
 #[doc(hidden)]
@@ -114,7 +116,14 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                 <html> => self.step(InBody, token),
 
                 tag @ <base> <basefont> <bgsound> <link> <meta> => {
-                    // FIXME: handle <meta charset=...> and <meta http-equiv="Content-Type">
+                    match tag.name {
+                        atom!(meta) => match self.meta_encoding(&tag) {
+                            Some(enc) => self.next_encoding = Some(enc),
+                            None => (),
+                        },
+                        atom!(base) => self.check_base_href(&tag),
+                        _ => (),
+                    }
                     self.insert_and_pop_element_for(tag);
                     DoneAckSelfClosing
                 }
@@ -135,7 +144,8 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                 }
 
                 tag @ <script> => {
-                    let elem = self.sink.create_element(qualname!(HTML, script), tag.attrs);
+                    self.start_raw_text_report(&tag);
+                    let elem = self.create_element_for_token(qualname!(HTML, script), tag.attrs);
                     if self.opts.fragment {
                         self.sink.mark_script_already_started(elem.clone());
                     }
@@ -311,7 +321,7 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
 
                     // FIXME: can we get here in the fragment case?
                     // What to do with the first element then?
-                    self.open_elems.truncate(1);
+                    self.pop_to(1);
                     self.insert_element_for(tag);
                     self.mode = InFrameset;
                     Done
@@ -328,7 +338,7 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                         self.check_body_end();
                         self.mode = AfterBody;
                     } else {
-                        self.sink.parse_error(Borrowed("</body> with no <body> in scope"));
+                        self.report(Diagnostic::error("</body> with no <body> in scope"));
                     }
                     Done
                 }
@@ -338,7 +348,7 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                         self.check_body_end();
                         Reprocess(AfterBody, token)
                     } else {
-                        self.sink.parse_error(Borrowed("</html> with no <body> in scope"));
+                        self.report(Diagnostic::error("</html> with no <body> in scope"));
                         Done
                     }
                 }
@@ -354,7 +364,7 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                 tag @ <h1> <h2> <h3> <h4> <h5> <h6> => {
                     self.close_p_element_in_button_scope();
                     if self.current_node_in(heading_tag) {
-                        self.sink.parse_error(Borrowed("nested heading tags"));
+                        self.report(Diagnostic::error("nested heading tags"));
                         self.pop();
                     }
                     self.insert_element_for(tag);
@@ -372,7 +382,7 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                 tag @ <form> => {
                     // FIXME: <template>
                     if self.form_elem.is_some() {
-                        self.sink.parse_error(Borrowed("nested forms"));
+                        self.report(Diagnostic::error("nested forms"));
                     } else {
                         self.close_p_element_in_button_scope();
                         let elem = self.insert_element_for(tag);
@@ -428,7 +438,7 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
 
                 tag @ <button> => {
                     if self.in_scope_named(default_scope, atom!(button)) {
-                        self.sink.parse_error(Borrowed("nested buttons"));
+                        self.report(Diagnostic::error("nested buttons"));
                         self.generate_implied_end(cursory_implied_end);
                         self.pop_until_named(atom!(button));
                     }
@@ -444,9 +454,15 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                   </nav> </ol> </pre> </section> </summary> </ul> => {
                     if !self.in_scope_named(default_scope, tag.name.clone()) {
                         self.unexpected(&tag);
+                        self.end_tag_recovery(&tag.name);
                     } else {
                         self.generate_implied_end(cursory_implied_end);
-                        self.expect_to_close(tag.name);
+                        if self.current_node_named(tag.name.clone())
+                            || self.end_tag_recovery(&tag.name) {
+                            self.expect_to_close(tag.name);
+                        } else {
+                            self.unexpected(&tag);
+                        }
                     }
                     Done
                 }
@@ -456,28 +472,28 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                     // Can't use unwrap_or_return!() due to rust-lang/rust#16617.
                     let node = match self.form_elem.take() {
                         None => {
-                            self.sink.parse_error(Borrowed("Null form element pointer on </form>"));
+                            self.report(Diagnostic::error("Null form element pointer on </form>"));
                             return Done;
                         }
                         Some(x) => x,
                     };
                     if !self.in_scope(default_scope,
                         |n| self.sink.same_node(node.clone(), n)) {
-                        self.sink.parse_error(Borrowed("Form element not in scope on </form>"));
+                        self.report(Diagnostic::error("Form element not in scope on </form>"));
                         return Done;
                     }
                     self.generate_implied_end(cursory_implied_end);
                     let current = self.current_node();
                     self.remove_from_stack(&node);
                     if !self.sink.same_node(current, node) {
-                        self.sink.parse_error(Borrowed("Bad open element on </form>"));
+                        self.report(Diagnostic::error("Bad open element on </form>"));
                     }
                     Done
                 }
 
                 </p> => {
                     if !self.in_scope_named(button_scope, atom!(p)) {
-                        self.sink.parse_error(Borrowed("No <p> tag to close"));
+                        self.report(Diagnostic::error("No <p> tag to close"));
                         self.insert_phantom(atom!(p));
                     }
                     self.close_p_element();
@@ -493,7 +509,7 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                         self.generate_implied_end_except(tag.name.clone());
                         self.expect_to_close(tag.name);
                     } else {
-                        self.sink.parse_error(Borrowed("No matching tag to close"));
+                        self.report(Diagnostic::error("No matching tag to close"));
                     }
                     Done
                 }
@@ -502,11 +518,11 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                     if self.in_scope(default_scope, |n| self.elem_in(n.clone(), heading_tag)) {
                         self.generate_implied_end(cursory_implied_end);
                         if !self.current_node_named(tag.name) {
-                            self.sink.parse_error(Borrowed("Closing wrong heading tag"));
+                            self.report(Diagnostic::error("Closing wrong heading tag"));
                         }
                         self.pop_until(heading_tag);
                     } else {
-                        self.sink.parse_error(Borrowed("No heading tag to close"));
+                        self.report(Diagnostic::error("No heading tag to close"));
                     }
                     Done
                 }
@@ -527,7 +543,7 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                 tag @ <nobr> => {
                     self.reconstruct_formatting();
                     if self.in_scope_named(default_scope, atom!(nobr)) {
-                        self.sink.parse_error(Borrowed("Nested <nobr>"));
+                        self.report(Diagnostic::error("Nested <nobr>"));
                         self.adoption_agency(atom!(nobr));
                         self.reconstruct_formatting();
                     }
@@ -676,15 +692,20 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                     Done
                 }
 
-                tag @ <math> <svg> => {
-                    if self.opts.ignore_missing_rules {
-                        self.sink.parse_error(Borrowed("Ignoring unimplemented rules for <math> or <svg>"));
-                        self.reconstruct_formatting();
-                        self.insert_element_for(tag);
-                        Done
-                    } else {
-                        panic!("FIXME: MathML and SVG are not implemented");
-                    }
+                tag @ <math> => {
+                    self.reconstruct_formatting();
+                    let mut tag = tag;
+                    self.adjust_mathml_attributes(&mut tag);
+                    self.adjust_foreign_attributes(&mut tag);
+                    self.insert_foreign_element(tag, ns!(MathML))
+                }
+
+                tag @ <svg> => {
+                    self.reconstruct_formatting();
+                    let mut tag = tag;
+                    self.adjust_svg_attributes(&mut tag);
+                    self.adjust_foreign_attributes(&mut tag);
+                    self.insert_foreign_element(tag, ns!(SVG))
                 }
 
                 <caption> <col> <colgroup> <frame> <head>
@@ -715,7 +736,13 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
 
             //§ parsing-main-incdata
             Text => match_token!(token {
-                CharacterTokens(_, text) => self.append_text(text),
+                CharacterTokens(_, text) => {
+                    match self.raw_text {
+                        Some((_, _, ref mut buf)) => buf.push_str(text.as_slice()),
+                        None => (),
+                    }
+                    self.append_text(text)
+                }
 
                 EOFToken => {
                     self.unexpected(&token);
@@ -723,12 +750,14 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                         let current = self.current_node();
                         self.sink.mark_script_already_started(current);
                     }
-                    self.pop();
+                    let node = self.pop();
+                    self.end_raw_text_report(node);
                     Reprocess(self.orig_mode.take().unwrap(), token)
                 }
 
                 tag @ </_> => {
                     let node = self.pop();
+                    self.end_raw_text_report(node.clone());
                     if tag.name == atom!(script) {
                         h5e_warn!("FIXME: </script> not fully implemented");
                         self.sink.complete_script(node);
@@ -859,7 +888,7 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
                     });
 
                     if contains_nonspace {
-                        self.sink.parse_error(Borrowed("Non-space table text"));
+                        self.report(Diagnostic::error("Non-space table text"));
                         for (split, text) in pending.into_iter() {
                             match self.foster_parent_in_body(CharacterTokens(split, text)) {
                                 Done => (),
@@ -1315,4 +1344,47 @@ impl<Handle, Sink> TreeBuilderStep<Handle>
             //§ END
         }
     }
+
+    //§ parsing-main-inforeign
+    fn step_foreign(&mut self, token: Token) -> ProcessResult {
+        match_token!(token {
+            NullCharacterToken => {
+                self.unexpected(&token);
+                self.append_text(String::from_str("\u{fffd}"))
+            }
+
+            CharacterTokens(_, text) => {
+                if any_not_whitespace(&text) {
+                    self.frameset_ok = false;
+                }
+                self.append_text(text)
+            }
+
+            CommentToken(text) => self.append_comment(text),
+
+            tag @ <_> => {
+                if breaks_out_of_foreign_content(&tag.name, tag.attrs.as_slice()) {
+                    self.unexpected_start_tag_in_foreign_content(tag)
+                } else {
+                    self.foreign_start_tag(tag)
+                }
+            }
+
+            // FIXME: the spec says to run the SVG script here.
+            tag @ </script> => {
+                if self.current_node_in(|n| n == qualname!(SVG, script)) {
+                    self.pop();
+                    Done
+                } else {
+                    self.foreign_end_tag(tag)
+                }
+            }
+
+            tag @ </_> => self.foreign_end_tag(tag),
+
+            // EOF is never processed as foreign content.
+            _ => panic!("impossible case in foreign content"),
+        })
+    }
+    //§ END
 }
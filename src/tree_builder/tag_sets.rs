@@ -9,7 +9,11 @@
 
 //! Various sets of HTML tag names, and macros for declaring them.
 
-use string_cache::QualName;
+use tokenizer::Attribute;
+
+use util::str::AsciiExt;
+
+use string_cache::{Atom, QualName};
 
 macro_rules! declare_tag_set_impl ( ($param:ident, $b:ident, $supr:ident, $($tag:tt)+) => (
     match $param {
@@ -46,8 +50,15 @@ macro_rules! declare_tag_set (
 #[inline(always)] pub fn empty_set(_: QualName) -> bool { false }
 #[inline(always)] pub fn full_set(_: QualName) -> bool { true }
 
-// FIXME: MathML, SVG
-declare_tag_set!(pub default_scope = applet caption html table td th marquee object template);
+declare_tag_set!(html_default_scope =
+    applet caption html table td th marquee object template);
+
+pub fn default_scope(name: QualName) -> bool {
+    html_default_scope(name.clone())
+        || mathml_text_integration_point(name.clone())
+        || svg_html_integration_point(name.clone())
+        || name == qualname!(MathML, "annotation-xml")
+}
 
 declare_tag_set!(pub list_item_scope = default_scope + ol ul);
 declare_tag_set!(pub button_scope = default_scope + button);
@@ -65,11 +76,73 @@ declare_tag_set!(pub thorough_implied_end = cursory_implied_end
 
 declare_tag_set!(pub heading_tag = h1 h2 h3 h4 h5 h6);
 
-declare_tag_set!(pub special_tag =
+declare_tag_set!(html_special_tag =
     address applet area article aside base basefont bgsound blockquote body br button caption
     center col colgroup dd details dir div dl dt embed fieldset figcaption figure footer form
     frame frameset h1 h2 h3 h4 h5 h6 head header hgroup hr html iframe img input isindex li
     link listing main marquee menu menuitem meta nav noembed noframes noscript object ol p
     param plaintext pre script section select source style summary table tbody td template
     textarea tfoot th thead title tr track ul wbr xmp);
+
+pub fn special_tag(name: QualName) -> bool {
+    html_special_tag(name.clone())
+        || mathml_text_integration_point(name.clone())
+        || svg_html_integration_point(name.clone())
+        || name == qualname!(MathML, "annotation-xml")
+}
+//§ END
+
+//§ mathml-text-integration-point
+pub fn mathml_text_integration_point(name: QualName) -> bool {
+    match name {
+        qualname!(MathML, mi) | qualname!(MathML, mo) | qualname!(MathML, mn)
+            | qualname!(MathML, ms) | qualname!(MathML, mtext) => true,
+        _ => false,
+    }
+}
+
+//§ html-integration-point
+/// The SVG elements which are HTML integration points.  MathML
+/// `<annotation-xml>` may be one too, depending on its attributes.
+pub fn svg_html_integration_point(name: QualName) -> bool {
+    match name {
+        qualname!(SVG, foreignObject) | qualname!(SVG, desc) | qualname!(SVG, title) => true,
+        _ => false,
+    }
+}
+
+/// Is a MathML `<annotation-xml>` with these attributes an HTML
+/// integration point?
+pub fn annotation_xml_integration_point(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.name == qualname!("", encoding) && {
+            let value = attr.value.as_slice().to_ascii_lower();
+            value.as_slice() == "text/html" || value.as_slice() == "application/xhtml+xml"
+        }
+    })
+}
+//§ END
+
+//§ parsing-main-inforeign
+/// Does a start tag with this name and these attributes, seen in
+/// foreign content, close the foreign elements and go back to HTML?
+pub fn breaks_out_of_foreign_content(name: &Atom, attrs: &[Attribute]) -> bool {
+    match *name {
+        atom!(b) | atom!(big) | atom!(blockquote) | atom!(body) | atom!(br) | atom!(center)
+        | atom!(code) | atom!(dd) | atom!(div) | atom!(dl) | atom!(dt) | atom!(em)
+        | atom!(embed) | atom!(h1) | atom!(h2) | atom!(h3) | atom!(h4) | atom!(h5)
+        | atom!(h6) | atom!(head) | atom!(hr) | atom!(i) | atom!(img) | atom!(li)
+        | atom!(listing) | atom!(menu) | atom!(meta) | atom!(nobr) | atom!(ol) | atom!(p)
+        | atom!(pre) | atom!(ruby) | atom!(s) | atom!(small) | atom!(span) | atom!(strong)
+        | atom!(strike) | atom!(sub) | atom!(sup) | atom!(table) | atom!(tt) | atom!(u)
+        | atom!(ul) | atom!(var) => true,
+
+        atom!(font) => attrs.iter().any(|attr| match attr.name {
+            qualname!("", color) | qualname!("", face) | qualname!("", size) => true,
+            _ => false,
+        }),
+
+        _ => false,
+    }
+}
 //§ END
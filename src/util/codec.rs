@@ -0,0 +1,236 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Numbers, strings, spans and diagnostics in the binary formats of
+//! token recordings and flattened documents.
+
+use core::prelude::*;
+
+use tokenizer::{Pos, Span};
+use diagnostic::{Diagnostic, Error, Warning};
+
+use core::mem;
+use core::str;
+use core::default::Default;
+use collections::vec::Vec;
+use collections::string::String;
+use collections::borrow::Cow::Owned;
+
+// Numbers are written seven bits at a time, least significant first,
+// with the high bit set on all but the last byte.
+pub fn put_uint(out: &mut Vec<u8>, mut n: uint) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_uint(out, s.len());
+    out.push_all(s.as_bytes());
+}
+
+pub fn put_span(out: &mut Vec<u8>, span: Span) {
+    for pos in [span.start, span.end].iter() {
+        put_uint(out, pos.offset);
+        put_uint(out, pos.line);
+        put_uint(out, pos.column);
+    }
+}
+
+// A flag byte, followed by the span if there is one.
+pub fn put_opt_span(out: &mut Vec<u8>, span: Option<Span>) {
+    match span {
+        Some(span) => {
+            out.push(1);
+            put_span(out, span);
+        }
+        None => out.push(0),
+    }
+}
+
+// The code isn't written, since it can't be read back as a
+// `&'static str`.
+pub fn put_diagnostic(out: &mut Vec<u8>, e: &Diagnostic) {
+    out.push(match e.severity {
+        Error => 0,
+        Warning => 1,
+    });
+    put_str(out, &*e.message);
+    put_opt_span(out, e.span);
+}
+
+/// An error in encoded data.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct DecodeError {
+    /// Byte offset of the error.
+    pub position: uint,
+
+    /// What was wrong.
+    pub message: &'static str,
+}
+
+pub struct Reader<'a> {
+    pub bytes: &'a [u8],
+    pub pos: uint,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8], pos: uint) -> Reader<'a> {
+        Reader {
+            bytes: bytes,
+            pos: pos,
+        }
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    pub fn error<T>(&self, message: &'static str) -> Result<T, DecodeError> {
+        Err(DecodeError {
+            position: self.pos,
+            message: message,
+        })
+    }
+
+    pub fn byte(&mut self) -> Result<u8, DecodeError> {
+        match self.bytes.get(self.pos) {
+            Some(&b) => {
+                self.pos += 1;
+                Ok(b)
+            }
+            None => self.error("Unexpected end of input"),
+        }
+    }
+
+    /// Read a number written by `put_uint`.  Numbers with bits which
+    /// don't fit in a `uint`, or with needless trailing zero bytes, are
+    /// errors.
+    pub fn uint(&mut self) -> Result<uint, DecodeError> {
+        let bits = mem::size_of::<uint>() * 8;
+        let mut n = 0;
+        let mut shift = 0;
+        loop {
+            let byte = try!(self.byte());
+            let low = (byte & 0x7f) as uint;
+            if shift >= bits || (bits - shift < 7 && low >> (bits - shift) != 0) {
+                return self.error("Number too large");
+            }
+            if byte == 0 && shift > 0 {
+                return self.error("Overlong number");
+            }
+            n |= low << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+            shift += 7;
+        }
+    }
+
+    pub fn str(&mut self) -> Result<&'a str, DecodeError> {
+        let len = try!(self.uint());
+        if len > self.bytes.len() - self.pos {
+            return self.error("Unexpected end of input");
+        }
+        match str::from_utf8(&self.bytes[self.pos..self.pos + len]) {
+            Ok(s) => {
+                self.pos += len;
+                Ok(s)
+            }
+            Err(_) => self.error("Invalid UTF-8"),
+        }
+    }
+
+    pub fn string(&mut self) -> Result<String, DecodeError> {
+        self.str().map(String::from_str)
+    }
+
+    pub fn span(&mut self) -> Result<Span, DecodeError> {
+        let mut pos = [Pos::default(), Pos::default()];
+        for p in pos.iter_mut() {
+            p.offset = try!(self.uint());
+            p.line = try!(self.uint());
+            p.column = try!(self.uint());
+        }
+        Ok(Span {
+            start: pos[0],
+            end: pos[1],
+        })
+    }
+
+    pub fn opt_span(&mut self) -> Result<Option<Span>, DecodeError> {
+        match try!(self.byte()) {
+            0 => Ok(None),
+            _ => self.span().map(Some),
+        }
+    }
+
+    /// Read a diagnostic written by `put_diagnostic`, giving it `code`.
+    pub fn diagnostic(&mut self, code: &'static str) -> Result<Diagnostic, DecodeError> {
+        let severity = match try!(self.byte()) {
+            0 => Error,
+            1 => Warning,
+            _ => return self.error("Unknown severity"),
+        };
+        let mut e = Diagnostic::error_with_message(code, Owned(try!(self.string())));
+        e.severity = severity;
+        e.span = try!(self.opt_span());
+        Ok(e)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+
+    use super::{put_uint, put_str, Reader, DecodeError};
+
+    #[test]
+    fn numbers_and_strings() {
+        let mut out = Vec::new();
+        for &n in [0u, 1, 127, 128, 300, !0].iter() {
+            put_uint(&mut out, n);
+        }
+        put_str(&mut out, "a\u{e9}");
+        let mut r = Reader::new(out.as_slice(), 0);
+        for &n in [0u, 1, 127, 128, 300, !0].iter() {
+            assert_eq!(r.uint(), Ok(n));
+        }
+        assert_eq!(r.str(), Ok("a\u{e9}"));
+        assert!(r.at_end());
+        assert_eq!(r.byte(), Err(DecodeError {
+            position: out.len(),
+            message: "Unexpected end of input",
+        }));
+    }
+
+    #[test]
+    fn bad_numbers() {
+        let mut too_large = vec!();
+        put_uint(&mut too_large, !0);
+        *too_large.last_mut().unwrap() |= 0x7e;
+        assert_eq!(Reader::new(too_large.as_slice(), 0).uint(), Err(DecodeError {
+            position: too_large.len(),
+            message: "Number too large",
+        }));
+
+        let overlong = [0x81, 0x00];
+        assert_eq!(Reader::new(&overlong, 0).uint(), Err(DecodeError {
+            position: 2,
+            message: "Overlong number",
+        }));
+    }
+}
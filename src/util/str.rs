@@ -13,10 +13,10 @@ use core::str::CharEq;
 use collections::vec::Vec;
 use collections::string::String;
 
-#[cfg(not(for_c))]
+#[cfg(feature = "std")]
 use core::fmt::Debug;
 
-#[cfg(not(for_c))]
+#[cfg(feature = "std")]
 pub fn to_escaped_string<T: Debug>(x: &T) -> String {
     use collections::str::StrExt;
     use core::fmt::Writer;
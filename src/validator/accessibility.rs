@@ -0,0 +1,248 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Accessibility lints: images without text alternatives, form controls
+//! without labels, duplicate `id`s, and skipped heading levels.
+//!
+//! Unlike the other checks, some of these flag documents which are
+//! conforming but hard to use with assistive technology.
+
+use core::prelude::*;
+
+use diagnostic::{Diagnostic, Warning};
+use sink::common::{Element, attr};
+use sink::rcdom::Handle;
+use tokenizer::{Attribute, Span};
+use tree_builder::TreeSink;
+use validator::sort_by_position;
+
+use util::str::AsciiExt;
+
+use collections::vec::Vec;
+use collections::string::String;
+use std::borrow::Cow::Owned;
+use std::collections::HashSet;
+
+// Does the element have a label given by ARIA attributes or `title`?
+fn has_aria_label(attrs: &[Attribute]) -> bool {
+    ["aria-label", "aria-labelledby", "title"].iter()
+        .any(|n| attr(attrs, *n).map_or(false, |v| !v.trim().is_empty()))
+}
+
+fn is_presentational(attrs: &[Attribute]) -> bool {
+    match attr(attrs, "role") {
+        Some(role) => role.eq_ignore_ascii_case("presentation") || role.eq_ignore_ascii_case("none"),
+        None => false,
+    }
+}
+
+// Does this element need a label?
+fn is_labelable_control(name: &str, attrs: &[Attribute]) -> bool {
+    match name {
+        "select" | "textarea" => true,
+        "input" => match attr(attrs, "type").map(|t| t.to_ascii_lower()) {
+            None => true,
+            Some(t) => match t.as_slice() {
+                // These are labelled by their value, or `alt`, or not at all.
+                "hidden" | "submit" | "reset" | "button" | "image" => false,
+                _ => true,
+            },
+        },
+        _ => false,
+    }
+}
+
+fn heading_level(name: &str) -> Option<uint> {
+    match name {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
+}
+
+struct Linter {
+    /// `id`s named by the `for` attribute of some `<label>`.
+    label_targets: HashSet<String>,
+
+    /// `id`s seen so far.
+    ids: HashSet<String>,
+
+    /// Level of the previous heading.
+    last_heading: Option<uint>,
+
+    /// Number of `<label>` elements we're inside.
+    label_depth: uint,
+
+    out: Vec<Diagnostic>,
+}
+
+impl Linter {
+    fn report(&mut self, span: Option<Span>, code: &'static str, spec: &'static str,
+              message: String) {
+        self.out.push(Diagnostic {
+            code: code,
+            message: Owned(message),
+            span: span,
+            severity: Warning,
+            spec: Some(spec),
+        });
+    }
+
+    fn find_label_targets(&mut self, handle: &Handle) {
+        let node = handle.borrow();
+        match node.node {
+            Element(ref name, ref attrs) if name == &qualname!(HTML, label) => {
+                match attr(attrs.as_slice(), "for") {
+                    Some(id) => { self.label_targets.insert(String::from_str(id)); }
+                    None => (),
+                }
+            }
+            _ => (),
+        }
+        for child in node.children.iter() {
+            self.find_label_targets(child);
+        }
+    }
+
+    fn check_element(&mut self, name: &str, attrs: &[Attribute], span: Option<Span>) {
+        match attr(attrs, "id") {
+            Some(id) if !id.is_empty() => {
+                if !self.ids.insert(String::from_str(id)) {
+                    self.report(span, "Duplicate id", "the-id-attribute",
+                        format!("Duplicate id \"{}\"", id));
+                }
+            }
+            _ => (),
+        }
+
+        if name == "img" && attr(attrs, "alt").is_none()
+            && !has_aria_label(attrs) && !is_presentational(attrs) {
+            self.report(span, "Missing alt", "alt",
+                String::from_str("<img> has no alt attribute"));
+        }
+
+        if name == "input" && attr(attrs, "type").map_or(false, |t| t.eq_ignore_ascii_case("image"))
+            && attr(attrs, "alt").map_or(true, |a| a.trim().is_empty()) {
+            self.report(span, "Missing alt", "image-button-state-(type=image)",
+                String::from_str("<input type=image> has no alt text"));
+        }
+
+        if is_labelable_control(name, attrs) && self.label_depth == 0 && !has_aria_label(attrs) {
+            let labelled = match attr(attrs, "id") {
+                Some(id) => self.label_targets.contains(id),
+                None => false,
+            };
+            if !labelled {
+                self.report(span, "Missing label", "the-label-element",
+                    format!("<{}> has no label", name));
+            }
+        }
+
+        match heading_level(name) {
+            Some(level) => {
+                match self.last_heading {
+                    Some(last) if level > last + 1 => self.report(span,
+                        "Skipped heading level", "headings-and-outlines",
+                        format!("Heading level skipped: <h{}> follows <h{}>", level, last)),
+                    _ => (),
+                }
+                self.last_heading = Some(level);
+            }
+            None => (),
+        }
+    }
+
+    fn walk(&mut self, handle: &Handle) {
+        let node = handle.borrow();
+        let is_label = match node.node {
+            Element(ref name, ref attrs) if name.ns == ns!(HTML) => {
+                let local = name.local.as_slice();
+                self.check_element(local, attrs.as_slice(), node.span);
+                local == "label"
+            }
+            _ => false,
+        };
+
+        if is_label {
+            self.label_depth += 1;
+        }
+        for child in node.children.iter() {
+            self.walk(child);
+        }
+        if is_label {
+            self.label_depth -= 1;
+        }
+    }
+}
+
+/// Run the accessibility lints on the tree below `document`, and pass
+/// the warnings to the sink's `report_diagnostic` in order of position.
+pub fn check<Sink: TreeSink>(document: &Handle, sink: &mut Sink) {
+    let mut linter = Linter {
+        label_targets: HashSet::new(),
+        ids: HashSet::new(),
+        last_heading: None,
+        label_depth: 0,
+        out: vec!(),
+    };
+    linter.find_label_targets(document);
+    linter.walk(document);
+    sort_by_position(&mut linter.out);
+    for diagnostic in linter.out.into_iter() {
+        sink.report_diagnostic(diagnostic);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::check;
+    use diagnostic::Warning;
+    use sink::rcdom::RcDom;
+    use driver::parse_str;
+
+    fn messages(input: &str) -> Vec<String> {
+        let mut dom: RcDom = parse_str(input);
+        dom.errors.clear();
+        let document = dom.document.clone();
+        check(&document, &mut dom);
+        dom.errors.into_iter().map(|d| {
+            assert_eq!(d.severity, Warning);
+            d.message.into_owned()
+        }).collect()
+    }
+
+    fn s(x: &str) -> String {
+        String::from_str(x)
+    }
+
+    test_eq!(accessible, messages("<h1>a</h1><h2>b</h2><h2>c</h2><h1>d</h1>\
+        <img alt=''><img role=presentation><label>Name <input></label>\
+        <label for=x>X</label><select id=x></select><textarea aria-label=y></textarea>\
+        <input type=hidden><input type=submit>"), Vec::<String>::new());
+
+    test_eq!(missing_alt, messages("<img src=a.png><input type=image src=b.png>"),
+        vec!(s("<img> has no alt attribute"), s("<input type=image> has no alt text")));
+
+    test_eq!(unlabelled, messages("<input id=a><label for=b>B</label><textarea></textarea>"),
+        vec!(s("<input> has no label"), s("<textarea> has no label")));
+
+    test_eq!(duplicate_id, messages("<p id=a></p><div id=a></div>"),
+        vec!(s("Duplicate id \"a\"")));
+
+    test_eq!(heading_skip, messages("<h1>a</h1><h3>b</h3>"),
+        vec!(s("Heading level skipped: <h3> follows <h1>")));
+}
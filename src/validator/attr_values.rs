@@ -0,0 +1,240 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checking attribute values against their microsyntaxes.
+
+use core::prelude::*;
+
+use diagnostic::{Diagnostic, Error};
+use microsyntax;
+use sink::common::Element;
+use sink::rcdom::Handle;
+use tokenizer::{Attribute, Span};
+
+use util::str::AsciiExt;
+
+use collections::vec::Vec;
+use collections::string::String;
+use std::borrow::Cow::Owned;
+
+use self::Syntax::*;
+
+/// The microsyntax of an attribute value.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+enum Syntax {
+    Integer,
+    NonNegativeInteger,
+    PositiveInteger,
+    FloatingPoint,
+    Step,
+    SimpleColor,
+    Date,
+    Month,
+    Week,
+    Time,
+    LocalDateTime,
+    DateWithOptionalTime,
+    TimeDatetime,
+}
+
+impl Syntax {
+    fn matches(self, value: &str) -> bool {
+        match self {
+            Integer => microsyntax::is_valid_integer(value),
+            NonNegativeInteger => microsyntax::is_valid_non_negative_integer(value),
+            PositiveInteger => microsyntax::is_valid_non_negative_integer(value)
+                && value.chars().any(|c| c != '0'),
+            FloatingPoint => microsyntax::is_valid_floating_point_number(value),
+            Step => value.eq_ignore_ascii_case("any")
+                || microsyntax::is_valid_floating_point_number(value),
+            SimpleColor => microsyntax::is_valid_simple_color(value),
+            Date => microsyntax::is_valid_date_string(value),
+            Month => microsyntax::is_valid_month_string(value),
+            Week => microsyntax::is_valid_week_string(value),
+            Time => microsyntax::is_valid_time_string(value),
+            LocalDateTime => microsyntax::is_valid_local_date_and_time_string(value),
+            DateWithOptionalTime => microsyntax::is_valid_date_string_with_optional_time(value),
+            TimeDatetime => microsyntax::is_valid_time_datetime_value(value),
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Integer => "an integer",
+            NonNegativeInteger => "a non-negative integer",
+            PositiveInteger => "a positive integer",
+            FloatingPoint => "a floating-point number",
+            Step => "a floating-point number or \"any\"",
+            SimpleColor => "a color of the form #rrggbb",
+            Date => "a date",
+            Month => "a month",
+            Week => "a week",
+            Time => "a time",
+            LocalDateTime => "a local date and time",
+            DateWithOptionalTime => "a date, optionally with a time",
+            TimeDatetime => "a date, time, or duration",
+        }
+    }
+}
+
+// The microsyntax for the `type` of an `<input>`.
+fn input_syntax(input_type: &str) -> Option<Syntax> {
+    match input_type {
+        "number" | "range" => Some(FloatingPoint),
+        "date" => Some(Date),
+        "month" => Some(Month),
+        "week" => Some(Week),
+        "time" => Some(Time),
+        "datetime-local" => Some(LocalDateTime),
+        _ => None,
+    }
+}
+
+fn syntax_for(element: &str, attr: &str, input_type: &str) -> Option<(Syntax, &'static str)> {
+    Some(match (element, attr) {
+        (_, "tabindex") => (Integer, "attr-tabindex"),
+
+        ("td", "colspan") | ("th", "colspan") => (PositiveInteger, "attr-tdth-colspan"),
+        ("td", "rowspan") | ("th", "rowspan") => (NonNegativeInteger, "attr-tdth-rowspan"),
+        ("col", "span") | ("colgroup", "span") => (PositiveInteger, "attr-col-span"),
+
+        ("canvas", "width") | ("canvas", "height")
+            | ("embed", "width") | ("embed", "height")
+            | ("iframe", "width") | ("iframe", "height")
+            | ("img", "width") | ("img", "height")
+            | ("input", "width") | ("input", "height")
+            | ("object", "width") | ("object", "height")
+            | ("video", "width") | ("video", "height")
+            => (NonNegativeInteger, "attr-dim-width"),
+
+        ("input", "maxlength") | ("textarea", "maxlength")
+            => (NonNegativeInteger, "attr-fe-maxlength"),
+        ("input", "minlength") | ("textarea", "minlength")
+            => (NonNegativeInteger, "attr-fe-minlength"),
+        ("input", "size") => (PositiveInteger, "attr-input-size"),
+        ("select", "size") => (PositiveInteger, "attr-select-size"),
+        ("textarea", "rows") => (PositiveInteger, "attr-textarea-rows"),
+        ("textarea", "cols") => (PositiveInteger, "attr-textarea-cols"),
+
+        ("input", "min") => (unwrap_or_return!(input_syntax(input_type), None), "attr-input-min"),
+        ("input", "max") => (unwrap_or_return!(input_syntax(input_type), None), "attr-input-max"),
+        ("input", "step") => match input_syntax(input_type) {
+            Some(_) => (Step, "attr-input-step"),
+            None => return None,
+        },
+        ("input", "value") => match input_type {
+            "color" => (SimpleColor, "color-state-(type=color)"),
+            _ => (unwrap_or_return!(input_syntax(input_type), None), "attr-input-value"),
+        },
+
+        ("ol", "start") => (Integer, "attr-ol-start"),
+        ("li", "value") => (Integer, "attr-li-value"),
+
+        ("meter", "value") | ("meter", "min") | ("meter", "max") | ("meter", "low")
+            | ("meter", "high") | ("meter", "optimum") => (FloatingPoint, "the-meter-element"),
+        ("progress", "value") | ("progress", "max") => (FloatingPoint, "the-progress-element"),
+
+        ("del", "datetime") | ("ins", "datetime") => (DateWithOptionalTime, "attr-mod-datetime"),
+        ("time", "datetime") => (TimeDatetime, "attr-time-datetime"),
+
+        _ => return None,
+    })
+}
+
+fn check_element(name: &str, attrs: &[Attribute], span: Option<Span>, out: &mut Vec<Diagnostic>) {
+    let input_type = attrs.iter()
+        .find(|a| a.name.ns == ns!("") && a.name.local.as_slice() == "type")
+        .map(|a| a.value.as_slice().to_ascii_lower())
+        .unwrap_or(String::new());
+
+    for attr in attrs.iter().filter(|a| a.name.ns == ns!("")) {
+        let local = attr.name.local.as_slice();
+        let value = attr.value.as_slice();
+        let (syntax, spec) = match syntax_for(name, local, input_type.as_slice()) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        // An empty value means no value, for an `<input>`.
+        if name == "input" && local == "value" && value.is_empty() {
+            continue;
+        }
+
+        if !syntax.matches(value) {
+            out.push(Diagnostic {
+                code: "Bad attribute value",
+                message: Owned(format!("Bad value \"{}\" for attribute {} on <{}>: expected {}",
+                    value, local, name, syntax.description())),
+                span: span,
+                severity: Error,
+                spec: Some(spec),
+            });
+        }
+    }
+}
+
+fn walk(handle: &Handle, out: &mut Vec<Diagnostic>) {
+    let node = handle.borrow();
+    match node.node {
+        Element(ref name, ref attrs) if name.ns == ns!(HTML) => {
+            check_element(name.local.as_slice(), attrs.as_slice(), node.span, out);
+        }
+        _ => (),
+    }
+    for child in node.children.iter() {
+        walk(child, out);
+    }
+}
+
+/// Check the values of attributes which have a defined microsyntax,
+/// such as `colspan` or `<input type=date min>`.
+pub fn check(document: &Handle, out: &mut Vec<Diagnostic>) {
+    walk(document, out);
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::check;
+    use sink::rcdom::RcDom;
+    use driver::parse_str;
+
+    fn messages(input: &str) -> Vec<String> {
+        let dom: RcDom = parse_str(input);
+        let mut out = vec!();
+        check(&dom.document, &mut out);
+        out.into_iter().map(|d| d.message.into_owned()).collect()
+    }
+
+    fn s(x: &str) -> String {
+        String::from_str(x)
+    }
+
+    test_eq!(valid, messages("<table><tr><td colspan=2 rowspan=0>x</td></tr></table>\
+        <input type=date min=2015-01-01 value=''><input type=number step=any>\
+        <input type=color value=#ff0000><time datetime=PT2H></time><img width=10>"),
+        Vec::<String>::new());
+
+    test_eq!(zero_colspan, messages("<table><td colspan=0>"),
+        vec!(s("Bad value \"0\" for attribute colspan on <td>: expected a positive integer")));
+
+    test_eq!(bad_date, messages("<input type=DATE max=2015-02-30>"),
+        vec!(s("Bad value \"2015-02-30\" for attribute max on <input>: expected a date")));
+
+    test_eq!(untyped_min, messages("<input min=whatever>"), Vec::<String>::new());
+
+    test_eq!(bad_width, messages("<img width=50%>"),
+        vec!(s("Bad value \"50%\" for attribute width on <img>: expected a non-negative integer")));
+
+    test_eq!(bad_tabindex, messages("<div tabindex=1.5></div>"),
+        vec!(s("Bad value \"1.5\" for attribute tabindex on <div>: expected an integer")));
+}
@@ -0,0 +1,356 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checking elements against their content models.
+//!
+//! This covers the common cases: phrasing-only elements, lists, tables,
+//! `<select>`, `<head>`, void elements, elements which must have a
+//! particular parent, and interactive content nested inside `<a>` or
+//! `<button>`.  Elements not listed here are treated as accepting any
+//! flow content, and foreign (SVG and MathML) content is not checked.
+
+use core::prelude::*;
+
+use diagnostic::{Diagnostic, Error};
+use sink::common::{Text, Element, attr};
+use sink::rcdom::Handle;
+use tokenizer::{Attribute, Span};
+
+use util::str::{AsciiExt, is_ascii_whitespace};
+
+use collections::vec::Vec;
+use collections::string::String;
+use std::borrow::Cow::Owned;
+
+use string_cache::Atom;
+
+use phf::Set;
+
+use self::ContentModel::*;
+
+/// What an element may contain.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+enum ContentModel {
+    /// Phrasing content.
+    Phrasing,
+
+    /// Metadata content.
+    Metadata,
+
+    /// The listed elements, script-supporting elements, and
+    /// inter-element whitespace.
+    Children(&'static [&'static str]),
+
+    /// Whatever the parent may contain.
+    Transparent,
+
+    /// Text, but no elements.
+    TextOnly,
+
+    /// Nothing at all.
+    Nothing,
+}
+
+static LIST_ITEMS: &'static [&'static str] = &["li"];
+static DL_CHILDREN: &'static [&'static str] = &["dt", "dd", "div"];
+static TABLE_CHILDREN: &'static [&'static str]
+    = &["caption", "colgroup", "thead", "tbody", "tfoot", "tr"];
+static ROWS: &'static [&'static str] = &["tr"];
+static CELLS: &'static [&'static str] = &["td", "th"];
+static COLS: &'static [&'static str] = &["col"];
+static SELECT_CHILDREN: &'static [&'static str] = &["option", "optgroup", "hr"];
+static OPTIONS: &'static [&'static str] = &["option"];
+static HTML_CHILDREN: &'static [&'static str] = &["head", "body"];
+
+static LISTS: &'static [&'static str] = &["ul", "ol", "menu"];
+static DESCRIPTION_LISTS: &'static [&'static str] = &["dl", "div"];
+static OPTION_PARENTS: &'static [&'static str] = &["select", "datalist", "optgroup"];
+static FIELDSETS: &'static [&'static str] = &["fieldset"];
+static FIGURES: &'static [&'static str] = &["figure"];
+static DETAILS: &'static [&'static str] = &["details"];
+
+// Some of these are phrasing content only in certain contexts, or with
+// certain attributes; we accept them everywhere.
+static PHRASING: Set<&'static str> = phf_set! {
+    "a", "abbr", "area", "audio", "b", "bdi", "bdo", "br", "button",
+    "canvas", "cite", "code", "data", "datalist", "del", "dfn", "em",
+    "embed", "i", "iframe", "img", "input", "ins", "kbd", "label", "link",
+    "map", "mark", "math", "meta", "meter", "noscript", "object", "output",
+    "picture", "progress", "q", "ruby", "s", "samp", "script", "select",
+    "slot", "small", "span", "strong", "sub", "sup", "svg", "template",
+    "textarea", "time", "u", "var", "video", "wbr",
+};
+
+static METADATA: Set<&'static str> = phf_set! {
+    "base", "link", "meta", "noscript", "script", "style", "template", "title",
+};
+
+static SCRIPT_SUPPORTING: Set<&'static str> = phf_set! {
+    "script", "template",
+};
+
+fn content_model(name: &str) -> Option<(ContentModel, &'static str)> {
+    Some(match name {
+        "p" => (Phrasing, "the-p-element"),
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+            => (Phrasing, "the-h1,-h2,-h3,-h4,-h5,-and-h6-elements"),
+        "pre" => (Phrasing, "the-pre-element"),
+        "abbr" | "b" | "bdi" | "bdo" | "button" | "cite" | "code" | "data"
+            | "dfn" | "em" | "i" | "kbd" | "label" | "mark" | "output" | "q"
+            | "s" | "samp" | "small" | "span" | "strong" | "sub" | "sup"
+            | "time" | "u" | "var" => (Phrasing, "phrasing-content"),
+
+        "a" | "audio" | "canvas" | "del" | "ins" | "map" | "noscript"
+            | "object" | "slot" | "video" => (Transparent, "transparent"),
+
+        "html" => (Children(HTML_CHILDREN), "the-html-element"),
+        "head" => (Metadata, "the-head-element"),
+        "ul" => (Children(LIST_ITEMS), "the-ul-element"),
+        "ol" => (Children(LIST_ITEMS), "the-ol-element"),
+        "menu" => (Children(LIST_ITEMS), "the-menu-element"),
+        "dl" => (Children(DL_CHILDREN), "the-dl-element"),
+        "table" => (Children(TABLE_CHILDREN), "the-table-element"),
+        "thead" => (Children(ROWS), "the-thead-element"),
+        "tbody" => (Children(ROWS), "the-tbody-element"),
+        "tfoot" => (Children(ROWS), "the-tfoot-element"),
+        "tr" => (Children(CELLS), "the-tr-element"),
+        "colgroup" => (Children(COLS), "the-colgroup-element"),
+        "select" => (Children(SELECT_CHILDREN), "the-select-element"),
+        "optgroup" => (Children(OPTIONS), "the-optgroup-element"),
+
+        "title" => (TextOnly, "the-title-element"),
+        "textarea" => (TextOnly, "the-textarea-element"),
+        "option" => (TextOnly, "the-option-element"),
+
+        "area" | "base" | "br" | "col" | "embed" | "hr" | "img" | "input"
+            | "link" | "meta" | "source" | "track" | "wbr" => (Nothing, "void-elements"),
+
+        _ => return None,
+    })
+}
+
+fn required_parents(name: &str) -> Option<(&'static [&'static str], &'static str)> {
+    Some(match name {
+        "li" => (LISTS, "the-li-element"),
+        "dt" => (DESCRIPTION_LISTS, "the-dt-element"),
+        "dd" => (DESCRIPTION_LISTS, "the-dd-element"),
+        "option" => (OPTION_PARENTS, "the-option-element"),
+        "legend" => (FIELDSETS, "the-legend-element"),
+        "figcaption" => (FIGURES, "the-figcaption-element"),
+        "summary" => (DETAILS, "the-summary-element"),
+        _ => return None,
+    })
+}
+
+fn is_phrasing(name: &str) -> bool {
+    // Autonomous custom elements are phrasing content.
+    PHRASING.contains(&name) || name.chars().any(|c| c == '-')
+}
+
+fn is_interactive(name: &str, attrs: &[Attribute]) -> bool {
+    match name {
+        "a" | "button" | "details" | "embed" | "iframe" | "label" | "select"
+            | "textarea" => true,
+        "input" => attr(attrs, "type").map_or(true, |t| !t.eq_ignore_ascii_case("hidden")),
+        "audio" | "video" => attr(attrs, "controls").is_some(),
+        "img" => attr(attrs, "usemap").is_some(),
+        _ => false,
+    }
+}
+
+// Find the content model which applies to children of the last of
+// `ancestors`, looking through transparent elements.  An element which
+// only allows certain children has already been checked against the
+// transparent element, so we don't report its descendants too.
+fn parent_model<'a>(ancestors: &'a [Atom]) -> Option<(&'a str, ContentModel, &'static str)> {
+    let mut through_transparent = false;
+    for a in ancestors.iter().rev() {
+        match content_model(a.as_slice()) {
+            Some((Transparent, _)) => through_transparent = true,
+            Some((Children(_), _)) if through_transparent => return None,
+            Some((model, spec)) => return Some((a.as_slice(), model, spec)),
+            None => return None,
+        }
+    }
+    None
+}
+
+fn report(out: &mut Vec<Diagnostic>, span: Option<Span>, code: &'static str,
+          spec: &'static str, message: String) {
+    out.push(Diagnostic {
+        code: code,
+        message: Owned(message),
+        span: span,
+        severity: Error,
+        spec: Some(spec),
+    });
+}
+
+fn check_element(name: &str, attrs: &[Attribute], span: Option<Span>,
+                 ancestors: &[Atom], out: &mut Vec<Diagnostic>) {
+    let parent = match ancestors.last() {
+        Some(p) => p.as_slice(),
+        None => return,
+    };
+
+    match parent_model(ancestors) {
+        Some((ancestor, model, spec)) => {
+            let allowed = match model {
+                Phrasing => is_phrasing(name),
+                Metadata => METADATA.contains(&name),
+                Children(names) => names.contains(&name) || SCRIPT_SUPPORTING.contains(&name),
+                TextOnly | Nothing => false,
+                Transparent => unreachable!(),
+            };
+            if !allowed {
+                report(out, span, "Element not allowed here", spec,
+                    format!("<{}> is not allowed in <{}>", name, ancestor));
+            }
+        }
+        None => (),
+    }
+
+    match required_parents(name) {
+        Some((parents, spec)) if !parents.contains(&parent) => {
+            let mut message = format!("<{}> must be a child of", name);
+            for (i, p) in parents.iter().enumerate() {
+                message.push_str(if i == 0 { " <" } else { " or <" });
+                message.push_str(*p);
+                message.push('>');
+            }
+            report(out, span, "Element needs another parent", spec, message);
+        }
+        _ => (),
+    }
+
+    if is_interactive(name, attrs) {
+        match ancestors.iter().rev().find(|a| a.as_slice() == "a" || a.as_slice() == "button") {
+            Some(a) => {
+                let spec = if a.as_slice() == "a" { "the-a-element" } else { "the-button-element" };
+                report(out, span, "Nested interactive element", spec, format!(
+                    "Interactive element <{}> is not allowed inside <{}>", name, a.as_slice()));
+            }
+            None => (),
+        }
+    }
+
+    if name == "form" && ancestors.iter().any(|a| a.as_slice() == "form") {
+        report(out, span, "Nested form", "the-form-element",
+            String::from_str("<form> is not allowed inside another <form>"));
+    }
+}
+
+fn check_text(text: &str, span: Option<Span>, ancestors: &[Atom], out: &mut Vec<Diagnostic>) {
+    if text.chars().all(is_ascii_whitespace) {
+        return;
+    }
+    match parent_model(ancestors) {
+        Some((parent, Children(_), spec))
+        | Some((parent, Metadata, spec))
+        | Some((parent, Nothing, spec))
+            => report(out, span, "Text not allowed here", spec,
+                format!("Text is not allowed in <{}>", parent)),
+        _ => (),
+    }
+}
+
+fn walk(handle: &Handle, ancestors: &mut Vec<Atom>, out: &mut Vec<Diagnostic>) {
+    let node = handle.borrow();
+    match node.node {
+        Text(ref text) => check_text(text.as_slice(), node.span, ancestors.as_slice(), out),
+        Element(ref name, ref attrs) => {
+            check_element(name.local.as_slice(), attrs.as_slice(), node.span,
+                ancestors.as_slice(), out);
+
+            // The content of foreign elements isn't checked.
+            if name.ns != ns!(HTML) {
+                return;
+            }
+            ancestors.push(name.local.clone());
+            for child in node.children.iter() {
+                walk(child, ancestors, out);
+            }
+            ancestors.pop();
+        }
+        _ => (),
+    }
+}
+
+/// Check that each element is somewhere it's allowed to be, and
+/// contains only what it may contain.
+pub fn check(document: &Handle, out: &mut Vec<Diagnostic>) {
+    let mut ancestors = vec!();
+    for child in document.borrow().children.iter() {
+        walk(child, &mut ancestors, out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use core::default::Default;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::check;
+    use sink::rcdom::RcDom;
+    use driver::{parse, parse_str, one_input, ParseOpts};
+    use tokenizer::TokenizerOpts;
+
+    fn messages(input: &str) -> Vec<String> {
+        let dom: RcDom = parse_str(input);
+        let mut out = vec!();
+        check(&dom.document, &mut out);
+        out.into_iter().map(|d| d.message.into_owned()).collect()
+    }
+
+    fn s(x: &str) -> String {
+        String::from_str(x)
+    }
+
+    test_eq!(valid, messages("<!DOCTYPE html><title>t</title><ul><li>a</li></ul>\
+        <p>hi <b>x</b></p><table><tr><td>1</td></tr></table>"), Vec::<String>::new());
+
+    test_eq!(div_in_ul, messages("<ul><div>x</div></ul>"),
+        vec!(s("<div> is not allowed in <ul>")));
+
+    test_eq!(text_in_ul, messages("<ul> text <li>a</ul>"),
+        vec!(s("Text is not allowed in <ul>")));
+
+    test_eq!(div_in_span, messages("<span><div></div></span>"),
+        vec!(s("<div> is not allowed in <span>")));
+
+    test_eq!(button_in_a, messages("<p><a href=x><button>b</button></a>"),
+        vec!(s("Interactive element <button> is not allowed inside <a>")));
+
+    test_eq!(hidden_input_in_a, messages("<a href=x><input type=hidden></a>"),
+        Vec::<String>::new());
+
+    test_eq!(li_outside_list, messages("<li>x</li>"),
+        vec!(s("<li> must be a child of <ul> or <ol> or <menu>")));
+
+    #[test]
+    fn has_span() {
+        let opts = ParseOpts {
+            tokenizer: TokenizerOpts {
+                track_positions: true,
+                .. Default::default()
+            },
+            .. Default::default()
+        };
+        let dom: RcDom = parse(one_input(String::from_str("<ul>\n <div></div></ul>")), opts);
+        let mut out = vec!();
+        check(&dom.document, &mut out);
+        assert_eq!(out.len(), 1);
+        let start = out[0].span.expect("no span").start;
+        assert_eq!((start.line, start.column), (2, 2));
+        assert_eq!(out[0].spec_url().unwrap().as_slice(),
+            "https://html.spec.whatwg.org/#the-ul-element");
+    }
+}
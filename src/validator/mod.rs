@@ -0,0 +1,76 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checking a parsed document against the authoring requirements
+//! of the HTML standard.
+//!
+//! The parser accepts any input and reports only syntax errors.  The
+//! validator walks the resulting `RcDom` and reports documents which
+//! parse cleanly but still aren't conforming, such as a `<div>` inside
+//! a `<ul>`.  Parse with the tokenizer's `track_positions` option to get
+//! a span for each diagnostic.
+//!
+//! Problems are reported as `Diagnostic`s, like parse errors, with the
+//! `spec` field set.  The accessibility lints are warnings; everything
+//! else is an error.
+
+use core::prelude::*;
+
+use sink::rcdom::RcDom;
+use diagnostic::Diagnostic;
+
+use core::cmp::Ordering::{Less, Equal, Greater};
+use collections::vec::Vec;
+
+pub mod content_model;
+pub mod attr_values;
+pub mod accessibility;
+pub mod polyglot;
+
+// Sort diagnostics by where they start in the input, with those
+// without a span at the end.  The sort is stable, so diagnostics from
+// the same place stay in the order they were found.
+fn sort_by_position(diagnostics: &mut Vec<Diagnostic>) {
+    diagnostics.sort_by(|a, b| match (a.span, b.span) {
+        (Some(x), Some(y)) => x.start.cmp(&y.start),
+        (Some(_), None) => Less,
+        (None, Some(_)) => Greater,
+        (None, None) => Equal,
+    });
+}
+
+/// Run all of the checks on a parsed document, and return the problems
+/// found, in order of position if the document was parsed with
+/// `track_positions`.
+pub fn validate(dom: &RcDom) -> Vec<Diagnostic> {
+    let mut out = vec!();
+    content_model::check(&dom.document, &mut out);
+    attr_values::check(&dom.document, &mut out);
+    sort_by_position(&mut out);
+    out
+}
+
+/// Run the accessibility lints, which aren't part of `validate` because
+/// they also flag some conforming documents.  The warnings go through
+/// the DOM's `report_diagnostic`, like parse errors, so they're added to
+/// `dom.errors`.
+pub fn lint_accessibility(dom: &mut RcDom) {
+    let document = dom.document.clone();
+    accessibility::check(&document, dom);
+}
+
+/// Check the source of a document for markup which would not parse the
+/// same way as XML, for documents served as both `text/html` and
+/// `application/xhtml+xml`.  Unlike the other checks this works on the
+/// source, since the tree doesn't record how the markup was written.
+pub fn check_polyglot(source: &str) -> Vec<Diagnostic> {
+    let mut out = vec!();
+    polyglot::check(source, &mut out);
+    out
+}
@@ -0,0 +1,162 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Polyglot checks: markup which parses as HTML but is not well-formed
+//! XML, or means something else as XML.  Unquoted and valueless
+//! attributes, named character references other than the five XML
+//! predefines, references without a `;`, and void elements without a
+//! `/>` are reported.
+//!
+//! These work on the source rather than the tree, which doesn't
+//! record how attributes were quoted or tags closed.
+
+use core::prelude::*;
+
+use diagnostic::{Diagnostic, Error};
+use highlight::{highlight, Highlight, Entity, TagPunctuation, TagName, AttributeName};
+use sink::common::is_void;
+
+use util::str::AsciiExt;
+
+use collections::vec::Vec;
+use collections::string::String;
+use std::borrow::Cow::Owned;
+
+use string_cache::Atom;
+
+/// Run the polyglot checks on the source of a document.
+pub fn check(source: &str, out: &mut Vec<Diagnostic>) {
+    let parts = highlight(source);
+    let text = |h: &Highlight| &source[h.span.start.offset..h.span.end.offset];
+    let report = |out: &mut Vec<Diagnostic>, h: &Highlight, code: &'static str,
+                  spec: &'static str, message: String| {
+        out.push(Diagnostic {
+            code: code,
+            message: Owned(message),
+            span: Some(h.span),
+            severity: Error,
+            spec: Some(spec),
+        });
+    };
+
+    for (i, h) in parts.iter().enumerate() {
+        let next = parts.get(i + 1);
+        match h.class {
+            AttributeName => {
+                let eq = match next {
+                    Some(n) if n.class == TagPunctuation && text(n).starts_with("=") => n,
+                    _ => {
+                        report(out, h, "Attribute without value", "attributes-2",
+                            format!("Attribute {} has no value", text(h)));
+                        continue;
+                    }
+                };
+                // The quote is merged with the `=` unless there's space
+                // between them.
+                let quoted = text(eq).len() > 1 || match parts.get(i + 2) {
+                    Some(q) => q.class == TagPunctuation
+                        && (text(q).starts_with("\"") || text(q).starts_with("'")),
+                    None => false,
+                };
+                if !quoted {
+                    report(out, h, "Unquoted attribute value", "attributes-2",
+                        format!("Value of attribute {} is not quoted", text(h)));
+                }
+            }
+
+            Entity => {
+                // Adjacent references are highlighted as one part.
+                for piece in text(h).split('&').skip(1) {
+                    let reference = format!("&{}", piece);
+                    if !piece.ends_with(";") {
+                        report(out, h, "Character reference without semicolon",
+                            "character-references",
+                            format!("Character reference {} has no semicolon", reference));
+                    } else if !piece.starts_with("#") {
+                        match piece {
+                            "amp;" | "lt;" | "gt;" | "quot;" | "apos;" => (),
+                            _ => report(out, h, "Named character reference not in XML",
+                                "named-character-references",
+                                format!("Named character reference {} is not defined in XML",
+                                    reference)),
+                        }
+                    }
+                }
+            }
+
+            TagName => {
+                let is_start = i > 0 && text(&parts[i - 1]).ends_with("<")
+                    && !text(&parts[i - 1]).ends_with("</");
+                let name = text(h).to_ascii_lower();
+                if !is_start || !is_void(&Atom::from_slice(name.as_slice())) {
+                    continue;
+                }
+                // The tag's `>` may be merged with the punctuation
+                // around it, as in `></p>`.
+                let close = parts[i + 1..].iter()
+                    .filter(|p| p.class == TagPunctuation)
+                    .filter_map(|p| text(p).find('>').map(|end| &text(p)[..end]))
+                    .next();
+                match close {
+                    Some(c) if !c.ends_with("/") => report(out, h,
+                        "Void element not self-closed", "void-elements",
+                        format!("Void element <{}> is not closed with />", name)),
+                    _ => (),
+                }
+            }
+
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::check;
+
+    fn messages(input: &str) -> Vec<String> {
+        let mut out = vec!();
+        check(input, &mut out);
+        out.into_iter().map(|d| d.message.into_owned()).collect()
+    }
+
+    fn s(x: &str) -> String {
+        String::from_str(x)
+    }
+
+    test_eq!(polyglot, messages("<!DOCTYPE html><html xmlns='http://www.w3.org/1999/xhtml'>\
+        <p class=\"a\" id = 'b'>x &amp; &#160; &#x41;<br/><img src='i.png' alt=''/></p>\
+        <svg><path d='M0'/></svg></html>"), Vec::<String>::new());
+
+    test_eq!(attributes, messages("<input type=text disabled><p a = b>"),
+        vec!(s("Void element <input> is not closed with />"),
+             s("Value of attribute type is not quoted"),
+             s("Attribute disabled has no value"),
+             s("Value of attribute a is not quoted")));
+
+    test_eq!(references, messages("<p title='&copy;'>&nbsp;&amp;&#65&lt;</p>"),
+        vec!(s("Named character reference &copy; is not defined in XML"),
+             s("Named character reference &nbsp; is not defined in XML"),
+             s("Character reference &#65 has no semicolon")));
+
+    test_eq!(unterminated_names, messages("<p title='&copy=x &amp'>&copy 2024 &notit</p>"),
+        vec!(s("Character reference &amp has no semicolon"),
+             s("Character reference &copy has no semicolon"),
+             s("Character reference &not has no semicolon")));
+
+    test_eq!(void_elements, messages("<BR><hr class='x'><img src='a'/></br><param><keygen>"),
+        vec!(s("Void element <br> is not closed with />"),
+             s("Void element <hr> is not closed with />"),
+             s("Void element <param> is not closed with />"),
+             s("Void element <keygen> is not closed with />")));
+}
@@ -0,0 +1,150 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Parsing XML, such as `application/xhtml+xml` documents, into the same
+//! `TreeSink` as HTML.
+//!
+//! This is not a validating parser, and it doesn't stop at the first
+//! well-formedness error: errors go to the sink's `report_diagnostic`,
+//! and parsing recovers as described in the `tokenizer` and
+//! `tree_builder` modules.  Elements and attributes get the namespaces
+//! declared with `xmlns`, so XHTML elements end up in `ns!(HTML)` just
+//! as they would from the HTML parser.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let dom: RcDom = parse_xml(one_input(my_str));
+//! ```
+
+use core::prelude::*;
+
+use driver::ParseResult;
+use tree_builder::TreeSink;
+
+use core::default::Default;
+use collections::string::String;
+
+use self::tokenizer::XmlTokenizer;
+use self::tree_builder::XmlTreeBuilder;
+
+pub mod tokenizer;
+pub mod tree_builder;
+
+/// Parse XML and send results to a `TreeSink`, returning whatever the
+/// sink's `finish` method does.
+pub fn parse_xml_to<
+        Sink: TreeSink,
+        It: Iterator<Item=String>
+    >(
+        sink: Sink,
+        input: It) -> Sink::Output {
+
+    let tb = XmlTreeBuilder::new(sink);
+    let mut tok = XmlTokenizer::new(tb);
+    for s in input {
+        tok.feed(s);
+    }
+    tok.end();
+    tok.unwrap().unwrap().finish()
+}
+
+/// Parse XML into a type which implements `ParseResult`.
+pub fn parse_xml<Output, It>(input: It) -> Output
+    where Output: ParseResult,
+          It: Iterator<Item=String>,
+{
+    let sink: Output::Sink = Default::default();
+    parse_xml_to(sink, input)
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::String;
+
+    use super::parse_xml;
+    use sink::common::{Document, Doctype, Text, Comment, Element};
+    use sink::rcdom::{RcDom, Handle};
+
+    // A node and its descendants, with element names as `{ns}local`.
+    fn describe(handle: &Handle, out: &mut String) {
+        let node = handle.borrow();
+        match node.node {
+            Document => (),
+            Doctype(ref name, _, ref system)
+                => out.push_str(format!("<!{} {}>", name, system).as_slice()),
+            Text(ref text) => out.push_str(text.as_slice()),
+            Comment(ref text) => out.push_str(format!("<!--{}-->", text).as_slice()),
+            Element(ref name, ref attrs) => {
+                out.push_str(format!("<{{{}}}{}", name.ns.0.as_slice(),
+                    name.local.as_slice()).as_slice());
+                for a in attrs.iter() {
+                    out.push_str(format!(" {{{}}}{}={}", a.name.ns.0.as_slice(),
+                        a.name.local.as_slice(), a.value).as_slice());
+                }
+                out.push_str(">");
+            }
+        }
+        for child in node.children.iter() {
+            describe(child, out);
+        }
+        match node.node {
+            Element(..) => out.push_str("</>"),
+            _ => (),
+        }
+    }
+
+    fn parse(input: &[&str]) -> (String, Vec<String>) {
+        let chunks: Vec<String> = input.iter().map(|s| String::from_str(*s)).collect();
+        let dom: RcDom = parse_xml(chunks.into_iter());
+        let mut out = String::new();
+        describe(&dom.document, &mut out);
+        (out, dom.errors.iter().map(|e| String::from_str(&*e.message)).collect())
+    }
+
+    #[test]
+    fn namespaces() {
+        let (tree, errors) = parse(&["<?xml version='1.0'?>\n<!DOCTYPE html SYSTEM 'x'>\n\
+            <html xmlns='http://www.w3.org/1999/xhtml' xmlns:svg='http://www.w3.org/2000/svg'>\
+            <!--c--><svg:svg xml:lang='en' width='1'/><p>a &amp; <![CDATA[<b>]]></p></html>\n"]);
+        assert_eq!(tree.as_slice(), "<!html x>\
+            <{http://www.w3.org/1999/xhtml}html \
+            {http://www.w3.org/2000/xmlns/}xmlns=http://www.w3.org/1999/xhtml \
+            {http://www.w3.org/2000/xmlns/}svg=http://www.w3.org/2000/svg><!--c-->\
+            <{http://www.w3.org/2000/svg}svg \
+            {http://www.w3.org/XML/1998/namespace}lang=en {}width=1></>\
+            <{http://www.w3.org/1999/xhtml}p>a & <b></></>");
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn recovery() {
+        let (tree, errors) = parse(&["x<a><b><c></b><d:e/></f>", "</a><g/>"]);
+        assert_eq!(tree.as_slice(), "<{}a><{}b><{}c></></><{}d:e></></><{}g></>");
+        assert_eq!(errors, vec!(
+            String::from_str("Text outside the root element"),
+            String::from_str("End tag closes unclosed elements"),
+            String::from_str("Unbound namespace prefix"),
+            String::from_str("End tag without a matching start tag"),
+            String::from_str("More than one root element")));
+    }
+
+    #[test]
+    fn error_spans() {
+        let chunks = vec!(String::from_str("<a/>\r\n"), String::from_str("<g/>"));
+        let dom: RcDom = parse_xml(chunks.into_iter());
+        let spans: Vec<(uint, uint)> = dom.errors.iter().map(|e| {
+            let span = e.span.unwrap();
+            (span.start.offset, span.end.offset)
+        }).collect();
+        assert_eq!(spans, vec!((6, 10)));
+    }
+}
@@ -0,0 +1,757 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tokenizing XML.
+//!
+//! This handles well-formed XML, and recovers from errors rather than
+//! stopping: each is reported as a `ParseError` token, with the span of
+//! the construct it was found in, and tokenizing carries on as sensibly
+//! as it can.  Only the five predefined entities
+//! and numeric character references are recognized; other entity
+//! references are reported and left in the text.  The internal subset
+//! of a doctype is skipped.
+//!
+//! Line endings are normalized to `\n`, and whitespace in attribute
+//! values to spaces.
+
+use core::prelude::*;
+use core::char::from_u32;
+
+use tokenizer::{Pos, Span};
+use diagnostic::Diagnostic;
+use util::str::is_ascii_whitespace;
+
+use core::cmp;
+use core::default::Default;
+use collections::vec::Vec;
+use collections::string::String;
+use collections::RingBuf;
+
+pub use self::XmlToken::{StartTag, EndTag, Characters, Comment, ProcessingInstruction};
+pub use self::XmlToken::{Doctype, ParseError, EOF};
+
+use self::Scan::{Done, Incomplete};
+
+/// A start tag, or an empty-element tag.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct XmlTag {
+    /// The name as written, possibly with a prefix.
+    pub name: String,
+
+    /// Attribute names as written, and their values.
+    pub attrs: Vec<(String, String)>,
+
+    /// Was this an empty-element tag, like `<br/>`?
+    pub empty: bool,
+}
+
+/// A `<!DOCTYPE>`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct XmlDoctype {
+    pub name: String,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum XmlToken {
+    StartTag(XmlTag),
+    EndTag(String),
+
+    /// Text, including the contents of CDATA sections.
+    Characters(String),
+
+    Comment(String),
+
+    /// A processing instruction's target and data.  The XML declaration
+    /// `<?xml ...?>` is not passed on.
+    ProcessingInstruction(String, String),
+
+    Doctype(XmlDoctype),
+    ParseError(Diagnostic),
+    EOF,
+}
+
+/// Types which can process XML tokens.
+pub trait XmlTokenSink {
+    fn process_token(&mut self, token: XmlToken);
+
+    /// The tokenizer will call this before each token, with the part of
+    /// the input the token came from.
+    fn set_current_span(&mut self, _span: Span) { }
+}
+
+// The result of scanning a construct at the start of the input.
+enum Scan<T> {
+    /// The construct, and how many bytes it took.
+    Done(T, uint),
+
+    /// The input ends before the construct does.
+    Incomplete,
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == ':' || (c as u32) >= 0x80
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start(c) || c.is_numeric() || c == '-' || c == '.'
+}
+
+fn error(msg: &'static str) -> XmlToken {
+    ParseError(Diagnostic::error(msg))
+}
+
+// Find `pat` in `rest`, at or after `from`.  The first `resume` bytes of
+// `rest` were searched before, so only a match which ends after them is
+// looked for.
+fn find_from(rest: &str, from: uint, resume: uint, pat: &str) -> Option<uint> {
+    let overlap = if resume >= pat.len() { resume + 1 - pat.len() } else { 0 };
+    let mut start = cmp::max(from, overlap);
+    while !rest.is_char_boundary(start) {
+        start -= 1;
+    }
+    rest[start..].find_str(pat).map(|i| start + i)
+}
+
+/// The XML tokenizer.
+pub struct XmlTokenizer<Sink> {
+    sink: Sink,
+
+    /// Input, of which everything before `pos` has been tokenized.
+    buf: String,
+    pos: uint,
+
+    /// How many bytes after `pos` were already searched for the end of
+    /// an incomplete construct.
+    resume: uint,
+
+    /// How many bytes of input, after line endings were normalized,
+    /// came before `buf`.
+    base: uint,
+
+    /// Where `pos` is in the input as it was given.
+    cursor: Pos,
+
+    /// Offsets, counted like `base`, of each `\n` which stood for a
+    /// CRLF, and so for two bytes of input, and which `cursor` hasn't
+    /// yet passed.
+    crlfs: RingBuf<uint>,
+
+    /// Did the last input end with `\r`?  Then a `\n` which starts the
+    /// next input belongs to the same line ending.
+    after_cr: bool,
+
+    at_eof: bool,
+}
+
+impl<Sink: XmlTokenSink> XmlTokenizer<Sink> {
+    pub fn new(sink: Sink) -> XmlTokenizer<Sink> {
+        XmlTokenizer {
+            sink: sink,
+            buf: String::new(),
+            pos: 0,
+            resume: 0,
+            base: 0,
+            cursor: Default::default(),
+            crlfs: RingBuf::new(),
+            after_cr: false,
+            at_eof: false,
+        }
+    }
+
+    pub fn sink<'a>(&'a self) -> &'a Sink {
+        &self.sink
+    }
+
+    pub fn sink_mut<'a>(&'a mut self) -> &'a mut Sink {
+        &mut self.sink
+    }
+
+    pub fn unwrap(self) -> Sink {
+        self.sink
+    }
+
+    /// Feed more input.  Constructs which aren't complete yet wait for
+    /// the next call.
+    pub fn feed(&mut self, input: String) {
+        let mut chars = input.as_slice().chars().peekable();
+        if self.after_cr && chars.peek() == Some(&'\n') {
+            chars.next();
+            let at = (self.base + self.buf.len()) - 1;
+            if at < self.base + self.pos {
+                self.cursor.offset += 1;
+            } else {
+                self.crlfs.push_back(at);
+            }
+        }
+        self.after_cr = false;
+        loop {
+            match chars.next() {
+                Some('\r') => {
+                    self.buf.push('\n');
+                    match chars.peek() {
+                        Some(&'\n') => {
+                            chars.next();
+                            self.crlfs.push_back(self.base + self.buf.len() - 1);
+                        }
+                        Some(_) => (),
+                        None => self.after_cr = true,
+                    }
+                }
+                Some(c) => self.buf.push(c),
+                None => break,
+            }
+        }
+        self.run();
+    }
+
+    /// Tokenize whatever input is left, and send `EOF`.
+    pub fn end(&mut self) {
+        self.at_eof = true;
+        self.run();
+        let span = Span {
+            start: self.cursor,
+            end: self.cursor,
+        };
+        self.send(EOF, span);
+    }
+
+    fn send(&mut self, mut token: XmlToken, span: Span) {
+        match token {
+            ParseError(ref mut error) => error.span = Some(span),
+            _ => (),
+        }
+        self.sink.set_current_span(span);
+        self.sink.process_token(token);
+    }
+
+    fn run(&mut self) {
+        while self.pos < self.buf.len() {
+            let scanned = {
+                let rest = &self.buf[self.pos..];
+                match self.scan(rest, self.resume) {
+                    Done(tokens, n) => Some((tokens, n)),
+                    Incomplete if self.at_eof
+                        => Some((vec!(error("Unexpected end of input")), rest.len())),
+                    Incomplete => None,
+                }
+            };
+            let (tokens, consumed) = unwrap_or_else!(scanned, {
+                self.resume = self.buf.len() - self.pos;
+                break;
+            });
+
+            let start = self.cursor;
+            self.advance(consumed);
+            self.resume = 0;
+            let span = Span {
+                start: start,
+                end: self.cursor,
+            };
+            for token in tokens.into_iter() {
+                self.send(token, span);
+            }
+        }
+
+        // Drop the tokenized input only once it's at least half of the
+        // buffer, so that the copying takes linear time overall.
+        if self.pos > 0 && self.pos * 2 >= self.buf.len() {
+            let rest = String::from_str(&self.buf[self.pos..]);
+            self.buf = rest;
+            self.base += self.pos;
+            self.pos = 0;
+        }
+    }
+
+    // Move `pos` and `cursor` over the next `n` bytes.
+    fn advance(&mut self, n: uint) {
+        let end = self.pos + n;
+        for c in self.buf[self.pos..end].chars() {
+            if c == '\n' {
+                self.cursor.line += 1;
+                self.cursor.column = 1;
+            } else {
+                self.cursor.column += 1;
+            }
+        }
+        let mut bytes = n;
+        while self.crlfs.front().map_or(false, |&at| at < self.base + end) {
+            self.crlfs.pop_front();
+            bytes += 1;
+        }
+        self.cursor.offset += bytes;
+        self.pos = end;
+    }
+
+    // Does `rest` start with `pat`?  `None` if it's too short to tell.
+    fn starts(&self, rest: &str, pat: &str) -> Option<bool> {
+        if rest.len() >= pat.len() || self.at_eof {
+            Some(rest.starts_with(pat))
+        } else if pat.starts_with(rest) {
+            None
+        } else {
+            Some(false)
+        }
+    }
+
+    // Scan the next construct in `rest`, which is not empty.  `resume`
+    // is how much of `rest` was scanned before without finding its end.
+    fn scan(&self, rest: &str, resume: uint) -> Scan<Vec<XmlToken>> {
+        macro_rules! starts ( ($pat:expr) => (
+            match self.starts(rest, $pat) {
+                Some(b) => b,
+                None => return Incomplete,
+            }
+        ));
+
+        if !rest.starts_with("<") {
+            let end = match find_from(rest, 0, resume, "<") {
+                Some(end) => end,
+                None if self.at_eof => rest.len(),
+                // Keep back what may be the start of a reference.  Text
+                // is incomplete only when it's a reference waiting for
+                // its ';', so the last '&' before `resume` is at 0.
+                None => match rest[resume..].rfind('&').map(|i| resume + i)
+                        .or(if resume > 0 { Some(0) } else { None }) {
+                    Some(0) if find_from(rest, 0, resume, ";").is_none() => return Incomplete,
+                    Some(amp) if !rest[amp..].contains_char(';') => amp,
+                    _ => rest.len(),
+                },
+            };
+            let mut tokens = vec!();
+            let text = decode(&rest[..end], false, &mut tokens);
+            tokens.push(Characters(text));
+            return Done(tokens, end);
+        }
+
+        if starts!("<!--") {
+            let end = unwrap_or_return!(find_from(rest, 4, resume, "-->"), Incomplete);
+            return Done(vec!(Comment(String::from_str(&rest[4..end]))), end + 3);
+        }
+        if starts!("<![CDATA[") {
+            let end = unwrap_or_return!(find_from(rest, 9, resume, "]]>"), Incomplete);
+            return Done(vec!(Characters(String::from_str(&rest[9..end]))), end + 3);
+        }
+        if starts!("<!DOCTYPE") {
+            return scan_doctype(rest);
+        }
+        if starts!("<?") {
+            let end = unwrap_or_return!(find_from(rest, 2, resume, "?>"), Incomplete);
+            let body = &rest[2..end];
+            let split = body.find(is_ascii_whitespace).unwrap_or(body.len());
+            let target = &body[..split];
+            let data = body[split..].trim_left_matches(is_ascii_whitespace);
+            let tokens = if target.is_empty() {
+                vec!(error("Processing instruction without a target"))
+            } else if target == "xml" {
+                vec!()
+            } else {
+                vec!(ProcessingInstruction(String::from_str(target), String::from_str(data)))
+            };
+            return Done(tokens, end + 2);
+        }
+        if starts!("</") {
+            let end = unwrap_or_return!(find_from(rest, 2, resume, ">"), Incomplete);
+            let name = rest[2..end].trim_matches(is_ascii_whitespace);
+            return Done(vec!(EndTag(String::from_str(name))), end + 1);
+        }
+
+        match rest[1..].chars().next() {
+            None if !self.at_eof => Incomplete,
+            Some(c) if is_name_start(c) => scan_start_tag(rest),
+            _ => Done(vec!(error("Unescaped '<'"), Characters(String::from_str("<"))), 1),
+        }
+    }
+}
+
+// The character for a numeric reference, or U+FFFD if it's not valid.
+fn number(digits: &str, base: u32) -> char {
+    let mut n: u32 = 0;
+    for c in digits.chars() {
+        match c.to_digit(base as uint) {
+            Some(d) if n <= 0x10FFFF => n = n * base + d as u32,
+            _ => return '\u{fffd}',
+        }
+    }
+    if digits.is_empty() {
+        return '\u{fffd}';
+    }
+    from_u32(n).unwrap_or('\u{fffd}')
+}
+
+// Decode references in text or an attribute value, reporting errors.
+fn decode(text: &str, attr: bool, errors: &mut Vec<XmlToken>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    loop {
+        let amp = match rest.find(|c: char| c == '&' || (attr && (c == '\t' || c == '\n'))) {
+            Some(i) => i,
+            None => break,
+        };
+        out.push_str(&rest[..amp]);
+        if rest.char_at(amp) != '&' {
+            out.push(' ');
+            rest = &rest[amp + 1..];
+            continue;
+        }
+        rest = &rest[amp..];
+
+        let semicolon = rest.find(';').unwrap_or(0);
+        let name = if semicolon > 1 { &rest[1..semicolon] } else { "" };
+        let decoded = match name {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "apos" => Some('\''),
+            "quot" => Some('"'),
+            _ if name.starts_with("#x") => {
+                Some(number(&name[2..], 16))
+            }
+            _ if name.starts_with("#") => {
+                Some(number(&name[1..], 10))
+            }
+            _ => None,
+        };
+        match decoded {
+            Some(c) => {
+                if c == '\u{fffd}' || c == '\0' {
+                    errors.push(error("Invalid character reference"));
+                }
+                out.push(if c == '\0' { '\u{fffd}' } else { c });
+                rest = &rest[semicolon + 1..];
+            }
+            None => {
+                errors.push(error(if name.is_empty() {
+                    "Unescaped '&'"
+                } else {
+                    "Unknown entity"
+                }));
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn scan_start_tag(input: &str) -> Scan<Vec<XmlToken>> {
+    let mut errors = vec!();
+    let mut pos = 1;
+    let name_end = unwrap_or_return!(input[pos..].find(|c: char| !is_name_char(c)), Incomplete);
+    let mut tag = XmlTag {
+        name: String::from_str(&input[pos..pos + name_end]),
+        attrs: vec!(),
+        empty: false,
+    };
+    pos += name_end;
+
+    loop {
+        let rest = &input[pos..];
+        let trimmed = rest.trim_left_matches(is_ascii_whitespace);
+        let had_space = trimmed.len() < rest.len();
+        pos += rest.len() - trimmed.len();
+        let rest = trimmed;
+
+        if rest.starts_with(">") {
+            pos += 1;
+            break;
+        }
+        if rest.starts_with("/>") {
+            tag.empty = true;
+            pos += 2;
+            break;
+        }
+        if rest.is_empty() || rest == "/" {
+            return Incomplete;
+        }
+
+        let c = rest.char_at(0);
+        if !is_name_start(c) {
+            errors.push(error("Bad character in tag"));
+            pos += c.len_utf8();
+            continue;
+        }
+        if !had_space {
+            errors.push(error("Missing space before attribute"));
+        }
+
+        let name_len = unwrap_or_return!(rest.find(|c: char| !is_name_char(c)), Incomplete);
+        let name = &rest[..name_len];
+        let after_name = &rest[name_len..];
+        let after_eq = after_name.trim_left_matches(is_ascii_whitespace);
+        if !after_eq.starts_with("=") {
+            if after_eq.is_empty() {
+                return Incomplete;
+            }
+            // As in HTML, an attribute without a value is empty.
+            errors.push(error("Attribute without a value"));
+            pos += name_len;
+            push_attr(&mut tag, name, String::new(), &mut errors);
+            continue;
+        }
+        let value_part = after_eq[1..].trim_left_matches(is_ascii_whitespace);
+        let value_start = rest.len() - value_part.len();
+        let quote = unwrap_or_return!(value_part.chars().next(), Incomplete);
+
+        let (raw, len) = if quote == '"' || quote == '\'' {
+            let end = unwrap_or_return!(value_part[1..].find(quote), Incomplete);
+            (&value_part[1..end + 1], end + 2)
+        } else {
+            errors.push(error("Unquoted attribute value"));
+            let end = unwrap_or_return!(
+                value_part.find(|c: char| is_ascii_whitespace(c) || c == '>'), Incomplete);
+            let end = if value_part[..end].ends_with("/") && value_part[end..].starts_with(">") {
+                end - 1
+            } else {
+                end
+            };
+            (&value_part[..end], end)
+        };
+        if raw.contains_char('<') {
+            errors.push(error("'<' in attribute value"));
+        }
+        let value = decode(raw, true, &mut errors);
+        push_attr(&mut tag, name, value, &mut errors);
+        pos += value_start + len;
+    }
+
+    errors.push(StartTag(tag));
+    Done(errors, pos)
+}
+
+fn push_attr(tag: &mut XmlTag, name: &str, value: String, errors: &mut Vec<XmlToken>) {
+    if tag.attrs.iter().any(|&(ref n, _)| n.as_slice() == name) {
+        errors.push(error("Duplicate attribute"));
+    } else {
+        tag.attrs.push((String::from_str(name), value));
+    }
+}
+
+fn scan_doctype(input: &str) -> Scan<Vec<XmlToken>> {
+    // Find the closing '>', outside quotes and the internal subset.
+    let mut quote = None;
+    let mut in_subset = false;
+    let mut subset_start = None;
+    let mut end = None;
+    for (i, c) in input.char_indices().skip(9) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '[') if !in_subset => {
+                in_subset = true;
+                subset_start = Some(i);
+            }
+            (None, ']') => in_subset = false,
+            (None, '>') if !in_subset => {
+                end = Some(i);
+                break;
+            }
+            _ => (),
+        }
+    }
+    let end = unwrap_or_return!(end, Incomplete);
+
+    // Split what's before the internal subset into words and quoted
+    // strings.
+    let body = &input[9..subset_start.unwrap_or(end)];
+    let mut words: Vec<&str> = vec!();
+    let mut rest = body.trim_left_matches(is_ascii_whitespace);
+    while !rest.is_empty() {
+        let q = rest.char_at(0);
+        let len = if q == '"' || q == '\'' {
+            match rest[1..].find(q) {
+                Some(close) => {
+                    words.push(&rest[1..close + 1]);
+                    close + 2
+                }
+                None => {
+                    words.push(&rest[1..]);
+                    rest.len()
+                }
+            }
+        } else {
+            let len = rest.find(is_ascii_whitespace).unwrap_or(rest.len());
+            words.push(&rest[..len]);
+            len
+        };
+        rest = rest[len..].trim_left_matches(is_ascii_whitespace);
+    }
+
+    let mut tokens = vec!();
+    let word = |i: uint| words.get(i).map(|w| String::from_str(*w));
+    let (public_id, system_id) = match (words.len(), words.get(1).map(|w| *w)) {
+        (1, _) => (None, None),
+        (3, Some("SYSTEM")) => (None, word(2)),
+        (4, Some("PUBLIC")) => (word(2), word(3)),
+        _ => {
+            tokens.push(error("Bad doctype"));
+            (None, None)
+        }
+    };
+    let doctype = XmlDoctype {
+        name: word(0).unwrap_or_else(String::new),
+        public_id: public_id,
+        system_id: system_id,
+    };
+    tokens.push(Doctype(doctype));
+    Done(tokens, end + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use core::prelude::*;
+    use collections::vec::Vec;
+    use collections::string::{String, ToString};
+
+    use super::{XmlTokenizer, XmlTokenSink, XmlToken, XmlTag, XmlDoctype};
+    use super::{StartTag, EndTag, Characters, Comment, ProcessingInstruction};
+    use super::{Doctype, ParseError, EOF, error};
+    use tokenizer::{Pos, Span};
+
+    struct Tokens {
+        tokens: Vec<XmlToken>,
+
+        /// Each error's code and span, which are left out of `tokens`.
+        errors: Vec<(&'static str, Span)>,
+    }
+
+    impl XmlTokenSink for Tokens {
+        fn process_token(&mut self, token: XmlToken) {
+            let token = match token {
+                ParseError(mut e) => {
+                    self.errors.push((e.code, e.span.unwrap()));
+                    e.span = None;
+                    ParseError(e)
+                }
+                token => token,
+            };
+            // Join up text, which may be split between inputs.
+            match (self.tokens.last_mut(), token) {
+                (Some(&mut Characters(ref mut a)), Characters(b)) => a.push_str(b.as_slice()),
+                (_, token) => self.tokens.push(token),
+            }
+        }
+    }
+
+    fn run(input: &[&str]) -> Tokens {
+        let mut tok = XmlTokenizer::new(Tokens {
+            tokens: vec!(),
+            errors: vec!(),
+        });
+        for chunk in input.iter() {
+            tok.feed(String::from_str(*chunk));
+        }
+        tok.end();
+        tok.unwrap()
+    }
+
+    fn tokenize(input: &[&str]) -> Vec<XmlToken> {
+        run(input).tokens
+    }
+
+    fn pos(offset: uint, line: uint, column: uint) -> Pos {
+        Pos {
+            offset: offset,
+            line: line,
+            column: column,
+        }
+    }
+
+    fn s(s: &str) -> String {
+        String::from_str(s)
+    }
+
+    fn tag(name: &str, attrs: &[(&str, &str)], empty: bool) -> XmlToken {
+        StartTag(XmlTag {
+            name: s(name),
+            attrs: attrs.iter().map(|&(n, v)| (s(n), s(v))).collect(),
+            empty: empty,
+        })
+    }
+
+    #[test]
+    fn document() {
+        assert_eq!(tokenize(&["<?xml version='1.0'?>\r\n<!DOCTYPE html \
+                PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" 'x.dtd' [<!ENTITY a 'b'>]>\
+                <?pi data ?><a:b xmlns:a='u' c=\"d&amp;e&#x41;\tf\"/>",
+                "<!--c--><p><![CDATA[<x>]]>&lt;</p>"]),
+            vec!(Characters(s("\n")),
+                 Doctype(XmlDoctype {
+                     name: s("html"),
+                     public_id: Some(s("-//W3C//DTD XHTML 1.0 Strict//EN")),
+                     system_id: Some(s("x.dtd")),
+                 }),
+                 ProcessingInstruction(s("pi"), s("data ")),
+                 tag("a:b", &[("xmlns:a", "u"), ("c", "d&eA f")], true),
+                 Comment(s("c")),
+                 tag("p", &[], false),
+                 Characters(s("<x><")),
+                 EndTag(s("p")),
+                 EOF));
+    }
+
+    #[test]
+    fn split_input() {
+        assert_eq!(tokenize(&["<a", " b='1", "'>x\r", "\ny</", "a>"]),
+            vec!(tag("a", &[("b", "1")], false), Characters(s("x\ny")), EndTag(s("a")), EOF));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(tokenize(&["<a b=1 c c='2'>&nbsp;& < </a><b"]),
+            vec!(error("Unquoted attribute value"),
+                 error("Attribute without a value"),
+                 error("Duplicate attribute"),
+                 tag("a", &[("b", "1"), ("c", "")], false),
+                 error("Unknown entity"),
+                 error("Unescaped '&'"),
+                 Characters(s("&nbsp;& ")),
+                 error("Unescaped '<'"),
+                 Characters(s("< ")),
+                 EndTag(s("a")),
+                 error("Unexpected end of input"),
+                 EOF));
+    }
+
+    #[test]
+    fn error_spans() {
+        // Offsets count each CRLF as two bytes, however the input is
+        // split.
+        let whole = "<a>\r\n\u{e9}&x;\r\n<b c=d>";
+        let tag_error = ("Unquoted attribute value",
+            Span { start: pos(12, 3, 1), end: pos(19, 3, 8) });
+        assert_eq!(run(&[whole]).errors, vec!(
+            ("Unknown entity", Span { start: pos(3, 1, 4), end: pos(12, 3, 1) }),
+            tag_error));
+
+        // Text is tokenized as it arrives, so the first error's span is
+        // only the reference.
+        let chunks: Vec<String> = whole.chars().map(|c| c.to_string()).collect();
+        let chunks: Vec<&str> = chunks.iter().map(|c| c.as_slice()).collect();
+        assert_eq!(run(chunks.as_slice()).errors, vec!(
+            ("Unknown entity", Span { start: pos(7, 2, 2), end: pos(10, 2, 5) }),
+            tag_error));
+    }
+
+    #[test]
+    fn many_small_chunks() {
+        let mut input = String::from_str("<!--");
+        for _ in 0..1000u {
+            input.push_str("-- > ]]>");
+        }
+        input.push_str("--><![CDATA[x]]>&amp<?a b?></p >");
+        let chunks: Vec<String> = input.as_slice().chars().map(|c| c.to_string()).collect();
+        let chunks: Vec<&str> = chunks.iter().map(|c| c.as_slice()).collect();
+        assert_eq!(tokenize(chunks.as_slice()), tokenize(&[input.as_slice()]));
+        assert_eq!(tokenize(chunks.as_slice()).len(), 7);
+    }
+}
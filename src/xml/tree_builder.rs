@@ -0,0 +1,265 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Building a tree from XML tokens, through the same `TreeSink` as the
+//! HTML parser.
+//!
+//! Names are resolved with the `xmlns` attributes in scope.  There are
+//! no implied tags: an end tag which doesn't match the current element
+//! closes the elements above the matching one, if one is open, and is
+//! otherwise ignored.  Either way it's a parse error.
+
+use core::prelude::*;
+
+use tokenizer::{Attribute, Span};
+use tree_builder::interface::{TreeSink, NoQuirks, AppendNode, AppendText};
+use diagnostic::Diagnostic;
+use util::str::is_ascii_whitespace;
+
+use xml::tokenizer::{XmlTokenSink, XmlToken, XmlTag};
+use xml::tokenizer::{StartTag, EndTag, Characters, Comment, ProcessingInstruction};
+use xml::tokenizer::{Doctype, ParseError, EOF};
+
+use collections::vec::Vec;
+use collections::string::String;
+
+use string_cache::{Atom, QualName, Namespace};
+
+struct OpenElem<Handle> {
+    handle: Handle,
+
+    /// The name as written in the start tag.
+    name: String,
+
+    /// How many namespace bindings the start tag added.
+    bindings: uint,
+}
+
+/// The XML tree builder.
+pub struct XmlTreeBuilder<Handle, Sink> {
+    sink: Sink,
+    doc_handle: Handle,
+    open_elems: Vec<OpenElem<Handle>>,
+
+    /// Namespace prefixes in scope, innermost last.  The default
+    /// namespace has the empty prefix.
+    bindings: Vec<(String, Namespace)>,
+
+    /// Has the root element been seen?
+    seen_root: bool,
+
+    /// The part of the input the current token came from.
+    current_span: Option<Span>,
+}
+
+impl<Handle, Sink> XmlTreeBuilder<Handle, Sink>
+    where Handle: Clone,
+          Sink: TreeSink<Handle=Handle>,
+{
+    pub fn new(mut sink: Sink) -> XmlTreeBuilder<Handle, Sink> {
+        let doc_handle = sink.get_document();
+        sink.set_quirks_mode(NoQuirks);
+        XmlTreeBuilder {
+            sink: sink,
+            doc_handle: doc_handle,
+            open_elems: vec!(),
+            bindings: vec!(),
+            seen_root: false,
+            current_span: None,
+        }
+    }
+
+    pub fn sink<'a>(&'a self) -> &'a Sink {
+        &self.sink
+    }
+
+    pub fn sink_mut<'a>(&'a mut self) -> &'a mut Sink {
+        &mut self.sink
+    }
+
+    pub fn unwrap(self) -> Sink {
+        self.sink
+    }
+
+    fn error(&mut self, msg: &'static str) {
+        let mut error = Diagnostic::error(msg);
+        error.span = self.current_span;
+        self.sink.report_diagnostic(error);
+    }
+
+    fn current_node(&self) -> Handle {
+        match self.open_elems.last() {
+            Some(elem) => elem.handle.clone(),
+            None => self.doc_handle.clone(),
+        }
+    }
+
+    fn lookup(&self, prefix: &str) -> Option<Namespace> {
+        match prefix {
+            "xml" => return Some(ns!(XML)),
+            "xmlns" => return Some(ns!(XMLNS)),
+            _ => (),
+        }
+        match self.bindings.iter().rev().find(|&&(ref p, _)| p.as_slice() == prefix) {
+            Some(&(_, ref ns)) => Some(ns.clone()),
+            None if prefix.is_empty() => Some(ns!("")),
+            None => None,
+        }
+    }
+
+    // Resolve a name.  Unprefixed attributes are in no namespace, rather
+    // than the default one.
+    fn resolve(&mut self, name: &str, attr: bool) -> QualName {
+        let (prefix, local) = match name.find(':') {
+            Some(i) if i > 0 && i + 1 < name.len() => (&name[..i], &name[i + 1..]),
+            _ if attr => return QualName::new(ns!(""), Atom::from_slice(name)),
+            _ => ("", name),
+        };
+        match self.lookup(prefix) {
+            Some(ns) => QualName::new(ns, Atom::from_slice(local)),
+            None => {
+                self.error("Unbound namespace prefix");
+                QualName::new(ns!(""), Atom::from_slice(name))
+            }
+        }
+    }
+
+    fn start_tag(&mut self, tag: XmlTag) {
+        if self.open_elems.is_empty() {
+            if self.seen_root {
+                self.error("More than one root element");
+            }
+            self.seen_root = true;
+        }
+
+        let mut bindings = 0;
+        for &(ref name, ref value) in tag.attrs.iter() {
+            let prefix = if name.as_slice() == "xmlns" {
+                ""
+            } else if name.as_slice().starts_with("xmlns:") {
+                &name[6..]
+            } else {
+                continue;
+            };
+            let ns = Namespace(Atom::from_slice(value.as_slice()));
+            self.bindings.push((String::from_str(prefix), ns));
+            bindings += 1;
+        }
+
+        let attrs = tag.attrs.into_iter().map(|(name, value)| {
+            let name = match name.as_slice() {
+                "xmlns" => QualName::new(ns!(XMLNS), Atom::from_slice("xmlns")),
+                n if n.starts_with("xmlns:")
+                    => QualName::new(ns!(XMLNS), Atom::from_slice(&n[6..])),
+                n => self.resolve(n, true),
+            };
+            Attribute {
+                name: name,
+                value: value,
+            }
+        }).collect();
+        let name = self.resolve(tag.name.as_slice(), false);
+        let elem = self.sink.create_element(name, attrs);
+        let parent = self.current_node();
+        self.sink.append(parent, AppendNode(elem.clone()));
+
+        if tag.empty {
+            self.bindings.truncate(self.bindings.len() - bindings);
+        } else {
+            self.open_elems.push(OpenElem {
+                handle: elem,
+                name: tag.name,
+                bindings: bindings,
+            });
+        }
+    }
+
+    fn pop(&mut self) {
+        let elem = self.open_elems.pop().expect("no open element to pop");
+        let len = self.bindings.len();
+        self.bindings.truncate(len - elem.bindings);
+    }
+
+    fn end_tag(&mut self, name: String) {
+        let index = match self.open_elems.iter().rposition(|e| e.name == name) {
+            Some(index) => index,
+            None => return self.error("End tag without a matching start tag"),
+        };
+        if index + 1 != self.open_elems.len() {
+            self.error("End tag closes unclosed elements");
+        }
+        while self.open_elems.len() > index {
+            self.pop();
+        }
+    }
+
+    fn characters(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        if self.open_elems.is_empty() {
+            // Whitespace outside the root element is not part of the
+            // document.
+            if !text.as_slice().chars().all(is_ascii_whitespace) {
+                self.error("Text outside the root element");
+            }
+            return;
+        }
+        let parent = self.current_node();
+        self.sink.append(parent, AppendText(text));
+    }
+}
+
+impl<Handle, Sink> XmlTokenSink for XmlTreeBuilder<Handle, Sink>
+    where Handle: Clone,
+          Sink: TreeSink<Handle=Handle>,
+{
+    fn process_token(&mut self, token: XmlToken) {
+        match token {
+            ParseError(error) => self.sink.report_diagnostic(error),
+            StartTag(tag) => self.start_tag(tag),
+            EndTag(name) => self.end_tag(name),
+            Characters(text) => self.characters(text),
+            Comment(text) => {
+                let comment = self.sink.create_comment(text);
+                let parent = self.current_node();
+                self.sink.append(parent, AppendNode(comment));
+            }
+            ProcessingInstruction(target, data) => {
+                let parent = self.current_node();
+                self.sink.append_processing_instruction(parent, target, data);
+            }
+            Doctype(doctype) => {
+                if self.seen_root {
+                    self.error("Doctype after the root element");
+                } else {
+                    self.sink.append_doctype_to_document(doctype.name,
+                        doctype.public_id.unwrap_or_else(String::new),
+                        doctype.system_id.unwrap_or_else(String::new));
+                }
+            }
+            EOF => {
+                if !self.open_elems.is_empty() {
+                    self.error("Unclosed elements at end of input");
+                }
+                if !self.seen_root {
+                    self.error("No root element");
+                }
+                while !self.open_elems.is_empty() {
+                    self.pop();
+                }
+            }
+        }
+    }
+
+    fn set_current_span(&mut self, span: Span) {
+        self.current_span = Some(span);
+        self.sink.set_current_span(span);
+    }
+}
@@ -30,6 +30,7 @@ use test::test_main;
 
 mod tokenizer;
 mod tree_builder;
+mod reference;
 mod util;
 
 // Needed to make `cargo test` run warning-free.
@@ -58,6 +59,10 @@ fn main() {
         tests.extend(tree_builder::tests(src_dir, &ignores));
     }
 
+    if env::var("HTML5EVER_NO_REF_TEST").is_err() {
+        tests.extend(reference::tests());
+    }
+
     let args: Vec<String> = env::args().collect();
     test_main(&args, tests);
 }
@@ -0,0 +1,471 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Property tests: pseudo-random, nearly valid HTML is tokenized by the
+//! real tokenizer and by a slow reference tokenizer, which must agree.
+//!
+//! The reference only understands what the generator produces: simple
+//! tags and attributes, comments without dashes, a doctype, a fixed set
+//! of character references, and a few kinds of junk.  Anything else
+//! makes it panic rather than guess.
+
+use std::char::CharExt;
+use std::default::Default;
+use std::thunk::Thunk;
+use std::vec::IntoIter;
+use test::{TestDesc, TestDescAndFn, DynTestName, DynTestFn};
+use test::ShouldFail::No;
+
+use html5ever::tokenizer::{Doctype, Attribute, StartTag, EndTag, Tag};
+use html5ever::tokenizer::{Token, DoctypeToken, TagToken, CommentToken};
+use html5ever::tokenizer::{CharacterTokens, EOFToken, ParseError};
+use html5ever::tokenizer::splits::{SplitRng, tokenize_chunks, random_split};
+
+use string_cache::{Atom, QualName};
+
+// How many inputs to try.
+const CASES: u64 = 500;
+
+// The character references the generator writes, and what they mean.
+static CHAR_REFS: &'static [(&'static str, &'static str)] = &[
+    ("&amp;", "&"),
+    ("&lt;", "<"),
+    ("&gt;", ">"),
+    ("&quot;", "\""),
+    ("&notin;", "\u{2209}"),
+    ("&#65;", "A"),
+    ("&#x42;", "B"),
+    ("&#x263a;", "\u{263a}"),
+    ("& ", "& "),
+];
+
+struct Generator {
+    rng: SplitRng,
+}
+
+impl Generator {
+    fn below(&mut self, n: usize) -> usize {
+        (self.rng.next() % (n as u64)) as usize
+    }
+
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[self.below(choices.len())]
+    }
+
+    // Between `min` and `max` characters from `alphabet`.
+    fn chars(&mut self, alphabet: &str, min: usize, max: usize) -> String {
+        let alphabet: Vec<char> = alphabet.chars().collect();
+        let len = min + self.below(max - min + 1);
+        (0..len).map(|_| alphabet[self.below(alphabet.len())]).collect()
+    }
+
+    fn tag_name(&mut self) -> String {
+        let mut name = self.chars("abAB", 1, 1);
+        name.push_str(self.chars("ab1B", 0, 2).as_slice());
+        name
+    }
+
+    fn attr_value(&mut self, quote: char) -> String {
+        let mut value = String::new();
+        for _ in 0..self.below(4) {
+            if self.below(3) == 0 {
+                let i = self.below(CHAR_REFS.len());
+                value.push_str(CHAR_REFS[i].0);
+            } else if quote == '"' {
+                value.push_str(self.chars("a b'>=<", 1, 2).as_slice());
+            } else {
+                value.push_str(self.chars("a b\">=<", 1, 2).as_slice());
+            }
+        }
+        value
+    }
+
+    fn start_tag(&mut self) -> String {
+        let mut tag = format!("<{}", self.tag_name());
+        for _ in 0..self.below(4) {
+            tag.push_str(self.pick(&[" ", "\n", "  "]));
+            tag.push_str(self.chars("xyX", 1, 2).as_slice());
+            let eq = self.pick(&["=", " = "]);
+            match self.below(4) {
+                0 => (),
+                1 => tag.push_str(format!("{}{}", eq, self.chars("ab1", 1, 3)).as_slice()),
+                2 => tag.push_str(format!("{}\"{}\"", eq, self.attr_value('"')).as_slice()),
+                _ => tag.push_str(format!("{}'{}'", eq, self.attr_value('\'')).as_slice()),
+            }
+        }
+        tag.push_str(self.pick(&[">", " >", " />"]));
+        tag
+    }
+
+    // A fragment of input which can go before or after any other.
+    fn fragment(&mut self) -> String {
+        match self.below(10) {
+            0 | 1 | 2 => self.chars("ab \n>=\"'/-?!", 1, 6),
+            3 | 4 => self.start_tag(),
+            5 => format!("</{}{}>", self.tag_name(), self.pick(&["", " "])),
+            6 => format!("<!--{}-->", self.chars("ab <", 0, 5)),
+            7 => {
+                let i = self.below(CHAR_REFS.len());
+                CHAR_REFS[i].0.to_string()
+            }
+            8 => self.pick(&["<!DOCTYPE html>", "<!doctype HTML>"]).to_string(),
+            _ => self.pick(&["< ", "<>", "</>", "<?x>", "<!x>", "</1>"]).to_string(),
+        }
+    }
+
+    // Fragments which make up a document, the last of which may be cut
+    // off by the end of the input.
+    fn document(&mut self) -> Vec<String> {
+        let mut fragments: Vec<String> = (0..self.below(12)).map(|_| self.fragment()).collect();
+        match self.below(4) {
+            0 => {
+                let tag = self.start_tag();
+                let len = 2 + self.below(tag.len() - 2);
+                fragments.push(tag[..len].to_string());
+            }
+            1 => {
+                let comment = format!("<!--{}-->", self.chars("ab <", 0, 3));
+                let len = 4 + self.below(comment.len() - 4);
+                fragments.push(comment[..len].to_string());
+            }
+            _ => (),
+        }
+        fragments
+    }
+}
+
+fn push_chars(out: &mut Vec<Token>, s: &str) {
+    match out.last_mut() {
+        Some(&mut CharacterTokens(ref mut chars)) => {
+            chars.push_str(s);
+            return;
+        }
+        _ => (),
+    }
+    out.push(CharacterTokens(s.to_string()));
+}
+
+fn starts_with(s: &[char], prefix: &str) -> bool {
+    let prefix: Vec<char> = prefix.chars().collect();
+    s.len() >= prefix.len() && &s[..prefix.len()] == prefix.as_slice()
+}
+
+fn starts_with_ignore_case(s: &[char], prefix: &str) -> bool {
+    let prefix: Vec<char> = prefix.chars().collect();
+    s.len() >= prefix.len()
+        && s.iter().zip(prefix.iter()).all(|(a, b)| a.to_lowercase() == b.to_lowercase())
+}
+
+fn lower(s: &[char]) -> String {
+    s.iter().map(|c| c.to_lowercase()).collect()
+}
+
+// The text a character reference at the start of `s` stands for, and
+// how many characters it takes up.
+fn char_ref(s: &[char]) -> (String, usize) {
+    for &(source, text) in CHAR_REFS.iter() {
+        if starts_with(s, source) {
+            return (text.to_string(), source.chars().count());
+        }
+    }
+    panic!("reference doesn't know the character reference at {:?}", s);
+}
+
+// The index of the first of `chars` at or after `i`.
+fn find(s: &[char], mut i: usize, chars: &str) -> Option<usize> {
+    while i < s.len() {
+        if chars.contains_char(s[i]) {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+fn skip_whitespace(s: &[char], mut i: usize) -> usize {
+    while i < s.len() && " \n".contains_char(s[i]) {
+        i += 1;
+    }
+    i
+}
+
+// A start tag's attributes and the end of the tag, starting after its
+// name, or `None` if the input ends first.
+fn attributes(s: &[char], mut i: usize) -> Option<(Vec<Attribute>, bool, usize)> {
+    let mut attrs: Vec<Attribute> = vec!();
+    loop {
+        i = skip_whitespace(s, i);
+        if i == s.len() {
+            return None;
+        }
+        if s[i] == '>' {
+            return Some((attrs, false, i + 1));
+        }
+        if s[i] == '/' {
+            if i + 1 == s.len() {
+                return None;
+            }
+            assert!(s[i+1] == '>', "reference doesn't know '/' inside a tag");
+            return Some((attrs, true, i + 2));
+        }
+
+        let end = match find(s, i, " \n/>=") {
+            Some(end) => end,
+            None => return None,
+        };
+        let name = QualName::new(ns!(""), Atom::from_slice(lower(&s[i..end]).as_slice()));
+        i = skip_whitespace(s, end);
+
+        let mut value = String::new();
+        if i < s.len() && s[i] == '=' {
+            i = skip_whitespace(s, i + 1);
+            if i == s.len() {
+                return None;
+            }
+            if s[i] == '"' || s[i] == '\'' {
+                let quote = s[i];
+                let end = match find(s, i + 1, if quote == '"' { "\"" } else { "'" }) {
+                    Some(end) => end,
+                    None => return None,
+                };
+                i += 1;
+                while i < end {
+                    if s[i] == '&' {
+                        let (text, len) = char_ref(&s[i..]);
+                        value.push_str(text.as_slice());
+                        i += len;
+                    } else {
+                        value.push(s[i]);
+                        i += 1;
+                    }
+                }
+                i = end + 1;
+            } else {
+                let end = match find(s, i, " \n>") {
+                    Some(end) => end,
+                    None => return None,
+                };
+                value = s[i..end].iter().map(|&c| c).collect();
+                i = end;
+            }
+        }
+
+        // The first of several attributes with the same name wins.
+        if !attrs.iter().any(|a| a.name == name) {
+            attrs.push(Attribute {
+                name: name,
+                value: value,
+            });
+        }
+    }
+}
+
+// Tokenize markup starting with `<` at `i`, returning where it ends.
+fn markup(s: &[char], i: usize, out: &mut Vec<Token>) -> usize {
+    let rest = &s[i+1..];
+    let to_gt = |start: usize| match find(s, start, ">") {
+        Some(end) => (s[start..end].iter().map(|&c| c).collect::<String>(), end + 1),
+        None => panic!("reference doesn't know how this ends: {:?}", &s[i..]),
+    };
+
+    if starts_with(rest, "!--") {
+        let start = i + 4;
+        let mut end = start;
+        while end < s.len() && !starts_with(&s[end..], "-->") {
+            end += 1;
+        }
+        let mut text: String = s[start..end].iter().map(|&c| c).collect();
+        if end == s.len() {
+            // The end of the input ends the comment, but not in the
+            // middle of "-->".
+            while text.ends_with("-") {
+                text.pop();
+            }
+            out.push(CommentToken(text));
+            return end;
+        }
+        out.push(CommentToken(text));
+        return end + 3;
+    }
+
+    if starts_with_ignore_case(rest, "!doctype ") {
+        let (name, end) = to_gt(i + 10);
+        out.push(DoctypeToken(Doctype {
+            name: Some(lower(name.chars().collect::<Vec<char>>().as_slice())),
+            public_id: None,
+            system_id: None,
+            force_quirks: false,
+        }));
+        return end;
+    }
+
+    match rest.first() {
+        Some(&'!') => {
+            let (text, end) = to_gt(i + 2);
+            out.push(CommentToken(text));
+            end
+        }
+        Some(&'?') => {
+            let (text, end) = to_gt(i + 1);
+            out.push(CommentToken(text));
+            end
+        }
+        Some(&'/') => match rest.get(1) {
+            Some(&'>') => i + 3,
+            Some(c) if c.is_alphabetic() => {
+                let end = match find(s, i + 2, " \n>") {
+                    Some(end) => end,
+                    None => return s.len(),
+                };
+                let name = lower(&s[i+2..end]);
+                let gt = skip_whitespace(s, end);
+                if gt == s.len() {
+                    return gt;
+                }
+                assert!(s[gt] == '>', "reference doesn't know end tag attributes");
+                out.push(TagToken(Tag {
+                    kind: EndTag,
+                    name: Atom::from_slice(name.as_slice()),
+                    self_closing: false,
+                    attrs: vec!(),
+                }));
+                gt + 1
+            }
+            Some(_) => {
+                let (text, end) = to_gt(i + 2);
+                out.push(CommentToken(text));
+                end
+            }
+            None => {
+                push_chars(out, "</");
+                s.len()
+            }
+        },
+        Some(c) if c.is_alphabetic() => {
+            let end = match find(s, i + 1, " \n/>") {
+                Some(end) => end,
+                None => return s.len(),
+            };
+            let name = lower(&s[i+1..end]);
+            match attributes(s, end) {
+                None => s.len(),
+                Some((attrs, self_closing, end)) => {
+                    out.push(TagToken(Tag {
+                        kind: StartTag,
+                        name: Atom::from_slice(name.as_slice()),
+                        self_closing: self_closing,
+                        attrs: attrs,
+                    }));
+                    end
+                }
+            }
+        }
+        _ => {
+            push_chars(out, "<");
+            i + 1
+        }
+    }
+}
+
+fn reference_tokenize(input: &str) -> Vec<Token> {
+    let s: Vec<char> = input.chars().collect();
+    let mut out = vec!();
+    let mut i = 0;
+    while i < s.len() {
+        match s[i] {
+            '<' => i = markup(s.as_slice(), i, &mut out),
+            '&' => {
+                let (text, len) = char_ref(&s[i..]);
+                push_chars(&mut out, text.as_slice());
+                i += len;
+            }
+            c => {
+                let mut buf = String::new();
+                buf.push(c);
+                push_chars(&mut out, buf.as_slice());
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+// Drop the tokens the reference doesn't produce, and join the character
+// tokens they separated.
+fn normalize(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out = vec!();
+    for token in tokens.into_iter() {
+        match token {
+            ParseError(_) | EOFToken => (),
+            CharacterTokens(s) => push_chars(&mut out, s.as_slice()),
+            token => out.push(token),
+        }
+    }
+    out
+}
+
+// Does the tokenizer disagree with the reference?  Check the input
+// whole and split into chunks.
+fn disagrees(input: &str, rng: &mut SplitRng) -> bool {
+    let expected = reference_tokenize(input);
+    normalize(tokenize_chunks(vec!(input.to_string()), Default::default())) != expected
+        || normalize(tokenize_chunks(random_split(input, rng), Default::default())) != expected
+}
+
+fn concat(fragments: &[String]) -> String {
+    let mut s = String::new();
+    for f in fragments.iter() {
+        s.push_str(f.as_slice());
+    }
+    s
+}
+
+fn check(seed: u64) {
+    let mut gen = Generator {
+        rng: SplitRng::new(seed),
+    };
+    let mut fragments = gen.document();
+    let mut rng = SplitRng::new(seed);
+    if !disagrees(concat(fragments.as_slice()).as_slice(), &mut rng) {
+        return;
+    }
+
+    // Shrink the input by dropping fragments, as long as the tokenizer
+    // still disagrees.
+    let mut i = 0;
+    while i < fragments.len() {
+        let mut smaller = fragments.clone();
+        smaller.remove(i);
+        if disagrees(concat(smaller.as_slice()).as_slice(), &mut SplitRng::new(seed)) {
+            fragments = smaller;
+        } else {
+            i += 1;
+        }
+    }
+
+    let input = concat(fragments.as_slice());
+    panic!("\nseed: {}\ninput: {:?}\ngot: {:?}\nexpected: {:?}", seed, input,
+        normalize(tokenize_chunks(vec!(input.clone()), Default::default())),
+        reference_tokenize(input.as_slice()));
+}
+
+pub fn tests() -> IntoIter<TestDescAndFn> {
+    let mut tests = vec!();
+    for seed in 1..CASES + 1 {
+        tests.push(TestDescAndFn {
+            desc: TestDesc {
+                name: DynTestName(format!("ref: seed {}", seed)),
+                ignore: false,
+                should_fail: No,
+            },
+            testfn: DynTestFn(Thunk::new(move || check(seed))),
+        });
+    }
+    tests.into_iter()
+}
@@ -19,14 +19,14 @@ use test::ShouldFail::No;
 use serialize::json;
 use serialize::json::Json;
 use std::collections::BTreeMap;
-use std::borrow::Cow::Borrowed;
 use std::vec::IntoIter;
 
 use html5ever::tokenizer::{Doctype, Attribute, StartTag, EndTag, Tag};
 use html5ever::tokenizer::{Token, DoctypeToken, TagToken, CommentToken};
 use html5ever::tokenizer::{CharacterTokens, NullCharacterToken, EOFToken, ParseError};
-use html5ever::tokenizer::{TokenSink, Tokenizer, TokenizerOpts};
+use html5ever::tokenizer::{TokenSink, TokenSinkResult, Continue, Tokenizer, TokenizerOpts};
 use html5ever::tokenizer::states::{Plaintext, RawData, Rcdata, Rawtext};
+use html5ever::diagnostic::Diagnostic;
 
 use string_cache::{Atom, QualName};
 
@@ -89,7 +89,7 @@ impl TokenLogger {
 }
 
 impl TokenSink for TokenLogger {
-    fn process_token(&mut self, token: Token) {
+    fn process_token(&mut self, token: Token) -> TokenSinkResult {
         match token {
             CharacterTokens(b) => {
                 self.current_str.push_str(b.as_slice());
@@ -100,7 +100,7 @@ impl TokenSink for TokenLogger {
             }
 
             ParseError(_) => if self.exact_errors {
-                self.push(ParseError(Borrowed("")));
+                self.push(ParseError(Diagnostic::error("")));
             },
 
             TagToken(mut t) => {
@@ -121,6 +121,7 @@ impl TokenSink for TokenLogger {
 
             _ => self.push(token),
         }
+        Continue
     }
 }
 
@@ -239,7 +240,7 @@ fn json_to_tokens(js: &Json, exact_errors: bool) -> Vec<Token> {
     for tok in js.get_list().iter() {
         match *tok {
             Json::String(ref s)
-                if s.as_slice() == "ParseError" => sink.process_token(ParseError(Borrowed(""))),
+                if s.as_slice() == "ParseError" => sink.process_token(ParseError(Diagnostic::error(""))),
             _ => sink.process_token(json_to_token(tok)),
         }
     }
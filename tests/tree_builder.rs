@@ -10,7 +10,6 @@
 use util::foreach_html5lib_test;
 
 use std::old_io as io;
-use std::iter::repeat;
 use std::mem::replace;
 use std::default::Default;
 use std::old_path::Path;
@@ -20,9 +19,10 @@ use std::thunk::Thunk;
 use test::{TestDesc, TestDescAndFn, DynTestName, DynTestFn};
 use test::ShouldFail::No;
 
-use html5ever::sink::common::{Document, Doctype, Text, Comment, Element};
-use html5ever::sink::rcdom::{RcDom, Handle};
-use html5ever::{parse, one_input};
+use html5ever::sink::rcdom::{RcDom, dump_tree};
+use html5ever::{parse, parse_fragment, one_input};
+
+use string_cache::{Atom, QualName};
 
 fn parse_tests<It: Iterator<Item=String>>(mut lines: It) -> Vec<HashMap<String, String>> {
     let mut tests = vec!();
@@ -65,63 +65,20 @@ fn parse_tests<It: Iterator<Item=String>>(mut lines: It) -> Vec<HashMap<String,
     tests
 }
 
-fn serialize(buf: &mut String, indent: usize, handle: Handle) {
-    buf.push_str("|");
-    buf.push_str(repeat(" ").take(indent).collect::<String>().as_slice());
-
-    let node = handle.borrow();
-    match node.node {
-        Document => panic!("should not reach Document"),
-
-        Doctype(ref name, ref public, ref system) => {
-            buf.push_str("<!DOCTYPE ");
-            buf.push_str(name.as_slice());
-            if !public.is_empty() || !system.is_empty() {
-                buf.push_str(format!(" \"{}\" \"{}\"", public, system).as_slice());
-            }
-            buf.push_str(">\n");
-        }
-
-        Text(ref text) => {
-            buf.push_str("\"");
-            buf.push_str(text.as_slice());
-            buf.push_str("\"\n");
-        }
-
-        Comment(ref text) => {
-            buf.push_str("<!-- ");
-            buf.push_str(text.as_slice());
-            buf.push_str(" -->\n");
-        }
-
-        Element(ref name, ref attrs) => {
-            assert!(name.ns == ns!(HTML));
-            buf.push_str("<");
-            buf.push_str(name.local.as_slice());
-            buf.push_str(">\n");
-
-            let mut attrs = attrs.clone();
-            attrs.sort_by(|x, y| x.name.local.cmp(&y.name.local));
-            // FIXME: sort by UTF-16 code unit
-
-            for attr in attrs.into_iter() {
-                assert!(attr.name.ns == ns!(""));
-                buf.push_str("|");
-                buf.push_str(repeat(" ").take(indent+2).collect::<String>().as_slice());
-                buf.push_str(format!("{}=\"{}\"\n",
-                    attr.name.local.as_slice(), attr.value).as_slice());
-            }
-        }
-    }
-
-    for child in node.children.iter() {
-        serialize(buf, indent+2, child.clone());
+// Parse the context element of a fragment test, e.g. "td" or "svg path".
+fn context_name(context: &str) -> QualName {
+    if context.starts_with("svg ") {
+        QualName::new(ns!(SVG), Atom::from_slice(&context[4..]))
+    } else if context.starts_with("math ") {
+        QualName::new(ns!(MathML), Atom::from_slice(&context[5..]))
+    } else {
+        QualName::new(ns!(HTML), Atom::from_slice(context))
     }
 }
 
 // Ignore tests containing these strings; we don't support these features yet.
 static IGNORE_SUBSTRS: &'static [&'static str]
-    = &["<math", "<svg", "<template"];
+    = &["<template"];
 
 fn make_test(
         tests: &mut Vec<TestDescAndFn>,
@@ -135,13 +92,10 @@ fn make_test(
         field.as_slice().trim_right_matches('\n').to_string()
     };
 
-    if fields.get("document-fragment").is_some() {
-        // FIXME
-        return;
-    }
-
     let data = get_field("data");
     let expected = get_field("document");
+    let context = fields.get("document-fragment")
+        .map(|field| context_name(field.as_slice().trim_right_matches('\n')));
     let name = format!("tb: {}-{}", path_str, idx);
     let ignore = ignores.contains(&name)
         || IGNORE_SUBSTRS.iter().any(|&ig| data.as_slice().contains(ig));
@@ -153,12 +107,13 @@ fn make_test(
             should_fail: No,
         },
         testfn: DynTestFn(Thunk::new(move || {
-            let dom: RcDom = parse(one_input(data.clone()), Default::default());
+            let dom: RcDom = match context {
+                None => parse(one_input(data.clone()), Default::default()),
+                Some(ref context) => parse_fragment(one_input(data.clone()), context.clone(),
+                    Default::default()),
+            };
 
-            let mut result = String::new();
-            for child in dom.document.borrow().children.iter() {
-                serialize(&mut result, 1, child.clone());
-            }
+            let mut result = dump_tree(&dom.document);
             let len = result.len();
             result.truncate(len - 1);  // drop the trailing newline
 
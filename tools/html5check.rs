@@ -0,0 +1,204 @@
+// Copyright 2014 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![feature(core, env, io, path)]
+
+//! Check HTML documents for conformance, offline.
+//!
+//!   html5check [--json] [--no-lint] [--polyglot] [FILE ...]
+//!
+//! Each file, or standard input if none are given, is parsed with
+//! exact parse errors and position tracking, then run through the
+//! validator and the accessibility lints.  With `--polyglot`, markup
+//! which wouldn't work as XHTML is also reported.
+//!
+//! Messages are printed as
+//!
+//!   FILE:LINE:COLUMN: error: MESSAGE [SPEC-URL]
+//!
+//! or with `--json`, as one JSON object per file and line, in the
+//! format used by the validator.nu web service:
+//!
+//!   {"url":"FILE","messages":[{"type":"error","message":"...",
+//!     "firstLine":1,"firstColumn":1,"lastLine":1,"lastColumn":5,
+//!     "spec":"https://..."}]}
+//!
+//! The exit status is 1 if
+//! there were any errors, not counting lint warnings, and 2 if a file
+//! couldn't be read.
+
+extern crate html5ever;
+
+use std::old_io as io;
+use std::old_io::File;
+use std::env;
+use std::default::Default;
+
+use html5ever::sink::rcdom::RcDom;
+use html5ever::driver::ParseOpts;
+use html5ever::tokenizer::{TokenizerOpts, Span};
+use html5ever::tree_builder::TreeBuilderOpts;
+use html5ever::validator;
+use html5ever::diagnostic::{Diagnostic, Error, Warning};
+use html5ever::{parse, one_input};
+
+struct Message {
+    kind: &'static str,
+    span: Option<Span>,
+    text: String,
+    spec: Option<String>,
+}
+
+impl Message {
+    fn from_diagnostic(d: &Diagnostic) -> Message {
+        Message {
+            kind: match d.severity {
+                Error => "error",
+                Warning => "warning",
+            },
+            span: d.span,
+            spec: d.spec_url(),
+            text: d.message.as_slice().to_string(),
+        }
+    }
+}
+
+fn check(input: String, lint: bool, polyglot: bool) -> Vec<Message> {
+    let opts = ParseOpts {
+        tokenizer: TokenizerOpts {
+            exact_errors: true,
+            track_positions: true,
+            .. Default::default()
+        },
+        tree_builder: TreeBuilderOpts {
+            exact_errors: true,
+            .. Default::default()
+        },
+        .. Default::default()
+    };
+    let polyglot_messages = if polyglot {
+        validator::check_polyglot(input.as_slice())
+    } else {
+        vec!()
+    };
+    let mut dom: RcDom = parse(one_input(input), opts);
+    if lint {
+        validator::lint_accessibility(&mut dom);
+    }
+
+    let mut messages: Vec<Message> = dom.errors.iter().map(Message::from_diagnostic).collect();
+    messages.extend(validator::validate(&dom).iter().map(Message::from_diagnostic));
+    messages.extend(polyglot_messages.iter().map(Message::from_diagnostic));
+    messages
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from_str("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(format!("\\u{:04x}", c as u32).as_slice()),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn print_json(name: &str, messages: &[Message]) {
+    let mut out = format!("{{\"url\":{},\"messages\":[", json_string(name));
+    for (i, m) in messages.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        // validator.nu reports warnings as "info" with a subtype.
+        match m.kind {
+            "warning" => out.push_str("{\"type\":\"info\",\"subType\":\"warning\""),
+            kind => out.push_str(format!("{{\"type\":{}", json_string(kind)).as_slice()),
+        }
+        out.push_str(format!(",\"message\":{}", json_string(m.text.as_slice())).as_slice());
+        match m.span {
+            Some(span) => out.push_str(format!(
+                ",\"firstLine\":{},\"firstColumn\":{},\"lastLine\":{},\"lastColumn\":{}",
+                span.start.line, span.start.column, span.end.line, span.end.column).as_slice()),
+            None => (),
+        }
+        match m.spec {
+            Some(ref url) => out.push_str(format!(",\"spec\":{}", json_string(url.as_slice())).as_slice()),
+            None => (),
+        }
+        out.push('}');
+    }
+    out.push_str("]}");
+    println!("{}", out);
+}
+
+fn print_text(name: &str, messages: &[Message]) {
+    for m in messages.iter() {
+        let place = match m.span {
+            Some(span) => format!("{}:{}:{}", name, span.start.line, span.start.column),
+            None => name.to_string(),
+        };
+        match m.spec {
+            Some(ref url) => println!("{}: {}: {} [{}]", place, m.kind, m.text, url),
+            None => println!("{}: {}: {}", place, m.kind, m.text),
+        }
+    }
+}
+
+fn main() {
+    let mut json = false;
+    let mut lint = true;
+    let mut polyglot = false;
+    let mut files = vec!();
+    for arg in env::args().skip(1) {
+        match arg.as_slice() {
+            "--json" => json = true,
+            "--no-lint" => lint = false,
+            "--polyglot" => polyglot = true,
+            _ => files.push(arg),
+        }
+    }
+
+    let inputs = if files.is_empty() {
+        vec!((String::from_str("-"), io::stdin().read_to_string()))
+    } else {
+        files.into_iter().map(|f| {
+            let input = File::open(&Path::new(f.as_slice())).read_to_string();
+            (f, input)
+        }).collect()
+    };
+
+    let mut status = 0;
+    for (name, input) in inputs.into_iter() {
+        let input = match input {
+            Ok(input) => input,
+            Err(e) => {
+                println!("{}: cannot read: {}", name, e);
+                status = 2;
+                continue;
+            }
+        };
+
+        let messages = check(input, lint, polyglot);
+        if status == 0 && messages.iter().any(|m| m.kind == "error") {
+            status = 1;
+        }
+        if json {
+            print_json(name.as_slice(), messages.as_slice());
+        } else {
+            print_text(name.as_slice(), messages.as_slice());
+        }
+    }
+    env::set_exit_status(status);
+}